@@ -36,11 +36,45 @@ vec4 tex_9s(in vec2 relativePixelCoord, in vec2 renderSizePixels, in vec2 uvOffs
     return texture(tex, uvOffset + uv);
 }
 
+vec4 gradient_color(in float t) {
+    // ストップは昇順に並んでいる前提で、順番に次のストップへ寄せていく
+    vec4 c = effectColors[0];
+    for (int i = 1; i < 4; i++) {
+        const float r = clamp((t - gradientStopOffsets[i - 1]) / max(gradientStopOffsets[i] - gradientStopOffsets[i - 1], 1.0e-5), 0.0, 1.0);
+        c = mix(c, effectColors[i], r);
+    }
+
+    return c;
+}
+
+float soft_alpharate() {
+    return min(
+        min(
+            clamp(screenUV.x / rectMaskSoftnessInScreenUV.x, 0.0, 1.0),
+            clamp((rectMaskInScreenUV.z - screenUV.x) / rectMaskSoftnessInScreenUV.z, 0.0, 1.0)
+        ),
+        min(
+            clamp(screenUV.y / rectMaskSoftnessInScreenUV.y, 0.0, 1.0),
+            clamp((rectMaskInScreenUV.w - screenUV.y) / rectMaskSoftnessInScreenUV.w, 0.0, 1.0)
+        )
+    );
+}
+
 void main() {
     if (rectMaskInScreenUV.x > screenUV.x || screenUV.x > rectMaskInScreenUV.z || rectMaskInScreenUV.y > screenUV.y || screenUV.y > rectMaskInScreenUV.w) {
         // out of mask
         discard;
     }
+    if (damageRectInScreenUV.x > screenUV.x || screenUV.x > damageRectInScreenUV.z || damageRectInScreenUV.y > screenUV.y || screenUV.y > damageRectInScreenUV.w) {
+        // out of damaged region(partial redraw)
+        discard;
+    }
+
+    if (uv_compositeMode_opacity.z == 5.0) {
+        // offscreen layer(already premultiplied)
+        col_out = texture(backdrop_tex, screenUV.xy) * uv_compositeMode_opacity.w * soft_alpharate();
+        return;
+    }
 
     if (uv_compositeMode_opacity.z == 2.0 || uv_compositeMode_opacity.z == 4.0) {
         // no texture mapping
@@ -62,19 +96,27 @@ void main() {
     if (uv_compositeMode_opacity.z == 1.0 || uv_compositeMode_opacity.z == 3.0) {
         // input is r8 format
         col_out = colorTint * vec4(1.0, 1.0, 1.0, col_out.r);
+    } else if (uv_compositeMode_opacity.z == 6.0) {
+        // linear gradient(input is r8 format)
+        const vec2 p = relativePixelCoord_renderSizePixels.xy / relativePixelCoord_renderSizePixels.zw;
+        const vec2 d = effectParams.zw - effectParams.xy;
+        const float t = dot(p - effectParams.xy, d) / max(dot(d, d), 1.0e-5);
+        col_out = gradient_color(t) * vec4(1.0, 1.0, 1.0, col_out.r);
+    } else if (uv_compositeMode_opacity.z == 7.0) {
+        // radial gradient(input is r8 format)
+        const vec2 p = relativePixelCoord_renderSizePixels.xy / relativePixelCoord_renderSizePixels.zw;
+        const float t = length((p - effectParams.xy) / max(effectParams.zw, vec2(1.0e-5)));
+        col_out = gradient_color(t) * vec4(1.0, 1.0, 1.0, col_out.r);
+    } else if (uv_compositeMode_opacity.z == 8.0) {
+        // inner glow(input is r8 format)
+        const vec2 rp = relativePixelCoord_renderSizePixels.xy;
+        const vec2 rs = relativePixelCoord_renderSizePixels.zw;
+        const float edge_distance = min(min(rp.x, rs.x - rp.x), min(rp.y, rs.y - rp.y));
+        const float glow = (1.0 - smoothstep(0.0, max(effectParams.x, 1.0e-5), edge_distance)) * effectColors[0].a;
+        col_out = vec4(mix(colorTint.rgb, effectColors[0].rgb, glow), max(colorTint.a, glow)) * vec4(1.0, 1.0, 1.0, col_out.r);
     }
 
-    const float soft_alpharate = min(
-        min(
-            clamp(screenUV.x / rectMaskSoftnessInScreenUV.x, 0.0, 1.0),
-            clamp((rectMaskInScreenUV.z - screenUV.x) / rectMaskSoftnessInScreenUV.z, 0.0, 1.0)
-        ),
-        min(
-            clamp(screenUV.y / rectMaskSoftnessInScreenUV.y, 0.0, 1.0),
-            clamp((rectMaskInScreenUV.w - screenUV.y) / rectMaskSoftnessInScreenUV.w, 0.0, 1.0)
-        )
-    );
-    col_out.a *= soft_alpharate;
+    col_out.a *= soft_alpharate();
 
     // apply opacity and premultiply
     col_out.a *= uv_compositeMode_opacity.w;
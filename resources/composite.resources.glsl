@@ -30,6 +30,11 @@ struct CompositeInstanceData {
     vec4 pos_height_animation_data;
     /// h_p1x, h_p1y, h_p2x, h_p2y
     vec4 pos_height_curve_control_points;
+    /// LinearGradient: start_x, start_y, end_x, end_y / RadialGradient: center_x, center_y, radius_x, radius_y / InnerGlow: width_px, reserved...
+    vec4 effect_params;
+    vec4 gradient_stop_offsets;
+    /// グラデーションの各ストップの色 / InnerGlowでは0番目がglow_color
+    vec4 effect_colors[4];
 };
 
 layout(set = 0, binding = 0, std140) readonly buffer InstanceDataArray {
@@ -49,6 +54,7 @@ layout(set = 1, binding = 0) uniform sampler2D backdrop_tex;
 layout(push_constant) uniform PushConstants {
     layout(offset = 16) vec4 rectMaskInScreenUV;
     layout(offset = 32) vec4 rectMaskSoftnessInScreenUV;
+    layout(offset = 48) vec4 damageRectInScreenUV;
 };
 
 #define VARYING_DIR in
@@ -61,3 +67,6 @@ layout(location = 3) VARYING_DIR vec4 sliceBordersLTRB;
 layout(location = 4) VARYING_DIR vec4 colorTint;
 layout(location = 5) VARYING_DIR vec4 texSlicedSizePixels;
 layout(location = 6) VARYING_DIR vec2 screenUV;
+layout(location = 7) flat VARYING_DIR vec4 effectParams;
+layout(location = 8) flat VARYING_DIR vec4 gradientStopOffsets;
+layout(location = 9) flat VARYING_DIR vec4 effectColors[4];
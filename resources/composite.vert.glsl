@@ -180,4 +180,7 @@ void main() {
     sliceBordersLTRB = instanceDataArray[gl_InstanceIndex].slice_borders;
     colorTint = instanceDataArray[gl_InstanceIndex].color_tint;
     texSlicedSizePixels = vec4(uvOffset_texSizePixels.zw * instanceDataArray[gl_InstanceIndex].uv_st.xy, 0.0f, 0.0f);
+    effectParams = instanceDataArray[gl_InstanceIndex].effect_params;
+    gradientStopOffsets = instanceDataArray[gl_InstanceIndex].gradient_stop_offsets;
+    effectColors = instanceDataArray[gl_InstanceIndex].effect_colors;
 }
@@ -0,0 +1,446 @@
+//! シェルがあってもなくても同じになるイベント処理とフレームの組み立て
+//!
+//! app_mainと[`crate::headless::HeadlessApp`]の両方から使う。
+//! ウィンドウの操作やファイルダイアログ、クリップボードなどシェルが必要なものは呼び出し側で処理する
+
+use bedrock as br;
+
+use crate::{
+    AppEvent, AppEventBus, AppUpdateContext, Application, PresenterInitContext, ViewInitContext,
+    base_system::{
+        AppBaseSystem, inject_cmd_pipeline_barrier_2, scratch_buffer::StagingScratchBuffer,
+    },
+    bg_worker::{BackgroundWorker, BackgroundWorkerViewFeedback},
+    complete_event,
+    composite::{CompositeRenderer, CompositeRenderingData, CompositeStreamingData},
+    feature,
+    hittest::HitTestTreeManager,
+    input::{self, KeyActionArgs, PointerCaptureHost, PointerInputManager},
+    report_rejected_sprite_sources,
+    uikit::{self, message_dialog::DialogRequest, popup::PopupManager},
+};
+
+/// イベントの処理に使うもの一式
+pub struct EventContext<'a, 'd, 'subsystem> {
+    pub base_system: &'a mut AppBaseSystem<'subsystem>,
+    pub app: &'a mut Application<'subsystem>,
+    pub popup_manager: &'a mut PopupManager,
+    pub pointer_input_manager: &'a mut PointerInputManager,
+    pub pointer_capture: &'a dyn PointerCaptureHost,
+    pub update_context: &'a mut AppUpdateContext<'d, 'subsystem>,
+    /// ポップアップを作るときのUIスケール
+    pub ui_scale_factor: f32,
+}
+impl EventContext<'_, '_, '_> {
+    fn recompute_enter_leave(&mut self) {
+        self.pointer_input_manager.recompute_enter_leave(
+            &mut self.base_system.hit_tree,
+            self.update_context,
+            HitTestTreeManager::ROOT,
+        );
+    }
+}
+
+/// バックグラウンド処理からのフィードバックを対応するAppEventにして積む
+pub fn push_background_feedback(events: &AppEventBus, bg_worker: &BackgroundWorker) {
+    while let Some(vf) = bg_worker.try_pop_view_feedback() {
+        events.push(match vf {
+            BackgroundWorkerViewFeedback::BeginWork(thread_number, message) => {
+                AppEvent::BeginBackgroundWork {
+                    thread_number,
+                    message,
+                }
+            }
+            BackgroundWorkerViewFeedback::EndWork(thread_number) => {
+                AppEvent::EndBackgroundWork { thread_number }
+            }
+            BackgroundWorkerViewFeedback::LoadSpriteSourceFailed(path) => {
+                AppEvent::SpriteSourceLoadFailed(path)
+            }
+        });
+    }
+}
+
+/// シェルに依存しないイベントを処理する
+///
+/// 時刻はupdate_contextのcurrent_secを使う。処理しなかったイベントはそのまま返す
+pub fn process_common_event(ctx: &mut EventContext, e: AppEvent) -> Option<AppEvent> {
+    let events = ctx.update_context.event_queue;
+    let app_state = ctx.update_context.state;
+    let current_sec = ctx.update_context.current_sec;
+
+    match e {
+        AppEvent::MainWindowPointerMove {
+            surface_x,
+            surface_y,
+        } => {
+            ctx.pointer_input_manager.handle_mouse_move(
+                surface_x,
+                surface_y,
+                &mut ctx.base_system.hit_tree,
+                ctx.update_context,
+                HitTestTreeManager::ROOT,
+            );
+        }
+        AppEvent::MainWindowPointerLeftDown => {
+            ctx.pointer_input_manager.handle_mouse_left_down(
+                ctx.pointer_capture,
+                &mut ctx.base_system.hit_tree,
+                ctx.update_context,
+                HitTestTreeManager::ROOT,
+                &mut ctx.base_system.keyboard_focus_manager,
+            );
+        }
+        AppEvent::MainWindowPointerLeftUp => {
+            ctx.pointer_input_manager.handle_mouse_left_up(
+                ctx.pointer_capture,
+                &mut ctx.base_system.hit_tree,
+                ctx.update_context,
+                HitTestTreeManager::ROOT,
+            );
+        }
+        AppEvent::MainWindowPointerScroll {
+            delta_x,
+            delta_y,
+            fling,
+        } => {
+            ctx.pointer_input_manager.handle_mouse_scroll(
+                delta_x,
+                delta_y,
+                fling,
+                &mut ctx.base_system.hit_tree,
+                ctx.update_context,
+                HitTestTreeManager::ROOT,
+            );
+        }
+        AppEvent::MainWindowKeyDown { key, modifiers } => {
+            if ctx
+                .base_system
+                .keyboard_focus_manager
+                .focused_element()
+                .is_none()
+            {
+                // Note: テキスト入力中でなければアプリ全体のショートカットとして扱う
+                match key {
+                    input::KeyCode::C if modifiers.contains(input::KeyModifiers::CTRL) => {
+                        events.push(AppEvent::UICopySelectedSprites);
+                    }
+                    input::KeyCode::V if modifiers.contains(input::KeyModifiers::CTRL) => {
+                        events.push(AppEvent::UIPasteSprites);
+                    }
+                    _ => (),
+                }
+            }
+
+            ctx.base_system.keyboard_focus_manager.dispatch_key_down(
+                &ctx.base_system.hit_tree,
+                ctx.update_context,
+                &KeyActionArgs { key, modifiers },
+            );
+        }
+        AppEvent::MainWindowTextInput(e) => {
+            ctx.base_system.keyboard_focus_manager.dispatch_text_input(
+                &ctx.base_system.hit_tree,
+                ctx.update_context,
+                &e,
+            );
+        }
+        AppEvent::UIMessageDialogRequest(request) => {
+            ctx.popup_manager.spawn::<uikit::message_dialog::Presenter>(
+                &mut PresenterInitContext {
+                    for_view: ViewInitContext {
+                        base_system: ctx.base_system,
+                        ui_scale_factor: ctx.ui_scale_factor,
+                    },
+                    app_state: &mut app_state.borrow_mut(),
+                },
+                current_sec,
+                request,
+            );
+            ctx.recompute_enter_leave();
+        }
+        AppEvent::UIFileBrowserRequest(request) => {
+            ctx.popup_manager.spawn::<uikit::file_browser::Presenter>(
+                &mut PresenterInitContext {
+                    for_view: ViewInitContext {
+                        base_system: ctx.base_system,
+                        ui_scale_factor: ctx.ui_scale_factor,
+                    },
+                    app_state: &mut app_state.borrow_mut(),
+                },
+                current_sec,
+                request,
+            );
+            ctx.recompute_enter_leave();
+        }
+        AppEvent::UIPopupClose { id } => {
+            ctx.popup_manager.close(ctx.base_system, current_sec, &id);
+            ctx.recompute_enter_leave();
+        }
+        AppEvent::UIPopupUnmount { id } => {
+            ctx.popup_manager.remove(ctx.base_system, &id);
+        }
+        AppEvent::AppMenuToggle => {
+            app_state.borrow_mut().toggle_menu();
+        }
+        AppEvent::AppMenuRequestAutoArrange => {
+            ctx.popup_manager
+                .spawn::<feature::auto_arrange_settings::Presenter>(
+                    &mut PresenterInitContext {
+                        for_view: ViewInitContext {
+                            base_system: ctx.base_system,
+                            ui_scale_factor: ctx.ui_scale_factor,
+                        },
+                        app_state: &mut app_state.borrow_mut(),
+                    },
+                    current_sec,
+                    (),
+                );
+            ctx.recompute_enter_leave();
+        }
+        AppEvent::AppMenuSetReopenLastAtlas(reopen) => {
+            ctx.base_system.settings.reopen_last_atlas = reopen;
+        }
+        AppEvent::SpriteListPaneVisibilityChanged { visible } => {
+            ctx.base_system.settings.sprite_list_visible = visible;
+        }
+        AppEvent::BeginBackgroundWork {
+            thread_number,
+            message,
+        } => {
+            tracing::trace!(thread_number, message, "TODO: BeginBackgroundWork");
+        }
+        AppEvent::EndBackgroundWork { thread_number } => {
+            tracing::trace!(thread_number, "TODO: EndBackgroundWork");
+        }
+        AppEvent::SpriteSourceLoadFailed(path) => {
+            app_state.borrow_mut().mark_sprite_source_missing(&path);
+        }
+        AppEvent::SelectSprite { index } => {
+            app_state.borrow_mut().select_sprite(index);
+        }
+        AppEvent::DeselectSprite => {
+            app_state.borrow_mut().deselect_sprite();
+        }
+        AppEvent::AddSpriteByPathList(paths) => {
+            let rejected = app_state.borrow_mut().add_sprites_from_file_paths(paths);
+            report_rejected_sprite_sources(events, rejected);
+        }
+        AppEvent::AddSpriteByImageData(data) => {
+            if let Err(e) = app_state
+                .borrow_mut()
+                .add_sprite_from_image_bytes("dropped", &data)
+            {
+                events.push(AppEvent::UIMessageDialogRequest(DialogRequest::error(
+                    "Adding dropped image failed",
+                    e,
+                )));
+            }
+        }
+        AppEvent::SaveFile { path, completion } => {
+            let path = path.or_else(|| {
+                app_state
+                    .borrow()
+                    .current_open_path()
+                    .map(std::path::Path::to_path_buf)
+            });
+            let r = match path {
+                Some(p) => app_state.borrow_mut().save(&p).map_err(|e| e.to_string()),
+                None => Err(String::from("no file is currently open")),
+            };
+            complete_event(events, completion, "Saving failed", r);
+        }
+        AppEvent::AutoArrange {
+            allow_rotation,
+            gap,
+            order,
+        } => {
+            ctx.base_system.settings.arrange_allow_rotation = allow_rotation;
+            ctx.base_system.settings.arrange_gap = gap;
+            ctx.base_system.settings.arrange_order = order;
+            app_state.borrow_mut().arrange(allow_rotation, gap, order);
+        }
+        AppEvent::UIShowDragAndDropOverlay => {
+            ctx.app.dnd_overlay.show(ctx.base_system, current_sec);
+        }
+        AppEvent::UIHideDragAndDropOverlay => {
+            ctx.app.dnd_overlay.hide(ctx.base_system, current_sec);
+        }
+        AppEvent::UIColorSchemeChanged(scheme) => {
+            ctx.base_system.set_color_scheme(scheme, current_sec);
+        }
+        AppEvent::UIAccentColorChanged(rgb) => {
+            ctx.base_system.set_accent_color(rgb, current_sec);
+        }
+        AppEvent::MainWindowTiledStateChanged { is_tiled } => {
+            ctx.app
+                .app_header
+                .on_shell_tiling_changed(ctx.base_system, is_tiled);
+        }
+        e => return Some(e),
+    }
+
+    None
+}
+
+/// フレームの組み立てに使うもの一式
+pub struct FrameContext<'a, 'subsystem> {
+    pub base_system: &'a mut AppBaseSystem<'subsystem>,
+    pub app: &'a mut Application<'subsystem>,
+    pub popup_manager: &'a mut PopupManager,
+    pub composite_renderer: &'a mut CompositeRenderer<'subsystem>,
+    /// 前回のフレームの描画命令(変わっていたら書き換える)
+    pub last_instructions: &'a mut CompositeRenderingData,
+    pub events: &'a AppEventBus,
+}
+
+/// ビューを更新して、コンポジットツリーのインスタンスデータと時刻を書き出す
+///
+/// 描画命令が前回から変わっていたら描画側の準備をし直してtrueを返す(描画コマンドの記録し直しが必要)
+pub fn prepare_frame(
+    ctx: &mut FrameContext,
+    client_size: (f32, f32),
+    rt_size: br::Extent2D,
+    current_sec: f32,
+) -> br::Result<bool> {
+    let base_system = &mut *ctx.base_system;
+
+    ctx.app.set_client_size(client_size.0, client_size.1);
+    ctx.popup_manager
+        .set_client_size(client_size.0, client_size.1);
+    ctx.app.update(base_system, current_sec);
+    ctx.popup_manager.update(base_system, current_sec);
+
+    // もろもろの判定がめんどいのでいったん毎回updateする
+    let n = base_system
+        .composite_instance_manager
+        .staging_memory_raw_handle();
+    let r = base_system.composite_instance_manager.range_all();
+    let flush_required = base_system
+        .composite_instance_manager
+        .memory_stg_requires_explicit_flush();
+    let ptr = unsafe {
+        base_system
+            .composite_instance_manager
+            .map_staging(&base_system.subsystem)?
+    };
+    let composite_render_instructions = unsafe {
+        base_system.composite_tree.update(
+            rt_size,
+            current_sec,
+            base_system.atlas.vk_extent(),
+            ptr.ptr(),
+            ctx.events,
+        )
+    };
+    if flush_required {
+        unsafe {
+            base_system
+                .subsystem
+                .flush_mapped_memory_ranges(&[br::MappedMemoryRange::new_raw(n, 0, r.end as _)])?;
+        }
+    }
+    drop(ptr);
+
+    let instructions_changed = *ctx.last_instructions != composite_render_instructions;
+    if instructions_changed {
+        ctx.composite_renderer.ready_input_backdrop_descriptor_sets(
+            composite_render_instructions.required_buffer_count(),
+        );
+
+        if composite_render_instructions.render_passes[0]
+            != ctx.app.editing_atlas_current_bound_pipeline
+        {
+            // editing atlas render pass changes
+            ctx.app.editing_atlas_current_bound_pipeline =
+                composite_render_instructions.render_passes[0];
+            ctx.app.editing_atlas_plane.recreate_render_resources(
+                base_system,
+                ctx.composite_renderer
+                    .select_subpass(&ctx.app.editing_atlas_current_bound_pipeline),
+                rt_size,
+            );
+        }
+
+        *ctx.last_instructions = composite_render_instructions;
+    }
+
+    let n = base_system
+        .composite_instance_manager
+        .streaming_memory_raw_handle();
+    let flush_required = base_system
+        .composite_instance_manager
+        .streaming_memory_requires_flush();
+    let mapped = unsafe {
+        base_system
+            .composite_instance_manager
+            .map_streaming(&base_system.subsystem)?
+    };
+    unsafe {
+        core::ptr::write(&mut (*mapped.ptr()).current_sec, current_sec);
+    }
+    if flush_required {
+        unsafe {
+            base_system
+                .subsystem
+                .flush_mapped_memory_ranges(&[br::MappedMemoryRange::new_raw(
+                    n,
+                    0,
+                    core::mem::size_of::<CompositeStreamingData>() as _,
+                )])?;
+        }
+    }
+    drop(mapped);
+
+    Ok(instructions_changed)
+}
+
+/// 描画の前に必要な転送(インスタンスデータと編集中のアトラスの変更分)を積む
+///
+/// sync_instancesがfalseならインスタンスデータは前回のまま使う
+pub fn inject_update_commands<'x, 'subsystem>(
+    r: br::CmdRecord<'x>,
+    base_system: &AppBaseSystem<'subsystem>,
+    composite_renderer: &CompositeRenderer<'subsystem>,
+    app: &Application<'subsystem>,
+    staging_scratch_buffer: &StagingScratchBuffer<'subsystem>,
+    sync_instances: bool,
+) -> br::CmdRecord<'x> {
+    r.inject(|r| {
+        if sync_instances {
+            base_system.composite_instance_manager.sync_buffer(r)
+        } else {
+            r
+        }
+    })
+    .inject(|r| {
+        inject_cmd_pipeline_barrier_2(
+            r,
+            base_system.subsystem,
+            &br::DependencyInfo::new(
+                &[br::MemoryBarrier2::new()
+                    .from(
+                        br::PipelineStageFlags2::COPY,
+                        br::AccessFlags2::TRANSFER.write,
+                    )
+                    .to(
+                        br::PipelineStageFlags2::VERTEX_SHADER,
+                        br::AccessFlags2::SHADER.read,
+                    )],
+                &[],
+                &[
+                    // Note: 0番目はbackdropなしの番兵としてつかわれるので初期化しておく
+                    br::ImageMemoryBarrier2::new(
+                        composite_renderer.default_backdrop_buffer(),
+                        br::ImageSubresourceRange::new(br::AspectMask::COLOR, 0..1, 0..1),
+                    )
+                    .transit_to(br::ImageLayout::ShaderReadOnlyOpt.from_undefined()),
+                ],
+            ),
+        )
+    })
+    .inject(|r| {
+        app.editing_atlas_plane
+            .process_dirty_data(base_system.subsystem, staging_scratch_buffer, r)
+    })
+}
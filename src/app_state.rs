@@ -1,11 +1,75 @@
 use std::{
     ffi::CString,
+    io::Write,
     path::{Path, PathBuf},
 };
 
 use uuid::Uuid;
 
-use crate::{coordinate::SizePixels, peridot, source_reader};
+use crate::{
+    coordinate::SizePixels,
+    peridot,
+    source_path::{self, DocumentPathMapping},
+    source_reader,
+};
+
+/// 貼り付けやドロップで受け取った画像を保存するフォルダの名前(アセットと同じ場所に作る)
+const IMPORTED_IMAGES_DIRECTORY_NAME: &str = "imported";
+/// 最近開いたファイルとして覚えておく最大数
+pub const MAX_RECENT_FILES: usize = 8;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImportImageError {
+    #[error("unsupported image data: {0}")]
+    UnsupportedImage(image::ImageError),
+    #[error("save the asset first so that imported images can be stored next to it")]
+    NoOpenAsset,
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+}
+
+/// 自動配置でスプライトを詰めていく順番
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArrangeOrder {
+    /// スプライトリストの並び順
+    #[default]
+    ListOrder,
+    /// 面積の大きいものから(同じ面積ならリストの並び順)
+    LargestFirst,
+}
+
+/// スプライトとして追加できなかったもの
+#[derive(Debug)]
+pub struct RejectedSpriteSource {
+    /// 表示用(パスかURI)
+    pub label: String,
+    pub reason: SpriteSourceRejection,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SpriteSourceRejection {
+    #[error(transparent)]
+    InvalidUri(#[from] source_path::FileUriError),
+    #[error("cannot open: {0}")]
+    OpenFailed(#[from] std::io::Error),
+    #[error("not a png image")]
+    NotPng,
+}
+
+/// PNGのヘッダを読んでスプライトにする
+fn load_sprite_source(path: &Path) -> Result<SpriteInfo, SpriteSourceRejection> {
+    let mut fs = std::fs::File::open(path)?;
+    let png_meta =
+        source_reader::png::Metadata::try_read(&mut fs).ok_or(SpriteSourceRejection::NotPng)?;
+
+    Ok(SpriteInfo::new(
+        path.file_stem()
+            .map_or_else(String::new, |x| x.to_string_lossy().into_owned()),
+        path.to_path_buf(),
+        png_meta.width,
+        png_meta.height,
+    ))
+}
 
 #[derive(Debug)]
 pub struct SpriteInfo {
@@ -23,6 +87,8 @@ pub struct SpriteInfo {
     pub bottom_slice: u32,
     pub rotated: bool,
     pub selected: bool,
+    /// ソース画像が見つからない(読めない)
+    pub source_missing: bool,
 }
 impl SpriteInfo {
     pub fn new(name: String, source_path: PathBuf, width: u32, height: u32) -> Self {
@@ -40,6 +106,7 @@ impl SpriteInfo {
             bottom_slice: 0,
             rotated: false,
             selected: false,
+            source_missing: false,
         }
     }
 
@@ -65,6 +132,12 @@ pub struct AppState<'subsystem> {
     visible_menu_view_feedbacks: Vec<Box<dyn FnMut(bool) + 'subsystem>>,
     current_open_path: Option<PathBuf>,
     current_open_path_view_feedbacks: Vec<Box<dyn FnMut(&Option<PathBuf>) + 'subsystem>>,
+    /// 最近開いた/保存したファイル(新しい順)
+    recent_files: Vec<PathBuf>,
+    recent_files_view_feedbacks: Vec<Box<dyn FnMut(&[PathBuf]) + 'subsystem>>,
+    /// 最後に保存/読み込みしてから変更があったか
+    modified: bool,
+    document_path_mapping: DocumentPathMapping,
 }
 impl<'subsystem> AppState<'subsystem> {
     pub fn new() -> Self {
@@ -80,6 +153,10 @@ impl<'subsystem> AppState<'subsystem> {
             visible_menu_view_feedbacks: Vec::new(),
             current_open_path: None,
             current_open_path_view_feedbacks: Vec::new(),
+            recent_files: Vec::new(),
+            recent_files_view_feedbacks: Vec::new(),
+            modified: false,
+            document_path_mapping: DocumentPathMapping::new(),
         }
     }
 
@@ -88,13 +165,61 @@ impl<'subsystem> AppState<'subsystem> {
         &self.sprites
     }
 
+    #[inline]
+    pub fn current_open_path(&self) -> Option<&Path> {
+        self.current_open_path.as_deref()
+    }
+
+    #[inline]
+    pub fn recent_files(&self) -> &[PathBuf] {
+        &self.recent_files
+    }
+
+    pub fn set_recent_files(&mut self, files: impl IntoIterator<Item = PathBuf>) {
+        self.recent_files.clear();
+        for f in files {
+            if !self.recent_files.contains(&f) {
+                self.recent_files.push(f);
+            }
+        }
+        self.recent_files.truncate(MAX_RECENT_FILES);
+
+        for cb in self.recent_files_view_feedbacks.iter_mut() {
+            cb(&self.recent_files);
+        }
+    }
+
+    /// 保存されていない変更があるか
+    pub const fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// ソース画像が見つからないスプライトの数
+    pub fn missing_source_count(&self) -> usize {
+        self.sprites.iter().filter(|x| x.source_missing).count()
+    }
+
+    /// 保存/読み込みのときにサンドボックス内のパスとホストのパスを行き来するための対応を差し替える
+    pub fn set_document_path_mapping(&mut self, mapping: DocumentPathMapping) {
+        self.document_path_mapping = mapping;
+    }
+
+    /// 追加できなかったものを返す(フォルダの中にあるPNGでないファイルは黙って飛ばす)
     pub fn add_sprites_from_file_paths(
         &mut self,
         paths: impl IntoIterator<Item = impl AsRef<Path>>,
-    ) {
+    ) -> Vec<RejectedSpriteSource> {
         let paths = paths.into_iter();
         let (lb, ub) = paths.size_hint();
         let mut added_sprites = Vec::with_capacity(ub.unwrap_or(lb));
+        let mut rejected = Vec::new();
+        let mut reject = |path: &Path, reason: SpriteSourceRejection| {
+            tracing::warn!(?path, ?reason, "rejected sprite source");
+            rejected.push(RejectedSpriteSource {
+                label: path.display().to_string(),
+                reason,
+            });
+        };
         for path in paths {
             let path = path.as_ref();
 
@@ -104,68 +229,112 @@ impl<'subsystem> AppState<'subsystem> {
                     .into_iter()
                     .filter_map(|e| e.ok())
                 {
-                    use crate::app_state::SpriteInfo;
-
                     let path = entry.path();
                     if !path.is_file() {
                         // 自分自身を含むみたいなのでその場合は見逃す
                         continue;
                     }
 
-                    let mut fs = std::fs::File::open(&path).unwrap();
-                    let Some(png_meta) = source_reader::png::Metadata::try_read(&mut fs) else {
+                    match load_sprite_source(path) {
+                        Ok(x) => added_sprites.push(x),
                         // PNGじゃないのは一旦見逃す
-                        continue;
-                    };
-
-                    added_sprites.push(SpriteInfo::new(
-                        path.file_stem().unwrap().to_str().unwrap().into(),
-                        path.to_path_buf(),
-                        png_meta.width,
-                        png_meta.height,
-                    ));
+                        Err(SpriteSourceRejection::NotPng) => (),
+                        Err(e) => reject(path, e),
+                    }
                 }
             } else {
-                use crate::app_state::SpriteInfo;
-
-                let mut fs = std::fs::File::open(&path).unwrap();
-                let png_meta = match source_reader::png::Metadata::try_read(&mut fs) {
-                    Some(x) => x,
-                    None => {
-                        tracing::warn!(?path, "not a png?");
-                        continue;
-                    }
-                };
-
-                added_sprites.push(SpriteInfo::new(
-                    path.file_stem().unwrap().to_str().unwrap().into(),
-                    path.to_path_buf(),
-                    png_meta.width,
-                    png_meta.height,
-                ));
+                match load_sprite_source(path) {
+                    Ok(x) => added_sprites.push(x),
+                    Err(e) => reject(path, e),
+                }
             }
         }
 
-        self.add_sprites(added_sprites);
+        if !added_sprites.is_empty() {
+            self.add_sprites(added_sprites);
+        }
+
+        rejected
     }
 
-    // TODO: uri_listを別途正しく解析してfrom_file_pathsのほうにまとめたい
-    pub fn add_sprites_by_uri_list(&mut self, uris: Vec<CString>) {
-        self.add_sprites_from_file_paths(uris.into_iter().filter_map(|x| {
-            Some(std::path::PathBuf::from(match x.to_str() {
-                Ok(x) => match x.strip_prefix("file://") {
-                    Some(x) => x,
-                    None => {
-                        tracing::warn!(?x, "not started by file://");
-                        x
-                    }
-                },
+    /// text/uri-listで渡されたものを追加する
+    ///
+    /// ローカルのファイルを指していないURIは追加できなかったものとして返す
+    pub fn add_sprites_by_uri_list(&mut self, uris: Vec<CString>) -> Vec<RejectedSpriteSource> {
+        let mut rejected = Vec::new();
+        let paths = uris
+            .into_iter()
+            .filter_map(|x| match source_path::path_from_file_uri(x.as_bytes()) {
+                Ok(p) => Some(p),
                 Err(e) => {
-                    tracing::warn!(reason = ?e, "invalid path");
-                    return None;
+                    tracing::warn!(uri = ?x, reason = ?e, "rejected uri");
+                    rejected.push(RejectedSpriteSource {
+                        label: x.to_string_lossy().into_owned(),
+                        reason: e.into(),
+                    });
+                    None
                 }
-            }))
-        }));
+            })
+            .collect::<Vec<_>>();
+        rejected.extend(self.add_sprites_from_file_paths(paths));
+
+        rejected
+    }
+
+    /// 貼り付けやドロップで受け取った画像を開いているアセットの隣の`imported`フォルダにPNGで保存して、スプライトとして追加する
+    ///
+    /// PNG以外の形式はデコードしてPNGにする
+    #[tracing::instrument(
+        name = "AppState::add_sprite_from_image_bytes",
+        skip(self, bytes),
+        err(Display)
+    )]
+    pub fn add_sprite_from_image_bytes(
+        &mut self,
+        name_prefix: &str,
+        bytes: &[u8],
+    ) -> Result<PathBuf, ImportImageError> {
+        let Some(asset_dir) = self.current_open_path.as_deref().and_then(Path::parent) else {
+            return Err(ImportImageError::NoOpenAsset);
+        };
+
+        let (png, width, height) = match source_reader::png::Metadata::try_read(&mut &bytes[..]) {
+            Some(m) => (std::borrow::Cow::Borrowed(bytes), m.width, m.height),
+            None => {
+                let image =
+                    image::load_from_memory(bytes).map_err(ImportImageError::UnsupportedImage)?;
+                let mut png = Vec::new();
+                image
+                    .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+                    .map_err(ImportImageError::UnsupportedImage)?;
+
+                (std::borrow::Cow::Owned(png), image.width(), image.height())
+            }
+        };
+
+        let dir = asset_dir.join(IMPORTED_IMAGES_DIRECTORY_NAME);
+        std::fs::create_dir_all(&dir)?;
+        // Note: 既存のファイルは上書きしないようにcreate_newで作れる名前を探す
+        let mut n = 1;
+        let (name, path) = loop {
+            let name = format!("{name_prefix}-{n}");
+            let path = dir.join(format!("{name}.png"));
+            match std::fs::File::options()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(mut fp) => {
+                    fp.write_all(&png)?;
+                    break (name, path);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => n += 1,
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        self.add_sprites([SpriteInfo::new(name, path.clone(), width, height)]);
+        Ok(path)
     }
 
     pub fn add_sprites(&mut self, sprites: impl IntoIterator<Item = SpriteInfo>) {
@@ -179,6 +348,7 @@ impl<'subsystem> AppState<'subsystem> {
 
             self.sprites.push(n);
         }
+        self.modified = true;
 
         if max_required_size != self.atlas_size {
             self.atlas_size = max_required_size;
@@ -202,6 +372,7 @@ impl<'subsystem> AppState<'subsystem> {
         let target_sprite = &mut self.sprites[index];
         target_sprite.left = left_pixels;
         target_sprite.top = top_pixels;
+        self.modified = true;
 
         // Sprite Atlasのサイズ調整
         let mut max_required_size = self.atlas_size;
@@ -246,6 +417,47 @@ impl<'subsystem> AppState<'subsystem> {
         }
     }
 
+    pub fn rename_sprite(&mut self, index: usize, name: String) {
+        let Some(sprite) = self.sprites.get_mut(index) else {
+            // removed while renaming
+            return;
+        };
+        if sprite.name == name {
+            // not changed
+            return;
+        }
+
+        sprite.name = name;
+        self.modified = true;
+
+        for cb in self.sprites_view_feedbacks.iter_mut() {
+            cb(&self.sprites);
+        }
+    }
+
+    /// fromにあるスプライトを、取り除いたあとの並びでtoの位置へ移す
+    ///
+    /// 並び順は重なったときの前後(大きいindexが手前)と自動配置の順番に効く
+    pub fn move_sprite(&mut self, from: usize, to: usize) {
+        if from >= self.sprites.len() {
+            // removed while dragging
+            return;
+        }
+        let to = to.min(self.sprites.len() - 1);
+        if from == to {
+            // not changed
+            return;
+        }
+
+        let sprite = self.sprites.remove(from);
+        self.sprites.insert(to, sprite);
+        self.modified = true;
+
+        for cb in self.sprites_view_feedbacks.iter_mut() {
+            cb(&self.sprites);
+        }
+    }
+
     pub fn toggle_menu(&mut self) {
         self.visible_menu = !self.visible_menu;
 
@@ -258,11 +470,12 @@ impl<'subsystem> AppState<'subsystem> {
         self.visible_menu
     }
 
-    pub fn arrange(&mut self, allow_rotation: bool) {
+    /// gapはスプライト同士の間隔(pixels 右と下に空ける)
+    pub fn arrange(&mut self, allow_rotation: bool, gap: u32, order: ArrangeOrder) {
         let (mut total_area, mut min_side_require) = (0, 0);
         for x in self.sprites.iter() {
-            total_area += (x.width * x.height) as u64;
-            min_side_require = min_side_require.max(x.width).max(x.height);
+            total_area += (x.width + gap) as u64 * (x.height + gap) as u64;
+            min_side_require = min_side_require.max(x.width + gap).max(x.height + gap);
         }
         let suitable_tex_size1 = (total_area as f64).sqrt().ceil() as u32;
         let suitable_tex_width = suitable_tex_size1.max(min_side_require).next_power_of_two();
@@ -489,10 +702,19 @@ impl<'subsystem> AppState<'subsystem> {
             }
         }
 
+        let mut alloc_order = (0..self.sprites.len()).collect::<Vec<_>>();
+        if order == ArrangeOrder::LargestFirst {
+            // Note: 安定ソートなので同じ面積ならリストの並び順のまま
+            alloc_order.sort_by_key(|&n| {
+                std::cmp::Reverse(self.sprites[n].width as u64 * self.sprites[n].height as u64)
+            });
+        }
+
         let mut dynamic_grid = DynamicGrid::new(suitable_tex_width, suitable_tex_height);
-        for x in self.sprites.iter_mut() {
+        for n in alloc_order {
+            let x = &mut self.sprites[n];
             // TODO: allow_rotation consideration
-            let Some((left, top)) = dynamic_grid.try_alloc(x.width, x.height) else {
+            let Some((left, top)) = dynamic_grid.try_alloc(x.width + gap, x.height + gap) else {
                 unreachable!("no suitable region(incorrect suitable tex size computation)");
             };
 
@@ -504,6 +726,7 @@ impl<'subsystem> AppState<'subsystem> {
         // TODO: この時点でサイズ切り詰められそうなら切り詰める
         self.atlas_size.width = suitable_tex_width;
         self.atlas_size.height = suitable_tex_height;
+        self.modified = true;
 
         for fb in self.atlas_size_view_feedbacks.iter_mut() {
             fb(&self.atlas_size);
@@ -516,15 +739,27 @@ impl<'subsystem> AppState<'subsystem> {
 
     #[tracing::instrument(name = "AppState::save", skip(self), fields(path = %path.as_ref().display()), err(Display))]
     pub fn save(&mut self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        // Note: 別の場所や別のマシンでも開けるように、ソースのパスはホスト側でのアセットファイルの場所からの相対パスで持つ
+        let base_dir = self
+            .document_path_mapping
+            .to_host(path.as_ref())
+            .and_then(|x| x.parent().map(Path::to_path_buf));
+        if base_dir.is_none() {
+            tracing::warn!(
+                "host path of the asset is unknown, sprite source paths are saved as is"
+            );
+        }
+
         let mut asset = peridot::SpriteAtlasAsset {
             width: self.atlas_size.width,
             height: self.atlas_size.height,
             sprites: self
                 .sprites
                 .iter()
-                .map(|x| peridot::Sprite {
+                .enumerate()
+                .map(|(n, x)| peridot::Sprite {
                     id: x.id.clone(),
-                    source_path: x.source_path.clone(),
+                    source_path: self.persistent_source_path(&x.source_path, base_dir.as_deref()),
                     name: x.name.clone(),
                     width: x.width,
                     height: x.height,
@@ -535,6 +770,7 @@ impl<'subsystem> AppState<'subsystem> {
                     border_right: x.right_slice,
                     border_bottom: x.bottom_slice,
                     rotated: x.rotated,
+                    order: n as _,
                 })
                 .collect(),
         };
@@ -548,6 +784,7 @@ impl<'subsystem> AppState<'subsystem> {
                 .open(&path)?,
         )?;
 
+        self.modified = false;
         self.update_current_open_path(path);
         Ok(())
     }
@@ -561,25 +798,43 @@ impl<'subsystem> AppState<'subsystem> {
             std::fs::File::open(&path)?,
         ))?;
 
-        self.sprites.clear();
-        self.sprites
-            .extend(asset.sprites.into_iter().map(|x| SpriteInfo {
-                id: x.id,
-                name: x.name,
-                source_path: x.source_path,
-                width: x.width,
-                height: x.height,
-                left: x.left,
-                top: x.top,
-                left_slice: x.border_left,
-                right_slice: x.border_right,
-                top_slice: x.border_top,
-                bottom_slice: x.border_bottom,
-                rotated: false,
-                selected: false,
-            }));
+        // Note: 相対パスはホスト側でのアセットファイルの場所から解決する(わからなければ見えているままの場所から)
+        let base_dir = self
+            .document_path_mapping
+            .to_host(path.as_ref())
+            .unwrap_or_else(|| path.as_ref().to_path_buf());
+        let base_dir = base_dir.parent().unwrap_or(Path::new(""));
+
+        let mut asset_sprites = asset.sprites;
+        asset_sprites.sort_by_key(|x| x.order);
+        let sprites = asset_sprites
+            .into_iter()
+            .map(|x| {
+                let (source_path, source_missing) =
+                    self.resolve_source_path(&x.source_path, base_dir);
+
+                SpriteInfo {
+                    id: x.id,
+                    name: x.name,
+                    source_path,
+                    width: x.width,
+                    height: x.height,
+                    left: x.left,
+                    top: x.top,
+                    left_slice: x.border_left,
+                    right_slice: x.border_right,
+                    top_slice: x.border_top,
+                    bottom_slice: x.border_bottom,
+                    rotated: false,
+                    selected: false,
+                    source_missing,
+                }
+            })
+            .collect();
+        self.sprites = sprites;
         self.atlas_size.width = asset.width;
         self.atlas_size.height = asset.height;
+        self.modified = false;
         self.update_current_open_path(path);
 
         for cb in self.atlas_size_view_feedbacks.iter_mut() {
@@ -593,12 +848,139 @@ impl<'subsystem> AppState<'subsystem> {
         Ok(())
     }
 
+    /// 保存するときのソースのパス
+    fn persistent_source_path(&self, source_path: &Path, base_dir: Option<&Path>) -> PathBuf {
+        let Some(host_path) = self.document_path_mapping.to_host(source_path) else {
+            tracing::warn!(
+                ?source_path,
+                "host path of the sprite source is unknown, saved as is"
+            );
+            return source_path.to_path_buf();
+        };
+
+        base_dir
+            .and_then(|b| source_path::relative_to(&host_path, b))
+            .unwrap_or(host_path)
+    }
+
+    /// 読み込んだソースのパスを実際に読める場所にする
+    ///
+    /// 見つからなかったときは(ホスト側の絶対パス, true)を返す そのまま保存し直しても場所の情報は失われない
+    fn resolve_source_path(&self, source_path: &Path, base_dir: &Path) -> (PathBuf, bool) {
+        let host_path = if source_path.is_relative() {
+            source_path::normalize(&base_dir.join(source_path))
+        } else {
+            source_path.to_path_buf()
+        };
+
+        if let Some(p) = self.document_path_mapping.to_sandbox(&host_path)
+            && p.is_file()
+        {
+            return (p, false);
+        }
+        if host_path.is_file() {
+            return (host_path, false);
+        }
+
+        tracing::warn!(path = ?host_path, "sprite source not found");
+        (host_path, true)
+    }
+
+    /// 読めなかったソースを使っているスプライトを見つからない状態にする
+    pub fn mark_sprite_source_missing(&mut self, source_path: &Path) {
+        let mut changed = false;
+        for x in self.sprites.iter_mut() {
+            if x.source_path == source_path && !x.source_missing {
+                x.source_missing = true;
+                changed = true;
+            }
+        }
+        if !changed {
+            return;
+        }
+
+        for cb in self.sprites_view_feedbacks.iter_mut() {
+            cb(&self.sprites);
+        }
+    }
+
+    /// 見つからないソースを、search_rootの下から同じファイル名のものを探してつなぎ直す
+    ///
+    /// つなぎ直せた数を返す
+    pub fn relink_missing_sources(&mut self, search_root: &Path) -> usize {
+        let mut candidates = std::collections::HashMap::new();
+        for entry in walkdir::WalkDir::new(search_root)
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            // Note: 同じ名前がいくつもあるときは浅いほう(先に見つかったほう)を使う
+            candidates
+                .entry(entry.file_name().to_os_string())
+                .or_insert_with(|| entry.into_path());
+        }
+
+        let mut relinked = 0;
+        for x in self.sprites.iter_mut().filter(|x| x.source_missing) {
+            let Some(path) = x.source_path.file_name().and_then(|n| candidates.get(n)) else {
+                continue;
+            };
+            let png_meta = match std::fs::File::open(path) {
+                Ok(mut fs) => source_reader::png::Metadata::try_read(&mut fs),
+                Err(e) => {
+                    tracing::warn!(?path, reason = ?e, "opening relink candidate failed");
+                    continue;
+                }
+            };
+            let Some(png_meta) = png_meta else {
+                tracing::warn!(?path, "relink candidate is not a png?");
+                continue;
+            };
+            if (png_meta.width, png_meta.height) != (x.width, x.height) {
+                tracing::warn!(
+                    ?path,
+                    old_width = x.width,
+                    old_height = x.height,
+                    new_width = png_meta.width,
+                    new_height = png_meta.height,
+                    "relinked sprite source has different size"
+                );
+            }
+
+            x.source_path = path.clone();
+            x.width = png_meta.width;
+            x.height = png_meta.height;
+            x.source_missing = false;
+            relinked += 1;
+        }
+        if relinked == 0 {
+            return 0;
+        }
+        self.modified = true;
+
+        for cb in self.sprites_view_feedbacks.iter_mut() {
+            cb(&self.sprites);
+        }
+
+        relinked
+    }
+
     fn update_current_open_path(&mut self, path: impl AsRef<Path>) {
         self.current_open_path = Some(path.as_ref().into());
 
         for cb in self.current_open_path_view_feedbacks.iter_mut() {
             cb(&self.current_open_path);
         }
+
+        self.recent_files.retain(|x| x != path.as_ref());
+        self.recent_files.insert(0, path.as_ref().into());
+        self.recent_files.truncate(MAX_RECENT_FILES);
+        for cb in self.recent_files_view_feedbacks.iter_mut() {
+            cb(&self.recent_files);
+        }
     }
 
     /// synchronizes views with the state: notifies current state to all view feedback receivers
@@ -615,6 +997,10 @@ impl<'subsystem> AppState<'subsystem> {
             cb(&self.current_open_path);
         }
 
+        for cb in self.recent_files_view_feedbacks.iter_mut() {
+            cb(&self.recent_files);
+        }
+
         for cb in self.visible_menu_view_feedbacks.iter_mut() {
             cb(self.visible_menu);
         }
@@ -646,4 +1032,73 @@ impl<'subsystem> AppState<'subsystem> {
     ) {
         self.current_open_path_view_feedbacks.push(Box::new(fb));
     }
+
+    // TODO: unregister
+    pub fn register_recent_files_view_feedback(
+        &mut self,
+        mut fb: impl FnMut(&[PathBuf]) + 'subsystem,
+    ) {
+        fb(&self.recent_files);
+        self.recent_files_view_feedbacks.push(Box::new(fb));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_load_keeps_sprite_order() {
+        let path = std::env::temp_dir().join(format!("{}.psa", Uuid::new_v4()));
+
+        let mut state = AppState::new();
+        state.add_sprites(
+            ["a", "b", "c"]
+                .map(|n| SpriteInfo::new(n.into(), PathBuf::from(format!("/{n}.png")), 8, 8)),
+        );
+        state.move_sprite(2, 0);
+        let names = |s: &AppState| {
+            s.sprites()
+                .iter()
+                .map(|x| x.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&state), ["c", "a", "b"]);
+        let ids = state.sprites().iter().map(|x| x.id).collect::<Vec<_>>();
+
+        state.save(&path).unwrap();
+        let mut loaded = AppState::new();
+        let r = loaded.load(&path);
+        std::fs::remove_file(&path).unwrap();
+        r.unwrap();
+
+        assert_eq!(names(&loaded), ["c", "a", "b"]);
+        assert_eq!(
+            loaded.sprites().iter().map(|x| x.id).collect::<Vec<_>>(),
+            ids
+        );
+    }
+
+    #[test]
+    fn arrange_largest_first_allocates_large_sprites_first() {
+        let mut state = AppState::new();
+        state.add_sprites([
+            SpriteInfo::new("small".into(), PathBuf::from("/small.png"), 8, 8),
+            SpriteInfo::new("large".into(), PathBuf::from("/large.png"), 16, 16),
+        ]);
+        let positions = |s: &AppState| {
+            s.sprites()
+                .iter()
+                .map(|x| (x.left, x.top))
+                .collect::<Vec<_>>()
+        };
+
+        state.arrange(false, 0, ArrangeOrder::ListOrder);
+        assert_eq!(positions(&state), [(0, 0), (8, 0)]);
+
+        state.arrange(false, 0, ArrangeOrder::LargestFirst);
+        assert_eq!(positions(&state), [(16, 0), (0, 0)]);
+        // 並べ替えても一覧の順番は変わらない
+        assert_eq!(state.sprites()[0].name, "small");
+    }
 }
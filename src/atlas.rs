@@ -1,6 +1,6 @@
 use bedrock as br;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct AtlasRect {
     pub left: u32,
     pub top: u32,
@@ -0,0 +1,517 @@
+//! D-Bus経由の自動操作インターフェイス
+//!
+//! セッションバスに`io.ct2.peridot.SpriteAtlasVisualizer`という名前でオブジェクトを公開する。
+//! 状態を変える操作はAppEventとしてメインループに流し、問い合わせはViewFeedbackで追従している写しから答える。
+//!
+//! Note: 手元で試すときは`dbus-run-session`で専用のバスを立てると他のインスタンスとぶつからない
+//! (例: `dbus-run-session -- sh -c 'peridot-sprite-atlas-visualizer & sleep 1; busctl --user introspect io.ct2.peridot.SpriteAtlasVisualizer /io/ct2/peridot/SpriteAtlasVisualizer'`)
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use dbus::MessageIterAppendLike;
+
+use crate::{
+    AppEvent, AppEventBus, AppEventCompletion, DBusLink,
+    app_state::{AppState, ArrangeOrder},
+    coordinate::SizePixels,
+};
+
+pub const BUS_NAME: &CStr = c"io.ct2.peridot.SpriteAtlasVisualizer";
+pub const OBJECT_PATH: &CStr = c"/io/ct2/peridot/SpriteAtlasVisualizer";
+pub const INTERFACE_NAME: &CStr = c"io.ct2.peridot.SpriteAtlasVisualizer";
+
+const INTROSPECTABLE_INTERFACE_NAME: &CStr = c"org.freedesktop.DBus.Introspectable";
+
+const ERROR_FAILED: &CStr = c"org.freedesktop.DBus.Error.Failed";
+const ERROR_INVALID_ARGS: &CStr = c"org.freedesktop.DBus.Error.InvalidArgs";
+const ERROR_UNKNOWN_METHOD: &CStr = c"org.freedesktop.DBus.Error.UnknownMethod";
+const ERROR_UNKNOWN_INTERFACE: &CStr = c"org.freedesktop.DBus.Error.UnknownInterface";
+const ERROR_UNKNOWN_OBJECT: &CStr = c"org.freedesktop.DBus.Error.UnknownObject";
+
+// Note: dbus::introspect_document::read_toplevelはDOCTYPEから始まっていることを要求する
+const INTROSPECTION_XML: &CStr = cr#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN" "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="io.ct2.peridot.SpriteAtlasVisualizer">
+    <method name="Open">
+      <arg name="path" type="s" direction="in"/>
+    </method>
+    <method name="Save">
+      <!-- empty string: overwrite the currently opened file -->
+      <arg name="path" type="s" direction="in"/>
+    </method>
+    <method name="AddSprites">
+      <arg name="paths" type="as" direction="in"/>
+    </method>
+    <method name="Arrange">
+      <arg name="allow_rotation" type="b" direction="in"/>
+      <arg name="gap" type="u" direction="in"/>
+    </method>
+    <method name="SelectSprite">
+      <arg name="index" type="u" direction="in"/>
+    </method>
+    <method name="GetSprites">
+      <!-- (name, source_path, left, top, width, height) -->
+      <arg name="sprites" type="a(ssuuuu)" direction="out"/>
+    </method>
+    <method name="GetAtlasSize">
+      <arg name="width" type="u" direction="out"/>
+      <arg name="height" type="u" direction="out"/>
+    </method>
+    <method name="GetCurrentPath">
+      <arg name="path" type="s" direction="out"/>
+    </method>
+    <signal name="SpritesChanged">
+      <arg name="count" type="u"/>
+    </signal>
+    <signal name="AtlasSizeChanged">
+      <arg name="width" type="u"/>
+      <arg name="height" type="u"/>
+    </signal>
+    <signal name="CurrentPathChanged">
+      <arg name="path" type="s"/>
+    </signal>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect">
+      <arg name="xml_data" type="s" direction="out"/>
+    </method>
+  </interface>
+</node>
+"#;
+
+struct SpriteEntry {
+    name: CString,
+    source_path: CString,
+    left: u32,
+    top: u32,
+    width: u32,
+    height: u32,
+}
+
+/// AppStateの写し(問い合わせにはこれで答える)
+struct StateMirror {
+    sprites: Vec<SpriteEntry>,
+    atlas_size: SizePixels,
+    current_path: CString,
+}
+
+pub struct Server {
+    mirror: Rc<RefCell<StateMirror>>,
+}
+impl Server {
+    pub fn new(dbus: &DBusLink, app_state: &mut AppState) -> Self {
+        match dbus
+            .underlying()
+            .request_name(BUS_NAME, dbus::NameFlags::DO_NOT_QUEUE)
+        {
+            Ok(dbus::REQUEST_NAME_REPLY_PRIMARY_OWNER | dbus::REQUEST_NAME_REPLY_ALREADY_OWNER) => {
+                tracing::info!(name = ?BUS_NAME, "automation interface exported");
+            }
+            Ok(r) => {
+                // 先に起動したインスタンスが名前を持っている(ユニーク名でなら呼べる)
+                tracing::warn!(
+                    name = ?BUS_NAME,
+                    reply = r,
+                    unique_name = ?dbus.underlying().unique_name(),
+                    "automation bus name is owned by another instance"
+                );
+            }
+            Err(e) => {
+                tracing::warn!(name = ?BUS_NAME, reason = ?e, "Failed to request automation bus name");
+            }
+        }
+
+        let mirror = Rc::new(RefCell::new(StateMirror {
+            sprites: Vec::new(),
+            atlas_size: SizePixels {
+                width: 0,
+                height: 0,
+            },
+            current_path: CString::default(),
+        }));
+
+        app_state.register_sprites_view_feedback({
+            let mirror = mirror.clone();
+            let con = dbus.underlying().clone();
+
+            move |sprites| {
+                let mut mirror = mirror.borrow_mut();
+                mirror.sprites.clear();
+                mirror.sprites.extend(sprites.iter().map(|x| SpriteEntry {
+                    name: to_dbus_string(&x.name),
+                    source_path: path_to_dbus_string(&x.source_path),
+                    left: x.left,
+                    top: x.top,
+                    width: x.width,
+                    height: x.height,
+                }));
+
+                emit_signal(&con, c"SpritesChanged", |a| {
+                    a.append_u32(sprites.len() as _)
+                });
+            }
+        });
+        app_state.register_atlas_size_view_feedback({
+            let mirror = mirror.clone();
+            let con = dbus.underlying().clone();
+
+            move |size| {
+                mirror.borrow_mut().atlas_size = *size;
+
+                emit_signal(&con, c"AtlasSizeChanged", |a| {
+                    a.append_u32(size.width)?;
+                    a.append_u32(size.height)
+                });
+            }
+        });
+        app_state.register_current_open_path_view_feedback({
+            let mirror = mirror.clone();
+            let con = dbus.underlying().clone();
+
+            move |path| {
+                let path = path
+                    .as_deref()
+                    .map_or_else(CString::default, path_to_dbus_string);
+                emit_signal(&con, c"CurrentPathChanged", |a| a.append_cstr(&path));
+                mirror.borrow_mut().current_path = path;
+            }
+        });
+
+        Self { mirror }
+    }
+
+    #[tracing::instrument(name = "automation::Server::handle_method_call", skip(self, dbus, call, events), fields(member = ?call.member()))]
+    pub fn handle_method_call(&self, dbus: &DBusLink, call: dbus::Message, events: &AppEventBus) {
+        let con = dbus.underlying();
+
+        if call.path() != Some(OBJECT_PATH) {
+            reply_error(con, &call, ERROR_UNKNOWN_OBJECT, "no such object");
+            return;
+        }
+        let Some(member) = call.member() else {
+            // method callには必ずmemberがあるはず
+            tracing::warn!("method call without member");
+            return;
+        };
+        let interface = call.interface();
+
+        if interface.is_none_or(|x| x == INTROSPECTABLE_INTERFACE_NAME) && member == c"Introspect" {
+            reply_with(con, &call, |a| a.append_cstr(INTROSPECTION_XML));
+            return;
+        }
+        if interface.is_some_and(|x| x != INTERFACE_NAME) {
+            reply_error(con, &call, ERROR_UNKNOWN_INTERFACE, "no such interface");
+            return;
+        }
+
+        match member.to_bytes() {
+            b"Open" => {
+                let Some(path) = read_string_arg(con, &call) else {
+                    return;
+                };
+                if path.is_empty() {
+                    reply_error(con, &call, ERROR_INVALID_ARGS, "path must not be empty");
+                    return;
+                }
+
+                events.push(AppEvent::OpenFile {
+                    path: PathBuf::from(path),
+                    completion: Some(reply_on_completion(con, call)),
+                });
+            }
+            b"Save" => {
+                let Some(path) = read_string_arg(con, &call) else {
+                    return;
+                };
+
+                events.push(AppEvent::SaveFile {
+                    path: if path.is_empty() {
+                        None
+                    } else {
+                        Some(PathBuf::from(path))
+                    },
+                    completion: Some(reply_on_completion(con, call)),
+                });
+            }
+            b"AddSprites" => {
+                if !check_signature(con, &call, c"as") {
+                    return;
+                }
+
+                let mut iter = call.iter();
+                let mut paths_iter = iter
+                    .try_begin_iter_array_content()
+                    .expect("signature checked");
+                let mut paths = Vec::new();
+                while paths_iter.arg_type() != dbus::TYPE_INVALID {
+                    let p = PathBuf::from(dbus_proto::cstr2str(
+                        paths_iter.try_get_cstr().expect("signature checked"),
+                    ));
+                    // Note: AppState::add_sprites_from_file_pathsと同じくディレクトリは中身を再帰的に追加するので通す
+                    if !p.is_file() && !p.is_dir() {
+                        reply_error(
+                            con,
+                            &call,
+                            ERROR_INVALID_ARGS,
+                            &format!("not a file or directory: {}", p.display()),
+                        );
+                        return;
+                    }
+                    paths.push(p);
+                    paths_iter.next();
+                }
+
+                events.push(AppEvent::AddSpriteByPathList(paths));
+                reply_with(con, &call, |_| Ok(()));
+            }
+            b"Arrange" => {
+                if !check_signature(con, &call, c"bu") {
+                    return;
+                }
+
+                let mut iter = call.iter();
+                let allow_rotation = iter.try_get_bool().expect("signature checked");
+                iter.next();
+                let gap = iter.try_get_u32().expect("signature checked");
+
+                // Note: 引数を増やすと既存の呼び出し側が壊れるので、順番はリストの並び順で固定
+                events.push(AppEvent::AutoArrange {
+                    allow_rotation,
+                    gap,
+                    order: ArrangeOrder::ListOrder,
+                });
+                reply_with(con, &call, |_| Ok(()));
+            }
+            b"SelectSprite" => {
+                if !check_signature(con, &call, c"u") {
+                    return;
+                }
+
+                let index = call.iter().try_get_u32().expect("signature checked") as usize;
+                if index >= self.mirror.borrow().sprites.len() {
+                    reply_error(con, &call, ERROR_INVALID_ARGS, "sprite index out of range");
+                    return;
+                }
+
+                events.push(AppEvent::SelectSprite { index });
+                reply_with(con, &call, |_| Ok(()));
+            }
+            b"GetSprites" => {
+                if !check_signature(con, &call, c"") {
+                    return;
+                }
+
+                let mirror = self.mirror.borrow();
+                reply_with(con, &call, |a| {
+                    let mut c = a.open_array_container(c"(ssuuuu)")?;
+                    for x in mirror.sprites.iter() {
+                        let mut s = c.open_struct_container()?;
+                        s.append_cstr(&x.name)?;
+                        s.append_cstr(&x.source_path)?;
+                        s.append_u32(x.left)?;
+                        s.append_u32(x.top)?;
+                        s.append_u32(x.width)?;
+                        s.append_u32(x.height)?;
+                        s.close()?;
+                    }
+                    c.close()
+                });
+            }
+            b"GetAtlasSize" => {
+                if !check_signature(con, &call, c"") {
+                    return;
+                }
+
+                let size = self.mirror.borrow().atlas_size;
+                reply_with(con, &call, |a| {
+                    a.append_u32(size.width)?;
+                    a.append_u32(size.height)
+                });
+            }
+            b"GetCurrentPath" => {
+                if !check_signature(con, &call, c"") {
+                    return;
+                }
+
+                let mirror = self.mirror.borrow();
+                reply_with(con, &call, |a| a.append_cstr(&mirror.current_path));
+            }
+            _ => {
+                reply_error(
+                    con,
+                    &call,
+                    ERROR_UNKNOWN_METHOD,
+                    &format!("no such method: {}", dbus_proto::cstr2str(member)),
+                );
+            }
+        }
+    }
+}
+
+/// D-Busの文字列はUTF-8でNULを含まない必要がある
+fn to_dbus_string(s: &str) -> CString {
+    CString::new(s.replace('\0', "")).expect("nul removed")
+}
+
+fn path_to_dbus_string(p: &Path) -> CString {
+    to_dbus_string(&p.to_string_lossy())
+}
+
+fn check_signature(con: &dbus::Connection, call: &dbus::Message, signature: &CStr) -> bool {
+    if call.signature() == signature {
+        return true;
+    }
+
+    reply_error(
+        con,
+        call,
+        ERROR_INVALID_ARGS,
+        &format!(
+            "expected arguments ({}), got ({})",
+            dbus_proto::cstr2str(signature),
+            dbus_proto::cstr2str(call.signature())
+        ),
+    );
+    false
+}
+
+fn read_string_arg(con: &dbus::Connection, call: &dbus::Message) -> Option<String> {
+    if !check_signature(con, call, c"s") {
+        return None;
+    }
+
+    Some(dbus_proto::cstr2str(call.iter().try_get_cstr().expect("signature checked")).to_owned())
+}
+
+fn reply_with(
+    con: &dbus::Connection,
+    call: &dbus::Message,
+    append_args: impl FnOnce(&mut dbus::MessageIterAppend) -> Result<(), dbus::NotEnoughMemory>,
+) {
+    if call.no_reply() {
+        return;
+    }
+
+    let mut msg = dbus::Message::new_method_return(call).expect("no enough memory");
+    append_args(&mut msg.iter_append()).expect("no enough memory");
+    con.send_with_serial(&mut msg).expect("no enough memory");
+}
+
+fn reply_error(con: &dbus::Connection, call: &dbus::Message, name: &CStr, message: &str) {
+    tracing::debug!(name = ?name, message, "automation method call failed");
+    if call.no_reply() {
+        return;
+    }
+
+    let mut msg =
+        dbus::Message::new_error(call, name, &to_dbus_string(message)).expect("no enough memory");
+    con.send_with_serial(&mut msg).expect("no enough memory");
+}
+
+/// AppEventの処理が終わってから応答する
+fn reply_on_completion(con: &dbus::Connection, call: dbus::Message) -> AppEventCompletion {
+    let con = con.clone();
+
+    Box::new(move |r| match r {
+        Ok(()) => reply_with(&con, &call, |_| Ok(())),
+        Err(e) => reply_error(&con, &call, ERROR_FAILED, &e),
+    })
+}
+
+fn emit_signal(
+    con: &dbus::Connection,
+    member: &CStr,
+    append_args: impl FnOnce(&mut dbus::MessageIterAppend) -> Result<(), dbus::NotEnoughMemory>,
+) {
+    let mut msg =
+        dbus::Message::new_signal(OBJECT_PATH, INTERFACE_NAME, member).expect("no enough memory");
+    append_args(&mut msg.iter_append()).expect("no enough memory");
+    con.send_with_serial(&mut msg).expect("no enough memory");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn introspection_xml_is_parsable() {
+        let mut members = Vec::new();
+        dbus::introspect_document::read_toplevel(
+            &mut quick_xml::Reader::from_str(INTROSPECTION_XML.to_str().unwrap()),
+            |_, ifname, r| {
+                let ifname = String::from_utf8(ifname.into_owned()).unwrap();
+                dbus::introspect_document::read_interface_tag_content(r, |content, r| {
+                    let (name, empty, is_method) = match content {
+                        dbus::introspect_document::InterfaceElementContent::Method {
+                            name,
+                            empty,
+                        } => (name, empty, true),
+                        dbus::introspect_document::InterfaceElementContent::Signal {
+                            name,
+                            empty,
+                        } => (name, empty, false),
+                        dbus::introspect_document::InterfaceElementContent::Property { .. } => {
+                            return Ok(());
+                        }
+                    };
+                    let mut arg_count = 0;
+                    let mut count_args =
+                        |c: dbus::introspect_document::MethodSignalElementContent<'_>| {
+                            if let dbus::introspect_document::MethodSignalElementContent::Arg {
+                                ..
+                            } = c
+                            {
+                                arg_count += 1;
+                            }
+                        };
+                    if empty {
+                        // 自己終了タグなので中身はない
+                    } else if is_method {
+                        dbus::introspect_document::read_method_tag_content(r, |c, _| {
+                            count_args(c);
+                            Ok(())
+                        })?;
+                    } else {
+                        dbus::introspect_document::read_signal_tag_content(r, |c, _| {
+                            count_args(c);
+                            Ok(())
+                        })?;
+                    }
+                    members.push((
+                        ifname.clone(),
+                        String::from_utf8(name.into_owned()).unwrap(),
+                        arg_count,
+                    ));
+
+                    Ok(())
+                })
+            },
+        )
+        .expect("INTROSPECTION_XML must be parsable");
+
+        let interface = INTERFACE_NAME.to_str().unwrap();
+        let find = |i: &str, n: &str| {
+            members
+                .iter()
+                .find(|(mi, mn, _)| mi == i && mn == n)
+                .map(|&(_, _, c)| c)
+        };
+        assert_eq!(find(interface, "Save"), Some(1));
+        assert_eq!(find(interface, "GetSprites"), Some(1));
+        assert_eq!(find(interface, "GetAtlasSize"), Some(2));
+        assert_eq!(find(interface, "AtlasSizeChanged"), Some(2));
+        assert_eq!(
+            find(
+                INTROSPECTABLE_INTERFACE_NAME.to_str().unwrap(),
+                "Introspect"
+            ),
+            Some(1)
+        );
+        assert_eq!(members.len(), 12);
+    }
+}
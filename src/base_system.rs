@@ -22,7 +22,7 @@ use crate::{
     hittest::{HitTestTreeData, HitTestTreeManager, HitTestTreeRef},
     input::KeyboardFocusManager,
     subsystem::Subsystem,
-    text::TextLayout,
+    text::{FontChain, GlyphBitmap, PlacedGlyph, TextLayout, TextLayoutOptions},
 };
 
 use bedrock::{
@@ -32,20 +32,28 @@ use bedrock::{
 use bitflags::bitflags;
 use cache::Cache;
 use parking_lot::RwLock;
-use scratch_buffer::StagingScratchBuffer;
+use scratch_buffer::{StagingScratchBuffer, StagingScratchBufferMapMode};
 
 mod cache;
 #[macro_use]
 pub mod prof;
 mod corner_cutout;
+mod glyph_cache;
+mod offscreen_target;
 pub mod scratch_buffer;
+mod settings;
 pub mod svg;
+pub mod theme;
 
 pub use self::corner_cutout::WindowCornerCutoutRenderer;
+use self::glyph_cache::{GlyphCache, GlyphKey};
+pub use self::offscreen_target::{OffscreenRenderTarget, ReadbackError};
+pub use self::settings::Settings;
+use self::theme::{ColorScheme, DEFAULT_ACCENT_COLOR, ThemePalette};
 
 pub struct FontSet {
-    pub ui_default: freetype::Owned<freetype::Face>,
-    pub ui_extra_large: freetype::Owned<freetype::Face>,
+    pub ui_default: FontChain,
+    pub ui_extra_large: FontChain,
 }
 
 bitflags! {
@@ -69,12 +77,21 @@ pub struct AppBaseSystem<'subsystem> {
     pub hit_tree: HitTestTreeManager<'subsystem>,
     pub keyboard_focus_manager: KeyboardFocusManager,
     pub fonts: FontSet,
+    pub theme: ThemePalette,
+    pub settings: Settings,
+    /// falseなら終了時に設定を書き戻さない
+    persist_settings: bool,
+    color_scheme: ColorScheme,
+    accent_color: [f32; 3],
     fs_cache: Cache,
     pipeline_cache: br::vk::VkPipelineCache,
     rounded_fill_rect_cache: HashMap<(SafeF32, SafeF32), AtlasRect>,
     rect_cache: HashMap<(SafeF32, SafeF32), AtlasRect>,
     rounded_rect_cache: HashMap<(SafeF32, SafeF32, SafeF32), AtlasRect>,
-    text_cache: HashMap<FontType, HashMap<String, AtlasRect>>,
+    text_cache: HashMap<(FontType, TextLayoutOptions), HashMap<String, AtlasRect>>,
+    /// text_maskで返したrectの参照数 free_mask_atlas_rectで0になったら実際に開放する
+    text_rect_refcounts: HashMap<AtlasRect, usize>,
+    glyph_cache: GlyphCache,
     render_to_mask_atlas_pass_cache:
         RefCell<HashMap<RenderPassOptions, Rc<br::RenderPassObject<&'subsystem Subsystem>>>>,
     loaded_shader_modules: RwLock<HashMap<PathBuf, br::vk::VkShaderModule>>,
@@ -83,6 +100,10 @@ pub struct AppBaseSystem<'subsystem> {
 }
 impl Drop for AppBaseSystem<'_> {
     fn drop(&mut self) {
+        if self.persist_settings {
+            let _ = self.settings.save();
+        }
+
         'try_save_pipeline_cache: {
             let dl = match unsafe {
                 br::vkfn_wrapper::get_pipeline_cache_data_byte_length(
@@ -205,34 +226,29 @@ impl<'subsystem> AppBaseSystem<'subsystem> {
     }
 
     pub fn new(subsystem: &'subsystem Subsystem) -> Self {
+        Self::init(subsystem, Settings::load(), true)
+    }
+
+    /// ユーザー設定を読み書きしない(ヘッドレス実行向け)
+    pub fn new_ephemeral(subsystem: &'subsystem Subsystem) -> Self {
+        Self::init(subsystem, Settings::default(), false)
+    }
+
+    fn init(subsystem: &'subsystem Subsystem, settings: Settings, persist_settings: bool) -> Self {
         // restore cache
         let fs_cache = Cache::new();
         let pipeline_cache = Self::load_or_create_pipeline_cache(subsystem, &fs_cache);
 
         // initialize typeface
         let (primary_face_path, primary_face_index);
+        #[cfg_attr(not(all(unix, not(target_os = "macos"))), allow(unused_mut))]
+        let mut fallback_faces = Vec::<(std::ffi::CString, core::ffi::c_int)>::new();
         #[cfg(all(unix, not(target_os = "macos")))]
         {
-            fontconfig::init();
-            let fc = fontconfig::Config::current();
-
-            let mut fc_pat = fontconfig::Pattern::new();
-            fc_pat.add_family_name(c"system-ui");
-            fc_pat.add_weight(80);
-            fc.substitute(&mut fc_pat, fontconfig::MatchKind::Pattern);
-            fc_pat.default_substitute();
-            let fc_set = fc.sort(&mut fc_pat, true).unwrap();
-            let mut primary_face_info = None;
-            for &f in fc_set.fonts() {
-                let file_path = f.get_file_path(0).unwrap();
-                let index = f.get_face_index(0).unwrap();
-
-                tracing::debug!(?file_path, index, "match font");
-
-                if primary_face_info.is_none() {
-                    primary_face_info = Some((file_path.to_owned(), index));
-                }
-            }
+            let mut faces = crate::text::sorted_ui_faces().into_iter();
+            let primary_face_info = faces.next();
+            // sort結果の2番目以降はプライマリにないグリフのフォールバックとして使う
+            fallback_faces.extend(faces);
             let Some((path, index)) = primary_face_info else {
                 tracing::error!("No UI face found");
                 std::process::exit(1);
@@ -373,6 +389,13 @@ impl<'subsystem> AppBaseSystem<'subsystem> {
             }
         };
 
+        let mut ft_face = FontChain::new(ft_face);
+        let mut ft_face_extra_large = FontChain::new(ft_face_extra_large);
+        for (path, index) in fallback_faces {
+            ft_face.add_fallback(path.clone(), index);
+            ft_face_extra_large.add_fallback(path, index);
+        }
+
         let composite_instance_buffer = CompositeInstanceManager::new(subsystem);
         let composition_alphamask_surface_atlas = CompositionSurfaceAtlas::new(
             subsystem,
@@ -380,9 +403,13 @@ impl<'subsystem> AppBaseSystem<'subsystem> {
             br::vk::VK_FORMAT_R8_UNORM,
         );
 
+        let mut composite_tree = CompositeTree::new();
+        // Note: システムの設定が取れるまではもともとの見た目であるダークで表示しておく
+        let theme = ThemePalette::new(&mut composite_tree, ColorScheme::Dark);
+
         Self {
             atlas: composition_alphamask_surface_atlas.unbound(),
-            composite_tree: CompositeTree::new(),
+            composite_tree,
             composite_instance_manager: composite_instance_buffer.unbound(),
             hit_tree: HitTestTreeManager::new(),
             keyboard_focus_manager: KeyboardFocusManager::new(),
@@ -390,11 +417,16 @@ impl<'subsystem> AppBaseSystem<'subsystem> {
                 ui_default: ft_face,
                 ui_extra_large: ft_face_extra_large,
             },
+            theme,
+            color_scheme: ColorScheme::Dark,
+            accent_color: DEFAULT_ACCENT_COLOR,
             pipeline_cache: pipeline_cache.unmanage().0,
             rounded_fill_rect_cache: HashMap::new(),
             rounded_rect_cache: HashMap::new(),
             rect_cache: HashMap::new(),
             text_cache: HashMap::new(),
+            text_rect_refcounts: HashMap::new(),
+            glyph_cache: GlyphCache::new(),
             render_to_mask_atlas_pass_cache: RefCell::new(HashMap::new()),
             loaded_shader_modules: RwLock::new(HashMap::new()),
             empty_pipeline_layout: OnceLock::new(),
@@ -402,30 +434,63 @@ impl<'subsystem> AppBaseSystem<'subsystem> {
                 FlippableStagingScratchBufferGroup::new(subsystem, 2),
             )),
             fs_cache,
+            settings,
+            persist_settings,
             subsystem,
         }
     }
 
-    pub fn rescale_fonts(&mut self, scale: f32) {
-        if let Err(e) =
-            self.fonts
-                .ui_default
-                .set_char_size((10.0 * 64.0) as _, 0, (96.0 * scale) as _, 0)
-        {
-            tracing::warn!(reason = ?e, "Failed to set char size");
+    pub const fn color_scheme(&self) -> ColorScheme {
+        self.color_scheme
+    }
+
+    /// 配色を切り替える（パレットから作った色はアニメーションしながら追従する）
+    pub fn set_color_scheme(&mut self, scheme: ColorScheme, current_sec: f32) {
+        if self.color_scheme == scheme {
+            return;
         }
 
-        if let Err(e) =
-            self.fonts
-                .ui_extra_large
-                .set_char_size((64.0 * 64.0) as _, 0, (96.0 * scale) as _, 0)
-        {
-            tracing::warn!(reason = ?e, "Failed to set char size");
+        self.color_scheme = scheme;
+        self.theme
+            .transition_scheme(&mut self.composite_tree, scheme, current_sec);
+    }
+
+    /// アクセントカラーを切り替える Noneでデフォルトに戻す
+    pub fn set_accent_color(&mut self, rgb: Option<[f32; 3]>, current_sec: f32) {
+        let rgb = rgb.unwrap_or(DEFAULT_ACCENT_COLOR);
+        if self.accent_color == rgb {
+            return;
         }
 
+        self.accent_color = rgb;
+        self.theme
+            .transition_accent_color(&mut self.composite_tree, rgb, current_sec);
+    }
+
+    pub fn rescale_fonts(&mut self, scale: f32) {
+        self.fonts
+            .ui_default
+            .set_char_size(10.0, (96.0 * scale) as _);
+        self.fonts
+            .ui_extra_large
+            .set_char_size(64.0, (96.0 * scale) as _);
+
         // evict all text caches
-        // TODO: atlasの領域開放してない
+        // Note: 使用中の文字列のrectは参照しているビュー側がfreeしたときに開放される
         self.text_cache.clear();
+        let (ui_default_size, ui_extra_large_size) = (
+            self.fonts.ui_default.size_key(),
+            self.fonts.ui_extra_large.size_key(),
+        );
+        for r in self.glyph_cache.purge(|k| {
+            k.size
+                != match k.font_type {
+                    FontType::UI => ui_default_size,
+                    FontType::UIExtraLarge => ui_extra_large_size,
+                }
+        }) {
+            self.atlas.free(r);
+        }
     }
 
     pub const fn mask_atlas_format(&self) -> br::Format {
@@ -507,8 +572,20 @@ impl<'subsystem> AppBaseSystem<'subsystem> {
         self.atlas.alloc(required_width, required_height)
     }
 
-    #[inline(always)]
     pub fn free_mask_atlas_rect(&mut self, rect: AtlasRect) {
+        if let Some(c) = self.text_rect_refcounts.get_mut(&rect) {
+            *c -= 1;
+            if *c > 0 {
+                // still referenced
+                return;
+            }
+
+            self.text_rect_refcounts.remove(&rect);
+            for m in self.text_cache.values_mut() {
+                m.retain(|_, r| *r != rect);
+            }
+        }
+
         self.atlas.free(rect)
     }
 
@@ -535,27 +612,166 @@ impl<'subsystem> AppBaseSystem<'subsystem> {
         Arc::downgrade(&self.staging_scratch_buffers)
     }
 
-    #[tracing::instrument(name = "AppBaseSystem::text_mask", skip(self), err(Display))]
+    /// text_maskで描いたときにキャレットを置ける位置(byte offset, 左端からのpixels)
+    pub fn text_caret_stops(&mut self, font_type: FontType, text: &str) -> Vec<(usize, f32)> {
+        let fonts = match font_type {
+            FontType::UI => &mut self.fonts.ui_default,
+            FontType::UIExtraLarge => &mut self.fonts.ui_extra_large,
+        };
+
+        fonts.caret_stops(&mut self.subsystem.ft.write(), text)
+    }
+
+    #[inline]
     pub fn text_mask(&mut self, font_type: FontType, text: &str) -> br::Result<AtlasRect> {
-        if let Some(&r) = self.text_cache.get(&font_type).and_then(|x| x.get(text)) {
+        self.text_mask_with_options(font_type, text, &TextLayoutOptions::SINGLE_LINE)
+    }
+
+    #[tracing::instrument(
+        name = "AppBaseSystem::text_mask_with_options",
+        skip(self),
+        err(Display)
+    )]
+    pub fn text_mask_with_options(
+        &mut self,
+        font_type: FontType,
+        text: &str,
+        options: &TextLayoutOptions,
+    ) -> br::Result<AtlasRect> {
+        if let Some(&r) = self
+            .text_cache
+            .get(&(font_type, *options))
+            .and_then(|x| x.get(text))
+        {
             // found in cache
+            *self.text_rect_refcounts.entry(r).or_insert(0) += 1;
             return Ok(r);
         }
 
         tracing::info!("creating fresh");
-        let layout = TextLayout::build_simple(
-            text,
-            match font_type {
-                FontType::UI => &mut self.fonts.ui_default,
-                FontType::UIExtraLarge => &mut self.fonts.ui_extra_large,
-            },
-        );
-        let atlas_rect = self.alloc_mask_atlas_rect(layout.width_px(), layout.height_px());
+        let fonts = match font_type {
+            FontType::UI => &mut self.fonts.ui_default,
+            FontType::UIExtraLarge => &mut self.fonts.ui_extra_large,
+        };
+        let mut ft = self.subsystem.ft.write();
+        let layout = TextLayout::build(text, fonts, &mut ft, options);
+        let size = fonts.size_key();
+        let glyph_key = |g: &PlacedGlyph| GlyphKey {
+            font_type,
+            face_index: g.face_index,
+            size,
+            glyph_id: g.glyph_id,
+            subpixel: g.subpixel,
+        };
+
+        // キャッシュにないグリフだけラスタライズする
+        self.glyph_cache.begin_use();
+        let mut fresh_glyphs = Vec::<(GlyphKey, GlyphBitmap)>::new();
+        for g in layout.glyphs() {
+            let key = glyph_key(g);
+            if self.glyph_cache.get(&key).is_none() && !fresh_glyphs.iter().any(|(k, _)| *k == key)
+            {
+                fresh_glyphs.push((key, fonts.rasterize(g.face_index, g.glyph_id, g.subpixel)));
+            }
+        }
+        drop(ft);
+
+        for r in self.glyph_cache.evict_for(
+            fresh_glyphs
+                .iter()
+                .map(|(_, b)| (b.width * b.rows) as u64)
+                .sum(),
+        ) {
+            self.atlas.free(r);
+        }
+        let fresh_glyphs = fresh_glyphs
+            .into_iter()
+            .map(|(k, b)| {
+                let rect = (!b.is_empty()).then(|| self.atlas.alloc(b.width as _, b.rows as _));
+                self.glyph_cache
+                    .insert(k, rect, b.left_offset as _, b.ascending_pixels as _);
+
+                (rect, b)
+            })
+            .collect::<Vec<_>>();
+
+        // グリフを並べたときの範囲
+        let placements = layout
+            .glyphs()
+            .iter()
+            .filter_map(|g| {
+                let c = self.glyph_cache.get(&glyph_key(g)).unwrap();
+                let r = c.rect?;
+
+                Some((r, g.left + c.left_offset, g.baseline - c.ascending_pixels))
+            })
+            .collect::<Vec<_>>();
+        let top = placements.iter().map(|&(_, _, t)| t).min().unwrap_or(0);
+        let bottom = placements
+            .iter()
+            .map(|&(r, _, t)| t + r.height() as i32)
+            .max()
+            .unwrap_or(0);
+        let width = layout.width_px();
+        let height = (bottom - top) as u32;
+        let atlas_rect = self.alloc_mask_atlas_rect(width, height);
+
+        // 文字列のrectにグリフをコピーする(はみ出る分は切り捨て)
+        let glyph_copies = placements
+            .into_iter()
+            .filter_map(|(r, left, top_in_layout)| {
+                let top_in_layout = top_in_layout - top;
+                let src_left = (-left).max(0);
+                let dst_left = left.max(0);
+                let copy_width = (r.width() as i32 - src_left).min(width as i32 - dst_left);
+                if copy_width <= 0 {
+                    return None;
+                }
+
+                Some(br::ImageCopy {
+                    srcSubresource: br::ImageSubresourceLayers::new(br::AspectMask::COLOR, 0, 0..1),
+                    dstSubresource: br::ImageSubresourceLayers::new(br::AspectMask::COLOR, 0, 0..1),
+                    srcOffset: br::Offset3D {
+                        x: r.left as i32 + src_left,
+                        y: r.top as _,
+                        z: 0,
+                    },
+                    dstOffset: br::Offset3D {
+                        x: atlas_rect.left as i32 + dst_left,
+                        y: atlas_rect.top as i32 + top_in_layout,
+                        z: 0,
+                    },
+                    extent: br::Extent3D {
+                        width: copy_width as _,
+                        height: r.height(),
+                        depth: 1,
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
 
         let mut staging_buffer_locked = self.active_staging_buffer_locked();
-        let image_pixels = layout.build_stg_image_pixel_buffer(&mut staging_buffer_locked);
+        let fresh_glyph_pixels = fresh_glyphs
+            .iter()
+            .filter_map(|(r, b)| {
+                Some((
+                    r.as_ref()?,
+                    b.build_stg_image_pixel_buffer(&mut staging_buffer_locked),
+                ))
+            })
+            .collect::<Vec<_>>();
+        // 隙間にゴミが残らないように一旦クリアする
+        let clear_pixels = staging_buffer_locked.reserve((width * height).max(1) as _);
+        unsafe {
+            staging_buffer_locked
+                .map(&clear_pixels, StagingScratchBufferMapMode::Write)
+                .expect("Failed to map staging scratch buffer")
+                .addr_of_mut::<u8>(0)
+                .write_bytes(0, (width * height) as _);
+        }
         self.sync_execute_graphics_commands(|rec| {
-            rec.inject(|r| {
+            // Note: グリフのコピー元とコピー先が同じイメージなのでGeneralでやる
+            let rec = rec.inject(|r| {
                 inject_cmd_pipeline_barrier_2(
                     r,
                     self.subsystem,
@@ -564,36 +780,75 @@ impl<'subsystem> AppBaseSystem<'subsystem> {
                         &[],
                         &[self
                             .barrier_for_mask_atlas_resource()
-                            .transit_to(br::ImageLayout::TransferDestOpt.from_undefined())
+                            .transit_to(br::ImageLayout::General.from_undefined())
                             .of_execution(
                                 br::PipelineStageFlags2(0),
                                 br::PipelineStageFlags2::COPY,
                             )],
                     ),
                 )
-            })
-            .inject(|r| {
-                let (b, o) = staging_buffer_locked.of(&image_pixels);
+            });
+            let rec = fresh_glyph_pixels
+                .iter()
+                .map(|(r, p)| (**r, p))
+                .chain(core::iter::once((atlas_rect, &clear_pixels)))
+                .filter(|(r, _)| r.width() > 0 && r.height() > 0)
+                .fold(rec, |rec, (r, p)| {
+                    let (b, o) = staging_buffer_locked.of(p);
+
+                    rec.copy_buffer_to_image(
+                        b,
+                        &self.mask_atlas_image_transparent_ref(),
+                        br::ImageLayout::General,
+                        &[br::vk::VkBufferImageCopy {
+                            bufferOffset: o,
+                            bufferRowLength: r.width(),
+                            bufferImageHeight: r.height(),
+                            imageSubresource: br::ImageSubresourceLayers::new(
+                                br::AspectMask::COLOR,
+                                0,
+                                0..1,
+                            ),
+                            imageOffset: r.lt_offset().with_z(0),
+                            imageExtent: r.extent().with_depth(1),
+                        }],
+                    )
+                });
 
-                r.copy_buffer_to_image(
-                    b,
+            let rec = rec.inject(|r| {
+                inject_cmd_pipeline_barrier_2(
+                    r,
+                    self.subsystem,
+                    &br::DependencyInfo::new(
+                        &[],
+                        &[],
+                        &[self
+                            .barrier_for_mask_atlas_resource()
+                            .transit_from(br::ImageLayout::General.to(br::ImageLayout::General))
+                            .from(
+                                br::PipelineStageFlags2::COPY,
+                                br::AccessFlags2::TRANSFER.write,
+                            )
+                            .to(
+                                br::PipelineStageFlags2::COPY,
+                                br::AccessFlags2::TRANSFER.read | br::AccessFlags2::TRANSFER.write,
+                            )],
+                    ),
+                )
+            });
+            let rec = if glyph_copies.is_empty() {
+                rec
+            } else {
+                rec.copy_image(
+                    &self.mask_atlas_image_transparent_ref(),
+                    br::ImageLayout::General,
                     &self.mask_atlas_image_transparent_ref(),
-                    br::ImageLayout::TransferDestOpt,
-                    &[br::vk::VkBufferImageCopy {
-                        bufferOffset: o,
-                        bufferRowLength: layout.width_px(),
-                        bufferImageHeight: layout.height_px(),
-                        imageSubresource: br::ImageSubresourceLayers::new(
-                            br::AspectMask::COLOR,
-                            0,
-                            0..1,
-                        ),
-                        imageOffset: atlas_rect.lt_offset().with_z(0),
-                        imageExtent: atlas_rect.extent().with_depth(1),
-                    }],
+                    br::ImageLayout::General,
+                    &glyph_copies,
                 )
-            })
-            .inject(|r| {
+            };
+
+            rec.inject(|r| {
                 inject_cmd_pipeline_barrier_2(
                     r,
                     self.subsystem,
@@ -603,8 +858,7 @@ impl<'subsystem> AppBaseSystem<'subsystem> {
                         &[self
                             .barrier_for_mask_atlas_resource()
                             .transit_from(
-                                br::ImageLayout::TransferDestOpt
-                                    .to(br::ImageLayout::ShaderReadOnlyOpt),
+                                br::ImageLayout::General.to(br::ImageLayout::ShaderReadOnlyOpt),
                             )
                             .of_memory(
                                 br::AccessFlags2::TRANSFER.write,
@@ -621,9 +875,10 @@ impl<'subsystem> AppBaseSystem<'subsystem> {
         drop(staging_buffer_locked);
 
         self.text_cache
-            .entry(font_type)
+            .entry((font_type, *options))
             .or_insert_with(HashMap::new)
             .insert(text.into(), atlas_rect);
+        self.text_rect_refcounts.insert(atlas_rect, 1);
         Ok(atlas_rect)
     }
 
@@ -2135,17 +2390,21 @@ impl Default for RenderTextureOptions {
 #[derive(Debug, Clone, Copy)]
 pub enum PixelFormat {
     R8,
+    Rgba8,
+    Bgra8,
 }
 impl PixelFormat {
-    const fn vk_format(&self) -> br::Format {
+    pub const fn vk_format(&self) -> br::Format {
         match self {
             Self::R8 => br::vk::VK_FORMAT_R8_UNORM,
+            Self::Rgba8 => br::vk::VK_FORMAT_R8G8B8A8_UNORM,
+            Self::Bgra8 => br::vk::VK_FORMAT_B8G8R8A8_UNORM,
         }
     }
 
     const fn aspect_mask(&self) -> br::AspectMask {
         match self {
-            Self::R8 => br::AspectMask::COLOR,
+            Self::R8 | Self::Rgba8 | Self::Bgra8 => br::AspectMask::COLOR,
         }
     }
 }
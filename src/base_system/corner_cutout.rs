@@ -339,6 +339,41 @@ impl<'subsystem> WindowCornerCutoutRenderer<'subsystem> {
         self.pipeline_cont = pipeline_cont;
     }
 
+    /// 画面上でカットアウトが描画される範囲（ピクセル単位、NDCで指定しているwidth_vp/height_vpの半分）
+    const CUTOUT_SIZE_PIXELS: u32 = 16;
+
+    /// カットアウトを描画する四隅の矩形
+    pub const fn cutout_rects(rt_size: br::Extent2D) -> [br::Rect2D; 4] {
+        let size = br::Extent2D {
+            width: Self::CUTOUT_SIZE_PIXELS,
+            height: Self::CUTOUT_SIZE_PIXELS,
+        };
+        let right = rt_size.width.saturating_sub(Self::CUTOUT_SIZE_PIXELS) as i32;
+        let bottom = rt_size.height.saturating_sub(Self::CUTOUT_SIZE_PIXELS) as i32;
+
+        [
+            br::Rect2D {
+                offset: br::Offset2D { x: 0, y: 0 },
+                extent: size,
+            },
+            br::Rect2D {
+                offset: br::Offset2D { x: right, y: 0 },
+                extent: size,
+            },
+            br::Rect2D {
+                offset: br::Offset2D { x: 0, y: bottom },
+                extent: size,
+            },
+            br::Rect2D {
+                offset: br::Offset2D {
+                    x: right,
+                    y: bottom,
+                },
+                extent: size,
+            },
+        ]
+    }
+
     #[inline]
     pub fn populate_commands<'x>(
         &self,
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+
+use crate::{atlas::AtlasRect, helper_types::SafeF32};
+
+use super::FontType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlyphKey {
+    pub font_type: FontType,
+    pub face_index: usize,
+    /// (ポイント数, dpi)
+    pub size: (SafeF32, u32),
+    pub glyph_id: u32,
+    pub subpixel: u8,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CachedGlyph {
+    /// 空グリフ(スペースなど)のときはNone
+    pub rect: Option<AtlasRect>,
+    pub left_offset: i32,
+    pub ascending_pixels: i32,
+    last_used: u64,
+}
+
+/// マスクアトラス上のグリフキャッシュ(LRU)
+pub struct GlyphCache {
+    entries: HashMap<GlyphKey, CachedGlyph>,
+    current_use: u64,
+    cached_pixels: u64,
+}
+impl GlyphCache {
+    /// これを超えたら古いものから捨てる
+    const MAX_CACHED_PIXELS: u64 = 1024 * 1024;
+
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            current_use: 0,
+            cached_pixels: 0,
+        }
+    }
+
+    /// 1回の文字列構築ごとに呼ぶ これ以降getしたものはevictされない
+    pub fn begin_use(&mut self) {
+        self.current_use += 1;
+    }
+
+    pub fn get(&mut self, key: &GlyphKey) -> Option<CachedGlyph> {
+        let e = self.entries.get_mut(key)?;
+        e.last_used = self.current_use;
+
+        Some(*e)
+    }
+
+    pub fn insert(
+        &mut self,
+        key: GlyphKey,
+        rect: Option<AtlasRect>,
+        left_offset: i32,
+        ascending_pixels: i32,
+    ) {
+        if let Some(r) = rect {
+            self.cached_pixels += r.width() as u64 * r.height() as u64;
+        }
+
+        self.entries.insert(
+            key,
+            CachedGlyph {
+                rect,
+                left_offset,
+                ascending_pixels,
+                last_used: self.current_use,
+            },
+        );
+    }
+
+    /// incoming_pixels分を追加しても上限を超えないように、今回使っていないものを古い順に捨てる
+    ///
+    /// 返したrectはアトラスに返却すること
+    pub fn evict_for(&mut self, incoming_pixels: u64) -> Vec<AtlasRect> {
+        if self.cached_pixels + incoming_pixels <= Self::MAX_CACHED_PIXELS {
+            return Vec::new();
+        }
+
+        let mut candidates = self
+            .entries
+            .iter()
+            .filter(|(_, e)| e.last_used < self.current_use)
+            .map(|(k, e)| (e.last_used, *k))
+            .collect::<Vec<_>>();
+        candidates.sort_unstable_by_key(|&(u, _)| u);
+
+        let mut freed = Vec::new();
+        for (_, k) in candidates {
+            if self.cached_pixels + incoming_pixels <= Self::MAX_CACHED_PIXELS {
+                break;
+            }
+
+            if let Some(r) = self.entries.remove(&k).and_then(|e| e.rect) {
+                self.cached_pixels -= r.width() as u64 * r.height() as u64;
+                freed.push(r);
+            }
+        }
+
+        freed
+    }
+
+    /// 条件に一致するものを全部捨てる(スケール変更時など)
+    ///
+    /// 返したrectはアトラスに返却すること
+    pub fn purge(&mut self, mut pred: impl FnMut(&GlyphKey) -> bool) -> Vec<AtlasRect> {
+        let mut freed = Vec::new();
+        self.entries.retain(|k, e| {
+            if !pred(k) {
+                return true;
+            }
+
+            if let Some(r) = e.rect {
+                freed.push(r);
+            }
+            false
+        });
+        self.cached_pixels -= freed
+            .iter()
+            .map(|r| r.width() as u64 * r.height() as u64)
+            .sum::<u64>();
+
+        freed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(glyph_id: u32) -> GlyphKey {
+        GlyphKey {
+            font_type: FontType::UI,
+            face_index: 0,
+            size: (SafeF32::new(12.0).unwrap(), 96),
+            glyph_id,
+            subpixel: 0,
+        }
+    }
+
+    /// 一辺sizeの正方形をx方向に並べて置いたrect
+    fn rect(n: u32, size: u32) -> AtlasRect {
+        AtlasRect {
+            left: n * size,
+            top: 0,
+            right: (n + 1) * size,
+            bottom: size,
+        }
+    }
+
+    /// 上限の1/4ずつの大きさのグリフ
+    const QUARTER: u32 = 512;
+
+    #[test]
+    fn glyphs_used_in_current_build_are_not_evicted() {
+        let mut cache = GlyphCache::new();
+        cache.begin_use();
+        for n in 0..4 {
+            cache.insert(key(n), Some(rect(n, QUARTER)), 0, 0);
+        }
+
+        // すべて今回の構築で使っているので、上限を超えても捨てない
+        assert!(cache.evict_for(QUARTER as u64 * QUARTER as u64).is_empty());
+        assert!((0..4).all(|n| cache.get(&key(n)).is_some()));
+    }
+
+    #[test]
+    fn eviction_is_oldest_first_until_under_limit() {
+        let mut cache = GlyphCache::new();
+        for n in 0..4 {
+            cache.begin_use();
+            cache.insert(key(n), Some(rect(n, QUARTER)), 0, 0);
+        }
+        cache.begin_use();
+        // 一番古いものを使いなおすと、次に古いものから捨てられる
+        assert!(cache.get(&key(0)).is_some());
+        // 空グリフはピクセルを持たない
+        cache.insert(key(4), None, 0, 0);
+
+        let freed = cache.evict_for(2 * QUARTER as u64 * QUARTER as u64);
+        assert_eq!(freed, [rect(1, QUARTER), rect(2, QUARTER)]);
+        assert_eq!(cache.cached_pixels, 2 * QUARTER as u64 * QUARTER as u64);
+        assert!(cache.get(&key(0)).is_some());
+        assert!(cache.get(&key(1)).is_none());
+        assert!(cache.get(&key(2)).is_none());
+        assert!(cache.get(&key(3)).is_some());
+        assert!(cache.get(&key(4)).is_some());
+    }
+
+    #[test]
+    fn purge_subtracts_freed_pixels() {
+        let mut cache = GlyphCache::new();
+        cache.begin_use();
+        cache.insert(key(0), Some(rect(0, 16)), 0, 0);
+        cache.insert(key(1), Some(rect(1, 8)), 0, 0);
+        cache.insert(key(2), None, 0, 0);
+        assert_eq!(cache.cached_pixels, 16 * 16 + 8 * 8);
+
+        let freed = cache.purge(|k| k.glyph_id != 1);
+        assert_eq!(freed, [rect(0, 16)]);
+        assert_eq!(cache.cached_pixels, 8 * 8);
+        assert!(cache.get(&key(2)).is_none());
+        assert!(cache.get(&key(1)).is_some());
+    }
+}
@@ -0,0 +1,266 @@
+//! CompositeRendererの描画先にできるオフスクリーンのテクスチャ
+
+use bedrock::{self as br, VkHandle};
+
+use crate::{composite::CompositeRenderTarget, subsystem::Subsystem};
+
+use super::{
+    AppBaseSystem, BufferCreationError, BufferMapMode, MemoryBoundBuffer, PixelFormat,
+    RenderTexture, RenderTextureFlags, RenderTextureOptions, inject_cmd_pipeline_barrier_2,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReadbackError {
+    #[error(transparent)]
+    Vulkan(#[from] br::vk::VkResult),
+    #[error(transparent)]
+    BufferCreation(#[from] BufferCreationError),
+}
+
+/// RenderTextureに描くCompositeRendererの描画先(バックバッファは1枚)
+///
+/// 中身は次に描くまで保たれるので、変化した部分だけを描き直せる
+pub struct OffscreenRenderTarget<'subsystem> {
+    texture: RenderTexture<'subsystem>,
+    size: br::Extent2D,
+    pixel_format: PixelFormat,
+}
+impl<'subsystem> OffscreenRenderTarget<'subsystem> {
+    pub fn new(
+        base_sys: &AppBaseSystem<'subsystem>,
+        size: br::Extent2D,
+        pixel_format: PixelFormat,
+    ) -> br::Result<Self> {
+        let texture = RenderTexture::new(
+            base_sys,
+            size,
+            pixel_format,
+            &RenderTextureOptions {
+                flags: RenderTextureFlags::ALLOW_TRANSFER_SRC | RenderTextureFlags::NON_SAMPLED,
+                msaa_count: None,
+            },
+        )?;
+
+        Ok(Self {
+            texture,
+            size,
+            pixel_format,
+        })
+    }
+
+    pub const fn size(&self) -> br::Extent2D {
+        self.size
+    }
+
+    /// 描いた内容を同じ大きさ・フォーマットのイメージ(スワップチェーンのバックバッファなど)全体に写す
+    ///
+    /// コピー先は中身を捨てて`dest_final_layout`に遷移する
+    pub fn inject_cmd_copy_to<'x>(
+        &self,
+        rec: br::CmdRecord<'x>,
+        subsystem: &Subsystem,
+        dest: &(impl br::VkHandle<Handle = br::vk::VkImage> + ?Sized),
+        dest_final_layout: br::ImageLayout,
+    ) -> br::CmdRecord<'x> {
+        rec.inject(|r| {
+            inject_cmd_pipeline_barrier_2(
+                r,
+                subsystem,
+                &br::DependencyInfo::new(
+                    &[],
+                    &[],
+                    &[
+                        br::ImageMemoryBarrier2::new(
+                            self.texture.as_image(),
+                            br::ImageSubresourceRange::new(br::AspectMask::COLOR, 0..1, 0..1),
+                        )
+                        .transit_from(
+                            br::ImageLayout::TransferSrcOpt.to(br::ImageLayout::TransferSrcOpt),
+                        )
+                        .from(
+                            br::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                            br::AccessFlags2::COLOR_ATTACHMENT.write,
+                        )
+                        .to(
+                            br::PipelineStageFlags2::COPY,
+                            br::AccessFlags2::TRANSFER.read,
+                        ),
+                        // Note: スワップチェーンのイメージはacquireの完了をCOLOR_ATTACHMENT_OUTPUTで待っているので、そこから繋げる
+                        br::ImageMemoryBarrier2::new(
+                            dest,
+                            br::ImageSubresourceRange::new(br::AspectMask::COLOR, 0..1, 0..1),
+                        )
+                        .transit_to(br::ImageLayout::TransferDestOpt.from_undefined())
+                        .from(
+                            br::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                            br::AccessFlags2::COLOR_ATTACHMENT.write,
+                        )
+                        .to(
+                            br::PipelineStageFlags2::COPY,
+                            br::AccessFlags2::TRANSFER.write,
+                        ),
+                    ],
+                ),
+            )
+        })
+        .copy_image(
+            self.texture.as_image(),
+            br::ImageLayout::TransferSrcOpt,
+            dest,
+            br::ImageLayout::TransferDestOpt,
+            &[br::ImageCopy {
+                srcSubresource: br::ImageSubresourceLayers::new(br::AspectMask::COLOR, 0, 0..1),
+                dstSubresource: br::ImageSubresourceLayers::new(br::AspectMask::COLOR, 0, 0..1),
+                srcOffset: br::Offset3D::ZERO,
+                dstOffset: br::Offset3D::ZERO,
+                extent: self.size.with_depth(1),
+            }],
+        )
+        .inject(|r| {
+            inject_cmd_pipeline_barrier_2(
+                r,
+                subsystem,
+                &br::DependencyInfo::new(
+                    &[],
+                    &[],
+                    &[br::ImageMemoryBarrier2::new(
+                        dest,
+                        br::ImageSubresourceRange::new(br::AspectMask::COLOR, 0..1, 0..1),
+                    )
+                    .transit_from(br::ImageLayout::TransferDestOpt.to(dest_final_layout))
+                    .from(
+                        br::PipelineStageFlags2::COPY,
+                        br::AccessFlags2::TRANSFER.write,
+                    )
+                    .to(
+                        br::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                        br::AccessFlags2::COLOR_ATTACHMENT.write,
+                    )],
+                ),
+            )
+        })
+    }
+
+    /// 描いた内容を1ピクセル4バイト(上の行から順、行の間に詰め物なし)で読み出す
+    ///
+    /// 描画コマンドの完了を待ってから呼ぶこと
+    #[tracing::instrument(name = "OffscreenRenderTarget::read_pixels", skip_all, err(Display))]
+    pub fn read_pixels(
+        &self,
+        base_sys: &AppBaseSystem<'subsystem>,
+    ) -> Result<Vec<u8>, ReadbackError> {
+        let byte_length = (self.size.width * self.size.height * 4) as usize;
+        let mut buf =
+            MemoryBoundBuffer::new_writable(base_sys, byte_length, br::BufferUsage::TRANSFER_DEST)?;
+
+        base_sys.sync_execute_graphics_commands(|rec| {
+            rec.inject(|r| {
+                inject_cmd_pipeline_barrier_2(
+                    r,
+                    base_sys.subsystem,
+                    &br::DependencyInfo::new(
+                        &[],
+                        &[],
+                        &[br::ImageMemoryBarrier2::new(
+                            self.texture.as_image(),
+                            br::ImageSubresourceRange::new(br::AspectMask::COLOR, 0..1, 0..1),
+                        )
+                        .transit_from(
+                            br::ImageLayout::TransferSrcOpt.to(br::ImageLayout::TransferSrcOpt),
+                        )
+                        .from(
+                            br::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                            br::AccessFlags2::COLOR_ATTACHMENT.write,
+                        )
+                        .to(
+                            br::PipelineStageFlags2::COPY,
+                            br::AccessFlags2::TRANSFER.read,
+                        )],
+                    ),
+                )
+            })
+            .copy_image_to_buffer(
+                self.texture.as_image(),
+                br::ImageLayout::TransferSrcOpt,
+                &buf,
+                &[br::vk::VkBufferImageCopy {
+                    bufferOffset: 0,
+                    bufferRowLength: self.size.width,
+                    bufferImageHeight: self.size.height,
+                    imageSubresource: br::ImageSubresourceLayers::new(
+                        br::AspectMask::COLOR,
+                        0,
+                        0..1,
+                    ),
+                    imageOffset: br::Offset3D::ZERO,
+                    imageExtent: self.size.with_depth(1),
+                }],
+            )
+            .inject(|r| {
+                inject_cmd_pipeline_barrier_2(
+                    r,
+                    base_sys.subsystem,
+                    &br::DependencyInfo::new(
+                        &[br::MemoryBarrier2::new()
+                            .from(
+                                br::PipelineStageFlags2::COPY,
+                                br::AccessFlags2::TRANSFER.write,
+                            )
+                            .to(br::PipelineStageFlags2::HOST, br::AccessFlags2::HOST.read)],
+                        &[],
+                        &[],
+                    ),
+                )
+            })
+        })?;
+
+        let mapped = buf.map(0..byte_length, BufferMapMode::Read)?;
+        let mut pixels = vec![0u8; byte_length];
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                mapped.addr_of_mut::<u8>(0),
+                pixels.as_mut_ptr(),
+                byte_length,
+            );
+        }
+        mapped.unmap()?;
+
+        Ok(pixels)
+    }
+}
+impl CompositeRenderTarget for OffscreenRenderTarget<'_> {
+    #[inline(always)]
+    fn extent(&self) -> br::Extent2D {
+        self.size
+    }
+
+    #[inline(always)]
+    fn color_format(&self) -> br::Format {
+        self.pixel_format.vk_format()
+    }
+
+    #[inline(always)]
+    fn backbuffer_count(&self) -> usize {
+        1
+    }
+
+    #[inline(always)]
+    fn backbuffer_image<'x>(&'x self, index: usize) -> br::VkHandleRef<'x, br::vk::VkImage> {
+        assert_eq!(index, 0, "offscreen render target has only one backbuffer");
+
+        unsafe { br::VkHandleRef::dangling(self.texture.as_image().native_ptr()) }
+    }
+
+    #[inline]
+    fn backbuffer_views<'x>(
+        &'x self,
+    ) -> impl Iterator<Item = br::VkHandleRef<'x, br::vk::VkImageView>> + 'x {
+        core::iter::once(unsafe { br::VkHandleRef::dangling(self.texture.native_ptr()) })
+    }
+
+    #[inline(always)]
+    fn presented_layout(&self) -> br::ImageLayout {
+        // Note: 描き終わったらそのままコピーや読み戻しができるようにしておく
+        br::ImageLayout::TransferSrcOpt
+    }
+}
@@ -0,0 +1,309 @@
+//! 再起動をまたいで残すユーザー設定
+
+use std::{
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::app_state::ArrangeOrder;
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// ウィンドウのクライアント領域の大きさ(論理ピクセル)
+    pub window_size: Option<(u32, u32)>,
+    pub arrange_allow_rotation: bool,
+    pub arrange_gap: u32,
+    pub arrange_order: ArrangeOrder,
+    pub sprite_list_visible: bool,
+    /// 起動したときに最後に開いていたアセットを開き直す
+    pub reopen_last_atlas: bool,
+    /// 最近開いた/保存したアセット(新しい順)
+    pub recent_files: Vec<PathBuf>,
+}
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            window_size: None,
+            arrange_allow_rotation: false,
+            arrange_gap: 0,
+            arrange_order: ArrangeOrder::ListOrder,
+            sprite_list_visible: true,
+            reopen_last_atlas: false,
+            recent_files: Vec::new(),
+        }
+    }
+}
+impl Settings {
+    /// 設定ファイルから読む(なければデフォルト)
+    #[tracing::instrument(name = "Settings::load")]
+    pub fn load() -> Self {
+        let Some(path) = settings_file_path() else {
+            tracing::warn!("no config directory found, settings will not be persisted");
+            return Self::default();
+        };
+
+        let fp = match std::fs::File::open(&path) {
+            Ok(x) => x,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                tracing::warn!(path = %path.display(), reason = ?e, "opening settings failed");
+                return Self::default();
+            }
+        };
+
+        match Self::read(&mut std::io::BufReader::new(fp)) {
+            Ok(x) => {
+                tracing::info!(path = %path.display(), "settings loaded");
+                x
+            }
+            Err(e) => {
+                tracing::warn!(path = %path.display(), reason = ?e, "reading settings failed");
+                Self::default()
+            }
+        }
+    }
+
+    #[tracing::instrument(name = "Settings::save", skip(self), err(Display))]
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(path) = settings_file_path() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        // Note: 書いている途中で落ちても前の設定が残るように別のファイルに書いてから置き換える
+        let temp_path = path.with_extension("new");
+        self.write(&mut std::io::BufWriter::new(std::fs::File::create(
+            &temp_path,
+        )?))?;
+        std::fs::rename(&temp_path, &path)
+    }
+
+    fn write(&self, sink: &mut (impl Write + ?Sized)) -> std::io::Result<()> {
+        if let Some((w, h)) = self.window_size {
+            writeln!(sink, "window_size={w},{h}")?;
+        }
+        writeln!(
+            sink,
+            "arrange={},{},{}",
+            if self.arrange_allow_rotation { 1 } else { 0 },
+            self.arrange_gap,
+            match self.arrange_order {
+                ArrangeOrder::ListOrder => 0,
+                ArrangeOrder::LargestFirst => 1,
+            }
+        )?;
+        writeln!(
+            sink,
+            "sprite_list_visible={}",
+            if self.sprite_list_visible { 1 } else { 0 }
+        )?;
+        writeln!(
+            sink,
+            "reopen_last_atlas={}",
+            if self.reopen_last_atlas { 1 } else { 0 }
+        )?;
+        for p in self.recent_files.iter() {
+            let Some(encoded) = encode_path(p) else {
+                tracing::warn!(path = %p.display(), "path cannot be persisted, skipping");
+                continue;
+            };
+
+            writeln!(sink, "recent_path={encoded}")?;
+        }
+
+        sink.flush()
+    }
+
+    /// 知らないキーや読めない値は無視する(新しいバージョンで書かれたものも読めるように)
+    fn read(src: &mut (impl BufRead + ?Sized)) -> std::io::Result<Self> {
+        let mut settings = Self::default();
+
+        for l in src.lines() {
+            let l = l?;
+            let Some((key, value)) = l.split_once('=') else {
+                continue;
+            };
+
+            match key {
+                "window_size" => {
+                    if let Some((w, h)) = value.split_once(',')
+                        && let (Ok(w), Ok(h)) = (w.parse(), h.parse())
+                    {
+                        settings.window_size = Some((w, h));
+                    }
+                }
+                "arrange" => {
+                    let mut values = value.split(',');
+                    settings.arrange_allow_rotation = values.next() == Some("1");
+                    settings.arrange_gap = values.next().and_then(|g| g.parse().ok()).unwrap_or(0);
+                    settings.arrange_order = match values.next() {
+                        Some("1") => ArrangeOrder::LargestFirst,
+                        _ => ArrangeOrder::ListOrder,
+                    };
+                }
+                "sprite_list_visible" => settings.sprite_list_visible = value == "1",
+                "reopen_last_atlas" => settings.reopen_last_atlas = value == "1",
+                "recent_path" => {
+                    if let Some(p) = decode_path(value) {
+                        settings.recent_files.push(p);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(settings)
+    }
+}
+
+/// パスを1行に収まるように書く
+///
+/// `%`と制御文字とASCII以外のバイトをパーセントエンコードする(unixではUTF-8でないパスもそのまま残せる)
+fn encode_path(path: &Path) -> Option<String> {
+    #[cfg(unix)]
+    let bytes = {
+        use std::os::unix::ffi::OsStrExt;
+
+        path.as_os_str().as_bytes()
+    };
+    // Note: Windowsでは対になっていないサロゲートを含むパスは扱わない
+    #[cfg(not(unix))]
+    let bytes = path.to_str()?.as_bytes();
+
+    let mut encoded = String::with_capacity(bytes.len());
+    for &b in bytes {
+        if b == b'%' || b.is_ascii_control() || !b.is_ascii() {
+            encoded.push_str(&format!("%{b:02X}"));
+        } else {
+            encoded.push(b as char);
+        }
+    }
+
+    Some(encoded)
+}
+
+fn decode_path(encoded: &str) -> Option<PathBuf> {
+    let hex = |b: Option<&u8>| b.and_then(|&b| (b as char).to_digit(16));
+
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut iter = encoded.as_bytes().iter();
+    while let Some(&b) = iter.next() {
+        if b != b'%' {
+            bytes.push(b);
+            continue;
+        }
+
+        let (Some(hi), Some(lo)) = (hex(iter.next()), hex(iter.next())) else {
+            return None;
+        };
+        bytes.push((hi << 4 | lo) as u8);
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+
+        Some(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+    }
+    #[cfg(not(unix))]
+    {
+        String::from_utf8(bytes).ok().map(PathBuf::from)
+    }
+}
+
+/// Platform specific config file path
+fn settings_file_path() -> Option<PathBuf> {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|x| !x.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|x| PathBuf::from(x).join(".config")))?;
+    #[cfg(target_os = "macos")]
+    let base = {
+        let fm = objc_rt::foundation::NSFileManager::default();
+        let url = fm
+            .url_for_directory(
+                objc_rt::foundation::NSSearchPathDirectory::ApplicationSupportDirectory,
+                objc_rt::foundation::NSSearchPathDomainMask::UserDomainMask,
+                None,
+                true,
+            )
+            .ok()?;
+
+        PathBuf::from(url.file_system_representation().to_str().ok()?)
+    };
+    #[cfg(windows)]
+    let base = PathBuf::from(std::env::var_os("APPDATA")?);
+
+    Some(base.join("peridot/sprite-atlas-visualizer/settings"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_load_round_trip() {
+        let settings = Settings {
+            window_size: Some((1280, 720)),
+            arrange_allow_rotation: true,
+            arrange_gap: 4,
+            arrange_order: ArrangeOrder::LargestFirst,
+            sprite_list_visible: false,
+            reopen_last_atlas: true,
+            recent_files: vec![
+                PathBuf::from("/home/user/atlas.peridot"),
+                PathBuf::from("/home/user/with space/100%=done.peridot"),
+                PathBuf::from("/home/user/line\nbreak\r.peridot"),
+                PathBuf::from("/home/user/日本語.peridot"),
+            ],
+        };
+
+        let mut buf = Vec::new();
+        settings.write(&mut buf).unwrap();
+        // 改行を含むパスがあっても1項目1行のまま
+        assert_eq!(
+            buf.split(|&b| b == b'\n').filter(|l| !l.is_empty()).count(),
+            8
+        );
+
+        let loaded = Settings::read(&mut &buf[..]).unwrap();
+        assert_eq!(loaded.window_size, settings.window_size);
+        assert_eq!(
+            loaded.arrange_allow_rotation,
+            settings.arrange_allow_rotation
+        );
+        assert_eq!(loaded.arrange_gap, settings.arrange_gap);
+        assert_eq!(loaded.arrange_order, settings.arrange_order);
+        assert_eq!(loaded.sprite_list_visible, settings.sprite_list_visible);
+        assert_eq!(loaded.reopen_last_atlas, settings.reopen_last_atlas);
+        assert_eq!(loaded.recent_files, settings.recent_files);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_path_round_trip() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = PathBuf::from(std::ffi::OsStr::from_bytes(b"/tmp/\xff\xfe.peridot"));
+        let settings = Settings {
+            recent_files: vec![path.clone()],
+            ..Default::default()
+        };
+
+        let mut buf = Vec::new();
+        settings.write(&mut buf).unwrap();
+        assert!(std::str::from_utf8(&buf).is_ok());
+        assert_eq!(Settings::read(&mut &buf[..]).unwrap().recent_files, [path]);
+    }
+
+    #[test]
+    fn read_skips_broken_and_unknown_entries() {
+        let src = b"recent_path=/broken%4\nrecent_path=/ok%25.peridot\nunknown=1\n";
+        let loaded = Settings::read(&mut &src[..]).unwrap();
+
+        assert_eq!(loaded.recent_files, [PathBuf::from("/ok%.peridot")]);
+    }
+}
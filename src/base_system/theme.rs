@@ -0,0 +1,154 @@
+//! UIの配色テーマ
+
+use crate::composite::{
+    AnimatableColor, AnimationCurve, CompositeTree, CompositeTreeFloatParameterRef,
+    CompositeTreeParameterStore, FloatParameter,
+};
+
+const TRANSITION_DURATION: f32 = 0.25;
+
+/// アクセントカラーが指定されていないときに使う色
+pub const DEFAULT_ACCENT_COLOR: [f32; 3] = [0.6, 0.8, 1.0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ThemeColor {
+    /// 通常のテキスト
+    Text,
+    /// 補助的なテキスト
+    TextSecondary,
+    /// 注意を引くテキスト(ソースが見つからないなど)
+    TextWarning,
+    /// 背景に対して最もコントラストの高い色 ホバー時のハイライトなどはこれのalphaを下げて使う
+    Foreground,
+    /// ポップアップの背景
+    PopupBackground,
+    /// ポップアップの枠線
+    PopupBorder,
+    /// ツールチップの背景
+    TooltipBackground,
+    /// ドロップダウンなどのメニューの背景
+    MenuBackground,
+    /// ヘッダーや入力欄など、背景から一段沈めて見せる面
+    Backdrop,
+    /// アクセントカラー
+    Accent,
+}
+impl ThemeColor {
+    /// (ライト, ダーク)の色
+    const fn scheme_values(self) -> ([f32; 4], [f32; 4]) {
+        match self {
+            Self::Text => ([0.1, 0.1, 0.1, 1.0], [0.9, 0.9, 0.9, 1.0]),
+            Self::TextSecondary => ([0.35, 0.35, 0.35, 1.0], [0.7, 0.7, 0.7, 1.0]),
+            Self::TextWarning => ([0.75, 0.3, 0.0, 1.0], [1.0, 0.6, 0.35, 1.0]),
+            Self::Foreground => ([0.0, 0.0, 0.0, 1.0], [1.0, 1.0, 1.0, 1.0]),
+            Self::PopupBackground => ([0.96, 0.96, 0.96, 1.0], [0.0, 0.0, 0.0, 1.0]),
+            Self::PopupBorder => ([0.75, 0.75, 0.75, 1.0], [0.25, 0.25, 0.25, 1.0]),
+            Self::TooltipBackground => ([0.95, 0.95, 0.95, 0.9], [0.1, 0.1, 0.1, 0.9]),
+            Self::MenuBackground => ([0.98, 0.98, 0.98, 0.95], [0.15, 0.15, 0.15, 0.95]),
+            Self::Backdrop => ([1.0, 1.0, 1.0, 0.375], [0.0, 0.0, 0.0, 0.25]),
+            // Note: アクセントカラーはパラメータから取るのでここの値は使わない
+            Self::Accent => ([1.0; 4], [1.0; 4]),
+        }
+    }
+}
+
+/// 配色テーマのパラメータ参照
+///
+/// 実際の値はCompositeTreeのパラメータとして持っているので、切り替えるとこのパレットから作った色はすべてアニメーションしながら追従する
+#[derive(Clone, Copy)]
+pub struct ThemePalette {
+    /// 0でライト、1でダーク
+    dark_rate: CompositeTreeFloatParameterRef,
+    accent: [CompositeTreeFloatParameterRef; 3],
+}
+impl ThemePalette {
+    pub fn new(ct: &mut CompositeTree, scheme: ColorScheme) -> Self {
+        let ps = ct.parameter_store_mut();
+
+        Self {
+            dark_rate: ps.alloc_float(FloatParameter::Value(match scheme {
+                ColorScheme::Light => 0.0,
+                ColorScheme::Dark => 1.0,
+            })),
+            accent: DEFAULT_ACCENT_COLOR.map(|x| ps.alloc_float(FloatParameter::Value(x))),
+        }
+    }
+
+    pub fn evaluate(&self, ps: &CompositeTreeParameterStore, color: ThemeColor) -> [f32; 4] {
+        if let ThemeColor::Accent = color {
+            let [r, g, b] = self.accent.map(|x| ps.float_value(x));
+
+            return [r, g, b, 1.0];
+        }
+
+        let (light, dark) = color.scheme_values();
+        let t = ps.float_value(self.dark_rate);
+
+        core::array::from_fn(|n| light[n] + (dark[n] - light[n]) * t)
+    }
+
+    pub fn color(&self, color: ThemeColor) -> AnimatableColor {
+        let this = *self;
+
+        AnimatableColor::Expression(Box::new(move |ps| this.evaluate(ps, color)))
+    }
+
+    /// alphaを掛けた色
+    pub fn color_with_alpha(&self, color: ThemeColor, alpha: f32) -> AnimatableColor {
+        let this = *self;
+
+        AnimatableColor::Expression(Box::new(move |ps| {
+            let [r, g, b, a] = this.evaluate(ps, color);
+
+            [r, g, b, a * alpha]
+        }))
+    }
+
+    pub fn transition_scheme(&self, ct: &mut CompositeTree, scheme: ColorScheme, current_sec: f32) {
+        Self::animate_to(
+            ct,
+            self.dark_rate,
+            match scheme {
+                ColorScheme::Light => 0.0,
+                ColorScheme::Dark => 1.0,
+            },
+            current_sec,
+        );
+        ct.mark_dirty(CompositeTree::ROOT);
+    }
+
+    pub fn transition_accent_color(&self, ct: &mut CompositeTree, rgb: [f32; 3], current_sec: f32) {
+        for (&r, v) in self.accent.iter().zip(rgb) {
+            Self::animate_to(ct, r, v, current_sec);
+        }
+        ct.mark_dirty(CompositeTree::ROOT);
+    }
+
+    fn animate_to(
+        ct: &mut CompositeTree,
+        r: CompositeTreeFloatParameterRef,
+        to_value: f32,
+        current_sec: f32,
+    ) {
+        let ps = ct.parameter_store_mut();
+        let from_value = ps.evaluate_float(r, current_sec);
+
+        ps.set_float(
+            r,
+            FloatParameter::Animated {
+                start_sec: current_sec,
+                end_sec: current_sec + TRANSITION_DURATION,
+                from_value,
+                to_value,
+                curve: AnimationCurve::Linear,
+                event_on_complete: None,
+            },
+        );
+    }
+}
@@ -9,7 +9,7 @@ use std::{
 
 use crossbeam::{
     channel::TryRecvError,
-    deque::{Injector, Worker},
+    deque::{Injector, Steal, Worker},
 };
 
 pub enum BackgroundWork<'subsystem> {
@@ -22,6 +22,8 @@ pub enum BackgroundWork<'subsystem> {
 pub enum BackgroundWorkerViewFeedback {
     BeginWork(usize, String),
     EndWork(usize),
+    /// スプライトのソース画像が読めなかった
+    LoadSpriteSourceFailed(PathBuf),
 }
 
 #[derive(Clone)]
@@ -53,11 +55,87 @@ impl<'subsystem> BackgroundWorkerEnqueueWeakAccess<'subsystem> {
 #[cfg(target_os = "macos")]
 pub struct MainThreadWaker;
 
+#[cfg(target_os = "linux")]
+type MainThreadWakerObject = linux_eventfd::EventFD;
+#[cfg(windows)]
+type MainThreadWakerObject = crate::platform::win32::event::EventObject;
+#[cfg(target_os = "macos")]
+type MainThreadWakerObject = MainThreadWaker;
+
+fn send_view_feedback(
+    sender: &crossbeam::channel::Sender<BackgroundWorkerViewFeedback>,
+    main_thread_waker: &MainThreadWakerObject,
+    feedback: BackgroundWorkerViewFeedback,
+) {
+    match sender.send(feedback) {
+        Ok(()) => (),
+        Err(e) => {
+            tracing::warn!(reason = ?e, "sending view feedback failed");
+        }
+    }
+    #[cfg(target_os = "linux")]
+    match main_thread_waker.add(1) {
+        Ok(_) => (),
+        Err(e) => {
+            tracing::warn!(reason = ?e, "waking main thread failed");
+        }
+    }
+    #[cfg(windows)]
+    match main_thread_waker.set() {
+        Ok(_) => (),
+        Err(e) => {
+            tracing::warn!(reason = ?e, "waking main thread failed");
+        }
+    }
+    #[cfg(target_os = "macos")]
+    let _ = main_thread_waker;
+}
+
+/// 1つの仕事を処理する(ワーカースレッドと[`BackgroundWorker::run_pending`]で共有)
+fn process_work(
+    thread_number: usize,
+    work: BackgroundWork,
+    view_feedback_sender: &crossbeam::channel::Sender<BackgroundWorkerViewFeedback>,
+    main_thread_waker: &MainThreadWakerObject,
+) {
+    match work {
+        BackgroundWork::LoadSpriteSource(path, mut on_complete) => {
+            send_view_feedback(
+                view_feedback_sender,
+                main_thread_waker,
+                BackgroundWorkerViewFeedback::BeginWork(
+                    thread_number,
+                    format!("Loading {}", path.display()),
+                ),
+            );
+
+            match image::open(&path) {
+                Ok(img) => on_complete(path, img),
+                Err(e) => {
+                    tracing::warn!(?path, reason = ?e, "loading sprite source failed");
+                    send_view_feedback(
+                        view_feedback_sender,
+                        main_thread_waker,
+                        BackgroundWorkerViewFeedback::LoadSpriteSourceFailed(path),
+                    );
+                }
+            }
+
+            send_view_feedback(
+                view_feedback_sender,
+                main_thread_waker,
+                BackgroundWorkerViewFeedback::EndWork(thread_number),
+            );
+        }
+    }
+}
+
 pub struct BackgroundWorker<'subsystem> {
     join_handles: Vec<JoinHandle<()>>,
     work_queue: Arc<Injector<BackgroundWork<'subsystem>>>,
     teardown_signal: Arc<AtomicBool>,
     view_feedback_receiver: crossbeam::channel::Receiver<BackgroundWorkerViewFeedback>,
+    view_feedback_sender: crossbeam::channel::Sender<BackgroundWorkerViewFeedback>,
     #[cfg(target_os = "linux")]
     main_thread_waker: Arc<linux_eventfd::EventFD>,
     #[cfg(windows)]
@@ -67,7 +145,19 @@ pub struct BackgroundWorker<'subsystem> {
 }
 impl<'subsystem> BackgroundWorker<'subsystem> {
     pub fn new() -> Self {
-        let worker_count = std::thread::available_parallelism().map_or(4, core::num::NonZero::get);
+        Self::with_worker_count(
+            std::thread::available_parallelism().map_or(4, core::num::NonZero::get),
+        )
+    }
+
+    /// ワーカースレッドを作らない(積まれた仕事は[`Self::run_pending`]を呼んだスレッドで処理する)
+    ///
+    /// 読み込みの完了するタイミングを固定したいスナップショットテストや入力の再生向け
+    pub fn new_synchronous() -> Self {
+        Self::with_worker_count(0)
+    }
+
+    fn with_worker_count(worker_count: usize) -> Self {
         let work_queue = Injector::new();
         let (mut join_handles, mut local_queues, mut stealers) = (
             Vec::with_capacity(worker_count),
@@ -97,8 +187,8 @@ impl<'subsystem> BackgroundWorker<'subsystem> {
         #[cfg(target_os = "macos")]
         let main_thread_waker = Arc::new(MainThreadWaker);
         for (n, local_queue) in local_queues.into_iter().enumerate() {
-            join_handles.push(
-                unsafe {std::thread::Builder::new()
+            join_handles.push(unsafe {
+                std::thread::Builder::new()
                     .name(format!("Background Worker #{}", n + 1))
                     .spawn_unchecked({
                         let stealers = stealers.clone();
@@ -120,52 +210,12 @@ impl<'subsystem> BackgroundWorker<'subsystem> {
                                 });
 
                                 match next {
-                                    Some(BackgroundWork::LoadSpriteSource(path, mut on_complete)) => {
-                                        match view_feedback_sender.send(BackgroundWorkerViewFeedback::BeginWork(n, format!("Loading {}", path.display()))) {
-                                            Ok(()) => (),
-                                            Err(e) => {
-                                                tracing::warn!(reason = ?e, "sending view feedback failed");
-                                            }
-                                        }
-                                        #[cfg(target_os = "linux")]
-                                        match main_thread_waker.add(1) {
-                                            Ok(_) => (),
-                                            Err(e) => {
-                                                tracing::warn!(reason = ?e, "waking main thread failed");
-                                            }
-                                        }
-                                        #[cfg(windows)]
-                                        match main_thread_waker.set() {
-                                            Ok(_) => (),
-                                            Err(e) => {
-                                                tracing::warn!(reason = ?e, "waking main thread failed");
-                                            }
-                                        }
-
-                                        let img = image::open(&path).unwrap();
-                                        on_complete(path, img);
-
-                                        match view_feedback_sender.send(BackgroundWorkerViewFeedback::EndWork(n)) {
-                                            Ok(()) => (),
-                                            Err(e) => {
-                                                tracing::warn!(reason = ?e, "sending view feedback failed");
-                                            }
-                                        }
-                                        #[cfg(target_os = "linux")]
-                                        match main_thread_waker.add(1) {
-                                            Ok(_) => (),
-                                            Err(e) => {
-                                                tracing::warn!(reason = ?e, "waking main thread failed");
-                                            }
-                                        }
-                                        #[cfg(windows)]
-                                        match main_thread_waker.set() {
-                                            Ok(_) => (),
-                                            Err(e) => {
-                                                tracing::warn!(reason = ?e, "waking main thread failed");
-                                            }
-                                        }
-                                    }
+                                    Some(work) => process_work(
+                                        n,
+                                        work,
+                                        &view_feedback_sender,
+                                        &main_thread_waker,
+                                    ),
                                     None => {
                                         // wait for new event
                                         // TODO: 一旦sleep(1)する（本当はparkとかしてあげたほうがいい）
@@ -175,8 +225,8 @@ impl<'subsystem> BackgroundWorker<'subsystem> {
                             }
                         }
                     })
-                    .unwrap()},
-            );
+                    .unwrap()
+            });
         }
 
         tracing::info!(parallelism = worker_count, "BackgroundWorker initialized");
@@ -186,6 +236,7 @@ impl<'subsystem> BackgroundWorker<'subsystem> {
             work_queue,
             teardown_signal,
             view_feedback_receiver,
+            view_feedback_sender,
             main_thread_waker,
         }
     }
@@ -237,6 +288,19 @@ impl<'subsystem> BackgroundWorker<'subsystem> {
         BackgroundWorkerEnqueueAccess(self.work_queue.clone())
     }
 
+    /// 積まれている仕事を呼び出したスレッドですべて処理する
+    pub fn run_pending(&self) {
+        loop {
+            match self.work_queue.steal() {
+                Steal::Success(work) => {
+                    process_work(0, work, &self.view_feedback_sender, &self.main_thread_waker)
+                }
+                Steal::Empty => break,
+                Steal::Retry => continue,
+            }
+        }
+    }
+
     pub fn teardown(self) {
         self.teardown_signal.store(true, Ordering::Release);
         for x in self.join_handles {
@@ -244,3 +308,55 @@ impl<'subsystem> BackgroundWorker<'subsystem> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn synchronous_worker_runs_pending_works_on_caller() {
+        let dir = std::env::temp_dir().join(format!("bg-worker-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.png");
+        image::RgbaImage::from_pixel(3, 2, image::Rgba([1, 2, 3, 255]))
+            .save(&path)
+            .unwrap();
+
+        let worker = BackgroundWorker::new_synchronous();
+        let loaded = Arc::new(parking_lot::Mutex::new(None));
+        worker
+            .enqueue_access()
+            .enqueue(BackgroundWork::LoadSpriteSource(
+                path,
+                Box::new({
+                    let loaded = loaded.clone();
+                    move |_, image| *loaded.lock() = Some((image.width(), image.height()))
+                }),
+            ));
+        worker
+            .enqueue_access()
+            .enqueue(BackgroundWork::LoadSpriteSource(
+                dir.join("missing.png"),
+                Box::new(|_, _| unreachable!()),
+            ));
+        assert!(loaded.lock().is_none());
+
+        worker.run_pending();
+        assert_eq!(*loaded.lock(), Some((3, 2)));
+        let feedbacks = core::iter::from_fn(|| worker.try_pop_view_feedback()).collect::<Vec<_>>();
+        assert_eq!(feedbacks.len(), 5);
+        assert!(matches!(
+            &feedbacks[..],
+            [
+                BackgroundWorkerViewFeedback::BeginWork(0, _),
+                BackgroundWorkerViewFeedback::EndWork(0),
+                BackgroundWorkerViewFeedback::BeginWork(0, _),
+                BackgroundWorkerViewFeedback::LoadSpriteSourceFailed(_),
+                BackgroundWorkerViewFeedback::EndWork(0),
+            ]
+        ));
+
+        worker.teardown();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
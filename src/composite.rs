@@ -1,6 +1,9 @@
 //! UI Rect Compositioning
 
-use std::collections::{BTreeSet, HashMap};
+use std::{
+    collections::{BTreeSet, HashMap},
+    hash::{DefaultHasher, Hash, Hasher},
+};
 
 use bedrock::{
     self as br, DescriptorPoolMut, Device, DeviceChildHandle, Image, ImageChild, MemoryBound,
@@ -9,7 +12,7 @@ use bedrock::{
 
 use crate::{
     AppEvent, AppEventBus, BLEND_STATE_SINGLE_NONE, IA_STATE_TRILIST, MS_STATE_EMPTY,
-    PrimaryRenderTarget, RASTER_STATE_DEFAULT_FILL_NOCULL, VI_STATE_EMPTY,
+    RASTER_STATE_DEFAULT_FILL_NOCULL, VI_STATE_EMPTY,
     atlas::{AtlasRect, DynamicAtlasManager},
     base_system::{
         AppBaseSystem, inject_cmd_begin_render_pass2, inject_cmd_end_render_pass2,
@@ -49,13 +52,18 @@ pub struct CompositeInstanceData {
     pub pos_height_animation_data: [f32; 4],
     /// h_p1x, h_p1y, h_p2x, h_p2y
     pub pos_height_curve_control_points: [f32; 4],
+    /// LinearGradient: start_x, start_y, end_x, end_y / RadialGradient: center_x, center_y, radius_x, radius_y / InnerGlow: width_px, reserved...
+    pub effect_params: [f32; 4],
+    pub gradient_stop_offsets: [f32; 4],
+    /// グラデーションの各ストップの色 / InnerGlowでは0番目がglow_color
+    pub effect_colors: [[f32; 4]; MAX_GRADIENT_STOPS],
 }
 
 pub const COMPOSITE_PUSH_CONSTANT_RANGES: &'static [br::PushConstantRange] = &[
     // { screen_x_pixels: f32, screen_y_pixels: f32 }
     br::PushConstantRange::new(br::vk::VK_SHADER_STAGE_VERTEX_BIT, 0..8),
-    // { rect_mask_left: f32, rect_mask_top: f32, rect_mask_right: f32, rect_mask_bottom: f32, rect_mask_left_softness: f32, rect_mask_top_softness: f32, rect_mask_right_softness: f32, rect_mask_bottom_softness: f32 }
-    br::PushConstantRange::new(br::vk::VK_SHADER_STAGE_FRAGMENT_BIT, 16..48),
+    // { rect_mask_left: f32, rect_mask_top: f32, rect_mask_right: f32, rect_mask_bottom: f32, rect_mask_left_softness: f32, rect_mask_top_softness: f32, rect_mask_right_softness: f32, rect_mask_bottom_softness: f32, damage_left: f32, damage_top: f32, damage_right: f32, damage_bottom: f32 }
+    br::PushConstantRange::new(br::vk::VK_SHADER_STAGE_FRAGMENT_BIT, 16..64),
 ];
 
 #[repr(C)]
@@ -69,6 +77,36 @@ pub enum CompositeMode {
     FillColor(AnimatableColor),
     ColorTintBackdropBlur(AnimatableColor, AnimatableFloat),
     FillColorBackdropBlur(AnimatableColor, AnimatableFloat),
+    /// マスク（rの値）をアルファとして線形グラデーションで塗る
+    ///
+    /// `start`/`end`はrect内の相対座標(0..1)
+    LinearGradient {
+        start: [f32; 2],
+        end: [f32; 2],
+        stops: Vec<GradientStop>,
+    },
+    /// マスク（rの値）をアルファとして放射状グラデーションで塗る
+    ///
+    /// `center`/`radius`はrect内の相対座標(0..1)
+    RadialGradient {
+        center: [f32; 2],
+        radius: [f32; 2],
+        stops: Vec<GradientStop>,
+    },
+    /// マスク（rの値）をアルファとして`color`で塗り、内側の縁から`width`(px)の範囲を`glow_color`で光らせる
+    InnerGlow {
+        color: AnimatableColor,
+        glow_color: AnimatableColor,
+        width: AnimatableFloat,
+    },
+    /// マスクの形状の影だけを描画する（本体は別のrectを上に重ねて描画する）
+    ///
+    /// `offset`(px)だけずらしてから`stdev`でぼかす（`stdev`の定義は`ColorTintBackdropBlur`などと同じ）
+    DropShadow {
+        color: AnimatableColor,
+        offset: [AnimatableFloat; 2],
+        stdev: AnimatableFloat,
+    },
 }
 impl CompositeMode {
     const fn shader_mode_value(&self) -> f32 {
@@ -78,10 +116,272 @@ impl CompositeMode {
             Self::FillColor(_) => 2.0,
             Self::ColorTintBackdropBlur(_, _) => 3.0,
             Self::FillColorBackdropBlur(_, _) => 4.0,
+            Self::LinearGradient { .. } => 6.0,
+            Self::RadialGradient { .. } => 7.0,
+            Self::InnerGlow { .. } => 8.0,
+            // 影の形状自体はColorTintと同じ（ぼかしはレイヤーで行う）
+            Self::DropShadow { .. } => 1.0,
+        }
+    }
+
+    /// backdropを参照できない場所（オフスクリーンレイヤー内）で使うモード値
+    const fn shader_mode_value_without_backdrop(&self) -> f32 {
+        match self {
+            Self::ColorTintBackdropBlur(_, _) => 1.0,
+            Self::FillColorBackdropBlur(_, _) => 2.0,
+            _ => self.shader_mode_value(),
+        }
+    }
+
+    fn is_settling_at(&self, current_sec: f32) -> bool {
+        match self {
+            Self::DirectSourceOver => false,
+            Self::ColorTint(t) | Self::FillColor(t) => t.is_settling_at(current_sec),
+            Self::ColorTintBackdropBlur(t, stdev) | Self::FillColorBackdropBlur(t, stdev) => {
+                t.is_settling_at(current_sec) || stdev.is_settling_at(current_sec)
+            }
+            Self::LinearGradient { stops, .. } | Self::RadialGradient { stops, .. } => {
+                stops.iter().any(|x| x.color.is_settling_at(current_sec))
+            }
+            Self::InnerGlow {
+                color,
+                glow_color,
+                width,
+            } => {
+                color.is_settling_at(current_sec)
+                    || glow_color.is_settling_at(current_sec)
+                    || width.is_settling_at(current_sec)
+            }
+            Self::DropShadow {
+                color,
+                offset,
+                stdev,
+            } => {
+                color.is_settling_at(current_sec)
+                    || offset.iter().any(|x| x.is_settling_at(current_sec))
+                    || stdev.is_settling_at(current_sec)
+            }
+        }
+    }
+
+    fn next_change_sec(&self, current_sec: f32) -> Option<f32> {
+        match self {
+            Self::DirectSourceOver => None,
+            Self::ColorTint(t) | Self::FillColor(t) => t.next_change_sec(current_sec),
+            Self::ColorTintBackdropBlur(t, stdev) | Self::FillColorBackdropBlur(t, stdev) => {
+                earliest_sec([
+                    t.next_change_sec(current_sec),
+                    stdev.next_change_sec(current_sec),
+                ])
+            }
+            Self::LinearGradient { stops, .. } | Self::RadialGradient { stops, .. } => {
+                earliest_sec(stops.iter().map(|x| x.color.next_change_sec(current_sec)))
+            }
+            Self::InnerGlow {
+                color,
+                glow_color,
+                width,
+            } => earliest_sec([
+                color.next_change_sec(current_sec),
+                glow_color.next_change_sec(current_sec),
+                width.next_change_sec(current_sec),
+            ]),
+            Self::DropShadow {
+                color,
+                offset,
+                stdev,
+            } => earliest_sec([
+                color.next_change_sec(current_sec),
+                offset[0].next_change_sec(current_sec),
+                offset[1].next_change_sec(current_sec),
+                stdev.next_change_sec(current_sec),
+            ]),
+        }
+    }
+
+    fn process_on_complete(&mut self, current_sec: f32, q: &AppEventBus) {
+        match self {
+            Self::DirectSourceOver => (),
+            Self::ColorTint(t) | Self::FillColor(t) => t.process_on_complete(current_sec, q),
+            Self::ColorTintBackdropBlur(t, stdev) | Self::FillColorBackdropBlur(t, stdev) => {
+                t.process_on_complete(current_sec, q);
+                stdev.process_on_complete(current_sec, q);
+            }
+            Self::LinearGradient { stops, .. } | Self::RadialGradient { stops, .. } => {
+                for x in stops.iter_mut() {
+                    x.color.process_on_complete(current_sec, q);
+                }
+            }
+            Self::InnerGlow {
+                color,
+                glow_color,
+                width,
+            } => {
+                color.process_on_complete(current_sec, q);
+                glow_color.process_on_complete(current_sec, q);
+                width.process_on_complete(current_sec, q);
+            }
+            Self::DropShadow {
+                color,
+                offset,
+                stdev,
+            } => {
+                color.process_on_complete(current_sec, q);
+                offset[0].process_on_complete(current_sec, q);
+                offset[1].process_on_complete(current_sec, q);
+                stdev.process_on_complete(current_sec, q);
+            }
+        }
+    }
+
+    /// return: (color_tint, effect_params, gradient_stop_offsets, effect_colors)
+    fn shader_parameters(
+        &self,
+        current_sec: f32,
+        parameter_store: &CompositeTreeParameterStore,
+        base_scale_factor: f32,
+    ) -> ([f32; 4], [f32; 4], [f32; 4], [[f32; 4]; MAX_GRADIENT_STOPS]) {
+        match self {
+            Self::DirectSourceOver => {
+                ([0.0; 4], [0.0; 4], [0.0; 4], [[0.0; 4]; MAX_GRADIENT_STOPS])
+            }
+            Self::ColorTint(t)
+            | Self::FillColor(t)
+            | Self::ColorTintBackdropBlur(t, _)
+            | Self::FillColorBackdropBlur(t, _)
+            | Self::DropShadow { color: t, .. } => (
+                t.evaluate(current_sec, parameter_store),
+                [0.0; 4],
+                [0.0; 4],
+                [[0.0; 4]; MAX_GRADIENT_STOPS],
+            ),
+            &Self::LinearGradient {
+                start: [sx, sy],
+                end: [ex, ey],
+                ref stops,
+            } => {
+                let (offsets, colors) =
+                    gradient_stop_parameters(stops, current_sec, parameter_store);
+
+                ([0.0; 4], [sx, sy, ex, ey], offsets, colors)
+            }
+            &Self::RadialGradient {
+                center: [cx, cy],
+                radius: [rx, ry],
+                ref stops,
+            } => {
+                let (offsets, colors) =
+                    gradient_stop_parameters(stops, current_sec, parameter_store);
+
+                ([0.0; 4], [cx, cy, rx, ry], offsets, colors)
+            }
+            Self::InnerGlow {
+                color,
+                glow_color,
+                width,
+            } => {
+                let mut colors = [[0.0; 4]; MAX_GRADIENT_STOPS];
+                colors[0] = glow_color.evaluate(current_sec, parameter_store);
+
+                (
+                    color.evaluate(current_sec, parameter_store),
+                    [
+                        width.evaluate(current_sec, parameter_store) * base_scale_factor,
+                        0.0,
+                        0.0,
+                        0.0,
+                    ],
+                    [0.0; 4],
+                    colors,
+                )
+            }
+        }
+    }
+}
+
+/// グラデーションで使えるストップの最大数（これを超える場合は等間隔にサンプリングし直して近似する）
+pub const MAX_GRADIENT_STOPS: usize = 4;
+
+pub struct GradientStop {
+    /// 0..1
+    pub offset: f32,
+    pub color: AnimatableColor,
+}
+
+fn gradient_stop_parameters(
+    stops: &[GradientStop],
+    current_sec: f32,
+    parameter_store: &CompositeTreeParameterStore,
+) -> ([f32; 4], [[f32; 4]; MAX_GRADIENT_STOPS]) {
+    let evaluated = stops
+        .iter()
+        .map(|s| (s.offset, s.color.evaluate(current_sec, parameter_store)))
+        .collect::<Vec<_>>();
+
+    pack_gradient_stops(&evaluated)
+}
+
+/// (offset, color)の列をシェーダに渡せる数のストップに詰める
+fn pack_gradient_stops(stops: &[(f32, [f32; 4])]) -> ([f32; 4], [[f32; 4]; MAX_GRADIENT_STOPS]) {
+    let mut offsets = [0.0; MAX_GRADIENT_STOPS];
+    let mut colors = [[0.0; 4]; MAX_GRADIENT_STOPS];
+    let (Some(&(first_offset, _)), Some(&(last_offset, _))) = (stops.first(), stops.last()) else {
+        // no stops
+        return (offsets, colors);
+    };
+
+    if stops.len() > MAX_GRADIENT_STOPS {
+        // 入りきらないので、最初と最後のストップの間を等間隔にサンプリングし直す
+        for n in 0..MAX_GRADIENT_STOPS {
+            let offset = lerp(
+                n as f32 / (MAX_GRADIENT_STOPS - 1) as f32,
+                first_offset,
+                last_offset,
+            );
+            offsets[n] = offset;
+            colors[n] = sample_gradient(stops, offset);
         }
+
+        return (offsets, colors);
+    }
+
+    for n in 0..MAX_GRADIENT_STOPS {
+        // 足りない分は最後のストップを繰り返す
+        let &(offset, color) = stops.get(n).unwrap_or(&stops[stops.len() - 1]);
+        offsets[n] = offset;
+        colors[n] = color;
+    }
+
+    (offsets, colors)
+}
+
+fn sample_gradient(stops: &[(f32, [f32; 4])], offset: f32) -> [f32; 4] {
+    let next = stops.partition_point(|&(o, _)| o <= offset);
+    if next == 0 {
+        return stops[0].1;
     }
+    if next >= stops.len() {
+        return stops[stops.len() - 1].1;
+    }
+
+    let ((a_offset, a), (b_offset, b)) = (stops[next - 1], stops[next]);
+    if b_offset <= a_offset {
+        return b;
+    }
+
+    lerp4((offset - a_offset) / (b_offset - a_offset), a, b)
 }
 
+/// ぼかし処理(BackdropEffectBlurProcessor)に渡す`stdev`で、ぼかしの影響が及ぶ距離(px)
+///
+/// backdrop blurと影のぼかしで同じ定義を使う
+const fn blur_reach(stdev: f32) -> f32 {
+    stdev * 3.0
+}
+
+/// オフスクリーンレイヤーの合成用（CompositeModeとしては公開しない）
+const LAYER_COMPOSITE_SHADER_MODE_VALUE: f32 = 5.0;
+
 const fn lerp(x: f32, a: f32, b: f32) -> f32 {
     a + (b - a) * x
 }
@@ -90,6 +390,22 @@ const fn lerp4(x: f32, [a, c, e, g]: [f32; 4], [b, d, f, h]: [f32; 4]) -> [f32;
     [lerp(x, a, b), lerp(x, c, d), lerp(x, e, f), lerp(x, g, h)]
 }
 
+/// start_sec..end_secで変化するアニメーションについて、current_sec以降で次に値が変化する時刻
+fn next_change_sec_in(start_sec: f32, end_sec: f32, current_sec: f32) -> Option<f32> {
+    if current_sec < start_sec {
+        Some(start_sec)
+    } else if current_sec < end_sec {
+        Some(current_sec)
+    } else {
+        None
+    }
+}
+
+/// 一番早い時刻
+fn earliest_sec(xs: impl IntoIterator<Item = Option<f32>>) -> Option<f32> {
+    xs.into_iter().flatten().reduce(f32::min)
+}
+
 // TODO: このへんうまくまとめたいが......
 
 pub enum FloatParameter {
@@ -121,6 +437,15 @@ impl FloatParameter {
             ),
         }
     }
+
+    pub fn next_change_sec(&self, current_sec: f32) -> Option<f32> {
+        match self {
+            &Self::Value(_) => None,
+            &Self::Animated {
+                start_sec, end_sec, ..
+            } => next_change_sec_in(start_sec, end_sec, current_sec),
+        }
+    }
 }
 
 pub enum AnimatableFloat {
@@ -134,6 +459,7 @@ pub enum AnimatableFloat {
         curve: AnimationCurve,
         event_on_complete: Option<AppEvent>,
     },
+    Track(KeyframeTrack<f32>),
 }
 impl AnimatableFloat {
     pub fn evaluate(&self, current_sec: f32, parameter_store: &CompositeTreeParameterStore) -> f32 {
@@ -152,20 +478,43 @@ impl AnimatableFloat {
                 from_value,
                 to_value,
             ),
+            &Self::Track(ref t) => t.evaluate(current_sec),
+        }
+    }
+
+    /// 指定時刻で終わりのあるアニメーションの途中かどうか(繰り返すトラックは終わらないので含めない)
+    pub fn is_settling_at(&self, current_sec: f32) -> bool {
+        match self {
+            &Self::Animated { end_sec, .. } => current_sec < end_sec,
+            &Self::Track(ref t) => t.is_settling_at(current_sec),
+            _ => false,
+        }
+    }
+
+    /// current_sec以降で次に値が変化する時刻（Expressionの変化はパラメータ側で扱う）
+    pub fn next_change_sec(&self, current_sec: f32) -> Option<f32> {
+        match self {
+            &Self::Animated {
+                start_sec, end_sec, ..
+            } => next_change_sec_in(start_sec, end_sec, current_sec),
+            &Self::Track(ref t) => t.next_change_sec(current_sec),
+            _ => None,
         }
     }
 
     fn process_on_complete(&mut self, current_sec: f32, q: &AppEventBus) {
-        if let &mut Self::Animated {
-            end_sec,
-            ref mut event_on_complete,
-            ..
-        } = self
-            && end_sec <= current_sec
-        {
-            if let Some(e) = event_on_complete.take() {
-                q.push(e);
+        match self {
+            &mut Self::Animated {
+                end_sec,
+                ref mut event_on_complete,
+                ..
+            } if end_sec <= current_sec => {
+                if let Some(e) = event_on_complete.take() {
+                    q.push(e);
+                }
             }
+            &mut Self::Track(ref mut t) => t.process_on_complete(current_sec, q),
+            _ => (),
         }
     }
 }
@@ -181,6 +530,7 @@ pub enum AnimatableColor {
         curve: AnimationCurve,
         event_on_complete: Option<AppEvent>,
     },
+    Track(KeyframeTrack<[f32; 4]>),
 }
 impl AnimatableColor {
     pub fn evaluate(
@@ -203,18 +553,206 @@ impl AnimatableColor {
                 from_value,
                 to_value,
             ),
+            &Self::Track(ref t) => t.evaluate(current_sec),
+        }
+    }
+
+    /// 指定時刻で終わりのあるアニメーションの途中かどうか(繰り返すトラックは終わらないので含めない)
+    pub fn is_settling_at(&self, current_sec: f32) -> bool {
+        match self {
+            &Self::Animated { end_sec, .. } => current_sec < end_sec,
+            &Self::Track(ref t) => t.is_settling_at(current_sec),
+            _ => false,
+        }
+    }
+
+    /// current_sec以降で次に値が変化する時刻（Expressionの変化はパラメータ側で扱う）
+    pub fn next_change_sec(&self, current_sec: f32) -> Option<f32> {
+        match self {
+            &Self::Animated {
+                start_sec, end_sec, ..
+            } => next_change_sec_in(start_sec, end_sec, current_sec),
+            &Self::Track(ref t) => t.next_change_sec(current_sec),
+            _ => None,
         }
     }
 
     fn process_on_complete(&mut self, current_sec: f32, q: &AppEventBus) {
-        if let &mut Self::Animated {
-            end_sec,
-            ref mut event_on_complete,
-            ..
-        } = self
-            && end_sec <= current_sec
-        {
-            if let Some(e) = event_on_complete.take() {
+        match self {
+            &mut Self::Animated {
+                end_sec,
+                ref mut event_on_complete,
+                ..
+            } if end_sec <= current_sec => {
+                if let Some(e) = event_on_complete.take() {
+                    q.push(e);
+                }
+            }
+            &mut Self::Track(ref mut t) => t.process_on_complete(current_sec, q),
+            _ => (),
+        }
+    }
+}
+
+/// キーフレーム間で補間可能な値
+pub trait KeyframeValue: Copy {
+    fn interpolate(x: f32, a: Self, b: Self) -> Self;
+}
+impl KeyframeValue for f32 {
+    #[inline(always)]
+    fn interpolate(x: f32, a: Self, b: Self) -> Self {
+        lerp(x, a, b)
+    }
+}
+impl KeyframeValue for [f32; 4] {
+    #[inline(always)]
+    fn interpolate(x: f32, a: Self, b: Self) -> Self {
+        lerp4(x, a, b)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationRepeat {
+    /// 最後のキーフレームの値で停止する
+    Once,
+    /// 最初のキーフレームから繰り返す
+    Loop,
+    /// 往復を繰り返す
+    PingPong,
+}
+
+#[derive(Clone)]
+pub struct Keyframe<T> {
+    /// トラック開始からの時刻(sec)
+    pub time_sec: f32,
+    pub value: T,
+    /// 直前のキーフレームからこのキーフレームまでの区間に適用するカーブ（先頭キーフレームでは無視される）
+    pub curve: AnimationCurve,
+}
+
+/// 複数キーフレームからなるアニメーショントラック
+pub struct KeyframeTrack<T> {
+    pub start_sec: f32,
+    pub keyframes: Vec<Keyframe<T>>,
+    pub repeat: AnimationRepeat,
+    pub event_on_complete: Option<AppEvent>,
+}
+impl<T: KeyframeValue> KeyframeTrack<T> {
+    pub fn new(start_sec: f32, initial_value: T) -> Self {
+        Self {
+            start_sec,
+            keyframes: vec![Keyframe {
+                time_sec: 0.0,
+                value: initial_value,
+                curve: AnimationCurve::Linear,
+            }],
+            repeat: AnimationRepeat::Once,
+            event_on_complete: None,
+        }
+    }
+
+    /// 直前のキーフレームから`duration_sec`かけて`value`へ遷移するキーフレームを追加する
+    pub fn then(mut self, duration_sec: f32, value: T, curve: AnimationCurve) -> Self {
+        let time_sec = self.duration_sec() + duration_sec;
+        self.keyframes.push(Keyframe {
+            time_sec,
+            value,
+            curve,
+        });
+
+        self
+    }
+
+    /// 直前のキーフレームの値を`duration_sec`だけ維持する
+    pub fn hold(self, duration_sec: f32) -> Self {
+        let value = self.keyframes.last().expect("no keyframes").value;
+
+        self.then(duration_sec, value, AnimationCurve::Linear)
+    }
+
+    pub fn repeat(mut self, repeat: AnimationRepeat) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    pub fn on_complete(mut self, event: AppEvent) -> Self {
+        self.event_on_complete = Some(event);
+        self
+    }
+
+    pub fn duration_sec(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |k| k.time_sec)
+    }
+
+    /// 繰り返しなしのトラックの終了時刻
+    pub fn end_sec(&self) -> Option<f32> {
+        match self.repeat {
+            AnimationRepeat::Once => Some(self.start_sec + self.duration_sec()),
+            AnimationRepeat::Loop | AnimationRepeat::PingPong => None,
+        }
+    }
+
+    pub fn is_animating_at(&self, current_sec: f32) -> bool {
+        if self.keyframes.len() < 2 {
+            // 単一値
+            return false;
+        }
+
+        self.end_sec().is_none_or(|e| current_sec < e)
+    }
+
+    /// 繰り返しなしで、指定時刻ではまだ終わっていないかどうか
+    pub fn is_settling_at(&self, current_sec: f32) -> bool {
+        self.keyframes.len() >= 2 && self.end_sec().is_some_and(|e| current_sec < e)
+    }
+
+    pub fn next_change_sec(&self, current_sec: f32) -> Option<f32> {
+        if !self.is_animating_at(current_sec) {
+            return None;
+        }
+
+        Some(current_sec.max(self.start_sec))
+    }
+
+    fn local_time(&self, current_sec: f32) -> f32 {
+        let duration = self.duration_sec();
+        let t = current_sec - self.start_sec;
+        if t <= 0.0 || duration <= 0.0 {
+            return 0.0;
+        }
+
+        match self.repeat {
+            AnimationRepeat::Once => t.min(duration),
+            AnimationRepeat::Loop => t % duration,
+            AnimationRepeat::PingPong => {
+                let t = t % (duration * 2.0);
+                if t > duration { duration * 2.0 - t } else { t }
+            }
+        }
+    }
+
+    pub fn evaluate(&self, current_sec: f32) -> T {
+        let t = self.local_time(current_sec);
+        let next = self.keyframes.partition_point(|k| k.time_sec <= t);
+        if next == 0 {
+            return self.keyframes[0].value;
+        }
+        if next >= self.keyframes.len() {
+            return self.keyframes[self.keyframes.len() - 1].value;
+        }
+
+        let (a, b) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        T::interpolate(
+            b.curve
+                .interpolate((t - a.time_sec) / (b.time_sec - a.time_sec)),
+            a.value,
+            b.value,
+        )
+    }
+
+    fn process_on_complete(&mut self, current_sec: f32, q: &AppEventBus) {
+        if self.end_sec().is_some_and(|e| e <= current_sec) {
+            if let Some(e) = self.event_on_complete.take() {
                 q.push(e);
             }
         }
@@ -224,18 +762,97 @@ impl AnimatableColor {
 #[derive(Clone)]
 pub enum AnimationCurve {
     Linear,
-    CubicBezier { p1: (f32, f32), p2: (f32, f32) },
+    CubicBezier {
+        p1: (f32, f32),
+        p2: (f32, f32),
+    },
+    /// 区間を`count`段階の階段状に変化させる（`jump_at_start`がtrueなら区間開始直後に最初の段階へ移る）
+    Steps {
+        count: u32,
+        jump_at_start: bool,
+    },
+    /// 減衰振動（ばね）
+    ///
+    /// `damping`が大きいほど早く収束し、`frequency`は区間全体での振動回数。`frequency`が0のときは臨界減衰になる
+    Spring {
+        damping: f32,
+        frequency: f32,
+    },
 }
 impl AnimationCurve {
+    // CSS easing functionsと同じもの
+    pub const EASE: Self = Self::CubicBezier {
+        p1: (0.25, 0.1),
+        p2: (0.25, 1.0),
+    };
+    pub const EASE_IN: Self = Self::CubicBezier {
+        p1: (0.42, 0.0),
+        p2: (1.0, 1.0),
+    };
+    pub const EASE_OUT: Self = Self::CubicBezier {
+        p1: (0.0, 0.0),
+        p2: (0.58, 1.0),
+    };
+    pub const EASE_IN_OUT: Self = Self::CubicBezier {
+        p1: (0.42, 0.0),
+        p2: (0.58, 1.0),
+    };
+
     #[inline]
     fn interpolate(&self, t: f32) -> f32 {
         match self {
             &AnimationCurve::Linear => t.clamp(0.0, 1.0),
             &AnimationCurve::CubicBezier { p1, p2 } => interpolate_cubic_bezier(t, p1, p2),
+            &AnimationCurve::Steps {
+                count,
+                jump_at_start,
+            } => interpolate_steps(t, count, jump_at_start),
+            &AnimationCurve::Spring { damping, frequency } => {
+                interpolate_spring(t, damping, frequency)
+            }
         }
     }
 }
 
+fn interpolate_steps(t: f32, count: u32, jump_at_start: bool) -> f32 {
+    // out of range
+    if t <= 0.0 {
+        return 0.0;
+    }
+    if t >= 1.0 || count == 0 {
+        return 1.0;
+    }
+
+    let count = count as f32;
+    if jump_at_start {
+        (t * count).ceil() / count
+    } else {
+        (t * count).floor() / count
+    }
+}
+
+fn interpolate_spring(t: f32, damping: f32, frequency: f32) -> f32 {
+    // out of range
+    if t <= 0.0 {
+        return 0.0;
+    }
+    if t >= 1.0 {
+        return 1.0;
+    }
+
+    let residual = |t: f32| {
+        if frequency == 0.0 {
+            // critically damped: (1 + dt)e^(-dt)
+            (1.0 + damping * t) * (-damping * t).exp()
+        } else {
+            (-damping * t).exp() * (core::f32::consts::TAU * frequency * t).cos()
+        }
+    };
+
+    // Note: 減衰しきらずに区間の終わりで残る振れ幅を線形に打ち消して、t=1でちょうど1になるようにする（そのままだと最後に値が飛ぶ）
+    1.0 - (residual(t) - residual(1.0) * t)
+}
+
 fn interpolate_cubic_bezier(t: f32, p1: (f32, f32), p2: (f32, f32)) -> f32 {
     // out of range
     if t <= 0.0 {
@@ -391,7 +1008,7 @@ fn interpolate_cubic_bezier(t: f32, p1: (f32, f32), p2: (f32, f32)) -> f32 {
         + p1.1 * 3.0 * t0
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Hash)]
 pub struct ClipConfig {
     pub left_softness: SafeF32,
     pub top_softness: SafeF32,
@@ -400,7 +1017,7 @@ pub struct ClipConfig {
 }
 
 #[repr(transparent)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct CustomRenderToken(usize);
 
 pub struct CompositeRect {
@@ -419,6 +1036,15 @@ pub struct CompositeRect {
     pub pivot: [f32; 2],
     pub scale_x: AnimatableFloat,
     pub scale_y: AnimatableFloat,
+    /// pivotを中心とした回転(degrees)
+    pub rotation: AnimatableFloat,
+    /// pivotを中心としたせん断(degrees)
+    pub skew_x: AnimatableFloat,
+    pub skew_y: AnimatableFloat,
+    /// `opacity`が1未満のあいだ、このノード以下をいったんオフスクリーンレイヤーに描画し、`opacity`をまとめて適用してから合成する
+    ///
+    /// レイヤーに描画しているあいだはbackdrop blurは無効になる（完全に不透明になれば有効に戻る）
+    pub offscreen_layer: bool,
     pub dirty: bool,
     pub parent: Option<usize>,
     pub children: Vec<usize>,
@@ -447,11 +1073,46 @@ impl Default for CompositeRect {
             pivot: [0.5; 2],
             scale_x: AnimatableFloat::Value(1.0),
             scale_y: AnimatableFloat::Value(1.0),
+            rotation: AnimatableFloat::Value(0.0),
+            skew_x: AnimatableFloat::Value(0.0),
+            skew_y: AnimatableFloat::Value(0.0),
+            offscreen_layer: false,
             parent: None,
             children: Vec::new(),
         }
     }
 }
+impl CompositeRect {
+    /// 指定時刻で終わりのあるアニメーションの途中かどうか(繰り返すトラックは終わらないので含めない)
+    pub fn is_settling_at(&self, current_sec: f32) -> bool {
+        self.offset.iter().any(|x| x.is_settling_at(current_sec))
+            || self.size.iter().any(|x| x.is_settling_at(current_sec))
+            || self.opacity.is_settling_at(current_sec)
+            || self.scale_x.is_settling_at(current_sec)
+            || self.scale_y.is_settling_at(current_sec)
+            || self.rotation.is_settling_at(current_sec)
+            || self.skew_x.is_settling_at(current_sec)
+            || self.skew_y.is_settling_at(current_sec)
+            || self.composite_mode.is_settling_at(current_sec)
+    }
+
+    /// current_sec以降で次に見た目が変化する時刻
+    pub fn next_change_sec(&self, current_sec: f32) -> Option<f32> {
+        earliest_sec([
+            self.offset[0].next_change_sec(current_sec),
+            self.offset[1].next_change_sec(current_sec),
+            self.size[0].next_change_sec(current_sec),
+            self.size[1].next_change_sec(current_sec),
+            self.opacity.next_change_sec(current_sec),
+            self.scale_x.next_change_sec(current_sec),
+            self.scale_y.next_change_sec(current_sec),
+            self.rotation.next_change_sec(current_sec),
+            self.skew_x.next_change_sec(current_sec),
+            self.skew_y.next_change_sec(current_sec),
+            self.composite_mode.next_change_sec(current_sec),
+        ])
+    }
+}
 
 /// Unbounded from gfx_device(must be externally managed)
 pub struct UnboundedCompositeInstanceManager {
@@ -799,6 +1460,16 @@ impl CompositeTreeParameterStore {
         self.float_values[r.0]
     }
 
+    fn next_change_sec(&self, current_sec: f32) -> Option<f32> {
+        earliest_sec(
+            self.float_parameters
+                .iter()
+                .enumerate()
+                .filter(|(n, _)| !self.unused_float_parameters.contains(n))
+                .map(|(_, p)| p.next_change_sec(current_sec)),
+        )
+    }
+
     fn evaluate_all(&mut self, current_sec: f32) {
         for (v, p) in self
             .float_values
@@ -839,6 +1510,11 @@ pub enum CompositeRenderingInstruction {
         dest_backdrop_buffer: usize,
         rects: Vec<br::Rect2D>,
     },
+    /// オフスクリーンレイヤーの内容を合成する
+    DrawLayer {
+        instance_index: usize,
+        layer: usize,
+    },
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -846,15 +1522,139 @@ pub struct CompositeRenderingData {
     pub instructions: Vec<CompositeRenderingInstruction>,
     pub render_passes: Vec<RenderPassRequirements>,
     pub required_backdrop_buffer_count: usize,
+    /// オフスクリーンレイヤー（メインの描画より前にこの順で描画される）
+    pub layers: Vec<CompositeLayerRenderingData>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CompositeLayerRenderingData {
+    pub instructions: Vec<CompositeRenderingInstruction>,
+    /// 描画後にレイヤー全体にかけるぼかし
+    pub blur_stdev: Option<SafeF32>,
+}
+impl CompositeRenderingData {
+    /// レイヤー用のバッファはbackdrop bufferの後ろに配置する（0番目は番兵なのでbackdropがなくても1つずらす）
+    pub fn layer_buffer_index(&self, layer: usize) -> usize {
+        self.required_backdrop_buffer_count.max(1) + layer
+    }
+
+    /// backdrop bufferとレイヤー用バッファの合計数
+    pub fn required_buffer_count(&self) -> usize {
+        self.layer_buffer_index(self.layers.len())
+    }
 }
 
-const fn rect_overlaps(a: &br::Rect2D, b: &br::Rect2D) -> bool {
+pub const fn rect_overlaps(a: &br::Rect2D, b: &br::Rect2D) -> bool {
     b.offset.x - (a.extent.width as i32) < a.offset.x
         && a.offset.x < b.offset.x + (b.extent.width as i32)
         && b.offset.y - (a.extent.height as i32) < a.offset.y
         && a.offset.y < b.offset.y + (b.extent.height as i32)
 }
 
+/// 両方の矩形を含む最小の矩形
+pub fn rect_union(a: &br::Rect2D, b: &br::Rect2D) -> br::Rect2D {
+    let left = a.offset.x.min(b.offset.x);
+    let top = a.offset.y.min(b.offset.y);
+    let right = (a.offset.x + a.extent.width as i32).max(b.offset.x + b.extent.width as i32);
+    let bottom = (a.offset.y + a.extent.height as i32).max(b.offset.y + b.extent.height as i32);
+
+    br::Rect2D {
+        offset: br::Offset2D { x: left, y: top },
+        extent: br::Extent2D {
+            width: (right - left) as _,
+            height: (bottom - top) as _,
+        },
+    }
+}
+
+/// スクリーン上での描画範囲（ピクセル単位、left/top/right/bottom）
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ScreenBounds {
+    left: f32,
+    top: f32,
+    right: f32,
+    bottom: f32,
+}
+impl ScreenBounds {
+    /// (0, 0)-(width, height)の矩形を変換したときの外接矩形
+    fn of_transformed_rect(matrix: &Matrix4, width: f32, height: f32) -> Self {
+        let [p0, p1, p2, p3] = [
+            matrix.transform_point(0.0, 0.0),
+            matrix.transform_point(width, 0.0),
+            matrix.transform_point(0.0, height),
+            matrix.transform_point(width, height),
+        ];
+
+        Self {
+            left: p0[0].min(p1[0]).min(p2[0]).min(p3[0]),
+            top: p0[1].min(p1[1]).min(p2[1]).min(p3[1]),
+            right: p0[0].max(p1[0]).max(p2[0]).max(p3[0]),
+            bottom: p0[1].max(p1[1]).max(p2[1]).max(p3[1]),
+        }
+    }
+
+    fn expand(self, amount: f32) -> Self {
+        Self {
+            left: self.left - amount,
+            top: self.top - amount,
+            right: self.right + amount,
+            bottom: self.bottom + amount,
+        }
+    }
+
+    fn union(self, other: Self) -> Self {
+        Self {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+
+    fn overlaps(&self, other: &Self) -> bool {
+        self.left < other.right
+            && other.left < self.right
+            && self.top < other.bottom
+            && other.top < self.bottom
+    }
+
+    fn contains(&self, other: &Self) -> bool {
+        self.left <= other.left
+            && other.right <= self.right
+            && self.top <= other.top
+            && other.bottom <= self.bottom
+    }
+
+    /// 外側に丸めたピクセル矩形にする（AAのにじみ分として1px余分にとる）
+    fn to_pixel_rect(&self, screen_size: br::Extent2D) -> Option<br::Rect2D> {
+        let left = ((self.left.floor() - 1.0).max(0.0) as u32).min(screen_size.width);
+        let top = ((self.top.floor() - 1.0).max(0.0) as u32).min(screen_size.height);
+        let right = ((self.right.ceil() + 1.0).max(0.0) as u32).min(screen_size.width);
+        let bottom = ((self.bottom.ceil() + 1.0).max(0.0) as u32).min(screen_size.height);
+        if left >= right || top >= bottom {
+            return None;
+        }
+
+        Some(br::Rect2D {
+            offset: br::Offset2D {
+                x: left as _,
+                y: top as _,
+            },
+            extent: br::Extent2D {
+                width: right - left,
+                height: bottom - top,
+            },
+        })
+    }
+}
+
+/// 前回のupdateで描画したときの状態
+struct DrawnRectState {
+    bounds: ScreenBounds,
+    /// 描画結果に影響するパラメータのハッシュ
+    digest: u64,
+}
+
 struct CompositeRenderingInstructionBuilder {
     insts: Vec<CompositeRenderingInstruction>,
     render_passes: Vec<RenderPassRequirements>,
@@ -866,6 +1666,12 @@ struct CompositeRenderingInstructionBuilder {
     screen_rect: br::Rect2D,
     active_clip_parameters: Option<[SafeF32; 8]>,
     clip_invalidated: bool,
+    layer_stack: Vec<(
+        Vec<CompositeRenderingInstruction>,
+        Option<[SafeF32; 8]>,
+        bool,
+    )>,
+    layers: Vec<CompositeLayerRenderingData>,
 }
 impl CompositeRenderingInstructionBuilder {
     fn new(screen_size: br::Extent2D) -> Self {
@@ -880,10 +1686,14 @@ impl CompositeRenderingInstructionBuilder {
             screen_rect: screen_size.into_rect(br::Offset2D::ZERO),
             active_clip_parameters: None,
             clip_invalidated: true,
+            layer_stack: Vec::new(),
+            layers: Vec::new(),
         }
     }
 
     fn build(mut self) -> CompositeRenderingData {
+        assert!(self.layer_stack.is_empty(), "unterminated offscreen layer");
+
         // process for last backdrop layer
         self.max_backdrop_buffer_count = self
             .max_backdrop_buffer_count
@@ -898,9 +1708,50 @@ impl CompositeRenderingInstructionBuilder {
             instructions: self.insts,
             render_passes: self.render_passes,
             required_backdrop_buffer_count: self.max_backdrop_buffer_count,
+            layers: self.layers,
         }
     }
 
+    const fn is_in_layer(&self) -> bool {
+        !self.layer_stack.is_empty()
+    }
+
+    /// 以降の描画命令をオフスクリーンレイヤーに向ける
+    fn begin_layer(&mut self) {
+        let parent_insts = core::mem::replace(
+            &mut self.insts,
+            vec![CompositeRenderingInstruction::ClearClip],
+        );
+        self.layer_stack.push((
+            parent_insts,
+            self.active_clip_parameters.take(),
+            self.clip_invalidated,
+        ));
+        self.clip_invalidated = true;
+    }
+
+    /// return: layer index
+    fn end_layer(&mut self, blur_stdev: Option<SafeF32>) -> usize {
+        let (parent_insts, parent_clip_parameters, parent_clip_invalidated) =
+            self.layer_stack.pop().expect("no active layer");
+        let layer_insts = core::mem::replace(&mut self.insts, parent_insts);
+        self.active_clip_parameters = parent_clip_parameters;
+        self.clip_invalidated = parent_clip_invalidated;
+        self.layers.push(CompositeLayerRenderingData {
+            instructions: layer_insts,
+            blur_stdev,
+        });
+
+        self.layers.len() - 1
+    }
+
+    fn draw_layer(&mut self, instance_index: usize, layer: usize) {
+        self.insts.push(CompositeRenderingInstruction::DrawLayer {
+            instance_index,
+            layer,
+        });
+    }
+
     fn draw_instance(&mut self, index: usize, backdrop_buffer_index: usize) {
         if let Some(&mut CompositeRenderingInstruction::DrawInstanceRange {
             ref mut index_range,
@@ -1109,6 +1960,52 @@ impl CompositeRenderingInstructionBuilder {
     }
 }
 
+/// オフスクリーンレイヤーを画面全体に合成するインスタンスを書き込む
+unsafe fn write_layer_composite_instance(
+    mapped_head: *mut core::ffi::c_void,
+    instance_slot_index: usize,
+    screen_size: br::Extent2D,
+    tex_size: br::Extent2D,
+    opacity: f32,
+) {
+    unsafe {
+        core::ptr::write(
+            mapped_head
+                .cast::<CompositeInstanceData>()
+                .add(instance_slot_index),
+            CompositeInstanceData {
+                pos_st: [
+                    screen_size.width as f32,
+                    screen_size.height as f32,
+                    0.0,
+                    0.0,
+                ],
+                uv_st: [0.0; 4],
+                position_modifier_matrix: Matrix4::IDENTITY.0,
+                slice_borders: [0.0; 4],
+                tex_size_pixels_composite_mode_opacity: [
+                    tex_size.width as _,
+                    tex_size.height as _,
+                    LAYER_COMPOSITE_SHADER_MODE_VALUE,
+                    opacity,
+                ],
+                color_tint: [0.0; 4],
+                pos_x_animation_data: [0.0; 4],
+                pos_x_curve_control_points: [0.0; 4],
+                pos_y_animation_data: [0.0; 4],
+                pos_y_curve_control_points: [0.0; 4],
+                pos_width_animation_data: [0.0; 4],
+                pos_width_curve_control_points: [0.0; 4],
+                pos_height_animation_data: [0.0; 4],
+                pos_height_curve_control_points: [0.0; 4],
+                effect_params: [0.0; 4],
+                gradient_stop_offsets: [0.0; 4],
+                effect_colors: [[0.0; 4]; MAX_GRADIENT_STOPS],
+            },
+        );
+    }
+}
+
 pub struct CompositeTree {
     rects: Vec<CompositeRect>,
     unused: BTreeSet<usize>,
@@ -1116,6 +2013,12 @@ pub struct CompositeTree {
     parameter_store: CompositeTreeParameterStore,
     custom_render_unused: BTreeSet<usize>,
     custom_render_last_id: usize,
+    settle_watchers: Vec<(CompositeTreeRef, AppEvent)>,
+    drawn_rects: HashMap<usize, DrawnRectState>,
+    custom_render_bounds: HashMap<CustomRenderToken, br::Rect2D>,
+    last_screen_size: br::Extent2D,
+    damage: Option<br::Rect2D>,
+    next_change_sec: Option<f32>,
 }
 impl CompositeTree {
     /// ルートノード
@@ -1140,6 +2043,15 @@ impl CompositeTree {
             },
             custom_render_unused: BTreeSet::new(),
             custom_render_last_id: 0,
+            settle_watchers: Vec::new(),
+            drawn_rects: HashMap::new(),
+            custom_render_bounds: HashMap::new(),
+            last_screen_size: br::Extent2D {
+                width: 0,
+                height: 0,
+            },
+            damage: None,
+            next_change_sec: None,
         }
     }
 
@@ -1155,6 +2067,8 @@ impl CompositeTree {
 
     pub fn free(&mut self, index: CompositeTreeRef) {
         self.unused.insert(index.0);
+        // Note: 解放されたノードはほかの用途で再利用されるので、それを待っているものは捨てる
+        self.settle_watchers.retain(|&(r, _)| r != index);
     }
 
     pub fn acquire_custom_render_token(&mut self) -> CustomRenderToken {
@@ -1188,6 +2102,23 @@ impl CompositeTree {
         core::mem::replace(&mut self.dirty, false)
     }
 
+    /// 直前のupdateで見た目が変化した領域（変化がなければNone）
+    pub const fn damage(&self) -> Option<br::Rect2D> {
+        self.damage
+    }
+
+    /// 直前のupdate以降で次にアニメーションによって見た目が変化する時刻
+    ///
+    /// updateに渡した時刻以下であれば現在アニメーション中、Noneならアニメーションしているものはない
+    pub const fn next_change_sec(&self) -> Option<f32> {
+        self.next_change_sec
+    }
+
+    /// 直前のupdateでCustom Renderが描画される範囲
+    pub fn custom_render_bounds(&self, token: CustomRenderToken) -> Option<br::Rect2D> {
+        self.custom_render_bounds.get(&token).copied()
+    }
+
     pub fn add_child(&mut self, parent: CompositeTreeRef, child: CompositeTreeRef) {
         if let Some(p) = self.rects[child.0].parent.replace(parent.0) {
             // unlink from old parent
@@ -1205,6 +2136,43 @@ impl CompositeTree {
         }
     }
 
+    /// 指定したノード以下のすべてのアニメーションが完了したらイベントを発行する
+    ///
+    /// 個々のアニメーションの長さに依存せずに遷移全体の完了を待ちたいとき（ポップアップのunmountなど）に使う
+    ///
+    /// 繰り返すトラック(Loop/PingPong)は終わらないので待たない。イベントが発行される前に`root`が解放されたら発行しない
+    pub fn notify_when_settled(&mut self, root: CompositeTreeRef, event: AppEvent) {
+        self.settle_watchers.push((root, event));
+        self.dirty = true;
+    }
+
+    fn is_subtree_settling_at(&self, root: CompositeTreeRef, current_sec: f32) -> bool {
+        let mut stack = vec![root.0];
+        while let Some(x) = stack.pop() {
+            let r = &self.rects[x];
+            if r.is_settling_at(current_sec) {
+                return true;
+            }
+
+            stack.extend(r.children.iter().copied());
+        }
+
+        false
+    }
+
+    fn process_settle_watchers(&mut self, current_sec: f32, event_bus: &AppEventBus) {
+        let mut n = 0;
+        while n < self.settle_watchers.len() {
+            if self.is_subtree_settling_at(self.settle_watchers[n].0, current_sec) {
+                n += 1;
+                continue;
+            }
+
+            let (_, e) = self.settle_watchers.swap_remove(n);
+            event_bus.push(e);
+        }
+    }
+
     pub const fn parameter_store(&self) -> &CompositeTreeParameterStore {
         &self.parameter_store
     }
@@ -1226,6 +2194,17 @@ impl CompositeTree {
 
         self.parameter_store.evaluate_all(current_sec);
 
+        // サイズが変わったときは全体を描き直す
+        let full_damage = self.last_screen_size.width != size.width
+            || self.last_screen_size.height != size.height;
+        self.last_screen_size = size;
+        let mut drawn_rects = HashMap::with_capacity(self.drawn_rects.len());
+        let mut damage = None::<ScreenBounds>;
+        // 下にあるものが変化したら描き直す必要があるもの（描画範囲, 参照する周辺の幅）
+        let mut damage_dependents = Vec::<(ScreenBounds, f32)>::new();
+        self.custom_render_bounds.clear();
+        let mut next_change_sec = self.parameter_store.next_change_sec(current_sec);
+
         let mut inst_builder = CompositeRenderingInstructionBuilder::new(size);
         let mut instance_slot_index = 0;
         let mut processes = vec![(
@@ -1240,21 +2219,54 @@ impl CompositeTree {
                 None::<([SafeF32; 4], ClipConfig)>,
             ),
         )];
-        while let Some((
-            r,
-            (
-                effective_base_left,
-                effective_base_top,
-                effective_width,
-                effective_height,
-                parent_opacity,
-                parent_matrix,
-                active_clip,
-            ),
-        )) = processes.pop()
-        {
-            let r = &mut self.rects[r];
-            r.dirty = false;
+        // (レイヤー開始時点のprocessesの長さ, レイヤー全体のopacity, レイヤー合成時のclip)
+        let mut open_layers = Vec::<(usize, f32, Option<([SafeF32; 4], ClipConfig)>)>::new();
+        loop {
+            while let Some(&(threshold, layer_opacity, layer_clip)) = open_layers.last()
+                && processes.len() <= threshold
+            {
+                // 子孫をすべて処理したのでレイヤーを閉じて合成する
+                open_layers.pop();
+                let layer = inst_builder.end_layer(None);
+                unsafe {
+                    write_layer_composite_instance(
+                        mapped_head,
+                        instance_slot_index,
+                        size,
+                        tex_size,
+                        layer_opacity,
+                    );
+                }
+
+                if let Some((clip_rect_px, clip_config)) = layer_clip {
+                    inst_builder.set_clip(&clip_rect_px, &clip_config);
+                } else {
+                    inst_builder.clear_clip();
+                }
+
+                inst_builder.draw_layer(instance_slot_index, layer);
+                instance_slot_index += 1;
+            }
+
+            let Some((
+                index,
+                (
+                    effective_base_left,
+                    effective_base_top,
+                    effective_width,
+                    effective_height,
+                    parent_opacity,
+                    parent_matrix,
+                    active_clip,
+                ),
+            )) = processes.pop()
+            else {
+                break;
+            };
+
+            let r = &mut self.rects[index];
+            let was_dirty = core::mem::replace(&mut r.dirty, false);
+            next_change_sec = earliest_sec([next_change_sec, r.next_change_sec(current_sec)]);
             let local_left =
                 r.offset[0].evaluate(current_sec, &self.parameter_store) * r.base_scale_factor;
             let local_top =
@@ -1273,12 +2285,26 @@ impl CompositeTree {
             let w = effective_width * r.relative_size_adjustment[0] + local_width;
             let h = effective_height * r.relative_size_adjustment[1] + local_height;
 
-            let opacity = parent_opacity * r.opacity.evaluate(current_sec, &self.parameter_store);
+            let mut opacity =
+                parent_opacity * r.opacity.evaluate(current_sec, &self.parameter_store);
             let matrix = parent_matrix.mul_mat4(
                 Matrix4::translate(
                     left - effective_base_left + r.pivot[0] * w,
                     top - effective_base_top + r.pivot[1] * h,
                 )
+                .mul_mat4(Matrix4::rotate_z(
+                    r.rotation
+                        .evaluate(current_sec, &self.parameter_store)
+                        .to_radians(),
+                ))
+                .mul_mat4(Matrix4::skew(
+                    r.skew_x
+                        .evaluate(current_sec, &self.parameter_store)
+                        .to_radians(),
+                    r.skew_y
+                        .evaluate(current_sec, &self.parameter_store)
+                        .to_radians(),
+                ))
                 .mul_mat4(Matrix4::scale(
                     r.scale_x.evaluate(current_sec, &self.parameter_store),
                     r.scale_y.evaluate(current_sec, &self.parameter_store),
@@ -1293,77 +2319,134 @@ impl CompositeTree {
             r.opacity.process_on_complete(current_sec, event_bus);
             r.scale_x.process_on_complete(current_sec, event_bus);
             r.scale_y.process_on_complete(current_sec, event_bus);
-            match r.composite_mode {
-                CompositeMode::DirectSourceOver => (),
-                CompositeMode::ColorTint(ref mut t) => {
-                    t.process_on_complete(current_sec, event_bus)
-                }
-                CompositeMode::FillColor(ref mut t) => {
-                    t.process_on_complete(current_sec, event_bus)
-                }
-                CompositeMode::ColorTintBackdropBlur(ref mut t, ref mut stdev) => {
-                    t.process_on_complete(current_sec, event_bus);
-                    stdev.process_on_complete(current_sec, event_bus);
-                }
-                CompositeMode::FillColorBackdropBlur(ref mut t, ref mut stdev) => {
-                    t.process_on_complete(current_sec, event_bus);
-                    stdev.process_on_complete(current_sec, event_bus);
-                }
+            r.rotation.process_on_complete(current_sec, event_bus);
+            r.skew_x.process_on_complete(current_sec, event_bus);
+            r.skew_y.process_on_complete(current_sec, event_bus);
+            r.composite_mode.process_on_complete(current_sec, event_bus);
+
+            if r.offscreen_layer && opacity < 1.0 {
+                // opacityはレイヤーを合成するときにまとめて適用する
+                // Note: 不透明なときはレイヤーを挟んでも見た目が変わらないので、直接描いてbackdrop blurやインスタンス枠を無駄にしない
+                inst_builder.begin_layer();
+                open_layers.push((processes.len(), opacity, active_clip));
+                opacity = 1.0;
             }
 
+            let mut drawn = None;
             if let Some(t) = r.custom_render_token {
                 // Custom Renderがある場合はそっちのみ
                 inst_builder.insert_custom_render_commands(t);
+
+                let bounds = ScreenBounds::of_transformed_rect(&matrix, w, h);
+                if let Some(rect) = bounds.to_pixel_rect(size) {
+                    self.custom_render_bounds.insert(t, rect);
+                }
+                // Custom Renderの中身は部分的に描き直せないので、少しでも重なったら全体を描き直す
+                damage_dependents.push((bounds, 0.0));
+
+                let mut hasher = DefaultHasher::new();
+                t.hash(&mut hasher);
+                drawn = Some(DrawnRectState {
+                    bounds,
+                    digest: hasher.finish(),
+                });
             } else if r.has_bitmap {
+                let (color_tint, effect_params, gradient_stop_offsets, effect_colors) = r
+                    .composite_mode
+                    .shader_parameters(current_sec, &self.parameter_store, r.base_scale_factor);
+                let mut shadow_blur_stdev = None;
+                let instance_matrix = match r.composite_mode {
+                    CompositeMode::DropShadow {
+                        ref offset,
+                        ref stdev,
+                        ..
+                    } => {
+                        // Note: backdrop blurと同じく、stdevはぼかし処理に渡す値そのもの（ui scaleを掛けない）
+                        let stdev = stdev.evaluate(current_sec, &self.parameter_store);
+                        if stdev > 0.0 {
+                            // ぼかすためにいったんレイヤーに描画する
+                            inst_builder.begin_layer();
+                            shadow_blur_stdev = Some(unsafe { SafeF32::new_unchecked(stdev) });
+                        }
+
+                        Matrix4::translate(
+                            offset[0].evaluate(current_sec, &self.parameter_store)
+                                * r.base_scale_factor,
+                            offset[1].evaluate(current_sec, &self.parameter_store)
+                                * r.base_scale_factor,
+                        )
+                        .mul_mat4(matrix.clone())
+                    }
+                    _ => matrix.clone(),
+                };
+
+                let instance = CompositeInstanceData {
+                    pos_st: [w, h, 0.0, 0.0],
+                    uv_st: [
+                        ((r.texatlas_rect.right as f32 - r.texatlas_rect.left as f32) - 1.0)
+                            / tex_size.width as f32,
+                        ((r.texatlas_rect.bottom as f32 - r.texatlas_rect.top as f32) - 1.0)
+                            / tex_size.height as f32,
+                        (r.texatlas_rect.left as f32 + 0.5) / tex_size.width as f32,
+                        (r.texatlas_rect.top as f32 + 0.5) / tex_size.height as f32,
+                    ],
+                    position_modifier_matrix: instance_matrix.transpose().0,
+                    slice_borders: r.slice_borders,
+                    tex_size_pixels_composite_mode_opacity: [
+                        tex_size.width as _,
+                        tex_size.height as _,
+                        if inst_builder.is_in_layer() {
+                            r.composite_mode.shader_mode_value_without_backdrop()
+                        } else {
+                            r.composite_mode.shader_mode_value()
+                        },
+                        // 影をレイヤーに描画する場合はopacityは合成時に適用する
+                        if shadow_blur_stdev.is_some() {
+                            1.0
+                        } else {
+                            opacity
+                        },
+                    ],
+                    color_tint,
+                    pos_x_animation_data: [0.0; 4],
+                    pos_x_curve_control_points: [0.0; 4],
+                    pos_y_animation_data: [0.0; 4],
+                    pos_y_curve_control_points: [0.0; 4],
+                    pos_width_animation_data: [0.0; 4],
+                    pos_width_curve_control_points: [0.0; 4],
+                    pos_height_animation_data: [0.0; 4],
+                    pos_height_curve_control_points: [0.0; 4],
+                    effect_params,
+                    gradient_stop_offsets,
+                    effect_colors,
+                };
+
+                let mut hasher = DefaultHasher::new();
+                hasher.write(unsafe {
+                    core::slice::from_raw_parts(
+                        (&instance as *const CompositeInstanceData).cast::<u8>(),
+                        core::mem::size_of::<CompositeInstanceData>(),
+                    )
+                });
+                opacity.to_bits().hash(&mut hasher);
+                active_clip.hash(&mut hasher);
+                for &(_, layer_opacity, ref layer_clip) in open_layers.iter() {
+                    // 外側のレイヤーの合成パラメータも見た目に影響する
+                    layer_opacity.to_bits().hash(&mut hasher);
+                    layer_clip.hash(&mut hasher);
+                }
+                shadow_blur_stdev.hash(&mut hasher);
+                let mut bounds = ScreenBounds::of_transformed_rect(&instance_matrix, w, h);
+                if let Some(stdev) = shadow_blur_stdev {
+                    bounds = bounds.expand(blur_reach(stdev.value()));
+                }
+
                 unsafe {
                     core::ptr::write(
                         mapped_head
                             .cast::<CompositeInstanceData>()
                             .add(instance_slot_index),
-                        CompositeInstanceData {
-                            pos_st: [w, h, 0.0, 0.0],
-                            uv_st: [
-                                ((r.texatlas_rect.right as f32 - r.texatlas_rect.left as f32)
-                                    - 1.0)
-                                    / tex_size.width as f32,
-                                ((r.texatlas_rect.bottom as f32 - r.texatlas_rect.top as f32)
-                                    - 1.0)
-                                    / tex_size.height as f32,
-                                (r.texatlas_rect.left as f32 + 0.5) / tex_size.width as f32,
-                                (r.texatlas_rect.top as f32 + 0.5) / tex_size.height as f32,
-                            ],
-                            position_modifier_matrix: matrix.clone().transpose().0,
-                            slice_borders: r.slice_borders,
-                            tex_size_pixels_composite_mode_opacity: [
-                                tex_size.width as _,
-                                tex_size.height as _,
-                                r.composite_mode.shader_mode_value(),
-                                opacity,
-                            ],
-                            color_tint: match r.composite_mode {
-                                CompositeMode::DirectSourceOver => [0.0; 4],
-                                CompositeMode::ColorTint(ref t) => {
-                                    t.evaluate(current_sec, &self.parameter_store)
-                                }
-                                CompositeMode::FillColor(ref t) => {
-                                    t.evaluate(current_sec, &self.parameter_store)
-                                }
-                                CompositeMode::ColorTintBackdropBlur(ref t, _) => {
-                                    t.evaluate(current_sec, &self.parameter_store)
-                                }
-                                CompositeMode::FillColorBackdropBlur(ref t, _) => {
-                                    t.evaluate(current_sec, &self.parameter_store)
-                                }
-                            },
-                            pos_x_animation_data: [0.0; 4],
-                            pos_x_curve_control_points: [0.0; 4],
-                            pos_y_animation_data: [0.0; 4],
-                            pos_y_curve_control_points: [0.0; 4],
-                            pos_width_animation_data: [0.0; 4],
-                            pos_width_curve_control_points: [0.0; 4],
-                            pos_height_animation_data: [0.0; 4],
-                            pos_height_curve_control_points: [0.0; 4],
-                        },
+                        instance,
                     );
                 }
 
@@ -1371,8 +2454,11 @@ impl CompositeTree {
                     CompositeMode::ColorTintBackdropBlur(_, ref stdev)
                     | CompositeMode::FillColorBackdropBlur(_, ref stdev) => {
                         let stdev = stdev.evaluate(current_sec, &self.parameter_store);
+                        stdev.to_bits().hash(&mut hasher);
 
-                        if stdev > 0.0 {
+                        if stdev > 0.0 && !inst_builder.is_in_layer() {
+                            // 背景がぼかしの範囲内で変化したら描き直す必要がある
+                            damage_dependents.push((bounds, blur_reach(stdev)));
                             inst_builder.request_backdrop_blur(
                                 unsafe { SafeF32::new_unchecked(stdev) },
                                 br::Rect2D {
@@ -1394,14 +2480,62 @@ impl CompositeTree {
                     _ => 0,
                 };
 
-                if let Some((clip_rect_px, clip_config)) = active_clip {
-                    inst_builder.set_clip(&clip_rect_px, &clip_config);
-                } else {
-                    inst_builder.clear_clip();
+                match active_clip {
+                    // レイヤー内はクリップせず、合成時にクリップする
+                    _ if shadow_blur_stdev.is_some() => inst_builder.clear_clip(),
+                    Some((clip_rect_px, clip_config)) => {
+                        inst_builder.set_clip(&clip_rect_px, &clip_config)
+                    }
+                    None => inst_builder.clear_clip(),
                 }
 
                 inst_builder.draw_instance(instance_slot_index, backdrop_buffer_index);
                 instance_slot_index += 1;
+
+                if let Some(stdev) = shadow_blur_stdev {
+                    let layer = inst_builder.end_layer(Some(stdev));
+                    unsafe {
+                        write_layer_composite_instance(
+                            mapped_head,
+                            instance_slot_index,
+                            size,
+                            tex_size,
+                            opacity,
+                        );
+                    }
+
+                    if let Some((clip_rect_px, clip_config)) = active_clip {
+                        inst_builder.set_clip(&clip_rect_px, &clip_config);
+                    } else {
+                        inst_builder.clear_clip();
+                    }
+
+                    inst_builder.draw_layer(instance_slot_index, layer);
+                    instance_slot_index += 1;
+                }
+
+                drawn = Some(DrawnRectState {
+                    bounds,
+                    digest: hasher.finish(),
+                });
+            }
+
+            if let Some(drawn) = drawn {
+                match self.drawn_rects.remove(&index) {
+                    Some(old)
+                        if !was_dirty
+                            && old.digest == drawn.digest
+                            && old.bounds == drawn.bounds => {}
+                    Some(old) => {
+                        let b = old.bounds.union(drawn.bounds);
+                        damage = Some(damage.map_or(b, |d| d.union(b)));
+                    }
+                    None => {
+                        damage = Some(damage.map_or(drawn.bounds, |d| d.union(drawn.bounds)));
+                    }
+                }
+
+                drawn_rects.insert(index, drawn);
             }
 
             processes.extend(r.children.iter().rev().map(|&x| {
@@ -1430,6 +2564,42 @@ impl CompositeTree {
             }));
         }
 
+        // 今回描画されなかったものは前回の範囲を消す必要がある
+        for (_, old) in self.drawn_rects.drain() {
+            damage = Some(damage.map_or(old.bounds, |d| d.union(old.bounds)));
+        }
+        self.drawn_rects = drawn_rects;
+
+        if let Some(ref mut d) = damage {
+            // 依存関係で広がった分がさらに別のものに重なることがあるので、広がらなくなるまで繰り返す
+            loop {
+                let mut expanded = false;
+                for &(bounds, margin) in damage_dependents.iter() {
+                    let area = bounds.expand(margin);
+                    if d.overlaps(&area) && !d.contains(&area) {
+                        *d = d.union(area);
+                        expanded = true;
+                    }
+                }
+
+                if !expanded {
+                    break;
+                }
+            }
+        }
+        self.damage = if full_damage {
+            Some(size.into_rect(br::Offset2D::ZERO))
+        } else {
+            damage.and_then(|d| d.to_pixel_rect(size))
+        };
+
+        self.process_settle_watchers(current_sec, event_bus);
+        if !self.settle_watchers.is_empty() {
+            // 完了待ちのものがある間は毎フレーム確認する
+            next_change_sec = Some(current_sec);
+        }
+        self.next_change_sec = next_change_sec;
+
         // let update_time = update_timer.elapsed();
         // println!("instbuild({update_time:?}): {:?}", inst_builder.insts);
 
@@ -1437,16 +2607,34 @@ impl CompositeTree {
     }
 }
 
+/// CompositeRendererの描画先(スワップチェーンやオフスクリーンのテクスチャ)
+pub trait CompositeRenderTarget {
+    fn extent(&self) -> br::Extent2D;
+    fn color_format(&self) -> br::Format;
+    fn backbuffer_count(&self) -> usize;
+    fn backbuffer_image<'x>(&'x self, index: usize) -> br::VkHandleRef<'x, br::vk::VkImage>;
+    fn backbuffer_views<'x>(
+        &'x self,
+    ) -> impl Iterator<Item = br::VkHandleRef<'x, br::vk::VkImageView>> + 'x;
+    /// 最終パスを抜けたあとのバックバッファのレイアウト
+    fn presented_layout(&self) -> br::ImageLayout;
+}
+
 pub struct CompositeRenderer<'subsystem> {
     gfx_device: &'subsystem Subsystem,
     rp_grabbed: br::RenderPassObject<&'subsystem Subsystem>,
     rp_final: br::RenderPassObject<&'subsystem Subsystem>,
     rp_continue_grabbed: br::RenderPassObject<&'subsystem Subsystem>,
     rp_continue_final: br::RenderPassObject<&'subsystem Subsystem>,
+    rp_partial_grabbed: br::RenderPassObject<&'subsystem Subsystem>,
+    rp_partial_final: br::RenderPassObject<&'subsystem Subsystem>,
+    rp_layer: br::RenderPassObject<&'subsystem Subsystem>,
     fbs_grabbed: Vec<br::vk::VkFramebuffer>,
     fbs_final: Vec<br::vk::VkFramebuffer>,
     fbs_continue_grabbed: Vec<br::vk::VkFramebuffer>,
     fbs_continue_final: Vec<br::vk::VkFramebuffer>,
+    fbs_partial_grabbed: Vec<br::vk::VkFramebuffer>,
+    fbs_partial_final: Vec<br::vk::VkFramebuffer>,
     sampler: br::SamplerObject<&'subsystem Subsystem>,
     _dsl_input: br::DescriptorSetLayoutObject<&'subsystem Subsystem>,
     dsl_input_backdrop: br::DescriptorSetLayoutObject<&'subsystem Subsystem>,
@@ -1475,6 +2663,8 @@ impl Drop for CompositeRenderer<'_> {
         Self::release_all_framebuffers(self.gfx_device, &mut self.fbs_final);
         Self::release_all_framebuffers(self.gfx_device, &mut self.fbs_continue_grabbed);
         Self::release_all_framebuffers(self.gfx_device, &mut self.fbs_continue_final);
+        Self::release_all_framebuffers(self.gfx_device, &mut self.fbs_partial_grabbed);
+        Self::release_all_framebuffers(self.gfx_device, &mut self.fbs_partial_final);
         Self::release_all_framebuffers(self.gfx_device, &mut self.backdrop_blur_destination_fbs);
     }
 }
@@ -1495,7 +2685,7 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
             br::vk::VkPipelineColorBlendAttachmentState::PREMULTIPLIED,
         ]);
 
-    pub fn new(base_sys: &mut AppBaseSystem<'subsystem>, rt: &PrimaryRenderTarget) -> Self {
+    pub fn new(base_sys: &mut AppBaseSystem<'subsystem>, rt: &impl CompositeRenderTarget) -> Self {
         let rp_grabbed = base_sys
             .create_render_pass(&br::RenderPassCreateInfo2::new(
                 &[br::AttachmentDescription2::new(rt.color_format())
@@ -1523,7 +2713,7 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
         let rp_final = base_sys
             .create_render_pass(&br::RenderPassCreateInfo2::new(
                 &[br::AttachmentDescription2::new(rt.color_format())
-                    .with_layout_to(br::ImageLayout::PresentSrc.from_undefined())
+                    .with_layout_to(rt.presented_layout().from_undefined())
                     .color_memory_op(br::LoadOp::DontCare, br::StoreOp::Store)],
                 &[br::SubpassDescription2::new()
                     .colors(&[br::AttachmentReference2::color_attachment_opt(0)])],
@@ -1568,15 +2758,64 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
                 )],
             ))
             .unwrap();
-        base_sys
-            .subsystem
-            .dbg_set_name(&rp_continue_grabbed, c"CompositeRenderer::rp[grabbed,cont]");
-        let rp_continue_final = base_sys
+        base_sys
+            .subsystem
+            .dbg_set_name(&rp_continue_grabbed, c"CompositeRenderer::rp[grabbed,cont]");
+        let rp_continue_final = base_sys
+            .create_render_pass(&br::RenderPassCreateInfo2::new(
+                &[br::AttachmentDescription2::new(rt.color_format())
+                    .with_layout_to(rt.presented_layout().from(br::ImageLayout::TransferSrcOpt))
+                    .color_memory_op(br::LoadOp::Load, br::StoreOp::Store)],
+                &[br::SubpassDescription2::new()
+                    .colors(&[br::AttachmentReference2::color_attachment_opt(0)])],
+                &[br::SubpassDependency2::new(
+                    br::SubpassIndex::Internal(0),
+                    br::SubpassIndex::External,
+                )
+                .of_execution(
+                    br::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    br::PipelineStageFlags(0),
+                )
+                .of_memory(
+                    br::AccessFlags::COLOR_ATTACHMENT.write,
+                    br::AccessFlags::MEMORY.read,
+                )
+                .by_region()],
+            ))
+            .unwrap();
+        base_sys
+            .subsystem
+            .dbg_set_name(&rp_continue_final, c"CompositeRenderer::rp[final,cont]");
+        // 部分再描画用（前回presentした内容を読み込んでその上に描く）
+        let rp_partial_grabbed = base_sys
+            .create_render_pass(&br::RenderPassCreateInfo2::new(
+                &[br::AttachmentDescription2::new(rt.color_format())
+                    .with_layout_to(br::ImageLayout::TransferSrcOpt.from(rt.presented_layout()))
+                    .color_memory_op(br::LoadOp::Load, br::StoreOp::Store)],
+                &[br::SubpassDescription2::new()
+                    .colors(&[br::AttachmentReference2::color_attachment_opt(0)])],
+                &[br::SubpassDependency2::new(
+                    br::SubpassIndex::Internal(0),
+                    br::SubpassIndex::External,
+                )
+                .of_execution(
+                    br::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    br::PipelineStageFlags::TRANSFER,
+                )
+                .of_memory(
+                    br::AccessFlags::COLOR_ATTACHMENT.write,
+                    br::AccessFlags::TRANSFER.read,
+                )],
+            ))
+            .unwrap();
+        base_sys.subsystem.dbg_set_name(
+            &rp_partial_grabbed,
+            c"CompositeRenderer::rp[grabbed,partial]",
+        );
+        let rp_partial_final = base_sys
             .create_render_pass(&br::RenderPassCreateInfo2::new(
                 &[br::AttachmentDescription2::new(rt.color_format())
-                    .with_layout_to(
-                        br::ImageLayout::PresentSrc.from(br::ImageLayout::TransferSrcOpt),
-                    )
+                    .with_layout_to(rt.presented_layout().from(rt.presented_layout()))
                     .color_memory_op(br::LoadOp::Load, br::StoreOp::Store)],
                 &[br::SubpassDescription2::new()
                     .colors(&[br::AttachmentReference2::color_attachment_opt(0)])],
@@ -1597,12 +2836,39 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
             .unwrap();
         base_sys
             .subsystem
-            .dbg_set_name(&rp_continue_final, c"CompositeRenderer::rp[final,cont]");
+            .dbg_set_name(&rp_partial_final, c"CompositeRenderer::rp[final,partial]");
+        // Note: レイヤーはbackdrop bufferと同じフォーマットなので、framebufferはbackdrop_blur_destination_fbsを、パイプラインはrp_final向けのものを流用する（互換なrender pass）
+        let rp_layer = base_sys
+            .create_render_pass(&br::RenderPassCreateInfo2::new(
+                &[br::AttachmentDescription2::new(rt.color_format())
+                    .with_layout_to(br::ImageLayout::ShaderReadOnlyOpt.from_undefined())
+                    .color_memory_op(br::LoadOp::Clear, br::StoreOp::Store)],
+                &[br::SubpassDescription2::new()
+                    .colors(&[br::AttachmentReference2::color_attachment_opt(0)])],
+                &[br::SubpassDependency2::new(
+                    br::SubpassIndex::Internal(0),
+                    br::SubpassIndex::External,
+                )
+                .of_execution(
+                    br::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    br::PipelineStageFlags::FRAGMENT_SHADER,
+                )
+                .of_memory(
+                    br::AccessFlags::COLOR_ATTACHMENT.write,
+                    br::AccessFlags::SHADER.read,
+                )],
+            ))
+            .unwrap();
+        base_sys
+            .subsystem
+            .dbg_set_name(&rp_layer, c"CompositeRenderer::rp[layer]");
 
         let mut fbs_grabbed = Vec::with_capacity(rt.backbuffer_count());
         let mut fbs_final = Vec::with_capacity(rt.backbuffer_count());
         let mut fbs_continue_grabbed = Vec::with_capacity(rt.backbuffer_count());
         let mut fbs_continue_final = Vec::with_capacity(rt.backbuffer_count());
+        let mut fbs_partial_grabbed = Vec::with_capacity(rt.backbuffer_count());
+        let mut fbs_partial_final = Vec::with_capacity(rt.backbuffer_count());
         for bb in rt.backbuffer_views() {
             fbs_grabbed.push(
                 br::FramebufferObject::new(
@@ -1610,8 +2876,8 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
                     &br::FramebufferCreateInfo::new(
                         &rp_grabbed,
                         &[bb.as_transparent_ref()],
-                        rt.size.width,
-                        rt.size.height,
+                        rt.extent().width,
+                        rt.extent().height,
                     ),
                 )
                 .unwrap(),
@@ -1622,8 +2888,8 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
                     &br::FramebufferCreateInfo::new(
                         &rp_final,
                         &[bb.as_transparent_ref()],
-                        rt.size.width,
-                        rt.size.height,
+                        rt.extent().width,
+                        rt.extent().height,
                     ),
                 )
                 .unwrap(),
@@ -1634,8 +2900,8 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
                     &br::FramebufferCreateInfo::new(
                         &rp_continue_grabbed,
                         &[bb.as_transparent_ref()],
-                        rt.size.width,
-                        rt.size.height,
+                        rt.extent().width,
+                        rt.extent().height,
                     ),
                 )
                 .unwrap(),
@@ -1646,8 +2912,32 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
                     &br::FramebufferCreateInfo::new(
                         &rp_continue_final,
                         &[bb.as_transparent_ref()],
-                        rt.size.width,
-                        rt.size.height,
+                        rt.extent().width,
+                        rt.extent().height,
+                    ),
+                )
+                .unwrap(),
+            );
+            fbs_partial_grabbed.push(
+                br::FramebufferObject::new(
+                    base_sys.subsystem,
+                    &br::FramebufferCreateInfo::new(
+                        &rp_partial_grabbed,
+                        &[bb.as_transparent_ref()],
+                        rt.extent().width,
+                        rt.extent().height,
+                    ),
+                )
+                .unwrap(),
+            );
+            fbs_partial_final.push(
+                br::FramebufferObject::new(
+                    base_sys.subsystem,
+                    &br::FramebufferCreateInfo::new(
+                        &rp_partial_final,
+                        &[bb.as_transparent_ref()],
+                        rt.extent().width,
+                        rt.extent().height,
                     ),
                 )
                 .unwrap(),
@@ -1706,7 +2996,7 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
             .size
             .into_rect(br::Offset2D::ZERO)
             .make_viewport(0.0..1.0)];
-        let scissors = [rt.size.into_rect(br::Offset2D::ZERO)];
+        let scissors = [rt.extent().into_rect(br::Offset2D::ZERO)];
         let vp_state = br::PipelineViewportStateCreateInfo::new_array(&viewports, &scissors);
         let [
             pipeline_grabbed,
@@ -1775,7 +3065,7 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
 
         let mut grab_buffer = br::ImageObject::new(
             base_sys.subsystem,
-            &br::ImageCreateInfo::new(rt.size, rt.color_format())
+            &br::ImageCreateInfo::new(rt.extent(), rt.color_format())
                 .with_usage(br::ImageUsageFlags::SAMPLED | br::ImageUsageFlags::TRANSFER_DEST),
         )
         .unwrap();
@@ -1802,7 +3092,7 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
             Vec::<br::DescriptorSet>::with_capacity(Self::INITIAL_BACKDROP_BUFFER_COUNT);
 
         let backdrop_fx_blur_processor =
-            BackdropEffectBlurProcessor::new(base_sys, rt.size, rt.color_format());
+            BackdropEffectBlurProcessor::new(base_sys, rt.extent(), rt.color_format());
 
         let mut fixed_descriptor_pool = br::DescriptorPoolObject::new(
             base_sys.subsystem,
@@ -1863,6 +3153,9 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
             rp_final,
             rp_continue_grabbed,
             rp_continue_final,
+            rp_partial_grabbed,
+            rp_partial_final,
+            rp_layer,
             fbs_grabbed: fbs_grabbed.into_iter().map(|x| x.unmanage().0).collect(),
             fbs_final: fbs_final.into_iter().map(|x| x.unmanage().0).collect(),
             fbs_continue_grabbed: fbs_continue_grabbed
@@ -1873,6 +3166,14 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
                 .into_iter()
                 .map(|x| x.unmanage().0)
                 .collect(),
+            fbs_partial_grabbed: fbs_partial_grabbed
+                .into_iter()
+                .map(|x| x.unmanage().0)
+                .collect(),
+            fbs_partial_final: fbs_partial_final
+                .into_iter()
+                .map(|x| x.unmanage().0)
+                .collect(),
             sampler,
             _dsl_input: dsl_input,
             dsl_input_backdrop,
@@ -1912,17 +3213,21 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
     pub fn recreate_rt_resources<'s>(
         &'s mut self,
         base_sys: &mut AppBaseSystem<'subsystem>,
-        rt: &PrimaryRenderTarget,
+        rt: &impl CompositeRenderTarget,
         descriptor_writes: &mut Vec<br::DescriptorSetWriteInfo<'s>>,
     ) {
         Self::release_all_framebuffers(self.gfx_device, &mut self.fbs_grabbed);
         Self::release_all_framebuffers(self.gfx_device, &mut self.fbs_final);
         Self::release_all_framebuffers(self.gfx_device, &mut self.fbs_continue_grabbed);
         Self::release_all_framebuffers(self.gfx_device, &mut self.fbs_continue_final);
+        Self::release_all_framebuffers(self.gfx_device, &mut self.fbs_partial_grabbed);
+        Self::release_all_framebuffers(self.gfx_device, &mut self.fbs_partial_final);
         let mut fbs_grabbed = Vec::with_capacity(rt.backbuffer_count());
         let mut fbs_final = Vec::with_capacity(rt.backbuffer_count());
         let mut fbs_continue_grabbed = Vec::with_capacity(rt.backbuffer_count());
         let mut fbs_continue_final = Vec::with_capacity(rt.backbuffer_count());
+        let mut fbs_partial_grabbed = Vec::with_capacity(rt.backbuffer_count());
+        let mut fbs_partial_final = Vec::with_capacity(rt.backbuffer_count());
         for bb in rt.backbuffer_views() {
             fbs_grabbed.push(
                 br::FramebufferObject::new(
@@ -1930,8 +3235,8 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
                     &br::FramebufferCreateInfo::new(
                         &self.rp_grabbed,
                         &[bb.as_transparent_ref()],
-                        rt.size.width,
-                        rt.size.height,
+                        rt.extent().width,
+                        rt.extent().height,
                     ),
                 )
                 .unwrap(),
@@ -1942,8 +3247,8 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
                     &br::FramebufferCreateInfo::new(
                         &self.rp_final,
                         &[bb.as_transparent_ref()],
-                        rt.size.width,
-                        rt.size.height,
+                        rt.extent().width,
+                        rt.extent().height,
                     ),
                 )
                 .unwrap(),
@@ -1954,8 +3259,8 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
                     &br::FramebufferCreateInfo::new(
                         &self.rp_continue_grabbed,
                         &[bb.as_transparent_ref()],
-                        rt.size.width,
-                        rt.size.height,
+                        rt.extent().width,
+                        rt.extent().height,
                     ),
                 )
                 .unwrap(),
@@ -1966,8 +3271,32 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
                     &br::FramebufferCreateInfo::new(
                         &self.rp_continue_final,
                         &[bb.as_transparent_ref()],
-                        rt.size.width,
-                        rt.size.height,
+                        rt.extent().width,
+                        rt.extent().height,
+                    ),
+                )
+                .unwrap(),
+            );
+            fbs_partial_grabbed.push(
+                br::FramebufferObject::new(
+                    self.gfx_device,
+                    &br::FramebufferCreateInfo::new(
+                        &self.rp_partial_grabbed,
+                        &[bb.as_transparent_ref()],
+                        rt.extent().width,
+                        rt.extent().height,
+                    ),
+                )
+                .unwrap(),
+            );
+            fbs_partial_final.push(
+                br::FramebufferObject::new(
+                    self.gfx_device,
+                    &br::FramebufferCreateInfo::new(
+                        &self.rp_partial_final,
+                        &[bb.as_transparent_ref()],
+                        rt.extent().width,
+                        rt.extent().height,
                     ),
                 )
                 .unwrap(),
@@ -1983,7 +3312,7 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
         }
         let mut grab_buffer = br::ImageObject::new(
             self.gfx_device,
-            &br::ImageCreateInfo::new(rt.size, rt.color_format())
+            &br::ImageCreateInfo::new(rt.extent(), rt.color_format())
                 .with_usage(br::ImageUsageFlags::SAMPLED | br::ImageUsageFlags::TRANSFER_DEST),
         )
         .unwrap();
@@ -2011,7 +3340,7 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
             .size
             .into_rect(br::Offset2D::ZERO)
             .make_viewport(0.0..1.0)];
-        let scissors = [rt.size.into_rect(br::Offset2D::ZERO)];
+        let scissors = [rt.extent().into_rect(br::Offset2D::ZERO)];
         let vp_state = br::PipelineViewportStateCreateInfo::new_array(&viewports, &scissors);
         let [
             pipeline_grabbed,
@@ -2071,8 +3400,11 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
         self.pipeline_continue_grabbed = pipeline_continue_grabbed;
         self.pipeline_continue_final = pipeline_continue_final;
 
-        self.backdrop_fx_blur_processor
-            .recreate_rt_resources(base_sys, rt.size, rt.color_format());
+        self.backdrop_fx_blur_processor.recreate_rt_resources(
+            base_sys,
+            rt.extent(),
+            rt.color_format(),
+        );
         self.backdrop_fx_blur_processor.write_input_descriptor_sets(
             descriptor_writes,
             &self.grab_buffer,
@@ -2087,6 +3419,10 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
             .extend(fbs_continue_grabbed.into_iter().map(|x| x.unmanage().0));
         self.fbs_continue_final
             .extend(fbs_continue_final.into_iter().map(|x| x.unmanage().0));
+        self.fbs_partial_grabbed
+            .extend(fbs_partial_grabbed.into_iter().map(|x| x.unmanage().0));
+        self.fbs_partial_final
+            .extend(fbs_partial_final.into_iter().map(|x| x.unmanage().0));
     }
 
     pub fn ready_input_backdrop_descriptor_sets(&mut self, required_count: usize) {
@@ -2133,7 +3469,7 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
     pub fn update_backdrop_resources(
         &mut self,
         base_sys: &AppBaseSystem<'subsystem>,
-        rt: &PrimaryRenderTarget,
+        rt: &impl CompositeRenderTarget,
     ) -> bool {
         if !self.backdrop_buffers_invalidated {
             // no changes
@@ -2153,9 +3489,10 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
         for _ in 0..backdrop_count {
             let image = br::ImageObject::new(
                 self.gfx_device,
-                &br::ImageCreateInfo::new(rt.size, rt.color_format()).with_usage(
+                &br::ImageCreateInfo::new(rt.extent(), rt.color_format()).with_usage(
                     br::ImageUsageFlags::SAMPLED
                         | br::ImageUsageFlags::COLOR_ATTACHMENT
+                        | br::ImageUsageFlags::TRANSFER_SRC
                         | br::ImageUsageFlags::TRANSFER_DEST,
                 ),
             )
@@ -2204,8 +3541,8 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
                     &br::FramebufferCreateInfo::new(
                         self.backdrop_fx_blur_processor.final_render_pass(),
                         &[b.as_transparent_ref()],
-                        rt.size.width,
-                        rt.size.height,
+                        rt.extent().width,
+                        rt.extent().height,
                     ),
                 )
                 .unwrap()
@@ -2274,6 +3611,223 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
         self.rp_continue_final.subpass(0)
     }
 
+    fn populate_layer_commands<'x>(
+        &self,
+        mut rec: br::CmdRecord<'x>,
+        render_data: &CompositeRenderingData,
+        layer: &CompositeLayerRenderingData,
+        buffer_index: usize,
+        rt_size: br::Extent2D,
+        custom_render: &mut impl FnMut(CustomRenderToken, br::CmdRecord<'x>) -> br::CmdRecord<'x>,
+    ) -> br::CmdRecord<'x> {
+        rec = rec.inject(|r| {
+            inject_cmd_begin_render_pass2(
+                r,
+                self.gfx_device,
+                &br::RenderPassBeginInfo::new(
+                    &self.rp_layer,
+                    br::VkHandleRef::from_raw_ref(
+                        &self.backdrop_blur_destination_fbs[buffer_index],
+                    ),
+                    rt_size.into_rect(br::Offset2D::ZERO),
+                    &[br::ClearValue::color_f32([0.0, 0.0, 0.0, 0.0])],
+                ),
+                &br::SubpassBeginInfo::new(br::SubpassContents::Inline),
+            )
+        });
+
+        let mut pipeline_bound = false;
+        for x in layer.instructions.iter() {
+            if !pipeline_bound {
+                pipeline_bound = true;
+
+                // rp_layerとrp_finalは互換なのでそのまま使える
+                rec = rec
+                    .bind_pipeline(br::PipelineBindPoint::Graphics, &self.pipeline_final)
+                    .push_constant(
+                        &self.pipeline_layout,
+                        br::vk::VK_SHADER_STAGE_VERTEX_BIT,
+                        0,
+                        &[rt_size.width as f32, rt_size.height as f32],
+                    )
+                    .push_constant(
+                        &self.pipeline_layout,
+                        br::vk::VK_SHADER_STAGE_FRAGMENT_BIT,
+                        48,
+                        &[0.0f32, 0.0, 1.0, 1.0],
+                    )
+                    .bind_descriptor_sets(
+                        br::PipelineBindPoint::Graphics,
+                        &self.pipeline_layout,
+                        0,
+                        &[self.alphamask_group_input_descriptor_set],
+                        &[],
+                    );
+            }
+
+            match x {
+                &CompositeRenderingInstruction::DrawInstanceRange {
+                    ref index_range,
+                    backdrop_buffer,
+                } => {
+                    rec = rec
+                        .bind_descriptor_sets(
+                            br::PipelineBindPoint::Graphics,
+                            &self.pipeline_layout,
+                            1,
+                            &[self.input_backdrop_descriptor_sets[backdrop_buffer]],
+                            &[],
+                        )
+                        .draw(4, index_range.len() as _, 0, index_range.start as _);
+                }
+                &CompositeRenderingInstruction::DrawLayer {
+                    instance_index,
+                    layer,
+                } => {
+                    rec = rec
+                        .bind_descriptor_sets(
+                            br::PipelineBindPoint::Graphics,
+                            &self.pipeline_layout,
+                            1,
+                            &[self.input_backdrop_descriptor_sets
+                                [render_data.layer_buffer_index(layer)]],
+                            &[],
+                        )
+                        .draw(4, 1, 0, instance_index as _);
+                }
+                &CompositeRenderingInstruction::InsertCustomRenderCommands(token) => {
+                    rec = custom_render(token, rec);
+
+                    // 別のパイプラインをつかっている可能性があるのでいったん紐づいているのを無効化する
+                    pipeline_bound = false;
+                }
+                &CompositeRenderingInstruction::SetClip {
+                    ref shader_parameters,
+                } => {
+                    rec = rec.push_constant(
+                        &self.pipeline_layout,
+                        br::vk::VK_SHADER_STAGE_FRAGMENT_BIT,
+                        16,
+                        &[
+                            shader_parameters[0].value() / rt_size.width as f32,
+                            shader_parameters[1].value() / rt_size.height as f32,
+                            shader_parameters[2].value() / rt_size.width as f32,
+                            shader_parameters[3].value() / rt_size.height as f32,
+                            shader_parameters[4].value() / rt_size.width as f32,
+                            shader_parameters[5].value() / rt_size.height as f32,
+                            shader_parameters[6].value() / rt_size.width as f32,
+                            shader_parameters[7].value() / rt_size.height as f32,
+                        ],
+                    );
+                }
+                CompositeRenderingInstruction::ClearClip => {
+                    rec = rec.push_constant(
+                        &self.pipeline_layout,
+                        br::vk::VK_SHADER_STAGE_FRAGMENT_BIT,
+                        16,
+                        &[0.0f32, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0],
+                    );
+                }
+                CompositeRenderingInstruction::GrabBackdrop
+                | CompositeRenderingInstruction::GenerateBackdropBlur { .. } => {
+                    unreachable!("backdrop operations are not allowed in offscreen layers")
+                }
+            }
+        }
+
+        rec = rec.inject(|r| {
+            inject_cmd_end_render_pass2(r, self.gfx_device, &br::SubpassEndInfo::new())
+        });
+
+        let Some(stdev) = layer.blur_stdev else {
+            // no postprocess
+            return rec;
+        };
+
+        // ぼかしの入力はgrab_buffer固定なので、いったんコピーしてからレイヤー自身に書き戻す
+        let layer_image = self.backdrop_buffers[buffer_index].image();
+        rec.inject(|r| {
+            inject_cmd_pipeline_barrier_2(
+                r,
+                self.gfx_device,
+                &br::DependencyInfo::new(
+                    &[],
+                    &[],
+                    &[
+                        br::ImageMemoryBarrier2::new(
+                            layer_image,
+                            br::ImageSubresourceRange::new(br::AspectMask::COLOR, 0..1, 0..1),
+                        )
+                        .transit_from(
+                            br::ImageLayout::ShaderReadOnlyOpt.to(br::ImageLayout::TransferSrcOpt),
+                        )
+                        .from(
+                            br::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                            br::AccessFlags2::COLOR_ATTACHMENT.write,
+                        )
+                        .to(
+                            br::PipelineStageFlags2::COPY,
+                            br::AccessFlags2::TRANSFER.read,
+                        ),
+                        br::ImageMemoryBarrier2::new(
+                            self.grab_buffer.image(),
+                            br::ImageSubresourceRange::new(br::AspectMask::COLOR, 0..1, 0..1),
+                        )
+                        .transit_to(br::ImageLayout::TransferDestOpt.from_undefined()),
+                    ],
+                ),
+            )
+        })
+        .copy_image(
+            layer_image,
+            br::ImageLayout::TransferSrcOpt,
+            self.grab_buffer.image(),
+            br::ImageLayout::TransferDestOpt,
+            &[br::ImageCopy {
+                srcSubresource: br::ImageSubresourceLayers::new(br::AspectMask::COLOR, 0, 0..1),
+                dstSubresource: br::ImageSubresourceLayers::new(br::AspectMask::COLOR, 0, 0..1),
+                srcOffset: br::Offset3D::ZERO,
+                dstOffset: br::Offset3D::ZERO,
+                extent: rt_size.with_depth(1),
+            }],
+        )
+        .inject(|r| {
+            inject_cmd_pipeline_barrier_2(
+                r,
+                self.gfx_device,
+                &br::DependencyInfo::new(
+                    &[],
+                    &[],
+                    &[br::ImageMemoryBarrier2::new(
+                        self.grab_buffer.image(),
+                        br::ImageSubresourceRange::new(br::AspectMask::COLOR, 0..1, 0..1),
+                    )
+                    .transit_from(
+                        br::ImageLayout::TransferDestOpt.to(br::ImageLayout::ShaderReadOnlyOpt),
+                    )
+                    .from(
+                        br::PipelineStageFlags2::COPY,
+                        br::AccessFlags2::TRANSFER.write,
+                    )
+                    .to(
+                        br::PipelineStageFlags2::FRAGMENT_SHADER,
+                        br::AccessFlags2::SHADER.read,
+                    )],
+                ),
+            )
+        })
+        .inject(|r| {
+            self.backdrop_fx_blur_processor.populate_commands(
+                r,
+                stdev,
+                br::VkHandleRef::from_raw_ref(&self.backdrop_blur_destination_fbs[buffer_index]),
+                self.gfx_device,
+                rt_size,
+                &self.blur_fixed_descriptor_sets,
+            )
+        })
+    }
+
     pub fn populate_commands<'x>(
         &self,
         mut rec: br::CmdRecord<'x>,
@@ -2281,9 +3835,47 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
         rt_size: br::Extent2D,
         rt_image: &(impl br::VkHandle<Handle = br::vk::VkImage> + ?Sized),
         backbuffer_index: usize,
+        damage: Option<br::Rect2D>,
         mut custom_render: impl FnMut(CustomRenderToken, br::CmdRecord<'x>) -> br::CmdRecord<'x>,
     ) -> br::CmdRecord<'x> {
-        let render_region = rt_size.into_rect(br::Offset2D::ZERO);
+        // damageが指定されている場合は前回の内容の上にその範囲だけ描き直す
+        let render_region = damage.unwrap_or_else(|| rt_size.into_rect(br::Offset2D::ZERO));
+        let damage_uv = [
+            render_region.offset.x as f32 / rt_size.width as f32,
+            render_region.offset.y as f32 / rt_size.height as f32,
+            (render_region.offset.x + render_region.extent.width as i32) as f32
+                / rt_size.width as f32,
+            (render_region.offset.y + render_region.extent.height as i32) as f32
+                / rt_size.height as f32,
+        ];
+        let (rp_first_grabbed, fbs_first_grabbed, rp_first_final, fbs_first_final) =
+            if damage.is_some() {
+                (
+                    &self.rp_partial_grabbed,
+                    &self.fbs_partial_grabbed,
+                    &self.rp_partial_final,
+                    &self.fbs_partial_final,
+                )
+            } else {
+                (
+                    &self.rp_grabbed,
+                    &self.fbs_grabbed,
+                    &self.rp_final,
+                    &self.fbs_final,
+                )
+            };
+
+        // オフスクリーンレイヤーはメインの描画の前に済ませておく
+        for (n, layer) in render_data.layers.iter().enumerate() {
+            rec = self.populate_layer_commands(
+                rec,
+                render_data,
+                layer,
+                render_data.layer_buffer_index(n),
+                rt_size,
+                &mut custom_render,
+            );
+        }
 
         let mut in_render_pass = false;
         let mut rpt_pointer = 0;
@@ -2291,10 +3883,23 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
 
         for x in render_data.instructions.iter() {
             match x {
-                &CompositeRenderingInstruction::DrawInstanceRange {
-                    ref index_range,
-                    backdrop_buffer,
-                } => {
+                x @ (CompositeRenderingInstruction::DrawInstanceRange { .. }
+                | CompositeRenderingInstruction::DrawLayer { .. }) => {
+                    let (index_range, backdrop_buffer) = match x {
+                        &CompositeRenderingInstruction::DrawInstanceRange {
+                            ref index_range,
+                            backdrop_buffer,
+                        } => (index_range.clone(), backdrop_buffer),
+                        &CompositeRenderingInstruction::DrawLayer {
+                            instance_index,
+                            layer,
+                        } => (
+                            instance_index..instance_index + 1,
+                            render_data.layer_buffer_index(layer),
+                        ),
+                        _ => unreachable!(),
+                    };
+
                     if !in_render_pass {
                         in_render_pass = true;
 
@@ -2304,15 +3909,15 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
                                 continued: false,
                                 after_operation: RenderPassAfterOperation::Grab,
                             } => {
-                                rp = &self.rp_grabbed;
-                                fb = self.fbs_grabbed[backbuffer_index];
+                                rp = rp_first_grabbed;
+                                fb = fbs_first_grabbed[backbuffer_index];
                             }
                             RenderPassRequirements {
                                 continued: false,
                                 after_operation: RenderPassAfterOperation::None,
                             } => {
-                                rp = &self.rp_final;
-                                fb = self.fbs_final[backbuffer_index];
+                                rp = rp_first_final;
+                                fb = fbs_first_final[backbuffer_index];
                             }
                             RenderPassRequirements {
                                 continued: true,
@@ -2375,6 +3980,12 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
                                 0,
                                 &[rt_size.width as f32, rt_size.height as f32],
                             )
+                            .push_constant(
+                                &self.pipeline_layout,
+                                br::vk::VK_SHADER_STAGE_FRAGMENT_BIT,
+                                48,
+                                &damage_uv,
+                            )
                             .bind_descriptor_sets(
                                 br::PipelineBindPoint::Graphics,
                                 &self.pipeline_layout,
@@ -2404,15 +4015,15 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
                                 continued: false,
                                 after_operation: RenderPassAfterOperation::Grab,
                             } => {
-                                rp = &self.rp_grabbed;
-                                fb = self.fbs_grabbed[backbuffer_index];
+                                rp = rp_first_grabbed;
+                                fb = fbs_first_grabbed[backbuffer_index];
                             }
                             RenderPassRequirements {
                                 continued: false,
                                 after_operation: RenderPassAfterOperation::None,
                             } => {
-                                rp = &self.rp_final;
-                                fb = self.fbs_final[backbuffer_index];
+                                rp = rp_first_final;
+                                fb = fbs_first_final[backbuffer_index];
                             }
                             RenderPassRequirements {
                                 continued: true,
@@ -2462,15 +4073,15 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
                                 continued: false,
                                 after_operation: RenderPassAfterOperation::Grab,
                             } => {
-                                rp = &self.rp_grabbed;
-                                fb = self.fbs_grabbed[backbuffer_index];
+                                rp = rp_first_grabbed;
+                                fb = fbs_first_grabbed[backbuffer_index];
                             }
                             RenderPassRequirements {
                                 continued: false,
                                 after_operation: RenderPassAfterOperation::None,
                             } => {
-                                rp = &self.rp_final;
-                                fb = self.fbs_final[backbuffer_index];
+                                rp = rp_first_final;
+                                fb = fbs_first_final[backbuffer_index];
                             }
                             RenderPassRequirements {
                                 continued: true,
@@ -2533,6 +4144,12 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
                                 0,
                                 &[rt_size.width as f32, rt_size.height as f32],
                             )
+                            .push_constant(
+                                &self.pipeline_layout,
+                                br::vk::VK_SHADER_STAGE_FRAGMENT_BIT,
+                                48,
+                                &damage_uv,
+                            )
                             .bind_descriptor_sets(
                                 br::PipelineBindPoint::Graphics,
                                 &self.pipeline_layout,
@@ -2568,15 +4185,15 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
                                 continued: false,
                                 after_operation: RenderPassAfterOperation::Grab,
                             } => {
-                                rp = &self.rp_grabbed;
-                                fb = self.fbs_grabbed[backbuffer_index];
+                                rp = rp_first_grabbed;
+                                fb = fbs_first_grabbed[backbuffer_index];
                             }
                             RenderPassRequirements {
                                 continued: false,
                                 after_operation: RenderPassAfterOperation::None,
                             } => {
-                                rp = &self.rp_final;
-                                fb = self.fbs_final[backbuffer_index];
+                                rp = rp_first_final;
+                                fb = fbs_first_final[backbuffer_index];
                             }
                             RenderPassRequirements {
                                 continued: true,
@@ -2639,6 +4256,12 @@ impl<'subsystem> CompositeRenderer<'subsystem> {
                                 0,
                                 &[rt_size.width as f32, rt_size.height as f32],
                             )
+                            .push_constant(
+                                &self.pipeline_layout,
+                                br::vk::VK_SHADER_STAGE_FRAGMENT_BIT,
+                                48,
+                                &damage_uv,
+                            )
                             .bind_descriptor_sets(
                                 br::PipelineBindPoint::Graphics,
                                 &self.pipeline_layout,
@@ -3543,3 +5166,215 @@ impl<'d> CompositionSurfaceAtlas<'d> {
         raw
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "expected {expected}, but was {actual}"
+        );
+    }
+
+    #[test]
+    fn curves_start_at_0_and_end_at_1() {
+        let curves = [
+            AnimationCurve::Linear,
+            AnimationCurve::EASE,
+            AnimationCurve::EASE_IN_OUT,
+            AnimationCurve::Steps {
+                count: 3,
+                jump_at_start: false,
+            },
+            AnimationCurve::Spring {
+                damping: 4.0,
+                frequency: 2.5,
+            },
+            AnimationCurve::Spring {
+                damping: 6.0,
+                frequency: 0.0,
+            },
+        ];
+
+        for c in &curves {
+            assert_near(c.interpolate(0.0), 0.0);
+            assert_near(c.interpolate(1.0), 1.0);
+        }
+    }
+
+    #[test]
+    fn spring_is_continuous_at_end() {
+        // 減衰しきらない設定でも終端で値が飛ばない
+        for (damping, frequency) in [(1.0, 2.5), (2.0, 0.3), (0.5, 0.0)] {
+            let before_end = interpolate_spring(1.0 - 1e-5, damping, frequency);
+            assert!(
+                (before_end - 1.0).abs() < 1e-3,
+                "damping={damping} frequency={frequency}: {before_end}"
+            );
+        }
+    }
+
+    #[test]
+    fn spring_overshoots() {
+        let max = (1..100)
+            .map(|n| interpolate_spring(n as f32 / 100.0, 3.0, 2.0))
+            .fold(0.0f32, f32::max);
+        assert!(max > 1.0);
+    }
+
+    #[test]
+    fn steps() {
+        assert_near(interpolate_steps(0.3, 4, false), 0.25);
+        assert_near(interpolate_steps(0.3, 4, true), 0.5);
+        assert_near(interpolate_steps(0.5, 4, false), 0.5);
+        assert_near(interpolate_steps(0.5, 0, false), 1.0);
+    }
+
+    #[test]
+    fn gradient_stops_are_padded_with_last_stop() {
+        let (offsets, colors) =
+            pack_gradient_stops(&[(0.0, [1.0, 0.0, 0.0, 1.0]), (0.5, [0.0, 1.0, 0.0, 1.0])]);
+
+        assert_eq!(offsets, [0.0, 0.5, 0.5, 0.5]);
+        assert_eq!(colors[3], [0.0, 1.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn excess_gradient_stops_are_resampled() {
+        let (offsets, colors) = pack_gradient_stops(&[
+            (0.0, [0.0, 0.0, 0.0, 1.0]),
+            (0.25, [0.25, 0.0, 0.0, 1.0]),
+            (0.5, [0.5, 0.0, 0.0, 1.0]),
+            (0.75, [0.75, 0.0, 0.0, 1.0]),
+            (1.0, [1.0, 0.0, 0.0, 1.0]),
+            (1.0, [1.0, 1.0, 1.0, 1.0]),
+        ]);
+
+        assert_near(offsets[0], 0.0);
+        assert_near(offsets[1], 1.0 / 3.0);
+        assert_near(offsets[3], 1.0);
+        assert_near(colors[1][0], 1.0 / 3.0);
+        assert_eq!(colors[0], [0.0, 0.0, 0.0, 1.0]);
+        // 最後のストップの色で終わる
+        assert_eq!(colors[3], [1.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn keyframe_track_once() {
+        let track = KeyframeTrack::new(1.0, 0.0f32)
+            .then(1.0, 10.0, AnimationCurve::Linear)
+            .hold(0.5)
+            .then(1.0, 20.0, AnimationCurve::Linear);
+
+        assert_near(track.duration_sec(), 2.5);
+        assert_eq!(track.end_sec(), Some(3.5));
+        assert_near(track.evaluate(0.0), 0.0);
+        assert_near(track.evaluate(1.5), 5.0);
+        assert_near(track.evaluate(2.25), 10.0);
+        assert_near(track.evaluate(3.0), 15.0);
+        assert_near(track.evaluate(10.0), 20.0);
+        assert!(track.is_animating_at(3.4));
+        assert!(!track.is_animating_at(3.5));
+        assert!(track.is_settling_at(3.4));
+        assert!(!track.is_settling_at(3.5));
+        assert_eq!(track.next_change_sec(0.5), Some(1.0));
+        assert_eq!(track.next_change_sec(4.0), None);
+    }
+
+    #[test]
+    fn keyframe_track_repeat() {
+        let looped = KeyframeTrack::new(0.0, 0.0f32)
+            .then(1.0, 10.0, AnimationCurve::Linear)
+            .repeat(AnimationRepeat::Loop);
+        assert_eq!(looped.end_sec(), None);
+        assert_near(looped.evaluate(2.25), 2.5);
+
+        let ping_pong = KeyframeTrack::new(0.0, 0.0f32)
+            .then(1.0, 10.0, AnimationCurve::Linear)
+            .repeat(AnimationRepeat::PingPong);
+        assert_near(ping_pong.evaluate(0.25), 2.5);
+        assert_near(ping_pong.evaluate(1.25), 7.5);
+        assert!(ping_pong.is_animating_at(100.0));
+        assert!(!ping_pong.is_settling_at(100.0));
+    }
+
+    #[test]
+    fn single_keyframe_is_static() {
+        let track = KeyframeTrack::new(0.0, [1.0f32, 2.0, 3.0, 4.0]);
+        assert!(!track.is_animating_at(0.0));
+        assert_eq!(track.evaluate(5.0), [1.0, 2.0, 3.0, 4.0]);
+    }
+
+    fn popped_unmount_ids(events: &AppEventBus) -> Vec<uuid::Uuid> {
+        std::iter::from_fn(|| events.pop())
+            .map(|e| match e {
+                AppEvent::UIPopupUnmount { id } => id,
+                _ => panic!("unexpected event"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn settle_watchers_ignore_repeating_tracks() {
+        let mut tree = CompositeTree::new();
+        let parent = tree.register(CompositeRect {
+            opacity: AnimatableFloat::Animated {
+                start_sec: 0.0,
+                end_sec: 1.0,
+                from_value: 1.0,
+                to_value: 0.0,
+                curve: AnimationCurve::Linear,
+                event_on_complete: None,
+            },
+            ..Default::default()
+        });
+        let spinner = tree.register(CompositeRect {
+            rotation: AnimatableFloat::Track(
+                KeyframeTrack::new(0.0, 0.0)
+                    .then(1.0, 360.0, AnimationCurve::Linear)
+                    .repeat(AnimationRepeat::Loop),
+            ),
+            ..Default::default()
+        });
+        tree.add_child(parent, spinner);
+        let id = uuid::Uuid::new_v4();
+        tree.notify_when_settled(parent, AppEvent::UIPopupUnmount { id });
+
+        let events = AppEventBus::new();
+        tree.process_settle_watchers(0.5, &events);
+        assert!(popped_unmount_ids(&events).is_empty());
+        tree.process_settle_watchers(1.0, &events);
+        assert_eq!(popped_unmount_ids(&events), [id]);
+    }
+
+    #[test]
+    fn settle_watchers_are_dropped_with_freed_root() {
+        let mut tree = CompositeTree::new();
+        let root = tree.register(CompositeRect {
+            opacity: AnimatableFloat::Animated {
+                start_sec: 0.0,
+                end_sec: 1.0,
+                from_value: 1.0,
+                to_value: 0.0,
+                curve: AnimationCurve::Linear,
+                event_on_complete: None,
+            },
+            ..Default::default()
+        });
+        tree.notify_when_settled(
+            root,
+            AppEvent::UIPopupUnmount {
+                id: uuid::Uuid::new_v4(),
+            },
+        );
+        tree.free(root);
+        // 同じインデックスが別のノードとして再利用される
+        assert!(tree.register(CompositeRect::default()) == root);
+
+        let events = AppEventBus::new();
+        tree.process_settle_watchers(2.0, &events);
+        assert!(popped_unmount_ids(&events).is_empty());
+    }
+}
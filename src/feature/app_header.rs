@@ -13,7 +13,7 @@ use crate::{
         AppBaseSystem, BufferMapMode, FontType, MemoryBoundBuffer, PixelFormat, RenderPassOptions,
         RenderTexture, RenderTextureFlags, RenderTextureOptions, inject_cmd_begin_render_pass2,
         inject_cmd_end_render_pass2, inject_cmd_pipeline_barrier_2,
-        scratch_buffer::StagingScratchBufferMapMode,
+        scratch_buffer::StagingScratchBufferMapMode, theme::ThemeColor,
     },
     composite::{
         AnimatableColor, AnimatableFloat, AnimationCurve, CompositeMode, CompositeRect,
@@ -318,10 +318,13 @@ impl SystemCommandButtonView {
         let ct_hover = init.base_system.register_composite_rect(CompositeRect {
             relative_size_adjustment: [1.0, 1.0],
             has_bitmap: true,
-            composite_mode: CompositeMode::FillColor(AnimatableColor::Value(match init_cmd {
-                SystemCommand::Close => [1.0, 0.0, 0.0, 1.0],
-                _ => [1.0, 1.0, 1.0, 0.5],
-            })),
+            composite_mode: CompositeMode::FillColor(match init_cmd {
+                SystemCommand::Close => AnimatableColor::Value([1.0, 0.0, 0.0, 1.0]),
+                _ => init
+                    .base_system
+                    .theme
+                    .color_with_alpha(ThemeColor::Foreground, 0.5),
+            }),
             opacity: AnimatableFloat::Value(0.0),
             ..Default::default()
         });
@@ -338,7 +341,9 @@ impl SystemCommandButtonView {
             ],
             has_bitmap: true,
             texatlas_rect: icon_atlas_rect.clone(),
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value([0.9, 0.9, 0.9, 1.0])),
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::Text),
+            ),
             ..Default::default()
         });
 
@@ -589,7 +594,10 @@ impl MenuButtonView {
         let ct_bg = init.base_system.register_composite_rect(CompositeRect {
             relative_size_adjustment: [1.0, 1.0],
             has_bitmap: true,
-            composite_mode: CompositeMode::FillColor(AnimatableColor::Value([1.0, 1.0, 1.0, 0.0])),
+            composite_mode: CompositeMode::FillColor(
+                init.base_system.theme.color(ThemeColor::Foreground),
+            ),
+            opacity: AnimatableFloat::Value(0.0),
             ..Default::default()
         });
         let ct_icon = init.base_system.register_composite_rect(CompositeRect {
@@ -605,7 +613,9 @@ impl MenuButtonView {
             relative_offset_adjustment: [0.5, 0.5],
             has_bitmap: true,
             texatlas_rect: icon_atlas_rect,
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value([0.9, 0.9, 0.9, 1.0])),
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::Text),
+            ),
             ..Default::default()
         });
 
@@ -675,24 +685,21 @@ impl MenuButtonView {
             _ => 0.0,
         };
 
-        let current = match composite_tree.get(self.ct_bg).composite_mode {
-            CompositeMode::FillColor(ref x) => {
-                x.evaluate(current_sec, composite_tree.parameter_store())
-            }
-            _ => unreachable!(),
+        let current = composite_tree
+            .get(self.ct_bg)
+            .opacity
+            .evaluate(current_sec, composite_tree.parameter_store());
+        composite_tree.get_mut(self.ct_bg).opacity = AnimatableFloat::Animated {
+            from_value: current,
+            to_value: opacity,
+            start_sec: current_sec,
+            end_sec: current_sec + 0.1,
+            curve: AnimationCurve::CubicBezier {
+                p1: (0.5, 0.0),
+                p2: (0.5, 1.0),
+            },
+            event_on_complete: None,
         };
-        composite_tree.get_mut(self.ct_bg).composite_mode =
-            CompositeMode::FillColor(AnimatableColor::Animated {
-                from_value: current,
-                to_value: [1.0, 1.0, 1.0, opacity],
-                start_sec: current_sec,
-                end_sec: current_sec + 0.1,
-                curve: AnimationCurve::CubicBezier {
-                    p1: (0.5, 0.0),
-                    p2: (0.5, 1.0),
-                },
-                event_on_complete: None,
-            });
         composite_tree.mark_dirty(self.ct_bg);
     }
 
@@ -734,7 +741,7 @@ impl BaseView {
     const TITLE_SPACING: f32 = 16.0;
     const TITLE_LEFT_OFFSET: f32 = 48.0;
     const ACTIVE_FILE_NAME_LEFT_MARGIN: f32 = 16.0;
-    const ACTIVE_FILE_NAME_COLOR: [f32; 4] = [0.75, 0.75, 0.75, 0.75];
+    const ACTIVE_FILE_NAME_ALPHA: f32 = 0.75;
 
     #[tracing::instrument(name = "BaseView::new", skip(ctx))]
     fn new(ctx: &mut ViewInitContext) -> Self {
@@ -826,7 +833,9 @@ impl BaseView {
             base_scale_factor: ctx.ui_scale_factor,
             relative_size_adjustment: [1.0, 0.0],
             size: [AnimatableFloat::Value(0.0), AnimatableFloat::Value(height)],
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value([0.0, 0.0, 0.0, 0.25])),
+            composite_mode: CompositeMode::ColorTint(
+                ctx.base_system.theme.color(ThemeColor::Backdrop),
+            ),
             texatlas_rect: bg_atlas_rect,
             has_bitmap: true,
             ..Default::default()
@@ -842,7 +851,7 @@ impl BaseView {
                 AnimatableFloat::Value(Self::TITLE_SPACING),
             ],
             texatlas_rect: text_atlas_rect,
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value([0.9, 0.9, 0.9, 1.0])),
+            composite_mode: CompositeMode::ColorTint(ctx.base_system.theme.color(ThemeColor::Text)),
             has_bitmap: true,
             ..Default::default()
         });
@@ -858,9 +867,11 @@ impl BaseView {
                 AnimatableFloat::Value(Self::TITLE_SPACING),
             ],
             // これだけ先に設定しておく
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value(
-                Self::ACTIVE_FILE_NAME_COLOR,
-            )),
+            composite_mode: CompositeMode::ColorTint(
+                ctx.base_system
+                    .theme
+                    .color_with_alpha(ThemeColor::TextSecondary, Self::ACTIVE_FILE_NAME_ALPHA),
+            ),
             ..Default::default()
         });
 
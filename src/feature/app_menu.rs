@@ -1,8 +1,13 @@
-use std::{cell::Cell, path::Path, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
 use crate::{
     AppEvent, AppUpdateContext, PresenterInitContext, ViewInitContext,
-    base_system::{AppBaseSystem, FontType, svg::SinglePathSVG},
+    app_state::MAX_RECENT_FILES,
+    base_system::{AppBaseSystem, FontType, svg::SinglePathSVG, theme::ThemeColor},
     composite::{
         AnimatableColor, AnimatableFloat, AnimationCurve, CompositeMode, CompositeRect,
         CompositeTreeFloatParameterRef, CompositeTreeRef, FloatParameter,
@@ -10,7 +15,9 @@ use crate::{
     helper_types::SafeF32,
     hittest::{self, HitTestTreeActionHandler, HitTestTreeData, HitTestTreeRef},
     input::EventContinueControl,
+    text::{TextLayoutOptions, TextTruncation},
     trigger_cell::TriggerCell,
+    uikit::common_controls::LabelledCheckboxView,
 };
 
 #[derive(Debug, Clone, Copy)]
@@ -44,9 +51,6 @@ impl CommandButtonView {
     const HPADDING: f32 = 16.0;
     const ICON_LABEL_GAP: f32 = 4.0;
 
-    const CONTENT_COLOR_SHOWN: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
-    const CONTENT_COLOR_HIDDEN: [f32; 4] = [1.0, 1.0, 1.0, 0.0];
-
     #[tracing::instrument(name = "AppMenuButtonView::new", skip(init), fields(icon_path = %icon_path.as_ref().display()))]
     fn new(
         init: &mut ViewInitContext,
@@ -89,6 +93,7 @@ impl CommandButtonView {
             .composite_tree
             .parameter_store_mut()
             .alloc_float(FloatParameter::Value(0.0));
+        let theme = init.base_system.theme;
         let ct_root = init.base_system.register_composite_rect(CompositeRect {
             base_scale_factor: init.ui_scale_factor,
             offset: [AnimatableFloat::Value(left), AnimatableFloat::Value(top)],
@@ -103,8 +108,9 @@ impl CommandButtonView {
                 move |ps| {
                     let opacity = ps.float_value(ct_bg_alpha_rate_shown) * 0.25
                         + ps.float_value(ct_bg_alpha_rate_pointer) * 0.25;
+                    let [r, g, b, a] = theme.evaluate(ps, ThemeColor::Foreground);
 
-                    [1.0, 1.0, 1.0, opacity]
+                    [r, g, b, a * opacity]
                 },
             ))),
             ..Default::default()
@@ -122,9 +128,8 @@ impl CommandButtonView {
             relative_offset_adjustment: [0.0, 0.5],
             has_bitmap: true,
             texatlas_rect: icon_atlas_rect,
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value(
-                Self::CONTENT_COLOR_HIDDEN,
-            )),
+            composite_mode: CompositeMode::ColorTint(theme.color(ThemeColor::Foreground)),
+            opacity: AnimatableFloat::Value(0.0),
             ..Default::default()
         });
         let ct_label = init.base_system.register_composite_rect(CompositeRect {
@@ -142,9 +147,8 @@ impl CommandButtonView {
             relative_offset_adjustment: [0.0, 0.5],
             has_bitmap: true,
             texatlas_rect: label_atlas_rect,
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value(
-                Self::CONTENT_COLOR_HIDDEN,
-            )),
+            composite_mode: CompositeMode::ColorTint(theme.color(ThemeColor::Foreground)),
+            opacity: AnimatableFloat::Value(0.0),
             ..Default::default()
         });
 
@@ -255,28 +259,24 @@ impl CommandButtonView {
                         event_on_complete: None,
                     },
                 );
-                app_system
-                    .composite_tree
-                    .get_mut(self.ct_icon)
-                    .composite_mode = CompositeMode::ColorTint(AnimatableColor::Animated {
-                    start_sec: current_sec + self.show_delay_sec,
-                    end_sec: current_sec + self.show_delay_sec + 0.25,
-                    from_value: Self::CONTENT_COLOR_HIDDEN,
-                    to_value: Self::CONTENT_COLOR_SHOWN,
-                    curve: AnimationCurve::Linear,
-                    event_on_complete: None,
-                });
-                app_system
-                    .composite_tree
-                    .get_mut(self.ct_label)
-                    .composite_mode = CompositeMode::ColorTint(AnimatableColor::Animated {
-                    start_sec: current_sec + self.show_delay_sec,
-                    end_sec: current_sec + self.show_delay_sec + 0.25,
-                    from_value: Self::CONTENT_COLOR_HIDDEN,
-                    to_value: Self::CONTENT_COLOR_SHOWN,
-                    curve: AnimationCurve::Linear,
-                    event_on_complete: None,
-                });
+                app_system.composite_tree.get_mut(self.ct_icon).opacity =
+                    AnimatableFloat::Animated {
+                        start_sec: current_sec + self.show_delay_sec,
+                        end_sec: current_sec + self.show_delay_sec + 0.25,
+                        from_value: 0.0,
+                        to_value: 1.0,
+                        curve: AnimationCurve::Linear,
+                        event_on_complete: None,
+                    };
+                app_system.composite_tree.get_mut(self.ct_label).opacity =
+                    AnimatableFloat::Animated {
+                        start_sec: current_sec + self.show_delay_sec,
+                        end_sec: current_sec + self.show_delay_sec + 0.25,
+                        from_value: 0.0,
+                        to_value: 1.0,
+                        curve: AnimationCurve::Linear,
+                        event_on_complete: None,
+                    };
                 app_system.composite_tree.get_mut(self.ct_root).offset[0] =
                     AnimatableFloat::Animated {
                         start_sec: current_sec + self.show_delay_sec,
@@ -305,28 +305,24 @@ impl CommandButtonView {
                         event_on_complete: None,
                     },
                 );
-                app_system
-                    .composite_tree
-                    .get_mut(self.ct_icon)
-                    .composite_mode = CompositeMode::ColorTint(AnimatableColor::Animated {
-                    start_sec: current_sec,
-                    end_sec: current_sec + 0.25,
-                    from_value: Self::CONTENT_COLOR_SHOWN,
-                    to_value: Self::CONTENT_COLOR_HIDDEN,
-                    curve: AnimationCurve::Linear,
-                    event_on_complete: None,
-                });
-                app_system
-                    .composite_tree
-                    .get_mut(self.ct_label)
-                    .composite_mode = CompositeMode::ColorTint(AnimatableColor::Animated {
-                    start_sec: current_sec,
-                    end_sec: current_sec + 0.25,
-                    from_value: Self::CONTENT_COLOR_SHOWN,
-                    to_value: Self::CONTENT_COLOR_HIDDEN,
-                    curve: AnimationCurve::Linear,
-                    event_on_complete: None,
-                });
+                app_system.composite_tree.get_mut(self.ct_icon).opacity =
+                    AnimatableFloat::Animated {
+                        start_sec: current_sec,
+                        end_sec: current_sec + 0.25,
+                        from_value: 1.0,
+                        to_value: 0.0,
+                        curve: AnimationCurve::Linear,
+                        event_on_complete: None,
+                    };
+                app_system.composite_tree.get_mut(self.ct_label).opacity =
+                    AnimatableFloat::Animated {
+                        start_sec: current_sec,
+                        end_sec: current_sec + 0.25,
+                        from_value: 1.0,
+                        to_value: 0.0,
+                        curve: AnimationCurve::Linear,
+                        event_on_complete: None,
+                    };
 
                 app_system.composite_tree.mark_dirty(self.ct_icon);
                 app_system.composite_tree.mark_dirty(self.ct_label);
@@ -389,6 +385,422 @@ impl CommandButtonView {
     }
 }
 
+/// 最近開いたファイルの一覧の1項目
+struct RecentFileItemView {
+    ct_root: CompositeTreeRef,
+    ct_label: CompositeTreeRef,
+    ht_root: HitTestTreeRef,
+    top: f32,
+    path: RefCell<Option<PathBuf>>,
+    hovering: Cell<bool>,
+    pressing: Cell<bool>,
+    is_dirty: Cell<bool>,
+}
+impl RecentFileItemView {
+    const HEIGHT: f32 = 28.0;
+    const HPADDING: f32 = 12.0;
+
+    fn new(init: &mut ViewInitContext, left: f32, top: f32) -> Self {
+        let bg_atlas_rect = init
+            .base_system
+            .rounded_fill_rect_mask(
+                unsafe { SafeF32::new_unchecked(init.ui_scale_factor) },
+                unsafe { SafeF32::new_unchecked(Self::HEIGHT / 2.0) },
+            )
+            .unwrap();
+
+        let theme = init.base_system.theme;
+        let ct_root = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            offset: [AnimatableFloat::Value(left), AnimatableFloat::Value(top)],
+            size: [
+                AnimatableFloat::Value(RecentFilesView::WIDTH),
+                AnimatableFloat::Value(Self::HEIGHT),
+            ],
+            has_bitmap: true,
+            texatlas_rect: bg_atlas_rect,
+            slice_borders: [Self::HEIGHT * 0.5 * init.ui_scale_factor; 4],
+            composite_mode: CompositeMode::ColorTint(AnimatableColor::Expression(Box::new(
+                move |ps| {
+                    let [r, g, b, a] = theme.evaluate(ps, ThemeColor::Foreground);
+
+                    [r, g, b, a * 0.25]
+                },
+            ))),
+            opacity: AnimatableFloat::Value(0.0),
+            ..Default::default()
+        });
+        let ct_label = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            offset: [
+                AnimatableFloat::Value(left + Self::HPADDING),
+                AnimatableFloat::Value(top),
+            ],
+            composite_mode: CompositeMode::ColorTint(theme.color(ThemeColor::Foreground)),
+            ..Default::default()
+        });
+
+        let ht_root = init.base_system.create_hit_tree(HitTestTreeData {
+            left,
+            top,
+            width: RecentFilesView::WIDTH,
+            height: Self::HEIGHT,
+            ..Default::default()
+        });
+
+        Self {
+            ct_root,
+            ct_label,
+            ht_root,
+            top,
+            path: RefCell::new(None),
+            hovering: Cell::new(false),
+            pressing: Cell::new(false),
+            is_dirty: Cell::new(false),
+        }
+    }
+
+    fn mount(
+        &self,
+        app_system: &mut AppBaseSystem,
+        ct_parent: CompositeTreeRef,
+        ht_parent: HitTestTreeRef,
+    ) {
+        app_system.set_composite_tree_parent(self.ct_root, ct_parent);
+        app_system.set_composite_tree_parent(self.ct_label, ct_parent);
+        app_system.hit_tree.add_child(ht_parent, self.ht_root);
+    }
+
+    fn rescale(&self, base_system: &mut AppBaseSystem, ui_scale_factor: f32) {
+        base_system
+            .free_mask_atlas_rect(base_system.composite_tree.get(self.ct_root).texatlas_rect);
+
+        let bg_atlas_rect = base_system
+            .rounded_fill_rect_mask(unsafe { SafeF32::new_unchecked(ui_scale_factor) }, unsafe {
+                SafeF32::new_unchecked(Self::HEIGHT / 2.0)
+            })
+            .unwrap();
+        let cr = self
+            .ct_root
+            .entity_mut_dirtified(&mut base_system.composite_tree);
+        cr.texatlas_rect = bg_atlas_rect;
+        cr.slice_borders = [Self::HEIGHT * 0.5 * ui_scale_factor; 4];
+        cr.base_scale_factor = ui_scale_factor;
+        self.ct_label
+            .entity_mut_dirtified(&mut base_system.composite_tree)
+            .base_scale_factor = ui_scale_factor;
+
+        self.rebuild_label(base_system);
+    }
+
+    fn set_path(&self, base_system: &mut AppBaseSystem, path: Option<&Path>) {
+        if self.path.borrow().as_deref() == path {
+            // no changes
+            return;
+        }
+
+        self.path.replace(path.map(Path::to_path_buf));
+        self.rebuild_label(base_system);
+        // Note: 項目がなくなったらハイライトも消しておく
+        self.hovering.set(self.hovering.get() && path.is_some());
+        self.pressing.set(self.pressing.get() && path.is_some());
+        self.is_dirty.set(true);
+    }
+
+    fn rebuild_label(&self, base_system: &mut AppBaseSystem) {
+        if self.ct_label.entity(&base_system.composite_tree).has_bitmap {
+            base_system.free_mask_atlas_rect(
+                self.ct_label
+                    .entity(&base_system.composite_tree)
+                    .texatlas_rect,
+            );
+        }
+
+        let ui_scale_factor = self
+            .ct_label
+            .entity(&base_system.composite_tree)
+            .base_scale_factor;
+        let path = self.path.borrow();
+        let Some(ref path) = *path else {
+            let cr = self
+                .ct_label
+                .entity_mut_dirtified(&mut base_system.composite_tree);
+            cr.has_bitmap = false;
+            cr.size = [AnimatableFloat::Value(0.0), AnimatableFloat::Value(0.0)];
+            return;
+        };
+
+        // Note: ファイル名が読めるように長いパスは途中を省略する
+        let atlas_rect = base_system
+            .text_mask_with_options(
+                FontType::UI,
+                &path.display().to_string(),
+                &TextLayoutOptions::truncated(
+                    unsafe {
+                        SafeF32::new_unchecked(
+                            (RecentFilesView::WIDTH - Self::HPADDING * 2.0) * ui_scale_factor,
+                        )
+                    },
+                    TextTruncation::Middle,
+                ),
+            )
+            .unwrap();
+        let cr = self
+            .ct_label
+            .entity_mut_dirtified(&mut base_system.composite_tree);
+        cr.has_bitmap = true;
+        cr.texatlas_rect = atlas_rect;
+        cr.size = [
+            AnimatableFloat::Value(atlas_rect.width() as f32 / ui_scale_factor),
+            AnimatableFloat::Value(atlas_rect.height() as f32 / ui_scale_factor),
+        ];
+        cr.offset[1] = AnimatableFloat::Value(
+            self.top + (Self::HEIGHT - atlas_rect.height() as f32 / ui_scale_factor) * 0.5,
+        );
+    }
+
+    fn update(&self, app_system: &mut AppBaseSystem, current_sec: f32) {
+        if self.is_dirty.replace(false) {
+            let current = app_system
+                .composite_tree
+                .get(self.ct_root)
+                .opacity
+                .evaluate(current_sec, app_system.composite_tree.parameter_store());
+            let target = match (self.hovering.get(), self.pressing.get()) {
+                (true, true) => 1.0,
+                (false, _) => 0.0,
+                _ => 0.5,
+            };
+
+            self.ct_root
+                .entity_mut_dirtified(&mut app_system.composite_tree)
+                .opacity = AnimatableFloat::Animated {
+                from_value: current,
+                to_value: target,
+                start_sec: current_sec,
+                end_sec: current_sec + 0.1,
+                curve: AnimationCurve::Linear,
+                event_on_complete: None,
+            };
+        }
+    }
+
+    fn is_bound(&self) -> bool {
+        self.path.borrow().is_some()
+    }
+
+    fn on_pointer_enter(&self) {
+        if !self.is_bound() {
+            return;
+        }
+
+        self.hovering.set(true);
+        self.is_dirty.set(true);
+    }
+
+    fn on_pointer_leave(&self) {
+        // はなれた際はpressingもなかったことにする
+        self.hovering.set(false);
+        self.pressing.set(false);
+        self.is_dirty.set(true);
+    }
+
+    fn on_press(&self) {
+        if !self.is_bound() {
+            return;
+        }
+
+        self.pressing.set(true);
+        self.is_dirty.set(true);
+    }
+
+    fn on_release(&self) {
+        self.pressing.set(false);
+        self.is_dirty.set(true);
+    }
+}
+
+/// 最近開いたファイルの一覧と、起動時に最後のファイルを開き直すかの設定
+struct RecentFilesView {
+    ct_root: CompositeTreeRef,
+    ct_heading: CompositeTreeRef,
+    items: Vec<RecentFileItemView>,
+    reopen_checkbox_view: LabelledCheckboxView,
+    left: f32,
+    top: f32,
+    show_delay_sec: f32,
+    shown: TriggerCell<bool>,
+    files: RefCell<Vec<PathBuf>>,
+    files_changed: Cell<bool>,
+}
+impl RecentFilesView {
+    const WIDTH: f32 = 320.0;
+    const HEADING: &'static str = "Recent Files";
+    const HEADING_ITEMS_GAP: f32 = 8.0;
+    const ITEMS_CHECKBOX_GAP: f32 = 12.0;
+
+    #[tracing::instrument(name = "AppMenuRecentFilesView::new", skip(init))]
+    fn new(init: &mut ViewInitContext, left: f32, top: f32, show_delay_sec: f32) -> Self {
+        let heading_atlas_rect = init
+            .base_system
+            .text_mask(FontType::UI, Self::HEADING)
+            .unwrap();
+
+        let theme = init.base_system.theme;
+        let ct_root = init.base_system.register_composite_rect(CompositeRect {
+            relative_size_adjustment: [1.0, 1.0],
+            opacity: AnimatableFloat::Value(0.0),
+            ..Default::default()
+        });
+        let ct_heading = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            offset: [AnimatableFloat::Value(left), AnimatableFloat::Value(top)],
+            size: [
+                AnimatableFloat::Value(heading_atlas_rect.width() as f32 / init.ui_scale_factor),
+                AnimatableFloat::Value(heading_atlas_rect.height() as f32 / init.ui_scale_factor),
+            ],
+            has_bitmap: true,
+            texatlas_rect: heading_atlas_rect,
+            composite_mode: CompositeMode::ColorTint(theme.color(ThemeColor::TextSecondary)),
+            ..Default::default()
+        });
+        init.base_system
+            .set_composite_tree_parent(ct_heading, ct_root);
+
+        let items_top = top
+            + heading_atlas_rect.height() as f32 / init.ui_scale_factor
+            + Self::HEADING_ITEMS_GAP;
+        let items = (0..MAX_RECENT_FILES)
+            .map(|n| {
+                RecentFileItemView::new(
+                    init,
+                    left,
+                    items_top + RecentFileItemView::HEIGHT * n as f32,
+                )
+            })
+            .collect::<Vec<_>>();
+        let reopen_checkbox_view = LabelledCheckboxView::new(init, "Reopen last atlas on startup");
+        reopen_checkbox_view.set_checked(init.base_system.settings.reopen_last_atlas);
+
+        let this = Self {
+            ct_root,
+            ct_heading,
+            items,
+            reopen_checkbox_view,
+            left,
+            top: items_top,
+            show_delay_sec,
+            shown: TriggerCell::new(false),
+            files: RefCell::new(Vec::new()),
+            files_changed: Cell::new(false),
+        };
+        this.place_checkbox(init.base_system, 0);
+
+        this
+    }
+
+    fn mount(
+        &self,
+        app_system: &mut AppBaseSystem,
+        ct_parent: CompositeTreeRef,
+        ht_parent: HitTestTreeRef,
+    ) {
+        app_system.set_composite_tree_parent(self.ct_root, ct_parent);
+        for v in self.items.iter() {
+            v.mount(app_system, self.ct_root, ht_parent);
+        }
+        self.reopen_checkbox_view
+            .mount(app_system, self.ct_root, ht_parent);
+    }
+
+    fn rescale(&self, base_system: &mut AppBaseSystem, ui_scale_factor: f32) {
+        base_system.free_mask_atlas_rect(
+            base_system
+                .composite_tree
+                .get(self.ct_heading)
+                .texatlas_rect,
+        );
+        let heading_atlas_rect = base_system.text_mask(FontType::UI, Self::HEADING).unwrap();
+        let cr = self
+            .ct_heading
+            .entity_mut_dirtified(&mut base_system.composite_tree);
+        cr.texatlas_rect = heading_atlas_rect;
+        cr.base_scale_factor = ui_scale_factor;
+
+        for v in self.items.iter() {
+            v.rescale(base_system, ui_scale_factor);
+        }
+        self.reopen_checkbox_view
+            .rescale(base_system, ui_scale_factor);
+    }
+
+    /// 一覧の下にチェックボックスを詰めて置く
+    fn place_checkbox(&self, base_system: &mut AppBaseSystem, file_count: usize) {
+        self.reopen_checkbox_view.set_position(
+            base_system,
+            self.left + RecentFileItemView::HPADDING,
+            self.top
+                + RecentFileItemView::HEIGHT * file_count.max(1) as f32
+                + Self::ITEMS_CHECKBOX_GAP,
+        );
+    }
+
+    fn update(&self, app_system: &mut AppBaseSystem, current_sec: f32) {
+        if self.files_changed.replace(false) {
+            let files = self.files.borrow();
+            for (n, v) in self.items.iter().enumerate() {
+                v.set_path(app_system, files.get(n).map(PathBuf::as_path));
+            }
+            self.place_checkbox(app_system, files.len());
+        }
+
+        if let Some(shown) = self.shown.get_if_triggered() {
+            self.ct_root
+                .entity_mut_dirtified(&mut app_system.composite_tree)
+                .opacity = if shown {
+                AnimatableFloat::Animated {
+                    start_sec: current_sec + self.show_delay_sec,
+                    end_sec: current_sec + self.show_delay_sec + 0.25,
+                    from_value: 0.0,
+                    to_value: 1.0,
+                    curve: AnimationCurve::Linear,
+                    event_on_complete: None,
+                }
+            } else {
+                AnimatableFloat::Animated {
+                    start_sec: current_sec,
+                    end_sec: current_sec + 0.25,
+                    from_value: 1.0,
+                    to_value: 0.0,
+                    curve: AnimationCurve::Linear,
+                    event_on_complete: None,
+                }
+            };
+        }
+
+        for v in self.items.iter() {
+            v.update(app_system, current_sec);
+        }
+        self.reopen_checkbox_view.update(app_system, current_sec);
+    }
+
+    fn set_files(&self, files: &[PathBuf]) {
+        let mut current = self.files.borrow_mut();
+        if current.as_slice() != files {
+            *current = files.to_vec();
+            self.files_changed.set(true);
+        }
+    }
+
+    fn show(&self) {
+        self.shown.set(true);
+    }
+
+    fn hide(&self) {
+        self.shown.set(false);
+    }
+}
+
 struct BaseView {
     ct_root: CompositeTreeRef,
     ht_root: HitTestTreeRef,
@@ -490,6 +902,7 @@ impl BaseView {
 struct ActionHandler {
     base_view: Rc<BaseView>,
     item_views: Rc<[CommandButtonView]>,
+    recent_files_view: Rc<RecentFilesView>,
     shown: Cell<bool>,
 }
 impl HitTestTreeActionHandler for ActionHandler {
@@ -509,6 +922,12 @@ impl HitTestTreeActionHandler for ActionHandler {
                 return EventContinueControl::STOP_PROPAGATION;
             }
         }
+        for v in self.recent_files_view.items.iter() {
+            if sender == v.ht_root {
+                v.on_pointer_enter();
+                return EventContinueControl::STOP_PROPAGATION;
+            }
+        }
 
         if sender == self.base_view.ht_root {
             return EventContinueControl::STOP_PROPAGATION;
@@ -529,6 +948,12 @@ impl HitTestTreeActionHandler for ActionHandler {
                 return EventContinueControl::STOP_PROPAGATION;
             }
         }
+        for v in self.recent_files_view.items.iter() {
+            if sender == v.ht_root {
+                v.on_pointer_leave();
+                return EventContinueControl::STOP_PROPAGATION;
+            }
+        }
 
         if sender == self.base_view.ht_root {
             return EventContinueControl::STOP_PROPAGATION;
@@ -549,6 +974,12 @@ impl HitTestTreeActionHandler for ActionHandler {
                 return EventContinueControl::STOP_PROPAGATION;
             }
         }
+        for v in self.recent_files_view.items.iter() {
+            if sender == v.ht_root {
+                v.on_press();
+                return EventContinueControl::STOP_PROPAGATION;
+            }
+        }
 
         if sender == self.base_view.ht_root {
             context.event_queue.push(AppEvent::AppMenuToggle);
@@ -585,6 +1016,12 @@ impl HitTestTreeActionHandler for ActionHandler {
                 return EventContinueControl::STOP_PROPAGATION;
             }
         }
+        for v in self.recent_files_view.items.iter() {
+            if sender == v.ht_root {
+                v.on_release();
+                return EventContinueControl::STOP_PROPAGATION;
+            }
+        }
 
         if sender == self.base_view.ht_root {
             return EventContinueControl::STOP_PROPAGATION;
@@ -621,6 +1058,27 @@ impl HitTestTreeActionHandler for ActionHandler {
                 return EventContinueControl::STOP_PROPAGATION;
             }
         }
+        for v in self.recent_files_view.items.iter() {
+            if sender == v.ht_root {
+                if let Some(ref path) = *v.path.borrow() {
+                    context
+                        .event_queue
+                        .push(AppEvent::AppMenuRequestOpenRecent(path.clone()));
+                }
+
+                return EventContinueControl::STOP_PROPAGATION;
+            }
+        }
+        let reopen_checkbox_view = &self.recent_files_view.reopen_checkbox_view;
+        if let Some(x) = reopen_checkbox_view.try_handle_on_click(sender) {
+            context
+                .event_queue
+                .push(AppEvent::AppMenuSetReopenLastAtlas(
+                    reopen_checkbox_view.checked(),
+                ));
+
+            return x;
+        }
 
         if sender == self.base_view.ht_root {
             return EventContinueControl::STOP_PROPAGATION;
@@ -637,6 +1095,18 @@ impl HitTestTreeActionHandler for ActionHandler {
         if self.item_views.iter().any(|x| x.ht_root == sender) {
             return hittest::CursorShape::Pointer;
         }
+        if self
+            .recent_files_view
+            .items
+            .iter()
+            .any(|x| x.ht_root == sender && x.is_bound())
+            || self
+                .recent_files_view
+                .reopen_checkbox_view
+                .is_sender(sender)
+        {
+            return hittest::CursorShape::Pointer;
+        }
 
         hittest::CursorShape::Default
     }
@@ -688,6 +1158,14 @@ impl Presenter {
             ),
         ]);
 
+        // Note: コマンドのボタンの右側に並べる
+        let recent_files_view = Rc::new(RecentFilesView::new(
+            &mut init.for_view,
+            64.0 + 224.0,
+            header_height + 32.0,
+            0.05 * 4.0,
+        ));
+
         for v in item_views.iter() {
             v.mount(
                 init.for_view.base_system,
@@ -695,16 +1173,36 @@ impl Presenter {
                 base_view.ht_root,
             );
         }
+        recent_files_view.mount(
+            init.for_view.base_system,
+            base_view.ct_root,
+            base_view.ht_root,
+        );
 
         let action_handler = Rc::new(ActionHandler {
             base_view: base_view.clone(),
             item_views,
+            recent_files_view,
             shown: Cell::new(false),
         });
 
+        init.app_state.register_recent_files_view_feedback({
+            let recent_files_view = Rc::downgrade(&action_handler.recent_files_view);
+
+            move |files| {
+                let Some(recent_files_view) = recent_files_view.upgrade() else {
+                    // app teardown-ed
+                    return;
+                };
+
+                recent_files_view.set_files(files);
+            }
+        });
+
         init.app_state.register_visible_menu_view_feedback({
             let base_view = Rc::downgrade(&base_view);
             let item_views = Rc::downgrade(&action_handler.item_views);
+            let recent_files_view = Rc::downgrade(&action_handler.recent_files_view);
             let action_handler = Rc::downgrade(&action_handler);
 
             move |visible| {
@@ -720,17 +1218,23 @@ impl Presenter {
                     // app teardown-ed
                     return;
                 };
+                let Some(recent_files_view) = recent_files_view.upgrade() else {
+                    // app teardown-ed
+                    return;
+                };
 
                 if visible {
                     base_view.show();
                     for v in item_views.iter() {
                         v.show();
                     }
+                    recent_files_view.show();
                 } else {
                     base_view.hide();
                     for v in item_views.iter() {
                         v.hide();
                     }
+                    recent_files_view.hide();
                 }
 
                 action_handler.shown.set(visible);
@@ -746,6 +1250,16 @@ impl Presenter {
                 .hit_tree
                 .set_action_handler(v.ht_root, &action_handler);
         }
+        for v in action_handler.recent_files_view.items.iter() {
+            init.for_view
+                .base_system
+                .hit_tree
+                .set_action_handler(v.ht_root, &action_handler);
+        }
+        action_handler
+            .recent_files_view
+            .reopen_checkbox_view
+            .bind_action_handler(init.for_view.base_system, &action_handler);
 
         Self {
             base_view,
@@ -766,6 +1280,9 @@ impl Presenter {
         for v in self.action_handler.item_views.iter() {
             v.rescale(base_system, ui_scale_factor);
         }
+        self.action_handler
+            .recent_files_view
+            .rescale(base_system, ui_scale_factor);
     }
 
     pub fn update(&self, app_system: &mut AppBaseSystem, current_sec: f32) {
@@ -773,5 +1290,8 @@ impl Presenter {
         for v in self.action_handler.item_views.iter() {
             v.update(app_system, current_sec);
         }
+        self.action_handler
+            .recent_files_view
+            .update(app_system, current_sec);
     }
 }
@@ -228,6 +228,25 @@ impl<'subsystem> Presenter<'subsystem> {
 
         rec
     }
+
+    /// スプライトのサムネイルを読み込み済みのソース画像から描く
+    ///
+    /// boundsの中にアスペクト比を保って収める clip_yはレンダーターゲット上での縦方向の描画範囲
+    #[inline]
+    pub fn render_sprite_thumbnail<'x>(
+        &self,
+        sprite_index: usize,
+        bounds: br::Rect2D,
+        clip_y: (f32, f32),
+        rt_size: br::Extent2D,
+        rec: br::CmdRecord<'x>,
+    ) -> br::CmdRecord<'x> {
+        self.action_handler
+            .grid_view
+            .renderer
+            .borrow()
+            .render_thumbnail_commands(sprite_index, bounds, clip_y, rt_size, rec)
+    }
 }
 
 enum DragState {
@@ -580,6 +599,10 @@ struct Renderer<'d> {
     sprite_atlas_rect_by_path: RefCell<HashMap<PathBuf, (u32, u32, u32, u32)>>,
     sprite_instance_render_pipeline_layout: br::PipelineLayoutObject<&'d Subsystem>,
     sprite_instance_render_pipeline: br::PipelineObject<&'d Subsystem>,
+    sprite_thumbnail_render_pipeline_layout: br::PipelineLayoutObject<&'d Subsystem>,
+    sprite_thumbnail_render_pipeline: br::PipelineObject<&'d Subsystem>,
+    /// スプライトごとの読み込み先アトラス上の範囲(x, y, width, height)
+    sprite_source_rects: RefCell<Vec<Option<(u32, u32, u32, u32)>>>,
     sprite_count: Cell<usize>,
     sprite_image_copies: Arc<RwLock<HashMap<usize, Vec<br::vk::VkBufferImageCopy>>>>,
 }
@@ -709,6 +732,7 @@ impl<'d> Renderer<'d> {
         let bg_fsh = app_system.require_shader("resources/atlas_bg.frag");
         let sprite_instance_vsh = app_system.require_shader("resources/sprite_instance.vert");
         let sprite_instance_fsh = app_system.require_shader("resources/sprite_instance.frag");
+        let sprite_thumbnail_vsh = app_system.require_shader("resources/sprite_thumbnail.vert");
 
         let render_pipeline_layout = match br::PipelineLayoutObject::new(
             app_system.subsystem,
@@ -745,6 +769,25 @@ impl<'d> Renderer<'d> {
                 std::process::exit(1);
             }
         };
+        let sprite_thumbnail_render_pipeline_layout = match br::PipelineLayoutObject::new(
+            app_system.subsystem,
+            &br::PipelineLayoutCreateInfo::new(
+                &[
+                    dsl_param.as_transparent_ref(),
+                    dsl_sprite_instance.as_transparent_ref(),
+                ],
+                &[br::PushConstantRange::for_type::<SpriteThumbnailParams>(
+                    br::vk::VK_SHADER_STAGE_VERTEX_BIT,
+                    0,
+                )],
+            ),
+        ) {
+            Ok(x) => x,
+            Err(e) => {
+                tracing::error!(reason = ?e, "Failed to create sprite thumbnail pipeline layout");
+                std::process::exit(1);
+            }
+        };
 
         let main_viewports = [main_buffer_size
             .into_rect(br::Offset2D::ZERO)
@@ -757,6 +800,7 @@ impl<'d> Renderer<'d> {
             render_pipeline,
             bg_render_pipeline,
             sprite_instance_render_pipeline,
+            sprite_thumbnail_render_pipeline,
         ] = app_system
             .create_graphics_pipelines_array(&[
                 br::GraphicsPipelineCreateInfo::new(
@@ -801,6 +845,20 @@ impl<'d> Renderer<'d> {
                     BLEND_STATE_SINGLE_PREMULTIPLIED,
                 )
                 .set_multisample_state(MS_STATE_EMPTY),
+                br::GraphicsPipelineCreateInfo::new(
+                    &sprite_thumbnail_render_pipeline_layout,
+                    rendered_pass,
+                    &[
+                        sprite_thumbnail_vsh.on_stage(br::ShaderStage::Vertex, c"main"),
+                        sprite_instance_fsh.on_stage(br::ShaderStage::Fragment, c"main"),
+                    ],
+                    VI_STATE_EMPTY,
+                    IA_STATE_TRISTRIP,
+                    &main_viewport_state,
+                    RASTER_STATE_DEFAULT_FILL_NOCULL,
+                    BLEND_STATE_SINGLE_PREMULTIPLIED,
+                )
+                .set_multisample_state(MS_STATE_EMPTY),
             ])
             .unwrap();
 
@@ -878,6 +936,9 @@ impl<'d> Renderer<'d> {
             sprite_atlas_rect_by_path: RefCell::new(HashMap::new()),
             sprite_instance_render_pipeline_layout,
             sprite_instance_render_pipeline,
+            sprite_thumbnail_render_pipeline_layout,
+            sprite_thumbnail_render_pipeline,
+            sprite_source_rects: RefCell::new(Vec::new()),
             sprite_count: Cell::new(0),
             sprite_image_copies: Arc::new(RwLock::new(HashMap::new())),
         }
@@ -928,7 +989,10 @@ impl<'d> Renderer<'d> {
         let mut buffers_mref = self.sprite_instance_buffers.borrow_mut();
         let mut rects_mref = self.sprite_atlas_rect_by_path.borrow_mut();
         let mut atlas_mref = self.loaded_sprite_source_atlas.borrow_mut();
+        let mut source_rects_mref = self.sprite_source_rects.borrow_mut();
 
+        source_rects_mref.clear();
+        source_rects_mref.resize(sprites.len(), None);
         buffers_mref.require_capacity(sprites.len() as _);
         if !sprites.is_empty() {
             let h = buffers_mref.stg_memory.native_ptr();
@@ -1017,6 +1081,7 @@ impl<'d> Renderer<'d> {
                         (ox, oy)
                     }
                 };
+                source_rects_mref[n] = Some((ox, oy, x.width, x.height));
 
                 unsafe {
                     let instance_ptr =
@@ -1219,6 +1284,7 @@ impl<'d> Renderer<'d> {
         let bg_fsh = app_system.require_shader("resources/atlas_bg.frag");
         let sprite_instance_vsh = app_system.require_shader("resources/sprite_instance.vert");
         let sprite_instance_fsh = app_system.require_shader("resources/sprite_instance.frag");
+        let sprite_thumbnail_vsh = app_system.require_shader("resources/sprite_thumbnail.vert");
 
         let main_viewport = [main_buffer_size
             .into_rect(br::Offset2D::ZERO)
@@ -1231,6 +1297,7 @@ impl<'d> Renderer<'d> {
             render_pipeline,
             bg_render_pipeline,
             sprite_instance_render_pipeline,
+            sprite_thumbnail_render_pipeline,
         ] = app_system
             .create_graphics_pipelines_array(&[
                 br::GraphicsPipelineCreateInfo::new(
@@ -1275,12 +1342,27 @@ impl<'d> Renderer<'d> {
                     BLEND_STATE_SINGLE_PREMULTIPLIED,
                 )
                 .set_multisample_state(MS_STATE_EMPTY),
+                br::GraphicsPipelineCreateInfo::new(
+                    &self.sprite_thumbnail_render_pipeline_layout,
+                    rendered_pass,
+                    &[
+                        sprite_thumbnail_vsh.on_stage(br::ShaderStage::Vertex, c"main"),
+                        sprite_instance_fsh.on_stage(br::ShaderStage::Fragment, c"main"),
+                    ],
+                    VI_STATE_EMPTY,
+                    IA_STATE_TRISTRIP,
+                    &main_viewport_state,
+                    RASTER_STATE_DEFAULT_FILL_NOCULL,
+                    BLEND_STATE_SINGLE_PREMULTIPLIED,
+                )
+                .set_multisample_state(MS_STATE_EMPTY),
             ])
             .unwrap();
 
         self.render_pipeline = render_pipeline;
         self.bg_render_pipeline = bg_render_pipeline;
         self.sprite_instance_render_pipeline = sprite_instance_render_pipeline;
+        self.sprite_thumbnail_render_pipeline = sprite_thumbnail_render_pipeline;
     }
 
     fn render_commands<'cb>(
@@ -1343,6 +1425,87 @@ impl<'d> Renderer<'d> {
                 .draw(4, inst_count as _, 0, 0)
             })
     }
+
+    fn render_thumbnail_commands<'cb>(
+        &self,
+        sprite_index: usize,
+        bounds: br::Rect2D,
+        clip_y: (f32, f32),
+        sc_size: br::Extent2D,
+        rec: br::CmdRecord<'cb>,
+    ) -> br::CmdRecord<'cb> {
+        let Some(&Some((ox, oy, width, height))) =
+            self.sprite_source_rects.borrow().get(sprite_index)
+        else {
+            // not loaded
+            return rec;
+        };
+        if width == 0 || height == 0 {
+            return rec;
+        }
+
+        // アスペクト比を保ったまま中央に収める
+        let scale = (bounds.extent.width as f32 / width as f32)
+            .min(bounds.extent.height as f32 / height as f32);
+        let (w, h) = (width as f32 * scale, height as f32 * scale);
+        let left = bounds.offset.x as f32 + (bounds.extent.width as f32 - w) * 0.5;
+        let mut top = bounds.offset.y as f32 + (bounds.extent.height as f32 - h) * 0.5;
+        let mut bottom = top + h;
+        let (mut v_top, mut v_bottom) = (oy as f32, (oy + height) as f32);
+        // Note: Custom Renderにはクリップが効かないので、縦方向だけここで削る(uvも同じだけ削る)
+        if top < clip_y.0 {
+            v_top += (clip_y.0 - top) / scale;
+            top = clip_y.0;
+        }
+        if bottom > clip_y.1 {
+            v_bottom -= (bottom - clip_y.1) / scale;
+            bottom = clip_y.1;
+        }
+        if bottom <= top {
+            // fully clipped
+            return rec;
+        }
+
+        let atlas_size = LoadedSpriteSourceAtlas::SIZE as f32;
+        let params = SpriteThumbnailParams {
+            rt_size: [sc_size.width as f32, sc_size.height as f32],
+            _pad: [0.0, 0.0],
+            pos_st: [w, bottom - top, left, top],
+            uv_st: [
+                width as f32 / atlas_size,
+                (v_bottom - v_top) / atlas_size,
+                ox as f32 / atlas_size,
+                v_top / atlas_size,
+            ],
+        };
+
+        rec.bind_pipeline(
+            br::PipelineBindPoint::Graphics,
+            &self.sprite_thumbnail_render_pipeline,
+        )
+        .bind_descriptor_sets(
+            br::PipelineBindPoint::Graphics,
+            &self.sprite_thumbnail_render_pipeline_layout,
+            0,
+            &[self.ds_param, self.ds_sprite_instance],
+            &[],
+        )
+        .push_constant(
+            &self.sprite_thumbnail_render_pipeline_layout,
+            br::vk::VK_SHADER_STAGE_VERTEX_BIT,
+            0,
+            &params,
+        )
+        .draw(4, 1, 0, 0)
+    }
+}
+
+#[repr(C)]
+struct SpriteThumbnailParams {
+    rt_size: [f32; 2],
+    _pad: [f32; 2],
+    pos_st: [f32; 4],
+    uv_st: [f32; 4],
 }
 
 struct LoadedSpriteSourceAtlas<'subsystem> {
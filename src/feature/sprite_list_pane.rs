@@ -5,32 +5,43 @@ use std::{
 };
 
 use crate::{
-    AppUpdateContext, BLEND_STATE_SINGLE_NONE, FillcolorRConstants, IA_STATE_TRILIST,
+    AppEvent, AppUpdateContext, BLEND_STATE_SINGLE_NONE, FillcolorRConstants, IA_STATE_TRILIST,
     MS_STATE_EMPTY, PresenterInitContext, RASTER_STATE_DEFAULT_FILL_NOCULL, VI_STATE_EMPTY,
     VI_STATE_FLOAT2_ONLY, ViewInitContext,
     atlas::AtlasRect,
     base_system::{
         AppBaseSystem, BufferMapMode, FontType, MemoryBoundBuffer, PixelFormat, RenderPassOptions,
         RenderTexture, RenderTextureFlags, RenderTextureOptions, inject_cmd_begin_render_pass2,
-        inject_cmd_end_render_pass2, inject_cmd_pipeline_barrier_2,
+        inject_cmd_end_render_pass2, inject_cmd_pipeline_barrier_2, theme::ThemeColor,
     },
     composite::{
         AnimatableColor, AnimatableFloat, AnimationCurve, ClipConfig, CompositeMode, CompositeRect,
-        CompositeTree, CompositeTreeRef,
+        CompositeTree, CompositeTreeFloatParameterRef, CompositeTreeRef, CustomRenderToken,
+        FloatParameter,
     },
     const_subpass_description_2_single_color_write_only,
     helper_types::SafeF32,
     hittest::{
         CursorShape, HitTestTreeActionHandler, HitTestTreeData, HitTestTreeRef, PointerActionArgs,
+        ScrollActionArgs,
     },
-    input::EventContinueControl,
+    input::{
+        EventContinueControl, FocusTargetToken, KeyActionArgs, TextInputEvent, TextInputTarget,
+    },
+    text::{TextLayoutOptions, TextTruncation},
     trigger_cell::TriggerCell,
+    uikit::{
+        common_controls::{DropdownView, TextInputEditEnd, TextInputValidation, TextInputView},
+        layout::Padding,
+        scroll::ScrollContainerView,
+    },
 };
 
 struct ToggleButtonView {
     icon_atlas_rect: AtlasRect,
     ct_root: CompositeTreeRef,
     ct_icon: CompositeTreeRef,
+    ct_bg_alpha: CompositeTreeFloatParameterRef,
     ht_root: HitTestTreeRef,
     hovering: Cell<bool>,
     pressing: Cell<bool>,
@@ -347,6 +358,12 @@ impl ToggleButtonView {
         );
         Self::render_icon_circle(init.base_system, &icon_atlas_rect, &circle_atlas_rect);
 
+        let ct_bg_alpha = init
+            .base_system
+            .composite_tree
+            .parameter_store_mut()
+            .alloc_float(FloatParameter::Value(0.0));
+        let theme = init.base_system.theme;
         let ct_root = init.base_system.register_composite_rect(CompositeRect {
             base_scale_factor: init.ui_scale_factor,
             size: [
@@ -360,7 +377,13 @@ impl ToggleButtonView {
             relative_offset_adjustment: [1.0, 0.0],
             has_bitmap: true,
             texatlas_rect: circle_atlas_rect,
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value([1.0, 1.0, 1.0, 0.0])),
+            composite_mode: CompositeMode::ColorTint(AnimatableColor::Expression(Box::new(
+                move |ps| {
+                    let [r, g, b, a] = theme.evaluate(ps, ThemeColor::Foreground);
+
+                    [r, g, b, a * ps.float_value(ct_bg_alpha)]
+                },
+            ))),
             ..Default::default()
         });
         let ct_icon = init.base_system.register_composite_rect(CompositeRect {
@@ -376,7 +399,7 @@ impl ToggleButtonView {
             ],
             has_bitmap: true,
             texatlas_rect: icon_atlas_rect.clone(),
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value([0.9, 0.9, 0.9, 1.0])),
+            composite_mode: CompositeMode::ColorTint(theme.color(ThemeColor::Text)),
             ..Default::default()
         });
 
@@ -395,6 +418,7 @@ impl ToggleButtonView {
             icon_atlas_rect,
             ct_root,
             ct_icon,
+            ct_bg_alpha,
             ht_root,
             hovering: Cell::new(false),
             pressing: Cell::new(false),
@@ -502,26 +526,24 @@ impl ToggleButtonView {
             _ => 0.0,
         };
 
-        let current = match app_system.composite_tree.get(self.ct_root).composite_mode {
-            CompositeMode::ColorTint(ref x) => {
-                x.evaluate(current_sec, app_system.composite_tree.parameter_store())
-            }
-            _ => unreachable!(),
-        };
-        app_system
+        let current = app_system
             .composite_tree
-            .get_mut(self.ct_root)
-            .composite_mode = CompositeMode::ColorTint(AnimatableColor::Animated {
-            from_value: current,
-            to_value: [1.0, 1.0, 1.0, opacity],
-            start_sec: current_sec,
-            end_sec: current_sec + 0.1,
-            curve: AnimationCurve::CubicBezier {
-                p1: (0.5, 0.0),
-                p2: (0.5, 1.0),
+            .parameter_store()
+            .evaluate_float(self.ct_bg_alpha, current_sec);
+        app_system.composite_tree.parameter_store_mut().set_float(
+            self.ct_bg_alpha,
+            FloatParameter::Animated {
+                from_value: current,
+                to_value: opacity,
+                start_sec: current_sec,
+                end_sec: current_sec + 0.1,
+                curve: AnimationCurve::CubicBezier {
+                    p1: (0.5, 0.0),
+                    p2: (0.5, 1.0),
+                },
+                event_on_complete: None,
             },
-            event_on_complete: None,
-        });
+        );
         app_system.composite_tree.mark_dirty(self.ct_root);
     }
 
@@ -562,11 +584,18 @@ struct CellView {
     ct_bg_selected: CompositeTreeRef,
     ct_label_clip: CompositeTreeRef,
     ct_label: CompositeTreeRef,
+    ct_thumbnail: CompositeTreeRef,
+    thumbnail_render_token: CustomRenderToken,
     ht_root: HitTestTreeRef,
     label: RefCell<String>,
+    label_max_width: Cell<f32>,
     top: Cell<f32>,
     hovering: TriggerCell<bool>,
-    bound_sprite_index: Cell<usize>,
+    /// 表示しているスプライト(Noneなら使われておらずツリーから外れている)
+    bound_sprite_index: Cell<Option<usize>>,
+    /// 表示している行(絞り込み/並べ替えたあとの位置)
+    bound_row: Cell<usize>,
+    source_missing: Cell<bool>,
 }
 impl CellView {
     const CORNER_RADIUS: SafeF32 = unsafe { SafeF32::new_unchecked(8.0) };
@@ -574,17 +603,38 @@ impl CellView {
     const HEIGHT: f32 = 24.0;
     const LABEL_MARGIN_H: f32 = 8.0;
     const LABEL_OVERFLOW_SOFTCLIP: f32 = 16.0;
+    const THUMBNAIL_SIZE: f32 = 16.0;
+    const THUMBNAIL_GAP: f32 = 6.0;
+    const LABEL_LEFT: f32 = Self::LABEL_MARGIN_H + Self::THUMBNAIL_SIZE + Self::THUMBNAIL_GAP;
+
+    /// ペイン幅からラベルに使える幅を出す
+    const fn label_max_width(pane_width: f32) -> f32 {
+        pane_width - Self::MARGIN_H * 2.0 - Self::LABEL_LEFT - Self::LABEL_MARGIN_H
+    }
+
+    fn label_layout_options(max_width: f32, ui_scale_factor: f32) -> TextLayoutOptions {
+        TextLayoutOptions::truncated(
+            unsafe { SafeF32::new_unchecked((max_width * ui_scale_factor).max(1.0)) },
+            TextTruncation::End,
+        )
+    }
 
     #[tracing::instrument(name = "SpriteListCellView::new", skip(init))]
     fn new(
         init: &mut ViewInitContext,
         init_label: &str,
+        init_label_max_width: f32,
         init_top: f32,
+        init_row: usize,
         init_sprite_index: usize,
     ) -> Self {
         let label_atlas_rect = init
             .base_system
-            .text_mask(FontType::UI, init_label)
+            .text_mask_with_options(
+                FontType::UI,
+                init_label,
+                &Self::label_layout_options(init_label_max_width, init.ui_scale_factor),
+            )
             .unwrap();
         let bg_atlas_rect = init
             .base_system
@@ -610,14 +660,14 @@ impl CellView {
         let ct_label_clip = init.base_system.register_composite_rect(CompositeRect {
             base_scale_factor: init.ui_scale_factor,
             offset: [
-                AnimatableFloat::Value(Self::LABEL_MARGIN_H),
+                AnimatableFloat::Value(Self::LABEL_LEFT),
                 AnimatableFloat::Value(
                     -(label_atlas_rect.height() as f32 / init.ui_scale_factor) * 0.5,
                 ),
             ],
             relative_offset_adjustment: [0.0, 0.5],
             size: [
-                AnimatableFloat::Value(-Self::LABEL_MARGIN_H * 2.0),
+                AnimatableFloat::Value(-(Self::LABEL_LEFT + Self::LABEL_MARGIN_H)),
                 AnimatableFloat::Value(label_atlas_rect.height() as f32 / init.ui_scale_factor),
             ],
             relative_size_adjustment: [1.0, 0.0],
@@ -639,16 +689,38 @@ impl CellView {
                 AnimatableFloat::Value(0.0),
             ],
             has_bitmap: true,
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value([0.9, 0.9, 0.9, 1.0])),
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::Text),
+            ),
             texatlas_rect: label_atlas_rect,
             ..Default::default()
         });
+        let thumbnail_render_token = init
+            .base_system
+            .composite_tree
+            .acquire_custom_render_token();
+        let ct_thumbnail = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            offset: [
+                AnimatableFloat::Value(Self::LABEL_MARGIN_H),
+                AnimatableFloat::Value(-Self::THUMBNAIL_SIZE * 0.5),
+            ],
+            relative_offset_adjustment: [0.0, 0.5],
+            size: [
+                AnimatableFloat::Value(Self::THUMBNAIL_SIZE),
+                AnimatableFloat::Value(Self::THUMBNAIL_SIZE),
+            ],
+            custom_render_token: Some(thumbnail_render_token),
+            ..Default::default()
+        });
         let ct_bg = init.base_system.register_composite_rect(CompositeRect {
             relative_size_adjustment: [1.0, 1.0],
             has_bitmap: true,
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value([
-                1.0, 1.0, 1.0, 0.125,
-            ])),
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system
+                    .theme
+                    .color_with_alpha(ThemeColor::Foreground, 0.125),
+            ),
             texatlas_rect: bg_atlas_rect,
             slice_borders: [Self::CORNER_RADIUS.value() * init.ui_scale_factor; 4],
             opacity: AnimatableFloat::Value(0.0),
@@ -657,7 +729,11 @@ impl CellView {
         let ct_bg_selected = init.base_system.register_composite_rect(CompositeRect {
             relative_size_adjustment: [1.0, 1.0],
             has_bitmap: true,
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value([0.6, 0.8, 1.0, 0.25])),
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system
+                    .theme
+                    .color_with_alpha(ThemeColor::Accent, 0.25),
+            ),
             texatlas_rect: bg_atlas_rect,
             slice_borders: [Self::CORNER_RADIUS.value() * init.ui_scale_factor; 4],
             opacity: AnimatableFloat::Value(0.0),
@@ -671,6 +747,8 @@ impl CellView {
             .set_composite_tree_parent(ct_label, ct_label_clip);
         init.base_system
             .set_composite_tree_parent(ct_label_clip, ct_root);
+        init.base_system
+            .set_composite_tree_parent(ct_thumbnail, ct_root);
 
         let ht_root = init.base_system.create_hit_tree(HitTestTreeData {
             left: Self::MARGIN_H,
@@ -687,11 +765,16 @@ impl CellView {
             ct_label,
             ct_bg,
             ct_bg_selected,
+            ct_thumbnail,
+            thumbnail_render_token,
             ht_root,
             label: RefCell::new(init_label.into()),
+            label_max_width: Cell::new(init_label_max_width),
             top: Cell::new(init_top),
             hovering: TriggerCell::new(false),
-            bound_sprite_index: Cell::new(init_sprite_index),
+            bound_sprite_index: Cell::new(Some(init_sprite_index)),
+            bound_row: Cell::new(init_row),
+            source_missing: Cell::new(false),
         }
     }
 
@@ -714,21 +797,27 @@ impl CellView {
         );
 
         let label_atlas_rect = base_system
-            .text_mask(FontType::UI, &self.label.borrow())
+            .text_mask_with_options(
+                FontType::UI,
+                &self.label.borrow(),
+                &Self::label_layout_options(self.label_max_width.get(), ui_scale_factor.value()),
+            )
             .unwrap();
         let bg_atlas_rect = base_system
             .rounded_fill_rect_mask(ui_scale_factor, Self::CORNER_RADIUS)
             .unwrap();
 
-        let cr = self
-            .ct_root
-            .entity_mut_dirtified(&mut base_system.composite_tree);
-        cr.base_scale_factor = ui_scale_factor.value();
+        for ct in [self.ct_root, self.ct_thumbnail] {
+            ct.entity_mut_dirtified(&mut base_system.composite_tree)
+                .base_scale_factor = ui_scale_factor.value();
+        }
         let cr = self
             .ct_label
             .entity_mut_dirtified(&mut base_system.composite_tree);
         cr.texatlas_rect = label_atlas_rect;
         cr.base_scale_factor = ui_scale_factor.value();
+        cr.size[0] =
+            AnimatableFloat::Value(label_atlas_rect.width() as f32 / ui_scale_factor.value());
         let cr = self
             .ct_label_clip
             .entity_mut_dirtified(&mut base_system.composite_tree);
@@ -812,13 +901,37 @@ impl CellView {
             return;
         }
 
+        self.label.replace(label.into());
+        self.relayout_label(base_system);
+    }
+
+    fn set_label_max_width(&self, max_width: f32, base_system: &mut AppBaseSystem) {
+        if self.label_max_width.replace(max_width) == max_width {
+            // no changes
+            return;
+        }
+
+        self.relayout_label(base_system);
+    }
+
+    fn relayout_label(&self, base_system: &mut AppBaseSystem) {
         base_system.free_mask_atlas_rect(
             self.ct_label
                 .entity(&base_system.composite_tree)
                 .texatlas_rect,
         );
 
-        let label_atlas_rect = base_system.text_mask(FontType::UI, label).unwrap();
+        let ui_scale_factor = self
+            .ct_label
+            .entity(&base_system.composite_tree)
+            .base_scale_factor;
+        let label_atlas_rect = base_system
+            .text_mask_with_options(
+                FontType::UI,
+                &self.label.borrow(),
+                &Self::label_layout_options(self.label_max_width.get(), ui_scale_factor),
+            )
+            .unwrap();
 
         let cr = self
             .ct_label
@@ -831,12 +944,42 @@ impl CellView {
             .entity_mut_dirtified(&mut base_system.composite_tree);
         cr.size[1] =
             AnimatableFloat::Value(label_atlas_rect.height() as f32 / cr.base_scale_factor);
+    }
 
-        self.label.replace(label.into());
+    /// ソースが見つからないスプライトはラベルの色を変えて知らせる
+    fn set_source_missing(&self, missing: bool, base_system: &mut AppBaseSystem) {
+        if self.source_missing.replace(missing) == missing {
+            // no changes
+            return;
+        }
+
+        let color = base_system.theme.color(if missing {
+            ThemeColor::TextWarning
+        } else {
+            ThemeColor::Text
+        });
+        self.ct_label
+            .entity_mut_dirtified(&mut base_system.composite_tree)
+            .composite_mode = CompositeMode::ColorTint(color);
     }
 
-    fn bind_sprite_index(&self, index: usize) {
-        self.bound_sprite_index.set(index);
+    fn bind(&self, row: usize, sprite_index: usize) {
+        self.bound_row.set(row);
+        self.bound_sprite_index.set(Some(sprite_index));
+    }
+
+    /// 見えなくなったので使い回せるようにする
+    fn unbind(&self, base_system: &mut AppBaseSystem) {
+        self.bound_sprite_index.set(None);
+        self.on_leave();
+        self.unmount(base_system);
+    }
+
+    /// 名前の編集中はラベルを隠す
+    fn set_label_visible(&self, visible: bool, ct: &mut CompositeTree) {
+        ct.get_mut(self.ct_label_clip).opacity =
+            AnimatableFloat::Value(if visible { 1.0 } else { 0.0 });
+        ct.mark_dirty(self.ct_label_clip);
     }
 }
 
@@ -1194,7 +1337,9 @@ impl FrameView {
             texatlas_rect: frame_image_atlas_rect,
             slice_borders: [Self::CORNER_RADIUS.value() * init.ui_scale_factor; 4],
             composite_mode: CompositeMode::ColorTintBackdropBlur(
-                AnimatableColor::Value([1.0, 1.0, 1.0, 0.03125]),
+                init.base_system
+                    .theme
+                    .color_with_alpha(ThemeColor::Foreground, 0.03125),
                 AnimatableFloat::Value(15.0),
             ),
             ..Default::default()
@@ -1218,7 +1363,9 @@ impl FrameView {
                 ),
             ],
             texatlas_rect: title_blurred_atlas_rect,
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value([0.7, 0.7, 0.7, 1.0])),
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::TextSecondary),
+            ),
             ..Default::default()
         });
         let ct_title = init.base_system.register_composite_rect(CompositeRect {
@@ -1236,7 +1383,9 @@ impl FrameView {
                 AnimatableFloat::Value(title_atlas_rect.height() as f32 / init.ui_scale_factor),
             ],
             texatlas_rect: title_atlas_rect,
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value([0.9, 0.9, 0.9, 1.0])),
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::Text),
+            ),
             ..Default::default()
         });
 
@@ -1398,23 +1547,488 @@ impl FrameView {
     }
 }
 
+/// 名前/パスで絞り込むための入力欄
+struct FilterBarView {
+    ct_root: CompositeTreeRef,
+    ct_placeholder: CompositeTreeRef,
+    ht_root: HitTestTreeRef,
+    input_view: TextInputView,
+    placeholder_shown: TriggerCell<bool>,
+}
+impl FilterBarView {
+    const CORNER_RADIUS: SafeF32 = unsafe { SafeF32::new_unchecked(4.0) };
+    const HEIGHT: f32 = 20.0;
+    const PADDING_H: f32 = 4.0;
+    const PLACEHOLDER: &'static str = "Filter (name, path or glob)";
+
+    #[tracing::instrument(name = "SpriteListFilterBarView::new", skip(init))]
+    fn new(init: &mut ViewInitContext, width: f32) -> Self {
+        let bg_atlas_rect = init
+            .base_system
+            .rounded_fill_rect_mask(
+                unsafe { SafeF32::new_unchecked(init.ui_scale_factor.ceil()) },
+                Self::CORNER_RADIUS,
+            )
+            .unwrap();
+        let placeholder_atlas_rect = init
+            .base_system
+            .text_mask(FontType::UI, Self::PLACEHOLDER)
+            .unwrap();
+
+        let ct_root = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(width),
+                AnimatableFloat::Value(Self::HEIGHT),
+            ],
+            has_bitmap: true,
+            texatlas_rect: bg_atlas_rect,
+            slice_borders: [Self::CORNER_RADIUS.value() * init.ui_scale_factor.ceil(); 4],
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::Backdrop),
+            ),
+            ..Default::default()
+        });
+        let ct_placeholder = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            offset: [
+                AnimatableFloat::Value(Self::PADDING_H + 2.0),
+                AnimatableFloat::Value(
+                    -0.5 * placeholder_atlas_rect.height() as f32 / init.ui_scale_factor,
+                ),
+            ],
+            relative_offset_adjustment: [0.0, 0.5],
+            size: [
+                AnimatableFloat::Value(
+                    placeholder_atlas_rect.width() as f32 / init.ui_scale_factor,
+                ),
+                AnimatableFloat::Value(
+                    placeholder_atlas_rect.height() as f32 / init.ui_scale_factor,
+                ),
+            ],
+            has_bitmap: true,
+            texatlas_rect: placeholder_atlas_rect,
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system
+                    .theme
+                    .color_with_alpha(ThemeColor::Text, 0.375),
+            ),
+            ..Default::default()
+        });
+        let ht_root = init.base_system.create_hit_tree(HitTestTreeData {
+            width,
+            height: Self::HEIGHT,
+            ..Default::default()
+        });
+        let input_view = TextInputView::new(
+            init,
+            "",
+            width - Self::PADDING_H * 2.0,
+            Self::HEIGHT,
+            TextInputValidation::Any,
+        );
+
+        init.base_system
+            .set_composite_tree_parent(ct_placeholder, ct_root);
+        input_view.mount(init.base_system, ct_root, ht_root);
+        input_view.set_position(init.base_system, Self::PADDING_H, 0.0);
+
+        Self {
+            ct_root,
+            ct_placeholder,
+            ht_root,
+            input_view,
+            placeholder_shown: TriggerCell::new(true),
+        }
+    }
+
+    fn mount(
+        &self,
+        base_system: &mut AppBaseSystem,
+        ct_parent: CompositeTreeRef,
+        ht_parent: HitTestTreeRef,
+    ) {
+        base_system.set_tree_parent((self.ct_root, self.ht_root), (ct_parent, ht_parent));
+    }
+
+    fn set_rect(&self, base_system: &mut AppBaseSystem, left: f32, top: f32, width: f32) {
+        let cr = self
+            .ct_root
+            .entity_mut_dirtified(&mut base_system.composite_tree);
+        cr.offset = [AnimatableFloat::Value(left), AnimatableFloat::Value(top)];
+        cr.size[0] = AnimatableFloat::Value(width);
+        let ht = base_system.hit_tree.get_data_mut(self.ht_root);
+        ht.left = left;
+        ht.top = top;
+        ht.width = width;
+        self.input_view.set_size(
+            base_system,
+            (width - Self::PADDING_H * 2.0).max(0.0),
+            Self::HEIGHT,
+        );
+    }
+
+    fn rescale(&self, base_system: &mut AppBaseSystem, ui_scale_factor: f32) {
+        base_system.free_mask_atlas_rect(
+            self.ct_root
+                .entity(&base_system.composite_tree)
+                .texatlas_rect,
+        );
+        base_system.free_mask_atlas_rect(
+            self.ct_placeholder
+                .entity(&base_system.composite_tree)
+                .texatlas_rect,
+        );
+
+        let bg_atlas_rect = base_system
+            .rounded_fill_rect_mask(
+                unsafe { SafeF32::new_unchecked(ui_scale_factor.ceil()) },
+                Self::CORNER_RADIUS,
+            )
+            .unwrap();
+        let placeholder_atlas_rect = base_system
+            .text_mask(FontType::UI, Self::PLACEHOLDER)
+            .unwrap();
+
+        let cr = self
+            .ct_root
+            .entity_mut_dirtified(&mut base_system.composite_tree);
+        cr.base_scale_factor = ui_scale_factor;
+        cr.texatlas_rect = bg_atlas_rect;
+        cr.slice_borders = [Self::CORNER_RADIUS.value() * ui_scale_factor.ceil(); 4];
+        let cr = self
+            .ct_placeholder
+            .entity_mut_dirtified(&mut base_system.composite_tree);
+        cr.base_scale_factor = ui_scale_factor;
+        cr.texatlas_rect = placeholder_atlas_rect;
+        cr.size = [
+            AnimatableFloat::Value(placeholder_atlas_rect.width() as f32 / ui_scale_factor),
+            AnimatableFloat::Value(placeholder_atlas_rect.height() as f32 / ui_scale_factor),
+        ];
+        cr.offset[1] =
+            AnimatableFloat::Value(-0.5 * placeholder_atlas_rect.height() as f32 / ui_scale_factor);
+
+        self.input_view.rescale(base_system, ui_scale_factor);
+    }
+
+    fn update(&self, base_system: &mut AppBaseSystem, current_sec: f32) {
+        self.placeholder_shown
+            .set(self.input_view.text().is_empty());
+        if let Some(shown) = self.placeholder_shown.get_if_triggered() {
+            self.ct_placeholder
+                .entity_mut_dirtified(&mut base_system.composite_tree)
+                .opacity = AnimatableFloat::Value(if shown { 1.0 } else { 0.0 });
+        }
+
+        self.input_view.update(base_system, current_sec);
+    }
+}
+
+/// 一覧の並べ順
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortMode {
+    /// 追加した順(AppStateでの並び順)
+    Insertion,
+    Name,
+    /// 長辺の大きい順
+    Size,
+    /// 面積の大きい順
+    Area,
+}
+impl SortMode {
+    const ALL: [Self; 4] = [Self::Insertion, Self::Name, Self::Size, Self::Area];
+
+    const fn label(self) -> &'static str {
+        match self {
+            Self::Insertion => "Added",
+            Self::Name => "Name",
+            Self::Size => "Size",
+            Self::Area => "Area",
+        }
+    }
+
+    fn compare(self, a: &SpriteListEntry, b: &SpriteListEntry) -> std::cmp::Ordering {
+        match self {
+            Self::Insertion => std::cmp::Ordering::Equal,
+            Self::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            Self::Size => (b.width.max(b.height), b.width, b.height).cmp(&(
+                a.width.max(a.height),
+                a.width,
+                a.height,
+            )),
+            Self::Area => {
+                (b.width as u64 * b.height as u64).cmp(&(a.width as u64 * a.height as u64))
+            }
+        }
+    }
+}
+
+/// 一覧に出すスプライトの情報
+struct SpriteListEntry {
+    name: String,
+    path: String,
+    width: u32,
+    height: u32,
+    selected: bool,
+    source_missing: bool,
+}
+impl SpriteListEntry {
+    /// 絞り込み文字列(前後の空白を除いて小文字にしたもの)に合うか
+    ///
+    /// `*`か`?`を含んでいればglobとして、そうでなければ部分文字列として名前とパスに対して調べる
+    fn matches(&self, filter: &str) -> bool {
+        if filter.is_empty() {
+            return true;
+        }
+
+        let (name, path) = (self.name.to_lowercase(), self.path.to_lowercase());
+        if filter.contains(['*', '?']) {
+            glob_match(filter, &name) || glob_match(filter, &path)
+        } else {
+            name.contains(filter) || path.contains(filter)
+        }
+    }
+}
+
+/// `*`(0文字以上)と`?`(1文字)だけのglob
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (
+        pattern.chars().collect::<Vec<_>>(),
+        text.chars().collect::<Vec<_>>(),
+    );
+    let (mut p, mut t) = (0, 0);
+    // 直前の`*`の位置と、そこから試しているtext側の位置
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(&'*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => {
+                let Some((bp, bt)) = backtrack else {
+                    return false;
+                };
+
+                // `*`に1文字多く食わせてやり直す
+                backtrack = Some((bp, bt + 1));
+                p = bp + 1;
+                t = bt + 1;
+            }
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// セルのドラッグ(並べ替え)の状態
+#[derive(Clone, Copy)]
+struct CellDrag {
+    ht_cell: HitTestTreeRef,
+    sprite_index: usize,
+    start_client_y: f32,
+    /// 動かし始めたか(それまではクリックとして扱う)
+    dragging: bool,
+}
+
 struct ActionHandler {
     view: Rc<FrameView>,
     toggle_button_view: Rc<ToggleButtonView>,
     cell_views: RefCell<Vec<CellView>>,
+    scroll_view: ScrollContainerView,
+    filter_bar_view: FilterBarView,
+    sort_dropdown_view: DropdownView,
     ht_resize_area: HitTestTreeRef,
     resize_state: Cell<Option<(f32, f32)>>,
     shown: Cell<bool>,
+    rename_input_view: TextInputView,
+    /// 名前を編集中のセル
+    renaming_cell: Cell<Option<usize>>,
+    rename_request: Cell<Option<usize>>,
+    rename_finished: Cell<bool>,
+    /// 直前にクリックされたスプライトとその時刻(ダブルクリック判定用)
+    /// (スプライトのインデックス, 時刻)
+    last_cell_click: Cell<Option<(usize, f32)>>,
+    /// 表示する行ごとのスプライトのindex(絞り込み/並べ替え済み)
+    rows: RefCell<Vec<usize>>,
+    cell_drag: Cell<Option<CellDrag>>,
+    /// ドラッグ中のセルを落とす位置(この行の直前に入る)
+    drop_row: Cell<Option<usize>>,
+}
+impl ActionHandler {
+    const DOUBLE_CLICK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(400);
+    /// クリック判定(4px)より少し大きく取って、クリックとの取り合いを避ける
+    const DRAG_START_DISTANCE: f32 = 6.0;
+
+    /// クライアント座標から落とす位置の行を出す
+    fn drop_row_at(&self, client_y: f32) -> usize {
+        let (viewport_top, _) = self.scroll_view.viewport_client_range_y();
+        let content_y = client_y - viewport_top + self.scroll_view.scroll_offset();
+
+        ((content_y / CellView::HEIGHT).round().max(0.0) as usize).min(self.rows.borrow().len())
+    }
+
+    /// drop_rowの直前に入れたときの、取り除いたあとの並びでの移動先
+    ///
+    /// 絞り込み中は見えている前後のスプライトを基準にする
+    fn move_target(&self, from: usize, drop_row: usize) -> Option<usize> {
+        let rows = self.rows.borrow();
+
+        if let Some(&before) = rows.get(drop_row) {
+            Some(if from < before { before - 1 } else { before })
+        } else {
+            let &last = rows.last()?;
+
+            Some(if from < last { last } else { last + 1 })
+        }
+    }
+
+    fn apply_rename_edit_end(&self, context: &mut AppUpdateContext) {
+        let Some(end) = self.rename_input_view.take_edit_end() else {
+            return;
+        };
+        let Some(cell_index) = self.renaming_cell.get() else {
+            return;
+        };
+
+        if let TextInputEditEnd::Commit(name) = end
+            && !name.trim().is_empty()
+            && let Some(sprite_index) = self.cell_views.borrow()[cell_index]
+                .bound_sprite_index
+                .get()
+        {
+            context.state.borrow_mut().rename_sprite(sprite_index, name);
+        }
+        self.rename_finished.set(true);
+    }
 }
 impl HitTestTreeActionHandler for ActionHandler {
     fn cursor_shape(&self, sender: HitTestTreeRef, _context: &mut AppUpdateContext) -> CursorShape {
         if sender == self.ht_resize_area && self.shown.get() {
             return CursorShape::ResizeHorizontal;
         }
+        if let Some(s) = self.rename_input_view.try_handle_cursor_shape(sender) {
+            return s;
+        }
+        if let Some(s) = self
+            .filter_bar_view
+            .input_view
+            .try_handle_cursor_shape(sender)
+        {
+            return s;
+        }
+        if let Some(s) = self.sort_dropdown_view.try_handle_cursor_shape(sender) {
+            return s;
+        }
 
         CursorShape::Default
     }
 
+    fn keyboard_focus(&self, sender: HitTestTreeRef) -> Option<FocusTargetToken> {
+        self.rename_input_view
+            .try_handle_keyboard_focus(sender)
+            .or_else(|| {
+                self.filter_bar_view
+                    .input_view
+                    .try_handle_keyboard_focus(sender)
+            })
+    }
+
+    fn text_input_target(&self, sender: HitTestTreeRef) -> Option<TextInputTarget> {
+        self.rename_input_view
+            .try_handle_text_input_target(sender)
+            .or_else(|| {
+                self.filter_bar_view
+                    .input_view
+                    .try_handle_text_input_target(sender)
+            })
+    }
+
+    fn on_focus(&self, sender: HitTestTreeRef, _context: &mut AppUpdateContext) {
+        if !self.rename_input_view.try_handle_focus(sender) {
+            self.filter_bar_view.input_view.try_handle_focus(sender);
+        }
+    }
+
+    fn on_blur(&self, sender: HitTestTreeRef, context: &mut AppUpdateContext) {
+        if self.rename_input_view.try_handle_blur(sender) {
+            self.apply_rename_edit_end(context);
+        }
+        if self.filter_bar_view.input_view.try_handle_blur(sender) {
+            // Note: 絞り込みは入力中から反映しているので、確定/取り消しの結果は使わない
+            self.filter_bar_view.input_view.take_edit_end();
+        }
+    }
+
+    fn on_key_down(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut AppUpdateContext,
+        args: &KeyActionArgs,
+    ) -> EventContinueControl {
+        if let Some(x) = self
+            .rename_input_view
+            .try_handle_key_down(sender, context, args)
+        {
+            self.apply_rename_edit_end(context);
+            return x;
+        }
+        if let Some(x) = self
+            .filter_bar_view
+            .input_view
+            .try_handle_key_down(sender, context, args)
+        {
+            self.filter_bar_view.input_view.take_edit_end();
+            return x;
+        }
+
+        EventContinueControl::empty()
+    }
+
+    fn on_text_input(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut AppUpdateContext,
+        event: &TextInputEvent,
+    ) -> EventContinueControl {
+        if let Some(x) = self.rename_input_view.try_handle_text_input(sender, event) {
+            return x;
+        }
+        if let Some(x) = self
+            .filter_bar_view
+            .input_view
+            .try_handle_text_input(sender, event)
+        {
+            return x;
+        }
+
+        EventContinueControl::empty()
+    }
+
+    fn on_scroll(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut AppUpdateContext,
+        args: &ScrollActionArgs,
+    ) -> EventContinueControl {
+        if !self.shown.get() {
+            return EventContinueControl::empty();
+        }
+        if let Some(x) = self
+            .scroll_view
+            .try_handle_scroll(sender, args, context.current_sec)
+        {
+            return x;
+        }
+
+        EventContinueControl::empty()
+    }
+
     fn on_pointer_enter(
         &self,
         sender: HitTestTreeRef,
@@ -1426,6 +2040,9 @@ impl HitTestTreeActionHandler for ActionHandler {
 
             return EventContinueControl::STOP_PROPAGATION;
         }
+        if let Some(x) = self.sort_dropdown_view.try_handle_pointer_enter(sender) {
+            return x;
+        }
 
         for v in self.cell_views.borrow().iter() {
             if sender == v.ht_root {
@@ -1448,6 +2065,9 @@ impl HitTestTreeActionHandler for ActionHandler {
 
             return EventContinueControl::STOP_PROPAGATION;
         }
+        if let Some(x) = self.sort_dropdown_view.try_handle_pointer_leave(sender) {
+            return x;
+        }
 
         for v in self.cell_views.borrow().iter() {
             if sender == v.ht_root {
@@ -1465,8 +2085,39 @@ impl HitTestTreeActionHandler for ActionHandler {
         _context: &mut AppUpdateContext,
         args: &PointerActionArgs,
     ) -> EventContinueControl {
+        if let Some(x) = self.rename_input_view.try_handle_pointer_down(sender, args) {
+            return x;
+        }
+        if let Some(x) = self
+            .filter_bar_view
+            .input_view
+            .try_handle_pointer_down(sender, args)
+        {
+            return x;
+        }
+        if let Some(x) = self.scroll_view.try_handle_pointer_down(sender, args) {
+            return x;
+        }
+
         if self.shown.get() {
-            if sender == self.view.ht_frame {
+            for v in self.cell_views.borrow().iter() {
+                if sender == v.ht_root
+                    && let Some(sprite_index) = v.bound_sprite_index.get()
+                {
+                    self.cell_drag.set(Some(CellDrag {
+                        ht_cell: sender,
+                        sprite_index,
+                        start_client_y: args.client_y,
+                        dragging: false,
+                    }));
+
+                    // Note: キャプチャしてもクリックは飛んでくる(動かしたときは飛ばない)
+                    return EventContinueControl::CAPTURE_ELEMENT
+                        | EventContinueControl::STOP_PROPAGATION;
+                }
+            }
+
+            if sender == self.view.ht_frame || self.scroll_view.is_viewport(sender) {
                 // guard fallback
                 return EventContinueControl::STOP_PROPAGATION;
             }
@@ -1495,8 +2146,40 @@ impl HitTestTreeActionHandler for ActionHandler {
         _context: &mut AppUpdateContext,
         args: &PointerActionArgs,
     ) -> EventContinueControl {
+        if let Some(x) = self.rename_input_view.try_handle_pointer_move(sender, args) {
+            return x;
+        }
+        if let Some(x) = self
+            .filter_bar_view
+            .input_view
+            .try_handle_pointer_move(sender, args)
+        {
+            return x;
+        }
+        if let Some(x) = self.scroll_view.try_handle_pointer_move(sender, args) {
+            return x;
+        }
+
+        if let Some(mut drag) = self.cell_drag.get()
+            && sender == drag.ht_cell
+        {
+            if !drag.dragging
+                && (args.client_y - drag.start_client_y).abs() >= Self::DRAG_START_DISTANCE
+                // Note: 並べ替えて表示しているときは並び順がわからないので追加順のときだけ
+                && SortMode::ALL[self.sort_dropdown_view.selected()] == SortMode::Insertion
+            {
+                drag.dragging = true;
+                self.cell_drag.set(Some(drag));
+            }
+            if drag.dragging {
+                self.drop_row.set(Some(self.drop_row_at(args.client_y)));
+            }
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
         if self.shown.get() {
-            if sender == self.view.ht_frame {
+            if sender == self.view.ht_frame || self.scroll_view.is_viewport(sender) {
                 // guard fallback
                 return EventContinueControl::STOP_PROPAGATION;
             }
@@ -1517,11 +2200,43 @@ impl HitTestTreeActionHandler for ActionHandler {
     fn on_pointer_up(
         &self,
         sender: HitTestTreeRef,
-        _context: &mut AppUpdateContext,
+        context: &mut AppUpdateContext,
         args: &PointerActionArgs,
     ) -> EventContinueControl {
+        if let Some(x) = self.rename_input_view.try_handle_pointer_up(sender, args) {
+            return x;
+        }
+        if let Some(x) = self
+            .filter_bar_view
+            .input_view
+            .try_handle_pointer_up(sender, args)
+        {
+            return x;
+        }
+        if let Some(x) = self.scroll_view.try_handle_pointer_up(sender, args) {
+            return x;
+        }
+
+        if let Some(drag) = self.cell_drag.get()
+            && sender == drag.ht_cell
+        {
+            self.cell_drag.set(None);
+            self.drop_row.set(None);
+            if drag.dragging
+                && let Some(to) =
+                    self.move_target(drag.sprite_index, self.drop_row_at(args.client_y))
+            {
+                context
+                    .state
+                    .borrow_mut()
+                    .move_sprite(drag.sprite_index, to);
+            }
+
+            return EventContinueControl::RELEASE_CAPTURE_ELEMENT;
+        }
+
         if self.shown.get() {
-            if sender == self.view.ht_frame {
+            if sender == self.view.ht_frame || self.scroll_view.is_viewport(sender) {
                 // guard fallback
                 return EventContinueControl::STOP_PROPAGATION;
             }
@@ -1549,12 +2264,22 @@ impl HitTestTreeActionHandler for ActionHandler {
         &self,
         sender: HitTestTreeRef,
         context: &mut AppUpdateContext,
-        _args: &PointerActionArgs,
+        args: &PointerActionArgs,
     ) -> EventContinueControl {
-        if self.shown.get() && sender == self.view.ht_frame {
+        if let Some(x) = self.sort_dropdown_view.try_handle_on_click(sender, args) {
+            return x;
+        }
+        if self.shown.get()
+            && (sender == self.view.ht_frame || self.scroll_view.is_viewport(sender))
+        {
             // guard fallback
             return EventContinueControl::STOP_PROPAGATION;
         }
+        if self.rename_input_view.is_sender(sender)
+            || self.filter_bar_view.input_view.is_sender(sender)
+        {
+            return EventContinueControl::STOP_PROPAGATION;
+        }
 
         if sender == self.toggle_button_view.ht_root {
             let show = !self.shown.get();
@@ -1567,17 +2292,31 @@ impl HitTestTreeActionHandler for ActionHandler {
                 self.view.hide();
                 self.toggle_button_view.place_outer();
             }
+            context
+                .event_queue
+                .push(AppEvent::SpriteListPaneVisibilityChanged { visible: show });
 
             return EventContinueControl::STOP_PROPAGATION
                 | EventContinueControl::RECOMPUTE_POINTER_ENTER;
         }
 
-        for v in self.cell_views.borrow().iter() {
-            if sender == v.ht_root {
-                context
-                    .state
-                    .borrow_mut()
-                    .select_sprite(v.bound_sprite_index.get());
+        for (n, v) in self.cell_views.borrow().iter().enumerate() {
+            if sender == v.ht_root
+                && let Some(sprite_index) = v.bound_sprite_index.get()
+            {
+                let now = context.current_sec;
+                if let Some((last_index, last_t)) =
+                    self.last_cell_click.replace(Some((sprite_index, now)))
+                    && last_index == sprite_index
+                    && now - last_t <= Self::DOUBLE_CLICK_INTERVAL.as_secs_f32()
+                {
+                    // ダブルクリックで名前の編集を始める
+                    self.last_cell_click.set(None);
+                    self.rename_request.set(Some(n));
+                    return EventContinueControl::STOP_PROPAGATION;
+                }
+
+                context.state.borrow_mut().select_sprite(sprite_index);
                 return EventContinueControl::STOP_PROPAGATION;
             }
         }
@@ -1589,15 +2328,77 @@ impl HitTestTreeActionHandler for ActionHandler {
 pub struct Presenter {
     view: Rc<FrameView>,
     needs_rebuild_list_cells: Rc<Cell<bool>>,
-    sprite_list_contents: Rc<RefCell<Vec<(String, bool)>>>,
+    sprite_list_contents: Rc<RefCell<Vec<SpriteListEntry>>>,
+    ct_drop_indicator: CompositeTreeRef,
+    rendered_drop_row: Cell<Option<usize>>,
+    applied_filter: RefCell<String>,
+    applied_sort_mode: Cell<SortMode>,
+    filter_row_width: Cell<f32>,
     ui_scale_factor: Cell<f32>,
     ht_action_handler: Rc<ActionHandler>,
 }
 impl Presenter {
+    const FILTER_ROW_TOP: f32 = 32.0;
+    const FILTER_ROW_MARGIN_H: f32 = 16.0;
+    const FILTER_ROW_GAP: f32 = 4.0;
+    const SORT_DROPDOWN_WIDTH: f32 = 64.0;
+    const DROP_INDICATOR_THICKNESS: f32 = 2.0;
+
     pub fn new(init: &mut PresenterInitContext, header_height: f32) -> Self {
         let view = Rc::new(FrameView::new(&mut init.for_view, header_height));
         let toggle_button_view = Rc::new(ToggleButtonView::new(&mut init.for_view));
+        let scroll_view = ScrollContainerView::new(
+            &mut init.for_view,
+            Padding {
+                left: 0.0,
+                top: Self::FILTER_ROW_TOP + FilterBarView::HEIGHT + 8.0,
+                right: 0.0,
+                bottom: 8.0,
+            },
+        );
+        let filter_bar_view = FilterBarView::new(
+            &mut init.for_view,
+            view.width.get()
+                - Self::FILTER_ROW_MARGIN_H * 2.0
+                - Self::SORT_DROPDOWN_WIDTH
+                - Self::FILTER_ROW_GAP,
+        );
+        let sort_dropdown_view = DropdownView::new(
+            &mut init.for_view,
+            &SortMode::ALL.map(SortMode::label),
+            Self::SORT_DROPDOWN_WIDTH,
+        );
 
+        scroll_view.mount(init.for_view.base_system, view.ct_root, view.ht_frame);
+        filter_bar_view.mount(init.for_view.base_system, view.ct_root, view.ht_frame);
+        let ct_drop_indicator = init
+            .for_view
+            .base_system
+            .register_composite_rect(CompositeRect {
+                base_scale_factor: init.for_view.ui_scale_factor,
+                offset: [
+                    AnimatableFloat::Value(CellView::MARGIN_H),
+                    AnimatableFloat::Value(0.0),
+                ],
+                relative_size_adjustment: [1.0, 0.0],
+                size: [
+                    AnimatableFloat::Value(-CellView::MARGIN_H * 2.0),
+                    AnimatableFloat::Value(Self::DROP_INDICATOR_THICKNESS),
+                ],
+                has_bitmap: true,
+                composite_mode: CompositeMode::FillColor(
+                    init.for_view.base_system.theme.color(ThemeColor::Accent),
+                ),
+                opacity: AnimatableFloat::Value(0.0),
+                ..Default::default()
+            });
+        init.for_view
+            .base_system
+            .set_composite_tree_parent(ct_drop_indicator, scroll_view.content_parents().0);
+        // Note: リサイズ領域はリストより手前に置きたいので付け直す
+        init.for_view
+            .base_system
+            .set_hit_tree_parent(view.ht_resize_area, view.ht_frame);
         toggle_button_view.mount(init.for_view.base_system, view.ct_root, view.ht_frame);
 
         let needs_rebuild_list_cells = Rc::new(Cell::new(false));
@@ -1619,19 +2420,62 @@ impl Presenter {
                 sprite_list_contents.borrow_mut().clear();
                 sprite_list_contents
                     .borrow_mut()
-                    .extend(sprites.iter().map(|x| (x.name.clone(), x.selected)));
+                    .extend(sprites.iter().map(|x| SpriteListEntry {
+                        name: x.name.clone(),
+                        path: x.source_path.to_string_lossy().into_owned(),
+                        width: x.width,
+                        height: x.height,
+                        selected: x.selected,
+                        source_missing: x.source_missing,
+                    }));
                 needs_rebuild_list_cells.set(true);
             }
         });
 
+        let initially_shown = init.for_view.base_system.settings.sprite_list_visible;
+        if !initially_shown {
+            view.hide();
+            toggle_button_view.place_outer();
+        }
+
         let ht_action_handler = Rc::new(ActionHandler {
             view: view.clone(),
             toggle_button_view: toggle_button_view.clone(),
             cell_views: RefCell::new(Vec::new()),
+            scroll_view,
+            filter_bar_view,
+            sort_dropdown_view,
             ht_resize_area: view.ht_resize_area,
             resize_state: Cell::new(None),
-            shown: Cell::new(true),
+            shown: Cell::new(initially_shown),
+            rename_input_view: TextInputView::new(
+                &mut init.for_view,
+                "",
+                CellView::label_max_width(view.width.get()),
+                CellView::HEIGHT,
+                TextInputValidation::Any,
+            ),
+            renaming_cell: Cell::new(None),
+            rename_request: Cell::new(None),
+            rename_finished: Cell::new(false),
+            last_cell_click: Cell::new(None),
+            rows: RefCell::new(Vec::new()),
+            cell_drag: Cell::new(None),
+            drop_row: Cell::new(None),
         });
+        ht_action_handler
+            .rename_input_view
+            .bind_action_handler(init.for_view.base_system, &ht_action_handler);
+        ht_action_handler
+            .scroll_view
+            .bind_action_handler(init.for_view.base_system, &ht_action_handler);
+        ht_action_handler
+            .filter_bar_view
+            .input_view
+            .bind_action_handler(init.for_view.base_system, &ht_action_handler);
+        ht_action_handler
+            .sort_dropdown_view
+            .bind_action_handler(init.for_view.base_system, &ht_action_handler);
         init.for_view
             .base_system
             .hit_tree
@@ -1649,6 +2493,11 @@ impl Presenter {
             view,
             needs_rebuild_list_cells,
             sprite_list_contents,
+            ct_drop_indicator,
+            rendered_drop_row: Cell::new(None),
+            applied_filter: RefCell::new(String::new()),
+            applied_sort_mode: Cell::new(SortMode::Insertion),
+            filter_row_width: Cell::new(0.0),
             ui_scale_factor: Cell::new(init.for_view.ui_scale_factor),
             ht_action_handler,
         }
@@ -1661,6 +2510,19 @@ impl Presenter {
         ht_parent: HitTestTreeRef,
     ) {
         self.view.mount(app_system, ct_parent, ht_parent);
+        // Note: 親はクライアント全体を覆っているので、ドロップダウンのリストもそこに出す
+        self.ht_action_handler.sort_dropdown_view.mount(
+            app_system,
+            self.view.ct_root,
+            self.view.ht_frame,
+            (ct_parent, ht_parent),
+        );
+    }
+
+    pub fn set_client_size(&self, width: f32, height: f32) {
+        self.ht_action_handler
+            .scroll_view
+            .set_client_size(width, height);
     }
 
     pub fn rescale(&self, base_system: &mut AppBaseSystem, ui_scale_factor: SafeF32) {
@@ -1670,9 +2532,24 @@ impl Presenter {
         self.ht_action_handler
             .toggle_button_view
             .rescale(base_system, ui_scale_factor);
+        self.ht_action_handler
+            .scroll_view
+            .rescale(base_system, ui_scale_factor.value());
+        self.ht_action_handler
+            .filter_bar_view
+            .rescale(base_system, ui_scale_factor.value());
+        self.ht_action_handler
+            .sort_dropdown_view
+            .rescale(base_system, ui_scale_factor.value());
         for v in self.ht_action_handler.cell_views.borrow().iter() {
             v.rescale(base_system, ui_scale_factor);
         }
+        self.ht_action_handler
+            .rename_input_view
+            .rescale(base_system, ui_scale_factor.value());
+        self.ct_drop_indicator
+            .entity_mut_dirtified(&mut base_system.composite_tree)
+            .base_scale_factor = ui_scale_factor.value();
     }
 
     pub fn update<'r, 'base_system, 'subsystem>(
@@ -1685,52 +2562,377 @@ impl Presenter {
             .toggle_button_view
             .update(app_system, current_sec);
 
-        if self.needs_rebuild_list_cells.replace(false) {
-            let sprite_list_contents = self.sprite_list_contents.borrow();
-            let visible_contents = &sprite_list_contents[..];
-            let mut cell_views = self.ht_action_handler.cell_views.borrow_mut();
-            for (n, &(ref c, sel)) in visible_contents.iter().enumerate() {
-                if cell_views.len() == n {
-                    // create new one
-                    let new_cell = CellView::new(
-                        &mut ViewInitContext {
-                            base_system: app_system,
-                            ui_scale_factor: self.ui_scale_factor.get(),
-                        },
-                        c,
-                        32.0 + n as f32 * CellView::HEIGHT,
-                        n,
-                    );
-                    new_cell.mount(
-                        self.ht_action_handler.view.ct_root,
-                        self.ht_action_handler.view.ht_frame,
-                        app_system,
-                    );
-                    app_system
-                        .hit_tree
-                        .set_action_handler(new_cell.ht_root, &self.ht_action_handler);
-                    if sel {
-                        new_cell.on_select(&mut app_system.composite_tree);
+        self.update_filter_row(app_system, current_sec);
+
+        let label_max_width = CellView::label_max_width(self.ht_action_handler.view.width.get());
+        let contents_changed = self.needs_rebuild_list_cells.replace(false);
+        let filter = self
+            .ht_action_handler
+            .filter_bar_view
+            .input_view
+            .text()
+            .trim()
+            .to_lowercase();
+        let filter_changed = *self.applied_filter.borrow() != filter;
+        let sort_mode = SortMode::ALL[self.ht_action_handler.sort_dropdown_view.selected()];
+        let sort_mode_changed = self.applied_sort_mode.replace(sort_mode) != sort_mode;
+        let rows_changed = contents_changed || filter_changed || sort_mode_changed;
+        if rows_changed {
+            self.applied_filter.replace(filter);
+            self.rebuild_rows();
+        }
+
+        self.ht_action_handler.scroll_view.set_content_height(
+            self.ht_action_handler.rows.borrow().len() as f32 * CellView::HEIGHT,
+        );
+        self.ht_action_handler
+            .scroll_view
+            .update(app_system, current_sec);
+        if self.ht_action_handler.scroll_view.take_scrolled() || rows_changed {
+            self.update_visible_cells(app_system, label_max_width, rows_changed);
+        }
+
+        // Note: リサイズ中は毎回作り直すことになるので確定したときだけ反映する
+        let resizing = self.ht_action_handler.resize_state.get().is_some();
+        for v in self.ht_action_handler.cell_views.borrow().iter() {
+            if !resizing {
+                v.set_label_max_width(label_max_width, app_system);
+            }
+            v.update(app_system, current_sec);
+        }
+
+        self.update_drop_indicator(app_system);
+        self.update_rename(app_system, label_max_width, current_sec);
+    }
+
+    fn update_drop_indicator(&self, app_system: &mut AppBaseSystem) {
+        let drop_row = self.ht_action_handler.drop_row.get();
+        if self.rendered_drop_row.replace(drop_row) == drop_row {
+            // no changes
+            return;
+        }
+
+        if drop_row.is_some() {
+            // Note: あとから付けたセルより手前に出したいので付け直す
+            let (ct_content, _) = self.ht_action_handler.scroll_view.content_parents();
+            app_system.set_composite_tree_parent(self.ct_drop_indicator, ct_content);
+        }
+        let cr = self
+            .ct_drop_indicator
+            .entity_mut_dirtified(&mut app_system.composite_tree);
+        match drop_row {
+            Some(row) => {
+                cr.offset[1] = AnimatableFloat::Value(
+                    row as f32 * CellView::HEIGHT - Self::DROP_INDICATOR_THICKNESS * 0.5,
+                );
+                cr.opacity = AnimatableFloat::Value(1.0);
+            }
+            None => {
+                cr.opacity = AnimatableFloat::Value(0.0);
+            }
+        }
+    }
+
+    /// サムネイルを描くCustom Renderなら、描くスプライトと縦方向の描画範囲(ピクセル)を返す
+    pub fn thumbnail_for_render_token(
+        &self,
+        token: CustomRenderToken,
+    ) -> Option<(usize, (f32, f32))> {
+        let h = &self.ht_action_handler;
+        let sprite_index = h
+            .cell_views
+            .borrow()
+            .iter()
+            .find(|c| c.thumbnail_render_token == token)?
+            .bound_sprite_index
+            .get()?;
+        let (top, bottom) = h.scroll_view.viewport_client_range_y();
+        let scale = self.ui_scale_factor.get();
+
+        Some((sprite_index, (top * scale, bottom * scale)))
+    }
+
+    fn update_filter_row(&self, app_system: &mut AppBaseSystem, current_sec: f32) {
+        let h = &self.ht_action_handler;
+
+        let width = h.view.width.get();
+        if self.filter_row_width.replace(width) != width {
+            h.filter_bar_view.set_rect(
+                app_system,
+                Self::FILTER_ROW_MARGIN_H,
+                Self::FILTER_ROW_TOP,
+                (width
+                    - Self::FILTER_ROW_MARGIN_H * 2.0
+                    - Self::SORT_DROPDOWN_WIDTH
+                    - Self::FILTER_ROW_GAP)
+                    .max(0.0),
+            );
+            h.sort_dropdown_view.set_position(
+                app_system,
+                width - Self::FILTER_ROW_MARGIN_H - Self::SORT_DROPDOWN_WIDTH,
+                Self::FILTER_ROW_TOP,
+            );
+        }
+
+        h.filter_bar_view.update(app_system, current_sec);
+        h.sort_dropdown_view.update(app_system, current_sec);
+    }
+
+    fn rebuild_rows(&self) {
+        let sprite_list_contents = self.sprite_list_contents.borrow();
+        let filter = self.applied_filter.borrow();
+        let sort_mode = self.applied_sort_mode.get();
+
+        let mut rows = (0..sprite_list_contents.len())
+            .filter(|&n| sprite_list_contents[n].matches(&filter))
+            .collect::<Vec<_>>();
+        // Note: 安定ソートなので同じ順位のものは追加順のまま
+        rows.sort_by(|&a, &b| {
+            sort_mode.compare(&sprite_list_contents[a], &sprite_list_contents[b])
+        });
+        *self.ht_action_handler.rows.borrow_mut() = rows;
+    }
+
+    /// 見えている範囲の行にだけセルを割り当てる(見えなくなったセルは使い回す)
+    fn update_visible_cells(
+        &self,
+        app_system: &mut AppBaseSystem,
+        label_max_width: f32,
+        rows_changed: bool,
+    ) {
+        let h = &self.ht_action_handler;
+        let sprite_list_contents = self.sprite_list_contents.borrow();
+        let rows = h.rows.borrow();
+        let (visible_top, visible_bottom) = h.scroll_view.visible_range();
+        let first = ((visible_top / CellView::HEIGHT).floor().max(0.0) as usize).min(rows.len());
+        let last =
+            ((visible_bottom / CellView::HEIGHT).ceil().max(0.0) as usize).clamp(first, rows.len());
+        let (ct_content, ht_content) = h.scroll_view.content_parents();
+
+        let mut cell_views = h.cell_views.borrow_mut();
+        // 範囲外になったセルを空ける
+        let mut bound_cells = vec![None; last - first];
+        for (n, c) in cell_views.iter().enumerate() {
+            if c.bound_sprite_index.get().is_none() {
+                continue;
+            }
+
+            let row = c.bound_row.get();
+            if (first..last).contains(&row) {
+                bound_cells[row - first] = Some(n);
+                continue;
+            }
+            if h.renaming_cell.get() == Some(n) && row < rows.len() {
+                // Note: 名前の編集中に見えなくなったセルはそのまま残しておく
+                continue;
+            }
+
+            c.unbind(app_system);
+        }
+        let mut free_cells = cell_views
+            .iter()
+            .enumerate()
+            .filter_map(|(n, c)| c.bound_sprite_index.get().is_none().then_some(n))
+            .collect::<Vec<_>>();
+
+        for (row, bound) in (first..last).zip(bound_cells) {
+            let sprite_index = rows[row];
+            let entry = &sprite_list_contents[sprite_index];
+            let top = row as f32 * CellView::HEIGHT;
+            let n = match bound {
+                Some(n) if rows_changed => n,
+                // 表示中で中身も変わっていない
+                Some(_) => continue,
+                None => match free_cells.pop() {
+                    Some(n) => {
+                        cell_views[n].mount(ct_content, ht_content, app_system);
+                        n
                     }
+                    None => {
+                        // create new one
+                        let new_cell = CellView::new(
+                            &mut ViewInitContext {
+                                base_system: app_system,
+                                ui_scale_factor: self.ui_scale_factor.get(),
+                            },
+                            &entry.name,
+                            label_max_width,
+                            top,
+                            row,
+                            sprite_index,
+                        );
+                        new_cell.mount(ct_content, ht_content, app_system);
+                        app_system.hit_tree.set_action_handler(new_cell.ht_root, h);
+                        if entry.selected {
+                            new_cell.on_select(&mut app_system.composite_tree);
+                        }
+                        new_cell.set_source_missing(entry.source_missing, app_system);
+
+                        cell_views.push(new_cell);
+                        continue;
+                    }
+                },
+            };
 
-                    cell_views.push(new_cell);
-                    continue;
-                }
+            let c = &cell_views[n];
+            c.bind(row, sprite_index);
+            c.set_top(top, app_system);
+            c.set_label(&entry.name, app_system);
+            c.set_source_missing(entry.source_missing, app_system);
+            if entry.selected {
+                c.on_select(&mut app_system.composite_tree);
+            } else {
+                c.on_deselect(&mut app_system.composite_tree);
+            }
+        }
+    }
 
-                // reuse existing
-                cell_views[n].set_top(32.0 + n as f32 * CellView::HEIGHT, app_system);
-                cell_views[n].set_label(c, app_system);
-                cell_views[n].bind_sprite_index(n);
-                if sel {
-                    cell_views[n].on_select(&mut app_system.composite_tree);
-                } else {
-                    cell_views[n].on_deselect(&mut app_system.composite_tree);
+    fn update_rename(
+        &self,
+        app_system: &mut AppBaseSystem,
+        label_max_width: f32,
+        current_sec: f32,
+    ) {
+        let h = &self.ht_action_handler;
+
+        if h.rename_finished.replace(false)
+            && let Some(n) = h.renaming_cell.take()
+        {
+            h.rename_input_view.unmount(app_system);
+            if let Some(cell) = h.cell_views.borrow().get(n) {
+                cell.set_label_visible(true, &mut app_system.composite_tree);
+            }
+        }
+
+        if let Some(n) = h.rename_request.take() {
+            let cell_views = h.cell_views.borrow();
+            if let Some(cell) = cell_views.get(n) {
+                if let Some(prev) = h.renaming_cell.replace(Some(n)) {
+                    cell_views[prev].set_label_visible(true, &mut app_system.composite_tree);
+                    h.rename_input_view.unmount(app_system);
                 }
+
+                h.rename_input_view
+                    .mount(app_system, cell.ct_root, cell.ht_root);
+                h.rename_input_view
+                    .set_position(app_system, CellView::LABEL_LEFT, 0.0);
+                h.rename_input_view
+                    .set_size(app_system, label_max_width, CellView::HEIGHT);
+                h.rename_input_view.set_text(&cell.label.borrow());
+                h.rename_input_view.select_all();
+                h.rename_input_view.focus(app_system);
+                cell.set_label_visible(false, &mut app_system.composite_tree);
             }
         }
 
-        for v in self.ht_action_handler.cell_views.borrow().iter() {
-            v.update(app_system, current_sec);
+        if h.renaming_cell.get().is_some() {
+            h.rename_input_view.update(app_system, current_sec);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, width: u32, height: u32) -> SpriteListEntry {
+        SpriteListEntry {
+            name: name.into(),
+            path: format!("/sprites/{name}.png"),
+            width,
+            height,
+            selected: false,
+            source_missing: false,
         }
     }
+
+    /// rebuild_rowsと同じく安定ソートで並べた名前
+    fn sorted_names(mode: SortMode, entries: &[SpriteListEntry]) -> Vec<&str> {
+        let mut rows = entries.iter().collect::<Vec<_>>();
+        rows.sort_by(|a, b| mode.compare(a, b));
+
+        rows.into_iter().map(|x| x.name.as_str()).collect()
+    }
+
+    #[test]
+    fn glob_star_backtracks() {
+        assert!(glob_match("a*bc", "abxbc"));
+        assert!(glob_match("*ab", "aab"));
+        assert!(glob_match("a*b*c", "axxbyybzc"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("a*z", "abc"));
+    }
+
+    #[test]
+    fn glob_question_matches_exactly_one_char() {
+        assert!(glob_match("sprite_??", "sprite_01"));
+        assert!(!glob_match("sprite_??", "sprite_1"));
+        assert!(!glob_match("sprite_??", "sprite_001"));
+        // バイトではなく文字単位
+        assert!(glob_match("?.png", "あ.png"));
+    }
+
+    #[test]
+    fn glob_trailing_star() {
+        assert!(glob_match("icon*", "icon"));
+        assert!(glob_match("icon*", "icon_large"));
+        assert!(glob_match("icon**", "icon"));
+        assert!(!glob_match("icon*", "big_icon"));
+    }
+
+    #[test]
+    fn glob_without_match() {
+        assert!(!glob_match("*.png", "a.jpg"));
+        assert!(!glob_match("", "a"));
+        assert!(!glob_match("a?", "a"));
+    }
+
+    #[test]
+    fn filter_uses_glob_only_with_wildcards() {
+        let e = entry("Player_Idle", 8, 8);
+
+        assert!(e.matches(""));
+        assert!(e.matches("idle"));
+        assert!(e.matches("player_*"));
+        assert!(e.matches("*/player_idle.png"));
+        // globは全体一致なので、部分文字列としては一致しない
+        assert!(!e.matches("idle*"));
+    }
+
+    #[test]
+    fn sort_by_size_breaks_ties_by_width_then_height() {
+        let entries = [
+            entry("tall", 10, 20),
+            entry("wide", 20, 10),
+            entry("long", 5, 30),
+            entry("wide2", 20, 10),
+            entry("square", 20, 20),
+        ];
+
+        // 長辺が同じなら幅の広いほう、さらに同じなら高いほうが先で、全部同じなら追加順
+        assert_eq!(
+            sorted_names(SortMode::Size, &entries),
+            ["long", "square", "wide", "wide2", "tall"]
+        );
+    }
+
+    #[test]
+    fn sort_by_area_keeps_insertion_order_on_ties() {
+        let entries = [
+            entry("a", 10, 20),
+            entry("b", 20, 10),
+            entry("c", 5, 30),
+            entry("d", 40, 5),
+        ];
+
+        assert_eq!(sorted_names(SortMode::Area, &entries), ["a", "b", "d", "c"]);
+    }
+
+    #[test]
+    fn sort_by_name_ignores_case() {
+        let entries = [entry("b", 1, 1), entry("A", 1, 1), entry("C", 1, 1)];
+
+        assert_eq!(sorted_names(SortMode::Name, &entries), ["A", "b", "C"]);
+        assert_eq!(sorted_names(SortMode::Insertion, &entries), ["b", "A", "C"]);
+    }
 }
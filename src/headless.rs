@@ -0,0 +1,295 @@
+//! ウィンドウシステムなしでアプリケーションを動かす(スナップショットテストなど向け)
+//!
+//! スワップチェーンの代わりにオフスクリーンのテクスチャへ描いて、結果をホストに読み戻す。
+//! GPUのない環境ではソフトウェア実装のドライバ(lavapipe)で動かせる
+//! (例: `VK_ICD_FILENAMES=/usr/share/vulkan/icd.d/lvp_icd.x86_64.json`)
+
+use std::cell::{Cell, RefCell};
+
+use bedrock as br;
+
+use crate::{
+    AppEventBus, AppUpdateContext, Application, PresenterInitContext, ViewInitContext, app_loop,
+    app_state::AppState,
+    base_system::{
+        AppBaseSystem, OffscreenRenderTarget, PixelFormat, ReadbackError,
+        inject_cmd_end_render_pass2,
+    },
+    bg_worker::BackgroundWorker,
+    composite::{CompositeRenderTarget, CompositeRenderer, CompositeRenderingData, CompositeTree},
+    hittest::{CursorShape, HitTestTreeData, HitTestTreeManager, HitTestTreeRef},
+    input::{PointerCaptureHost, PointerInputManager},
+    uikit::popup::PopupManager,
+};
+
+/// ポインタのキャプチャを覚えておくだけのPointerCaptureHost
+#[derive(Default)]
+struct HeadlessPointerCapture(Cell<bool>);
+impl PointerCaptureHost for HeadlessPointerCapture {
+    #[inline(always)]
+    fn capture_pointer(&self) {
+        self.0.set(true);
+    }
+
+    #[inline(always)]
+    fn release_pointer(&self) {
+        self.0.set(false);
+    }
+}
+
+/// シェルなしで動かすアプリケーション
+///
+/// 時刻は呼び出し側が渡す(実時間は使わない)ので、同じ操作なら毎回同じ結果になる
+pub struct HeadlessApp<'sys, 'subsystem> {
+    base_system: &'sys mut AppBaseSystem<'subsystem>,
+    state: &'sys RefCell<AppState<'subsystem>>,
+    events: &'sys AppEventBus,
+    bg_worker: &'sys BackgroundWorker<'subsystem>,
+    target: OffscreenRenderTarget<'subsystem>,
+    composite_renderer: CompositeRenderer<'subsystem>,
+    app: Application<'subsystem>,
+    popup_manager: PopupManager,
+    pointer_input_manager: PointerInputManager,
+    pointer_capture: HeadlessPointerCapture,
+    last_composite_render_instructions: CompositeRenderingData,
+    ui_scale_factor: f32,
+    /// 最後にイベントを処理した時刻
+    current_sec: f32,
+}
+impl<'sys, 'subsystem> HeadlessApp<'sys, 'subsystem> {
+    pub fn new(
+        base_system: &'sys mut AppBaseSystem<'subsystem>,
+        state: &'sys RefCell<AppState<'subsystem>>,
+        events: &'sys AppEventBus,
+        bg_worker: &'sys BackgroundWorker<'subsystem>,
+        size: br::Extent2D,
+        ui_scale_factor: f32,
+    ) -> br::Result<Self> {
+        let target = OffscreenRenderTarget::new(base_system, size, PixelFormat::Rgba8)?;
+        let composite_renderer = CompositeRenderer::new(base_system, &target);
+        let app = Application::new(
+            &mut PresenterInitContext {
+                for_view: ViewInitContext {
+                    base_system,
+                    ui_scale_factor,
+                },
+                app_state: &mut state.borrow_mut(),
+            },
+            &composite_renderer,
+            size,
+            true,
+        );
+
+        let popup_hit_layer = base_system.create_hit_tree(HitTestTreeData {
+            width_adjustment_factor: 1.0,
+            height_adjustment_factor: 1.0,
+            ..Default::default()
+        });
+        base_system.set_hit_tree_parent(popup_hit_layer, HitTestTreeManager::ROOT);
+        let popup_manager = PopupManager::new(popup_hit_layer, CompositeTree::ROOT);
+
+        state.borrow_mut().synchronize_view();
+
+        Ok(Self {
+            pointer_input_manager: PointerInputManager::new(
+                size.width as f32 / ui_scale_factor,
+                size.height as f32 / ui_scale_factor,
+            ),
+            pointer_capture: HeadlessPointerCapture::default(),
+            base_system,
+            state,
+            events,
+            bg_worker,
+            target,
+            composite_renderer,
+            app,
+            popup_manager,
+            last_composite_render_instructions: CompositeRenderingData {
+                instructions: Vec::new(),
+                render_passes: Vec::new(),
+                required_backdrop_buffer_count: 0,
+                layers: Vec::new(),
+            },
+            ui_scale_factor,
+            current_sec: 0.0,
+        })
+    }
+
+    /// クライアント領域の大きさ(論理ピクセル)
+    pub fn client_size(&self) -> (f32, f32) {
+        (
+            self.target.size().width as f32 / self.ui_scale_factor,
+            self.target.size().height as f32 / self.ui_scale_factor,
+        )
+    }
+
+    pub fn state(&self) -> std::cell::Ref<'_, AppState<'subsystem>> {
+        self.state.borrow()
+    }
+
+    pub const fn events(&self) -> &'sys AppEventBus {
+        self.events
+    }
+
+    pub const fn base_system(&self) -> &AppBaseSystem<'subsystem> {
+        self.base_system
+    }
+
+    /// ヘッダーの高さ(論理ピクセル)
+    pub fn header_height(&self) -> f32 {
+        self.app.app_header.height()
+    }
+
+    /// 開いているポップアップの数
+    pub fn popup_count(&self) -> usize {
+        self.popup_manager.count()
+    }
+
+    /// クライアント座標(論理ピクセル)にあるヒット対象
+    pub fn hit_at(&self, client_x: f32, client_y: f32) -> Option<HitTestTreeRef> {
+        let (client_width, client_height) = self.client_size();
+
+        self.base_system.hit_tree.test(
+            HitTestTreeManager::ROOT,
+            client_x,
+            client_y,
+            0.0,
+            0.0,
+            client_width,
+            client_height,
+        )
+    }
+
+    /// いまのポインタの位置で表示されるカーソルの形
+    pub fn cursor_shape(&mut self) -> CursorShape {
+        let mut ctx = AppUpdateContext {
+            event_queue: self.events,
+            state: self.state,
+            ui_scale_factor: self.ui_scale_factor,
+            current_sec: self.current_sec,
+        };
+
+        self.pointer_input_manager
+            .cursor_shape(&mut self.base_system.hit_tree, &mut ctx)
+    }
+
+    pub fn is_pointer_captured(&self) -> bool {
+        self.pointer_capture.0.get()
+    }
+
+    /// たまっているAppEventを処理してから1フレーム描く
+    pub fn advance(&mut self, current_sec: f32) -> br::Result<()> {
+        self.process_events(current_sec);
+        self.render_frame(current_sec)
+    }
+
+    /// たまっているAppEventを処理する
+    ///
+    /// ファイルダイアログやクリップボードなど、シェルが必要なものは無視する
+    pub fn process_events(&mut self, current_sec: f32) {
+        self.current_sec = current_sec;
+        self.app.editing_atlas_plane.sync_with_app_state(
+            self.base_system,
+            &self.state.borrow(),
+            &self.bg_worker.enqueue_access(),
+        );
+        // Note: BackgroundWorker::new_synchronousで作ったものならスプライトの読み込みはここで終わる
+        self.bg_worker.run_pending();
+        app_loop::push_background_feedback(self.events, self.bg_worker);
+
+        let mut update_context = AppUpdateContext {
+            event_queue: self.events,
+            state: self.state,
+            ui_scale_factor: self.ui_scale_factor,
+            current_sec,
+        };
+        while let Some(e) = self.events.pop() {
+            let unhandled = app_loop::process_common_event(
+                &mut app_loop::EventContext {
+                    base_system: self.base_system,
+                    app: &mut self.app,
+                    popup_manager: &mut self.popup_manager,
+                    pointer_input_manager: &mut self.pointer_input_manager,
+                    pointer_capture: &self.pointer_capture,
+                    update_context: &mut update_context,
+                    ui_scale_factor: self.ui_scale_factor,
+                },
+                e,
+            );
+            if unhandled.is_some() {
+                tracing::debug!("event ignored in headless mode");
+            }
+        }
+
+        self.base_system
+            .keyboard_focus_manager
+            .dispatch_focus_changes(&self.base_system.hit_tree, &mut update_context);
+    }
+
+    /// 1フレーム描いて完了まで待つ
+    #[tracing::instrument(name = "HeadlessApp::render_frame", skip(self), err(Display))]
+    pub fn render_frame(&mut self, current_sec: f32) -> br::Result<()> {
+        let client_size = self.client_size();
+        let rt_size = self.target.size();
+        app_loop::prepare_frame(
+            &mut app_loop::FrameContext {
+                base_system: self.base_system,
+                app: &mut self.app,
+                popup_manager: &mut self.popup_manager,
+                composite_renderer: &mut self.composite_renderer,
+                last_instructions: &mut self.last_composite_render_instructions,
+                events: self.events,
+            },
+            client_size,
+            rt_size,
+            current_sec,
+        )?;
+        self.composite_renderer
+            .update_backdrop_resources(self.base_system, &self.target);
+
+        // Note: 毎フレーム完了まで待つので、更新も描画もコマンドは毎回記録し直す
+        let base_system = &*self.base_system;
+        let mut staging_scratch_buffers_locked = base_system.lock_staging_buffers();
+        base_system.sync_execute_graphics_commands(|rec| {
+            app_loop::inject_update_commands(
+                rec,
+                base_system,
+                &self.composite_renderer,
+                &self.app,
+                staging_scratch_buffers_locked.active_buffer(),
+                true,
+            )
+        })?;
+        staging_scratch_buffers_locked.flip_next_and_ready();
+        drop(staging_scratch_buffers_locked);
+
+        base_system.sync_execute_graphics_commands(|rec| {
+            rec.inject(|r| {
+                self.composite_renderer.populate_commands(
+                    r,
+                    &self.last_composite_render_instructions,
+                    rt_size,
+                    &self.target.backbuffer_image(0),
+                    0,
+                    None,
+                    |token, r| {
+                        self.app
+                            .handle_custom_render(base_system, token, rt_size, r)
+                    },
+                )
+            })
+            .inject(|r| {
+                inject_cmd_end_render_pass2(r, base_system.subsystem, &br::SubpassEndInfo::new())
+            })
+        })
+    }
+
+    /// 最後に描いたフレームの内容をRGBA8で読み出す
+    pub fn read_pixels(&self) -> Result<Vec<u8>, ReadbackError> {
+        self.target.read_pixels(self.base_system)
+    }
+
+    pub const fn size(&self) -> br::Extent2D {
+        self.target.size()
+    }
+}
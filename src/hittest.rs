@@ -2,7 +2,9 @@ use std::collections::BTreeSet;
 
 use crate::{
     AppUpdateContext,
-    input::{EventContinueControl, FocusTargetToken},
+    input::{
+        EventContinueControl, FocusTargetToken, KeyActionArgs, TextInputEvent, TextInputTarget,
+    },
 };
 
 pub struct HitTestTreeData<'h> {
@@ -14,6 +16,8 @@ pub struct HitTestTreeData<'h> {
     pub height: f32,
     pub width_adjustment_factor: f32,
     pub height_adjustment_factor: f32,
+    /// 子へのヒットを自分の範囲内に限る(スクロール領域などで、はみ出した子にあたらないようにする)
+    pub clip_children: bool,
     pub action_handler: Option<std::rc::Weak<dyn HitTestTreeActionHandler + 'h>>,
 }
 impl Default for HitTestTreeData<'_> {
@@ -28,6 +32,7 @@ impl Default for HitTestTreeData<'_> {
             height: 0.0,
             width_adjustment_factor: 0.0,
             height_adjustment_factor: 0.0,
+            clip_children: false,
             action_handler: None,
         }
     }
@@ -75,6 +80,7 @@ impl<'h> HitTestTreeManager<'h> {
             height: 0.0,
             width_adjustment_factor: 1.0,
             height_adjustment_factor: 1.0,
+            clip_children: false,
             action_handler: None,
         });
 
@@ -257,29 +263,30 @@ impl<'h> HitTestTreeManager<'h> {
             parent_global_top + parent_effective_height * d.top_adjustment_factor + d.top;
         let global_right = global_left + effective_width;
         let global_bottom = global_top + effective_height;
+        let inside = global_left <= global_x
+            && global_x <= global_right
+            && global_top <= global_y
+            && global_y <= global_bottom;
 
         // 後ろにあるほうが上なので優先して見る
-        if let Some(t) = self.relations[root.0].children.iter().rev().find_map(|&c| {
-            self.test(
-                HitTestTreeRef(c),
-                global_x,
-                global_y,
-                global_left,
-                global_top,
-                effective_width,
-                effective_height,
-            )
-        }) {
+        if (inside || !d.clip_children)
+            && let Some(t) = self.relations[root.0].children.iter().rev().find_map(|&c| {
+                self.test(
+                    HitTestTreeRef(c),
+                    global_x,
+                    global_y,
+                    global_left,
+                    global_top,
+                    effective_width,
+                    effective_height,
+                )
+            })
+        {
             // 子にヒット
             return Some(t);
         }
 
-        if global_left <= global_x
-            && global_x <= global_right
-            && global_top <= global_y
-            && global_y <= global_bottom
-            && d.action_handler.is_some()
-        {
+        if inside && d.action_handler.is_some() {
             // 自分にヒット ただしaction handlerが設定されていない場合は透過とみなす(うしろにあるHitTestTreeにあたってほしい)
             return Some(root);
         }
@@ -289,7 +296,7 @@ impl<'h> HitTestTreeManager<'h> {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CursorShape {
     Default,
     Pointer,
@@ -297,6 +304,7 @@ pub enum CursorShape {
     ResizeHorizontal,
 }
 
+#[derive(Clone, Copy)]
 pub struct PointerActionArgs {
     pub client_x: f32,
     pub client_y: f32,
@@ -304,6 +312,16 @@ pub struct PointerActionArgs {
     pub client_height: f32,
 }
 
+#[derive(Clone, Copy)]
+pub struct ScrollActionArgs {
+    pub pointer: PointerActionArgs,
+    /// スクロール量(正の値で右/下の内容を見る方向)
+    pub delta_x: f32,
+    pub delta_y: f32,
+    /// タッチパッドから指が離れた(ここから慣性スクロールを始めてよい)
+    pub fling: bool,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Role {
     ForceClient,
@@ -340,6 +358,13 @@ pub trait HitTestTreeActionHandler {
         None
     }
 
+    /// テキスト入力を受け付けるときはキャレットの位置(senderのローカル座標)などを返す
+    #[allow(unused_variables)]
+    #[inline]
+    fn text_input_target(&self, sender: HitTestTreeRef) -> Option<TextInputTarget> {
+        None
+    }
+
     #[allow(unused_variables)]
     fn on_pointer_enter(
         &self,
@@ -399,4 +424,42 @@ pub trait HitTestTreeActionHandler {
     ) -> EventContinueControl {
         EventContinueControl::empty()
     }
+
+    #[allow(unused_variables)]
+    fn on_scroll(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut AppUpdateContext,
+        args: &ScrollActionArgs,
+    ) -> EventContinueControl {
+        EventContinueControl::empty()
+    }
+
+    /// キーボードフォーカスを得た
+    #[allow(unused_variables)]
+    fn on_focus(&self, sender: HitTestTreeRef, context: &mut AppUpdateContext) {}
+
+    /// キーボードフォーカスを失った
+    #[allow(unused_variables)]
+    fn on_blur(&self, sender: HitTestTreeRef, context: &mut AppUpdateContext) {}
+
+    #[allow(unused_variables)]
+    fn on_key_down(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut AppUpdateContext,
+        args: &KeyActionArgs,
+    ) -> EventContinueControl {
+        EventContinueControl::empty()
+    }
+
+    #[allow(unused_variables)]
+    fn on_text_input(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut AppUpdateContext,
+        event: &TextInputEvent,
+    ) -> EventContinueControl {
+        EventContinueControl::empty()
+    }
 }
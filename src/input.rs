@@ -4,8 +4,9 @@ use bitflags::bitflags;
 
 use crate::{
     AppUpdateContext,
-    hittest::{CursorShape, HitTestTreeManager, HitTestTreeRef, PointerActionArgs, Role},
-    shell::AppShell,
+    hittest::{
+        CursorShape, HitTestTreeManager, HitTestTreeRef, PointerActionArgs, Role, ScrollActionArgs,
+    },
 };
 
 bitflags! {
@@ -18,6 +19,12 @@ bitflags! {
     }
 }
 
+/// ポインタのキャプチャを受け付ける側(ふつうはシェル)
+pub trait PointerCaptureHost {
+    fn capture_pointer(&self);
+    fn release_pointer(&self);
+}
+
 enum PointerFocusState {
     None,
     Entering(HitTestTreeRef),
@@ -94,7 +101,7 @@ impl PointerInputManager {
 
     fn dispatch_pointer_down(
         &self,
-        sh: &AppShell,
+        sh: &dyn PointerCaptureHost,
         action_args: &PointerActionArgs,
         ht: &HitTestTreeManager,
         action_context: &mut AppUpdateContext,
@@ -118,7 +125,7 @@ impl PointerInputManager {
                 .action_handler()
                 .and_then(|x| x.keyboard_focus(ht_ref))
             {
-                Some(x) => kfm.set_focus(x),
+                Some(x) => kfm.set_focus(x, ht_ref),
                 None => kfm.clear_focus(),
             }
 
@@ -170,7 +177,7 @@ impl PointerInputManager {
 
     fn dispatch_pointer_up(
         &self,
-        sh: &AppShell,
+        sh: &dyn PointerCaptureHost,
         action_args: &PointerActionArgs,
         ht: &HitTestTreeManager,
         action_context: &mut AppUpdateContext,
@@ -206,7 +213,7 @@ impl PointerInputManager {
 
     fn dispatch_click(
         &self,
-        sh: &AppShell,
+        sh: &dyn PointerCaptureHost,
         action_args: &PointerActionArgs,
         ht: &HitTestTreeManager,
         action_context: &mut AppUpdateContext,
@@ -381,7 +388,7 @@ impl PointerInputManager {
 
     pub fn handle_mouse_left_down(
         &mut self,
-        sh: &AppShell,
+        sh: &dyn PointerCaptureHost,
         ht: &mut HitTestTreeManager,
         action_context: &mut AppUpdateContext,
         ht_root: HitTestTreeRef,
@@ -417,7 +424,7 @@ impl PointerInputManager {
                     .action_handler()
                     .and_then(|x| x.keyboard_focus(ht_ref))
                 {
-                    Some(x) => kfm.set_focus(x),
+                    Some(x) => kfm.set_focus(x, ht_ref),
                     None => kfm.clear_focus(),
                 }
 
@@ -457,7 +464,7 @@ impl PointerInputManager {
 
     pub fn handle_mouse_left_up(
         &mut self,
-        sh: &AppShell,
+        sh: &dyn PointerCaptureHost,
         ht: &mut HitTestTreeManager,
         action_context: &mut AppUpdateContext,
         ht_root: HitTestTreeRef,
@@ -588,6 +595,63 @@ impl PointerInputManager {
         }
     }
 
+    /// ホイール/タッチパッドのスクロール ポインタの下(キャプチャ中ならその要素)から親へ流す
+    pub fn handle_mouse_scroll(
+        &mut self,
+        delta_x: f32,
+        delta_y: f32,
+        fling: bool,
+        ht: &mut HitTestTreeManager,
+        action_context: &mut AppUpdateContext,
+        ht_root: HitTestTreeRef,
+    ) {
+        let Some((client_x, client_y)) = self.last_client_pointer_pos else {
+            // no pointer on the surface
+            return;
+        };
+        let (client_width, client_height) = self.client_size;
+        let ht_target = match self.pointer_focus {
+            PointerFocusState::Capturing(ht_ref) | PointerFocusState::Entering(ht_ref) => ht_ref,
+            PointerFocusState::None => return,
+        };
+        let args = ScrollActionArgs {
+            pointer: PointerActionArgs {
+                client_x,
+                client_y,
+                client_width,
+                client_height,
+            },
+            delta_x,
+            delta_y,
+            fling,
+        };
+
+        let mut needs_recompute_pointer_enter = false;
+        let mut p = Some(ht_target);
+        while let Some(ht_ref) = p {
+            let flags = ht
+                .get_data(ht_ref)
+                .action_handler()
+                .map_or(EventContinueControl::empty(), |h| {
+                    h.on_scroll(ht_ref, action_context, &args)
+                });
+            if flags.contains(EventContinueControl::RECOMPUTE_POINTER_ENTER) {
+                needs_recompute_pointer_enter = true;
+            }
+            if flags.contains(EventContinueControl::STOP_PROPAGATION) {
+                break;
+            }
+
+            p = ht.parent_of(ht_ref);
+        }
+
+        if needs_recompute_pointer_enter
+            && matches!(self.pointer_focus, PointerFocusState::Entering(_))
+        {
+            self.handle_mouse_enter_leave(client_x, client_y, ht, action_context, ht_root);
+        }
+    }
+
     pub fn recompute_enter_leave(
         &mut self,
         ht: &mut HitTestTreeManager,
@@ -712,13 +776,77 @@ impl PointerInputManager {
     }
 }
 
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct KeyModifiers: u8 {
+        const SHIFT = 1 << 0;
+        const CTRL = 1 << 1;
+        const ALT = 1 << 2;
+        const SUPER = 1 << 3;
+    }
+}
+
+/// テキスト編集とショートカットで使うキー(文字の入力はTextInputEventで来る)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCode {
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    Backspace,
+    Delete,
+    Enter,
+    Escape,
+    Tab,
+    A,
+    C,
+    V,
+    X,
+    Other,
+}
+
+pub struct KeyActionArgs {
+    pub key: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+#[derive(Debug, Clone)]
+pub enum TextInputEvent {
+    /// 確定した文字列(キー入力/IME/ペースト)
+    Commit(String),
+    /// IMEの変換中文字列 cursorはtext内のbyte offsetの範囲(Noneならカーソル非表示)
+    Preedit {
+        text: String,
+        cursor: Option<(usize, usize)>,
+    },
+    /// キャレットの前後を削除する(byte単位)
+    DeleteSurrounding {
+        before_length: usize,
+        after_length: usize,
+    },
+}
+
+/// テキスト入力を受け付けている要素の情報(IMEの候補ウィンドウの位置決めなどに使う)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextInputTarget {
+    pub caret_left: f32,
+    pub caret_top: f32,
+    pub caret_width: f32,
+    pub caret_height: f32,
+    pub numeric_only: bool,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct FocusTargetToken(usize);
 
 pub struct KeyboardFocusManager {
     last_token: usize,
     unused_token: BTreeSet<usize>,
-    current_focus: Option<usize>,
+    current_focus: Option<(usize, HitTestTreeRef)>,
+    /// 最後にon_focusを通知した要素
+    notified_focus: Option<HitTestTreeRef>,
 }
 impl KeyboardFocusManager {
     pub fn new() -> Self {
@@ -726,6 +854,7 @@ impl KeyboardFocusManager {
             last_token: 0,
             unused_token: BTreeSet::new(),
             current_focus: None,
+            notified_focus: None,
         }
     }
 
@@ -740,6 +869,10 @@ impl KeyboardFocusManager {
     }
 
     pub fn release_token(&mut self, tok: FocusTargetToken) {
+        if self.current_focus.is_some_and(|(x, _)| x == tok.0) {
+            self.current_focus = None;
+        }
+
         if tok.0 == self.last_token - 1 {
             self.last_token -= 1;
         } else {
@@ -748,14 +881,108 @@ impl KeyboardFocusManager {
     }
 
     pub fn has_focus(&self, tok: &FocusTargetToken) -> bool {
-        self.current_focus.is_some_and(|x| x == tok.0)
+        self.current_focus.is_some_and(|(x, _)| x == tok.0)
     }
 
-    pub fn set_focus(&mut self, tok: FocusTargetToken) {
-        self.current_focus = Some(tok.0);
+    /// targetはキーボードイベントを最初に受け取る要素
+    pub fn set_focus(&mut self, tok: FocusTargetToken, target: HitTestTreeRef) {
+        self.current_focus = Some((tok.0, target));
     }
 
     pub fn clear_focus(&mut self) {
         self.current_focus = None;
     }
+
+    pub fn focused_element(&self) -> Option<HitTestTreeRef> {
+        self.current_focus.map(|(_, r)| r)
+    }
+
+    pub fn dispatch_key_down(
+        &self,
+        ht: &HitTestTreeManager,
+        action_context: &mut AppUpdateContext,
+        args: &KeyActionArgs,
+    ) {
+        let mut p = self.focused_element();
+        while let Some(ht_ref) = p {
+            let flags = ht
+                .get_data(ht_ref)
+                .action_handler()
+                .map_or(EventContinueControl::empty(), |h| {
+                    h.on_key_down(ht_ref, action_context, args)
+                });
+            if flags.contains(EventContinueControl::STOP_PROPAGATION) {
+                break;
+            }
+
+            p = ht.parent_of(ht_ref);
+        }
+    }
+
+    pub fn dispatch_text_input(
+        &self,
+        ht: &HitTestTreeManager,
+        action_context: &mut AppUpdateContext,
+        event: &TextInputEvent,
+    ) {
+        let mut p = self.focused_element();
+        while let Some(ht_ref) = p {
+            let flags = ht
+                .get_data(ht_ref)
+                .action_handler()
+                .map_or(EventContinueControl::empty(), |h| {
+                    h.on_text_input(ht_ref, action_context, event)
+                });
+            if flags.contains(EventContinueControl::STOP_PROPAGATION) {
+                break;
+            }
+
+            p = ht.parent_of(ht_ref);
+        }
+    }
+
+    /// 前回の呼び出しからフォーカスが移っていればon_blur/on_focusを通知する
+    pub fn dispatch_focus_changes(
+        &mut self,
+        ht: &HitTestTreeManager,
+        action_context: &mut AppUpdateContext,
+    ) {
+        let current = self.focused_element();
+        if current == self.notified_focus {
+            return;
+        }
+
+        if let Some(old) = core::mem::replace(&mut self.notified_focus, current)
+            && let Some(h) = ht.get_data(old).action_handler()
+        {
+            h.on_blur(old, action_context);
+        }
+        if let Some(new) = current
+            && let Some(h) = ht.get_data(new).action_handler()
+        {
+            h.on_focus(new, action_context);
+        }
+    }
+
+    /// フォーカスのある要素がテキスト入力を受け付けていればその情報をクライアント座標で返す
+    pub fn text_input_target(
+        &self,
+        ht: &HitTestTreeManager,
+        client_width: f32,
+        client_height: f32,
+    ) -> Option<TextInputTarget> {
+        let ht_ref = self.focused_element()?;
+        let t = ht
+            .get_data(ht_ref)
+            .action_handler()?
+            .text_input_target(ht_ref)?;
+        let (left, top, _, _) =
+            ht.translate_client_to_tree_local(ht_ref, 0.0, 0.0, client_width, client_height);
+
+        Some(TextInputTarget {
+            caret_left: t.caret_left - left,
+            caret_top: t.caret_top - top,
+            ..t
+        })
+    }
 }
@@ -0,0 +1,719 @@
+//! 入力の再生によるUIの結合テスト
+//!
+//! `--replay-test <script>...`で起動すると、スクリプトに書かれた操作をAppEventとして流し、
+//! 途中に書かれた期待値をAppStateやヒットテストの結果と比べる。
+//! 時計は実時間ではなく、操作ごとに1フレーム(1/60秒)ずつ進む。
+//!
+//! スクリプトは1行1命令で、行頭か空白の直後にある`#`から行末まではコメント(引用符の中は除く)。
+//! 空白や`#`を含む引数は`"..."`で囲む(`\"`と`\\`でエスケープ)。
+//! 座標はクライアント座標(論理ピクセル)で、`header+16`のようにヘッダーの下端からの距離でも書ける。
+//!
+//! ```text
+//! add_sprites a.png "b c.png"  # スクリプトのあるディレクトリからの相対パス
+//! wait 0.5                     # 指定秒数だけ時計を進める
+//! move 100 200
+//! down / up
+//! click 100 200
+//! click 16 header+18
+//! drag 100 200 300 400 [steps]
+//! scroll 0 -120
+//! key Ctrl+Shift+A
+//! text abc def                 # 命令名のあとの残りをそのままTextInputEvent::Commitとして流す
+//! text "#1 \"quoted\""         # 引用符で囲むと`#`や前後の空白も含められる
+//! expect menu_visible true
+//! expect sprite_list_visible false
+//! expect popups 1
+//! expect sprite_count 2
+//! expect selected 0 1          # 選択されているスプライトのインデックス(なければ`none`)
+//! expect sprite 0 at 32 0
+//! expect modified true
+//! expect hit 100 200 some      # `some`/`none`
+//! expect cursor pointer        # default/pointer/ibeam/resize_horizontal
+//! expect captured false
+//! ```
+
+use std::{
+    cell::RefCell,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use bedrock as br;
+
+use crate::{
+    AppEvent, AppEventBus,
+    app_state::AppState,
+    base_system::AppBaseSystem,
+    bg_worker::BackgroundWorker,
+    headless::HeadlessApp,
+    hittest::CursorShape,
+    input::{KeyCode, KeyModifiers, TextInputEvent},
+    subsystem::Subsystem,
+};
+
+const FRAME_SIZE: br::Extent2D = br::Extent2D {
+    width: 960,
+    height: 640,
+};
+const UI_SCALE_FACTOR: f32 = 1.0;
+const FRAME_INTERVAL_SEC: f32 = 1.0 / 60.0;
+const DEFAULT_DRAG_STEPS: u32 = 8;
+
+pub struct Options {
+    pub scripts: Vec<PathBuf>,
+}
+impl Options {
+    /// `--replay-test`が指定されていなければNone
+    pub fn from_args(args: impl Iterator<Item = OsString>) -> Option<Self> {
+        let mut args = args.peekable();
+        let mut enabled = false;
+        let mut scripts = Vec::new();
+        while let Some(a) = args.next() {
+            if a == "--replay-test" {
+                enabled = true;
+                while let Some(p) = args.next_if(|x| !x.to_string_lossy().starts_with("--")) {
+                    scripts.push(PathBuf::from(p));
+                }
+            }
+        }
+
+        enabled.then_some(Self { scripts })
+    }
+}
+
+/// 操作をAppEventとして流し、1フレームずつ時計を進めるドライバ
+pub struct InputReplayDriver<'app, 'sys, 'subsystem> {
+    app: &'app mut HeadlessApp<'sys, 'subsystem>,
+    current_sec: f32,
+}
+impl<'app, 'sys, 'subsystem> InputReplayDriver<'app, 'sys, 'subsystem> {
+    pub const fn new(app: &'app mut HeadlessApp<'sys, 'subsystem>) -> Self {
+        Self {
+            app,
+            current_sec: 0.0,
+        }
+    }
+
+    pub const fn app(&self) -> &HeadlessApp<'sys, 'subsystem> {
+        self.app
+    }
+
+    pub const fn app_mut(&mut self) -> &mut HeadlessApp<'sys, 'subsystem> {
+        self.app
+    }
+
+    pub const fn current_sec(&self) -> f32 {
+        self.current_sec
+    }
+
+    /// イベントを流して1フレーム進める
+    pub fn push(&mut self, e: AppEvent) -> br::Result<()> {
+        self.app.events().push(e);
+        self.step()
+    }
+
+    fn step(&mut self) -> br::Result<()> {
+        self.current_sec += FRAME_INTERVAL_SEC;
+        self.app.advance(self.current_sec)
+    }
+
+    pub fn wait(&mut self, sec: f32) -> br::Result<()> {
+        let until = self.current_sec + sec;
+        while self.current_sec < until {
+            self.step()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn pointer_move(&mut self, x: f32, y: f32) -> br::Result<()> {
+        self.push(AppEvent::MainWindowPointerMove {
+            surface_x: x,
+            surface_y: y,
+        })
+    }
+
+    pub fn left_down(&mut self) -> br::Result<()> {
+        self.push(AppEvent::MainWindowPointerLeftDown)
+    }
+
+    pub fn left_up(&mut self) -> br::Result<()> {
+        self.push(AppEvent::MainWindowPointerLeftUp)
+    }
+
+    pub fn click(&mut self, x: f32, y: f32) -> br::Result<()> {
+        self.pointer_move(x, y)?;
+        self.left_down()?;
+        self.left_up()
+    }
+
+    /// `steps`回に分けてポインタを動かす
+    pub fn drag(&mut self, from: (f32, f32), to: (f32, f32), steps: u32) -> br::Result<()> {
+        self.pointer_move(from.0, from.1)?;
+        self.left_down()?;
+        let steps = steps.max(1);
+        for n in 1..=steps {
+            let t = n as f32 / steps as f32;
+            self.pointer_move(from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t)?;
+        }
+        self.left_up()
+    }
+
+    pub fn scroll(&mut self, delta_x: f32, delta_y: f32) -> br::Result<()> {
+        self.push(AppEvent::MainWindowPointerScroll {
+            delta_x,
+            delta_y,
+            fling: false,
+        })
+    }
+
+    pub fn key_down(&mut self, key: KeyCode, modifiers: KeyModifiers) -> br::Result<()> {
+        self.push(AppEvent::MainWindowKeyDown { key, modifiers })
+    }
+
+    pub fn text_input(&mut self, text: impl Into<String>) -> br::Result<()> {
+        self.push(AppEvent::MainWindowTextInput(TextInputEvent::Commit(
+            text.into(),
+        )))
+    }
+}
+
+/// スクリプト中の座標
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Coord {
+    Client(f32),
+    /// ヘッダーの下端からの距離(`header+16`のように書く)
+    BelowHeader(f32),
+}
+impl Coord {
+    fn resolve(self, header_height: f32) -> f32 {
+        match self {
+            Self::Client(x) => x,
+            Self::BelowHeader(x) => header_height + x,
+        }
+    }
+}
+
+enum Command {
+    Move(Coord, Coord),
+    Down,
+    Up,
+    Click(Coord, Coord),
+    Drag {
+        from: (Coord, Coord),
+        to: (Coord, Coord),
+        steps: u32,
+    },
+    Scroll(f32, f32),
+    Key(KeyCode, KeyModifiers),
+    Text(String),
+    Wait(f32),
+    AddSprites(Vec<PathBuf>),
+    Expect(Expectation),
+}
+
+enum Expectation {
+    MenuVisible(bool),
+    SpriteListVisible(bool),
+    Popups(usize),
+    SpriteCount(usize),
+    Selected(Vec<usize>),
+    SpritePosition { index: usize, left: u32, top: u32 },
+    Modified(bool),
+    Hit { x: Coord, y: Coord, some: bool },
+    Cursor(CursorShape),
+    Captured(bool),
+}
+impl Expectation {
+    /// 一致しなければ(期待値, 実際の値)
+    fn check(&self, driver: &mut InputReplayDriver) -> Result<(), (String, String)> {
+        fn compare<T: PartialEq + std::fmt::Debug>(
+            expected: &T,
+            actual: T,
+        ) -> Result<(), (String, String)> {
+            if *expected == actual {
+                Ok(())
+            } else {
+                Err((format!("{expected:?}"), format!("{actual:?}")))
+            }
+        }
+
+        match self {
+            Self::MenuVisible(x) => compare(x, driver.app().state().is_visible_menu()),
+            Self::SpriteListVisible(x) => {
+                compare(x, driver.app().base_system().settings.sprite_list_visible)
+            }
+            Self::Popups(x) => compare(x, driver.app().popup_count()),
+            Self::SpriteCount(x) => compare(x, driver.app().state().sprites().len()),
+            Self::Selected(x) => compare(
+                x,
+                driver
+                    .app()
+                    .state()
+                    .selected_sprites_with_index()
+                    .map(|(n, _)| n)
+                    .collect(),
+            ),
+            &Self::SpritePosition { index, left, top } => {
+                let state = driver.app().state();
+                let Some(s) = state.sprites().get(index) else {
+                    return Err((format!("sprite #{index}"), String::from("none")));
+                };
+
+                compare(&(left, top), (s.left, s.top))
+            }
+            Self::Modified(x) => compare(x, driver.app().state().is_modified()),
+            &Self::Hit { x, y, some } => {
+                let app = driver.app();
+                let h = app.header_height();
+                compare(&some, app.hit_at(x.resolve(h), y.resolve(h)).is_some())
+            }
+            Self::Cursor(x) => compare(x, driver.app_mut().cursor_shape()),
+            Self::Captured(x) => compare(x, driver.app().is_pointer_captured()),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum ScriptError {
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+    #[error(transparent)]
+    Vulkan(#[from] br::vk::VkResult),
+    #[error("line {line}: {message}")]
+    Parse { line: usize, message: String },
+    #[error("line {line}: expected {expected}, but was {actual}")]
+    ExpectationFailed {
+        line: usize,
+        expected: String,
+        actual: String,
+    },
+}
+
+fn parse_script(source: &str, base_dir: &Path) -> Result<Vec<(usize, Command)>, ScriptError> {
+    let mut commands = Vec::new();
+    for (n, l) in source.lines().enumerate() {
+        let line = n + 1;
+        let l = strip_comment(l)
+            .map_err(|message| ScriptError::Parse { line, message })?
+            .trim();
+        if l.is_empty() {
+            continue;
+        }
+
+        let c =
+            parse_command(l, base_dir).map_err(|message| ScriptError::Parse { line, message })?;
+        commands.push((line, c));
+    }
+
+    Ok(commands)
+}
+
+/// コメントを取り除く
+///
+/// `#`は行頭か空白の直後にあって、引用符の外にあるときだけコメントの始まりとみなす
+/// (引用符も行頭か空白の直後にあるときだけ引数の囲みとみなす)
+fn strip_comment(l: &str) -> Result<&str, String> {
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut after_space = true;
+    for (n, c) in l.char_indices() {
+        if quoted {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => quoted = false,
+                _ => (),
+            }
+        } else if c == '"' && after_space {
+            quoted = true;
+        } else if c == '#' && after_space {
+            return Ok(&l[..n]);
+        }
+
+        after_space = c.is_whitespace();
+    }
+
+    if quoted {
+        return Err(String::from("unterminated quote"));
+    }
+
+    Ok(l)
+}
+
+/// 空白で区切る `"..."`で囲むと空白や`#`を含められる(`\"`と`\\`でエスケープ)
+fn split_args(s: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut chars = s.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(c) = chars.next() else {
+            break;
+        };
+
+        let mut arg = String::new();
+        if c == '"' {
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(c @ ('"' | '\\')) => arg.push(c),
+                        Some(c) => return Err(format!("unknown escape: \\{c}")),
+                        None => return Err(String::from("unterminated quote")),
+                    },
+                    Some(c) => arg.push(c),
+                    None => return Err(String::from("unterminated quote")),
+                }
+            }
+            if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                return Err(String::from("expected whitespace after closing quote"));
+            }
+        } else {
+            arg.push(c);
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                arg.push(c);
+            }
+        }
+
+        args.push(arg);
+    }
+
+    Ok(args)
+}
+
+fn parse_command(l: &str, base_dir: &Path) -> Result<Command, String> {
+    let (name, rest) = l.split_once(char::is_whitespace).unwrap_or((l, ""));
+    let rest = rest.trim();
+    if name == "text" {
+        // Note: 引用符で囲まれていなければ残りをそのまま流す
+        if rest.starts_with('"') {
+            return match &split_args(rest)?[..] {
+                [t] => Ok(Command::Text(t.clone())),
+                _ => Err(format!("expected one quoted text: {rest}")),
+            };
+        }
+        if rest.is_empty() {
+            return Err(String::from("text requires an argument"));
+        }
+
+        return Ok(Command::Text(rest.to_owned()));
+    }
+
+    let args = split_args(rest)?;
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+    match (name, &args[..]) {
+        ("move", &[x, y]) => Ok(Command::Move(parse_coord(x)?, parse_coord(y)?)),
+        ("down", &[]) => Ok(Command::Down),
+        ("up", &[]) => Ok(Command::Up),
+        ("click", &[x, y]) => Ok(Command::Click(parse_coord(x)?, parse_coord(y)?)),
+        ("drag", &[fx, fy, tx, ty]) => Ok(Command::Drag {
+            from: (parse_coord(fx)?, parse_coord(fy)?),
+            to: (parse_coord(tx)?, parse_coord(ty)?),
+            steps: DEFAULT_DRAG_STEPS,
+        }),
+        ("drag", &[fx, fy, tx, ty, steps]) => Ok(Command::Drag {
+            from: (parse_coord(fx)?, parse_coord(fy)?),
+            to: (parse_coord(tx)?, parse_coord(ty)?),
+            steps: parse_num(steps)?,
+        }),
+        ("scroll", &[dx, dy]) => Ok(Command::Scroll(parse_num(dx)?, parse_num(dy)?)),
+        ("key", &[k]) => parse_key(k).map(|(k, m)| Command::Key(k, m)),
+        ("wait", &[sec]) => Ok(Command::Wait(parse_num(sec)?)),
+        ("add_sprites", paths) if !paths.is_empty() => Ok(Command::AddSprites(
+            paths.iter().map(|p| base_dir.join(p)).collect(),
+        )),
+        ("expect", &[what, ref values @ ..]) => {
+            parse_expectation(what, values).map(Command::Expect)
+        }
+        _ => Err(format!("unrecognized command: {l}")),
+    }
+}
+
+fn parse_expectation(what: &str, values: &[&str]) -> Result<Expectation, String> {
+    match (what, values) {
+        ("menu_visible", &[x]) => Ok(Expectation::MenuVisible(parse_bool(x)?)),
+        ("sprite_list_visible", &[x]) => Ok(Expectation::SpriteListVisible(parse_bool(x)?)),
+        ("popups", &[x]) => Ok(Expectation::Popups(parse_num(x)?)),
+        ("sprite_count", &[x]) => Ok(Expectation::SpriteCount(parse_num(x)?)),
+        ("selected", &["none"]) => Ok(Expectation::Selected(Vec::new())),
+        ("selected", xs) if !xs.is_empty() => Ok(Expectation::Selected(
+            xs.iter().map(|x| parse_num(x)).collect::<Result<_, _>>()?,
+        )),
+        ("sprite", &[index, "at", left, top]) => Ok(Expectation::SpritePosition {
+            index: parse_num(index)?,
+            left: parse_num(left)?,
+            top: parse_num(top)?,
+        }),
+        ("modified", &[x]) => Ok(Expectation::Modified(parse_bool(x)?)),
+        ("hit", &[x, y, some]) => Ok(Expectation::Hit {
+            x: parse_coord(x)?,
+            y: parse_coord(y)?,
+            some: match some {
+                "some" => true,
+                "none" => false,
+                _ => return Err(format!("expected some or none: {some}")),
+            },
+        }),
+        ("cursor", &[shape]) => Ok(Expectation::Cursor(match shape {
+            "default" => CursorShape::Default,
+            "pointer" => CursorShape::Pointer,
+            "ibeam" => CursorShape::IBeam,
+            "resize_horizontal" => CursorShape::ResizeHorizontal,
+            _ => return Err(format!("unknown cursor shape: {shape}")),
+        })),
+        ("captured", &[x]) => Ok(Expectation::Captured(parse_bool(x)?)),
+        _ => Err(format!("unrecognized expectation: {what}")),
+    }
+}
+
+fn parse_num<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("invalid number: {s}"))
+}
+
+/// `12.5`か`header`/`header+16`/`header-4`
+fn parse_coord(s: &str) -> Result<Coord, String> {
+    let Some(rest) = s.strip_prefix("header") else {
+        return parse_num(s).map(Coord::Client);
+    };
+
+    match rest.as_bytes().first() {
+        None => Ok(Coord::BelowHeader(0.0)),
+        Some(b'+') => parse_num(&rest[1..]).map(Coord::BelowHeader),
+        Some(b'-') => parse_num::<f32>(&rest[1..]).map(|x| Coord::BelowHeader(-x)),
+        Some(_) => Err(format!("invalid coordinate: {s}")),
+    }
+}
+
+fn parse_bool(s: &str) -> Result<bool, String> {
+    s.parse()
+        .map_err(|_| format!("expected true or false: {s}"))
+}
+
+/// `Ctrl+Shift+A`のような表記
+fn parse_key(s: &str) -> Result<(KeyCode, KeyModifiers), String> {
+    let mut parts = s.split('+').collect::<Vec<_>>();
+    let key = parts.pop().unwrap_or_default();
+
+    let mut modifiers = KeyModifiers::empty();
+    for m in parts {
+        modifiers |= match m.to_ascii_lowercase().as_str() {
+            "shift" => KeyModifiers::SHIFT,
+            "ctrl" => KeyModifiers::CTRL,
+            "alt" => KeyModifiers::ALT,
+            "super" => KeyModifiers::SUPER,
+            _ => return Err(format!("unknown modifier: {m}")),
+        };
+    }
+
+    let key = match key.to_ascii_lowercase().as_str() {
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "backspace" => KeyCode::Backspace,
+        "delete" => KeyCode::Delete,
+        "enter" => KeyCode::Enter,
+        "escape" => KeyCode::Escape,
+        "tab" => KeyCode::Tab,
+        "a" => KeyCode::A,
+        "c" => KeyCode::C,
+        "v" => KeyCode::V,
+        "x" => KeyCode::X,
+        _ => return Err(format!("unknown key: {key}")),
+    };
+
+    Ok((key, modifiers))
+}
+
+/// すべてのスクリプトが期待どおりならtrue
+pub fn run(options: &Options) -> bool {
+    if options.scripts.is_empty() {
+        tracing::error!("no replay scripts given");
+        return false;
+    }
+
+    let subsystem = Subsystem::init();
+    let events = AppEventBus::new();
+    // Note: 読み込みをワーカースレッドに任せるとフレームとの前後関係が毎回変わるので、HeadlessAppがイベントを処理するときにその場で実行する
+    let bg_worker = BackgroundWorker::new_synchronous();
+
+    let mut passed = true;
+    for p in &options.scripts {
+        match run_script(&subsystem, &events, &bg_worker, p) {
+            Ok(()) => tracing::info!(script = %p.display(), "ok"),
+            Err(e) => {
+                tracing::error!(script = %p.display(), reason = %e, "replay test failed");
+                passed = false;
+            }
+        }
+
+        // 次のスクリプトに前のスクリプトのイベントを持ち越さない
+        while events.pop().is_some() {}
+    }
+
+    bg_worker.teardown();
+    passed
+}
+
+fn run_script<'subsystem>(
+    subsystem: &'subsystem Subsystem,
+    events: &AppEventBus,
+    bg_worker: &BackgroundWorker<'subsystem>,
+    path: &Path,
+) -> Result<(), ScriptError> {
+    let source = std::fs::read_to_string(path)?;
+    let commands = parse_script(&source, path.parent().unwrap_or(Path::new(".")))?;
+
+    // Note: ビューはベースシステム側のツリーに残るので、スクリプトごとに作り直す
+    let mut base_system = AppBaseSystem::new_ephemeral(subsystem);
+    base_system.rescale_fonts(UI_SCALE_FACTOR);
+    let state = RefCell::new(AppState::new());
+    let mut app = HeadlessApp::new(
+        &mut base_system,
+        &state,
+        events,
+        bg_worker,
+        FRAME_SIZE,
+        UI_SCALE_FACTOR,
+    )?;
+    let mut driver = InputReplayDriver::new(&mut app);
+    // 最初のフレームでビューを配置しておく
+    driver.step()?;
+
+    // Note: ヘッダーの高さはフォントで変わるので、座標はヘッダーからの相対でも書けるようにしている
+    let header_height = driver.app().header_height();
+    let resolve = |(x, y): (Coord, Coord)| (x.resolve(header_height), y.resolve(header_height));
+    for (line, c) in commands {
+        match c {
+            Command::Move(x, y) => {
+                let (x, y) = resolve((x, y));
+                driver.pointer_move(x, y)?
+            }
+            Command::Down => driver.left_down()?,
+            Command::Up => driver.left_up()?,
+            Command::Click(x, y) => {
+                let (x, y) = resolve((x, y));
+                driver.click(x, y)?
+            }
+            Command::Drag { from, to, steps } => {
+                let (from, to) = (resolve(from), resolve(to));
+                driver.drag(from, to, steps)?
+            }
+            Command::Scroll(dx, dy) => driver.scroll(dx, dy)?,
+            Command::Key(k, m) => driver.key_down(k, m)?,
+            Command::Text(t) => driver.text_input(t)?,
+            Command::Wait(sec) => driver.wait(sec)?,
+            Command::AddSprites(paths) => driver.push(AppEvent::AddSpriteByPathList(paths))?,
+            Command::Expect(x) => {
+                if let Err((expected, actual)) = x.check(&mut driver) {
+                    return Err(ScriptError::ExpectationFailed {
+                        line,
+                        expected,
+                        actual,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replay_scripts() -> Vec<PathBuf> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("replays");
+        let mut scripts = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().is_some_and(|x| x == "replay"))
+            .collect::<Vec<_>>();
+        scripts.sort();
+
+        scripts
+    }
+
+    #[test]
+    fn comments_start_only_outside_args() {
+        assert_eq!(strip_comment("# comment").unwrap(), "");
+        assert_eq!(strip_comment("click 1 2 # comment").unwrap(), "click 1 2 ");
+        assert_eq!(strip_comment("text a#b").unwrap(), "text a#b");
+        assert_eq!(
+            strip_comment(r#"text "a # b" # comment"#).unwrap(),
+            r#"text "a # b" "#
+        );
+        assert_eq!(
+            strip_comment(r##"text "\"# b" # comment"##).unwrap(),
+            r##"text "\"# b" "##
+        );
+        assert!(strip_comment(r#"text "a # b"#).is_err());
+    }
+
+    #[test]
+    fn quoted_args() {
+        assert_eq!(
+            split_args(r#" a "b c" "d\"\\" "#).unwrap(),
+            ["a", "b c", r#"d"\"#]
+        );
+        assert!(split_args(r#""a"b"#).is_err());
+        assert!(split_args(r#""a\n""#).is_err());
+    }
+
+    #[test]
+    fn text_keeps_hash() {
+        let commands =
+            parse_script("text a#b c\ntext \"  #2 \" # comment\n", Path::new(".")).unwrap();
+        let texts = commands
+            .iter()
+            .map(|(_, c)| match c {
+                Command::Text(t) => t.as_str(),
+                _ => panic!("not a text command"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(texts, ["a#b c", "  #2 "]);
+    }
+
+    #[test]
+    fn coordinates_relative_to_header() {
+        assert_eq!(parse_coord("12.5").unwrap(), Coord::Client(12.5));
+        assert_eq!(parse_coord("header").unwrap(), Coord::BelowHeader(0.0));
+        assert_eq!(parse_coord("header+16").unwrap(), Coord::BelowHeader(16.0));
+        assert_eq!(parse_coord("header-4").unwrap(), Coord::BelowHeader(-4.0));
+        assert!(parse_coord("headers").is_err());
+        assert_eq!(Coord::BelowHeader(16.0).resolve(40.0), 56.0);
+    }
+
+    #[test]
+    fn add_sprites_paths_are_relative_to_script() {
+        let commands = parse_script(r#"add_sprites a.png "b c.png""#, Path::new("dir")).unwrap();
+        let [(1, Command::AddSprites(paths))] = &commands[..] else {
+            panic!("not an add_sprites command");
+        };
+        assert_eq!(paths, &[Path::new("dir/a.png"), Path::new("dir/b c.png")]);
+    }
+
+    #[test]
+    fn replay_scripts_parse() {
+        let scripts = replay_scripts();
+        assert!(!scripts.is_empty());
+        for p in scripts {
+            let source = std::fs::read_to_string(&p).unwrap();
+            if let Err(e) = parse_script(&source, p.parent().unwrap()) {
+                panic!("{}: {e}", p.display());
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device (e.g. lavapipe); run with `cargo test -- --ignored`"]
+    fn replay_scripts_pass() {
+        assert!(run(&Options {
+            scripts: replay_scripts(),
+        }));
+    }
+}
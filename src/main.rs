@@ -10,21 +10,28 @@ macro_rules! warn_bailout_scope {
     }
 }
 
+mod app_loop;
 mod app_state;
 mod atlas;
+#[cfg(target_os = "linux")]
+mod automation;
 mod base_system;
 mod bg_worker;
 mod composite;
 mod coordinate;
 mod feature;
+mod headless;
 mod helper_types;
 mod hittest;
 mod input;
+mod input_replay;
 mod mathext;
 mod peridot;
 mod platform;
 mod quadtree;
 mod shell;
+mod snapshot;
+mod source_path;
 mod source_reader;
 mod subsystem;
 mod text;
@@ -33,43 +40,61 @@ mod uikit;
 
 use helper_types::SafeF32;
 use shared_perflog_proto::{ProfileMarker, ProfileMarkerCategory};
-use uikit::popup::PopupManager;
+use uikit::{
+    message_dialog::{DialogIcon, DialogRequest},
+    popup::PopupManager,
+};
 
 #[cfg(all(unix, not(target_os = "macos")))]
 use std::os::fd::AsRawFd;
 use std::{
-    cell::{RefCell, UnsafeCell},
+    cell::{Cell, RefCell, UnsafeCell},
     collections::{BTreeSet, HashMap, VecDeque},
     rc::Rc,
 };
+#[cfg(target_os = "linux")]
+use uikit::file_browser::{FileBrowserMode, FileBrowserRequest, FileFilter};
 
 use crate::{
-    base_system::{FontType, inject_cmd_end_render_pass2, inject_cmd_pipeline_barrier_2},
+    base_system::{
+        FontType, inject_cmd_end_render_pass2,
+        theme::{ColorScheme, ThemeColor},
+    },
     coordinate::SizePixels,
 };
-use app_state::AppState;
-use base_system::{AppBaseSystem, WindowCornerCutoutRenderer, prof::ProfilingContext};
+use app_state::{AppState, RejectedSpriteSource};
+use base_system::{
+    AppBaseSystem, OffscreenRenderTarget, PixelFormat, WindowCornerCutoutRenderer,
+    prof::ProfilingContext,
+};
 
 use bedrock::{
     self as br, CommandBufferMut, CommandPoolMut, Device, Fence, FenceMut, InstanceChild,
     PhysicalDevice, Swapchain, VkHandle, VkHandleMut, VkObject, VkRawHandle,
 };
-use bg_worker::{BackgroundWorker, BackgroundWorkerViewFeedback};
+use bg_worker::BackgroundWorker;
 use composite::{
     AnimatableColor, AnimatableFloat, AnimationCurve, CompositeMode, CompositeRect,
-    CompositeRenderer, CompositeRenderingData, CompositeStreamingData, CompositeTree,
-    CompositeTreeRef, RenderPassAfterOperation, RenderPassRequirements,
+    CompositeRenderTarget, CompositeRenderer, CompositeRenderingData, CompositeTree,
+    CompositeTreeRef, CustomRenderToken, RenderPassAfterOperation, RenderPassRequirements,
+    rect_overlaps, rect_union,
 };
 use hittest::{HitTestTreeData, HitTestTreeManager};
 use shell::AppShell;
 use subsystem::Subsystem;
 
+/// AppEventの処理結果を受け取る(失敗時はメッセージ)
+pub type AppEventCompletion = Box<dyn FnOnce(Result<(), String>)>;
+
 pub enum AppEvent {
     ToplevelWindowNewSize {
         width_px: u32,
         height_px: u32,
     },
+    /// 閉じる要求(保存されていない変更があれば確認してから閉じる)
     ToplevelWindowClose,
+    /// 確認せずに閉じる
+    ToplevelWindowForceClose,
     ToplevelWindowFrameTiming,
     ToplevelWindowMinimizeRequest,
     ToplevelWindowToggleMaximizeRestoreRequest,
@@ -79,15 +104,24 @@ pub enum AppEvent {
     },
     MainWindowPointerLeftDown,
     MainWindowPointerLeftUp,
+    MainWindowPointerScroll {
+        delta_x: f32,
+        delta_y: f32,
+        fling: bool,
+    },
+    MainWindowKeyDown {
+        key: input::KeyCode,
+        modifiers: input::KeyModifiers,
+    },
+    MainWindowTextInput(input::TextInputEvent),
     MainWindowTiledStateChanged {
         is_tiled: bool,
     },
     UIPopupClose {
         id: uuid::Uuid,
     },
-    UIMessageDialogRequest {
-        content: String,
-    },
+    UIMessageDialogRequest(DialogRequest),
+    UIFileBrowserRequest(uikit::file_browser::FileBrowserRequest),
     UIPopupUnmount {
         id: uuid::Uuid,
     },
@@ -96,6 +130,13 @@ pub enum AppEvent {
     AppMenuRequestOpen,
     AppMenuRequestSave,
     AppMenuRequestAutoArrange,
+    /// 最近開いたファイルを開く(未保存の変更があれば確認する)
+    AppMenuRequestOpenRecent(std::path::PathBuf),
+    /// 起動時に最後に開いていたアセットを開き直すかを切り替える
+    AppMenuSetReopenLastAtlas(bool),
+    SpriteListPaneVisibilityChanged {
+        visible: bool,
+    },
     BeginBackgroundWork {
         thread_number: usize,
         message: String,
@@ -103,14 +144,43 @@ pub enum AppEvent {
     EndBackgroundWork {
         thread_number: usize,
     },
+    SpriteSourceLoadFailed(std::path::PathBuf),
     SelectSprite {
         index: usize,
     },
     DeselectSprite,
     AddSpritesByUriList(Vec<std::ffi::CString>),
     AddSpriteByPathList(Vec<std::path::PathBuf>),
+    /// ドロップされた画像データ(ファイルにしてから追加する)
+    AddSpriteByImageData(Vec<u8>),
+    /// 自動操作でファイルを開く(未保存の変更があれば開かずに失敗を返す)
+    OpenFile {
+        path: std::path::PathBuf,
+        completion: Option<AppEventCompletion>,
+    },
+    /// pathがNoneなら開いているファイルに上書きする
+    SaveFile {
+        path: Option<std::path::PathBuf>,
+        completion: Option<AppEventCompletion>,
+    },
+    AutoArrange {
+        allow_rotation: bool,
+        gap: u32,
+        order: app_state::ArrangeOrder,
+    },
     UIShowDragAndDropOverlay,
     UIHideDragAndDropOverlay,
+    UICopyText(String),
+    /// クリップボードの文字列をフォーカスのある要素にTextInputEvent::Commitとして流す
+    UIPasteText,
+    /// 選択中のスプライトをPNG画像と矩形のテキストとしてクリップボードに置く
+    UICopySelectedSprites,
+    /// クリップボードのファイルの一覧か画像をスプライトとして追加する
+    UIPasteSprites,
+    /// システムの配色設定が変わった
+    UIColorSchemeChanged(ColorScheme),
+    /// システムのアクセントカラーが変わった(Noneなら未設定)
+    UIAccentColorChanged(Option<[f32; 3]>),
 }
 
 pub struct AppEventBus {
@@ -121,6 +191,16 @@ pub struct AppEventBus {
     event_notify: platform::win32::event::EventObject,
 }
 impl AppEventBus {
+    pub fn new() -> Self {
+        Self {
+            queue: UnsafeCell::new(VecDeque::new()),
+            #[cfg(target_os = "linux")]
+            efd: linux_eventfd::EventFD::new(0, linux_eventfd::EventFDOptions::NONBLOCK).unwrap(),
+            #[cfg(windows)]
+            event_notify: platform::win32::event::EventObject::new(None, true, false).unwrap(),
+        }
+    }
+
     pub fn push(&self, e: AppEvent) {
         unsafe { &mut *self.queue.get() }.push_back(e);
         #[cfg(target_os = "linux")]
@@ -243,6 +323,8 @@ pub struct AppUpdateContext<'d, 'subsystem> {
     pub event_queue: &'d AppEventBus,
     pub state: &'d RefCell<AppState<'subsystem>>,
     pub ui_scale_factor: f32,
+    /// イベントを処理している時刻(フレームの更新と同じ時計の秒数)
+    pub current_sec: f32,
 }
 
 const fn const_subpass_description_2_single_color_write_only<const ATTACHMENT_INDEX: u32>()
@@ -261,7 +343,13 @@ pub struct DragAndDropOverlayView {
     ct_text: CompositeTreeRef,
 }
 impl DragAndDropOverlayView {
-    const BG_COLOR: AnimatableColor = AnimatableColor::Value([1.0, 1.0, 1.0, 0.125]);
+    const BG_ALPHA: f32 = 0.125;
+
+    fn bg_color(base_system: &AppBaseSystem) -> AnimatableColor {
+        base_system
+            .theme
+            .color_with_alpha(ThemeColor::Foreground, Self::BG_ALPHA)
+    }
 
     #[tracing::instrument(name = "DragAndDropOverlayView::new", skip(init))]
     pub fn new(init: &mut ViewInitContext) -> Self {
@@ -274,7 +362,7 @@ impl DragAndDropOverlayView {
             relative_size_adjustment: [1.0, 1.0],
             has_bitmap: true,
             composite_mode: CompositeMode::FillColorBackdropBlur(
-                Self::BG_COLOR,
+                Self::bg_color(init.base_system),
                 AnimatableFloat::Value(0.0),
             ),
             opacity: AnimatableFloat::Value(0.0),
@@ -329,6 +417,7 @@ impl DragAndDropOverlayView {
     }
 
     pub fn show(&self, base_system: &mut AppBaseSystem, current_sec: f32) {
+        let bg_color = Self::bg_color(base_system);
         self.ct_root
             .entity_mut_dirtified(&mut base_system.composite_tree)
             .opacity = AnimatableFloat::Animated {
@@ -342,7 +431,7 @@ impl DragAndDropOverlayView {
         self.ct_root
             .entity_mut_dirtified(&mut base_system.composite_tree)
             .composite_mode = CompositeMode::FillColorBackdropBlur(
-            Self::BG_COLOR,
+            bg_color,
             AnimatableFloat::Animated {
                 from_value: 0.0,
                 to_value: 9.0,
@@ -358,6 +447,7 @@ impl DragAndDropOverlayView {
     }
 
     pub fn hide(&self, base_system: &mut AppBaseSystem, current_sec: f32) {
+        let bg_color = Self::bg_color(base_system);
         self.ct_root
             .entity_mut_dirtified(&mut base_system.composite_tree)
             .opacity = AnimatableFloat::Animated {
@@ -371,7 +461,7 @@ impl DragAndDropOverlayView {
         self.ct_root
             .entity_mut_dirtified(&mut base_system.composite_tree)
             .composite_mode = CompositeMode::FillColorBackdropBlur(
-            Self::BG_COLOR,
+            bg_color,
             AnimatableFloat::Animated {
                 from_value: 9.0,
                 to_value: 0.0,
@@ -611,7 +701,9 @@ impl<'s> PrimaryRenderTarget<'s> {
                         2,
                         sc_format,
                         sc_size,
-                        br::ImageUsageFlags::COLOR_ATTACHMENT | br::ImageUsageFlags::TRANSFER_SRC,
+                        br::ImageUsageFlags::COLOR_ATTACHMENT
+                            | br::ImageUsageFlags::TRANSFER_SRC
+                            | br::ImageUsageFlags::TRANSFER_DEST,
                     )
                     .pre_transform(sc_transform)
                     .composite_alpha(sc_composite_alpha)
@@ -669,29 +761,6 @@ impl<'s> PrimaryRenderTarget<'s> {
         }
     }
 
-    pub const fn color_format(&self) -> br::Format {
-        self.format.format
-    }
-
-    #[inline(always)]
-    pub fn backbuffer_count(&self) -> usize {
-        self.backbuffers.len()
-    }
-
-    #[inline(always)]
-    pub fn backbuffer_image<'x>(&'x self, index: usize) -> br::VkHandleRef<'x, br::vk::VkImage> {
-        unsafe { br::VkHandleRef::dangling(self.backbuffers[index]) }
-    }
-
-    #[inline]
-    pub fn backbuffer_views<'x>(
-        &'x self,
-    ) -> impl Iterator<Item = br::VkHandleRef<'x, br::vk::VkImageView>> + use<'x> {
-        self.backbuffer_views
-            .iter()
-            .map(|&x| unsafe { br::VkHandleRef::dangling(x) })
-    }
-
     pub fn resize(&mut self, new_size: br::Extent2D) {
         self.backbuffers.clear();
         unsafe {
@@ -710,7 +779,9 @@ impl<'s> PrimaryRenderTarget<'s> {
                     2,
                     self.format,
                     new_size,
-                    br::ImageUsageFlags::COLOR_ATTACHMENT | br::ImageUsageFlags::TRANSFER_SRC,
+                    br::ImageUsageFlags::COLOR_ATTACHMENT
+                        | br::ImageUsageFlags::TRANSFER_SRC
+                        | br::ImageUsageFlags::TRANSFER_DEST,
                 )
                 .pre_transform(self.transform)
                 .composite_alpha(self.composite_alpha)
@@ -765,6 +836,50 @@ impl<'s> PrimaryRenderTarget<'s> {
 
         self.size = new_size;
     }
+
+    /// バックバッファと同じ並びのPixelFormat
+    pub fn pixel_format(&self) -> PixelFormat {
+        if self.format.format == br::vk::VK_FORMAT_B8G8R8A8_UNORM {
+            PixelFormat::Bgra8
+        } else {
+            PixelFormat::Rgba8
+        }
+    }
+}
+impl CompositeRenderTarget for PrimaryRenderTarget<'_> {
+    #[inline(always)]
+    fn extent(&self) -> br::Extent2D {
+        self.size
+    }
+
+    #[inline(always)]
+    fn color_format(&self) -> br::Format {
+        self.format.format
+    }
+
+    #[inline(always)]
+    fn backbuffer_count(&self) -> usize {
+        self.backbuffers.len()
+    }
+
+    #[inline(always)]
+    fn backbuffer_image<'x>(&'x self, index: usize) -> br::VkHandleRef<'x, br::vk::VkImage> {
+        unsafe { br::VkHandleRef::dangling(self.backbuffers[index]) }
+    }
+
+    #[inline]
+    fn backbuffer_views<'x>(
+        &'x self,
+    ) -> impl Iterator<Item = br::VkHandleRef<'x, br::vk::VkImageView>> + 'x {
+        self.backbuffer_views
+            .iter()
+            .map(|&x| unsafe { br::VkHandleRef::dangling(x) })
+    }
+
+    #[inline(always)]
+    fn presented_layout(&self) -> br::ImageLayout {
+        br::ImageLayout::PresentSrc
+    }
 }
 
 fn main() {
@@ -790,18 +905,19 @@ fn main() {
         objc_rt::foundation::NSSetUncaughtExceptionHandler(fault_exc_handler);
     }
 
+    if let Some(options) = snapshot::Options::from_args(std::env::args_os().skip(1)) {
+        std::process::exit(if snapshot::run(&options) { 0 } else { 1 });
+    }
+    if let Some(options) = input_replay::Options::from_args(std::env::args_os().skip(1)) {
+        std::process::exit(if input_replay::run(&options) { 0 } else { 1 });
+    }
+
     tracing::info!("Initializing BaseSystem...");
     let setup_timer = std::time::Instant::now();
 
     let syslink = SystemLink::new();
 
-    let events = AppEventBus {
-        queue: UnsafeCell::new(VecDeque::new()),
-        #[cfg(target_os = "linux")]
-        efd: linux_eventfd::EventFD::new(0, linux_eventfd::EventFDOptions::NONBLOCK).unwrap(),
-        #[cfg(windows)]
-        event_notify: platform::win32::event::EventObject::new(None, true, false).unwrap(),
-    };
+    let events = AppEventBus::new();
 
     let subsystem = Subsystem::init();
     let mut app_system = AppBaseSystem::new(&subsystem);
@@ -908,6 +1024,11 @@ impl<'subsystem> Application<'subsystem> {
         self.dnd_overlay.rescale(base_sys, ui_scale_factor);
     }
 
+    /// クライアント領域の大きさ(論理ピクセル)
+    pub fn set_client_size(&self, width: f32, height: f32) {
+        self.sprite_list_pane.set_client_size(width, height);
+    }
+
     pub fn update<'base_sys>(
         &self,
         base_sys: &'base_sys mut AppBaseSystem<'subsystem>,
@@ -935,6 +1056,48 @@ impl<'subsystem> Application<'subsystem> {
     pub fn needs_update_command(&self) -> bool {
         self.editing_atlas_plane.needs_update()
     }
+
+    pub fn handle_custom_render<'x>(
+        &self,
+        base_sys: &AppBaseSystem<'subsystem>,
+        token: CustomRenderToken,
+        rt_size: br::Extent2D,
+        rec: br::CmdRecord<'x>,
+    ) -> br::CmdRecord<'x> {
+        if let Some((sprite_index, clip_y)) =
+            self.sprite_list_pane.thumbnail_for_render_token(token)
+        {
+            let Some(bounds) = base_sys.composite_tree.custom_render_bounds(token) else {
+                // not rendered
+                return rec;
+            };
+
+            return self.editing_atlas_plane.render_sprite_thumbnail(
+                sprite_index,
+                shrink_custom_render_bounds(bounds),
+                clip_y,
+                rt_size,
+                rec,
+            );
+        }
+
+        self.editing_atlas_plane
+            .handle_custom_render(&token, rt_size, rec)
+    }
+}
+
+/// custom_render_boundsは1px広げてあるので元の大きさに戻す
+fn shrink_custom_render_bounds(bounds: br::Rect2D) -> br::Rect2D {
+    br::Rect2D {
+        offset: br::Offset2D {
+            x: bounds.offset.x + 1,
+            y: bounds.offset.y + 1,
+        },
+        extent: br::Extent2D {
+            width: bounds.extent.width.saturating_sub(2),
+            height: bounds.extent.height.saturating_sub(2),
+        },
+    }
 }
 
 fn app_main<'sys, 'event_bus, 'subsystem>(
@@ -959,7 +1122,10 @@ fn app_main<'sys, 'event_bus, 'subsystem>(
         },
         subsystem: app_system.subsystem,
     });
-    let mut composite_renderer = CompositeRenderer::new(app_system, &sc);
+    // Note: スワップチェーンのイメージは表示後に中身が保たれる保証がないので、常設のイメージに描いてから毎回全体を写す
+    let mut frame_target =
+        OffscreenRenderTarget::new(app_system, sc.size, sc.pixel_format()).unwrap();
+    let mut composite_renderer = CompositeRenderer::new(app_system, &frame_target);
     let mut corner_cutout_renderer = if !app_shell.server_side_decoration_provided() {
         // window decorations should be rendered by client size(not provided by window system server)
         Some(WindowCornerCutoutRenderer::new(
@@ -1010,16 +1176,49 @@ fn app_main<'sys, 'event_bus, 'subsystem>(
         &br::CommandPoolCreateInfo::new(app_system.subsystem.graphics_queue_family_index),
     )
     .unwrap();
-    let mut main_cbs = br::CommandBufferObject::alloc(
+    let [mut main_cb] = br::CommandBufferObject::alloc_array(
         app_system.subsystem,
-        &br::CommandBufferAllocateInfo::new(
+        &br::CommandBufferFixedCountAllocateInfo::new(
             &mut main_cp,
-            sc.backbuffer_count() as _,
             br::CommandBufferLevel::Primary,
         ),
     )
     .unwrap();
     let mut main_cb_invalid = true;
+    // 描いた内容をバックバッファに写す（バックバッファごと）
+    let mut present_cp = br::CommandPoolObject::new(
+        app_system.subsystem,
+        &br::CommandPoolCreateInfo::new(app_system.subsystem.graphics_queue_family_index),
+    )
+    .unwrap();
+    let mut present_cbs = br::CommandBufferObject::alloc(
+        app_system.subsystem,
+        &br::CommandBufferAllocateInfo::new(
+            &mut present_cp,
+            sc.backbuffer_count() as _,
+            br::CommandBufferLevel::Primary,
+        ),
+    )
+    .unwrap();
+    let mut present_cbs_invalid = true;
+    // 部分再描画用（毎フレーム記録し直す）
+    let mut partial_cp = br::CommandPoolObject::new(
+        app_system.subsystem,
+        &br::CommandPoolCreateInfo::new(app_system.subsystem.graphics_queue_family_index),
+    )
+    .unwrap();
+    let [mut partial_cb] = br::CommandBufferObject::alloc_array(
+        app_system.subsystem,
+        &br::CommandBufferFixedCountAllocateInfo::new(
+            &mut partial_cp,
+            br::CommandBufferLevel::Primary,
+        ),
+    )
+    .unwrap();
+    // frame_targetに描き直す必要のある領域
+    let mut frame_target_damage = Some(sc.size.into_rect(br::Offset2D::ZERO));
+    // 最後にpresentしてから変化した領域(incremental presentでウィンドウシステムに伝える)
+    let mut unpresented_damage = Some(sc.size.into_rect(br::Offset2D::ZERO));
 
     let mut update_cp = br::CommandPoolObject::new(
         app_system.subsystem,
@@ -1064,9 +1263,38 @@ fn app_main<'sys, 'event_bus, 'subsystem>(
         .unwrap();
     let mut last_updating = false;
 
+    #[cfg(target_os = "linux")]
+    let automation_server = syslink
+        .dbus
+        .as_ref()
+        .map(|dbus| (dbus, automation::Server::new(dbus, app_state.get_mut())));
+    #[cfg(target_os = "linux")]
+    task_worker
+        .spawn(syslink.watch_appearance_settings(events))
+        .detach();
+
+    app_state
+        .get_mut()
+        .set_recent_files(app_system.settings.recent_files.iter().cloned());
     app_state.get_mut().synchronize_view();
     app_shell.flush();
 
+    if app_system.settings.reopen_last_atlas
+        && let Some(path) = app_state.get_mut().recent_files().first().cloned()
+    {
+        if path.exists() {
+            // Note: Flatpak環境ではドキュメントポータル経由のパスの再リンクが必要なのでopen_assetを通す
+            let (shell, state) = (&*app_shell, &*app_state);
+            task_worker
+                .spawn(async move {
+                    open_asset(syslink, shell, state, events, &path, None).await;
+                })
+                .detach();
+        } else {
+            tracing::info!(path = %path.display(), "last atlas no longer exists, skipping reopen");
+        }
+    }
+
     #[cfg(target_os = "linux")]
     let mut poll_fd_pool = RefCell::new(PollFDPool::new());
     #[cfg(target_os = "linux")]
@@ -1101,18 +1329,23 @@ fn app_main<'sys, 'event_bus, 'subsystem>(
         .unwrap();
 
     #[cfg(target_os = "linux")]
-    syslink.dbus.con.set_watch_functions(Box::new(DBusWatcher {
-        epoll: &epoll,
-        fd_pool: &poll_fd_pool,
-        fd_to_pool_index: HashMap::new(),
-    }));
+    if let Some(ref dbus) = syslink.dbus {
+        dbus.con.set_watch_functions(Box::new(DBusWatcher {
+            epoll: &epoll,
+            fd_pool: &poll_fd_pool,
+            fd_to_pool_index: HashMap::new(),
+        }));
+    }
 
     // initialize misc state
     let mut newsize_request = None;
+    // 閉じる前の確認を出している間はtrue
+    let close_prompt_pending = Rc::new(Cell::new(false));
     let mut last_composite_render_instructions = CompositeRenderingData {
         instructions: Vec::new(),
         render_passes: Vec::new(),
         required_backdrop_buffer_count: 0,
+        layers: Vec::new(),
     };
     let mut composite_instance_buffer_dirty;
 
@@ -1123,6 +1356,7 @@ fn app_main<'sys, 'event_bus, 'subsystem>(
         event_queue: &events,
         state: &app_state,
         ui_scale_factor: app_shell.ui_scale_factor(),
+        current_sec: 0.0,
     };
 
     let elapsed = setup_timer.elapsed();
@@ -1132,12 +1366,36 @@ fn app_main<'sys, 'event_bus, 'subsystem>(
     let mut epoll_events =
         [const { core::mem::MaybeUninit::<linux_epoll::epoll_event>::uninit() }; 8];
     let t = std::time::Instant::now();
+    // 次のToplevelWindowFrameTimingが来ることになっているか（frameコールバック待ちか、すでにキューにある）
+    #[cfg(target_os = "linux")]
+    let mut frame_pending = true;
+    // 静止中に次のアニメーションが始まる時刻
+    #[cfg(target_os = "linux")]
+    let mut next_animation_wakeup = None::<std::time::Instant>;
     let mut _profiler = ProfilingContext::init("./local/profile");
     'app: loop {
+        #[cfg(target_os = "linux")]
+        let mut redraw_required = false;
         #[cfg(target_os = "linux")]
         {
             app_shell.prepare_read_events().unwrap();
-            let wake_count = epoll.wait(&mut epoll_events, None).unwrap();
+            let wakeup = if frame_pending {
+                None
+            } else {
+                next_animation_wakeup
+            };
+            // キーリピートは描画待ちの間も止めない
+            let wakeup = match (wakeup, app_shell.next_key_repeat_time()) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            let timeout = wakeup.map(|w| {
+                w.saturating_duration_since(std::time::Instant::now())
+                    .as_micros()
+                    .div_ceil(1000)
+                    .min(core::ffi::c_int::MAX as _) as core::ffi::c_int
+            });
+            let wake_count = epoll.wait(&mut epoll_events, timeout).unwrap();
             let mut shell_event_processed = false;
             for e in &epoll_events[..wake_count] {
                 let e = unsafe { e.assume_init_ref() };
@@ -1161,23 +1419,7 @@ fn app_main<'sys, 'event_bus, 'subsystem>(
                             }
                         }
 
-                        while let Some(vf) = bg_worker.try_pop_view_feedback() {
-                            match vf {
-                                BackgroundWorkerViewFeedback::BeginWork(thread_number, message) => {
-                                    app_update_context.event_queue.push(
-                                        AppEvent::BeginBackgroundWork {
-                                            thread_number,
-                                            message,
-                                        },
-                                    )
-                                }
-                                BackgroundWorkerViewFeedback::EndWork(thread_number) => {
-                                    app_update_context
-                                        .event_queue
-                                        .push(AppEvent::EndBackgroundWork { thread_number })
-                                }
-                            }
-                        }
+                        app_loop::push_background_feedback(events, bg_worker);
                     }
                     Some(&PollFDType::DBusWatch(watch_ptr)) => {
                         let watch_ptr = unsafe { &mut *watch_ptr };
@@ -1198,7 +1440,13 @@ fn app_main<'sys, 'event_bus, 'subsystem>(
                             tracing::warn!(?flags, "dbus_watch_handle failed");
                         }
 
-                        syslink.dbus.dispatch();
+                        // Note: ウォッチはセッションバスにつながっているときしか登録されない
+                        if let Some((dbus, ref server)) = automation_server {
+                            dbus.dispatch();
+                            while let Some(m) = dbus.pop_method_call() {
+                                server.handle_method_call(dbus, m, events);
+                            }
+                        }
                     }
                     // ignore
                     None => (),
@@ -1207,6 +1455,15 @@ fn app_main<'sys, 'event_bus, 'subsystem>(
             if !shell_event_processed {
                 app_shell.cancel_read_events();
             }
+            app_shell.process_key_repeat();
+
+            if !frame_pending
+                && next_animation_wakeup.is_some_and(|w| w <= std::time::Instant::now())
+            {
+                next_animation_wakeup = None;
+                events.push(AppEvent::ToplevelWindowFrameTiming);
+                frame_pending = true;
+            }
         }
         #[cfg(windows)]
         {
@@ -1238,21 +1495,7 @@ fn app_main<'sys, 'event_bus, 'subsystem>(
                 }
             }
 
-            while let Some(vf) = bg_worker.try_pop_view_feedback() {
-                match vf {
-                    BackgroundWorkerViewFeedback::BeginWork(thread_number, message) => {
-                        app_update_context
-                            .event_queue
-                            .push(AppEvent::BeginBackgroundWork {
-                                thread_number,
-                                message,
-                            })
-                    }
-                    BackgroundWorkerViewFeedback::EndWork(thread_number) => app_update_context
-                        .event_queue
-                        .push(AppEvent::EndBackgroundWork { thread_number }),
-                }
-            }
+            app_loop::push_background_feedback(events, bg_worker);
 
             app_shell.process_pending_events();
 
@@ -1296,6 +1539,7 @@ fn app_main<'sys, 'event_bus, 'subsystem>(
 
             app_system.rescale_fonts(active_ui_scale);
             app.rescale(app_system, active_ui_scale);
+            popup_manager.rescale(app_system, active_ui_scale);
         }
 
         task_worker.try_tick();
@@ -1307,107 +1551,150 @@ fn app_main<'sys, 'event_bus, 'subsystem>(
         );
 
         while let Some(e) = app_update_context.event_queue.pop() {
-            match e {
-                AppEvent::ToplevelWindowClose => {
-                    app_shell.close_safe();
-                    break 'app;
-                }
-                AppEvent::ToplevelWindowMinimizeRequest => {
-                    app_shell.minimize();
-                }
-                AppEvent::ToplevelWindowToggleMaximizeRestoreRequest => {
-                    app_shell.toggle_maximize_restore();
-                }
-                AppEvent::ToplevelWindowFrameTiming => {
-                    let mut _pf = _profiler.begin_frame();
+            #[cfg(target_os = "linux")]
+            if !matches!(e, AppEvent::ToplevelWindowFrameTiming) {
+                // なにかしら状態が変わった可能性があるので描き直す
+                redraw_required = true;
+            }
 
-                    let current_t = t.elapsed();
-                    let current_sec = current_t.as_secs_f32();
+            let pointer_moved = matches!(
+                e,
+                AppEvent::MainWindowPointerMove { .. }
+                    | AppEvent::MainWindowPointerLeftDown
+                    | AppEvent::MainWindowPointerLeftUp
+            );
+            app_update_context.ui_scale_factor = app_shell.ui_scale_factor();
+            app_update_context.current_sec = t.elapsed().as_secs_f32();
+            let unhandled = app_loop::process_common_event(
+                &mut app_loop::EventContext {
+                    base_system: app_system,
+                    app: &mut app,
+                    popup_manager: &mut popup_manager,
+                    pointer_input_manager: unsafe { &mut *app_shell.pointer_input_manager().get() },
+                    pointer_capture: &*app_shell,
+                    update_context: &mut app_update_context,
+                    ui_scale_factor: active_ui_scale,
+                },
+                e,
+            );
+            if pointer_moved {
+                app_shell.set_cursor_shape(
+                    unsafe { &mut *app_shell.pointer_input_manager().get() }
+                        .cursor_shape(&mut app_system.hit_tree, &mut app_update_context),
+                );
+            }
+
+            if let Some(e) = unhandled {
+                match e {
+                    AppEvent::ToplevelWindowClose => {
+                        if !app_state.borrow().is_modified() {
+                            app_shell.close_safe();
+                            break 'app;
+                        }
 
-                    if last_rendering {
-                        last_render_command_fence.wait().unwrap();
-                        last_render_command_fence.reset().unwrap();
-                        last_rendering = false;
+                        // Note: 確認を出している間にまた閉じる要求が来ても(閉じるボタンの連打など)重ねて出さない
+                        if !close_prompt_pending.replace(true) {
+                            let close_request =
+                                app_on_close_request(syslink, app_shell, app_state, events);
+                            let close_prompt_pending = close_prompt_pending.clone();
+                            task_worker
+                                .spawn(async move {
+                                    close_request.await;
+                                    close_prompt_pending.set(false);
+                                })
+                                .detach();
+                        }
+                    }
+                    AppEvent::ToplevelWindowForceClose => {
+                        app_shell.close_safe();
+                        break 'app;
+                    }
+                    AppEvent::ToplevelWindowMinimizeRequest => {
+                        app_shell.minimize();
                     }
+                    AppEvent::ToplevelWindowToggleMaximizeRestoreRequest => {
+                        app_shell.toggle_maximize_restore();
+                    }
+                    AppEvent::ToplevelWindowFrameTiming => {
+                        let mut _pf = _profiler.begin_frame();
+                        #[cfg(target_os = "linux")]
+                        {
+                            frame_pending = false;
+                        }
 
-                    if let Some((width, height)) = newsize_request.take() {
-                        let _pf = _pf.scoped(ProfileMarker::Resize);
-                        tracing::trace!(width, height, "frame resize");
+                        let current_t = t.elapsed();
+                        let current_sec = current_t.as_secs_f32();
 
-                        unsafe {
-                            main_cp.reset(br::CommandPoolResetFlags::EMPTY).unwrap();
+                        if last_rendering {
+                            last_render_command_fence.wait().unwrap();
+                            last_render_command_fence.reset().unwrap();
+                            last_rendering = false;
                         }
-                        main_cb_invalid = true;
 
-                        sc.resize(br::Extent2D { width, height });
+                        if let Some((width, height)) = newsize_request.take() {
+                            let _pf = _pf.scoped(ProfileMarker::Resize);
+                            tracing::trace!(width, height, "frame resize");
 
-                        let mut descriptor_writes = Vec::new();
-                        composite_renderer.recreate_rt_resources(
-                            app_system,
-                            &sc,
-                            &mut descriptor_writes,
-                        );
-                        app_system
-                            .subsystem
-                            .update_descriptor_sets(&descriptor_writes, &[]);
+                            unsafe {
+                                main_cp.reset(br::CommandPoolResetFlags::EMPTY).unwrap();
+                            }
+                            main_cb_invalid = true;
 
-                        if let Some(ref mut r) = corner_cutout_renderer {
-                            r.resize_rt(
-                                app_system,
-                                sc.size,
-                                composite_renderer.subpass_final(),
-                                composite_renderer.subpass_continue_final(),
-                            );
-                        }
+                            unsafe {
+                                present_cp.reset(br::CommandPoolResetFlags::EMPTY).unwrap();
+                            }
+                            present_cbs_invalid = true;
 
-                        app.resize_frame(sc.size.into(), app_system, &composite_renderer);
-                    }
+                            sc.resize(br::Extent2D { width, height });
+                            frame_target =
+                                OffscreenRenderTarget::new(app_system, sc.size, sc.pixel_format())
+                                    .unwrap();
+                            // 作り直したイメージは中身が不定なので全体を描き直す
+                            frame_target_damage = Some(sc.size.into_rect(br::Offset2D::ZERO));
+                            unpresented_damage = Some(sc.size.into_rect(br::Offset2D::ZERO));
 
-                    app.update(app_system, current_sec);
-                    popup_manager.update(app_system, current_sec);
-
-                    {
-                        let _pf = _pf.scoped(ProfileMarker::PopulateCompositeInstances);
-
-                        // もろもろの判定がめんどいのでいったん毎回updateする
-                        let n = app_system
-                            .composite_instance_manager
-                            .staging_memory_raw_handle();
-                        let r = app_system.composite_instance_manager.range_all();
-                        let flush_required = app_system
-                            .composite_instance_manager
-                            .memory_stg_requires_explicit_flush();
-                        let ptr = unsafe {
+                            let mut descriptor_writes = Vec::new();
+                            composite_renderer.recreate_rt_resources(
+                                app_system,
+                                &frame_target,
+                                &mut descriptor_writes,
+                            );
                             app_system
-                                .composite_instance_manager
-                                .map_staging(&app_system.subsystem)
-                                .unwrap()
-                        };
-                        let composite_render_instructions = unsafe {
-                            app_system.composite_tree.update(
-                                sc.size,
-                                current_t.as_secs_f32(),
-                                app_system.atlas.vk_extent(),
-                                ptr.ptr(),
-                                &events,
-                            )
-                        };
-                        if flush_required {
-                            unsafe {
-                                app_system
-                                    .subsystem
-                                    .flush_mapped_memory_ranges(&[br::MappedMemoryRange::new_raw(
-                                        n, 0, r.end as _,
-                                    )])
-                                    .unwrap();
+                                .subsystem
+                                .update_descriptor_sets(&descriptor_writes, &[]);
+
+                            if let Some(ref mut r) = corner_cutout_renderer {
+                                r.resize_rt(
+                                    app_system,
+                                    sc.size,
+                                    composite_renderer.subpass_final(),
+                                    composite_renderer.subpass_continue_final(),
+                                );
                             }
+
+                            app.resize_frame(sc.size.into(), app_system, &composite_renderer);
                         }
-                        drop(ptr);
 
-                        if last_composite_render_instructions != composite_render_instructions {
-                            // needs update render commands
-                            if !main_cb_invalid {
-                                // invalidate first
+                        let frame_damage;
+                        {
+                            let _pf = _pf.scoped(ProfileMarker::PopulateCompositeInstances);
+
+                            let instructions_changed = app_loop::prepare_frame(
+                                &mut app_loop::FrameContext {
+                                    base_system: app_system,
+                                    app: &mut app,
+                                    popup_manager: &mut popup_manager,
+                                    composite_renderer: &mut composite_renderer,
+                                    last_instructions: &mut last_composite_render_instructions,
+                                    events,
+                                },
+                                app_shell.client_size(),
+                                sc.size,
+                                current_sec,
+                            )
+                            .unwrap();
+                            if instructions_changed && !main_cb_invalid {
+                                // needs update render commands(invalidate first)
                                 if let Err(e) =
                                     unsafe { main_cp.reset(br::CommandPoolResetFlags::EMPTY) }
                                 {
@@ -1417,159 +1704,88 @@ fn app_main<'sys, 'event_bus, 'subsystem>(
                                 main_cb_invalid = true;
                             }
 
-                            composite_renderer.ready_input_backdrop_descriptor_sets(
-                                composite_render_instructions.required_backdrop_buffer_count,
-                            );
-
-                            if composite_render_instructions.render_passes[0]
-                                != app.editing_atlas_current_bound_pipeline
-                            {
-                                // editing atlas render pass changes
-                                app.editing_atlas_current_bound_pipeline =
-                                    composite_render_instructions.render_passes[0];
-                                app.editing_atlas_plane.recreate_render_resources(
-                                    app_system,
-                                    composite_renderer
-                                        .select_subpass(&app.editing_atlas_current_bound_pipeline),
-                                    sc.size,
-                                );
+                            frame_damage = if app.needs_update_command() {
+                                // Custom Renderの中身の変化はツリーからは見えないので全体を描き直す
+                                Some(sc.size.into_rect(br::Offset2D::ZERO))
+                            } else {
+                                app_system.composite_tree.damage()
+                            };
+                            if let Some(d) = frame_damage {
+                                for x in [&mut frame_target_damage, &mut unpresented_damage] {
+                                    *x = Some(x.map_or(d, |x| rect_union(&x, &d)));
+                                }
                             }
 
-                            last_composite_render_instructions = composite_render_instructions;
+                            composite_instance_buffer_dirty = true;
                         }
 
-                        composite_instance_buffer_dirty = true;
-                    }
-
-                    let n = app_system
-                        .composite_instance_manager
-                        .streaming_memory_raw_handle();
-                    let flush_required = app_system
-                        .composite_instance_manager
-                        .streaming_memory_requires_flush();
-                    let mapped = unsafe {
-                        app_system
-                            .composite_instance_manager
-                            .map_streaming(&app_system.subsystem)
-                            .unwrap()
-                    };
-                    unsafe {
-                        core::ptr::write(&mut (*mapped.ptr()).current_sec, current_t.as_secs_f32());
-                    }
-                    if flush_required {
-                        unsafe {
-                            app_system
-                                .subsystem
-                                .flush_mapped_memory_ranges(&[br::MappedMemoryRange::new_raw(
-                                    n,
-                                    0,
-                                    core::mem::size_of::<CompositeStreamingData>() as _,
-                                )])
-                                .unwrap();
+                        let composite_instance_buffer_dirty =
+                            core::mem::replace(&mut composite_instance_buffer_dirty, false);
+                        let mut needs_update =
+                            composite_instance_buffer_dirty || app.needs_update_command();
+                        if composite_renderer.update_backdrop_resources(app_system, &frame_target) {
+                            needs_update = true;
                         }
-                    }
-                    drop(mapped);
-
-                    let composite_instance_buffer_dirty =
-                        core::mem::replace(&mut composite_instance_buffer_dirty, false);
-                    let mut needs_update =
-                        composite_instance_buffer_dirty || app.needs_update_command();
-                    if composite_renderer.update_backdrop_resources(app_system, &sc) {
-                        needs_update = true;
-                    }
 
-                    if needs_update {
-                        let _pf = _pf.scoped(ProfileMarker::UpdateWorkSubmission);
+                        if needs_update {
+                            let _pf = _pf.scoped(ProfileMarker::UpdateWorkSubmission);
 
-                        if last_updating {
-                            last_update_command_fence.wait().unwrap();
-                            last_update_command_fence.reset().unwrap();
-                        }
+                            if last_updating {
+                                last_update_command_fence.wait().unwrap();
+                                last_update_command_fence.reset().unwrap();
+                            }
 
-                        let mut staging_scratch_buffers_locked = app_system.lock_staging_buffers();
-                        unsafe {
-                            update_cp.reset(br::CommandPoolResetFlags::EMPTY).unwrap();
-                        }
-                        unsafe { update_cb.begin(&br::CommandBufferBeginInfo::new()).unwrap() }
-                            .inject(|r| {
-                                if composite_instance_buffer_dirty {
-                                    app_system.composite_instance_manager.sync_buffer(r)
-                                } else {
-                                    r
-                                }
-                            })
-                            .inject(|r| {
-                                inject_cmd_pipeline_barrier_2(
-                                    r,
-                                    app_system.subsystem,
-                                    &br::DependencyInfo::new(
-                                        &[br::MemoryBarrier2::new()
-                                            .from(
-                                                br::PipelineStageFlags2::COPY,
-                                                br::AccessFlags2::TRANSFER.write,
-                                            )
-                                            .to(
-                                                br::PipelineStageFlags2::VERTEX_SHADER,
-                                                br::AccessFlags2::SHADER.read,
-                                            )],
+                            let mut staging_scratch_buffers_locked =
+                                app_system.lock_staging_buffers();
+                            unsafe {
+                                update_cp.reset(br::CommandPoolResetFlags::EMPTY).unwrap();
+                            }
+                            unsafe { update_cb.begin(&br::CommandBufferBeginInfo::new()).unwrap() }
+                                .inject(|r| {
+                                    app_loop::inject_update_commands(
+                                        r,
+                                        app_system,
+                                        &composite_renderer,
+                                        &app,
+                                        staging_scratch_buffers_locked.active_buffer(),
+                                        composite_instance_buffer_dirty,
+                                    )
+                                })
+                                .end()
+                                .unwrap();
+                            app_system
+                                .subsystem
+                                .submit_graphics_works(
+                                    &[br::SubmitInfo2::new(
                                         &[],
-                                        &[
-                                            // Note: 0番目はbackdropなしの番兵としてつかわれるので初期化しておく
-                                            br::ImageMemoryBarrier2::new(
-                                                composite_renderer.default_backdrop_buffer(),
-                                                br::ImageSubresourceRange::new(
-                                                    br::AspectMask::COLOR,
-                                                    0..1,
-                                                    0..1,
-                                                ),
-                                            )
-                                            .transit_to(
-                                                br::ImageLayout::ShaderReadOnlyOpt.from_undefined(),
-                                            ),
-                                        ],
-                                    ),
-                                )
-                            })
-                            .inject(|r| {
-                                app.editing_atlas_plane.process_dirty_data(
-                                    app_system.subsystem,
-                                    &staging_scratch_buffers_locked.active_buffer(),
-                                    r,
+                                        &[br::CommandBufferSubmitInfo::new(&update_cb)],
+                                        &[],
+                                    )],
+                                    Some(last_update_command_fence.as_transparent_ref_mut()),
                                 )
-                            })
-                            .end()
-                            .unwrap();
-                        app_system
-                            .subsystem
-                            .submit_graphics_works(
-                                &[br::SubmitInfo2::new(
-                                    &[],
-                                    &[br::CommandBufferSubmitInfo::new(&update_cb)],
-                                    &[],
-                                )],
-                                Some(last_update_command_fence.as_transparent_ref_mut()),
-                            )
-                            .unwrap();
+                                .unwrap();
 
-                        staging_scratch_buffers_locked.flip_next_and_ready();
-                        last_updating = true;
-                    }
+                            staging_scratch_buffers_locked.flip_next_and_ready();
+                            last_updating = true;
+                        }
 
-                    if main_cb_invalid {
-                        let _pf = _pf.scoped(ProfileMarker::MainCommandBufferPopulation);
+                        if main_cb_invalid {
+                            let _pf = _pf.scoped(ProfileMarker::MainCommandBufferPopulation);
 
-                        for (n, cb) in main_cbs.iter_mut().enumerate() {
-                            unsafe { cb.begin(&br::CommandBufferBeginInfo::new()).unwrap() }
+                            {
+                                unsafe {
+                                    main_cb.begin(&br::CommandBufferBeginInfo::new()).unwrap()
+                                }
                                 .inject(|r| {
                                     composite_renderer.populate_commands(
                                         r,
                                         &last_composite_render_instructions,
                                         sc.size,
-                                        &sc.backbuffer_image(n),
-                                        n,
+                                        &frame_target.backbuffer_image(0),
+                                        0,
+                                        None,
                                         |token, r| {
-                                            app.editing_atlas_plane
-                                                .handle_custom_render(&token, sc.size, r)
+                                            app.handle_custom_render(app_system, token, sc.size, r)
                                         },
                                     )
                                 })
@@ -1607,234 +1823,341 @@ fn app_main<'sys, 'event_bus, 'subsystem>(
                                 })
                                 .end()
                                 .unwrap();
+                            }
+
+                            main_cb_invalid = false;
                         }
 
-                        main_cb_invalid = false;
-                    }
+                        if present_cbs_invalid {
+                            for (n, cb) in present_cbs.iter_mut().enumerate() {
+                                frame_target
+                                    .inject_cmd_copy_to(
+                                        unsafe {
+                                            cb.begin(&br::CommandBufferBeginInfo::new()).unwrap()
+                                        },
+                                        app_system.subsystem,
+                                        &sc.backbuffer_image(n),
+                                        br::ImageLayout::PresentSrc,
+                                    )
+                                    .end()
+                                    .unwrap();
+                            }
 
-                    _pf.record(
-                        ProfileMarker::RenderWorkSubmission,
-                        ProfileMarkerCategory::Begin,
-                    );
-                    let next = match sc.acquire_next(
-                        None,
-                        br::CompletionHandlerMut::Queue(
-                            acquire_completion.as_transparent_ref_mut(),
-                        ),
-                    ) {
-                        Ok(x) => x,
-                        Err(e) if e == br::vk::VK_ERROR_OUT_OF_DATE_KHR => {
-                            tracing::warn!("swapchain out of date");
-                            // force recreate resources
-                            newsize_request = Some(app_shell.client_size_pixels());
-                            continue;
-                        }
-                        Err(e) => {
-                            tracing::error!(reason = ?e, "vkAcquireNextImageKHR failed");
-                            std::process::abort();
+                            present_cbs_invalid = false;
                         }
-                    };
-                    app_system
-                        .subsystem
-                        .submit_graphics_works(
-                            &[br::SubmitInfo2::new(
-                                &[br::SemaphoreSubmitInfo::new(&acquire_completion)
-                                    .on_color_attachment_output()],
-                                &[br::CommandBufferSubmitInfo::new(&main_cbs[next as usize])],
-                                &[br::SemaphoreSubmitInfo::new(
-                                    &render_completion_per_backbuffer[next as usize],
-                                )
-                                .on_color_attachment_output()],
-                            )],
-                            Some(last_render_command_fence.as_transparent_ref_mut()),
-                        )
-                        .unwrap();
-                    last_rendering = true;
-                    let mut results = [br::vk::VkResult(0)];
-                    match app_system.subsystem.queue_present(&br::PresentInfo::new(
-                        &[render_completion_per_backbuffer[next as usize].as_transparent_ref()],
-                        &[sc.as_transparent_ref()],
-                        &[next],
-                        &mut results,
-                    )) {
-                        Ok(_) => (),
-                        Err(e) if e == br::vk::VK_ERROR_OUT_OF_DATE_KHR => {
-                            tracing::warn!(?results, "swapchain out of date");
-                            // force recreate resources
-                            newsize_request = Some(app_shell.client_size_pixels());
-                            continue;
+
+                        _pf.record(
+                            ProfileMarker::RenderWorkSubmission,
+                            ProfileMarkerCategory::Begin,
+                        );
+                        let next = match sc.acquire_next(
+                            None,
+                            br::CompletionHandlerMut::Queue(
+                                acquire_completion.as_transparent_ref_mut(),
+                            ),
+                        ) {
+                            Ok(x) => x,
+                            Err(e) if e == br::vk::VK_ERROR_OUT_OF_DATE_KHR => {
+                                tracing::warn!("swapchain out of date");
+                                // force recreate resources
+                                newsize_request = Some(app_shell.client_size_pixels());
+                                #[cfg(target_os = "linux")]
+                                {
+                                    redraw_required = true;
+                                }
+                                continue;
+                            }
+                            Err(e) => {
+                                tracing::error!(reason = ?e, "vkAcquireNextImageKHR failed");
+                                std::process::abort();
+                            }
+                        };
+
+                        let damage = frame_target_damage.take();
+                        let corner_cutout_active =
+                            corner_cutout_renderer.is_some() && !app_shell.is_tiled();
+                        let render_cb = match damage {
+                            // 何も変わっていないので前回の内容をそのまま出す
+                            None => None,
+                            Some(d)
+                                if d.extent.width == sc.size.width
+                                    && d.extent.height == sc.size.height =>
+                            {
+                                Some(&main_cb)
+                            }
+                            // 角のカットアウトは描画済みの内容に重ねて適用すると二重にかかってしまうので全体を描き直す
+                            Some(d)
+                                if corner_cutout_active
+                                    && WindowCornerCutoutRenderer::cutout_rects(sc.size)
+                                        .iter()
+                                        .any(|c| rect_overlaps(c, &d)) =>
+                            {
+                                Some(&main_cb)
+                            }
+                            Some(d) => {
+                                let _pf = _pf.scoped(ProfileMarker::MainCommandBufferPopulation);
+
+                                unsafe {
+                                    partial_cp.reset(br::CommandPoolResetFlags::EMPTY).unwrap();
+                                }
+                                unsafe {
+                                    partial_cb
+                                        .begin(&br::CommandBufferBeginInfo::new())
+                                        .unwrap()
+                                }
+                                .inject(|r| {
+                                    composite_renderer.populate_commands(
+                                        r,
+                                        &last_composite_render_instructions,
+                                        sc.size,
+                                        &frame_target.backbuffer_image(0),
+                                        0,
+                                        Some(d),
+                                        |token, r| {
+                                            if !app_system
+                                                .composite_tree
+                                                .custom_render_bounds(token)
+                                                .is_some_and(|b| rect_overlaps(&b, &d))
+                                            {
+                                                // damageと重ならないなら描く必要はない
+                                                return r;
+                                            }
+
+                                            app.handle_custom_render(app_system, token, sc.size, r)
+                                        },
+                                    )
+                                })
+                                .inject(|r| {
+                                    inject_cmd_end_render_pass2(
+                                        r,
+                                        app_system.subsystem,
+                                        &br::SubpassEndInfo::new(),
+                                    )
+                                })
+                                .end()
+                                .unwrap();
+
+                                Some(&partial_cb)
+                            }
+                        };
+                        app_system
+                            .subsystem
+                            .submit_graphics_works(
+                                &[br::SubmitInfo2::new(
+                                    &[br::SemaphoreSubmitInfo::new(&acquire_completion)
+                                        .on_color_attachment_output()],
+                                    // Note: 何も変わっていなくてもバックバッファの中身は不定なので、必ず描き終わった内容を写してから出す
+                                    &render_cb
+                                        .into_iter()
+                                        .chain(core::iter::once(&present_cbs[next as usize]))
+                                        .map(|cb| br::CommandBufferSubmitInfo::new(cb))
+                                        .collect::<Vec<_>>(),
+                                    &[br::SemaphoreSubmitInfo::new(
+                                        &render_completion_per_backbuffer[next as usize],
+                                    )
+                                    .on_color_attachment_output()],
+                                )],
+                                Some(last_render_command_fence.as_transparent_ref_mut()),
+                            )
+                            .unwrap();
+                        last_rendering = true;
+                        #[cfg(target_os = "linux")]
+                        {
+                            let next_change_sec = app_system.composite_tree.next_change_sec();
+                            if next_change_sec.is_some_and(|x| x <= current_sec)
+                                || app.needs_update_command()
+                            {
+                                // アニメーション中なので次のフレームも描く（frameコールバックはこのpresentのcommitにのせる）
+                                app_shell.request_next_frame();
+                                frame_pending = true;
+                                next_animation_wakeup = None;
+                            } else {
+                                // 静止しているのでframeコールバックは要求せず、次のアニメーションの開始まで寝る
+                                next_animation_wakeup = next_change_sec
+                                    .map(|x| t + std::time::Duration::from_secs_f32(x));
+                            }
                         }
-                        Err(e) => {
-                            tracing::error!(reason = ?e, "vkQueuePresentKHR failed");
-                            std::process::abort();
+                        // Note: 矩形が0個だと全体が変化した扱いになるので、変化がないときは大きさ0の矩形を渡す
+                        let present_damage = unpresented_damage.take().unwrap_or(
+                            br::Extent2D {
+                                width: 0,
+                                height: 0,
+                            }
+                            .into_rect(br::Offset2D::ZERO),
+                        );
+                        let present_damage_rect = br::vk::VkRectLayerKHR {
+                            offset: present_damage.offset,
+                            extent: present_damage.extent,
+                            layer: 0,
+                        };
+                        let present_region = br::vk::VkPresentRegionKHR {
+                            rectangleCount: 1,
+                            pRectangles: &present_damage_rect,
+                        };
+                        let present_regions = br::vk::VkPresentRegionsKHR {
+                            sType: <br::vk::VkPresentRegionsKHR as br::TypedVulkanStructure>::TYPE,
+                            pNext: core::ptr::null(),
+                            swapchainCount: 1,
+                            pRegions: &present_region,
+                        };
+                        let mut results = [br::vk::VkResult(0)];
+                        let present_info =
+                            br::PresentInfo::new(
+                                &[render_completion_per_backbuffer[next as usize]
+                                    .as_transparent_ref()],
+                                &[sc.as_transparent_ref()],
+                                &[next],
+                                &mut results,
+                            );
+                        let present_info = if app_system.subsystem.supports_incremental_present() {
+                            present_info.with_next(&present_regions)
+                        } else {
+                            present_info
+                        };
+                        match app_system.subsystem.queue_present(&present_info) {
+                            Ok(_) => (),
+                            Err(e) if e == br::vk::VK_ERROR_OUT_OF_DATE_KHR => {
+                                tracing::warn!(?results, "swapchain out of date");
+                                // force recreate resources
+                                newsize_request = Some(app_shell.client_size_pixels());
+                                #[cfg(target_os = "linux")]
+                                {
+                                    // commitされていないframeコールバックは当てにしない
+                                    frame_pending = false;
+                                    redraw_required = true;
+                                }
+                                continue;
+                            }
+                            Err(e) => {
+                                tracing::error!(reason = ?e, "vkQueuePresentKHR failed");
+                                std::process::abort();
+                            }
                         }
-                    }
-                    _pf.record(
-                        ProfileMarker::RenderWorkSubmission,
-                        ProfileMarkerCategory::End,
-                    );
+                        _pf.record(
+                            ProfileMarker::RenderWorkSubmission,
+                            ProfileMarkerCategory::End,
+                        );
 
-                    app_shell.request_next_frame();
-                }
-                AppEvent::ToplevelWindowNewSize {
-                    width_px,
-                    height_px,
-                } => {
-                    if sc.size.width != width_px || sc.size.height != height_px {
-                        newsize_request = Some((width_px, height_px));
+                        #[cfg(not(target_os = "linux"))]
+                        app_shell.request_next_frame();
                     }
-                }
-                AppEvent::MainWindowPointerMove {
-                    surface_x,
-                    surface_y,
-                } => {
-                    app_update_context.ui_scale_factor = app_shell.ui_scale_factor();
-
-                    unsafe { &mut *app_shell.pointer_input_manager().get() }.handle_mouse_move(
-                        surface_x,
-                        surface_y,
-                        &mut app_system.hit_tree,
-                        &mut app_update_context,
-                        HitTestTreeManager::ROOT,
-                    );
-                    app_shell.set_cursor_shape(
-                        unsafe { &mut *app_shell.pointer_input_manager().get() }
-                            .cursor_shape(&mut app_system.hit_tree, &mut app_update_context),
-                    );
-                }
-                AppEvent::MainWindowPointerLeftDown => {
-                    app_update_context.ui_scale_factor = app_shell.ui_scale_factor();
+                    AppEvent::ToplevelWindowNewSize {
+                        width_px,
+                        height_px,
+                    } => {
+                        if sc.size.width != width_px || sc.size.height != height_px {
+                            newsize_request = Some((width_px, height_px));
+                        }
 
-                    unsafe { &mut *app_shell.pointer_input_manager().get() }
-                        .handle_mouse_left_down(
-                            &app_shell,
-                            &mut app_system.hit_tree,
-                            &mut app_update_context,
-                            HitTestTreeManager::ROOT,
-                            &mut app_system.keyboard_focus_manager,
-                        );
-                    app_shell.set_cursor_shape(
-                        unsafe { &mut *app_shell.pointer_input_manager().get() }
-                            .cursor_shape(&mut app_system.hit_tree, &mut app_update_context),
-                    );
-                }
-                AppEvent::MainWindowPointerLeftUp => {
-                    app_update_context.ui_scale_factor = app_shell.ui_scale_factor();
-
-                    unsafe { &mut *app_shell.pointer_input_manager().get() }.handle_mouse_left_up(
-                        &app_shell,
-                        &mut app_system.hit_tree,
-                        &mut app_update_context,
-                        HitTestTreeManager::ROOT,
-                    );
-                    app_shell.set_cursor_shape(
-                        unsafe { &mut *app_shell.pointer_input_manager().get() }
-                            .cursor_shape(&mut app_system.hit_tree, &mut app_update_context),
-                    );
-                }
-                AppEvent::UIMessageDialogRequest { content } => {
-                    popup_manager.spawn::<uikit::message_dialog::Presenter>(
-                        &mut PresenterInitContext {
-                            for_view: ViewInitContext {
-                                base_system: app_system,
-                                ui_scale_factor: active_ui_scale,
-                            },
-                            app_state: &mut *app_state.borrow_mut(),
-                        },
-                        t.elapsed().as_secs_f32(),
-                        &content,
-                    );
-                    unsafe { &mut *app_shell.pointer_input_manager().get() }.recompute_enter_leave(
-                        &mut app_system.hit_tree,
-                        &mut app_update_context,
-                        HitTestTreeManager::ROOT,
-                    );
-                }
-                AppEvent::UIPopupClose { id } => {
-                    popup_manager.close(app_system, t.elapsed().as_secs_f32(), &id);
-                    unsafe { &mut *app_shell.pointer_input_manager().get() }.recompute_enter_leave(
-                        &mut app_system.hit_tree,
-                        &mut app_update_context,
-                        HitTestTreeManager::ROOT,
-                    );
-                }
-                AppEvent::UIPopupUnmount { id } => {
-                    popup_manager.remove(app_system, &id);
-                }
-                AppEvent::AppMenuToggle => {
-                    app_state.borrow_mut().toggle_menu();
-                }
-                AppEvent::AppMenuRequestAddSprite => {
-                    task_worker
-                        .spawn(app_menu_on_add_sprite(
-                            syslink, app_shell, events, app_state,
-                        ))
-                        .detach();
-                }
-                AppEvent::AppMenuRequestOpen => {
-                    task_worker
-                        .spawn(app_menu_on_open(syslink, app_shell, app_state, events))
-                        .detach();
-                }
-                AppEvent::AppMenuRequestSave => {
-                    task_worker
-                        .spawn(app_menu_on_save(syslink, app_shell, app_state, events))
-                        .detach();
-                }
-                AppEvent::AppMenuRequestAutoArrange => {
-                    popup_manager.spawn::<feature::auto_arrange_settings::Presenter>(
-                        &mut PresenterInitContext {
-                            for_view: ViewInitContext {
-                                base_system: app_system,
-                                ui_scale_factor: active_ui_scale,
-                            },
-                            app_state: &mut *app_state.borrow_mut(),
-                        },
-                        t.elapsed().as_secs_f32(),
-                        (),
-                    );
-                    unsafe { &mut *app_shell.pointer_input_manager().get() }.recompute_enter_leave(
-                        &mut app_system.hit_tree,
-                        &mut app_update_context,
-                        HitTestTreeManager::ROOT,
-                    );
-                }
-                AppEvent::BeginBackgroundWork {
-                    thread_number,
-                    message,
-                } => {
-                    tracing::trace!(thread_number, message, "TODO: BeginBackgroundWork");
-                }
-                AppEvent::EndBackgroundWork { thread_number } => {
-                    tracing::trace!(thread_number, "TODO: EndBackgroundWork");
-                }
-                AppEvent::SelectSprite { index } => {
-                    app_state.borrow_mut().select_sprite(index);
-                }
-                AppEvent::DeselectSprite => {
-                    app_state.borrow_mut().deselect_sprite();
-                }
-                AppEvent::AddSpritesByUriList(uris) => {
-                    app_state.borrow_mut().add_sprites_by_uri_list(uris);
-                }
-                AppEvent::AddSpriteByPathList(paths) => {
-                    app_state.borrow_mut().add_sprites_from_file_paths(paths);
-                }
-                AppEvent::UIShowDragAndDropOverlay => {
-                    app.dnd_overlay.show(app_system, t.elapsed().as_secs_f32());
-                }
-                AppEvent::UIHideDragAndDropOverlay => {
-                    app.dnd_overlay.hide(app_system, t.elapsed().as_secs_f32());
-                }
-                AppEvent::MainWindowTiledStateChanged { is_tiled } => {
-                    app.app_header.on_shell_tiling_changed(app_system, is_tiled);
+                        let (w, h) = app_shell.client_size();
+                        app_system.settings.window_size = Some((w.round() as _, h.round() as _));
+                    }
+                    AppEvent::UICopyText(text) => {
+                        app_shell.set_clipboard_text(text);
+                    }
+                    AppEvent::UIPasteText => {
+                        if let Some(text) = app_shell.clipboard_text() {
+                            app_system.keyboard_focus_manager.dispatch_text_input(
+                                &app_system.hit_tree,
+                                &mut app_update_context,
+                                &input::TextInputEvent::Commit(text),
+                            );
+                        }
+                    }
+                    AppEvent::UICopySelectedSprites => {
+                        copy_selected_sprites(app_shell, &app_state.borrow(), events);
+                    }
+                    AppEvent::UIPasteSprites => {
+                        // Note: ファイルマネージャーからのコピーは画像も一緒に来ることがあるので、元のファイルを参照できるほうを優先する
+                        if !app_shell.paste_clipboard_files() {
+                            match app_shell.clipboard_png() {
+                                Some(png) => {
+                                    if let Err(e) = app_state
+                                        .borrow_mut()
+                                        .add_sprite_from_image_bytes("pasted", &png)
+                                    {
+                                        events.push(AppEvent::UIMessageDialogRequest(
+                                            DialogRequest::error("Pasting image failed", e),
+                                        ));
+                                    }
+                                }
+                                None => tracing::debug!("nothing to paste as sprites"),
+                            }
+                        }
+                    }
+                    AppEvent::AppMenuRequestAddSprite => {
+                        task_worker
+                            .spawn(app_menu_on_add_sprite(
+                                syslink, app_shell, events, app_state,
+                            ))
+                            .detach();
+                    }
+                    AppEvent::AppMenuRequestOpen => {
+                        task_worker
+                            .spawn(app_menu_on_open(syslink, app_shell, app_state, events))
+                            .detach();
+                    }
+                    AppEvent::AppMenuRequestSave => {
+                        task_worker
+                            .spawn(app_menu_on_save(syslink, app_shell, app_state, events))
+                            .detach();
+                    }
+                    AppEvent::AppMenuRequestOpenRecent(path) => {
+                        task_worker
+                            .spawn(app_menu_on_open_recent(
+                                syslink, app_shell, app_state, events, path,
+                            ))
+                            .detach();
+                    }
+                    AppEvent::OpenFile { path, completion } => {
+                        task_worker
+                            .spawn(automation_on_open(
+                                syslink, app_shell, app_state, events, path, completion,
+                            ))
+                            .detach();
+                    }
+                    AppEvent::AddSpritesByUriList(uris) => {
+                        let rejected = app_state.borrow_mut().add_sprites_by_uri_list(uris);
+                        report_rejected_sprite_sources(events, rejected);
+                        // Note: サンドボックス内ではドロップされたファイルもDocuments portal越しに渡ってくる
+                        task_worker
+                            .spawn(refresh_document_path_mapping(syslink, app_state))
+                            .detach();
+                    }
+                    // ほかはapp_loop::process_common_eventで処理済み
+                    _ => (),
                 }
             }
             app_update_context.event_queue.notify_clear().unwrap();
         }
+
+        app_update_context.ui_scale_factor = app_shell.ui_scale_factor();
+        app_system
+            .keyboard_focus_manager
+            .dispatch_focus_changes(&app_system.hit_tree, &mut app_update_context);
+
+        // フォーカスの移動やキャレットの移動をIMEに伝える
+        let (client_width, client_height) = app_shell.client_size();
+        app_shell.set_text_input_target(app_system.keyboard_focus_manager.text_input_target(
+            &app_system.hit_tree,
+            client_width,
+            client_height,
+        ));
+
+        #[cfg(target_os = "linux")]
+        if !frame_pending
+            && (redraw_required
+                || app_system.composite_tree.take_dirty()
+                || app.needs_update_command())
+        {
+            // 静止中に変化があったのですぐに描く
+            events.push(AppEvent::ToplevelWindowFrameTiming);
+            frame_pending = true;
+        }
     }
 
     _profiler.flush();
+    app_system.settings.recent_files = app_state.borrow().recent_files().to_vec();
 
     if let Err(e) = unsafe { app_system.subsystem.wait() } {
         tracing::warn!(reason = ?e, "Error in waiting pending works before shutdown");
@@ -1847,17 +2170,19 @@ async fn app_menu_on_add_sprite<'subsystem>(
     events: &AppEventBus,
     app_state: &RefCell<AppState<'subsystem>>,
 ) {
-    let added_paths = match syslink.select_sprite_files(shell).await {
+    let added_paths = match syslink.select_sprite_files(shell, events).await {
         Ok(x) => x,
         Err(e) => {
             e.ui_feedback(events);
             return;
         }
     };
+    refresh_document_path_mapping(syslink, app_state).await;
 
-    app_state
+    let rejected = app_state
         .borrow_mut()
         .add_sprites_from_file_paths(added_paths);
+    report_rejected_sprite_sources(events, rejected);
 }
 
 async fn app_menu_on_open<'sys, 'subsystem>(
@@ -1866,7 +2191,12 @@ async fn app_menu_on_open<'sys, 'subsystem>(
     app_state: &'sys RefCell<AppState<'subsystem>>,
     event_bus: &AppEventBus,
 ) {
-    let path = match syslink.select_open_file(shell).await {
+    if !confirm_unsaved_changes(syslink, shell, app_state, event_bus, "opening another file").await
+    {
+        return;
+    }
+
+    let path = match syslink.select_open_file(shell, event_bus).await {
         Ok(Some(x)) => x,
         Ok(None) => return,
         Err(e) => {
@@ -1874,11 +2204,124 @@ async fn app_menu_on_open<'sys, 'subsystem>(
             return;
         }
     };
+    open_asset(syslink, shell, app_state, event_bus, &path, None).await;
+}
 
-    if app_state.borrow_mut().load(&path).is_err() {
-        event_bus.push(AppEvent::UIMessageDialogRequest {
-            content: "Opening failed".into(),
-        });
+async fn app_menu_on_open_recent<'sys, 'subsystem>(
+    syslink: &'sys SystemLink,
+    shell: &'sys AppShell<'_, 'subsystem>,
+    app_state: &'sys RefCell<AppState<'subsystem>>,
+    event_bus: &AppEventBus,
+    path: std::path::PathBuf,
+) {
+    if !confirm_unsaved_changes(syslink, shell, app_state, event_bus, "opening another file").await
+    {
+        return;
+    }
+    open_asset(syslink, shell, app_state, event_bus, &path, None).await;
+}
+
+/// 自動操作(D-Bus)からの要求で開く
+///
+/// Note: 呼び出し元は確認ダイアログに答えられないので、未保存の変更があるときは捨てずに失敗を返す
+async fn automation_on_open<'sys, 'subsystem>(
+    syslink: &'sys SystemLink,
+    shell: &'sys AppShell<'_, 'subsystem>,
+    app_state: &'sys RefCell<AppState<'subsystem>>,
+    event_bus: &AppEventBus,
+    path: std::path::PathBuf,
+    completion: Option<AppEventCompletion>,
+) {
+    if app_state.borrow().is_modified() {
+        complete_event(
+            event_bus,
+            completion,
+            "Opening failed",
+            Err(String::from("the current atlas has unsaved changes")),
+        );
+        return;
+    }
+
+    open_asset(syslink, shell, app_state, event_bus, &path, completion).await;
+}
+
+/// completionがあれば読み込みの結果を渡し、なければ失敗したときだけダイアログで知らせる
+async fn open_asset<'sys, 'subsystem>(
+    syslink: &'sys SystemLink,
+    shell: &'sys AppShell<'_, 'subsystem>,
+    app_state: &'sys RefCell<AppState<'subsystem>>,
+    event_bus: &AppEventBus,
+    path: &std::path::Path,
+    completion: Option<AppEventCompletion>,
+) {
+    refresh_document_path_mapping(syslink, app_state).await;
+
+    let r = app_state.borrow_mut().load(path).map_err(|e| e.to_string());
+    let loaded = r.is_ok();
+    complete_event(event_bus, completion, "Opening failed", r);
+    if !loaded {
+        return;
+    }
+
+    relink_missing_sprite_sources(syslink, shell, app_state, event_bus).await;
+}
+
+/// ソース画像が見つからないスプライトがあれば、探す場所を選んでもらってつなぎ直す
+async fn relink_missing_sprite_sources<'sys, 'subsystem>(
+    syslink: &'sys SystemLink,
+    shell: &'sys AppShell<'_, 'subsystem>,
+    app_state: &'sys RefCell<AppState<'subsystem>>,
+    event_bus: &AppEventBus,
+) {
+    let missing = app_state.borrow().missing_source_count();
+    if missing == 0 {
+        return;
+    }
+
+    const LOCATE: usize = 1;
+    match DialogRequest::message(format!(
+        "{missing} sprite source file(s) could not be found. Choose a folder to search them by file name."
+    ))
+    .with_title("Missing source files")
+    .with_icon(DialogIcon::Warning)
+    .with_buttons(&["Ignore", "Locate..."])
+    .request(event_bus)
+    .await
+    {
+        Some(LOCATE) => (),
+        _ => return,
+    }
+
+    let folder = match syslink.select_folder(shell, event_bus).await {
+        Ok(Some(x)) => x,
+        Ok(None) => return,
+        Err(e) => {
+            e.ui_feedback(event_bus);
+            return;
+        }
+    };
+    refresh_document_path_mapping(syslink, app_state).await;
+
+    let relinked = app_state.borrow_mut().relink_missing_sources(&folder);
+    let remaining = app_state.borrow().missing_source_count();
+    if remaining > 0 {
+        event_bus.push(AppEvent::UIMessageDialogRequest(
+            DialogRequest::message(format!(
+                "{relinked} sprite source file(s) were relinked, but {remaining} could not be found in the folder."
+            ))
+            .with_title("Missing source files")
+            .with_icon(DialogIcon::Warning),
+        ));
+    }
+}
+
+/// Documents portal越しに渡されたパスを保存時にホストのパスへ戻せるよう、対応を取り直す
+async fn refresh_document_path_mapping<'subsystem>(
+    syslink: &SystemLink,
+    app_state: &RefCell<AppState<'subsystem>>,
+) {
+    if let Some(m) = syslink.document_path_mapping().await {
+        app_state.borrow_mut().set_document_path_mapping(m);
     }
 }
 
@@ -1888,7 +2331,7 @@ async fn app_menu_on_save<'sys, 'subsystem>(
     app_state: &'sys RefCell<AppState<'subsystem>>,
     event_bus: &AppEventBus,
 ) {
-    let path = match syslink.select_save_file(shell).await {
+    let path = match syslink.select_save_file(shell, event_bus).await {
         Ok(Some(x)) => x,
         Ok(None) => return,
         Err(e) => {
@@ -1896,11 +2339,178 @@ async fn app_menu_on_save<'sys, 'subsystem>(
             return;
         }
     };
+    refresh_document_path_mapping(syslink, app_state).await;
 
-    if app_state.borrow_mut().save(&path).is_err() {
-        event_bus.push(AppEvent::UIMessageDialogRequest {
-            content: "Saving failed".into(),
-        });
+    save_to(app_state, event_bus, &path);
+}
+
+/// スプライトとして追加できなかったものをまとめてダイアログで知らせる
+fn report_rejected_sprite_sources(events: &AppEventBus, rejected: Vec<RejectedSpriteSource>) {
+    // Note: 大量にあるとダイアログに収まらないので先頭のいくつかだけ並べる
+    const MAX_LISTED: usize = 8;
+
+    if rejected.is_empty() {
+        return;
+    }
+
+    let mut body = rejected
+        .iter()
+        .take(MAX_LISTED)
+        .map(|x| format!("{}: {}", x.label, x.reason))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if rejected.len() > MAX_LISTED {
+        body.push_str(&format!("\n...and {} more", rejected.len() - MAX_LISTED));
+    }
+
+    events.push(AppEvent::UIMessageDialogRequest(
+        DialogRequest::message(body)
+            .with_title(match rejected.len() {
+                1 => String::from("1 item could not be added"),
+                n => format!("{n} items could not be added"),
+            })
+            .with_icon(DialogIcon::Warning),
+    ));
+}
+
+/// 選択中のスプライトをクリップボードに置く
+///
+/// 画像は先頭のスプライトのソース画像をそのまま使い、テキストは表計算ソフトなどに貼れるように
+/// 選択中のすべての矩形を1行ずつタブ区切りで並べる(名前, x, y, 幅, 高さ)
+fn copy_selected_sprites(shell: &AppShell<'_, '_>, app_state: &AppState, events: &AppEventBus) {
+    let selected = app_state
+        .selected_sprites_with_index()
+        .map(|(_, x)| x)
+        .collect::<Vec<_>>();
+    let [first, ..] = &selected[..] else {
+        return;
+    };
+
+    let png = match std::fs::read(&first.source_path) {
+        Ok(x) => x,
+        Err(e) => {
+            tracing::warn!(path = ?first.source_path, reason = ?e, "Failed to read sprite source");
+            events.push(AppEvent::UIMessageDialogRequest(DialogRequest::error(
+                "Copying sprite failed",
+                format!("{}: {e}", first.source_path.display()),
+            )));
+            return;
+        }
+    };
+    let text = selected
+        .iter()
+        .map(|x| {
+            format!(
+                "{}\t{}\t{}\t{}\t{}",
+                x.name, x.left, x.top, x.width, x.height
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    shell.set_clipboard_image(png, text);
+}
+
+/// completionがあれば結果を渡し、なければ失敗したときだけダイアログで知らせる
+fn complete_event(
+    event_bus: &AppEventBus,
+    completion: Option<AppEventCompletion>,
+    error_title: &str,
+    result: Result<(), String>,
+) {
+    match (completion, result) {
+        (Some(c), r) => c(r),
+        (None, Ok(())) => (),
+        (None, Err(e)) => event_bus.push(AppEvent::UIMessageDialogRequest(DialogRequest::error(
+            error_title,
+            e,
+        ))),
+    }
+}
+
+/// 保存できたらtrue(失敗したときはダイアログで知らせる)
+fn save_to(app_state: &RefCell<AppState>, event_bus: &AppEventBus, path: &std::path::Path) -> bool {
+    if let Err(e) = app_state.borrow_mut().save(path) {
+        event_bus.push(AppEvent::UIMessageDialogRequest(DialogRequest::error(
+            "Saving failed",
+            e,
+        )));
+        return false;
+    }
+
+    true
+}
+
+/// 開いているファイルに上書き保存する(まだファイルがなければ保存先を選ぶ)
+///
+/// 保存できたらtrue
+async fn save_current<'sys, 'subsystem>(
+    syslink: &'sys SystemLink,
+    shell: &'sys AppShell<'_, 'subsystem>,
+    app_state: &'sys RefCell<AppState<'subsystem>>,
+    event_bus: &AppEventBus,
+) -> bool {
+    let current_path = app_state
+        .borrow()
+        .current_open_path()
+        .map(std::path::Path::to_path_buf);
+    let path = match current_path {
+        Some(x) => x,
+        None => match syslink.select_save_file(shell, event_bus).await {
+            Ok(Some(x)) => {
+                refresh_document_path_mapping(syslink, app_state).await;
+                x
+            }
+            Ok(None) => return false,
+            Err(e) => {
+                e.ui_feedback(event_bus);
+                return false;
+            }
+        },
+    };
+
+    save_to(app_state, event_bus, &path)
+}
+
+/// 保存されていない変更があれば、保存するかどうか聞く
+///
+/// そのまま続けてよい(保存できたか、変更を捨てることにした)ならtrue
+async fn confirm_unsaved_changes<'sys, 'subsystem>(
+    syslink: &'sys SystemLink,
+    shell: &'sys AppShell<'_, 'subsystem>,
+    app_state: &'sys RefCell<AppState<'subsystem>>,
+    event_bus: &AppEventBus,
+    action: &str,
+) -> bool {
+    if !app_state.borrow().is_modified() {
+        return true;
+    }
+
+    const DONT_SAVE: usize = 1;
+    const SAVE: usize = 2;
+    match DialogRequest::message(format!(
+        "Do you want to save changes before {action}? Unsaved changes will be lost."
+    ))
+    .with_title("Save changes?")
+    .with_icon(DialogIcon::Warning)
+    .with_buttons(&["Cancel", "Don't Save", "Save"])
+    .request(event_bus)
+    .await
+    {
+        Some(DONT_SAVE) => true,
+        Some(SAVE) => save_current(syslink, shell, app_state, event_bus).await,
+        _ => false,
+    }
+}
+
+async fn app_on_close_request<'sys, 'subsystem>(
+    syslink: &'sys SystemLink,
+    shell: &'sys AppShell<'_, 'subsystem>,
+    app_state: &'sys RefCell<AppState<'subsystem>>,
+    event_bus: &AppEventBus,
+) {
+    if confirm_unsaved_changes(syslink, shell, app_state, event_bus, "closing").await {
+        event_bus.push(AppEvent::ToplevelWindowForceClose);
     }
 }
 
@@ -1931,28 +2541,32 @@ impl DesktopPortal {
                     )
                 )
                 .await;
+            if let Some(e) = reply_msg.try_get_error() {
+                tracing::info!(reason = ?e, "Failed to introspect the portal object");
+                return None;
+            }
             let reply_iter = reply_msg.iter();
-            let doc = reply_iter
-                .try_get_cstr()
-                .expect("invalid introspection response")
-                .to_str()
-                .unwrap();
+            let Some(doc) = reply_iter.try_get_cstr().ok().and_then(|x| x.to_str().ok()) else {
+                tracing::warn!(signature = ?reply_msg.signature(), "malformed introspection response from the portal object");
+                return None;
+            };
 
             let mut has_file_chooser = false;
             if let Err(e) = dbus::introspect_document::read_toplevel(
                 &mut quick_xml::Reader::from_str(doc),
                 |_, ifname, r| {
-                    has_file_chooser = ifname.as_ref() == b"org.freedesktop.portal.FileChooser";
+                    has_file_chooser |= ifname.as_ref() == b"org.freedesktop.portal.FileChooser";
 
                     dbus::introspect_document::skip_read_interface_tag_contents(r)
                 },
             ) {
                 tracing::warn!(reason = ?e, "Failed to parse introspection document from portal object");
             }
+            if !has_file_chooser {
+                return None;
+            }
 
-            let version = match desktop_portal_proto::file_chooser::read_get_version_reply(
-                dbus.wait_for_reply(desktop_portal_proto::file_chooser::get_version(dbus.underlying())).await
-            ) {
+            let version = match DesktopPortal::file_chooser_proxy(dbus).version().await {
                 Ok(x) => x,
                 Err(e) => {
                     tracing::warn!(reason = ?e, "FileChooser get version failed, assuming v1");
@@ -1960,10 +2574,43 @@ impl DesktopPortal {
                 }
             };
 
-            has_file_chooser.then_some(DesktopPortalFileChooser { version })
+            Some(DesktopPortalFileChooser { version })
         }).await.as_ref()
     }
 
+    #[inline(always)]
+    pub const fn file_chooser_proxy(
+        dbus: &DBusLink,
+    ) -> desktop_portal_proto::file_chooser::Proxy<'_, DBusLink> {
+        desktop_portal_proto::file_chooser::Proxy::new(
+            dbus,
+            Some(c"org.freedesktop.portal.Desktop"),
+            c"/org/freedesktop/portal/desktop",
+        )
+    }
+
+    #[inline(always)]
+    pub const fn documents_proxy(
+        dbus: &DBusLink,
+    ) -> desktop_portal_proto::documents::Proxy<'_, DBusLink> {
+        desktop_portal_proto::documents::Proxy::new(
+            dbus,
+            Some(desktop_portal_proto::documents::BUS_NAME),
+            desktop_portal_proto::documents::OBJECT_PATH,
+        )
+    }
+
+    #[inline(always)]
+    pub const fn settings_proxy(
+        dbus: &DBusLink,
+    ) -> desktop_portal_proto::settings::Proxy<'_, DBusLink> {
+        desktop_portal_proto::settings::Proxy::new(
+            dbus,
+            Some(c"org.freedesktop.portal.Desktop"),
+            c"/org/freedesktop/portal/desktop",
+        )
+    }
+
     pub const fn open_request_object(
         path: desktop_portal_proto::ObjectPath,
     ) -> DesktopPortalRequestObject {
@@ -1995,15 +2642,13 @@ impl DesktopPortalFileChooser {
         options_builder: impl FnOnce(desktop_portal_proto::file_chooser::OpenFileOptionsAppender),
     ) -> Result<DesktopPortalRequestObject, dbus::Error> {
         Ok(DesktopPortal::open_request_object(
-            desktop_portal_proto::file_chooser::read_open_file_reply(
-                dbus.wait_for_reply(desktop_portal_proto::file_chooser::open_file(
-                    dbus.underlying(),
-                    parent_window,
-                    title,
-                    options_builder,
-                ))
-                .await,
-            )?,
+            DesktopPortal::file_chooser_proxy(dbus)
+                .open_file(parent_window.unwrap_or(c""), title, |options| {
+                    options_builder(
+                        desktop_portal_proto::file_chooser::OpenFileOptionsAppender::new(options),
+                    )
+                })
+                .await?,
         ))
     }
 
@@ -2016,15 +2661,13 @@ impl DesktopPortalFileChooser {
         options_builder: impl FnOnce(desktop_portal_proto::file_chooser::SaveFileOptionsAppender),
     ) -> Result<DesktopPortalRequestObject, dbus::Error> {
         Ok(DesktopPortal::open_request_object(
-            desktop_portal_proto::file_chooser::read_save_file_reply(
-                dbus.wait_for_reply(desktop_portal_proto::file_chooser::save_file(
-                    dbus.underlying(),
-                    parent_window,
-                    title,
-                    options_builder,
-                ))
-                .await,
-            )?,
+            DesktopPortal::file_chooser_proxy(dbus)
+                .save_file(parent_window.unwrap_or(c""), title, |options| {
+                    options_builder(
+                        desktop_portal_proto::file_chooser::SaveFileOptionsAppender::new(options),
+                    )
+                })
+                .await?,
         ))
     }
 }
@@ -2053,9 +2696,9 @@ impl DesktopPortalRequestObject {
         object_path.push_str(token);
 
         Self::new(unsafe {
-            core::mem::transmute(std::ffi::CString::from_vec_unchecked(
-                object_path.into_bytes(),
-            ))
+            desktop_portal_proto::ObjectPath::from_cstring_unchecked(
+                std::ffi::CString::from_vec_unchecked(object_path.into_bytes()),
+            )
         })
     }
 
@@ -2071,8 +2714,8 @@ impl DesktopPortalRequestObject {
     ) -> DBusWaitForSignalFuture<'link> {
         dbus.wait_for_signal(
             std::rc::Rc::from(self.0.as_c_str()),
-            std::rc::Rc::from(c"org.freedesktop.portal.Request"),
-            std::rc::Rc::from(c"Response"),
+            std::rc::Rc::from(desktop_portal_proto::request::INTERFACE),
+            std::rc::Rc::from(desktop_portal_proto::request::ResponseSignal::MEMBER),
         )
     }
 }
@@ -2087,10 +2730,11 @@ pub enum SystemLinkError {
 impl SystemLinkError {
     pub fn ui_feedback(self, events: &AppEventBus) {
         match self {
-            Self::UnrecoverableException(_) => {
-                events.push(AppEvent::UIMessageDialogRequest {
-                    content: "Operation failed".into(),
-                });
+            Self::UnrecoverableException(e) => {
+                events.push(AppEvent::UIMessageDialogRequest(DialogRequest::error(
+                    "Operation failed",
+                    e,
+                )));
             }
         }
     }
@@ -2119,12 +2763,13 @@ impl SystemLink {
 
     #[tracing::instrument(
         name = "SystemLink::select_sprite_files",
-        skip(self, for_shell),
+        skip(self, for_shell, _events),
         ret(Debug)
     )]
     pub async fn select_sprite_files(
         &self,
         for_shell: &AppShell<'_, '_>,
+        _events: &AppEventBus,
     ) -> Result<Vec<std::path::PathBuf>, SystemLinkError> {
         let picker = syslink_unrecoverable!(
             windows::Storage::Pickers::FileOpenPicker::new(),
@@ -2203,12 +2848,13 @@ impl SystemLink {
 
     #[tracing::instrument(
         name = "SystemLink::select_open_file",
-        skip(self, for_shell),
+        skip(self, for_shell, _events),
         ret(Debug)
     )]
     pub async fn select_open_file(
         &self,
         for_shell: &AppShell<'_, '_>,
+        _events: &AppEventBus,
     ) -> Result<Option<std::path::PathBuf>, SystemLinkError> {
         let picker = syslink_unrecoverable!(
             windows::Storage::Pickers::FileOpenPicker::new(),
@@ -2265,12 +2911,13 @@ impl SystemLink {
 
     #[tracing::instrument(
         name = "SystemLink::select_save_file",
-        skip(self, for_shell),
+        skip(self, for_shell, _events),
         ret(Debug)
     )]
     pub async fn select_save_file(
         &self,
         for_shell: &AppShell<'_, '_>,
+        _events: &AppEventBus,
     ) -> Result<Option<std::path::PathBuf>, SystemLinkError> {
         let picker = syslink_unrecoverable!(
             windows::Storage::Pickers::FileSavePicker::new(),
@@ -2320,36 +2967,107 @@ impl SystemLink {
                 .into(),
         ))
     }
+
+    #[tracing::instrument(
+        name = "SystemLink::select_folder",
+        skip(self, for_shell, _events),
+        ret(Debug)
+    )]
+    pub async fn select_folder(
+        &self,
+        for_shell: &AppShell<'_, '_>,
+        _events: &AppEventBus,
+    ) -> Result<Option<std::path::PathBuf>, SystemLinkError> {
+        let picker = syslink_unrecoverable!(
+            windows::Storage::Pickers::FolderPicker::new(),
+            "FolderPicker::new failed"
+        );
+        syslink_unrecoverable!(
+            unsafe {
+                syslink_unrecoverable!(
+                    windows::core::Interface::cast::<
+                        windows::Win32::UI::Shell::IInitializeWithWindow,
+                    >(&picker,),
+                    "querying IInitializeWithWindow failed"
+                )
+                .Initialize(for_shell.hwnd())
+            },
+            "picker initialization failed"
+        );
+
+        'try_set_filter: {
+            warn_bailout_scope!(
+                'try_set_filter,
+                warn_bailout_scope!(
+                    'try_set_filter,
+                    picker.FileTypeFilter(),
+                    "getting FileTypeFilter failed"
+                )
+                .Append(windows::core::h!("*")),
+                "appending filter failed"
+            );
+        }
+
+        let folder = match syslink_unrecoverable!(
+            picker.PickSingleFolderAsync(),
+            "PickSingleFolderAsync failed"
+        )
+        .await
+        {
+            Ok(x) => x,
+            Err(e) if e.code() == windows::Win32::Foundation::S_OK => {
+                tracing::warn!("Operation was cancelled");
+                return Ok(None);
+            }
+            Err(e) => {
+                tracing::error!(reason = ?e, "FolderPicker.PickSingleFolderAsync failed");
+                return Err(SystemLinkError::UnrecoverableException(e));
+            }
+        };
+        Ok(Some(
+            syslink_unrecoverable!(folder.Path(), "getting path failed")
+                .to_os_string()
+                .into(),
+        ))
+    }
+
+    /// サンドボックスがないのでパスはそのまま使える
+    pub async fn document_path_mapping(&self) -> Option<source_path::DocumentPathMapping> {
+        None
+    }
 }
 
 #[cfg(target_os = "linux")]
 #[derive(Debug, thiserror::Error)]
 pub enum SelectSpriteFilesError {
-    #[error("No org.freedesktop.portal.FileChooser found")]
-    NoFileChooser,
     #[error("FileChooser.OpenFile failed: {0:?}")]
     OpenFileFailed(dbus::Error),
     #[error("FileChooser.SaveFile failed: {0:?}")]
     SaveFileFailed(dbus::Error),
+    #[error("malformed Response signal from the portal")]
+    MalformedResponse,
 }
 #[cfg(target_os = "linux")]
 impl SelectSpriteFilesError {
     pub fn ui_feedback(self, events: &AppEventBus) {
         match self {
-            Self::NoFileChooser => {
-                events.push(AppEvent::UIMessageDialogRequest {
-                    content: "org.freedesktop.portal.FileChooser not found".into(),
-                });
+            Self::OpenFileFailed(e) => {
+                events.push(AppEvent::UIMessageDialogRequest(DialogRequest::error(
+                    "FileChooser.OpenFile failed",
+                    format!("{e:?}"),
+                )));
             }
-            Self::OpenFileFailed(_) => {
-                events.push(AppEvent::UIMessageDialogRequest {
-                    content: "FileChooser.OpenFile failed".into(),
-                });
+            Self::SaveFileFailed(e) => {
+                events.push(AppEvent::UIMessageDialogRequest(DialogRequest::error(
+                    "FileChooser.SaveFile failed",
+                    format!("{e:?}"),
+                )));
             }
-            Self::SaveFileFailed(_) => {
-                events.push(AppEvent::UIMessageDialogRequest {
-                    content: "FileChooser.SaveFile failed".into(),
-                });
+            Self::MalformedResponse => {
+                events.push(AppEvent::UIMessageDialogRequest(DialogRequest::error(
+                    "FileChooser failed",
+                    "the portal returned a malformed response",
+                )));
             }
         }
     }
@@ -2357,41 +3075,64 @@ impl SelectSpriteFilesError {
 
 #[cfg(target_os = "linux")]
 pub struct SystemLink {
-    dbus: DBusLink,
+    /// セッションバスにつながらなかったときはNone(ポータルを使う機能は内蔵の代替に切り替わる)
+    dbus: Option<DBusLink>,
     dp: DesktopPortal,
 }
 #[cfg(target_os = "linux")]
 impl SystemLink {
     pub fn new() -> Self {
+        let dbus = match DBusLink::new() {
+            Ok(x) => Some(x),
+            Err(e) => {
+                tracing::warn!(reason = ?e, "Failed to connect to the session bus, desktop portal features are disabled");
+                None
+            }
+        };
+
         Self {
-            dbus: DBusLink::new(),
+            dbus,
             dp: DesktopPortal::new(),
         }
     }
 
+    async fn file_chooser(&self) -> Option<(&DBusLink, &DesktopPortalFileChooser)> {
+        let dbus = self.dbus.as_ref()?;
+
+        Some((dbus, self.dp.try_get_file_chooser(dbus).await?))
+    }
+
     #[tracing::instrument(
         name = "SystemLink::select_sprite_files",
-        skip(self, for_shell),
+        skip(self, for_shell, events),
         ret(Debug)
     )]
     pub async fn select_sprite_files(
         &self,
         for_shell: &AppShell<'_, '_>,
+        events: &AppEventBus,
     ) -> Result<Vec<std::path::PathBuf>, SelectSpriteFilesError> {
-        let file_chooser = self
-            .dp
-            .try_get_file_chooser(&self.dbus)
-            .await
-            .ok_or(SelectSpriteFilesError::NoFileChooser)?;
+        let Some((dbus, file_chooser)) = self.file_chooser().await else {
+            tracing::info!("No FileChooser portal found, using the built-in file browser");
+            return Ok(
+                FileBrowserRequest::new(FileBrowserMode::OpenFiles, "Add Sprite")
+                    .with_filters([
+                        FileFilter::new("PNG images", &["png"]),
+                        FileFilter::all_files(),
+                    ])
+                    .request(events)
+                    .await
+                    .unwrap_or_default(),
+            );
+        };
 
         let dialog_token = uuid::Uuid::new_v4().as_simple().to_string();
-        let mut request_object =
-            DesktopPortal::open_request_object_for_token(&self.dbus, &dialog_token);
+        let mut request_object = DesktopPortal::open_request_object_for_token(dbus, &dialog_token);
 
         let exported_shell = for_shell.try_export_toplevel();
         let request_handle = file_chooser
             .open_file(
-                &self.dbus,
+                dbus,
                 exported_shell.as_ref().map(|x| x.handle.as_c_str()),
                 c"Add Sprite",
                 |mut options_appender| {
@@ -2431,18 +3172,19 @@ impl SystemLink {
             );
             request_object = request_handle;
         }
-        let resp = request_object.wait_for_response(&self.dbus).await;
+        let resp = request_object.wait_for_response(dbus).await;
         drop(exported_shell);
 
-        let mut resp_iter = resp.iter();
-        let response = desktop_portal_proto::RequestResponseCode::read(&resp_iter);
+        let Some(mut resp) = desktop_portal_proto::request::ResponseSignal::read(&resp) else {
+            return Err(SelectSpriteFilesError::MalformedResponse);
+        };
+        let response = desktop_portal_proto::RequestResponseCode::from(resp.response);
         if response != desktop_portal_proto::RequestResponseCode::Success {
             tracing::warn!(?response, "Operation was cancelled");
             return Ok(Vec::new());
         }
 
-        resp_iter.next();
-        let res = desktop_portal_proto::file_chooser::ResponseResults::read_all(&mut resp_iter);
+        let res = desktop_portal_proto::file_chooser::ResponseResults::read_all(&mut resp.results);
         Ok(res
             .uris
             .into_iter()
@@ -2456,27 +3198,30 @@ impl SystemLink {
 
     #[tracing::instrument(
         name = "SystemLink::select_open_file",
-        skip(self, for_shell),
+        skip(self, for_shell, events),
         ret(Debug)
     )]
     pub async fn select_open_file(
         &self,
         for_shell: &AppShell<'_, '_>,
+        events: &AppEventBus,
     ) -> Result<Option<std::path::PathBuf>, SelectSpriteFilesError> {
-        let file_chooser = self
-            .dp
-            .try_get_file_chooser(&self.dbus)
-            .await
-            .ok_or(SelectSpriteFilesError::NoFileChooser)?;
+        let Some((dbus, file_chooser)) = self.file_chooser().await else {
+            tracing::info!("No FileChooser portal found, using the built-in file browser");
+            return Ok(FileBrowserRequest::new(FileBrowserMode::OpenFile, "Open")
+                .with_filters([FileFilter::new("Peridot Sprite Atlas asset", &["psa"])])
+                .request(events)
+                .await
+                .and_then(|x| x.into_iter().next()));
+        };
 
         let dialog_token = uuid::Uuid::new_v4().as_simple().to_string();
-        let mut request_object =
-            DesktopPortal::open_request_object_for_token(&self.dbus, &dialog_token);
+        let mut request_object = DesktopPortal::open_request_object_for_token(dbus, &dialog_token);
 
         let exported_shell = for_shell.try_export_toplevel();
         let request_handle = file_chooser
             .open_file(
-                &self.dbus,
+                dbus,
                 exported_shell.as_ref().map(|x| x.handle.as_c_str()),
                 c"Open",
                 |mut options_appender| {
@@ -2502,18 +3247,19 @@ impl SystemLink {
             );
             request_object = request_handle;
         }
-        let resp = request_object.wait_for_response(&self.dbus).await;
+        let resp = request_object.wait_for_response(dbus).await;
         drop(exported_shell);
 
-        let mut resp_iter = resp.iter();
-        let response = desktop_portal_proto::RequestResponseCode::read(&resp_iter);
+        let Some(mut resp) = desktop_portal_proto::request::ResponseSignal::read(&resp) else {
+            return Err(SelectSpriteFilesError::MalformedResponse);
+        };
+        let response = desktop_portal_proto::RequestResponseCode::from(resp.response);
         if response != desktop_portal_proto::RequestResponseCode::Success {
             tracing::warn!(?response, "Operation was cancelled");
             return Ok(None);
         }
 
-        resp_iter.next();
-        let res = desktop_portal_proto::file_chooser::ResponseResults::read_all(&mut resp_iter);
+        let res = desktop_portal_proto::file_chooser::ResponseResults::read_all(&mut resp.results);
         match res.uris[..] {
             [] => Ok(None),
             [ref uri, ..] => Ok(Some(std::path::PathBuf::from(
@@ -2524,27 +3270,52 @@ impl SystemLink {
 
     #[tracing::instrument(
         name = "SystemLink::select_save_file",
-        skip(self, for_shell),
+        skip(self, for_shell, events),
         ret(Debug)
     )]
     pub async fn select_save_file(
         &self,
         for_shell: &AppShell<'_, '_>,
+        events: &AppEventBus,
     ) -> Result<Option<std::path::PathBuf>, SelectSpriteFilesError> {
-        let file_chooser = self
-            .dp
-            .try_get_file_chooser(&self.dbus)
-            .await
-            .ok_or(SelectSpriteFilesError::NoFileChooser)?;
+        let Some((dbus, file_chooser)) = self.file_chooser().await else {
+            tracing::info!("No FileChooser portal found, using the built-in file browser");
+            let Some(path) = FileBrowserRequest::new(FileBrowserMode::SaveFile, "Save")
+                .with_filters([FileFilter::new("Peridot Sprite Atlas asset", &["psa"])])
+                .request(events)
+                .await
+                .and_then(|x| x.into_iter().next())
+            else {
+                return Ok(None);
+            };
+
+            // Note: ポータルのダイアログは上書きの確認を自前でやるので、こちらでも合わせる
+            const REPLACE: usize = 1;
+            if path.exists()
+                && DialogRequest::message(format!(
+                    "{} already exists. Do you want to replace it?",
+                    path.display()
+                ))
+                .with_title("Replace file")
+                .with_icon(DialogIcon::Warning)
+                .with_buttons(&["Cancel", "Replace"])
+                .request(events)
+                .await
+                    != Some(REPLACE)
+            {
+                return Ok(None);
+            }
+
+            return Ok(Some(path));
+        };
 
         let dialog_token = uuid::Uuid::new_v4().as_simple().to_string();
-        let mut request_object =
-            DesktopPortal::open_request_object_for_token(&self.dbus, &dialog_token);
+        let mut request_object = DesktopPortal::open_request_object_for_token(dbus, &dialog_token);
 
         let exported_shell = for_shell.try_export_toplevel();
         let request_handle = file_chooser
             .save_file(
-                &self.dbus,
+                dbus,
                 exported_shell.as_ref().map(|x| x.handle.as_c_str()),
                 c"Save",
                 |mut options_appender| {
@@ -2569,18 +3340,19 @@ impl SystemLink {
             );
             request_object = request_handle;
         }
-        let resp = request_object.wait_for_response(&self.dbus).await;
+        let resp = request_object.wait_for_response(dbus).await;
         drop(exported_shell);
 
-        let mut resp_iter = resp.iter();
-        let response = desktop_portal_proto::RequestResponseCode::read(&resp_iter);
+        let Some(mut resp) = desktop_portal_proto::request::ResponseSignal::read(&resp) else {
+            return Err(SelectSpriteFilesError::MalformedResponse);
+        };
+        let response = desktop_portal_proto::RequestResponseCode::from(resp.response);
         if response != desktop_portal_proto::RequestResponseCode::Success {
             tracing::warn!(?response, "Operation was cancelled");
             return Ok(None);
         }
 
-        resp_iter.next();
-        let res = desktop_portal_proto::file_chooser::ResponseResults::read_all(&mut resp_iter);
+        let res = desktop_portal_proto::file_chooser::ResponseResults::read_all(&mut resp.results);
         match res.uris[..] {
             [] => Ok(None),
             // TODO: add ext at here, if needed
@@ -2589,6 +3361,249 @@ impl SystemLink {
             ))),
         }
     }
+
+    #[tracing::instrument(
+        name = "SystemLink::select_folder",
+        skip(self, for_shell, events),
+        ret(Debug)
+    )]
+    pub async fn select_folder(
+        &self,
+        for_shell: &AppShell<'_, '_>,
+        events: &AppEventBus,
+    ) -> Result<Option<std::path::PathBuf>, SelectSpriteFilesError> {
+        let Some((dbus, file_chooser)) = self.file_chooser().await else {
+            tracing::info!("No FileChooser portal found, using the built-in file browser");
+            return Ok(FileBrowserRequest::new(
+                FileBrowserMode::SelectFolder,
+                "Locate Missing Sources",
+            )
+            .request(events)
+            .await
+            .and_then(|x| x.into_iter().next()));
+        };
+
+        let dialog_token = uuid::Uuid::new_v4().as_simple().to_string();
+        let mut request_object = DesktopPortal::open_request_object_for_token(dbus, &dialog_token);
+
+        let exported_shell = for_shell.try_export_toplevel();
+        let request_handle = file_chooser
+            .open_file(
+                dbus,
+                exported_shell.as_ref().map(|x| x.handle.as_c_str()),
+                c"Locate Missing Sources",
+                |mut options_appender| {
+                    options_appender.append_handle_token(
+                        &std::ffi::CString::new(dialog_token.clone()).unwrap(),
+                    );
+                    options_appender.append_multiple(false);
+                    options_appender.append_directory(true);
+                },
+            )
+            .await
+            .map_err(SelectSpriteFilesError::OpenFileFailed)?;
+        if !request_object.points_same_object(&request_handle) {
+            tracing::debug!(
+                open_file_dialog_handle = ?request_handle.0,
+                request_object_path = ?request_object.0,
+                "returned object_path did not match with the expected, switching request object..."
+            );
+            request_object = request_handle;
+        }
+        let resp = request_object.wait_for_response(dbus).await;
+        drop(exported_shell);
+
+        let Some(mut resp) = desktop_portal_proto::request::ResponseSignal::read(&resp) else {
+            return Err(SelectSpriteFilesError::MalformedResponse);
+        };
+        let response = desktop_portal_proto::RequestResponseCode::from(resp.response);
+        if response != desktop_portal_proto::RequestResponseCode::Success {
+            tracing::warn!(?response, "Operation was cancelled");
+            return Ok(None);
+        }
+
+        let res = desktop_portal_proto::file_chooser::ResponseResults::read_all(&mut resp.results);
+        match res.uris[..] {
+            [] => Ok(None),
+            [ref uri, ..] => Ok(Some(std::path::PathBuf::from(
+                desktop_portal_proto::file_chooser::uri_path_part(dbus_proto::cstr2str(uri)),
+            ))),
+        }
+    }
+
+    /// Documents portalで公開されているファイルのホスト側のパスを集める
+    ///
+    /// サンドボックスの外(パスがそのまま使える)ならNone
+    #[tracing::instrument(name = "SystemLink::document_path_mapping", skip(self))]
+    pub async fn document_path_mapping(&self) -> Option<source_path::DocumentPathMapping> {
+        use std::os::unix::ffi::OsStringExt;
+
+        if !std::path::Path::new("/.flatpak-info").exists() {
+            return None;
+        }
+        let Some(dbus) = self.dbus.as_ref() else {
+            tracing::warn!("no session bus, document paths cannot be mapped");
+            return None;
+        };
+
+        let proxy = DesktopPortal::documents_proxy(dbus);
+        let mount_point = match proxy.get_mount_point().await {
+            Ok(reply) => desktop_portal_proto::documents::read_mount_point(&reply)?,
+            Err(e) => {
+                tracing::warn!(reason = ?e, "Documents.GetMountPoint failed");
+                return None;
+            }
+        };
+        let mount_point = std::path::PathBuf::from(std::ffi::OsString::from_vec(mount_point));
+        let mut mapping = source_path::DocumentPathMapping::with_mount_point(mount_point.clone());
+
+        let version = match proxy.version().await {
+            Ok(x) => x,
+            Err(e) => {
+                tracing::warn!(reason = ?e, "Documents get version failed, assuming v1");
+                1
+            }
+        };
+        if version < desktop_portal_proto::documents::GET_HOST_PATHS_SINCE {
+            tracing::warn!(version, "Documents.GetHostPaths is not available");
+            return Some(mapping);
+        }
+
+        // Note: サンドボックス内のマウント先には、このアプリに公開されているドキュメントのIDだけが並んでいる
+        let doc_ids = match std::fs::read_dir(&mount_point) {
+            Ok(entries) => entries
+                .filter_map(|e| e.ok())
+                .filter_map(|e| std::ffi::CString::new(e.file_name().into_vec()).ok())
+                .collect::<Vec<_>>(),
+            Err(e) => {
+                tracing::warn!(reason = ?e, "listing documents failed");
+                return Some(mapping);
+            }
+        };
+        if doc_ids.is_empty() {
+            return Some(mapping);
+        }
+
+        let reply = match proxy
+            .get_host_paths(|args| {
+                desktop_portal_proto::documents::append_doc_ids(
+                    args,
+                    doc_ids.iter().map(|x| x.as_c_str()),
+                )
+            })
+            .await
+        {
+            Ok(x) => x,
+            Err(e) => {
+                tracing::warn!(reason = ?e, "Documents.GetHostPaths failed");
+                return Some(mapping);
+            }
+        };
+        for (doc_id, host_path) in desktop_portal_proto::documents::read_host_paths(&reply) {
+            let host_path = std::path::PathBuf::from(std::ffi::OsString::from_vec(host_path));
+            let Some(name) = host_path.file_name() else {
+                continue;
+            };
+
+            // Note: ファイルもフォルダも<マウント先>/<doc_id>/<名前>に置かれる
+            mapping.insert(
+                mount_point.join(dbus_proto::cstr2str(&doc_id)).join(name),
+                host_path,
+            );
+        }
+
+        Some(mapping)
+    }
+
+    /// Settings portalの配色設定(org.freedesktop.appearance)を読んで、変わるたびにイベントで知らせる
+    #[tracing::instrument(name = "SystemLink::watch_appearance_settings", skip(self, events))]
+    pub async fn watch_appearance_settings(&self, events: &AppEventBus) {
+        use desktop_portal_proto::settings::{
+            ACCENT_COLOR_KEY, APPEARANCE_NAMESPACE, AccentColor, COLOR_SCHEME_KEY,
+            ColorScheme as PortalColorScheme, SettingChangedSignal,
+        };
+
+        let Some(dbus) = self.dbus.as_ref() else {
+            return;
+        };
+        let proxy = DesktopPortal::settings_proxy(dbus);
+        let version = match proxy.version().await {
+            Ok(x) => x,
+            Err(e) => {
+                tracing::info!(reason = ?e, "Settings portal is not available, using default appearance");
+                return;
+            }
+        };
+
+        // Note: SettingChangedはブロードキャストなので明示的に購読しないと届かない
+        // (ほかのクライアントが同じ名前のシグナルを流してきても拾わないように送信元も絞る)
+        if let Err(e) = dbus.underlying().add_match(
+            c"type='signal',sender='org.freedesktop.portal.Desktop',interface='org.freedesktop.portal.Settings',member='SettingChanged',arg0='org.freedesktop.appearance'",
+        ) {
+            tracing::warn!(reason = ?e, "Failed to subscribe SettingChanged, appearance changes will not be followed");
+        }
+
+        let signal_key = (
+            std::rc::Rc::<core::ffi::CStr>::from(c"/org/freedesktop/portal/desktop"),
+            std::rc::Rc::<core::ffi::CStr>::from(desktop_portal_proto::settings::INTERFACE),
+            std::rc::Rc::<core::ffi::CStr>::from(SettingChangedSignal::MEMBER),
+        );
+        loop {
+            match proxy
+                .read_value(version, APPEARANCE_NAMESPACE, COLOR_SCHEME_KEY)
+                .await
+            {
+                Ok(m) => {
+                    let scheme = PortalColorScheme::read(m.iter());
+                    tracing::debug!(?scheme, "color-scheme");
+                    events.push(AppEvent::UIColorSchemeChanged(match scheme {
+                        Some(PortalColorScheme::PreferLight) => ColorScheme::Light,
+                        // Note: 設定がなければもともとの見た目であるダークにしておく
+                        Some(PortalColorScheme::PreferDark | PortalColorScheme::NoPreference)
+                        | None => ColorScheme::Dark,
+                    }));
+                }
+                Err(e) => {
+                    tracing::warn!(reason = ?e, "Failed to read color-scheme");
+                }
+            }
+            match proxy
+                .read_value(version, APPEARANCE_NAMESPACE, ACCENT_COLOR_KEY)
+                .await
+            {
+                Ok(m) => {
+                    let accent = AccentColor::read(m.iter());
+                    tracing::debug!(?accent, "accent-color");
+                    events.push(AppEvent::UIAccentColorChanged(
+                        accent.map(|c| [c.r as f32, c.g as f32, c.b as f32]),
+                    ));
+                }
+                Err(e) => {
+                    // 古い実装ではキーがなくてエラーになる
+                    tracing::debug!(reason = ?e, "Failed to read accent-color");
+                    events.push(AppEvent::UIAccentColorChanged(None));
+                }
+            }
+
+            // Note: 待ち受けを再登録するまでに届いたシグナルは取りこぼすので、シグナルの値は使わずに毎回両方読み直す
+            loop {
+                let msg = dbus
+                    .wait_for_signal(
+                        signal_key.0.clone(),
+                        signal_key.1.clone(),
+                        signal_key.2.clone(),
+                    )
+                    .await;
+                let Some(changed) = SettingChangedSignal::read(&msg) else {
+                    tracing::debug!(sender = ?msg.sender(), "ignoring malformed SettingChanged signal");
+                    continue;
+                };
+                if changed.namespace.as_c_str() == APPEARANCE_NAMESPACE {
+                    break;
+                }
+            }
+        }
+    }
 }
 
 #[cfg(target_os = "macos")]
@@ -2601,12 +3616,13 @@ impl SystemLink {
 
     #[tracing::instrument(
         name = "SystemLink::select_sprite_files",
-        skip(self, for_shell),
+        skip(self, for_shell, _events),
         ret(Debug)
     )]
     pub async fn select_sprite_files(
         &self,
         for_shell: &AppShell<'_, '_>,
+        _events: &AppEventBus,
     ) -> Result<Vec<std::path::PathBuf>, SystemLinkError> {
         // TODO: file chooser
 
@@ -2624,12 +3640,13 @@ impl SystemLink {
 
     #[tracing::instrument(
         name = "SystemLink::select_open_file",
-        skip(self, for_shell),
+        skip(self, for_shell, _events),
         ret(Debug)
     )]
     pub async fn select_open_file(
         &self,
         for_shell: &AppShell<'_, '_>,
+        _events: &AppEventBus,
     ) -> Result<Option<std::path::PathBuf>, SystemLinkError> {
         // TODO: file chooser
         Ok(None)
@@ -2637,16 +3654,36 @@ impl SystemLink {
 
     #[tracing::instrument(
         name = "SystemLink::select_save_file",
-        skip(self, for_shell),
+        skip(self, for_shell, _events),
         ret(Debug)
     )]
     pub async fn select_save_file(
         &self,
         for_shell: &AppShell<'_, '_>,
+        _events: &AppEventBus,
     ) -> Result<Option<std::path::PathBuf>, SystemLinkError> {
         // TODO: file chooser
         Ok(None)
     }
+
+    #[tracing::instrument(
+        name = "SystemLink::select_folder",
+        skip(self, for_shell, _events),
+        ret(Debug)
+    )]
+    pub async fn select_folder(
+        &self,
+        for_shell: &AppShell<'_, '_>,
+        _events: &AppEventBus,
+    ) -> Result<Option<std::path::PathBuf>, SystemLinkError> {
+        // TODO: file chooser
+        Ok(None)
+    }
+
+    // TODO: App Sandboxのsecurity-scoped bookmarkを使う
+    pub async fn document_path_mapping(&self) -> Option<source_path::DocumentPathMapping> {
+        None
+    }
 }
 
 #[cfg(target_os = "macos")]
@@ -2660,9 +3697,9 @@ impl SystemLinkError {
     pub fn ui_feedback(self, events: &AppEventBus) {
         match self {
             Self::UnrecoverableException() => {
-                events.push(AppEvent::UIMessageDialogRequest {
-                    content: "Operation failed".into(),
-                });
+                events.push(AppEvent::UIMessageDialogRequest(
+                    DialogRequest::message("Operation failed").with_icon(DialogIcon::Error),
+                ));
             }
         }
     }
@@ -2834,15 +3871,18 @@ pub struct DBusLink {
             )>,
         >,
     >,
+    /// 外から呼び出されたメソッド(automation::Serverが処理する)
+    incoming_method_calls: RefCell<VecDeque<dbus::Message>>,
 }
 #[cfg(target_os = "linux")]
 impl DBusLink {
-    pub fn new() -> Self {
-        Self {
-            con: dbus::Connection::connect_bus(dbus::BusType::Session).unwrap(),
+    pub fn new() -> Result<Self, dbus::Error> {
+        Ok(Self {
+            con: dbus::Connection::connect_bus(dbus::BusType::Session)?,
             wait_for_reply_wakers: RefCell::new(HashMap::new()),
             wait_for_signal_wakers: RefCell::new(HashMap::new()),
-        }
+            incoming_method_calls: RefCell::new(VecDeque::new()),
+        })
     }
 
     #[inline(always)]
@@ -2878,8 +3918,10 @@ impl DBusLink {
             let span =
                 tracing::info_span!(target: "dbus_loop", "dbus message recv", r#type = m.r#type());
             let _enter = span.enter();
-            if m.r#type() == dbus::MESSAGE_TYPE_METHOD_RETURN {
-                // method return
+            if m.r#type() == dbus::MESSAGE_TYPE_METHOD_RETURN
+                || m.r#type() == dbus::MESSAGE_TYPE_ERROR
+            {
+                // method return(エラーの返答も呼び出し側で見る)
                 tracing::trace!(target: "dbus_loop", reply_serial = m.reply_serial(), signature = ?m.signature(), "method return data");
                 self.wake_for_reply(m);
             } else if m.r#type() == dbus::MESSAGE_TYPE_SIGNAL {
@@ -2891,12 +3933,21 @@ impl DBusLink {
                     m.member().unwrap().into(),
                     m,
                 );
+            } else if m.r#type() == dbus::MESSAGE_TYPE_METHOD_CALL {
+                // method call
+                tracing::trace!(target: "dbus_loop", sender = ?m.sender(), path = ?m.path(), interface = ?m.interface(), member = ?m.member(), "method call data");
+                self.incoming_method_calls.borrow_mut().push_back(m);
             } else {
                 tracing::trace!(target: "dbus_loop", "unknown dbus message");
             }
         }
     }
 
+    #[inline]
+    pub fn pop_method_call(&self) -> Option<dbus::Message> {
+        self.incoming_method_calls.borrow_mut().pop_front()
+    }
+
     pub fn register_wait_for_reply(
         &self,
         serial: u32,
@@ -2984,6 +4035,19 @@ impl DBusLink {
     }
 }
 
+#[cfg(target_os = "linux")]
+impl dbus::AsyncConnection for DBusLink {
+    #[inline(always)]
+    fn connection(&self) -> &dbus::Connection {
+        &self.con
+    }
+
+    #[inline(always)]
+    fn wait_for_reply(&self, serial: u32) -> impl core::future::Future<Output = dbus::Message> {
+        DBusWaitForReplyFuture::new(self, serial)
+    }
+}
+
 #[cfg(target_os = "linux")]
 pub struct DBusWaitForReplyFuture<'link> {
     link: &'link DBusLink,
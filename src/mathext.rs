@@ -18,6 +18,23 @@ impl Matrix4 {
         ])
     }
 
+    /// Z軸まわりの回転（スクリーン座標系では時計回り）
+    pub fn rotate_z(rad: f32) -> Self {
+        let (s, c) = rad.sin_cos();
+
+        Self([
+            c, -s, 0.0, 0.0, s, c, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+        ])
+    }
+
+    pub fn skew(x_rad: f32, y_rad: f32) -> Self {
+        let (tx, ty) = (x_rad.tan(), y_rad.tan());
+
+        Self([
+            1.0, tx, 0.0, 0.0, ty, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+        ])
+    }
+
     pub const fn transpose(self) -> Self {
         Self([
             self.0[0], self.0[4], self.0[8], self.0[12], self.0[1], self.0[5], self.0[9],
@@ -26,6 +43,14 @@ impl Matrix4 {
         ])
     }
 
+    /// 2次元の点を変換する（z = 0, w = 1として扱う）
+    pub const fn transform_point(&self, x: f32, y: f32) -> [f32; 2] {
+        [
+            self.0[0] * x + self.0[1] * y + self.0[3],
+            self.0[4] * x + self.0[5] * y + self.0[7],
+        ]
+    }
+
     const fn row(&self, r: usize) -> [f32; 4] {
         [
             self.0[r * 4 + 0],
@@ -10,6 +10,7 @@ use uuid::Uuid;
 pub struct Sprite {
     pub id: Uuid,
     pub name: String,
+    /// relative to the directory of the asset file (absolute paths written by older versions are also accepted)
     pub source_path: PathBuf,
     pub width: u32,
     pub height: u32,
@@ -20,6 +21,8 @@ pub struct Sprite {
     pub border_right: u32,
     pub border_bottom: u32,
     pub rotated: bool,
+    /// エディタ上での並び順(ファイル内はid順なので別に持つ)
+    pub order: u32,
 }
 
 pub struct SpriteAtlasAsset {
@@ -45,12 +48,13 @@ impl SpriteAtlasAsset {
             border_right,
             border_bottom,
             rotated,
+            order,
         } in self.sprites.iter()
         {
             // Note: 比較的変わりにくいもの -> 変わりやすいもの の順でならべている（行ごとの差分を見やすくするため）
             writeln!(
                 sink,
-                "{id}={width},{height},{rotated},{border_left},{border_top},{border_right},{border_bottom},{left},{top},{source_path},{name}",
+                "{id}={width},{height},{rotated},{border_left},{border_top},{border_right},{border_bottom},{left},{top},{source_path},{name},{order}",
                 id = id.as_simple(),
                 rotated = if rotated { 1 } else { 0 },
                 source_path = source_path.display()
@@ -89,6 +93,7 @@ impl SpriteAtlasAsset {
                 continue;
             }
 
+            let index = sprites.len() as u32;
             sprites.push(Sprite {
                 id: id
                     .parse::<uuid::fmt::Simple>()
@@ -152,6 +157,13 @@ impl SpriteAtlasAsset {
                     .next()
                     .ok_or(SpriteAtlasAssetReadError::MissingParam("name"))?
                     .into(),
+                // Note: 並び順を持っていない古いファイルでは行の順にする
+                order: params
+                    .next()
+                    .map(str::parse)
+                    .transpose()
+                    .map_err(|e| SpriteAtlasAssetReadError::InvalidParamFormat("order", e))?
+                    .unwrap_or(index),
             });
         }
 
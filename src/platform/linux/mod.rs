@@ -15,6 +15,7 @@ bitflags! {
     #[derive(Clone, Copy)]
     pub struct MemoryMapFlags : core::ffi::c_int {
         const SHARED = libc::MAP_SHARED;
+        const PRIVATE = libc::MAP_PRIVATE;
     }
 }
 
@@ -17,8 +17,11 @@ use objc_rt::{
 };
 
 use crate::{
-    AppEvent, AppEventBus, base_system::AppBaseSystem, hittest::CursorShape,
-    input::PointerInputManager, subsystem::Subsystem,
+    AppEvent, AppEventBus,
+    base_system::AppBaseSystem,
+    hittest::CursorShape,
+    input::{PointerInputManager, TextInputTarget},
+    subsystem::Subsystem,
 };
 
 pub struct AppShell<'event_bus, 'subsystem> {
@@ -27,6 +30,9 @@ pub struct AppShell<'event_bus, 'subsystem> {
     window_state_vars: Pin<Box<ShellWindowStateVars<'event_bus>>>,
     frame_timing_observation_thread: core::mem::ManuallyDrop<std::thread::JoinHandle<()>>,
     support_thread_termination_flag: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    // TODO: NSPasteboardを使う それまではアプリ内だけでやりとりする
+    clipboard_text: core::cell::RefCell<Option<String>>,
+    clipboard_png: core::cell::RefCell<Option<Vec<u8>>>,
     _marker: core::marker::PhantomData<(&'event_bus AppEventBus, &'subsystem Subsystem)>,
 }
 impl Drop for AppShell<'_, '_> {
@@ -139,6 +145,8 @@ impl<'event_bus, 'subsystem> AppShell<'event_bus, 'subsystem> {
                 frame_timing_observation_thread,
             ),
             support_thread_termination_flag,
+            clipboard_text: core::cell::RefCell::new(None),
+            clipboard_png: core::cell::RefCell::new(None),
             _marker: core::marker::PhantomData,
         }
     }
@@ -228,6 +236,33 @@ impl<'event_bus, 'subsystem> AppShell<'event_bus, 'subsystem> {
         }
     }
 
+    pub fn set_text_input_target(&self, _target: Option<TextInputTarget>) {
+        // TODO: NSTextInputClientを実装する
+    }
+
+    pub fn set_clipboard_text(&self, text: String) {
+        *self.clipboard_text.borrow_mut() = Some(text);
+        *self.clipboard_png.borrow_mut() = None;
+    }
+
+    pub fn set_clipboard_image(&self, png: Vec<u8>, text: String) {
+        *self.clipboard_text.borrow_mut() = Some(text);
+        *self.clipboard_png.borrow_mut() = Some(png);
+    }
+
+    pub fn clipboard_text(&self) -> Option<String> {
+        self.clipboard_text.borrow().clone()
+    }
+
+    pub fn clipboard_png(&self) -> Option<Vec<u8>> {
+        self.clipboard_png.borrow().clone()
+    }
+
+    pub fn paste_clipboard_files(&self) -> bool {
+        // TODO: NSPasteboardのファイルURLを読む
+        false
+    }
+
     // このへんのwaylandべったりなやつなんとかしたい
     pub fn post_configure(&mut self, _serial: u32) {}
 
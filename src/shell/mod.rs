@@ -12,3 +12,15 @@ pub use self::win32::AppShell;
 pub mod macos;
 #[cfg(target_os = "macos")]
 pub use self::macos::AppShell;
+
+impl crate::input::PointerCaptureHost for AppShell<'_, '_> {
+    #[inline(always)]
+    fn capture_pointer(&self) {
+        AppShell::capture_pointer(self)
+    }
+
+    #[inline(always)]
+    fn release_pointer(&self) {
+        AppShell::release_pointer(self)
+    }
+}
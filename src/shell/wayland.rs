@@ -1,7 +1,9 @@
 use std::{
     cell::{Cell, UnsafeCell},
-    os::fd::{AsRawFd, RawFd},
+    io::Write,
+    os::fd::{AsRawFd, FromRawFd, RawFd},
     pin::Pin,
+    rc::Rc,
 };
 
 use bedrock::{self as br, SurfaceCreateInfo};
@@ -12,10 +14,18 @@ use crate::{
     AppEvent, AppEventBus,
     base_system::AppBaseSystem,
     hittest::{CursorShape, Role},
-    input::PointerInputManager,
-    platform::linux::{MemoryMapFlags, MemoryProtectionFlags, OpenFlags, TemporalSharedMemory},
+    input::{KeyCode, KeyModifiers, PointerInputManager, TextInputEvent, TextInputTarget},
+    platform::linux::{
+        MemoryMapFlags, MemoryProtectionFlags, OpenFlags, TemporalSharedMemory, mmap_random,
+    },
 };
 
+/// クリップボードでやりとりするテキストのmime type(優先順)
+const CLIPBOARD_TEXT_MIME_TYPES: &[&core::ffi::CStr] =
+    &[c"text/plain;charset=utf-8", c"UTF8_STRING", c"text/plain"];
+const PNG_MIME_TYPE: &core::ffi::CStr = c"image/png";
+const URI_LIST_MIME_TYPE: &core::ffi::CStr = c"text/uri-list";
+
 struct DataOfferSession {
     obj: wl::Owned<wl::DataOffer>,
     offered_mime_types: Vec<std::ffi::CString>,
@@ -58,6 +68,218 @@ impl DataOfferSession {
     fn is_offer(&self, other: &wl::DataOffer) -> bool {
         self.obj.ref_eq(other)
     }
+
+    /// mime_typesの優先順で最初に提供されているもの
+    fn find_mime_type<'m>(
+        &self,
+        mime_types: &[&'m core::ffi::CStr],
+    ) -> Option<&'m core::ffi::CStr> {
+        mime_types
+            .iter()
+            .copied()
+            .find(|m| self.offered_mime_types.iter().any(|x| x.as_c_str() == *m))
+    }
+
+    /// 画像として受け取れるもの(PNGを優先する)
+    fn find_image_mime_type(&self) -> Option<&core::ffi::CStr> {
+        self.find_mime_type(&[PNG_MIME_TYPE]).or_else(|| {
+            self.offered_mime_types
+                .iter()
+                .map(|x| x.as_c_str())
+                .find(|x| x.to_bytes().starts_with(b"image/"))
+        })
+    }
+}
+
+/// 相手が書き終わるのを待つ最大時間
+const RECEIVE_DATA_OFFER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// offerの中身を受け取る
+///
+/// flushでreceiveリクエストを送り出してから、相手が書き終わる(閉じる)まで読む
+/// (RECEIVE_DATA_OFFER_TIMEOUTを過ぎたら諦める)
+fn receive_data_offer(
+    offer: &wl::DataOffer,
+    mime_type: &core::ffi::CStr,
+    flush: impl FnOnce(),
+) -> Option<Vec<u8>> {
+    let mut pipefd = [0 as core::ffi::c_int; 2];
+    if unsafe { libc::pipe(pipefd.as_mut_ptr()) } < 0 {
+        tracing::warn!(reason = ?std::io::Error::last_os_error(), "Failed to create pipe");
+        return None;
+    }
+    let [readfd, writefd] = pipefd;
+
+    let r = offer.receive(mime_type, &writefd);
+    unsafe {
+        libc::close(writefd);
+    }
+    if let Err(e) = r {
+        tracing::warn!(reason = ?e, "Failed to request receive");
+        unsafe {
+            libc::close(readfd);
+        }
+        return None;
+    }
+    flush();
+
+    let received = read_until_closed(readfd, RECEIVE_DATA_OFFER_TIMEOUT);
+    unsafe {
+        libc::close(readfd);
+    }
+
+    received
+}
+
+/// fdを書き込み側が閉じるまで読む
+///
+/// Note: 相手が応答しない(自分自身がsourceの場合も含む)とブロックし続けてしまうので、
+/// 非ブロッキングにしてpollで待ちつつ全体の時間を制限する
+fn read_until_closed(fd: core::ffi::c_int, timeout: std::time::Duration) -> Option<Vec<u8>> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        tracing::warn!(reason = ?std::io::Error::last_os_error(), "Failed to set O_NONBLOCK");
+        return None;
+    }
+
+    let deadline = std::time::Instant::now() + timeout;
+    let mut received = Vec::<u8>::new();
+    let mut readbuf = vec![0u8; 8192];
+    loop {
+        let b = unsafe { libc::read(fd, readbuf.as_mut_ptr() as *mut _, readbuf.len()) };
+        if b == 0 {
+            return Some(received);
+        }
+        if b > 0 {
+            received.extend(&readbuf[..b as usize]);
+            continue;
+        }
+
+        let e = std::io::Error::last_os_error();
+        match e.kind() {
+            std::io::ErrorKind::Interrupted => continue,
+            std::io::ErrorKind::WouldBlock => (),
+            _ => {
+                tracing::warn!(reason = ?e, "Failed to read offered data");
+                return None;
+            }
+        }
+
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            tracing::warn!(?timeout, "Timed out waiting for offered data");
+            return None;
+        }
+        let mut pfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // Note: 端数を切り上げておかないと残りが1ms未満のときに0でbusy loopになる
+        let timeout_ms = remaining.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32;
+        if unsafe { libc::poll(&mut pfd, 1, timeout_ms) } < 0 {
+            let e = std::io::Error::last_os_error();
+            if e.kind() != std::io::ErrorKind::Interrupted {
+                tracing::warn!(reason = ?e, "Failed to poll offered data");
+                return None;
+            }
+        }
+    }
+}
+
+/// text/uri-list(RFC 2483)の行を取り出す(コメント行は飛ばす)
+fn parse_uri_list(bytes: &[u8]) -> Vec<std::ffi::CString> {
+    bytes
+        .split(|&b| b == b'\n')
+        .map(|x| x.strip_suffix(b"\r").unwrap_or(x))
+        .filter(|x| !x.is_empty() && !x.starts_with(b"#"))
+        .filter_map(|x| std::ffi::CString::new(x).ok())
+        .collect()
+}
+
+/// 自分がクリップボードに置いたデータ
+struct ClipboardSource {
+    obj: wl::Owned<wl::DataSource>,
+    /// mime typeごとの中身(テキストは複数のmime typeで同じものを共有する)
+    representations: Vec<(&'static core::ffi::CStr, Rc<[u8]>)>,
+    cancelled: bool,
+}
+impl wl::DataSourceEventListener for ClipboardSource {
+    fn target(&mut self, _sender: &mut wl::DataSource, _mime_type: Option<&core::ffi::CStr>) {}
+
+    #[tracing::instrument(
+        name = "<ClipboardSource as DataSourceEventListener>::send",
+        skip(self, _sender)
+    )]
+    fn send(&mut self, _sender: &mut wl::DataSource, mime_type: &core::ffi::CStr, fd: RawFd) {
+        // Note: Fileに所有権を渡してdropで閉じる
+        let mut fp = unsafe { std::fs::File::from_raw_fd(fd) };
+        let Some(content) = self.find(&[mime_type]) else {
+            tracing::warn!("requested mime type is not offered");
+            return;
+        };
+        if let Err(e) = fp.write_all(content) {
+            tracing::warn!(reason = ?e, "Failed to send clipboard content");
+        }
+    }
+
+    fn cancelled(&mut self, _sender: &mut wl::DataSource) {
+        // 他のクライアントに選択が移った
+        self.cancelled = true;
+    }
+
+    fn dnd_drop_performed(&mut self, _sender: &mut wl::DataSource) {}
+
+    fn dnd_finished(&mut self, _sender: &mut wl::DataSource) {}
+
+    fn action(
+        &mut self,
+        _sender: &mut wl::DataSource,
+        _dnd_action: wl::DataDeviceManagerDndAction,
+    ) {
+    }
+}
+impl ClipboardSource {
+    /// mime_typesの優先順で最初に見つかった中身
+    fn find(&self, mime_types: &[&core::ffi::CStr]) -> Option<&[u8]> {
+        mime_types.iter().find_map(|m| {
+            self.representations
+                .iter()
+                .find(|(x, _)| x == m)
+                .map(|(_, c)| &c[..])
+        })
+    }
+}
+
+/// zwp_text_input_v3のdoneまでに溜めておく変更
+#[derive(Default)]
+struct PendingTextInput {
+    delete_surrounding: Option<(u32, u32)>,
+    commit: Option<String>,
+    preedit: Option<(String, i32, i32)>,
+}
+
+fn key_code_from_keysym(sym: xkbcommon::Keysym) -> KeyCode {
+    use xkbcommon::keysyms;
+
+    match sym {
+        keysyms::LEFT => KeyCode::Left,
+        keysyms::RIGHT => KeyCode::Right,
+        keysyms::UP => KeyCode::Up,
+        keysyms::DOWN => KeyCode::Down,
+        keysyms::HOME => KeyCode::Home,
+        keysyms::END => KeyCode::End,
+        keysyms::BACKSPACE => KeyCode::Backspace,
+        keysyms::DELETE => KeyCode::Delete,
+        keysyms::RETURN | keysyms::KP_ENTER => KeyCode::Enter,
+        keysyms::ESCAPE => KeyCode::Escape,
+        keysyms::TAB => KeyCode::Tab,
+        keysyms::LOWER_A | keysyms::UPPER_A => KeyCode::A,
+        keysyms::LOWER_C | keysyms::UPPER_C => KeyCode::C,
+        keysyms::LOWER_V | keysyms::UPPER_V => KeyCode::V,
+        keysyms::LOWER_X | keysyms::UPPER_X => KeyCode::X,
+        _ => KeyCode::Other,
+    }
 }
 
 enum PointerOnSurface {
@@ -65,6 +287,12 @@ enum PointerOnSurface {
     Main { serial: u32 },
     ResizeEdge { edge: wl::XdgToplevelResizeEdge },
 }
+
+// wl_pointer::axis/axis_sourceの値
+const WL_POINTER_AXIS_VERTICAL_SCROLL: u32 = 0;
+const WL_POINTER_AXIS_HORIZONTAL_SCROLL: u32 = 1;
+const WL_POINTER_AXIS_SOURCE_FINGER: u32 = 1;
+
 struct WaylandShellEventHandler<'a, 'subsystem> {
     app_event_bus: &'a AppEventBus,
     cached_client_size_px: (u32, u32),
@@ -82,7 +310,127 @@ struct WaylandShellEventHandler<'a, 'subsystem> {
     pointer_last_surface_pos: (wl::Fixed, wl::Fixed),
     tiled: bool,
     title_bar_last_click: Option<std::time::Instant>,
+    /// frameでまとめて流すスクロール量
+    pending_scroll: (f32, f32),
+    pending_scroll_from_finger: bool,
+    pending_scroll_fling: bool,
     active_data_offer: Option<Pin<Box<DataOfferSession>>>,
+    selection_offer: Option<Pin<Box<DataOfferSession>>>,
+    clipboard_source: Option<Pin<Box<ClipboardSource>>>,
+    data_device_manager_proxy_ptr: *mut wl::DataDeviceManager,
+    data_device_proxy_ptr: *mut wl::DataDevice,
+    /// set_selectionに使う直近の入力イベントのserial
+    last_input_serial: u32,
+    xkb_context: Option<xkbcommon::Context>,
+    xkb_keymap: Option<xkbcommon::Keymap>,
+    xkb_state: Option<xkbcommon::State>,
+    keyboard_focused: bool,
+    /// (rate[回/秒], delay[ms])
+    key_repeat_info: (i32, i32),
+    /// リピート中のキーと次に発生させる時刻
+    key_repeat: Option<(u32, std::time::Instant)>,
+    text_input_proxy_ptr: *mut wl::ZwpTextInputV3,
+    text_input_entered: bool,
+    text_input_target: Option<TextInputTarget>,
+    /// 最後にtext_inputにcommitした内容
+    text_input_applied: Option<TextInputTarget>,
+    pending_text_input: PendingTextInput,
+}
+impl WaylandShellEventHandler<'_, '_> {
+    fn current_key_modifiers(&self) -> KeyModifiers {
+        let Some(ref state) = self.xkb_state else {
+            return KeyModifiers::empty();
+        };
+
+        let mut modifiers = KeyModifiers::empty();
+        if state.mod_name_is_active(xkbcommon::MOD_NAME_SHIFT) {
+            modifiers |= KeyModifiers::SHIFT;
+        }
+        if state.mod_name_is_active(xkbcommon::MOD_NAME_CTRL) {
+            modifiers |= KeyModifiers::CTRL;
+        }
+        if state.mod_name_is_active(xkbcommon::MOD_NAME_ALT) {
+            modifiers |= KeyModifiers::ALT;
+        }
+        if state.mod_name_is_active(xkbcommon::MOD_NAME_LOGO) {
+            modifiers |= KeyModifiers::SUPER;
+        }
+
+        modifiers
+    }
+
+    /// evdevのキーコードで押下を通知する
+    fn emit_key_down(&self, key: u32) {
+        let Some(ref state) = self.xkb_state else {
+            // no keymap
+            return;
+        };
+        let keycode = key + 8;
+
+        let modifiers = self.current_key_modifiers();
+        self.app_event_bus.push(AppEvent::MainWindowKeyDown {
+            key: key_code_from_keysym(state.key_get_one_sym(keycode)),
+            modifiers,
+        });
+
+        if !modifiers.intersects(KeyModifiers::CTRL | KeyModifiers::ALT | KeyModifiers::SUPER) {
+            let text = state.key_get_utf8(keycode);
+            if !text.is_empty() && !text.chars().any(char::is_control) {
+                self.app_event_bus
+                    .push(AppEvent::MainWindowTextInput(TextInputEvent::Commit(text)));
+            }
+        }
+    }
+
+    fn sync_text_input(&mut self) {
+        if self.text_input_proxy_ptr.is_null() || !self.text_input_entered {
+            return;
+        }
+        if self.text_input_applied == self.text_input_target {
+            // no changes
+            return;
+        }
+
+        if let Err(e) = self.send_text_input_state() {
+            tracing::warn!(reason = ?e, "Failed to update text input state");
+        }
+        self.text_input_applied = self.text_input_target;
+    }
+
+    fn send_text_input_state(&self) -> Result<(), std::io::Error> {
+        let text_input = unsafe { &*self.text_input_proxy_ptr };
+        let Some(t) = self.text_input_target else {
+            text_input.disable()?;
+            return text_input.commit();
+        };
+
+        if self.text_input_applied.is_none() {
+            text_input.enable()?;
+        }
+        // Note: enableすると状態がリセットされるのでそのときは一緒に送り直す
+        if self
+            .text_input_applied
+            .is_none_or(|x| x.numeric_only != t.numeric_only)
+        {
+            text_input.set_content_type(
+                wl::ZwpTextInputV3ContentHint::NONE,
+                if t.numeric_only {
+                    wl::ZwpTextInputV3ContentPurpose::Digits
+                } else {
+                    wl::ZwpTextInputV3ContentPurpose::Normal
+                },
+            )?;
+        }
+        let to_surface = self.ui_scale_factor / self.buffer_scale as f32;
+        text_input.set_cursor_rectangle(
+            (t.caret_left * to_surface) as _,
+            (t.caret_top * to_surface) as _,
+            (t.caret_width * to_surface).ceil().max(1.0) as _,
+            (t.caret_height * to_surface).ceil() as _,
+        )?;
+
+        text_input.commit()
+    }
 }
 impl wl::XdgWmBaseEventListener for WaylandShellEventHandler<'_, '_> {
     fn ping(&mut self, wm_base: &mut wl::XdgWmBase, serial: u32) {
@@ -310,6 +658,8 @@ impl wl::PointerEventListener for WaylandShellEventHandler<'_, '_> {
         button: u32,
         state: wl::PointerButtonState,
     ) {
+        self.last_input_serial = serial;
+
         match self.pointer_on_surface {
             PointerOnSurface::None => (),
             PointerOnSurface::ResizeEdge { edge } => {
@@ -376,18 +726,46 @@ impl wl::PointerEventListener for WaylandShellEventHandler<'_, '_> {
 
     fn axis(&mut self, _pointer: &mut wl::Pointer, time: u32, axis: u32, value: wl::Fixed) {
         tracing::trace!(time, axis, value = value.to_f32(), "axis");
+
+        if !matches!(self.pointer_on_surface, PointerOnSurface::Main { .. }) {
+            return;
+        }
+
+        let value = value.to_f32() * self.buffer_scale as f32 / self.ui_scale_factor;
+        match axis {
+            WL_POINTER_AXIS_VERTICAL_SCROLL => self.pending_scroll.1 += value,
+            WL_POINTER_AXIS_HORIZONTAL_SCROLL => self.pending_scroll.0 += value,
+            _ => (),
+        }
     }
 
     fn frame(&mut self, _pointer: &mut wl::Pointer) {
-        // do nothing
+        let (delta_x, delta_y) = core::mem::replace(&mut self.pending_scroll, (0.0, 0.0));
+        let fling = core::mem::replace(&mut self.pending_scroll_fling, false);
+        self.pending_scroll_from_finger = false;
+
+        if delta_x != 0.0 || delta_y != 0.0 || fling {
+            self.app_event_bus.push(AppEvent::MainWindowPointerScroll {
+                delta_x,
+                delta_y,
+                fling,
+            });
+        }
     }
 
     fn axis_source(&mut self, _pointer: &mut wl::Pointer, axis_source: u32) {
         tracing::trace!(axis_source, "axis source");
+
+        self.pending_scroll_from_finger = axis_source == WL_POINTER_AXIS_SOURCE_FINGER;
     }
 
     fn axis_stop(&mut self, _pointer: &mut wl::Pointer, _time: u32, axis: u32) {
         tracing::trace!(axis, "axis stop");
+
+        // Note: 指を離したときだけ来る(ホイールでは来ない)
+        if self.pending_scroll_from_finger {
+            self.pending_scroll_fling = true;
+        }
     }
 
     fn axis_discrete(&mut self, _pointer: &mut wl::Pointer, axis: u32, discrete: i32) {
@@ -447,6 +825,212 @@ impl wl::ZxdgToplevelDecorationV1EventListener for WaylandShellEventHandler<'_,
         todo!("non server side decoration support");
     }
 }
+impl wl::KeyboardEventListener for WaylandShellEventHandler<'_, '_> {
+    #[tracing::instrument(
+        name = "<WaylandShellEventHandler as KeyboardEventListener>::keymap",
+        skip(self, _keyboard, fd)
+    )]
+    fn keymap(
+        &mut self,
+        _keyboard: &mut wl::Keyboard,
+        format: u32,
+        fd: std::os::fd::OwnedFd,
+        size: u32,
+    ) {
+        if format != 1 {
+            tracing::warn!("unsupported keymap format");
+            return;
+        }
+
+        let mapped = match mmap_random(
+            fd.as_raw_fd(),
+            0..size as usize,
+            MemoryProtectionFlags::READ,
+            MemoryMapFlags::PRIVATE,
+        ) {
+            Ok(x) => x,
+            Err(e) => {
+                tracing::warn!(reason = ?e, "Failed to map keymap");
+                return;
+            }
+        };
+        let buffer =
+            unsafe { core::slice::from_raw_parts(mapped.ptr_of::<u8>().as_ptr(), size as _) };
+
+        if self.xkb_context.is_none() {
+            self.xkb_context = xkbcommon::Context::new();
+        }
+        let Some(ref ctx) = self.xkb_context else {
+            tracing::warn!("Failed to create xkb context");
+            return;
+        };
+        let Some(keymap) = xkbcommon::Keymap::from_text_v1(ctx, buffer) else {
+            tracing::warn!("Failed to compile keymap");
+            return;
+        };
+
+        self.xkb_state = xkbcommon::State::new(&keymap);
+        self.xkb_keymap = Some(keymap);
+    }
+
+    fn enter(
+        &mut self,
+        _keyboard: &mut wl::Keyboard,
+        serial: u32,
+        surface: &mut wl::Surface,
+        _keys: &[u32],
+    ) {
+        if core::ptr::addr_eq(surface, self.main_surface_proxy_ptr) {
+            self.keyboard_focused = true;
+            self.last_input_serial = serial;
+        }
+    }
+
+    fn leave(&mut self, _keyboard: &mut wl::Keyboard, _serial: u32, surface: &mut wl::Surface) {
+        if core::ptr::addr_eq(surface, self.main_surface_proxy_ptr) {
+            self.keyboard_focused = false;
+            self.key_repeat = None;
+        }
+    }
+
+    fn key(
+        &mut self,
+        _keyboard: &mut wl::Keyboard,
+        serial: u32,
+        _time: u32,
+        key: u32,
+        state: wl::KeyboardKeyState,
+    ) {
+        self.last_input_serial = serial;
+        if !self.keyboard_focused {
+            return;
+        }
+
+        match state {
+            wl::KeyboardKeyState::Pressed => {
+                self.emit_key_down(key);
+
+                let (rate, delay) = self.key_repeat_info;
+                self.key_repeat = if rate > 0
+                    && self
+                        .xkb_keymap
+                        .as_ref()
+                        .is_some_and(|k| k.key_repeats(key + 8))
+                {
+                    Some((
+                        key,
+                        std::time::Instant::now()
+                            + std::time::Duration::from_millis(delay.max(0) as _),
+                    ))
+                } else {
+                    None
+                };
+            }
+            wl::KeyboardKeyState::Repeated => {
+                // コンポジタ側でリピートしてくれる場合
+                self.emit_key_down(key);
+            }
+            wl::KeyboardKeyState::Released => {
+                if self.key_repeat.is_some_and(|(k, _)| k == key) {
+                    self.key_repeat = None;
+                }
+            }
+        }
+    }
+
+    fn modifiers(
+        &mut self,
+        _keyboard: &mut wl::Keyboard,
+        _serial: u32,
+        mods_depressed: u32,
+        mods_latched: u32,
+        mods_locked: u32,
+        group: u32,
+    ) {
+        if let Some(ref mut state) = self.xkb_state {
+            state.update_mask(mods_depressed, mods_latched, mods_locked, group);
+        }
+    }
+
+    fn repeat_info(&mut self, _keyboard: &mut wl::Keyboard, rate: i32, delay: i32) {
+        self.key_repeat_info = (rate, delay);
+    }
+}
+impl wl::ZwpTextInputV3EventListener for WaylandShellEventHandler<'_, '_> {
+    fn enter(&mut self, _sender: &mut wl::ZwpTextInputV3, surface: &mut wl::Surface) {
+        if !core::ptr::addr_eq(surface, self.main_surface_proxy_ptr) {
+            return;
+        }
+
+        self.text_input_entered = true;
+        self.text_input_applied = None;
+        self.sync_text_input();
+    }
+
+    fn leave(&mut self, _sender: &mut wl::ZwpTextInputV3, surface: &mut wl::Surface) {
+        if !core::ptr::addr_eq(surface, self.main_surface_proxy_ptr) {
+            return;
+        }
+
+        self.text_input_entered = false;
+        self.text_input_applied = None;
+        self.pending_text_input = PendingTextInput::default();
+    }
+
+    fn preedit_string(
+        &mut self,
+        _sender: &mut wl::ZwpTextInputV3,
+        text: Option<&core::ffi::CStr>,
+        cursor_begin: i32,
+        cursor_end: i32,
+    ) {
+        self.pending_text_input.preedit =
+            text.map(|x| (x.to_string_lossy().into_owned(), cursor_begin, cursor_end));
+    }
+
+    fn commit_string(&mut self, _sender: &mut wl::ZwpTextInputV3, text: Option<&core::ffi::CStr>) {
+        self.pending_text_input.commit = text.map(|x| x.to_string_lossy().into_owned());
+    }
+
+    fn delete_surrounding_text(
+        &mut self,
+        _sender: &mut wl::ZwpTextInputV3,
+        before_length: u32,
+        after_length: u32,
+    ) {
+        self.pending_text_input.delete_surrounding = Some((before_length, after_length));
+    }
+
+    fn done(&mut self, _sender: &mut wl::ZwpTextInputV3, _serial: u32) {
+        // Note: 削除 -> 確定文字列の挿入 -> preeditの置き換えの順で適用する(プロトコルの規定通り)
+        let pending = core::mem::take(&mut self.pending_text_input);
+        if let Some((before_length, after_length)) = pending.delete_surrounding {
+            self.app_event_bus.push(AppEvent::MainWindowTextInput(
+                TextInputEvent::DeleteSurrounding {
+                    before_length: before_length as _,
+                    after_length: after_length as _,
+                },
+            ));
+        }
+        if let Some(text) = pending.commit {
+            self.app_event_bus
+                .push(AppEvent::MainWindowTextInput(TextInputEvent::Commit(text)));
+        }
+        // preeditは来なかった場合も消す必要があるので常に送る
+        let (text, cursor) = match pending.preedit {
+            Some((text, begin, end)) if begin >= 0 && end >= 0 => {
+                (text, Some((begin as usize, end as usize)))
+            }
+            Some((text, _, _)) => (text, None),
+            None => (String::new(), None),
+        };
+        self.app_event_bus
+            .push(AppEvent::MainWindowTextInput(TextInputEvent::Preedit {
+                text,
+                cursor,
+            }));
+    }
+}
 impl wl::DataDeviceEventListener for WaylandShellEventHandler<'_, '_> {
     #[tracing::instrument(
         name = "<WaylandShellEventHandler as DataDeviceEventListener>::data_offer",
@@ -502,9 +1086,8 @@ impl wl::DataDeviceEventListener for WaylandShellEventHandler<'_, '_> {
         }
 
         let Some(accepted_mime) = offer_session
-            .offered_mime_types
-            .iter()
-            .find(|x| x.as_c_str() == c"text/uri-list")
+            .find_mime_type(&[URI_LIST_MIME_TYPE])
+            .or_else(|| offer_session.find_image_mime_type())
         else {
             tracing::warn!(offered_mime_types = ?offer_session.offered_mime_types, "cannot accept any of offerred mime types");
             self.active_data_offer = None;
@@ -570,72 +1153,43 @@ impl wl::DataDeviceEventListener for WaylandShellEventHandler<'_, '_> {
             return;
         };
 
-        let Some(accepted_mime) = offer_session
-            .offered_mime_types
-            .iter()
-            .find(|x| x.as_c_str() == c"text/uri-list")
-        else {
-            tracing::warn!(offered_mime_types = ?offer_session.offered_mime_types, "cannot accept any of offerred mime types");
-            self.active_data_offer = None;
-            return;
+        let display = sender.display();
+        let roundtrip = || unsafe {
+            wl::ffi::wl_display_roundtrip(display);
         };
 
-        let mut pipefd = [0 as core::ffi::c_int; 2];
-        let r = unsafe { libc::pipe(pipefd.as_mut_ptr()) };
-        if r < 0 {
-            tracing::warn!(reason = ?std::io::Error::last_os_error(), "Failed to create pipe");
-            return;
-        }
-        let [readfd, writefd] = pipefd;
-
-        if let Err(e) = offer_session.obj.receive(accepted_mime, &writefd) {
-            tracing::warn!(reason = ?e, "Failed to request receive");
-            unsafe {
-                libc::close(readfd);
-                libc::close(writefd);
+        let uris = offer_session
+            .find_mime_type(&[URI_LIST_MIME_TYPE])
+            .and_then(|m| receive_data_offer(&offer_session.obj, m, roundtrip))
+            .map(|x| parse_uri_list(&x));
+        // Note: ブラウザなどからはhttpなどのURIと画像データが一緒に来るので、ローカルのファイルがなければ画像のほうを使う
+        let has_local_file = uris
+            .iter()
+            .flatten()
+            .any(|x| crate::source_path::path_from_file_uri(x.as_bytes()).is_ok());
+        let event = match (uris, offer_session.find_image_mime_type()) {
+            (Some(uris), _) if has_local_file => AppEvent::AddSpritesByUriList(uris),
+            (_, Some(image_mime)) => {
+                let Some(data) = receive_data_offer(&offer_session.obj, image_mime, roundtrip)
+                else {
+                    return;
+                };
+
+                AppEvent::AddSpriteByImageData(data)
             }
-            return;
-        }
-        unsafe {
-            libc::close(writefd);
-        }
-
-        let r = unsafe { wl::ffi::wl_display_roundtrip(sender.display()) };
-        println!("rt {r}");
-
-        let mut received = Vec::<u8>::new();
-        let mut readbuf = vec![0u8; 8192];
-        loop {
-            let b = unsafe { libc::read(readfd, readbuf.as_mut_ptr() as *mut _, readbuf.len()) };
-            if b <= 0 {
-                break;
+            (Some(uris), None) => AppEvent::AddSpritesByUriList(uris),
+            (None, None) => {
+                tracing::warn!(offered_mime_types = ?offer_session.offered_mime_types, "cannot accept any of offerred mime types");
+                return;
             }
-
-            received.extend(&readbuf[..b as usize]);
-        }
-
-        println!("dnd receive: {}", unsafe {
-            str::from_utf8_unchecked(&received)
-        });
-
-        unsafe {
-            libc::close(readfd);
-        }
+        };
 
         if let Err(e) = offer_session.obj.finish() {
             tracing::warn!(reason = ?e, "Failed to emit finish");
             return;
         }
 
-        self.app_event_bus
-            .push(AppEvent::AddSpritesByUriList(unsafe {
-                str::from_utf8_unchecked(&received)
-                    .split("\r\n")
-                    .filter(|x| !x.starts_with("#"))
-                    .filter(|x| !x.is_empty())
-                    .map(|x| std::ffi::CString::new(x).unwrap())
-                    .collect::<Vec<_>>()
-            }));
+        self.app_event_bus.push(event);
     }
 
     #[tracing::instrument(
@@ -645,11 +1199,19 @@ impl wl::DataDeviceEventListener for WaylandShellEventHandler<'_, '_> {
     fn selection(&mut self, _sender: &mut wl::DataDevice, id: Option<&wl::DataOffer>) {
         tracing::trace!("selection");
 
-        if let (Some(offer), Some(active_offer)) = (id, self.active_data_offer.as_ref())
-            && active_offer.is_offer(offer)
+        let Some(offer) = id else {
+            // selection cleared
+            self.selection_offer = None;
+            return;
+        };
+
+        if self
+            .active_data_offer
+            .as_ref()
+            .is_some_and(|x| x.is_offer(offer))
         {
-            // drop active offer
-            self.active_data_offer = None;
+            // クリップボードの中身として取っておく
+            self.selection_offer = self.active_data_offer.take();
         }
     }
 }
@@ -683,6 +1245,7 @@ impl<'a, 'subsystem> AppShell<'a, 'subsystem> {
             viewporter: Option<wl::Owned<wl::WpViewporter>>,
             zxdg_decoration_manager_v1: Option<wl::Owned<wl::ZxdgDecorationManagerV1>>,
             data_device_manager: Option<wl::Owned<wl::DataDeviceManager>>,
+            text_input_manager: Option<wl::Owned<wl::ZwpTextInputManagerV3>>,
         }
         impl wl::RegistryListener for RegistryListener {
             #[tracing::instrument(name = "RegistryListener::global", skip(self, registry))]
@@ -734,6 +1297,8 @@ impl<'a, 'subsystem> AppShell<'a, 'subsystem> {
                     self.zxdg_decoration_manager_v1 = try_bind(registry, name, version);
                 } else if interface == c"wl_data_device_manager" {
                     self.data_device_manager = try_bind(registry, name, version);
+                } else if interface == c"zwp_text_input_manager_v3" {
+                    self.text_input_manager = try_bind(registry, name, version);
                 }
             }
 
@@ -754,6 +1319,7 @@ impl<'a, 'subsystem> AppShell<'a, 'subsystem> {
             viewporter: None,
             zxdg_decoration_manager_v1: None,
             data_device_manager: None,
+            text_input_manager: None,
         };
         if let Err(e) = registry.add_listener(&mut rl) {
             tracing::warn!(target = "registry", reason = ?e, "Failed to set listener");
@@ -776,6 +1342,7 @@ impl<'a, 'subsystem> AppShell<'a, 'subsystem> {
             viewporter,
             zxdg_decoration_manager_v1,
             data_device_manager,
+            text_input_manager,
         );
         match rl {
             RegistryListener {
@@ -791,6 +1358,7 @@ impl<'a, 'subsystem> AppShell<'a, 'subsystem> {
                 viewporter: Some(viewporter1),
                 zxdg_decoration_manager_v1: zxdg_decoration_manager_v11,
                 data_device_manager: data_device_manager1,
+                text_input_manager: text_input_manager1,
             } => {
                 compositor = compositor1;
                 subcompositor = subcompositor1;
@@ -804,6 +1372,7 @@ impl<'a, 'subsystem> AppShell<'a, 'subsystem> {
                 viewporter = viewporter1;
                 zxdg_decoration_manager_v1 = zxdg_decoration_manager_v11;
                 data_device_manager = data_device_manager1;
+                text_input_manager = text_input_manager1;
             }
             rl => {
                 if rl.compositor.is_none() {
@@ -849,6 +1418,7 @@ impl<'a, 'subsystem> AppShell<'a, 'subsystem> {
 
         struct SeatListener {
             pointer: Option<wl::Owned<wl::Pointer>>,
+            keyboard: Option<wl::Owned<wl::Keyboard>>,
         }
         impl wl::SeatEventListener for SeatListener {
             fn capabilities(&mut self, seat: &mut wl::Seat, capabilities: u32) {
@@ -864,13 +1434,26 @@ impl<'a, 'subsystem> AppShell<'a, 'subsystem> {
                         }
                     };
                 }
+                if (capabilities & 0x02) != 0 {
+                    // keyboard
+                    self.keyboard = match seat.get_keyboard() {
+                        Ok(x) => Some(x),
+                        Err(e) => {
+                            tracing::warn!(reason = ?e, "Failed to get keyboard");
+                            None
+                        }
+                    };
+                }
             }
 
             fn name(&mut self, _seat: &mut wl::Seat, name: &core::ffi::CStr) {
                 tracing::debug!(?name, "seat event");
             }
         }
-        let mut seat_listener = SeatListener { pointer: None };
+        let mut seat_listener = SeatListener {
+            pointer: None,
+            keyboard: None,
+        };
         if let Err(e) = seat.add_listener(&mut seat_listener) {
             tracing::warn!(target = "seat", reason = ?e, "Failed to set listener");
         }
@@ -878,8 +1461,11 @@ impl<'a, 'subsystem> AppShell<'a, 'subsystem> {
             tracing::warn!(reason = ?e, "Failed to roundtrip");
         }
 
-        let mut pointer = match seat_listener {
-            SeatListener { pointer: Some(p) } => p,
+        let (mut pointer, mut keyboard) = match seat_listener {
+            SeatListener {
+                pointer: Some(p),
+                keyboard,
+            } => (p, keyboard),
             _ => {
                 tracing::error!("No pointer from seat");
                 std::process::abort();
@@ -974,13 +1560,30 @@ impl<'a, 'subsystem> AppShell<'a, 'subsystem> {
             None
         };
 
-        let pointer_input_manager =
-            Box::pin(UnsafeCell::new(PointerInputManager::new(640.0, 480.0)));
+        let mut text_input = if let Some(ref m) = text_input_manager {
+            match m.get_text_input(&seat) {
+                Ok(x) => Some(x),
+                Err(e) => {
+                    tracing::warn!(reason = ?e, "Failed to get text input");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        // Note: 最初のconfigureで大きさを指定されなかったときはこの大きさになる
+        let (init_width, init_height) =
+            unsafe { (*base_sys).settings.window_size }.unwrap_or((640, 480));
+        let pointer_input_manager = Box::pin(UnsafeCell::new(PointerInputManager::new(
+            init_width as _,
+            init_height as _,
+        )));
 
         let mut shell_event_handler = Box::new(UnsafeCell::new(WaylandShellEventHandler {
             app_event_bus: events,
-            // 現時点ではわからないので適当な値を設定
-            cached_client_size_px: (640, 480),
+            // 現時点ではわからないので前回の大きさを設定(スケールも未確定なので論理ピクセルのまま)
+            cached_client_size_px: (init_width, init_height),
             buffer_scale: 1,
             ui_scale_factor: 1.0,
             pointer_on_surface: PointerOnSurface::None,
@@ -998,7 +1601,33 @@ impl<'a, 'subsystem> AppShell<'a, 'subsystem> {
             ),
             tiled: false,
             title_bar_last_click: None,
+            pending_scroll: (0.0, 0.0),
+            pending_scroll_from_finger: false,
+            pending_scroll_fling: false,
             active_data_offer: None,
+            selection_offer: None,
+            clipboard_source: None,
+            data_device_manager_proxy_ptr: data_device_manager
+                .as_ref()
+                .map_or_else(core::ptr::null_mut, |x| unsafe { x.copy_ptr().as_ptr() }),
+            data_device_proxy_ptr: data_device
+                .as_ref()
+                .map_or_else(core::ptr::null_mut, |x| unsafe { x.copy_ptr().as_ptr() }),
+            last_input_serial: 0,
+            xkb_context: None,
+            xkb_keymap: None,
+            xkb_state: None,
+            keyboard_focused: false,
+            // repeat_infoが来なかったときの値(westonのデフォルトと同じ)
+            key_repeat_info: (40, 400),
+            key_repeat: None,
+            text_input_proxy_ptr: text_input
+                .as_ref()
+                .map_or_else(core::ptr::null_mut, |x| unsafe { x.copy_ptr().as_ptr() }),
+            text_input_entered: false,
+            text_input_target: None,
+            text_input_applied: None,
+            pending_text_input: PendingTextInput::default(),
         }));
 
         if let Err(e) = pointer.add_listener(shell_event_handler.get_mut()) {
@@ -1053,6 +1682,16 @@ impl<'a, 'subsystem> AppShell<'a, 'subsystem> {
         {
             tracing::warn!(target = "wl_data_device", reason = ?e, "Failed to set listener");
         }
+        if let Some(ref mut x) = keyboard
+            && let Err(e) = x.add_listener(shell_event_handler.get_mut())
+        {
+            tracing::warn!(target = "wl_keyboard", reason = ?e, "Failed to set listener");
+        }
+        if let Some(ref mut x) = text_input
+            && let Err(e) = x.add_listener(shell_event_handler.get_mut())
+        {
+            tracing::warn!(target = "zwp_text_input_v3", reason = ?e, "Failed to set listener");
+        }
 
         'optin_decoration: {
             let Some(ref m) = zxdg_decoration_manager_v1 else {
@@ -1100,6 +1739,15 @@ impl<'a, 'subsystem> AppShell<'a, 'subsystem> {
         if let Some(x) = data_device {
             x.leak();
         }
+        if let Some(x) = keyboard {
+            x.leak();
+        }
+        if let Some(x) = text_input_manager {
+            x.leak();
+        }
+        if let Some(x) = text_input {
+            x.leak();
+        }
         let has_server_side_decoration = zxdg_decoration_manager_v1.is_some();
         if let Some(zxdg_decoration_manager_v1) = zxdg_decoration_manager_v1 {
             zxdg_decoration_manager_v1.leak();
@@ -1299,6 +1947,7 @@ impl<'a, 'subsystem> AppShell<'a, 'subsystem> {
             match shape {
                 CursorShape::Default => WpCursorShapeDeviceV1Shape::Default,
                 CursorShape::Pointer => WpCursorShapeDeviceV1Shape::Pointer,
+                CursorShape::IBeam => WpCursorShapeDeviceV1Shape::Text,
                 CursorShape::ResizeHorizontal => WpCursorShapeDeviceV1Shape::EwResize,
             },
         ) {
@@ -1316,6 +1965,156 @@ impl<'a, 'subsystem> AppShell<'a, 'subsystem> {
         unsafe { &(*self.shell_event_handler.get()).pointer_input_manager }
     }
 
+    /// キーリピートで次にキー入力が発生する時刻
+    pub fn next_key_repeat_time(&self) -> Option<std::time::Instant> {
+        unsafe { &*self.shell_event_handler.get() }
+            .key_repeat
+            .map(|(_, t)| t)
+    }
+
+    pub fn process_key_repeat(&self) {
+        let h = unsafe { &mut *self.shell_event_handler.get() };
+        let Some((key, t)) = h.key_repeat else {
+            return;
+        };
+        let now = std::time::Instant::now();
+        if t > now {
+            return;
+        }
+
+        h.emit_key_down(key);
+        // Note: 遅れた分をまとめて発生させるとあふれるので次の時刻は現在時刻から計算する
+        let interval = std::time::Duration::from_secs(1) / h.key_repeat_info.0.max(1) as u32;
+        h.key_repeat = Some((key, now + interval));
+    }
+
+    pub fn set_text_input_target(&self, target: Option<TextInputTarget>) {
+        let h = unsafe { &mut *self.shell_event_handler.get() };
+        h.text_input_target = target;
+        h.sync_text_input();
+    }
+
+    #[tracing::instrument(skip(self, text))]
+    pub fn set_clipboard_text(&self, text: String) {
+        let text: Rc<[u8]> = text.into_bytes().into();
+        self.set_clipboard(
+            CLIPBOARD_TEXT_MIME_TYPES
+                .iter()
+                .map(|&m| (m, text.clone()))
+                .collect(),
+        );
+    }
+
+    /// PNG画像とそのテキスト表現をクリップボードに置く
+    #[tracing::instrument(skip(self, png, text))]
+    pub fn set_clipboard_image(&self, png: Vec<u8>, text: String) {
+        let text: Rc<[u8]> = text.into_bytes().into();
+        self.set_clipboard(
+            core::iter::once((PNG_MIME_TYPE, png.into()))
+                .chain(CLIPBOARD_TEXT_MIME_TYPES.iter().map(|&m| (m, text.clone())))
+                .collect(),
+        );
+    }
+
+    fn set_clipboard(&self, representations: Vec<(&'static core::ffi::CStr, Rc<[u8]>)>) {
+        let h = unsafe { &mut *self.shell_event_handler.get() };
+        if h.data_device_manager_proxy_ptr.is_null() || h.data_device_proxy_ptr.is_null() {
+            tracing::warn!("No wl_data_device_manager found on the system");
+            return;
+        }
+
+        let source = match unsafe { &*h.data_device_manager_proxy_ptr }.create_data_source() {
+            Ok(x) => x,
+            Err(e) => {
+                tracing::warn!(reason = ?e, "Failed to create data source");
+                return;
+            }
+        };
+        for &(m, _) in representations.iter() {
+            if let Err(e) = source.offer(m) {
+                tracing::warn!(reason = ?e, mime_type = ?m, "Failed to offer mime type");
+            }
+        }
+        let mut session = Box::pin(ClipboardSource {
+            obj: source,
+            representations,
+            cancelled: false,
+        });
+        if let Err(_) = unsafe {
+            session
+                .obj
+                .copy_ptr()
+                .as_mut()
+                .add_listener(session.as_mut().get_mut())
+        } {
+            tracing::warn!("Failed to set wl_data_source listener");
+        }
+
+        if let Err(e) = unsafe { &*h.data_device_proxy_ptr }
+            .set_selection(Some(&session.obj), h.last_input_serial)
+        {
+            tracing::warn!(reason = ?e, "Failed to set selection");
+            return;
+        }
+
+        // 前のsourceはここで破棄される
+        h.clipboard_source = Some(session);
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub fn clipboard_text(&self) -> Option<String> {
+        match String::from_utf8(self.receive_clipboard(CLIPBOARD_TEXT_MIME_TYPES)?) {
+            Ok(x) => Some(x),
+            Err(e) => {
+                tracing::warn!(reason = ?e, "clipboard text is not a valid utf-8 sequence");
+                None
+            }
+        }
+    }
+
+    /// クリップボードにあるPNG画像
+    #[tracing::instrument(skip(self))]
+    pub fn clipboard_png(&self) -> Option<Vec<u8>> {
+        self.receive_clipboard(&[PNG_MIME_TYPE])
+    }
+
+    /// クリップボードにファイルの一覧があれば、ドロップされたときと同じイベントで流す
+    #[tracing::instrument(skip(self))]
+    pub fn paste_clipboard_files(&self) -> bool {
+        let Some(received) = self.receive_clipboard(&[URI_LIST_MIME_TYPE]) else {
+            return false;
+        };
+        let uris = parse_uri_list(&received);
+        if uris.is_empty() {
+            return false;
+        }
+
+        let h = unsafe { &*self.shell_event_handler.get() };
+        h.app_event_bus.push(AppEvent::AddSpritesByUriList(uris));
+        true
+    }
+
+    /// mime_typesの優先順で最初に見つかったクリップボードの中身を読む
+    fn receive_clipboard(&self, mime_types: &[&core::ffi::CStr]) -> Option<Vec<u8>> {
+        let h = unsafe { &mut *self.shell_event_handler.get() };
+        if let Some(ref s) = h.clipboard_source {
+            if !s.cancelled {
+                // Note: 自分自身から受け取ろうとするとsendを処理できずにreadが終わらないので直接返す
+                return s.find(mime_types).map(<[u8]>::to_vec);
+            }
+
+            h.clipboard_source = None;
+        }
+
+        let offer = h.selection_offer.as_ref()?;
+        let Some(mime_type) = offer.find_mime_type(mime_types) else {
+            tracing::debug!(offered_mime_types = ?offer.offered_mime_types, ?mime_types, "no matching content in clipboard");
+            return None;
+        };
+
+        receive_data_offer(&offer.obj, mime_type, || self.flush())
+    }
+
     // wayland specific functionality
     #[tracing::instrument(name = "AppShell::try_export_toplevel", skip(self))]
     pub fn try_export_toplevel(&self) -> Option<ExportedShellData> {
@@ -2026,3 +2825,59 @@ fn try_create_shm_random_suffix(
     tracing::error!("shm creation failed(similar names already exists, try limit reached)");
     Ok(None)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipe() -> (core::ffi::c_int, core::ffi::c_int) {
+        let mut pipefd = [0 as core::ffi::c_int; 2];
+        assert_eq!(unsafe { libc::pipe(pipefd.as_mut_ptr()) }, 0);
+
+        (pipefd[0], pipefd[1])
+    }
+
+    #[test]
+    fn read_until_closed_reads_everything() {
+        let (readfd, writefd) = pipe();
+        let writer = std::thread::spawn(move || {
+            for chunk in [&b"hello, "[..], b"world"] {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                unsafe {
+                    libc::write(writefd, chunk.as_ptr() as *const _, chunk.len());
+                }
+            }
+            unsafe {
+                libc::close(writefd);
+            }
+        });
+
+        let received = read_until_closed(readfd, std::time::Duration::from_secs(5));
+        writer.join().unwrap();
+        unsafe {
+            libc::close(readfd);
+        }
+
+        assert_eq!(received.as_deref(), Some(&b"hello, world"[..]));
+    }
+
+    #[test]
+    fn read_until_closed_gives_up_after_timeout() {
+        let (readfd, writefd) = pipe();
+        unsafe {
+            libc::write(writefd, b"partial".as_ptr() as *const _, 7);
+        }
+
+        let started = std::time::Instant::now();
+        let received = read_until_closed(readfd, std::time::Duration::from_millis(50));
+        let elapsed = started.elapsed();
+        unsafe {
+            libc::close(readfd);
+            libc::close(writefd);
+        }
+
+        assert_eq!(received, None);
+        assert!(elapsed >= std::time::Duration::from_millis(50));
+        assert!(elapsed < std::time::Duration::from_secs(1));
+    }
+}
@@ -10,7 +10,8 @@ use bedrock::{self as br, SurfaceCreateInfo};
 use windows::{
     Win32::{
         Foundation::{
-            E_NOTIMPL, GetLastError, HGLOBAL, HINSTANCE, HWND, LPARAM, LRESULT, POINT, WPARAM,
+            E_NOTIMPL, GetLastError, HANDLE, HGLOBAL, HINSTANCE, HWND, LPARAM, LRESULT, POINT,
+            WPARAM,
         },
         Graphics::{
             Dwm::{
@@ -26,18 +27,28 @@ use windows::{
                 CLSCTX_INPROC_SERVER, CoCreateInstance, DVASPECT_CONTENT, FORMATETC, STGMEDIUM,
                 TYMED_HGLOBAL,
             },
+            DataExchange::{
+                CloseClipboard, EmptyClipboard, GetClipboardData, OpenClipboard,
+                RegisterClipboardFormatW, SetClipboardData,
+            },
             LibraryLoader::GetModuleHandleW,
-            Memory::{GlobalLock, GlobalUnlock},
+            Memory::{
+                GMEM_MOVEABLE, GlobalAlloc, GlobalFree, GlobalLock, GlobalSize, GlobalUnlock,
+            },
             Ole::{
-                CF_HDROP, DROPEFFECT_LINK, IDropTarget, IDropTarget_Impl, OleInitialize,
-                RegisterDragDrop, ReleaseStgMedium,
+                CF_HDROP, CF_UNICODETEXT, DROPEFFECT_LINK, IDropTarget, IDropTarget_Impl,
+                OleInitialize, RegisterDragDrop, ReleaseStgMedium,
             },
             Performance::{QueryPerformanceCounter, QueryPerformanceFrequency},
         },
         UI::{
             Controls::MARGINS,
-            HiDpi::GetDpiForWindow,
-            Input::KeyboardAndMouse::{ReleaseCapture, SetCapture},
+            HiDpi::{GetDpiForSystem, GetDpiForWindow},
+            Input::KeyboardAndMouse::{
+                GetKeyState, ReleaseCapture, SetCapture, VIRTUAL_KEY, VK_A, VK_BACK, VK_C,
+                VK_CONTROL, VK_DELETE, VK_DOWN, VK_END, VK_ESCAPE, VK_HOME, VK_LEFT, VK_LWIN,
+                VK_MENU, VK_RETURN, VK_RIGHT, VK_RWIN, VK_SHIFT, VK_TAB, VK_UP, VK_V, VK_X,
+            },
             Shell::{CLSID_DragDropHelper, DragQueryFileW, HDROP, IDropTargetHelper},
             WindowsAndMessaging::{
                 CREATESTRUCTW, CW_USEDEFAULT, CloseWindow, CreateWindowExW, DefWindowProcW,
@@ -49,9 +60,10 @@ use windows::{
                 RegisterClassExW, SIZE_MAXIMIZED, SIZE_RESTORED, SM_CXSIZEFRAME, SM_CYSIZEFRAME,
                 SW_RESTORE, SW_SHOWMAXIMIZED, SW_SHOWNORMAL, SWP_FRAMECHANGED, SetCursor,
                 SetWindowLongPtrW, SetWindowPos, ShowWindow, TranslateMessage, WM_ACTIVATE,
-                WM_CREATE, WM_DESTROY, WM_DPICHANGED, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MOUSEMOVE,
-                WM_NCCALCSIZE, WM_NCHITTEST, WM_NCLBUTTONDOWN, WM_NCLBUTTONUP, WM_NCMOUSEMOVE,
-                WM_SIZE, WNDCLASS_STYLES, WNDCLASSEXW, WS_EX_APPWINDOW, WS_OVERLAPPEDWINDOW,
+                WM_CHAR, WM_CREATE, WM_DESTROY, WM_DPICHANGED, WM_KEYDOWN, WM_LBUTTONDOWN,
+                WM_LBUTTONUP, WM_MOUSEHWHEEL, WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_NCCALCSIZE,
+                WM_NCHITTEST, WM_NCLBUTTONDOWN, WM_NCLBUTTONUP, WM_NCMOUSEMOVE, WM_SIZE,
+                WNDCLASS_STYLES, WNDCLASSEXW, WS_EX_APPWINDOW, WS_OVERLAPPEDWINDOW,
             },
         },
     },
@@ -64,7 +76,7 @@ use crate::{
     AppEvent, AppEventBus,
     base_system::AppBaseSystem,
     hittest::{CursorShape, HitTestTreeManager, Role},
-    input::PointerInputManager,
+    input::{KeyCode, KeyModifiers, PointerInputManager, TextInputEvent, TextInputTarget},
 };
 
 struct WindowState<'sys, 'subsystem> {
@@ -72,6 +84,8 @@ struct WindowState<'sys, 'subsystem> {
     ui_scale_factor: Cell<f32>,
     pointer_input_manager: UnsafeCell<PointerInputManager>,
     base_sys: *mut AppBaseSystem<'subsystem>,
+    /// WM_CHARで先に来たサロゲートペアの上位
+    pending_high_surrogate: Cell<Option<u16>>,
 }
 
 pub struct AppShell<'sys, 'subsystem> {
@@ -121,7 +135,20 @@ impl<'sys, 'base_sys, 'subsystem> AppShell<'sys, 'subsystem> {
             ui_scale_factor: Cell::new(1.0),
             pointer_input_manager: UnsafeCell::new(PointerInputManager::new(640.0, 480.0)),
             base_sys,
+            pending_high_surrogate: Cell::new(None),
         });
+        // 前回の大きさがあればそれで作る(ウィンドウができるまでDPIがわからないのでシステムのDPIで換算する)
+        let (init_width, init_height) = match unsafe { (*base_sys).settings.window_size } {
+            Some((w, h)) => {
+                let scale = unsafe { GetDpiForSystem() } as f32 / 96.0;
+
+                (
+                    (w as f32 * scale).round() as i32,
+                    (h as f32 * scale).round() as i32,
+                )
+            }
+            None => (CW_USEDEFAULT, CW_USEDEFAULT),
+        };
         let hwnd = unsafe {
             CreateWindowExW(
                 WS_EX_APPWINDOW,
@@ -130,8 +157,8 @@ impl<'sys, 'base_sys, 'subsystem> AppShell<'sys, 'subsystem> {
                 WS_OVERLAPPEDWINDOW,
                 CW_USEDEFAULT,
                 CW_USEDEFAULT,
-                CW_USEDEFAULT,
-                CW_USEDEFAULT,
+                init_width,
+                init_height,
                 None,
                 None,
                 Some(hinstance),
@@ -460,6 +487,69 @@ impl<'sys, 'base_sys, 'subsystem> AppShell<'sys, 'subsystem> {
             return LRESULT(0);
         }
 
+        if msg == WM_KEYDOWN {
+            let key = match VIRTUAL_KEY(wparam.0 as _) {
+                VK_LEFT => KeyCode::Left,
+                VK_RIGHT => KeyCode::Right,
+                VK_UP => KeyCode::Up,
+                VK_DOWN => KeyCode::Down,
+                VK_HOME => KeyCode::Home,
+                VK_END => KeyCode::End,
+                VK_BACK => KeyCode::Backspace,
+                VK_DELETE => KeyCode::Delete,
+                VK_RETURN => KeyCode::Enter,
+                VK_ESCAPE => KeyCode::Escape,
+                VK_TAB => KeyCode::Tab,
+                VK_A => KeyCode::A,
+                VK_C => KeyCode::C,
+                VK_V => KeyCode::V,
+                VK_X => KeyCode::X,
+                _ => KeyCode::Other,
+            };
+            // Note: GetKeyStateは最上位ビットが立っていれば押されている
+            let pressed = |vk: VIRTUAL_KEY| unsafe { GetKeyState(vk.0 as _) } < 0;
+            let mut modifiers = KeyModifiers::empty();
+            if pressed(VK_SHIFT) {
+                modifiers |= KeyModifiers::SHIFT;
+            }
+            if pressed(VK_CONTROL) {
+                modifiers |= KeyModifiers::CTRL;
+            }
+            if pressed(VK_MENU) {
+                modifiers |= KeyModifiers::ALT;
+            }
+            if pressed(VK_LWIN) || pressed(VK_RWIN) {
+                modifiers |= KeyModifiers::SUPER;
+            }
+
+            Self::window_state_ref(hwnd)
+                .app_event_bus
+                .push(AppEvent::MainWindowKeyDown { key, modifiers });
+            return LRESULT(0);
+        }
+
+        if msg == WM_CHAR {
+            let state_ref = Self::window_state_ref(hwnd);
+            let unit = wparam.0 as u16;
+            if (0xd800..0xdc00).contains(&unit) {
+                state_ref.pending_high_surrogate.set(Some(unit));
+                return LRESULT(0);
+            }
+
+            let units = match state_ref.pending_high_surrogate.take() {
+                Some(high) => vec![high, unit],
+                None => vec![unit],
+            };
+            let text = String::from_utf16_lossy(&units);
+            // Ctrl+Aなどで来る制御文字は無視する(WM_KEYDOWNで処理する)
+            if !text.chars().any(char::is_control) {
+                state_ref
+                    .app_event_bus
+                    .push(AppEvent::MainWindowTextInput(TextInputEvent::Commit(text)));
+            }
+            return LRESULT(0);
+        }
+
         if msg == WM_MOUSEMOVE {
             let state_ref = Self::window_state_ref(hwnd);
             let ui_scale_factor = state_ref.ui_scale_factor.get();
@@ -473,6 +563,27 @@ impl<'sys, 'base_sys, 'subsystem> AppShell<'sys, 'subsystem> {
             return LRESULT(0);
         }
 
+        if msg == WM_MOUSEWHEEL || msg == WM_MOUSEHWHEEL {
+            // 1ノッチ(WHEEL_DELTA)で3行ぶんくらい動かす
+            const PIXELS_PER_WHEEL_DELTA: f32 = 48.0 / 120.0;
+            let wheel = ((wparam.0 >> 16) & 0xffff) as i16 as f32 * PIXELS_PER_WHEEL_DELTA;
+
+            // Note: 縦ホイールは上に回すと正なので逆にする
+            let (delta_x, delta_y) = if msg == WM_MOUSEWHEEL {
+                (0.0, -wheel)
+            } else {
+                (wheel, 0.0)
+            };
+            Self::window_state_ref(hwnd)
+                .app_event_bus
+                .push(AppEvent::MainWindowPointerScroll {
+                    delta_x,
+                    delta_y,
+                    fling: false,
+                });
+            return LRESULT(0);
+        }
+
         if msg == WM_NCMOUSEMOVE {
             let mut p = [POINT {
                 x: (lparam.0 & 0xffff) as i16 as _,
@@ -612,6 +723,138 @@ impl<'sys, 'base_sys, 'subsystem> AppShell<'sys, 'subsystem> {
         self.hwnd_state.ui_scale_factor.get()
     }
 
+    pub fn set_text_input_target(&self, _target: Option<TextInputTarget>) {
+        // TODO: IMMで候補ウィンドウの位置を設定する
+    }
+
+    #[tracing::instrument(skip(self, text))]
+    pub fn set_clipboard_text(&self, text: String) {
+        let Some(mem) = alloc_clipboard_text(&text) else {
+            return;
+        };
+
+        self.set_clipboard(vec![(CF_UNICODETEXT.0 as _, mem)]);
+    }
+
+    /// PNG画像とそのテキスト表現をクリップボードに置く
+    #[tracing::instrument(skip(self, png, text))]
+    pub fn set_clipboard_image(&self, png: Vec<u8>, text: String) {
+        let mut formats = Vec::with_capacity(2);
+        if let Some(format) = png_clipboard_format()
+            && let Some(mem) = alloc_clipboard_data(&png)
+        {
+            formats.push((format, mem));
+        }
+        if let Some(mem) = alloc_clipboard_text(&text) {
+            formats.push((CF_UNICODETEXT.0 as _, mem));
+        }
+
+        self.set_clipboard(formats);
+    }
+
+    fn set_clipboard(&self, formats: Vec<(u32, HGLOBAL)>) {
+        let free_all = |formats: Vec<(u32, HGLOBAL)>| {
+            for (_, mem) in formats {
+                let _ = unsafe { GlobalFree(Some(mem)) };
+            }
+        };
+
+        if let Err(e) = unsafe { OpenClipboard(Some(self.hwnd)) } {
+            tracing::warn!(reason = ?e, "OpenClipboard failed");
+            free_all(formats);
+            return;
+        }
+        if let Err(e) = unsafe { EmptyClipboard() } {
+            tracing::warn!(reason = ?e, "EmptyClipboard failed");
+            free_all(formats);
+        } else {
+            for (format, mem) in formats {
+                if let Err(e) = unsafe { SetClipboardData(format, Some(HANDLE(mem.0))) } {
+                    tracing::warn!(reason = ?e, format, "Failed to set clipboard data");
+                    // Note: SetClipboardDataが成功したときだけメモリの所有権がシステムに移る
+                    let _ = unsafe { GlobalFree(Some(mem)) };
+                }
+            }
+        }
+        if let Err(e) = unsafe { CloseClipboard() } {
+            tracing::warn!(reason = ?e, "CloseClipboard failed");
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub fn clipboard_text(&self) -> Option<String> {
+        self.read_clipboard(CF_UNICODETEXT.0 as _, |gl| {
+            let head = gl.ptr as *const u16;
+            let mut len = 0;
+            while unsafe { *head.add(len) } != 0 {
+                len += 1;
+            }
+
+            Some(String::from_utf16_lossy(unsafe {
+                core::slice::from_raw_parts(head, len)
+            }))
+        })
+    }
+
+    /// クリップボードにあるPNG画像
+    #[tracing::instrument(skip(self))]
+    pub fn clipboard_png(&self) -> Option<Vec<u8>> {
+        self.read_clipboard(png_clipboard_format()?, |gl| {
+            let size = unsafe { GlobalSize(gl.handle) };
+            Some(unsafe { core::slice::from_raw_parts(gl.ptr as *const u8, size) }.to_vec())
+        })
+    }
+
+    /// クリップボードにファイルの一覧があれば、ドロップされたときと同じイベントで流す
+    #[tracing::instrument(skip(self))]
+    pub fn paste_clipboard_files(&self) -> bool {
+        let Some(file_paths) = self.read_clipboard(CF_HDROP.0 as _, |gl| {
+            Some(unsafe { query_drop_file_paths(core::mem::transmute(gl.ptr)) })
+        }) else {
+            return false;
+        };
+        if file_paths.is_empty() {
+            return false;
+        }
+
+        self.hwnd_state
+            .app_event_bus
+            .push(AppEvent::AddSpriteByPathList(file_paths));
+        true
+    }
+
+    fn read_clipboard<R>(
+        &self,
+        format: u32,
+        read: impl FnOnce(&LockedHGLOBAL) -> Option<R>,
+    ) -> Option<R> {
+        if let Err(e) = unsafe { OpenClipboard(Some(self.hwnd)) } {
+            tracing::warn!(reason = ?e, "OpenClipboard failed");
+            return None;
+        }
+
+        let r = match unsafe { GetClipboardData(format) } {
+            Ok(h) => match unsafe { LockedHGLOBAL::acquire(HGLOBAL(h.0)) } {
+                Ok(gl) => read(&gl),
+                Err(e) => {
+                    tracing::warn!(reason = ?e, "GlobalLock failed");
+                    None
+                }
+            },
+            Err(e) => {
+                // その形式のデータがない場合もここに来る
+                tracing::debug!(reason = ?e, format, "GetClipboardData failed");
+                None
+            }
+        };
+
+        if let Err(e) = unsafe { CloseClipboard() } {
+            tracing::warn!(reason = ?e, "CloseClipboard failed");
+        }
+
+        r
+    }
+
     #[inline]
     pub fn capture_pointer(&self) {
         unsafe {
@@ -729,24 +972,7 @@ impl IDropTarget_Impl for DropTargetHandler_Impl<'_> {
                 .unwrap()
         });
         let gl = unsafe { LockedHGLOBAL::acquire(data.hglobal_unchecked()).unwrap() };
-        let hdrop: HDROP = unsafe { core::mem::transmute(gl.ptr) };
-        let file_count = unsafe { DragQueryFileW(hdrop, 0xffff_ffff, None) };
-        let mut file_paths = Vec::with_capacity(file_count as _);
-        for n in 0..file_count {
-            let len = unsafe { DragQueryFileW(hdrop, n, None) };
-            let mut path = Vec::with_capacity((len + 1) as _);
-            unsafe {
-                path.set_len(path.capacity());
-            }
-            if unsafe { DragQueryFileW(hdrop, n, Some(&mut path)) } == 0 {
-                tracing::error!("DragQueryFileW(querying file path) failed");
-                panic!("cannot continue");
-            }
-
-            file_paths.push(PathBuf::from(std::ffi::OsString::from_wide(
-                &path[..path.len() - 1],
-            )));
-        }
+        let file_paths = unsafe { query_drop_file_paths(core::mem::transmute(gl.ptr)) };
         drop((gl, data));
         self.app_event_bus
             .push(AppEvent::AddSpriteByPathList(file_paths));
@@ -783,6 +1009,72 @@ impl OwnedStgMedium {
     }
 }
 
+/// クリップボード用に確保したメモリにbytesを詰める
+fn alloc_clipboard_data(bytes: &[u8]) -> Option<HGLOBAL> {
+    let mem = match unsafe { GlobalAlloc(GMEM_MOVEABLE, bytes.len()) } {
+        Ok(x) => x,
+        Err(e) => {
+            tracing::warn!(reason = ?e, "GlobalAlloc failed");
+            return None;
+        }
+    };
+    match unsafe { LockedHGLOBAL::acquire(mem) } {
+        Ok(gl) => unsafe {
+            core::ptr::copy_nonoverlapping(bytes.as_ptr(), gl.ptr as *mut u8, bytes.len());
+        },
+        Err(e) => {
+            tracing::warn!(reason = ?e, "GlobalLock failed");
+            let _ = unsafe { GlobalFree(Some(mem)) };
+            return None;
+        }
+    }
+
+    Some(mem)
+}
+
+/// CF_UNICODETEXT用(NUL終端のUTF-16)
+fn alloc_clipboard_text(text: &str) -> Option<HGLOBAL> {
+    let units = text.encode_utf16().chain([0]).collect::<Vec<_>>();
+
+    alloc_clipboard_data(unsafe {
+        core::slice::from_raw_parts(units.as_ptr() as *const u8, units.len() * 2)
+    })
+}
+
+/// 他のアプリと画像をやりとりするときに使われる"PNG"形式
+fn png_clipboard_format() -> Option<u32> {
+    match unsafe { RegisterClipboardFormatW(w!("PNG")) } {
+        0 => {
+            tracing::warn!(reason = ?std::io::Error::last_os_error(), "RegisterClipboardFormatW failed");
+            None
+        }
+        x => Some(x),
+    }
+}
+
+/// HDROPに入っているファイルのパス
+unsafe fn query_drop_file_paths(hdrop: HDROP) -> Vec<PathBuf> {
+    let file_count = unsafe { DragQueryFileW(hdrop, 0xffff_ffff, None) };
+    let mut file_paths = Vec::with_capacity(file_count as _);
+    for n in 0..file_count {
+        let len = unsafe { DragQueryFileW(hdrop, n, None) };
+        let mut path = Vec::with_capacity((len + 1) as _);
+        unsafe {
+            path.set_len(path.capacity());
+        }
+        if unsafe { DragQueryFileW(hdrop, n, Some(&mut path)) } == 0 {
+            tracing::error!("DragQueryFileW(querying file path) failed");
+            continue;
+        }
+
+        file_paths.push(PathBuf::from(std::ffi::OsString::from_wide(
+            &path[..path.len() - 1],
+        )));
+    }
+
+    file_paths
+}
+
 struct LockedHGLOBAL {
     handle: HGLOBAL,
     ptr: *mut core::ffi::c_void,
@@ -0,0 +1,377 @@
+//! オフスクリーン描画による画面のスナップショットテスト
+//!
+//! `--snapshot-test <dir>`で起動すると、決まった場面をいくつか描いて`<dir>/<場面名>.png`と比べる。
+//! 一致しなかった場面は結果を`<dir>/<場面名>.actual.png`に書き出す。
+//! `--update`をつけると比べずに期待画像を書き直す。
+//! 期待画像がない場面も失敗にする(`--allow-missing-goldens`をつけると警告だけ出して飛ばす)。
+//!
+//! どの場面も同じスプライトをいくつか読み込んで並べ、先頭を選択した状態から始める。
+//!
+//! Note: 文字の描画結果は入っているフォントに左右されるので、期待画像はテストを走らせるのと同じ環境(CIのコンテナなど)で作ること
+//! (例: `VK_ICD_FILENAMES=/usr/share/vulkan/icd.d/lvp_icd.x86_64.json peridot-sprite-atlas-visualizer --snapshot-test snapshots --update`)
+
+use std::{
+    cell::RefCell,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use bedrock as br;
+
+use crate::{
+    AppEvent, AppEventBus,
+    app_state::{AppState, ArrangeOrder},
+    base_system::{AppBaseSystem, ReadbackError},
+    bg_worker::BackgroundWorker,
+    headless::HeadlessApp,
+    subsystem::Subsystem,
+};
+
+const FRAME_SIZE: br::Extent2D = br::Extent2D {
+    width: 960,
+    height: 640,
+};
+const UI_SCALE_FACTOR: f32 = 1.0;
+/// すべてのアニメーションが終わっているはずの時刻
+const SETTLED_SEC: f32 = 10.0;
+/// チャンネルごとに許す差(ドライバによる丸めの違いを吸収する)
+const CHANNEL_TOLERANCE: u8 = 2;
+/// 場面に読み込むスプライト(ファイル名, 幅, 高さ, 色)
+const SEED_SPRITES: &[(&str, u32, u32, [u8; 3])] = &[
+    ("red.png", 64, 64, [0xe0, 0x40, 0x40]),
+    ("green.png", 96, 48, [0x40, 0xc0, 0x60]),
+    ("blue.png", 40, 80, [0x40, 0x70, 0xe0]),
+    ("yellow.png", 32, 32, [0xe0, 0xc0, 0x30]),
+];
+const SEED_ARRANGE_GAP: u32 = 4;
+
+pub struct Options {
+    pub golden_dir: PathBuf,
+    /// 比べずに期待画像を書き直す
+    pub update: bool,
+    /// 期待画像がない場面を失敗にせず飛ばす
+    pub allow_missing_goldens: bool,
+}
+impl Options {
+    /// `--snapshot-test`が指定されていなければNone
+    pub fn from_args(mut args: impl Iterator<Item = OsString>) -> Option<Self> {
+        let mut golden_dir = None;
+        let mut enabled = false;
+        let mut update = false;
+        let mut allow_missing_goldens = false;
+        while let Some(a) = args.next() {
+            if a == "--snapshot-test" {
+                enabled = true;
+                golden_dir = args.next().map(PathBuf::from);
+            } else if a == "--update" {
+                update = true;
+            } else if a == "--allow-missing-goldens" {
+                allow_missing_goldens = true;
+            }
+        }
+
+        enabled.then(|| Self {
+            golden_dir: golden_dir.unwrap_or_else(|| PathBuf::from("snapshots")),
+            update,
+            allow_missing_goldens,
+        })
+    }
+}
+
+struct Scene {
+    name: &'static str,
+    /// 最初のフレームを描いたあとに行う操作
+    setup: fn(&AppEventBus),
+}
+const SCENES: &[Scene] = &[
+    Scene {
+        // ヘッダーとスプライトリスト、編集中のアトラス
+        name: "main_window",
+        setup: |_| (),
+    },
+    Scene {
+        name: "app_menu",
+        setup: |e| e.push(AppEvent::AppMenuToggle),
+    },
+    Scene {
+        name: "arrange_dialog",
+        setup: |e| e.push(AppEvent::AppMenuRequestAutoArrange),
+    },
+];
+
+#[derive(Debug, thiserror::Error)]
+enum SceneError {
+    #[error(transparent)]
+    Vulkan(#[from] br::vk::VkResult),
+    #[error(transparent)]
+    Readback(#[from] ReadbackError),
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+enum Outcome {
+    Matched,
+    Updated,
+    Mismatched { differing_pixels: usize },
+    SizeMismatched { width: u32, height: u32 },
+    NoGolden,
+}
+
+/// すべての場面が期待どおりならtrue
+pub fn run(options: &Options) -> bool {
+    tracing::info!(dir = %options.golden_dir.display(), update = options.update, "Running snapshot tests");
+
+    if options.update
+        && let Err(e) = std::fs::create_dir_all(&options.golden_dir)
+    {
+        tracing::error!(reason = ?e, "Failed to create snapshot directory");
+        return false;
+    }
+
+    let seed_dir = std::env::temp_dir().join(format!("peridot-snapshot-{}", uuid::Uuid::new_v4()));
+    let seed_sprites = match write_seed_sprites(&seed_dir) {
+        Ok(x) => x,
+        Err(e) => {
+            tracing::error!(reason = %e, "Failed to write seed sprites");
+            return false;
+        }
+    };
+
+    let subsystem = Subsystem::init();
+    let events = AppEventBus::new();
+    // Note: 読み込みが終わるタイミングで結果が変わらないように、スプライトの読み込みは描画の前に同期的に済ませる
+    let bg_worker = BackgroundWorker::new_synchronous();
+
+    let mut passed = true;
+    for s in SCENES {
+        match run_scene(&subsystem, &events, &bg_worker, s, &seed_sprites, options) {
+            Ok(Outcome::Matched) => tracing::info!(scene = s.name, "ok"),
+            Ok(Outcome::Updated) => tracing::info!(scene = s.name, "updated"),
+            Ok(Outcome::Mismatched { differing_pixels }) => {
+                tracing::error!(scene = s.name, differing_pixels, "snapshot mismatch");
+                passed = false;
+            }
+            Ok(Outcome::SizeMismatched { width, height }) => {
+                tracing::error!(scene = s.name, width, height, "golden image size mismatch");
+                passed = false;
+            }
+            Ok(Outcome::NoGolden) if options.allow_missing_goldens => {
+                tracing::warn!(
+                    scene = s.name,
+                    "no golden image, skipped (run with --update to create)"
+                );
+            }
+            Ok(Outcome::NoGolden) => {
+                tracing::error!(
+                    scene = s.name,
+                    "no golden image (run with --update to create)"
+                );
+                passed = false;
+            }
+            Err(e) => {
+                tracing::error!(scene = s.name, reason = %e, "snapshot failed");
+                passed = false;
+            }
+        }
+
+        // 次の場面に前の場面のイベントを持ち越さない
+        while events.pop().is_some() {}
+    }
+
+    bg_worker.teardown();
+    if let Err(e) = std::fs::remove_dir_all(&seed_dir) {
+        tracing::warn!(reason = ?e, dir = %seed_dir.display(), "Failed to remove seed sprites");
+    }
+    passed
+}
+
+/// 場面に読み込むスプライトの画像をdirに書き出す
+fn write_seed_sprites(dir: &Path) -> Result<Vec<PathBuf>, SceneError> {
+    std::fs::create_dir_all(dir)?;
+
+    SEED_SPRITES
+        .iter()
+        .map(
+            |&(name, width, height, [r, g, b])| -> Result<PathBuf, SceneError> {
+                // Note: 向きがわかるように左上の角だけ暗くしておく
+                let image = image::RgbaImage::from_fn(width, height, |x, y| {
+                    if x < width / 4 && y < height / 4 {
+                        image::Rgba([r / 2, g / 2, b / 2, 0xff])
+                    } else {
+                        image::Rgba([r, g, b, 0xff])
+                    }
+                });
+                let path = dir.join(name);
+                image.save(&path)?;
+
+                Ok(path)
+            },
+        )
+        .collect()
+}
+
+fn run_scene<'subsystem>(
+    subsystem: &'subsystem Subsystem,
+    events: &AppEventBus,
+    bg_worker: &BackgroundWorker<'subsystem>,
+    scene: &Scene,
+    seed_sprites: &[PathBuf],
+    options: &Options,
+) -> Result<Outcome, SceneError> {
+    // Note: ビューはベースシステム側のツリーに残るので、場面ごとに作り直す
+    let mut base_system = AppBaseSystem::new_ephemeral(subsystem);
+    base_system.rescale_fonts(UI_SCALE_FACTOR);
+    let state = RefCell::new(AppState::new());
+
+    let pixels = {
+        let mut app = HeadlessApp::new(
+            &mut base_system,
+            &state,
+            events,
+            bg_worker,
+            FRAME_SIZE,
+            UI_SCALE_FACTOR,
+        )?;
+        app.advance(0.0)?;
+        events.push(AppEvent::AddSpriteByPathList(seed_sprites.to_vec()));
+        events.push(AppEvent::AutoArrange {
+            allow_rotation: false,
+            gap: SEED_ARRANGE_GAP,
+            order: ArrangeOrder::ListOrder,
+        });
+        events.push(AppEvent::SelectSprite { index: 0 });
+        app.advance(0.0)?;
+        // 追加したスプライトの読み込みはここで終わる
+        app.advance(0.0)?;
+        (scene.setup)(events);
+        app.advance(0.0)?;
+        app.advance(SETTLED_SEC)?;
+        // アニメーションの完了で発生したイベントを処理したあとの状態を撮る
+        app.advance(SETTLED_SEC)?;
+
+        app.read_pixels()?
+    };
+    let actual = image::RgbaImage::from_raw(FRAME_SIZE.width, FRAME_SIZE.height, pixels)
+        .expect("readback size mismatch");
+
+    let golden_path = options.golden_dir.join(format!("{}.png", scene.name));
+    if options.update {
+        actual.save(&golden_path)?;
+        return Ok(Outcome::Updated);
+    }
+
+    let golden = match image::open(&golden_path) {
+        Ok(x) => x.into_rgba8(),
+        Err(image::ImageError::IoError(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Outcome::NoGolden);
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if golden.dimensions() != actual.dimensions() {
+        let (width, height) = golden.dimensions();
+        return Ok(Outcome::SizeMismatched { width, height });
+    }
+
+    let differing_pixels = count_differing_pixels(&golden, &actual);
+    if differing_pixels == 0 {
+        return Ok(Outcome::Matched);
+    }
+
+    actual.save(
+        options
+            .golden_dir
+            .join(format!("{}.actual.png", scene.name)),
+    )?;
+    Ok(Outcome::Mismatched { differing_pixels })
+}
+
+/// チャンネルのどれかがCHANNEL_TOLERANCEより大きく違うピクセルの数(大きさは同じであること)
+fn count_differing_pixels(golden: &image::RgbaImage, actual: &image::RgbaImage) -> usize {
+    golden
+        .pixels()
+        .zip(actual.pixels())
+        .filter(|(g, a)| {
+            g.0.iter()
+                .zip(a.0.iter())
+                .any(|(&g, &a)| g.abs_diff(a) > CHANNEL_TOLERANCE)
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(xs: &[&str]) -> impl Iterator<Item = OsString> {
+        xs.iter()
+            .map(OsString::from)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn options_from_args() {
+        assert!(Options::from_args(args(&["--update"])).is_none());
+
+        let o = Options::from_args(args(&["--snapshot-test", "out"])).unwrap();
+        assert_eq!(o.golden_dir, Path::new("out"));
+        assert!(!o.update);
+        assert!(!o.allow_missing_goldens);
+
+        let o = Options::from_args(args(&[
+            "--update",
+            "--snapshot-test",
+            "out",
+            "--allow-missing-goldens",
+        ]))
+        .unwrap();
+        assert!(o.update);
+        assert!(o.allow_missing_goldens);
+
+        let o = Options::from_args(args(&["--snapshot-test"])).unwrap();
+        assert_eq!(o.golden_dir, Path::new("snapshots"));
+    }
+
+    #[test]
+    fn differing_pixels_respect_tolerance() {
+        let golden = image::RgbaImage::from_pixel(4, 4, image::Rgba([100, 100, 100, 255]));
+        let mut actual = golden.clone();
+        actual.put_pixel(0, 0, image::Rgba([100 + CHANNEL_TOLERANCE, 100, 100, 255]));
+        assert_eq!(count_differing_pixels(&golden, &actual), 0);
+
+        actual.put_pixel(
+            1,
+            0,
+            image::Rgba([100, 100 - CHANNEL_TOLERANCE - 1, 100, 255]),
+        );
+        actual.put_pixel(2, 0, image::Rgba([100, 100, 100, 0]));
+        assert_eq!(count_differing_pixels(&golden, &actual), 2);
+    }
+
+    #[test]
+    fn seed_sprites_are_written() {
+        let dir = std::env::temp_dir().join(format!("peridot-snapshot-{}", uuid::Uuid::new_v4()));
+        let paths = write_seed_sprites(&dir).unwrap();
+        assert_eq!(paths.len(), SEED_SPRITES.len());
+        for (p, &(_, width, height, _)) in paths.iter().zip(SEED_SPRITES) {
+            assert_eq!(image::image_dimensions(p).unwrap(), (width, height));
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// リポジトリのsnapshots/にある期待画像と比べる(期待画像の作り方はsnapshots/README.mdを参照)
+    #[test]
+    #[ignore = "needs a Vulkan device (e.g. lavapipe); run with `cargo test -- --ignored`"]
+    fn snapshots_match_goldens() {
+        let options = Options {
+            golden_dir: Path::new(env!("CARGO_MANIFEST_DIR")).join("snapshots"),
+            update: false,
+            allow_missing_goldens: false,
+        };
+
+        assert!(run(&options));
+    }
+}
@@ -0,0 +1,417 @@
+//! スプライトのソース画像のパスの扱い
+
+use std::{
+    ffi::OsString,
+    path::{Component, Path, PathBuf},
+};
+
+/// `.`と`..`を字句的に畳む(シンボリックリンクは解決しない)
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => (),
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                // ルートより上には行けない
+                Some(Component::RootDir | Component::Prefix(_)) => (),
+                // Note: 相対パスの先頭の..は畳めないので残す
+                _ => normalized.push(c),
+            },
+            c => normalized.push(c),
+        }
+    }
+
+    normalized
+}
+
+/// base_dirからtargetへの相対パス
+///
+/// どちらも絶対パスで、ルート(Windowsならドライブ)が同じときだけ作れる
+/// 別の環境でも読めるように区切りは常に`/`にする
+pub fn relative_to(target: &Path, base_dir: &Path) -> Option<PathBuf> {
+    if !target.is_absolute() || !base_dir.is_absolute() {
+        return None;
+    }
+
+    let (target, base_dir) = (normalize(target), normalize(base_dir));
+    let (mut target_components, mut base_components) = (
+        target.components().peekable(),
+        base_dir.components().peekable(),
+    );
+    if target_components.peek() != base_components.peek() {
+        // different root
+        return None;
+    }
+    while let (Some(a), Some(b)) = (target_components.peek(), base_components.peek()) {
+        if a != b {
+            break;
+        }
+
+        target_components.next();
+        base_components.next();
+    }
+
+    let mut relative = OsString::new();
+    for c in base_components
+        .map(|_| Component::ParentDir)
+        .chain(target_components)
+    {
+        if !relative.is_empty() {
+            relative.push("/");
+        }
+        relative.push(c.as_os_str());
+    }
+
+    Some(relative.into())
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FileUriError {
+    #[error("not a local file")]
+    NotFileUri,
+    #[error("file on another host({0}) is not supported")]
+    RemoteHost(String),
+    #[error("invalid percent-encoding")]
+    InvalidPercentEncoding,
+    #[error("not an absolute path")]
+    NotAbsolute,
+    #[cfg(windows)]
+    #[error("path is not a valid utf-8 sequence")]
+    InvalidUtf8,
+}
+
+/// RFC 8089の`file` URIをローカルのパスにする
+///
+/// ホストは空か`localhost`のときだけ受け付ける(`file:/path`の短い形も可)
+/// パスのパーセントエンコーディングはバイト列としてデコードする
+pub fn path_from_file_uri(uri: &[u8]) -> Result<PathBuf, FileUriError> {
+    let Some(colon) = uri.iter().position(|&b| b == b':') else {
+        return Err(FileUriError::NotFileUri);
+    };
+    let (scheme, rest) = (&uri[..colon], &uri[colon + 1..]);
+    if !scheme.eq_ignore_ascii_case(b"file") {
+        return Err(FileUriError::NotFileUri);
+    }
+    // クエリとフラグメントはパスに関係ない
+    let rest = rest
+        .split(|&b| b == b'?' || b == b'#')
+        .next()
+        .unwrap_or(rest);
+
+    let path = match rest.strip_prefix(b"//") {
+        Some(auth_path) => {
+            let (host, path) = auth_path.split_at(
+                auth_path
+                    .iter()
+                    .position(|&b| b == b'/')
+                    .unwrap_or(auth_path.len()),
+            );
+            if !host.is_empty() && !host.eq_ignore_ascii_case(b"localhost") {
+                return Err(FileUriError::RemoteHost(
+                    String::from_utf8_lossy(&percent_decode(host)?).into_owned(),
+                ));
+            }
+
+            path
+        }
+        None => rest,
+    };
+    if !path.starts_with(b"/") {
+        return Err(FileUriError::NotAbsolute);
+    }
+
+    path_from_decoded_bytes(percent_decode(path)?)
+}
+
+fn percent_decode(bytes: &[u8]) -> Result<Vec<u8>, FileUriError> {
+    let hex = |b: Option<&u8>| b.and_then(|&b| (b as char).to_digit(16));
+
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter();
+    while let Some(&b) = iter.next() {
+        if b != b'%' {
+            decoded.push(b);
+            continue;
+        }
+
+        let (Some(hi), Some(lo)) = (hex(iter.next()), hex(iter.next())) else {
+            return Err(FileUriError::InvalidPercentEncoding);
+        };
+        decoded.push((hi << 4 | lo) as u8);
+    }
+
+    Ok(decoded)
+}
+
+#[cfg(unix)]
+fn path_from_decoded_bytes(bytes: Vec<u8>) -> Result<PathBuf, FileUriError> {
+    use std::os::unix::ffi::OsStringExt;
+
+    Ok(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+#[cfg(windows)]
+fn path_from_decoded_bytes(bytes: Vec<u8>) -> Result<PathBuf, FileUriError> {
+    let path = String::from_utf8(bytes).map_err(|_| FileUriError::InvalidUtf8)?;
+    // Note: `/C:/...`のドライブレターの前の`/`は要らない
+    let path = match path.as_bytes() {
+        [b'/', d, b':', ..] if d.is_ascii_alphabetic() => &path[1..],
+        _ => &path[..],
+    };
+
+    Ok(PathBuf::from(path.replace('/', "\\")))
+}
+
+/// サンドボックスの中から見えるパス(Documents portalのマウント先の下)とホスト側のパスの対応
+///
+/// サンドボックスの外で動いているときは空のままで、どちらの向きにもそのままのパスを返す
+#[derive(Debug, Clone, Default)]
+pub struct DocumentPathMapping {
+    mount_point: Option<PathBuf>,
+    /// (サンドボックス内のパス, ホストのパス)
+    entries: Vec<(PathBuf, PathBuf)>,
+}
+impl DocumentPathMapping {
+    pub const fn new() -> Self {
+        Self {
+            mount_point: None,
+            entries: Vec::new(),
+        }
+    }
+
+    pub const fn with_mount_point(mount_point: PathBuf) -> Self {
+        Self {
+            mount_point: Some(mount_point),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, sandbox_path: PathBuf, host_path: PathBuf) {
+        self.entries.push((sandbox_path, host_path));
+    }
+
+    /// ホスト側のパスにする
+    ///
+    /// マウント先の下にあって対応がわからないものはNone
+    pub fn to_host(&self, path: &Path) -> Option<PathBuf> {
+        if let Some(p) = Self::deepest_match(
+            self.entries.iter().map(|(s, h)| (s.as_path(), h.as_path())),
+            path,
+        ) {
+            return Some(p);
+        }
+
+        match self.mount_point {
+            Some(ref m) if path.starts_with(m) => None,
+            _ => Some(path.to_path_buf()),
+        }
+    }
+
+    /// ホスト側のパスをサンドボックスの中から見えるパスにする
+    ///
+    /// 公開されていなければNone(そのままのパスで読めるかもしれないので呼び出し側で確かめる)
+    pub fn to_sandbox(&self, host_path: &Path) -> Option<PathBuf> {
+        Self::deepest_match(
+            self.entries.iter().map(|(s, h)| (h.as_path(), s.as_path())),
+            host_path,
+        )
+    }
+
+    /// (変換元, 変換先)の組のうちpathに一番深く一致する変換元で置き換える
+    ///
+    /// Note: フォルダごと公開されているものと中のファイルが個別に公開されているものがありうるので、どちらの向きでも一番深く一致するものを使う
+    fn deepest_match<'a>(
+        pairs: impl Iterator<Item = (&'a Path, &'a Path)>,
+        path: &Path,
+    ) -> Option<PathBuf> {
+        pairs
+            .filter_map(|(from, to)| {
+                let rest = path.strip_prefix(from).ok()?;
+
+                Some((from.components().count(), to, rest))
+            })
+            .max_by_key(|&(depth, _, _)| depth)
+            .map(|(_, to, rest)| {
+                if rest.as_os_str().is_empty() {
+                    to.to_path_buf()
+                } else {
+                    to.join(rest)
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_decode_bytes() {
+        assert_eq!(percent_decode(b"a%20b").unwrap(), b"a b");
+        assert_eq!(percent_decode(b"%41%6a%2F").unwrap(), b"Aj/");
+        assert_eq!(percent_decode(b"%E3%81%82").unwrap(), "あ".as_bytes());
+        assert_eq!(percent_decode(b"%ff").unwrap(), [0xff]);
+        assert!(matches!(
+            percent_decode(b"%zz"),
+            Err(FileUriError::InvalidPercentEncoding)
+        ));
+        assert!(matches!(
+            percent_decode(b"abc%2"),
+            Err(FileUriError::InvalidPercentEncoding)
+        ));
+    }
+
+    #[test]
+    fn file_uri_host() {
+        #[cfg(unix)]
+        {
+            let expected = Path::new("/tmp/a.png");
+            assert_eq!(path_from_file_uri(b"file:///tmp/a.png").unwrap(), expected);
+            assert_eq!(
+                path_from_file_uri(b"file://localhost/tmp/a.png").unwrap(),
+                expected
+            );
+            assert_eq!(
+                path_from_file_uri(b"FILE://LocalHost/tmp/a.png").unwrap(),
+                expected
+            );
+            // 短い形
+            assert_eq!(path_from_file_uri(b"file:/tmp/a.png").unwrap(), expected);
+        }
+
+        assert!(matches!(
+            path_from_file_uri(b"file://example.com/tmp/a.png"),
+            Err(FileUriError::RemoteHost(h)) if h == "example.com"
+        ));
+        assert!(matches!(
+            path_from_file_uri(b"https://localhost/tmp/a.png"),
+            Err(FileUriError::NotFileUri)
+        ));
+        assert!(matches!(
+            path_from_file_uri(b"/tmp/a.png"),
+            Err(FileUriError::NotFileUri)
+        ));
+        assert!(matches!(
+            path_from_file_uri(b"file:tmp/a.png"),
+            Err(FileUriError::NotAbsolute)
+        ));
+        assert!(matches!(
+            path_from_file_uri(b"file://localhost"),
+            Err(FileUriError::NotAbsolute)
+        ));
+    }
+
+    #[test]
+    fn file_uri_strips_query_and_fragment() {
+        #[cfg(unix)]
+        {
+            assert_eq!(
+                path_from_file_uri(b"file:///tmp/a%20b.png?x=1#top").unwrap(),
+                Path::new("/tmp/a b.png")
+            );
+            assert_eq!(
+                path_from_file_uri(b"file:///tmp/a.png#frag?not-a-query").unwrap(),
+                Path::new("/tmp/a.png")
+            );
+            // エンコードされていれば?や#もパスの一部
+            assert_eq!(
+                path_from_file_uri(b"file:///tmp/%23%3F.png").unwrap(),
+                Path::new("/tmp/#?.png")
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_uri_non_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        assert_eq!(
+            path_from_file_uri(b"file:///tmp/%ff.png")
+                .unwrap()
+                .as_os_str()
+                .as_bytes(),
+            b"/tmp/\xff.png"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn document_mapping_uses_deepest_match() {
+        let mut mapping = DocumentPathMapping::with_mount_point(PathBuf::from("/run/doc"));
+        // フォルダごとの公開と、その中のファイルの個別の公開
+        mapping.insert(
+            PathBuf::from("/run/doc/aaa/assets"),
+            PathBuf::from("/home/u/assets"),
+        );
+        mapping.insert(
+            PathBuf::from("/run/doc/bbb/a.png"),
+            PathBuf::from("/home/u/assets/sub/a.png"),
+        );
+
+        assert_eq!(
+            mapping.to_host(Path::new("/run/doc/bbb/a.png")),
+            Some(PathBuf::from("/home/u/assets/sub/a.png"))
+        );
+        assert_eq!(
+            mapping.to_host(Path::new("/run/doc/aaa/assets/sub/b.png")),
+            Some(PathBuf::from("/home/u/assets/sub/b.png"))
+        );
+        assert_eq!(mapping.to_host(Path::new("/run/doc/ccc/x.png")), None);
+        assert_eq!(
+            mapping.to_host(Path::new("/tmp/x.png")),
+            Some(PathBuf::from("/tmp/x.png"))
+        );
+
+        assert_eq!(
+            mapping.to_sandbox(Path::new("/home/u/assets/sub/a.png")),
+            Some(PathBuf::from("/run/doc/bbb/a.png"))
+        );
+        assert_eq!(
+            mapping.to_sandbox(Path::new("/home/u/assets/sub/b.png")),
+            Some(PathBuf::from("/run/doc/aaa/assets/sub/b.png"))
+        );
+        assert_eq!(mapping.to_sandbox(Path::new("/home/u/other.png")), None);
+
+        // 登録順に依らない
+        let mut reversed = DocumentPathMapping::with_mount_point(PathBuf::from("/run/doc"));
+        reversed.insert(
+            PathBuf::from("/run/doc/aaa/assets/sub"),
+            PathBuf::from("/home/u/other/sub"),
+        );
+        reversed.insert(
+            PathBuf::from("/run/doc/aaa/assets"),
+            PathBuf::from("/home/u/assets"),
+        );
+        assert_eq!(
+            reversed.to_host(Path::new("/run/doc/aaa/assets/sub/c.png")),
+            Some(PathBuf::from("/home/u/other/sub/c.png"))
+        );
+    }
+
+    #[test]
+    fn file_uri_drive_letter() {
+        #[cfg(windows)]
+        {
+            assert_eq!(
+                path_from_file_uri(b"file:///C:/Users/a.png").unwrap(),
+                Path::new("C:\\Users\\a.png")
+            );
+            assert_eq!(
+                path_from_file_uri(b"file:/c:/a%20b.png").unwrap(),
+                Path::new("c:\\a b.png")
+            );
+        }
+        #[cfg(unix)]
+        {
+            // Note: unixではドライブレターは特別扱いしない
+            assert_eq!(
+                path_from_file_uri(b"file:///C:/Users/a.png").unwrap(),
+                Path::new("/C:/Users/a.png")
+            );
+        }
+    }
+}
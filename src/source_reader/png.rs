@@ -4,6 +4,23 @@ const fn as_mut_u8_slice<T>(sink: &mut T) -> &mut [u8] {
     unsafe { core::slice::from_raw_parts_mut(sink as *mut _ as _, core::mem::size_of::<T>()) }
 }
 
+/// 全部埋まるまで読む(途中で終わったり読めなかったりしたらNone)
+fn read_vectored_exact(
+    reader: &mut (impl Read + ?Sized),
+    mut bufs: &mut [IoSliceMut],
+) -> Option<()> {
+    while !bufs.is_empty() {
+        match reader.read_vectored(bufs) {
+            Ok(0) => return None,
+            Ok(r) => IoSliceMut::advance_slices(&mut bufs, r),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+            Err(_) => return None,
+        }
+    }
+
+    Some(())
+}
+
 pub struct Metadata {
     pub width: u32,
     pub height: u32,
@@ -11,7 +28,7 @@ pub struct Metadata {
 impl Metadata {
     pub fn try_read(reader: &mut (impl Read + ?Sized)) -> Option<Self> {
         let mut buf = [0u8; 8];
-        reader.read_exact(&mut buf).unwrap();
+        reader.read_exact(&mut buf).ok()?;
         if buf != [137, 80, 78, 71, 13, 10, 26, 10] {
             // signature mismatch
             return None;
@@ -20,32 +37,32 @@ impl Metadata {
         // find ihdr
         let mut chunk_data_byte_length = 0u32;
         let mut chunk_type = [0u8; 4];
-        let mut readbuf = &mut [
-            IoSliceMut::new(as_mut_u8_slice(&mut chunk_data_byte_length)),
-            IoSliceMut::new(&mut chunk_type),
-        ][..];
-        while !readbuf.is_empty() {
-            let r = reader.read_vectored(readbuf).unwrap();
-            IoSliceMut::advance_slices(&mut readbuf, r);
-        }
+        read_vectored_exact(
+            reader,
+            &mut [
+                IoSliceMut::new(as_mut_u8_slice(&mut chunk_data_byte_length)),
+                IoSliceMut::new(&mut chunk_type),
+            ],
+        )?;
         let chunk_data_byte_length = u32::from_be(chunk_data_byte_length);
         if chunk_type != *b"IHDR" {
-            panic!("invalid png format: no IHDR chunk at head");
+            tracing::warn!("invalid png format: no IHDR chunk at head");
+            return None;
         }
         if chunk_data_byte_length < 8 {
-            panic!("IHDR chunk is too short");
+            tracing::warn!("IHDR chunk is too short");
+            return None;
         }
 
         let mut width = 0u32;
         let mut height = 0u32;
-        let mut readbuf = &mut [
-            IoSliceMut::new(as_mut_u8_slice(&mut width)),
-            IoSliceMut::new(as_mut_u8_slice(&mut height)),
-        ][..];
-        while !readbuf.is_empty() {
-            let r = reader.read_vectored(readbuf).unwrap();
-            IoSliceMut::advance_slices(&mut readbuf, r);
-        }
+        read_vectored_exact(
+            reader,
+            &mut [
+                IoSliceMut::new(as_mut_u8_slice(&mut width)),
+                IoSliceMut::new(as_mut_u8_slice(&mut height)),
+            ],
+        )?;
 
         Some(Self {
             width: u32::from_be(width),
@@ -97,6 +97,7 @@ pub struct Subsystem {
     graphics_queue: br::vk::VkQueue,
     pub ft: RwLock<FreeType>,
     vk_ext_commands: SubsystemExtCommandCache,
+    incremental_present_supported: bool,
 }
 unsafe impl Sync for Subsystem {}
 unsafe impl Send for Subsystem {}
@@ -351,9 +352,45 @@ impl Subsystem {
             );
         }
 
+        let incremental_present_supported = unsafe {
+            let mut count = 0;
+            let _ = br::vkfn::enumerate_device_extension_properties(
+                adapter.native_ptr(),
+                core::ptr::null(),
+                &mut count,
+                core::ptr::null_mut(),
+            );
+            let mut props = Vec::<br::vk::VkExtensionProperties>::with_capacity(count as _);
+            let _ = br::vkfn::enumerate_device_extension_properties(
+                adapter.native_ptr(),
+                core::ptr::null(),
+                &mut count,
+                props.as_mut_ptr(),
+            );
+            props.set_len(count as _);
+
+            props.iter().any(|x| {
+                x.extensionName
+                    .as_cstr()
+                    .is_ok_and(|n| n == c"VK_KHR_incremental_present")
+            })
+        };
+        tracing::debug!(incremental_present_supported, "device extensions");
+
         let graphics_queue_family_index = adapter_queue_info
             .find_matching_index(br::QueueFlags::GRAPHICS)
             .unwrap();
+        let mut device_extensions = vec![c"VK_KHR_swapchain".into()];
+        #[cfg(feature = "platform-macos")]
+        device_extensions.extend([
+            c"VK_KHR_portability_subset".into(),
+            c"VK_KHR_synchronization2".into(),
+            c"VK_KHR_create_renderpass2".into(),
+        ]);
+        if incremental_present_supported {
+            // 変化した領域をpresentで合成側に伝える
+            device_extensions.push(c"VK_KHR_incremental_present".into());
+        }
         let device = br::DeviceObject::new(
             &adapter,
             &br::DeviceCreateInfo::new(
@@ -362,15 +399,7 @@ impl Subsystem {
                     &[1.0],
                 )],
                 &[],
-                &[
-                    c"VK_KHR_swapchain".into(),
-                    #[cfg(feature = "platform-macos")]
-                    c"VK_KHR_portability_subset".into(),
-                    #[cfg(feature = "platform-macos")]
-                    c"VK_KHR_synchronization2".into(),
-                    #[cfg(feature = "platform-macos")]
-                    c"VK_KHR_create_renderpass2".into(),
-                ],
+                &device_extensions,
             )
             .with_next(
                 &br::PhysicalDeviceFeatures2::new(br::vk::VkPhysicalDeviceFeatures {
@@ -422,9 +451,15 @@ impl Subsystem {
             adapter_properties,
             ft: RwLock::new(ft),
             vk_ext_commands: SubsystemExtCommandCache::new(),
+            incremental_present_supported,
         }
     }
 
+    /// VK_KHR_incremental_presentが有効か
+    pub const fn supports_incremental_present(&self) -> bool {
+        self.incremental_present_supported
+    }
+
     pub const fn adapter(&self) -> &impl PhysicalDevice {
         unsafe { core::mem::transmute::<_, &SubsystemAdapterAccess>(self) }
     }
@@ -1,11 +1,17 @@
 use freetype as ft;
 use harfbuzz as hb;
 
-use crate::base_system::scratch_buffer::{
-    StagingScratchBuffer, StagingScratchBufferMapMode, StagingScratchBufferReservation,
+use std::num::NonZeroUsize;
+
+use crate::{
+    base_system::scratch_buffer::{
+        StagingScratchBuffer, StagingScratchBufferMapMode, StagingScratchBufferReservation,
+    },
+    helper_types::SafeF32,
 };
 
-struct GlyphBitmap {
+/// 1グリフのラスタライズ結果
+pub struct GlyphBitmap {
     pub buf: Box<[u8]>,
     pub width: usize,
     pub pitch: usize,
@@ -14,11 +20,23 @@ struct GlyphBitmap {
     pub ascending_pixels: isize,
 }
 impl GlyphBitmap {
-    pub fn copy_from_ft_glyph_slot(slot: &ft::GlyphSlotRec) -> Self {
-        assert!(
-            slot.bitmap.pitch >= 0,
-            "inverted flow is not supported at this point"
-        );
+    pub fn empty() -> Self {
+        Self {
+            buf: Box::new([]),
+            width: 0,
+            pitch: 0,
+            rows: 0,
+            left_offset: 0,
+            ascending_pixels: 0,
+        }
+    }
+
+    /// 8bitグレースケール以外(BGRAのカラービットマップ等)や下から上に並んでいるものはNone
+    pub fn copy_from_ft_glyph_slot(slot: &ft::GlyphSlotRec) -> Option<Self> {
+        if slot.bitmap.pixel_mode != ft::PIXEL_MODE_GRAY || slot.bitmap.pitch < 0 {
+            return None;
+        }
+
         let bytes = slot.bitmap.pitch as usize * slot.bitmap.rows as usize;
         let mut buf = Vec::with_capacity(bytes);
         unsafe {
@@ -29,86 +47,825 @@ impl GlyphBitmap {
             core::ptr::copy_nonoverlapping(slot.bitmap.buffer, buf.as_mut_ptr(), bytes);
         }
 
-        Self {
+        Some(Self {
             buf,
             width: slot.bitmap.width as _,
             pitch: slot.bitmap.pitch as _,
             rows: slot.bitmap.rows as _,
             left_offset: slot.bitmap_left as _,
             ascending_pixels: slot.bitmap_top as _,
+        })
+    }
+
+    /// カラービットマップ(BGRA)はアルファをマスクとして取り出し、scale倍に縮小/拡大する
+    ///
+    /// ビットマップしか持たないフェイス(カラー絵文字等)のストライクを要求サイズに合わせる用
+    pub fn copy_scaled_from_ft_glyph_slot(slot: &ft::GlyphSlotRec, scale: f32) -> Option<Self> {
+        let bitmap = &slot.bitmap;
+        if bitmap.pitch < 0 {
+            return None;
+        }
+
+        let (width, rows, pitch) = (
+            bitmap.width as usize,
+            bitmap.rows as usize,
+            bitmap.pitch as usize,
+        );
+        let src = if bitmap.buffer.is_null() {
+            &[][..]
+        } else {
+            unsafe { core::slice::from_raw_parts(bitmap.buffer, pitch * rows) }
+        };
+        let mask = match bitmap.pixel_mode {
+            ft::PIXEL_MODE_GRAY => (0..rows)
+                .flat_map(|y| &src[pitch * y..pitch * y + width])
+                .copied()
+                .collect::<Vec<_>>(),
+            ft::PIXEL_MODE_BGRA => alpha_mask_from_bgra(src, width, rows, pitch),
+            _ => return None,
+        };
+
+        let scaled_width = (width as f32 * scale).round() as usize;
+        let scaled_rows = (rows as f32 * scale).round() as usize;
+        Some(Self {
+            buf: resample_mask(&mask, width, rows, scaled_width, scaled_rows),
+            width: scaled_width,
+            pitch: scaled_width,
+            rows: scaled_rows,
+            left_offset: (slot.bitmap_left as f32 * scale).round() as _,
+            ascending_pixels: (slot.bitmap_top as f32 * scale).round() as _,
+        })
+    }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.width == 0 || self.rows == 0
+    }
+
+    /// width x rowsに詰めてステージングバッファに書き込む
+    pub fn build_stg_image_pixel_buffer(
+        &self,
+        staging_scratch_buffer: &mut StagingScratchBuffer,
+    ) -> StagingScratchBufferReservation {
+        let buf = staging_scratch_buffer.reserve((self.width * self.rows) as _);
+        let ptr = staging_scratch_buffer
+            .map(&buf, StagingScratchBufferMapMode::Write)
+            .expect("Failed to map staging scratch buffer");
+        for y in 0..self.rows {
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    self.buf.as_ptr().add(self.pitch * y),
+                    ptr.addr_of_mut(self.width * y),
+                    self.width,
+                )
+            }
+        }
+
+        buf
+    }
+}
+
+/// premultipliedなBGRAのアルファチャンネルをwidth x rowsに詰めて取り出す
+fn alpha_mask_from_bgra(src: &[u8], width: usize, rows: usize, pitch: usize) -> Vec<u8> {
+    (0..rows)
+        .flat_map(|y| (0..width).map(move |x| src[pitch * y + x * 4 + 3]))
+        .collect()
+}
+
+/// width x rowsのマスクをdst_width x dst_rowsにする
+///
+/// 出力の1ピクセルに対応する入力の範囲の平均をとる(拡大のときは最近傍と同じ)
+fn resample_mask(
+    src: &[u8],
+    width: usize,
+    rows: usize,
+    dst_width: usize,
+    dst_rows: usize,
+) -> Box<[u8]> {
+    if width == 0 || rows == 0 {
+        return vec![0; dst_width * dst_rows].into_boxed_slice();
+    }
+
+    let source_range = |d: usize, src_len: usize, dst_len: usize| {
+        let begin = d * src_len / dst_len;
+        let end = ((d + 1) * src_len).div_ceil(dst_len).max(begin + 1);
+
+        begin..end
+    };
+
+    let mut dst = Vec::with_capacity(dst_width * dst_rows);
+    for dy in 0..dst_rows {
+        let ys = source_range(dy, rows, dst_rows);
+        for dx in 0..dst_width {
+            let xs = source_range(dx, width, dst_width);
+            let count = ys.len() * xs.len();
+            let sum = ys
+                .clone()
+                .flat_map(|y| &src[width * y + xs.start..width * y + xs.end])
+                .map(|&x| x as usize)
+                .sum::<usize>();
+            dst.push(((sum + count / 2) / count) as u8);
+        }
+    }
+
+    dst.into_boxed_slice()
+}
+
+#[derive(Debug)]
+enum RasterizeError {
+    Load(ft::Error),
+    Render(ft::Error),
+    /// グレースケール以外のビットマップが出てきた
+    UnsupportedBitmap,
+}
+
+enum FallbackFace {
+    Unloaded {
+        path: std::ffi::CString,
+        index: core::ffi::c_int,
+    },
+    Loaded {
+        face: ft::Owned<ft::Face>,
+        /// ビットマップしか持たないフェイスのとき、選んだストライクから要求サイズへの倍率
+        bitmap_scale: Option<f32>,
+    },
+    Unavailable,
+}
+
+/// フェイスにサイズを設定する
+///
+/// ビットマップしか持たないフェイス(カラー絵文字等)は任意のサイズにできないので、一番近いストライクを選んで要求サイズへの倍率を返す
+fn apply_char_size(face: &mut ft::Face, points: f32, dpi: u32) -> Option<f32> {
+    if face.is_scalable() {
+        if let Err(e) = face.set_char_size((points * 64.0) as _, 0, dpi, 0) {
+            tracing::warn!(reason = ?e, "Failed to set char size");
+        }
+
+        return None;
+    }
+
+    let pixels = points * dpi as f32 / 72.0;
+    let Some((strike_index, strike_pixels)) = face
+        .fixed_size_pixels()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| (a - pixels).abs().total_cmp(&(b - pixels).abs()))
+    else {
+        tracing::warn!("bitmap-only face has no strikes");
+        return None;
+    };
+    if let Err(e) = face.select_size(strike_index) {
+        tracing::warn!(strike_index, reason = ?e, "Failed to select strike");
+        return None;
+    }
+
+    Some(pixels / strike_pixels)
+}
+
+/// fontconfigでUIフォント(system-ui)に合うフェイスを優先度順に並べたもの(重複なし)
+///
+/// 先頭をプライマリ、残りをフォールバックとして使う
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn sorted_ui_faces() -> Vec<(std::ffi::CString, core::ffi::c_int)> {
+    fontconfig::init();
+    let fc = fontconfig::Config::current();
+
+    let mut fc_pat = fontconfig::Pattern::new();
+    fc_pat.add_family_name(c"system-ui");
+    fc_pat.add_weight(80);
+    fc.substitute(&mut fc_pat, fontconfig::MatchKind::Pattern);
+    fc_pat.default_substitute();
+    let fc_set = fc.sort(&mut fc_pat, true).unwrap();
+
+    let mut faces = Vec::<(std::ffi::CString, core::ffi::c_int)>::new();
+    for &f in fc_set.fonts() {
+        let file_path = f.get_file_path(0).unwrap();
+        let index = f.get_face_index(0).unwrap();
+
+        tracing::debug!(?file_path, index, "match font");
+
+        if !faces
+            .iter()
+            .any(|(p, i)| p.as_c_str() == file_path && *i == index)
+        {
+            faces.push((file_path.to_owned(), index));
+        }
+    }
+
+    faces
+}
+
+/// プライマリフェイス + フォールバックフェイスの列
+///
+/// フォールバック側は実際にグリフが必要になるまでロードしない
+pub struct FontChain {
+    primary: ft::Owned<ft::Face>,
+    fallbacks: Vec<FallbackFace>,
+    char_size: Option<(f32, u32)>,
+}
+impl FontChain {
+    pub fn new(primary: ft::Owned<ft::Face>) -> Self {
+        Self {
+            primary,
+            fallbacks: Vec::new(),
+            char_size: None,
+        }
+    }
+
+    pub fn add_fallback(&mut self, path: std::ffi::CString, index: core::ffi::c_int) {
+        self.fallbacks.push(FallbackFace::Unloaded { path, index });
+    }
+
+    /// ロード済みのフェイスに適用し、未ロードのものにはロード時に適用する
+    pub fn set_char_size(&mut self, points: f32, dpi: u32) {
+        self.char_size = Some((points, dpi));
+        if let Err(e) = self.primary.set_char_size((points * 64.0) as _, 0, dpi, 0) {
+            tracing::warn!(reason = ?e, "Failed to set char size");
+        }
+        for f in self.fallbacks.iter_mut() {
+            if let FallbackFace::Loaded { face, bitmap_scale } = f {
+                *bitmap_scale = apply_char_size(face, points, dpi);
+            }
+        }
+    }
+
+    /// グリフキャッシュのキー用 ポイント数とdpi
+    pub fn size_key(&self) -> (SafeF32, u32) {
+        match self.char_size {
+            Some((points, dpi)) => (unsafe { SafeF32::new_unchecked(points) }, dpi),
+            None => (SafeF32::ZERO, 0),
         }
     }
+
+    /// subpixel / GLYPH_SUBPIXEL_STEPS pxだけ右にずらしてラスタライズする
+    ///
+    /// ラスタライズできなかったときはプライマリフェイスの.notdef(tofu)で代用する
+    pub fn rasterize(&mut self, face_index: usize, glyph_id: u32, subpixel: u8) -> GlyphBitmap {
+        let r = match self.bitmap_scale(face_index) {
+            Some(scale) => Self::rasterize_bitmap_face(self.face_mut(face_index), glyph_id, scale),
+            None => Self::rasterize_face(self.face_mut(face_index), glyph_id, subpixel),
+        };
+        match r {
+            Ok(b) => b,
+            Err(e) => {
+                tracing::warn!(face_index, glyph_id, reason = ?e, "Failed to rasterize glyph");
+
+                Self::rasterize_face(&mut self.primary, 0, subpixel).unwrap_or_else(|e| {
+                    tracing::warn!(reason = ?e, "Failed to rasterize notdef glyph");
+                    GlyphBitmap::empty()
+                })
+            }
+        }
+    }
+
+    fn rasterize_face(
+        face: &mut ft::Face,
+        glyph_id: u32,
+        subpixel: u8,
+    ) -> Result<GlyphBitmap, RasterizeError> {
+        face.set_transform(
+            None,
+            Some(&ft::Vector {
+                x: (subpixel as u32 * 64 / GLYPH_SUBPIXEL_STEPS) as _,
+                y: 0,
+            }),
+        );
+        face.load_glyph(glyph_id, ft::LoadFlags::DEFAULT)
+            .map_err(RasterizeError::Load)?;
+        face.render_glyph(ft::RenderMode::Normal)
+            .map_err(RasterizeError::Render)?;
+
+        face.glyph_slot()
+            .and_then(GlyphBitmap::copy_from_ft_glyph_slot)
+            .ok_or(RasterizeError::UnsupportedBitmap)
+    }
+
+    /// ビットマップしか持たないフェイスのストライクをscale倍にしてマスクにする
+    ///
+    /// Note: ビットマップはずらせないのでサブピクセル位置は無視する
+    fn rasterize_bitmap_face(
+        face: &mut ft::Face,
+        glyph_id: u32,
+        scale: f32,
+    ) -> Result<GlyphBitmap, RasterizeError> {
+        face.set_transform(None, None);
+        face.load_glyph(glyph_id, ft::LoadFlags::COLOR)
+            .map_err(RasterizeError::Load)?;
+        face.render_glyph(ft::RenderMode::Normal)
+            .map_err(RasterizeError::Render)?;
+
+        face.glyph_slot()
+            .and_then(|s| GlyphBitmap::copy_scaled_from_ft_glyph_slot(s, scale))
+            .ok_or(RasterizeError::UnsupportedBitmap)
+    }
+
+    fn face_mut(&mut self, index: usize) -> &mut ft::Face {
+        if index == 0 {
+            return &mut self.primary;
+        }
+
+        match self.fallbacks[index - 1] {
+            FallbackFace::Loaded { ref mut face, .. } => face,
+            _ => unreachable!("face not loaded"),
+        }
+    }
+
+    /// ビットマップしか持たないフェイスなら、ストライクから要求サイズへの倍率
+    fn bitmap_scale(&self, index: usize) -> Option<f32> {
+        match index.checked_sub(1).map(|n| &self.fallbacks[n]) {
+            Some(&FallbackFace::Loaded { bitmap_scale, .. }) => bitmap_scale,
+            _ => None,
+        }
+    }
+
+    fn fallback_face_mut(
+        &mut self,
+        ft: &mut ft::FreeType,
+        index: usize,
+    ) -> Option<&mut ft::Owned<ft::Face>> {
+        if let FallbackFace::Unloaded { .. } = self.fallbacks[index] {
+            let FallbackFace::Unloaded {
+                path,
+                index: face_index,
+            } = core::mem::replace(&mut self.fallbacks[index], FallbackFace::Unavailable)
+            else {
+                unreachable!();
+            };
+
+            self.fallbacks[index] = match ft.new_face(&path, face_index as _) {
+                Ok(mut face) => {
+                    tracing::debug!(
+                        ?path,
+                        face_index,
+                        scalable = face.is_scalable(),
+                        "load fallback face"
+                    );
+                    let bitmap_scale = self
+                        .char_size
+                        .and_then(|(points, dpi)| apply_char_size(&mut face, points, dpi));
+
+                    FallbackFace::Loaded { face, bitmap_scale }
+                }
+                Err(e) => {
+                    tracing::warn!(?path, face_index, reason = ?e, "Failed to load fallback face");
+                    FallbackFace::Unavailable
+                }
+            };
+        }
+
+        match self.fallbacks[index] {
+            FallbackFace::Loaded { ref mut face, .. } => Some(face),
+            _ => None,
+        }
+    }
+
+    /// chを持っている最初のフェイスのインデックス(0がプライマリ) どれも持っていなければプライマリ(tofu)
+    fn covering_face_index(&mut self, ft: &mut ft::FreeType, ch: char) -> usize {
+        if self.primary.char_index(ch as _) != 0 {
+            return 0;
+        }
+
+        for n in 0..self.fallbacks.len() {
+            if let Some(f) = self.fallback_face_mut(ft, n)
+                && f.char_index(ch as _) != 0
+            {
+                return n + 1;
+            }
+        }
+
+        0
+    }
+}
+
+/// 直前の文字とつなげてシェーピングしないといけない文字(結合文字/ZWJ/異体字セレクタ等)
+const fn is_cluster_continuation(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{0300}'..='\u{036F}'
+            | '\u{200C}'..='\u{200D}'
+            | '\u{20D0}'..='\u{20FF}'
+            | '\u{3099}'..='\u{309A}'
+            | '\u{FE00}'..='\u{FE0F}'
+            | '\u{1F3FB}'..='\u{1F3FF}'
+            | '\u{E0020}'..='\u{E007F}'
+            | '\u{E0100}'..='\u{E01EF}'
+    )
+}
+
+struct ShapedGlyph {
+    face_index: usize,
+    glyph_id: u32,
+    /// シェーピングしたテキスト内でのbyte offset
+    cluster: usize,
+    x_advance: f32,
+}
+
+/// byte offsetごとの送り幅の累積(clusterがそのoffsetより前にあるグリフの合計)
+///
+/// 範囲の送り幅を定数時間で引けるようにしておく
+struct AdvancePrefixSums(Vec<f32>);
+impl AdvancePrefixSums {
+    fn new(glyphs: &[ShapedGlyph], text_len: usize) -> Self {
+        let mut sums = vec![0.0; text_len + 1];
+        for g in glyphs {
+            sums[g.cluster + 1] += g.x_advance;
+        }
+        for n in 1..sums.len() {
+            sums[n] += sums[n - 1];
+        }
+
+        Self(sums)
+    }
+
+    /// clusterがrangeに含まれるグリフの送り幅の合計
+    #[inline]
+    fn sum(&self, range: core::ops::Range<usize>) -> f32 {
+        self.0[range.end] - self.0[range.start]
+    }
+}
+
+impl FontChain {
+    fn shape(&mut self, ft: &mut ft::FreeType, text: &str) -> Vec<ShapedGlyph> {
+        // グリフを持っているフェイスごとにランを切る
+        let mut runs = Vec::<(core::ops::Range<usize>, usize)>::new();
+        for (p, ch) in text.char_indices() {
+            let face_index = match runs.last() {
+                Some(&(_, last_face_index)) if is_cluster_continuation(ch) => last_face_index,
+                // Note: 制御文字(改行とか)はどのフェイスも持ってないことが多いのでフォールバックを探さない
+                _ if ch.is_control() => 0,
+                _ => self.covering_face_index(ft, ch),
+            };
+
+            match runs.last_mut() {
+                Some((r, last_face_index)) if *last_face_index == face_index => {
+                    r.end = p + ch.len_utf8();
+                }
+                _ => runs.push((p..p + ch.len_utf8(), face_index)),
+            }
+        }
+
+        let mut glyphs = Vec::new();
+        for (range, face_index) in runs {
+            let mut hb_buffer = hb::Buffer::new();
+            hb_buffer.add_range(text, range);
+            hb_buffer.guess_segment_properties();
+            // Note: ビットマップしか持たないフェイスはストライクの大きさで送り幅が出てくるので要求サイズに直す
+            let advance_scale = self.bitmap_scale(face_index).unwrap_or(1.0);
+            let mut hb_font = hb::Font::from_ft_face_referenced(self.face_mut(face_index));
+            hb::shape(&mut hb_font, &mut hb_buffer, &[]);
+            let (glyph_infos, glyph_positions) = hb_buffer.get_shape_results();
+            glyphs.extend(
+                glyph_infos
+                    .iter()
+                    .zip(glyph_positions.iter())
+                    .map(|(info, pos)| ShapedGlyph {
+                        face_index,
+                        glyph_id: info.codepoint,
+                        cluster: info.cluster as _,
+                        x_advance: pos.x_advance as f32 / 64.0 * advance_scale,
+                    }),
+            );
+        }
+
+        glyphs
+    }
+
+    /// 1行のテキストでキャレットを置ける位置(byte offset)と、先頭からの送り幅(pixels)
+    pub fn caret_stops(&mut self, ft: &mut ft::FreeType, text: &str) -> Vec<(usize, f32)> {
+        let advances = AdvancePrefixSums::new(&self.shape(ft, text), text.len());
+
+        text.char_indices()
+            .map(|(p, _)| p)
+            .chain([text.len()])
+            // 結合文字などの前にはキャレットを置かない
+            .filter(|&p| {
+                !text[p..]
+                    .chars()
+                    .next()
+                    .is_some_and(is_cluster_continuation)
+            })
+            .map(|p| (p, advances.sum(0..p)))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextAlignment {
+    Left,
+    Center,
+    Right,
+}
+
+/// 収まらなかったときにどこを省略するか
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextTruncation {
+    End,
+    Middle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextLayoutOptions {
+    /// 折り返し幅(pixels) Noneなら改行文字でしか改行しない
+    pub max_width: Option<SafeF32>,
+    pub alignment: TextAlignment,
+    /// 行送り(フェイスのheightに対する倍率)
+    pub line_spacing: SafeF32,
+    /// 最大行数 超えた場合は最終行をtruncationに従って省略する
+    pub max_lines: Option<NonZeroUsize>,
+    pub truncation: TextTruncation,
+}
+impl Default for TextLayoutOptions {
+    fn default() -> Self {
+        Self::SINGLE_LINE
+    }
+}
+impl TextLayoutOptions {
+    /// 折り返しなしの1行
+    pub const SINGLE_LINE: Self = Self {
+        max_width: None,
+        alignment: TextAlignment::Left,
+        line_spacing: unsafe { SafeF32::new_unchecked(1.0) },
+        max_lines: None,
+        truncation: TextTruncation::End,
+    };
+
+    /// max_widthで折り返す
+    pub const fn wrapped(max_width: SafeF32) -> Self {
+        Self {
+            max_width: Some(max_width),
+            ..Self::SINGLE_LINE
+        }
+    }
+
+    /// 1行に収め、はみ出した分を省略する
+    pub const fn truncated(max_width: SafeF32, truncation: TextTruncation) -> Self {
+        Self {
+            max_width: Some(max_width),
+            max_lines: Some(NonZeroUsize::MIN),
+            truncation,
+            ..Self::SINGLE_LINE
+        }
+    }
+
+    pub const fn with_alignment(self, alignment: TextAlignment) -> Self {
+        Self { alignment, ..self }
+    }
+
+    pub const fn with_line_spacing(self, line_spacing: SafeF32) -> Self {
+        Self {
+            line_spacing,
+            ..self
+        }
+    }
+
+    pub const fn with_max_lines(self, max_lines: NonZeroUsize) -> Self {
+        Self {
+            max_lines: Some(max_lines),
+            ..self
+        }
+    }
+}
+
+const ELLIPSIS: &str = "\u{2026}";
+
+/// 行末の空白/改行を除いた範囲
+fn trim_line_end(text: &str, range: core::ops::Range<usize>) -> core::ops::Range<usize> {
+    range.start..range.start + text[range].trim_end().len()
+}
+
+/// UAX #14の改行位置で貪欲に行分割する 1単語で収まらない場合は文字単位で割る
+fn break_lines(
+    text: &str,
+    glyphs: &[ShapedGlyph],
+    max_width: Option<f32>,
+) -> Vec<core::ops::Range<usize>> {
+    let advances = AdvancePrefixSums::new(glyphs, text.len());
+    let line_width = |r: core::ops::Range<usize>| advances.sum(trim_line_end(text, r));
+
+    let mut lines = Vec::new();
+    let mut line_start = 0;
+    let mut last_fit = None;
+    for (p, op) in unicode_linebreak::linebreaks(text) {
+        if let Some(mw) = max_width
+            && line_width(line_start..p) > mw
+        {
+            if let Some(b) = last_fit.take() {
+                lines.push(line_start..b);
+                line_start = b;
+            }
+
+            while line_width(line_start..p) > mw {
+                // 最低でも1文字は置く
+                let first_end =
+                    line_start + text[line_start..].chars().next().map_or(0, char::len_utf8);
+                let mut e = first_end;
+                for (o, ch) in text[first_end..p].char_indices() {
+                    if line_width(line_start..first_end + o + ch.len_utf8()) > mw {
+                        break;
+                    }
+                    e = first_end + o + ch.len_utf8();
+                }
+                if e >= p {
+                    break;
+                }
+
+                lines.push(line_start..e);
+                line_start = e;
+            }
+        }
+
+        match op {
+            unicode_linebreak::BreakOpportunity::Mandatory => {
+                lines.push(line_start..p);
+                line_start = p;
+                last_fit = None;
+            }
+            unicode_linebreak::BreakOpportunity::Allowed => {
+                last_fit = Some(p);
+            }
+        }
+    }
+    if lines.is_empty() {
+        lines.push(0..0);
+    }
+
+    lines
+}
+
+/// 省略記号の前に残す先頭側の長さと、後ろに残す末尾側の開始位置(どちらもbyte offset)
+///
+/// budgetは省略記号を除いて使える幅 Noneなら全部残す
+fn ellipsis_split(
+    rest: &str,
+    glyphs: &[ShapedGlyph],
+    budget: Option<f32>,
+    truncation: TextTruncation,
+) -> (usize, usize) {
+    // 先頭側は最初の改行まで、末尾側は最後の改行以降しか使わない
+    let head_limit = rest.find(['\r', '\n']).unwrap_or(rest.len());
+    let tail_limit = match truncation {
+        TextTruncation::End => rest.len(),
+        TextTruncation::Middle => rest.rfind(['\r', '\n']).map_or(0, |p| p + 1),
+    };
+    let advances = AdvancePrefixSums::new(glyphs, rest.len());
+    let fits = |head: usize, tail: usize| {
+        budget.is_none_or(|b| advances.sum(0..head) + advances.sum(tail..rest.len()) <= b)
+    };
+
+    let (mut head, mut tail) = (0, rest.len());
+    let (mut head_stopped, mut tail_stopped) = (false, truncation == TextTruncation::End);
+    while !head_stopped || !tail_stopped {
+        if !head_stopped {
+            match rest[head..head_limit.min(tail)].chars().next() {
+                Some(ch) if fits(head + ch.len_utf8(), tail) => head += ch.len_utf8(),
+                _ => head_stopped = true,
+            }
+        }
+        if !tail_stopped {
+            match rest[tail_limit.max(head)..tail].chars().next_back() {
+                Some(ch) if fits(head, tail - ch.len_utf8()) => tail -= ch.len_utf8(),
+                _ => tail_stopped = true,
+            }
+        }
+    }
+
+    (head, tail)
+}
+
+/// 1pxを何分割してグリフをキャッシュするか
+pub const GLYPH_SUBPIXEL_STEPS: u32 = 4;
+
+/// レイアウト済みのグリフ
+#[derive(Debug, Clone, Copy)]
+pub struct PlacedGlyph {
+    pub face_index: usize,
+    pub glyph_id: u32,
+    /// ペン位置の整数部
+    pub left: i32,
+    /// ペン位置の小数部(GLYPH_SUBPIXEL_STEPS分割)
+    pub subpixel: u8,
+    /// 1行目のベースラインからこのグリフのベースラインまでの距離(下向き正)
+    pub baseline: i32,
 }
 
 pub struct TextLayout {
-    bitmaps: Vec<GlyphBitmap>,
-    final_left_pos: f32,
-    max_ascender: i32,
-    max_descender: i32,
+    glyphs: Vec<PlacedGlyph>,
+    width: f32,
 }
 impl TextLayout {
-    pub fn build_simple(text: &str, face: &mut ft::Face) -> Self {
-        let mut hb_buffer = hb::Buffer::new();
-        hb_buffer.add(text);
-        hb_buffer.guess_segment_properties();
-        let mut hb_font = hb::Font::from_ft_face_referenced(face);
-        hb::shape(&mut hb_font, &mut hb_buffer, &[]);
-        let (glyph_infos, glyph_positions) = hb_buffer.get_shape_results();
-        let mut left_pos = 0.0;
-        let top_pos = 0.0;
-        let mut max_ascender = 0;
-        let mut max_descender = 0;
-        // println!(
-        //     "base metrics: {} {}",
-        //     face.ascender_pixels(),
-        //     face.height_pixels()
-        // );
-        let mut glyph_bitmaps = Vec::with_capacity(glyph_infos.len());
-        for (info, pos) in glyph_infos.iter().zip(glyph_positions.iter()) {
-            face.set_transform(
-                None,
-                Some(&ft::Vector {
-                    x: (left_pos * 64.0) as _,
-                    y: (top_pos * 64.0) as _,
-                }),
+    pub fn build(
+        text: &str,
+        fonts: &mut FontChain,
+        ft: &mut ft::FreeType,
+        options: &TextLayoutOptions,
+    ) -> Self {
+        let max_width = options.max_width.map(|x| x.value());
+        let paragraph_glyphs = fonts.shape(ft, text);
+        let mut line_ranges = break_lines(text, &paragraph_glyphs, max_width);
+
+        let mut line_texts = Vec::with_capacity(line_ranges.len());
+        if let Some(max_lines) = options.max_lines
+            && line_ranges.len() > max_lines.get()
+        {
+            line_ranges.truncate(max_lines.get());
+            let last = line_ranges.pop().unwrap();
+            line_texts.extend(
+                line_ranges
+                    .iter()
+                    .map(|r| text[trim_line_end(text, r.clone())].to_owned()),
             );
-            face.load_glyph(info.codepoint, ft::LoadFlags::DEFAULT)
-                .unwrap();
-            face.render_glyph(ft::RenderMode::Normal).unwrap();
-            let slot = face.glyph_slot().unwrap();
-
-            // println!(
-            //     "glyph {} {} {} {} {} {} {} {} {} {}",
-            //     info.codepoint,
-            //     pos.x_advance as f32 / 64.0,
-            //     pos.y_advance as f32 / 64.0,
-            //     pos.x_offset,
-            //     pos.y_offset,
-            //     slot.bitmap_left,
-            //     slot.bitmap_top,
-            //     slot.bitmap.width,
-            //     slot.bitmap.rows,
-            //     slot.bitmap.pitch,
-            // );
-
-            glyph_bitmaps.push(GlyphBitmap::copy_from_ft_glyph_slot(slot));
-
-            left_pos += pos.x_advance as f32 / 64.0;
-            max_ascender = max_ascender.max(slot.bitmap_top);
-            max_descender = max_descender.max(slot.bitmap.rows as i32 - slot.bitmap_top);
-        }
-        // println!("final metrics: {left_pos} {top_pos} {max_ascender} {max_descender}");
+            line_texts.push(Self::truncate_with_ellipsis(
+                &text[last.start..],
+                fonts,
+                ft,
+                max_width,
+                options.truncation,
+            ));
+        } else {
+            line_texts.extend(
+                line_ranges
+                    .iter()
+                    .map(|r| text[trim_line_end(text, r.clone())].to_owned()),
+            );
+        }
+
+        let lines = line_texts
+            .iter()
+            .map(|t| {
+                let glyphs = fonts.shape(ft, t);
+                let width = glyphs.iter().map(|g| g.x_advance).sum::<f32>();
+
+                (glyphs, width)
+            })
+            .collect::<Vec<_>>();
+        let width = lines.iter().fold(0.0f32, |a, &(_, w)| a.max(w));
+        let line_height = fonts.primary.height_pixels() as f32 * options.line_spacing.value();
+
+        let mut placed_glyphs = Vec::new();
+        for (n, (glyphs, line_width)) in lines.into_iter().enumerate() {
+            let baseline = (n as f32 * line_height).round() as i32;
+            let mut left_pos = match options.alignment {
+                TextAlignment::Left => 0.0,
+                TextAlignment::Center => ((width - line_width) * 0.5).floor(),
+                TextAlignment::Right => (width - line_width).floor(),
+            };
+
+            placed_glyphs.reserve(glyphs.len());
+            for g in glyphs {
+                let mut left = left_pos.floor() as i32;
+                let mut subpixel =
+                    ((left_pos - left as f32) * GLYPH_SUBPIXEL_STEPS as f32).round() as u32;
+                if subpixel >= GLYPH_SUBPIXEL_STEPS {
+                    left += 1;
+                    subpixel = 0;
+                }
+
+                placed_glyphs.push(PlacedGlyph {
+                    face_index: g.face_index,
+                    glyph_id: g.glyph_id,
+                    left,
+                    subpixel: subpixel as _,
+                    baseline,
+                });
+                left_pos += g.x_advance;
+            }
+        }
 
         Self {
-            bitmaps: glyph_bitmaps,
-            final_left_pos: left_pos,
-            max_ascender,
-            max_descender,
+            glyphs: placed_glyphs,
+            width,
         }
     }
 
+    /// 最終行用 rest(残り全部)を省略記号付きで1行に詰める
+    fn truncate_with_ellipsis(
+        rest: &str,
+        fonts: &mut FontChain,
+        ft: &mut ft::FreeType,
+        max_width: Option<f32>,
+        truncation: TextTruncation,
+    ) -> String {
+        let ellipsis_width = fonts
+            .shape(ft, ELLIPSIS)
+            .iter()
+            .map(|g| g.x_advance)
+            .sum::<f32>();
+        let glyphs = fonts.shape(ft, rest);
+        let (head, tail) = ellipsis_split(
+            rest,
+            &glyphs,
+            max_width.map(|w| w - ellipsis_width),
+            truncation,
+        );
+
+        format!(
+            "{}{ELLIPSIS}{}",
+            rest[..head].trim_end(),
+            rest[tail..].trim_start()
+        )
+    }
+
     pub const fn width(&self) -> f32 {
-        self.final_left_pos
+        self.width
     }
 
     #[inline]
@@ -116,36 +873,188 @@ impl TextLayout {
         self.width().ceil() as _
     }
 
-    pub const fn height(&self) -> f32 {
-        self.max_ascender as f32 + self.max_descender as f32
+    #[inline]
+    pub fn glyphs(&self) -> &[PlacedGlyph] {
+        &self.glyphs
     }
+}
 
-    #[inline]
-    pub fn height_px(&self) -> u32 {
-        self.height().ceil() as _
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1文字1グリフ、送り幅widthで並べたシェーピング結果の代わり
+    fn monospace_glyphs(text: &str, width: impl Fn(char) -> f32) -> Vec<ShapedGlyph> {
+        text.char_indices()
+            .map(|(p, ch)| ShapedGlyph {
+                face_index: 0,
+                glyph_id: 0,
+                cluster: p,
+                x_advance: width(ch),
+            })
+            .collect()
     }
 
-    pub fn build_stg_image_pixel_buffer(
-        &self,
-        staging_scratch_buffer: &mut StagingScratchBuffer,
-    ) -> StagingScratchBufferReservation {
-        let buf = staging_scratch_buffer.reserve((self.width_px() * self.height_px()) as _);
-        let ptr = staging_scratch_buffer
-            .map(&buf, StagingScratchBufferMapMode::Write)
-            .expect("Failed to map staging scratch buffer");
-        for b in self.bitmaps.iter() {
-            for y in 0..b.rows {
-                let dy = y as isize + self.max_ascender as isize - b.ascending_pixels;
-                unsafe {
-                    core::ptr::copy_nonoverlapping(
-                        b.buf.as_ptr().add(b.pitch * y),
-                        ptr.addr_of_mut((self.width_px() as isize * dy + b.left_offset) as _),
-                        b.width,
-                    )
-                }
-            }
+    fn lines(text: &str, width: impl Fn(char) -> f32, max_width: Option<f32>) -> Vec<&str> {
+        break_lines(text, &monospace_glyphs(text, width), max_width)
+            .into_iter()
+            .map(|r| &text[r])
+            .collect()
+    }
+
+    #[test]
+    fn break_at_spaces() {
+        assert_eq!(lines("aa bb cc", |_| 10.0, Some(55.0)), ["aa bb ", "cc"]);
+        assert_eq!(lines("aa bb cc", |_| 10.0, None), ["aa bb cc"]);
+    }
+
+    #[test]
+    fn break_at_newline() {
+        assert_eq!(lines("a\nb", |_| 10.0, None), ["a\n", "b"]);
+    }
+
+    #[test]
+    fn break_between_cjk_characters() {
+        assert_eq!(
+            lines("あいうえお", |_| 10.0, Some(25.0)),
+            ["あい", "うえ", "お"]
+        );
+    }
+
+    #[test]
+    fn split_word_without_break_opportunity() {
+        assert_eq!(
+            lines("abcdefgh", |_| 10.0, Some(35.0)),
+            ["abc", "def", "gh"]
+        );
+    }
+
+    #[test]
+    fn zero_max_width_places_one_char_per_line() {
+        assert_eq!(lines("abc", |_| 10.0, Some(0.0)), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn zero_width_glyphs_never_break() {
+        assert_eq!(
+            lines(
+                "e\u{301}e\u{301}",
+                |ch| if is_cluster_continuation(ch) {
+                    0.0
+                } else {
+                    10.0
+                },
+                Some(20.0)
+            ),
+            ["e\u{301}e\u{301}"]
+        );
+    }
+
+    #[test]
+    fn empty_text_has_one_line() {
+        assert_eq!(lines("", |_| 10.0, Some(10.0)), [""]);
+    }
+
+    fn ellipsized(text: &str, budget: Option<f32>, truncation: TextTruncation) -> (&str, &str) {
+        let (head, tail) =
+            ellipsis_split(text, &monospace_glyphs(text, |_| 10.0), budget, truncation);
+
+        (&text[..head], &text[tail..])
+    }
+
+    #[test]
+    fn ellipsis_at_end() {
+        assert_eq!(
+            ellipsized("abcdefgh", Some(35.0), TextTruncation::End),
+            ("abc", "")
+        );
+    }
+
+    #[test]
+    fn ellipsis_in_middle() {
+        assert_eq!(
+            ellipsized("abcdefgh", Some(40.0), TextTruncation::Middle),
+            ("ab", "gh")
+        );
+    }
+
+    #[test]
+    fn ellipsis_cjk() {
+        assert_eq!(
+            ellipsized("あいうえお", Some(20.0), TextTruncation::End),
+            ("あい", "")
+        );
+    }
+
+    #[test]
+    fn ellipsis_stops_at_newline() {
+        assert_eq!(
+            ellipsized("ab\ncd\nef", Some(100.0), TextTruncation::Middle),
+            ("ab", "ef")
+        );
+    }
+
+    #[test]
+    fn ellipsis_with_no_room() {
+        assert_eq!(
+            ellipsized("abc", Some(0.0), TextTruncation::Middle),
+            ("", "")
+        );
+        assert_eq!(ellipsized("abc", None, TextTruncation::End), ("abc", ""));
+    }
+
+    #[test]
+    fn alpha_mask_skips_pitch_padding() {
+        // 2x2 BGRA、行末に4byteの詰め物
+        let src = [
+            1, 2, 3, 10, 1, 2, 3, 20, 0, 0, 0, 0, //
+            1, 2, 3, 30, 1, 2, 3, 40, 0, 0, 0, 0,
+        ];
+        assert_eq!(alpha_mask_from_bgra(&src, 2, 2, 12), [10, 20, 30, 40]);
+    }
+
+    #[test]
+    fn resample_mask_averages_when_shrinking() {
+        let src = [
+            0, 255, 100, 100, //
+            255, 0, 100, 100,
+        ];
+        assert_eq!(&*resample_mask(&src, 4, 2, 2, 1), [128, 100]);
+    }
+
+    #[test]
+    fn resample_mask_repeats_when_enlarging() {
+        assert_eq!(
+            &*resample_mask(&[10, 20], 2, 1, 4, 2),
+            [10, 10, 20, 20, 10, 10, 20, 20]
+        );
+        assert_eq!(&*resample_mask(&[], 0, 0, 2, 1), [0, 0]);
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    #[ignore = "needs fontconfig and an emoji font (e.g. Noto Color Emoji)"]
+    fn emoji_resolves_to_fallback_face() {
+        let mut ft = ft::FreeType::new().unwrap();
+        let mut faces = sorted_ui_faces().into_iter();
+        let (path, index) = faces.next().expect("no UI face");
+        let mut chain = FontChain::new(ft.new_face(&path, index as _).unwrap());
+        for (path, index) in faces {
+            chain.add_fallback(path, index);
         }
+        chain.set_char_size(12.0, 96);
 
-        buf
+        let face_index = chain.covering_face_index(&mut ft, '\u{1F600}');
+        assert_ne!(face_index, 0);
+
+        let glyphs = chain.shape(&mut ft, "\u{1F600}");
+        assert_eq!(glyphs.len(), 1);
+        assert_eq!(glyphs[0].face_index, face_index);
+        // 12pt@96dpi = 16px 前後に縮められている
+        assert!(glyphs[0].x_advance < 32.0, "{}", glyphs[0].x_advance);
+
+        let bitmap = chain.rasterize(face_index, glyphs[0].glyph_id, 0);
+        assert!(!bitmap.is_empty());
+        assert!(bitmap.rows <= 32, "{}", bitmap.rows);
     }
 }
@@ -1,22 +1,40 @@
 //! Common Component(Standalone View)s
 
-use std::{cell::Cell, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use bedrock::{self as br, RenderPass, ShaderModule, VkHandle};
 
 use crate::{
-    ViewInitContext,
-    base_system::{AppBaseSystem, FontType},
+    AppEvent, AppUpdateContext, BLEND_STATE_SINGLE_NONE, FillcolorRConstants, IA_STATE_TRILIST,
+    RASTER_STATE_DEFAULT_FILL_NOCULL, VI_STATE_FLOAT2_ONLY, ViewInitContext,
+    atlas::AtlasRect,
+    base_system::{
+        AppBaseSystem, DeviceLocalBuffer, FontType, PixelFormat, RenderTexture, RenderTextureFlags,
+        RenderTextureOptions, inject_cmd_begin_render_pass2, inject_cmd_end_render_pass2,
+        inject_cmd_pipeline_barrier_2, theme::ThemeColor,
+    },
     composite::{
-        AnimatableColor, AnimatableFloat, AnimationCurve, CompositeMode, CompositeRect,
-        CompositeTree, CompositeTreeRef,
+        AnimatableColor, AnimatableFloat, AnimationCurve, ClipConfig, CompositeMode, CompositeRect,
+        CompositeTree, CompositeTreeFloatParameterRef, CompositeTreeRef, FloatParameter,
     },
     helper_types::SafeF32,
     hittest::{
         CursorShape, HitTestTreeActionHandler, HitTestTreeData, HitTestTreeManager, HitTestTreeRef,
+        PointerActionArgs,
+    },
+    input::{
+        EventContinueControl, FocusTargetToken, KeyActionArgs, KeyCode, KeyModifiers,
+        TextInputEvent, TextInputTarget,
     },
+    uikit::layout::LayoutElement,
 };
 
 pub struct CommonButtonView {
     ct_root: CompositeTreeRef,
+    ct_bg_alpha: CompositeTreeFloatParameterRef,
     ht_root: HitTestTreeRef,
     preferred_width: f32,
     preferred_height: f32,
@@ -52,6 +70,12 @@ impl CommonButtonView {
             Self::PADDING_H * 2.0 + text_atlas_rect.width() as f32 / init.ui_scale_factor;
         let preferred_height = Self::PADDING_V * 2.0 + 12.0;
 
+        let ct_bg_alpha = init
+            .base_system
+            .composite_tree
+            .parameter_store_mut()
+            .alloc_float(FloatParameter::Value(0.0));
+        let theme = init.base_system.theme;
         let ct_root = init.base_system.register_composite_rect(CompositeRect {
             base_scale_factor: init.ui_scale_factor,
             size: [
@@ -61,7 +85,13 @@ impl CommonButtonView {
             has_bitmap: true,
             texatlas_rect: frame_image_atlas_rect,
             slice_borders: [Self::CORNER_RADIUS.value() * init.ui_scale_factor.ceil(); 4],
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value([1.0, 1.0, 1.0, 0.0])),
+            composite_mode: CompositeMode::ColorTint(AnimatableColor::Expression(Box::new(
+                move |ps| {
+                    let [r, g, b, a] = theme.evaluate(ps, ThemeColor::Foreground);
+
+                    [r, g, b, a * ps.float_value(ct_bg_alpha)]
+                },
+            ))),
             ..Default::default()
         });
         let ct_border = init.base_system.register_composite_rect(CompositeRect {
@@ -69,7 +99,9 @@ impl CommonButtonView {
             has_bitmap: true,
             texatlas_rect: frame_border_image_atlas_rect,
             slice_borders: [Self::CORNER_RADIUS.value() * init.ui_scale_factor.ceil(); 4],
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value([1.0, 1.0, 1.0, 0.25])),
+            composite_mode: CompositeMode::ColorTint(
+                theme.color_with_alpha(ThemeColor::Foreground, 0.25),
+            ),
             ..Default::default()
         });
         let ct_label = init.base_system.register_composite_rect(CompositeRect {
@@ -84,7 +116,7 @@ impl CommonButtonView {
             relative_offset_adjustment: [0.5, 0.5],
             has_bitmap: true,
             texatlas_rect: text_atlas_rect,
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value([0.9, 0.9, 0.9, 1.0])),
+            composite_mode: CompositeMode::ColorTint(theme.color(ThemeColor::Text)),
             ..Default::default()
         });
 
@@ -101,6 +133,7 @@ impl CommonButtonView {
 
         Self {
             ct_root,
+            ct_bg_alpha,
             ht_root,
             preferred_width,
             preferred_height,
@@ -114,53 +147,6 @@ impl CommonButtonView {
         self.preferred_width
     }
 
-    pub const fn preferred_height(&self) -> f32 {
-        self.preferred_height
-    }
-
-    #[inline]
-    pub fn ct_mut<'c>(&self, ct: &'c mut CompositeTree) -> &'c mut CompositeRect {
-        ct.get_mut(self.ct_root)
-    }
-
-    #[inline]
-    pub fn ht_mut<'h, 'ah>(
-        &self,
-        ht: &'h mut HitTestTreeManager<'ah>,
-    ) -> &'h mut HitTestTreeData<'ah> {
-        ht.get_data_mut(self.ht_root)
-    }
-
-    #[inline]
-    pub fn set_position(&self, base_sys: &mut AppBaseSystem, x: f32, y: f32) {
-        let ct = self
-            .ct_root
-            .entity_mut_dirtified(&mut base_sys.composite_tree);
-        ct.offset = [AnimatableFloat::Value(x), AnimatableFloat::Value(y)];
-        base_sys.hit_tree.get_data_mut(self.ht_root).left = x;
-        base_sys.hit_tree.get_data_mut(self.ht_root).top = y;
-    }
-
-    #[inline]
-    pub fn set_relative_offset_adjustments(
-        &self,
-        base_sys: &mut AppBaseSystem,
-        rel_x: f32,
-        rel_y: f32,
-    ) {
-        self.ct_root
-            .entity_mut_dirtified(&mut base_sys.composite_tree)
-            .relative_offset_adjustment = [rel_x, rel_y];
-        base_sys
-            .hit_tree
-            .get_data_mut(self.ht_root)
-            .left_adjustment_factor = rel_x;
-        base_sys
-            .hit_tree
-            .get_data_mut(self.ht_root)
-            .top_adjustment_factor = rel_y;
-    }
-
     #[inline]
     pub fn bind_action_handler(
         &self,
@@ -196,14 +182,14 @@ impl CommonButtonView {
             _ => 0.0,
         };
 
-        let current = match ct.get(self.ct_root).composite_mode {
-            CompositeMode::ColorTint(ref x) => x.evaluate(current_sec, ct.parameter_store()),
-            _ => unreachable!(),
-        };
-        ct.get_mut(self.ct_root).composite_mode =
-            CompositeMode::ColorTint(AnimatableColor::Animated {
+        let current = ct
+            .parameter_store()
+            .evaluate_float(self.ct_bg_alpha, current_sec);
+        ct.parameter_store_mut().set_float(
+            self.ct_bg_alpha,
+            FloatParameter::Animated {
                 from_value: current,
-                to_value: [1.0, 1.0, 1.0, opacity],
+                to_value: opacity,
                 start_sec: current_sec,
                 end_sec: current_sec + 0.1,
                 curve: AnimationCurve::CubicBezier {
@@ -211,7 +197,8 @@ impl CommonButtonView {
                     p2: (0.5, 1.0),
                 },
                 event_on_complete: None,
-            });
+            },
+        );
         ct.mark_dirty(self.ct_root);
     }
 
@@ -246,3 +233,3144 @@ impl CommonButtonView {
         self.is_dirty.set(true);
     }
 }
+impl LayoutElement for CommonButtonView {
+    fn layout_roots(&self) -> (CompositeTreeRef, Option<HitTestTreeRef>) {
+        (self.ct_root, Some(self.ht_root))
+    }
+
+    fn preferred_size(&self) -> (f32, f32) {
+        (self.preferred_width, self.preferred_height)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextInputValidation {
+    Any,
+    /// 0-9のみ受け付ける
+    Numeric,
+}
+impl TextInputValidation {
+    fn filter(self, text: &str) -> String {
+        match self {
+            Self::Any => text.chars().filter(|c| !c.is_control()).collect(),
+            Self::Numeric => text.chars().filter(char::is_ascii_digit).collect(),
+        }
+    }
+}
+
+/// 編集の終わり方
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextInputEditEnd {
+    /// Enterまたはフォーカスが外れた
+    Commit(String),
+    /// Escape
+    Cancel,
+}
+
+struct TextInputState {
+    text: String,
+    /// byte offset
+    caret: usize,
+    /// 選択の起点(caretと同じなら選択なし)
+    anchor: usize,
+    /// IMEの変換中文字列(caretの位置に表示される)
+    preedit: Option<(String, Option<(usize, usize)>)>,
+}
+impl TextInputState {
+    const fn selection(&self) -> core::ops::Range<usize> {
+        if self.caret < self.anchor {
+            self.caret..self.anchor
+        } else {
+            self.anchor..self.caret
+        }
+    }
+
+    fn prev_boundary(&self, p: usize) -> usize {
+        self.text[..p]
+            .char_indices()
+            .next_back()
+            .map_or(0, |(x, _)| x)
+    }
+
+    fn next_boundary(&self, p: usize) -> usize {
+        self.text[p..]
+            .chars()
+            .next()
+            .map_or(self.text.len(), |c| p + c.len_utf8())
+    }
+
+    fn move_caret(&mut self, p: usize, extend: bool) {
+        self.caret = p;
+        if !extend {
+            self.anchor = p;
+        }
+    }
+
+    fn replace_selection(&mut self, text: &str) {
+        let r = self.selection();
+        let p = r.start + text.len();
+        self.text.replace_range(r, text);
+        self.move_caret(p, false);
+    }
+
+    /// Backspace 選択がなければキャレットの前の1文字を消す
+    fn delete_backward(&mut self) {
+        if self.selection().is_empty() {
+            self.anchor = self.prev_boundary(self.caret);
+        }
+        self.replace_selection("");
+    }
+
+    /// Delete 選択がなければキャレットの後ろの1文字を消す
+    fn delete_forward(&mut self) {
+        if self.selection().is_empty() {
+            self.anchor = self.next_boundary(self.caret);
+        }
+        self.replace_selection("");
+    }
+
+    /// IMEからの削除要求 前後の長さはbyte単位
+    fn delete_surrounding(&mut self, before_length: usize, after_length: usize) {
+        let mut start = self.caret.saturating_sub(before_length);
+        let mut end = (self.caret + after_length).min(self.text.len());
+        // 文字の途中にかからないように広げる
+        while !self.text.is_char_boundary(start) {
+            start -= 1;
+        }
+        while !self.text.is_char_boundary(end) {
+            end += 1;
+        }
+        self.text.replace_range(start..end, "");
+        self.move_caret(start, false);
+    }
+
+    /// 表示するテキスト(変換中文字列込み)
+    fn display_text(&self) -> String {
+        match self.preedit {
+            Some((ref t, _)) => {
+                let mut s = self.text.clone();
+                s.insert_str(self.caret, t);
+                s
+            }
+            None => self.text.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct PendingPointer {
+    client_x: f32,
+    client_y: f32,
+    client_width: f32,
+    client_height: f32,
+    /// 選択範囲を広げる(ドラッグ中)
+    extend: bool,
+}
+
+/// 1行のテキスト入力欄
+pub struct TextInputView {
+    ct_root: CompositeTreeRef,
+    ct_content: CompositeTreeRef,
+    ct_selection: CompositeTreeRef,
+    ct_text: CompositeTreeRef,
+    ct_preedit_underline: CompositeTreeRef,
+    ct_caret: CompositeTreeRef,
+    ht_root: HitTestTreeRef,
+    focus_token: FocusTargetToken,
+    validation: TextInputValidation,
+    size: Cell<(f32, f32)>,
+    ui_scale_factor: Cell<f32>,
+    state: RefCell<TextInputState>,
+    text_rect: Cell<Option<AtlasRect>>,
+    /// 表示テキスト上のキャレット位置(byte offset)と先頭からのx座標
+    caret_stops: RefCell<Vec<(usize, f32)>>,
+    scroll_x: Cell<f32>,
+    caret_x: Cell<f32>,
+    text_dirty: Cell<bool>,
+    caret_dirty: Cell<bool>,
+    pending_pointer: Cell<Option<PendingPointer>>,
+    dragging: Cell<bool>,
+    focused_render: Cell<bool>,
+    blur_requested: Cell<bool>,
+    editing: Cell<bool>,
+    edit_end: RefCell<Option<TextInputEditEnd>>,
+}
+impl TextInputView {
+    const PADDING_H: f32 = 2.0;
+    const CARET_WIDTH: f32 = 1.0;
+    const CARET_HEIGHT: f32 = 14.0;
+    const SELECTION_ALPHA: f32 = 0.375;
+
+    #[tracing::instrument(name = "TextInputView::new", skip(init))]
+    pub fn new(
+        init: &mut ViewInitContext,
+        init_text: &str,
+        width: f32,
+        height: f32,
+        validation: TextInputValidation,
+    ) -> Self {
+        let ct_root = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(width),
+                AnimatableFloat::Value(height),
+            ],
+            clip_child: Some(ClipConfig {
+                left_softness: SafeF32::ZERO,
+                top_softness: SafeF32::ZERO,
+                right_softness: SafeF32::ZERO,
+                bottom_softness: SafeF32::ZERO,
+            }),
+            ..Default::default()
+        });
+        let ct_content = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            offset: [
+                AnimatableFloat::Value(Self::PADDING_H),
+                AnimatableFloat::Value(0.0),
+            ],
+            relative_size_adjustment: [0.0, 1.0],
+            ..Default::default()
+        });
+        let ct_selection = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(0.0),
+                AnimatableFloat::Value(Self::CARET_HEIGHT),
+            ],
+            offset: [
+                AnimatableFloat::Value(0.0),
+                AnimatableFloat::Value(-0.5 * Self::CARET_HEIGHT),
+            ],
+            relative_offset_adjustment: [0.0, 0.5],
+            has_bitmap: true,
+            composite_mode: CompositeMode::FillColor(
+                init.base_system
+                    .theme
+                    .color_with_alpha(ThemeColor::Accent, Self::SELECTION_ALPHA),
+            ),
+            opacity: AnimatableFloat::Value(0.0),
+            ..Default::default()
+        });
+        let ct_text = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            relative_offset_adjustment: [0.0, 0.5],
+            has_bitmap: true,
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::Text),
+            ),
+            opacity: AnimatableFloat::Value(0.0),
+            ..Default::default()
+        });
+        let ct_preedit_underline = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [AnimatableFloat::Value(0.0), AnimatableFloat::Value(1.0)],
+            offset: [
+                AnimatableFloat::Value(0.0),
+                AnimatableFloat::Value(0.5 * Self::CARET_HEIGHT),
+            ],
+            relative_offset_adjustment: [0.0, 0.5],
+            has_bitmap: true,
+            composite_mode: CompositeMode::FillColor(
+                init.base_system.theme.color(ThemeColor::Text),
+            ),
+            opacity: AnimatableFloat::Value(0.0),
+            ..Default::default()
+        });
+        let ct_caret = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(Self::CARET_WIDTH),
+                AnimatableFloat::Value(Self::CARET_HEIGHT),
+            ],
+            offset: [
+                AnimatableFloat::Value(0.0),
+                AnimatableFloat::Value(-0.5 * Self::CARET_HEIGHT),
+            ],
+            relative_offset_adjustment: [0.0, 0.5],
+            has_bitmap: true,
+            composite_mode: CompositeMode::FillColor(
+                init.base_system.theme.color(ThemeColor::Text),
+            ),
+            opacity: AnimatableFloat::Value(0.0),
+            ..Default::default()
+        });
+
+        init.base_system
+            .set_composite_tree_parent(ct_selection, ct_content);
+        init.base_system
+            .set_composite_tree_parent(ct_text, ct_content);
+        init.base_system
+            .set_composite_tree_parent(ct_preedit_underline, ct_content);
+        init.base_system
+            .set_composite_tree_parent(ct_caret, ct_content);
+        init.base_system
+            .set_composite_tree_parent(ct_content, ct_root);
+
+        let ht_root = init.base_system.create_hit_tree(HitTestTreeData {
+            width,
+            height,
+            ..Default::default()
+        });
+
+        let focus_token = init.base_system.keyboard_focus_manager.acquire_token();
+
+        let init_text = validation.filter(init_text);
+        let caret = init_text.len();
+
+        Self {
+            ct_root,
+            ct_content,
+            ct_selection,
+            ct_text,
+            ct_preedit_underline,
+            ct_caret,
+            ht_root,
+            focus_token,
+            validation,
+            size: Cell::new((width, height)),
+            ui_scale_factor: Cell::new(init.ui_scale_factor),
+            state: RefCell::new(TextInputState {
+                text: init_text,
+                caret,
+                anchor: caret,
+                preedit: None,
+            }),
+            text_rect: Cell::new(None),
+            caret_stops: RefCell::new(vec![(0, 0.0)]),
+            scroll_x: Cell::new(0.0),
+            caret_x: Cell::new(0.0),
+            text_dirty: Cell::new(true),
+            caret_dirty: Cell::new(true),
+            pending_pointer: Cell::new(None),
+            dragging: Cell::new(false),
+            focused_render: Cell::new(false),
+            blur_requested: Cell::new(false),
+            editing: Cell::new(false),
+            edit_end: RefCell::new(None),
+        }
+    }
+
+    pub fn mount(
+        &self,
+        base_sys: &mut AppBaseSystem,
+        ct_parent: CompositeTreeRef,
+        ht_parent: HitTestTreeRef,
+    ) {
+        base_sys.set_tree_parent((self.ct_root, self.ht_root), (ct_parent, ht_parent));
+    }
+
+    pub fn unmount(&self, base_sys: &mut AppBaseSystem) {
+        if base_sys.keyboard_focus_manager.has_focus(&self.focus_token) {
+            base_sys.keyboard_focus_manager.clear_focus();
+        }
+        base_sys.composite_tree.remove_child(self.ct_root);
+        base_sys.hit_tree.remove_child(self.ht_root);
+    }
+
+    pub fn bind_action_handler<'subsystem>(
+        &self,
+        base_sys: &mut AppBaseSystem<'subsystem>,
+        handler: &Rc<impl HitTestTreeActionHandler + 'subsystem>,
+    ) {
+        base_sys.hit_tree.set_action_handler(self.ht_root, handler);
+    }
+
+    pub fn set_position(&self, base_sys: &mut AppBaseSystem, x: f32, y: f32) {
+        self.ct_root
+            .entity_mut_dirtified(&mut base_sys.composite_tree)
+            .offset = [AnimatableFloat::Value(x), AnimatableFloat::Value(y)];
+        base_sys.hit_tree.get_data_mut(self.ht_root).left = x;
+        base_sys.hit_tree.get_data_mut(self.ht_root).top = y;
+    }
+
+    pub fn set_size(&self, base_sys: &mut AppBaseSystem, width: f32, height: f32) {
+        self.size.set((width, height));
+        self.ct_root
+            .entity_mut_dirtified(&mut base_sys.composite_tree)
+            .size = [
+            AnimatableFloat::Value(width),
+            AnimatableFloat::Value(height),
+        ];
+        let ht = base_sys.hit_tree.get_data_mut(self.ht_root);
+        ht.width = width;
+        ht.height = height;
+        self.caret_dirty.set(true);
+    }
+
+    pub fn rescale(&self, base_sys: &mut AppBaseSystem, ui_scale_factor: f32) {
+        self.ui_scale_factor.set(ui_scale_factor);
+        for r in [
+            self.ct_root,
+            self.ct_content,
+            self.ct_selection,
+            self.ct_text,
+            self.ct_preedit_underline,
+            self.ct_caret,
+        ] {
+            r.entity_mut_dirtified(&mut base_sys.composite_tree)
+                .base_scale_factor = ui_scale_factor;
+        }
+        self.text_dirty.set(true);
+    }
+
+    pub fn text(&self) -> String {
+        self.state.borrow().text.clone()
+    }
+
+    pub fn set_text(&self, text: &str) {
+        let text = self.validation.filter(text);
+        let mut st = self.state.borrow_mut();
+        st.caret = text.len();
+        st.anchor = st.caret;
+        st.text = text;
+        st.preedit = None;
+        self.text_dirty.set(true);
+    }
+
+    pub fn select_all(&self) {
+        let mut st = self.state.borrow_mut();
+        st.anchor = 0;
+        st.caret = st.text.len();
+        self.caret_dirty.set(true);
+    }
+
+    pub fn focus(&self, base_sys: &mut AppBaseSystem) {
+        base_sys
+            .keyboard_focus_manager
+            .set_focus(self.focus_token, self.ht_root);
+    }
+
+    /// フォーカスを持っていれば外す(次のupdateで反映される)
+    pub fn blur(&self) {
+        self.blur_requested.set(true);
+    }
+
+    /// Enter/Escapeやフォーカスが外れて編集が終わっていればその結果を取り出す
+    pub fn take_edit_end(&self) -> Option<TextInputEditEnd> {
+        self.edit_end.borrow_mut().take()
+    }
+
+    fn end_edit(&self, end: TextInputEditEnd) {
+        if self.editing.replace(false) {
+            *self.edit_end.borrow_mut() = Some(end);
+        }
+        self.blur_requested.set(true);
+    }
+
+    pub fn update(&self, base_sys: &mut AppBaseSystem, _current_sec: f32) {
+        if self.blur_requested.replace(false)
+            && base_sys.keyboard_focus_manager.has_focus(&self.focus_token)
+        {
+            base_sys.keyboard_focus_manager.clear_focus();
+        }
+
+        let focused = base_sys.keyboard_focus_manager.has_focus(&self.focus_token);
+        if self.focused_render.replace(focused) != focused {
+            self.ct_caret
+                .entity_mut_dirtified(&mut base_sys.composite_tree)
+                .opacity = AnimatableFloat::Value(if focused { 1.0 } else { 0.0 });
+            if !focused {
+                // 変換中の文字列と選択はフォーカスと一緒に捨てる
+                let mut st = self.state.borrow_mut();
+                if st.preedit.take().is_some() {
+                    self.text_dirty.set(true);
+                }
+                st.anchor = st.caret;
+                self.dragging.set(false);
+            }
+            self.caret_dirty.set(true);
+        }
+
+        if self.text_dirty.replace(false) {
+            self.render_text(base_sys);
+            self.caret_dirty.set(true);
+        }
+
+        if let Some(p) = self.pending_pointer.take() {
+            let (x, _, _, _) = base_sys.hit_tree.translate_client_to_tree_local(
+                self.ht_root,
+                p.client_x,
+                p.client_y,
+                p.client_width,
+                p.client_height,
+            );
+            let x = x - Self::PADDING_H + self.scroll_x.get();
+            let offset = self
+                .caret_stops
+                .borrow()
+                .iter()
+                .min_by(|(_, a), (_, b)| (a - x).abs().total_cmp(&(b - x).abs()))
+                .map_or(0, |&(o, _)| o);
+            let mut st = self.state.borrow_mut();
+            // Note: 変換中は表示上の位置とtext上の位置がずれるので動かさない
+            if st.preedit.is_none() {
+                st.move_caret(offset, p.extend);
+                self.caret_dirty.set(true);
+            }
+        }
+
+        if self.caret_dirty.replace(false) {
+            self.place_caret(base_sys);
+        }
+    }
+
+    fn render_text(&self, base_sys: &mut AppBaseSystem) {
+        if let Some(r) = self.text_rect.take() {
+            base_sys.free_mask_atlas_rect(r);
+        }
+
+        let display = self.state.borrow().display_text();
+        if display.is_empty() {
+            *self.caret_stops.borrow_mut() = vec![(0, 0.0)];
+            self.ct_text
+                .entity_mut_dirtified(&mut base_sys.composite_tree)
+                .opacity = AnimatableFloat::Value(0.0);
+            return;
+        }
+
+        let ui_scale_factor = self.ui_scale_factor.get();
+        let rect = base_sys.text_mask(FontType::UI, &display).unwrap();
+        *self.caret_stops.borrow_mut() = base_sys
+            .text_caret_stops(FontType::UI, &display)
+            .into_iter()
+            .map(|(o, x)| (o, x / ui_scale_factor))
+            .collect();
+
+        let ct = self
+            .ct_text
+            .entity_mut_dirtified(&mut base_sys.composite_tree);
+        ct.texatlas_rect = rect;
+        ct.size = [
+            AnimatableFloat::Value(rect.width() as f32 / ui_scale_factor),
+            AnimatableFloat::Value(rect.height() as f32 / ui_scale_factor),
+        ];
+        ct.offset = [
+            AnimatableFloat::Value(0.0),
+            AnimatableFloat::Value(-0.5 * rect.height() as f32 / ui_scale_factor),
+        ];
+        ct.opacity = AnimatableFloat::Value(1.0);
+        self.text_rect.set(Some(rect));
+    }
+
+    fn place_caret(&self, base_sys: &mut AppBaseSystem) {
+        let st = self.state.borrow();
+        let stops = self.caret_stops.borrow();
+        let x_of = |p: usize| {
+            stops
+                .iter()
+                .find(|&&(o, _)| o >= p)
+                .or(stops.last())
+                .map_or(0.0, |&(_, x)| x)
+        };
+
+        let (caret_x, selection, preedit) = match st.preedit {
+            Some((ref t, cursor)) => (
+                x_of(st.caret + cursor.map_or(t.len(), |(b, _)| b)),
+                None,
+                Some((x_of(st.caret), x_of(st.caret + t.len()))),
+            ),
+            None => {
+                let r = st.selection();
+                (
+                    x_of(st.caret),
+                    (!r.is_empty()).then(|| (x_of(r.start), x_of(r.end))),
+                    None,
+                )
+            }
+        };
+        let text_width = stops.last().map_or(0.0, |&(_, x)| x);
+
+        // キャレットが見える範囲にスクロールする
+        let visible_width = (self.size.get().0 - Self::PADDING_H * 2.0).max(0.0);
+        let mut scroll = self.scroll_x.get();
+        if caret_x + Self::CARET_WIDTH - scroll > visible_width {
+            scroll = caret_x + Self::CARET_WIDTH - visible_width;
+        }
+        if caret_x < scroll {
+            scroll = caret_x;
+        }
+        let scroll = scroll
+            .min((text_width + Self::CARET_WIDTH - visible_width).max(0.0))
+            .max(0.0);
+        self.scroll_x.set(scroll);
+        self.caret_x.set(caret_x - scroll);
+
+        self.ct_content
+            .entity_mut_dirtified(&mut base_sys.composite_tree)
+            .offset[0] = AnimatableFloat::Value(Self::PADDING_H - scroll);
+        self.ct_caret
+            .entity_mut_dirtified(&mut base_sys.composite_tree)
+            .offset[0] = AnimatableFloat::Value(caret_x);
+
+        let ct = self
+            .ct_selection
+            .entity_mut_dirtified(&mut base_sys.composite_tree);
+        let (l, r) = selection.unwrap_or((0.0, 0.0));
+        ct.offset[0] = AnimatableFloat::Value(l);
+        ct.size[0] = AnimatableFloat::Value(r - l);
+        ct.opacity = AnimatableFloat::Value(if selection.is_some() { 1.0 } else { 0.0 });
+
+        let ct = self
+            .ct_preedit_underline
+            .entity_mut_dirtified(&mut base_sys.composite_tree);
+        let (l, r) = preedit.unwrap_or((0.0, 0.0));
+        ct.offset[0] = AnimatableFloat::Value(l);
+        ct.size[0] = AnimatableFloat::Value(r - l);
+        ct.opacity = AnimatableFloat::Value(if preedit.is_some() { 1.0 } else { 0.0 });
+    }
+
+    #[inline]
+    pub fn is_sender(&self, sender: HitTestTreeRef) -> bool {
+        sender == self.ht_root
+    }
+
+    pub fn try_handle_cursor_shape(&self, sender: HitTestTreeRef) -> Option<CursorShape> {
+        if self.is_sender(sender) {
+            return Some(CursorShape::IBeam);
+        }
+
+        None
+    }
+
+    pub fn try_handle_keyboard_focus(&self, sender: HitTestTreeRef) -> Option<FocusTargetToken> {
+        if self.is_sender(sender) {
+            return Some(self.focus_token);
+        }
+
+        None
+    }
+
+    pub fn try_handle_text_input_target(&self, sender: HitTestTreeRef) -> Option<TextInputTarget> {
+        if !self.is_sender(sender) {
+            return None;
+        }
+
+        Some(TextInputTarget {
+            caret_left: Self::PADDING_H + self.caret_x.get(),
+            caret_top: 0.5 * (self.size.get().1 - Self::CARET_HEIGHT),
+            caret_width: Self::CARET_WIDTH,
+            caret_height: Self::CARET_HEIGHT,
+            numeric_only: self.validation == TextInputValidation::Numeric,
+        })
+    }
+
+    /// フォーカスを得たら編集開始とする
+    pub fn try_handle_focus(&self, sender: HitTestTreeRef) -> bool {
+        if !self.is_sender(sender) {
+            return false;
+        }
+
+        self.editing.set(true);
+        true
+    }
+
+    /// Enter/Escape以外でフォーカスが外れたら確定扱いにする
+    pub fn try_handle_blur(&self, sender: HitTestTreeRef) -> bool {
+        if !self.is_sender(sender) {
+            return false;
+        }
+
+        if self.editing.replace(false) {
+            *self.edit_end.borrow_mut() = Some(TextInputEditEnd::Commit(self.text()));
+        }
+        true
+    }
+
+    fn set_pending_pointer(&self, args: &PointerActionArgs, extend: bool) {
+        // Note: 同じフレームで押下->移動と来たときは押下のほう(選択の解除)を優先する
+        let extend = extend && self.pending_pointer.get().is_none_or(|p| p.extend);
+        self.pending_pointer.set(Some(PendingPointer {
+            client_x: args.client_x,
+            client_y: args.client_y,
+            client_width: args.client_width,
+            client_height: args.client_height,
+            extend,
+        }));
+    }
+
+    pub fn try_handle_pointer_down(
+        &self,
+        sender: HitTestTreeRef,
+        args: &PointerActionArgs,
+    ) -> Option<EventContinueControl> {
+        if !self.is_sender(sender) {
+            return None;
+        }
+
+        self.set_pending_pointer(args, false);
+        self.dragging.set(true);
+        Some(EventContinueControl::STOP_PROPAGATION | EventContinueControl::CAPTURE_ELEMENT)
+    }
+
+    pub fn try_handle_pointer_move(
+        &self,
+        sender: HitTestTreeRef,
+        args: &PointerActionArgs,
+    ) -> Option<EventContinueControl> {
+        if !self.is_sender(sender) {
+            return None;
+        }
+
+        if self.dragging.get() {
+            self.set_pending_pointer(args, true);
+        }
+        Some(EventContinueControl::STOP_PROPAGATION)
+    }
+
+    pub fn try_handle_pointer_up(
+        &self,
+        sender: HitTestTreeRef,
+        args: &PointerActionArgs,
+    ) -> Option<EventContinueControl> {
+        if !self.is_sender(sender) {
+            return None;
+        }
+
+        if self.dragging.replace(false) {
+            self.set_pending_pointer(args, true);
+            return Some(
+                EventContinueControl::STOP_PROPAGATION
+                    | EventContinueControl::RELEASE_CAPTURE_ELEMENT,
+            );
+        }
+        Some(EventContinueControl::STOP_PROPAGATION)
+    }
+
+    pub fn try_handle_key_down(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut AppUpdateContext,
+        args: &KeyActionArgs,
+    ) -> Option<EventContinueControl> {
+        if !self.is_sender(sender) {
+            return None;
+        }
+
+        let mut st = self.state.borrow_mut();
+        if st.preedit.is_some() {
+            // 変換中のキー操作はIMEのもの
+            return Some(EventContinueControl::STOP_PROPAGATION);
+        }
+
+        let shift = args.modifiers.contains(KeyModifiers::SHIFT);
+        let ctrl = args.modifiers.contains(KeyModifiers::CTRL);
+        let selection = st.selection();
+        match args.key {
+            KeyCode::Left => {
+                let p = if !shift && !selection.is_empty() {
+                    selection.start
+                } else {
+                    st.prev_boundary(st.caret)
+                };
+                st.move_caret(p, shift);
+            }
+            KeyCode::Right => {
+                let p = if !shift && !selection.is_empty() {
+                    selection.end
+                } else {
+                    st.next_boundary(st.caret)
+                };
+                st.move_caret(p, shift);
+            }
+            KeyCode::Home | KeyCode::Up => st.move_caret(0, shift),
+            KeyCode::End | KeyCode::Down => {
+                let p = st.text.len();
+                st.move_caret(p, shift);
+            }
+            KeyCode::Backspace => {
+                st.delete_backward();
+                self.text_dirty.set(true);
+            }
+            KeyCode::Delete => {
+                st.delete_forward();
+                self.text_dirty.set(true);
+            }
+            KeyCode::Enter => {
+                let text = st.text.clone();
+                drop(st);
+                self.end_edit(TextInputEditEnd::Commit(text));
+            }
+            KeyCode::Escape => {
+                drop(st);
+                self.end_edit(TextInputEditEnd::Cancel);
+            }
+            KeyCode::A if ctrl => {
+                st.anchor = 0;
+                st.caret = st.text.len();
+            }
+            KeyCode::C if ctrl => {
+                if !selection.is_empty() {
+                    context
+                        .event_queue
+                        .push(AppEvent::UICopyText(st.text[selection].to_owned()));
+                }
+            }
+            KeyCode::X if ctrl => {
+                if !selection.is_empty() {
+                    context
+                        .event_queue
+                        .push(AppEvent::UICopyText(st.text[selection].to_owned()));
+                    st.replace_selection("");
+                    self.text_dirty.set(true);
+                }
+            }
+            KeyCode::V if ctrl => {
+                // 中身はTextInputEvent::Commitとして戻ってくる
+                context.event_queue.push(AppEvent::UIPasteText);
+            }
+            // Tabなどは親に任せる
+            _ => return Some(EventContinueControl::empty()),
+        }
+
+        self.caret_dirty.set(true);
+        Some(EventContinueControl::STOP_PROPAGATION)
+    }
+
+    pub fn try_handle_text_input(
+        &self,
+        sender: HitTestTreeRef,
+        event: &TextInputEvent,
+    ) -> Option<EventContinueControl> {
+        if !self.is_sender(sender) {
+            return None;
+        }
+
+        let mut st = self.state.borrow_mut();
+        match event {
+            TextInputEvent::Commit(text) => {
+                st.preedit = None;
+                let filtered = self.validation.filter(text);
+                // Note: 全部弾かれた入力で選択範囲が消えないようにする
+                if !filtered.is_empty() {
+                    st.replace_selection(&filtered);
+                }
+            }
+            TextInputEvent::Preedit { text, cursor } => {
+                st.preedit = (!text.is_empty()).then(|| {
+                    let cursor = cursor
+                        .filter(|&(b, e)| b <= e && e <= text.len() && text.is_char_boundary(b));
+
+                    (text.clone(), cursor)
+                });
+            }
+            &TextInputEvent::DeleteSurrounding {
+                before_length,
+                after_length,
+            } => st.delete_surrounding(before_length, after_length),
+        }
+
+        self.text_dirty.set(true);
+        Some(EventContinueControl::STOP_PROPAGATION)
+    }
+}
+
+pub struct LabelledInputFieldView {
+    ct_root: CompositeTreeRef,
+    ht_root: HitTestTreeRef,
+    spin_box_view: SpinBoxView,
+    preferred_width: f32,
+    preferred_height: f32,
+}
+impl LabelledInputFieldView {
+    const MARGIN_H_LABEL_FIELD: f32 = 4.0;
+    const MARGIN_H_FIELD_UNIT: f32 = 2.0;
+    const FIELD_UNDERLINE_THICKNESS: f32 = 1.0;
+
+    /// 値はmin..=maxの範囲で、増減ボタンつきで入力する
+    pub fn new(
+        init: &mut ViewInitContext,
+        label: &str,
+        value_size: f32,
+        unit: &str,
+        min: u32,
+        max: u32,
+    ) -> Self {
+        let label_atlas_rect = init.base_system.text_mask(FontType::UI, label).unwrap();
+        let unit_atlas_rect = init.base_system.text_mask(FontType::UI, unit).unwrap();
+
+        let field_width = value_size + SpinBoxView::BUTTON_WIDTH * 2.0;
+        let preferred_width = label_atlas_rect.width() as f32 / init.ui_scale_factor
+            + Self::MARGIN_H_LABEL_FIELD
+            + field_width
+            + Self::MARGIN_H_FIELD_UNIT
+            + unit_atlas_rect.width() as f32 / init.ui_scale_factor;
+        let preferred_height = SpinBoxView::HEIGHT;
+        let ct_root = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(preferred_width),
+                AnimatableFloat::Value(preferred_height),
+            ],
+            ..Default::default()
+        });
+        let ct_label = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(label_atlas_rect.width() as f32 / init.ui_scale_factor),
+                AnimatableFloat::Value(label_atlas_rect.height() as f32 / init.ui_scale_factor),
+            ],
+            // TODO: baseline alignment
+            offset: [
+                AnimatableFloat::Value(0.0),
+                AnimatableFloat::Value(
+                    -0.5 * label_atlas_rect.height() as f32 / init.ui_scale_factor,
+                ),
+            ],
+            relative_offset_adjustment: [0.0, 0.5],
+            has_bitmap: true,
+            texatlas_rect: label_atlas_rect,
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::Text),
+            ),
+            ..Default::default()
+        });
+        let ct_unit = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(unit_atlas_rect.width() as f32 / init.ui_scale_factor),
+                AnimatableFloat::Value(unit_atlas_rect.height() as f32 / init.ui_scale_factor),
+            ],
+            // TODO: baseline alignment
+            offset: [
+                AnimatableFloat::Value(-(unit_atlas_rect.width() as f32) / init.ui_scale_factor),
+                AnimatableFloat::Value(
+                    -0.5 * unit_atlas_rect.height() as f32 / init.ui_scale_factor,
+                ),
+            ],
+            relative_offset_adjustment: [1.0, 0.5],
+            has_bitmap: true,
+            texatlas_rect: unit_atlas_rect,
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::Text),
+            ),
+            ..Default::default()
+        });
+        let ct_field = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(
+                    -(label_atlas_rect.width() as f32 / init.ui_scale_factor
+                        + Self::MARGIN_H_LABEL_FIELD
+                        + unit_atlas_rect.width() as f32 / init.ui_scale_factor
+                        + Self::MARGIN_H_FIELD_UNIT),
+                ),
+                AnimatableFloat::Value(0.0),
+            ],
+            relative_size_adjustment: [1.0, 1.0],
+            offset: [
+                AnimatableFloat::Value(
+                    label_atlas_rect.width() as f32 / init.ui_scale_factor
+                        + Self::MARGIN_H_LABEL_FIELD,
+                ),
+                AnimatableFloat::Value(0.0),
+            ],
+            ..Default::default()
+        });
+        let ct_field_underline = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(0.0),
+                AnimatableFloat::Value(Self::FIELD_UNDERLINE_THICKNESS),
+            ],
+            relative_size_adjustment: [1.0, 0.0],
+            offset: [
+                AnimatableFloat::Value(0.0),
+                AnimatableFloat::Value(-Self::FIELD_UNDERLINE_THICKNESS),
+            ],
+            relative_offset_adjustment: [0.0, 1.0],
+            has_bitmap: true,
+            composite_mode: CompositeMode::FillColor(
+                init.base_system.theme.color(ThemeColor::TextSecondary),
+            ),
+            ..Default::default()
+        });
+        init.base_system
+            .set_composite_tree_parent(ct_field_underline, ct_field);
+        init.base_system
+            .set_composite_tree_parent(ct_label, ct_root);
+        init.base_system
+            .set_composite_tree_parent(ct_field, ct_root);
+        init.base_system.set_composite_tree_parent(ct_unit, ct_root);
+
+        let ht_root = init.base_system.create_hit_tree(HitTestTreeData {
+            width: preferred_width,
+            height: preferred_height,
+            ..Default::default()
+        });
+
+        let spin_box_view = SpinBoxView::new(init, min, max, 1, min, field_width);
+        spin_box_view.mount(init.base_system, ct_root, ht_root);
+        spin_box_view.set_position(
+            init.base_system,
+            label_atlas_rect.width() as f32 / init.ui_scale_factor + Self::MARGIN_H_LABEL_FIELD,
+            0.0,
+        );
+
+        Self {
+            ct_root,
+            ht_root,
+            spin_box_view,
+            preferred_width,
+            preferred_height,
+        }
+    }
+
+    pub fn mount(&self, base_sys: &mut AppBaseSystem, parents: (CompositeTreeRef, HitTestTreeRef)) {
+        base_sys.set_tree_parent((self.ct_root, self.ht_root), parents);
+    }
+
+    pub fn update(&self, base_sys: &mut AppBaseSystem, current_sec: f32) {
+        self.spin_box_view.update(base_sys, current_sec);
+    }
+
+    pub fn blur(&self) {
+        self.spin_box_view.blur();
+    }
+
+    #[inline]
+    pub const fn spin_box_view(&self) -> &SpinBoxView {
+        &self.spin_box_view
+    }
+
+    pub fn bind_action_handler<'subsystem>(
+        &self,
+        base_sys: &mut AppBaseSystem<'subsystem>,
+        handler: &Rc<impl HitTestTreeActionHandler + 'subsystem>,
+    ) {
+        base_sys.hit_tree.set_action_handler(self.ht_root, handler);
+        self.spin_box_view.bind_action_handler(base_sys, handler);
+    }
+}
+impl LayoutElement for LabelledInputFieldView {
+    fn layout_roots(&self) -> (CompositeTreeRef, Option<HitTestTreeRef>) {
+        (self.ct_root, Some(self.ht_root))
+    }
+
+    fn preferred_size(&self) -> (f32, f32) {
+        (self.preferred_width, self.preferred_height)
+    }
+}
+
+const fn o(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    [a[0] + b[0], a[1] + b[1]]
+}
+
+pub struct LabelledCheckboxView {
+    ct_root: CompositeTreeRef,
+    ct_box_outer: CompositeTreeRef,
+    ct_box_check: CompositeTreeRef,
+    ct_label: CompositeTreeRef,
+    ht_root: HitTestTreeRef,
+    label: String,
+    preferred_width: f32,
+    checked: Cell<bool>,
+    checked_rendered: Cell<bool>,
+}
+impl LabelledCheckboxView {
+    const CHECKICON_SIZE: f32 = 10.0;
+    const CHECKICON_THICKNESS: f32 = 3.0;
+    const CHECKICON_VERTICES: &'static [[f32; 2]] = &[
+        o(
+            [0.0, 0.5],
+            [0.0, -Self::CHECKICON_THICKNESS * 0.5 / Self::CHECKICON_SIZE],
+        ),
+        o(
+            [0.4, 0.85],
+            [0.0, -Self::CHECKICON_THICKNESS * 0.5 / Self::CHECKICON_SIZE],
+        ),
+        o(
+            [1.0, 0.1],
+            [0.0, -Self::CHECKICON_THICKNESS * 0.5 / Self::CHECKICON_SIZE],
+        ),
+        o(
+            [0.0, 0.5],
+            [0.0, Self::CHECKICON_THICKNESS * 0.5 / Self::CHECKICON_SIZE],
+        ),
+        o(
+            [0.4, 0.85],
+            [0.0, Self::CHECKICON_THICKNESS * 0.5 / Self::CHECKICON_SIZE],
+        ),
+        o(
+            [1.0, 0.1],
+            [0.0, Self::CHECKICON_THICKNESS * 0.5 / Self::CHECKICON_SIZE],
+        ),
+    ];
+    const CHECKICON_INDICES: &'static [u16] = &[0, 3, 1, 3, 1, 4, 1, 2, 4, 2, 4, 5];
+
+    fn gen_checkicon_surface(base_sys: &mut AppBaseSystem, scale: f32) -> AtlasRect {
+        let size_px = (Self::CHECKICON_SIZE * scale).ceil() as u32;
+        let atlas_rect = base_sys.alloc_mask_atlas_rect(size_px, size_px);
+
+        let msaa_tempbuf = RenderTexture::new(
+            base_sys,
+            br::Extent2D::spread1(size_px),
+            PixelFormat::R8,
+            &RenderTextureOptions {
+                msaa_count: Some(4),
+                flags: RenderTextureFlags::NON_SAMPLED | RenderTextureFlags::ALLOW_TRANSFER_SRC,
+            },
+        )
+        .unwrap();
+
+        let rp = base_sys
+            .create_render_pass(&br::RenderPassCreateInfo2::new(
+                &[msaa_tempbuf
+                    .make_attachment_description()
+                    .color_memory_op(br::LoadOp::Clear, br::StoreOp::Store)
+                    .layout_transition(
+                        br::ImageLayout::Undefined,
+                        br::ImageLayout::TransferSrcOpt,
+                    )],
+                &[br::SubpassDescription2::new()
+                    .colors(&[br::AttachmentReference2::color_attachment_opt(0)])],
+                &[br::SubpassDependency2::new(
+                    br::SubpassIndex::Internal(0),
+                    br::SubpassIndex::External,
+                )
+                .of_memory(
+                    br::AccessFlags::COLOR_ATTACHMENT.write,
+                    br::AccessFlags::TRANSFER.read,
+                )
+                .of_execution(
+                    br::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    br::PipelineStageFlags::TRANSFER,
+                )],
+            ))
+            .unwrap();
+        let fb = br::FramebufferObject::new(
+            base_sys.subsystem,
+            &br::FramebufferCreateInfo::new(
+                &rp,
+                &[msaa_tempbuf.as_transparent_ref()],
+                size_px,
+                size_px,
+            ),
+        )
+        .unwrap();
+
+        let vsh = base_sys.require_shader("resources/normalized_01_2d.vert");
+        let fsh = base_sys.require_shader("resources/fillcolor_r.frag");
+        let [pipeline] = base_sys
+            .create_graphics_pipelines_array(&[br::GraphicsPipelineCreateInfo::new(
+                base_sys.require_empty_pipeline_layout(),
+                rp.subpass(0),
+                &[
+                    vsh.on_stage(br::ShaderStage::Vertex, c"main"),
+                    fsh.on_stage(br::ShaderStage::Fragment, c"main")
+                        .with_specialization_info(&br::SpecializationInfo::new(
+                            &FillcolorRConstants { r: 1.0 },
+                        )),
+                ],
+                VI_STATE_FLOAT2_ONLY,
+                IA_STATE_TRILIST,
+                &br::PipelineViewportStateCreateInfo::new_array(
+                    &[br::Extent2D::spread1(size_px)
+                        .into_rect(br::Offset2D::ZERO)
+                        .make_viewport(0.0..1.0)],
+                    &[br::Extent2D::spread1(size_px).into_rect(br::Offset2D::ZERO)],
+                ),
+                RASTER_STATE_DEFAULT_FILL_NOCULL,
+                BLEND_STATE_SINGLE_NONE,
+            )
+            .set_multisample_state(
+                &br::PipelineMultisampleStateCreateInfo::new().rasterization_samples(4),
+            )])
+            .unwrap();
+
+        let index_offset = Self::CHECKICON_VERTICES.len() * core::mem::size_of::<[f32; 2]>();
+        let drawbuf = DeviceLocalBuffer::new(
+            base_sys,
+            index_offset + Self::CHECKICON_INDICES.len() * core::mem::size_of::<u16>(),
+            br::BufferUsage::VERTEX_BUFFER
+                | br::BufferUsage::INDEX_BUFFER
+                | br::BufferUsage::TRANSFER_DEST,
+        )
+        .unwrap();
+
+        base_sys
+            .sync_execute_graphics_commands(|rec| {
+                rec.update_buffer_slice(&drawbuf, 0, Self::CHECKICON_VERTICES)
+                    .update_buffer_slice(&drawbuf, index_offset as _, Self::CHECKICON_INDICES)
+                    .inject(|r| {
+                        inject_cmd_pipeline_barrier_2(
+                            r,
+                            base_sys.subsystem,
+                            &br::DependencyInfo::new(
+                                &[br::MemoryBarrier2::new()
+                                    .from(
+                                        br::PipelineStageFlags2::COPY,
+                                        br::AccessFlags2::TRANSFER.write,
+                                    )
+                                    .to(
+                                        br::PipelineStageFlags2::VERTEX_ATTRIBUTE_INPUT
+                                            | br::PipelineStageFlags2::INDEX_INPUT,
+                                        br::AccessFlags2::VERTEX_ATTRIBUTE_READ
+                                            | br::AccessFlags2::INDEX_READ,
+                                    )],
+                                &[],
+                                &[],
+                            ),
+                        )
+                    })
+                    .inject(|r| {
+                        inject_cmd_begin_render_pass2(
+                            r,
+                            base_sys.subsystem,
+                            &br::RenderPassBeginInfo::new(
+                                &rp,
+                                &fb,
+                                br::Extent2D::spread1(size_px).into_rect(br::Offset2D::ZERO),
+                                &[br::ClearValue::color_f32([0.0; 4])],
+                            ),
+                            &br::SubpassBeginInfo::new(br::SubpassContents::Inline),
+                        )
+                    })
+                    .bind_pipeline(br::PipelineBindPoint::Graphics, &pipeline)
+                    .bind_vertex_buffer_array(0, &[drawbuf.as_transparent_ref()], &[0])
+                    .bind_index_buffer(&drawbuf, index_offset, br::IndexType::U16)
+                    .draw_indexed(Self::CHECKICON_INDICES.len() as _, 1, 0, 0, 0)
+                    .inject(|r| {
+                        inject_cmd_end_render_pass2(
+                            r,
+                            base_sys.subsystem,
+                            &br::SubpassEndInfo::new(),
+                        )
+                    })
+                    .resolve_image(
+                        msaa_tempbuf.as_image(),
+                        br::ImageLayout::TransferSrcOpt,
+                        base_sys.mask_atlas_image_transparent_ref(),
+                        br::ImageLayout::TransferDestOpt,
+                        &[br::vk::VkImageResolve {
+                            srcSubresource: br::ImageSubresourceLayers::new(
+                                br::AspectMask::COLOR,
+                                0,
+                                0..1,
+                            ),
+                            srcOffset: br::Offset3D::ZERO,
+                            dstSubresource: br::ImageSubresourceLayers::new(
+                                br::AspectMask::COLOR,
+                                0,
+                                0..1,
+                            ),
+                            dstOffset: atlas_rect.lt_offset().with_z(0),
+                            extent: atlas_rect.extent().with_depth(1),
+                        }],
+                    )
+                    .inject(|r| {
+                        inject_cmd_pipeline_barrier_2(
+                            r,
+                            base_sys.subsystem,
+                            &br::DependencyInfo::new(
+                                &[],
+                                &[],
+                                &[base_sys
+                                    .barrier_for_mask_atlas_resource()
+                                    .from(
+                                        br::PipelineStageFlags2::RESOLVE,
+                                        br::AccessFlags2::TRANSFER.write,
+                                    )
+                                    .to(
+                                        br::PipelineStageFlags2::FRAGMENT_SHADER,
+                                        br::AccessFlags2::SHADER_SAMPLED_READ,
+                                    )
+                                    .transit_from(
+                                        br::ImageLayout::TransferDestOpt
+                                            .to(br::ImageLayout::ShaderReadOnlyOpt),
+                                    )],
+                            ),
+                        )
+                    })
+            })
+            .unwrap();
+
+        atlas_rect
+    }
+
+    pub fn new(init: &mut ViewInitContext, label: &str) -> Self {
+        let label_atlas_rect = init.base_system.text_mask(FontType::UI, label).unwrap();
+        let border_atlas_rect = init
+            .base_system
+            .rect_mask(
+                unsafe { SafeF32::new_unchecked(init.ui_scale_factor) },
+                unsafe { SafeF32::new_unchecked(1.0) },
+            )
+            .unwrap();
+        let checkicon_atlas_rect =
+            Self::gen_checkicon_surface(init.base_system, init.ui_scale_factor);
+
+        let ct_root = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(
+                    16.0 + 4.0 + label_atlas_rect.width() as f32 / init.ui_scale_factor,
+                ),
+                AnimatableFloat::Value(16.0),
+            ],
+            ..Default::default()
+        });
+        let ct_box_outer = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [AnimatableFloat::Value(16.0), AnimatableFloat::Value(16.0)],
+            has_bitmap: true,
+            texatlas_rect: border_atlas_rect,
+            slice_borders: [(1.0 * init.ui_scale_factor).ceil(); 4],
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::Text),
+            ),
+            ..Default::default()
+        });
+        let ct_box_check = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            relative_size_adjustment: [1.0, 1.0],
+            offset: [AnimatableFloat::Value(3.0), AnimatableFloat::Value(3.0)],
+            size: [AnimatableFloat::Value(-6.0), AnimatableFloat::Value(-6.0)],
+            has_bitmap: true,
+            texatlas_rect: checkicon_atlas_rect,
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::Foreground),
+            ),
+            opacity: AnimatableFloat::Value(0.0),
+            ..Default::default()
+        });
+        let ct_label = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(label_atlas_rect.width() as f32 / init.ui_scale_factor),
+                AnimatableFloat::Value(label_atlas_rect.height() as f32 / init.ui_scale_factor),
+            ],
+            offset: [
+                AnimatableFloat::Value(16.0 + 4.0),
+                AnimatableFloat::Value(
+                    -0.5 * label_atlas_rect.height() as f32 / init.ui_scale_factor,
+                ),
+            ],
+            relative_offset_adjustment: [0.0, 0.5],
+            has_bitmap: true,
+            texatlas_rect: label_atlas_rect,
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::Text),
+            ),
+            ..Default::default()
+        });
+
+        init.base_system
+            .set_composite_tree_parent(ct_box_outer, ct_root);
+        init.base_system
+            .set_composite_tree_parent(ct_box_check, ct_box_outer);
+        init.base_system
+            .set_composite_tree_parent(ct_label, ct_root);
+
+        let ht_root = init.base_system.create_hit_tree(HitTestTreeData {
+            width: 16.0 + 4.0 + label_atlas_rect.width() as f32 / init.ui_scale_factor,
+            height: 16.0,
+            ..Default::default()
+        });
+
+        Self {
+            ct_root,
+            ct_box_outer,
+            ct_box_check,
+            ct_label,
+            ht_root,
+            label: label.into(),
+            preferred_width: 16.0 + 4.0 + label_atlas_rect.width() as f32 / init.ui_scale_factor,
+            checked: Cell::new(false),
+            checked_rendered: Cell::new(false),
+        }
+    }
+
+    pub fn mount(
+        &self,
+        base_sys: &mut AppBaseSystem,
+        ct_parent: CompositeTreeRef,
+        ht_parent: HitTestTreeRef,
+    ) {
+        base_sys.set_tree_parent((self.ct_root, self.ht_root), (ct_parent, ht_parent));
+    }
+
+    pub fn bind_action_handler<'subsystem>(
+        &self,
+        base_sys: &mut AppBaseSystem<'subsystem>,
+        handler: &Rc<impl HitTestTreeActionHandler + 'subsystem>,
+    ) {
+        base_sys.hit_tree.set_action_handler(self.ht_root, handler);
+    }
+
+    pub fn rescale(&self, base_sys: &mut AppBaseSystem, ui_scale_factor: f32) {
+        let border_atlas_rect = base_sys
+            .rect_mask(unsafe { SafeF32::new_unchecked(ui_scale_factor) }, unsafe {
+                SafeF32::new_unchecked(1.0)
+            })
+            .unwrap();
+        base_sys.free_mask_atlas_rect(
+            self.ct_box_check
+                .entity(&base_sys.composite_tree)
+                .texatlas_rect,
+        );
+        let checkicon_atlas_rect = Self::gen_checkicon_surface(base_sys, ui_scale_factor);
+
+        let cr = self
+            .ct_box_outer
+            .entity_mut_dirtified(&mut base_sys.composite_tree);
+        cr.base_scale_factor = ui_scale_factor;
+        cr.texatlas_rect = border_atlas_rect;
+        cr.slice_borders = [(1.0 * ui_scale_factor).ceil(); 4];
+        let cr = self
+            .ct_box_check
+            .entity_mut_dirtified(&mut base_sys.composite_tree);
+        cr.base_scale_factor = ui_scale_factor;
+        cr.texatlas_rect = checkicon_atlas_rect;
+        self.ct_root
+            .entity_mut_dirtified(&mut base_sys.composite_tree)
+            .base_scale_factor = ui_scale_factor;
+        replace_text_label(base_sys, self.ct_label, &self.label, ui_scale_factor);
+    }
+
+    pub fn update(&self, base_sys: &mut AppBaseSystem, current_sec: f32) {
+        if self.checked.get() != self.checked_rendered.get() {
+            let c = self.checked.get();
+            self.ct_box_check
+                .entity_mut_dirtified(&mut base_sys.composite_tree)
+                .opacity = AnimatableFloat::Animated {
+                from_value: if c { 0.0 } else { 1.0 },
+                to_value: if c { 1.0 } else { 0.0 },
+                start_sec: current_sec,
+                end_sec: current_sec + 0.1,
+                curve: AnimationCurve::Linear,
+                event_on_complete: None,
+            };
+            self.checked_rendered.set(c);
+        }
+    }
+
+    pub fn try_handle_on_click(&self, sender: HitTestTreeRef) -> Option<EventContinueControl> {
+        if sender == self.ht_root {
+            self.toggle();
+            return Some(EventContinueControl::STOP_PROPAGATION);
+        }
+
+        None
+    }
+
+    pub fn set_position(&self, base_sys: &mut AppBaseSystem, x: f32, y: f32) {
+        let ct = self
+            .ct_root
+            .entity_mut_dirtified(&mut base_sys.composite_tree);
+        ct.offset = [AnimatableFloat::Value(x), AnimatableFloat::Value(y)];
+        base_sys.hit_tree.get_data_mut(self.ht_root).left = x;
+        base_sys.hit_tree.get_data_mut(self.ht_root).top = y;
+    }
+
+    #[inline]
+    pub fn toggle(&self) {
+        self.checked.update(|x| !x);
+    }
+
+    #[inline]
+    pub fn set_checked(&self, checked: bool) {
+        self.checked.set(checked);
+    }
+
+    pub const fn checked(&self) -> bool {
+        self.checked.get()
+    }
+
+    pub fn is_sender(&self, sender: HitTestTreeRef) -> bool {
+        sender == self.ht_root
+    }
+}
+impl LayoutElement for LabelledCheckboxView {
+    fn layout_roots(&self) -> (CompositeTreeRef, Option<HitTestTreeRef>) {
+        (self.ct_root, Some(self.ht_root))
+    }
+
+    fn preferred_size(&self) -> (f32, f32) {
+        (self.preferred_width, 16.0)
+    }
+}
+
+/// 縦方向中央揃えのテキストラベル
+fn text_label_rect(
+    atlas_rect: AtlasRect,
+    ui_scale_factor: f32,
+    left: f32,
+    color: AnimatableColor,
+) -> CompositeRect {
+    CompositeRect {
+        base_scale_factor: ui_scale_factor,
+        size: [
+            AnimatableFloat::Value(atlas_rect.width() as f32 / ui_scale_factor),
+            AnimatableFloat::Value(atlas_rect.height() as f32 / ui_scale_factor),
+        ],
+        offset: [
+            AnimatableFloat::Value(left),
+            AnimatableFloat::Value(-0.5 * atlas_rect.height() as f32 / ui_scale_factor),
+        ],
+        relative_offset_adjustment: [0.0, 0.5],
+        has_bitmap: true,
+        texatlas_rect: atlas_rect,
+        composite_mode: CompositeMode::ColorTint(color),
+        ..Default::default()
+    }
+}
+
+/// text_label_rectで作ったラベルのテキストを差し替える
+fn replace_text_label(
+    base_sys: &mut AppBaseSystem,
+    ct: CompositeTreeRef,
+    text: &str,
+    ui_scale_factor: f32,
+) -> AtlasRect {
+    base_sys.free_mask_atlas_rect(ct.entity(&base_sys.composite_tree).texatlas_rect);
+    let atlas_rect = base_sys.text_mask(FontType::UI, text).unwrap();
+
+    let cr = ct.entity_mut_dirtified(&mut base_sys.composite_tree);
+    cr.base_scale_factor = ui_scale_factor;
+    cr.texatlas_rect = atlas_rect;
+    cr.size = [
+        AnimatableFloat::Value(atlas_rect.width() as f32 / ui_scale_factor),
+        AnimatableFloat::Value(atlas_rect.height() as f32 / ui_scale_factor),
+    ];
+    cr.offset[1] = AnimatableFloat::Value(-0.5 * atlas_rect.height() as f32 / ui_scale_factor);
+
+    atlas_rect
+}
+
+/// 選択肢をポップアップのリストから選ぶ
+pub struct DropdownView {
+    ct_root: CompositeTreeRef,
+    ct_label: CompositeTreeRef,
+    ct_chevron: CompositeTreeRef,
+    ct_list: CompositeTreeRef,
+    ct_list_highlight: CompositeTreeRef,
+    ct_item_labels: Vec<CompositeTreeRef>,
+    ht_root: HitTestTreeRef,
+    ht_dismiss: HitTestTreeRef,
+    ht_list: HitTestTreeRef,
+    ht_items: Vec<HitTestTreeRef>,
+    items: Vec<String>,
+    width: f32,
+    ui_scale_factor: Cell<f32>,
+    /// リストを出すレイヤー(クライアント全体を覆うもの)
+    overlay: Cell<Option<(CompositeTreeRef, HitTestTreeRef)>>,
+    selected: Cell<usize>,
+    selected_rendered: Cell<Option<usize>>,
+    hovered_item: Cell<Option<usize>>,
+    hovered_item_rendered: Cell<Option<usize>>,
+    open_request: Cell<Option<PointerActionArgs>>,
+    close_request: Cell<bool>,
+    opened: Cell<bool>,
+}
+impl DropdownView {
+    const HEIGHT: f32 = 20.0;
+    const PADDING_H: f32 = 6.0;
+    const ITEM_HEIGHT: f32 = 20.0;
+    const LIST_PADDING_V: f32 = 4.0;
+    const LIST_GAP: f32 = 2.0;
+    const CORNER_RADIUS: SafeF32 = unsafe { SafeF32::new_unchecked(4.0) };
+    const CHEVRON: &'static str = "▾";
+
+    /// itemsは空にできない
+    #[tracing::instrument(name = "DropdownView::new", skip(init))]
+    pub fn new(init: &mut ViewInitContext, items: &[&str], width: f32) -> Self {
+        assert!(!items.is_empty(), "dropdown requires at least one item");
+
+        let render_scale = unsafe { SafeF32::new_unchecked(init.ui_scale_factor.ceil()) };
+        let frame_atlas_rect = init
+            .base_system
+            .rounded_rect_mask(render_scale, Self::CORNER_RADIUS, unsafe {
+                SafeF32::new_unchecked(1.0)
+            })
+            .unwrap();
+        let list_bg_atlas_rect = init
+            .base_system
+            .rounded_fill_rect_mask(render_scale, Self::CORNER_RADIUS)
+            .unwrap();
+        let label_atlas_rect = init.base_system.text_mask(FontType::UI, items[0]).unwrap();
+        let chevron_atlas_rect = init
+            .base_system
+            .text_mask(FontType::UI, Self::CHEVRON)
+            .unwrap();
+        let list_height = items.len() as f32 * Self::ITEM_HEIGHT + Self::LIST_PADDING_V * 2.0;
+
+        let ct_root = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(width),
+                AnimatableFloat::Value(Self::HEIGHT),
+            ],
+            has_bitmap: true,
+            texatlas_rect: frame_atlas_rect,
+            slice_borders: [Self::CORNER_RADIUS.value() * render_scale.value(); 4],
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system
+                    .theme
+                    .color_with_alpha(ThemeColor::Text, 0.5),
+            ),
+            ..Default::default()
+        });
+        let ct_label = init.base_system.register_composite_rect(text_label_rect(
+            label_atlas_rect,
+            init.ui_scale_factor,
+            Self::PADDING_H,
+            init.base_system.theme.color(ThemeColor::Text),
+        ));
+        let ct_chevron = init.base_system.register_composite_rect(CompositeRect {
+            relative_offset_adjustment: [1.0, 0.5],
+            ..text_label_rect(
+                chevron_atlas_rect,
+                init.ui_scale_factor,
+                -Self::PADDING_H - chevron_atlas_rect.width() as f32 / init.ui_scale_factor,
+                init.base_system.theme.color(ThemeColor::Text),
+            )
+        });
+        let ct_list = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(width),
+                AnimatableFloat::Value(list_height),
+            ],
+            has_bitmap: true,
+            texatlas_rect: list_bg_atlas_rect,
+            slice_borders: [Self::CORNER_RADIUS.value() * render_scale.value(); 4],
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::MenuBackground),
+            ),
+            ..Default::default()
+        });
+        let ct_list_highlight = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(0.0),
+                AnimatableFloat::Value(Self::ITEM_HEIGHT),
+            ],
+            relative_size_adjustment: [1.0, 0.0],
+            has_bitmap: true,
+            composite_mode: CompositeMode::FillColor(
+                init.base_system
+                    .theme
+                    .color_with_alpha(ThemeColor::Foreground, 0.125),
+            ),
+            opacity: AnimatableFloat::Value(0.0),
+            ..Default::default()
+        });
+
+        init.base_system
+            .set_composite_tree_parent(ct_label, ct_root);
+        init.base_system
+            .set_composite_tree_parent(ct_chevron, ct_root);
+        init.base_system
+            .set_composite_tree_parent(ct_list_highlight, ct_list);
+
+        let ht_root = init.base_system.create_hit_tree(HitTestTreeData {
+            width,
+            height: Self::HEIGHT,
+            ..Default::default()
+        });
+        // Note: リストの外をクリックしたら閉じる
+        let ht_dismiss = init.base_system.create_hit_tree(HitTestTreeData {
+            width_adjustment_factor: 1.0,
+            height_adjustment_factor: 1.0,
+            ..Default::default()
+        });
+        let ht_list = init.base_system.create_hit_tree(HitTestTreeData {
+            width,
+            height: list_height,
+            ..Default::default()
+        });
+
+        let mut ct_item_labels = Vec::with_capacity(items.len());
+        let mut ht_items = Vec::with_capacity(items.len());
+        for (n, &item) in items.iter().enumerate() {
+            let top = Self::LIST_PADDING_V + n as f32 * Self::ITEM_HEIGHT;
+
+            let item_atlas_rect = init.base_system.text_mask(FontType::UI, item).unwrap();
+            let ct_row = init.base_system.register_composite_rect(CompositeRect {
+                base_scale_factor: init.ui_scale_factor,
+                offset: [AnimatableFloat::Value(0.0), AnimatableFloat::Value(top)],
+                size: [
+                    AnimatableFloat::Value(0.0),
+                    AnimatableFloat::Value(Self::ITEM_HEIGHT),
+                ],
+                relative_size_adjustment: [1.0, 0.0],
+                ..Default::default()
+            });
+            let ct_item_label = init.base_system.register_composite_rect(text_label_rect(
+                item_atlas_rect,
+                init.ui_scale_factor,
+                Self::PADDING_H,
+                init.base_system.theme.color(ThemeColor::Text),
+            ));
+            init.base_system
+                .set_composite_tree_parent(ct_item_label, ct_row);
+            init.base_system.set_composite_tree_parent(ct_row, ct_list);
+
+            let ht_item = init.base_system.create_hit_tree(HitTestTreeData {
+                top,
+                height: Self::ITEM_HEIGHT,
+                width_adjustment_factor: 1.0,
+                ..Default::default()
+            });
+            init.base_system.set_hit_tree_parent(ht_item, ht_list);
+
+            ct_item_labels.push(ct_item_label);
+            ht_items.push(ht_item);
+        }
+
+        Self {
+            ct_root,
+            ct_label,
+            ct_chevron,
+            ct_list,
+            ct_list_highlight,
+            ct_item_labels,
+            ht_root,
+            ht_dismiss,
+            ht_list,
+            ht_items,
+            items: items.iter().map(|&x| x.into()).collect(),
+            width,
+            ui_scale_factor: Cell::new(init.ui_scale_factor),
+            overlay: Cell::new(None),
+            selected: Cell::new(0),
+            selected_rendered: Cell::new(Some(0)),
+            hovered_item: Cell::new(None),
+            hovered_item_rendered: Cell::new(None),
+            open_request: Cell::new(None),
+            close_request: Cell::new(false),
+            opened: Cell::new(false),
+        }
+    }
+
+    /// overlayにはリストを重ねて表示するための、クライアント全体を覆う親を指定する
+    pub fn mount(
+        &self,
+        base_sys: &mut AppBaseSystem,
+        ct_parent: CompositeTreeRef,
+        ht_parent: HitTestTreeRef,
+        overlay: (CompositeTreeRef, HitTestTreeRef),
+    ) {
+        base_sys.set_tree_parent((self.ct_root, self.ht_root), (ct_parent, ht_parent));
+        self.overlay.set(Some(overlay));
+    }
+
+    pub fn bind_action_handler<'subsystem>(
+        &self,
+        base_sys: &mut AppBaseSystem<'subsystem>,
+        handler: &Rc<impl HitTestTreeActionHandler + 'subsystem>,
+    ) {
+        base_sys.hit_tree.set_action_handler(self.ht_root, handler);
+        base_sys
+            .hit_tree
+            .set_action_handler(self.ht_dismiss, handler);
+        base_sys.hit_tree.set_action_handler(self.ht_list, handler);
+        for &x in self.ht_items.iter() {
+            base_sys.hit_tree.set_action_handler(x, handler);
+        }
+    }
+
+    pub fn set_position(&self, base_sys: &mut AppBaseSystem, x: f32, y: f32) {
+        self.ct_root
+            .entity_mut_dirtified(&mut base_sys.composite_tree)
+            .offset = [AnimatableFloat::Value(x), AnimatableFloat::Value(y)];
+        base_sys.hit_tree.get_data_mut(self.ht_root).left = x;
+        base_sys.hit_tree.get_data_mut(self.ht_root).top = y;
+    }
+
+    pub fn rescale(&self, base_sys: &mut AppBaseSystem, ui_scale_factor: f32) {
+        self.ui_scale_factor.set(ui_scale_factor);
+
+        let render_scale = unsafe { SafeF32::new_unchecked(ui_scale_factor.ceil()) };
+        let frame_atlas_rect = base_sys
+            .rounded_rect_mask(render_scale, Self::CORNER_RADIUS, unsafe {
+                SafeF32::new_unchecked(1.0)
+            })
+            .unwrap();
+        let list_bg_atlas_rect = base_sys
+            .rounded_fill_rect_mask(render_scale, Self::CORNER_RADIUS)
+            .unwrap();
+        for (ct, atlas_rect) in [
+            (self.ct_root, frame_atlas_rect),
+            (self.ct_list, list_bg_atlas_rect),
+        ] {
+            let cr = ct.entity_mut_dirtified(&mut base_sys.composite_tree);
+            cr.base_scale_factor = ui_scale_factor;
+            cr.texatlas_rect = atlas_rect;
+            cr.slice_borders = [Self::CORNER_RADIUS.value() * render_scale.value(); 4];
+        }
+        self.ct_list_highlight
+            .entity_mut_dirtified(&mut base_sys.composite_tree)
+            .base_scale_factor = ui_scale_factor;
+
+        let chevron_atlas_rect =
+            replace_text_label(base_sys, self.ct_chevron, Self::CHEVRON, ui_scale_factor);
+        self.ct_chevron
+            .entity_mut_dirtified(&mut base_sys.composite_tree)
+            .offset[0] = AnimatableFloat::Value(
+            -Self::PADDING_H - chevron_atlas_rect.width() as f32 / ui_scale_factor,
+        );
+        for (&ct, item) in self.ct_item_labels.iter().zip(self.items.iter()) {
+            replace_text_label(base_sys, ct, item, ui_scale_factor);
+        }
+        self.selected_rendered.set(None);
+    }
+
+    pub fn update(&self, base_sys: &mut AppBaseSystem, current_sec: f32) {
+        if self.selected_rendered.get() != Some(self.selected.get()) {
+            replace_text_label(
+                base_sys,
+                self.ct_label,
+                &self.items[self.selected.get()],
+                self.ui_scale_factor.get(),
+            );
+            self.selected_rendered.set(Some(self.selected.get()));
+        }
+
+        if self.close_request.replace(false) && self.opened.replace(false) {
+            base_sys.composite_tree.remove_child(self.ct_list);
+            base_sys.hit_tree.remove_child(self.ht_list);
+            base_sys.hit_tree.remove_child(self.ht_dismiss);
+            self.hovered_item.set(None);
+        }
+
+        if let Some(args) = self.open_request.take()
+            && let Some((ct_overlay, ht_overlay)) = self.overlay.get()
+            && !self.opened.replace(true)
+        {
+            let (x, y, _, _) = base_sys.hit_tree.translate_client_to_tree_local(
+                self.ht_root,
+                0.0,
+                0.0,
+                args.client_width,
+                args.client_height,
+            );
+            let (overlay_x, overlay_y, _, overlay_height) =
+                base_sys.hit_tree.translate_client_to_tree_local(
+                    ht_overlay,
+                    0.0,
+                    0.0,
+                    args.client_width,
+                    args.client_height,
+                );
+            // 自分の左上をoverlay上の座標にする
+            let (left, top) = (overlay_x - x, overlay_y - y);
+            let list_height = base_sys.hit_tree.get_data(self.ht_list).height;
+            // 下にはみ出るなら上に出す
+            let list_top = if top + Self::HEIGHT + Self::LIST_GAP + list_height > overlay_height {
+                top - Self::LIST_GAP - list_height
+            } else {
+                top + Self::HEIGHT + Self::LIST_GAP
+            };
+
+            let cr = self
+                .ct_list
+                .entity_mut_dirtified(&mut base_sys.composite_tree);
+            cr.offset = [
+                AnimatableFloat::Value(left),
+                AnimatableFloat::Value(list_top),
+            ];
+            cr.opacity = AnimatableFloat::Animated {
+                from_value: 0.0,
+                to_value: 1.0,
+                start_sec: current_sec,
+                end_sec: current_sec + 0.1,
+                curve: AnimationCurve::Linear,
+                event_on_complete: None,
+            };
+            let ht = base_sys.hit_tree.get_data_mut(self.ht_list);
+            ht.left = left;
+            ht.top = list_top;
+
+            base_sys.set_hit_tree_parent(self.ht_dismiss, ht_overlay);
+            base_sys.set_tree_parent((self.ct_list, self.ht_list), (ct_overlay, ht_overlay));
+        }
+
+        if self.hovered_item_rendered.get() != self.hovered_item.get() {
+            let hovered = self.hovered_item.get();
+            let cr = self
+                .ct_list_highlight
+                .entity_mut_dirtified(&mut base_sys.composite_tree);
+            cr.offset[1] = AnimatableFloat::Value(
+                Self::LIST_PADDING_V + hovered.unwrap_or(0) as f32 * Self::ITEM_HEIGHT,
+            );
+            cr.opacity = AnimatableFloat::Value(if hovered.is_some() { 1.0 } else { 0.0 });
+            self.hovered_item_rendered.set(hovered);
+        }
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected.get()
+    }
+
+    pub fn close(&self) {
+        self.close_request.set(true);
+    }
+
+    /// リストやその外側を含めて自分の要素か
+    pub fn is_sender(&self, sender: HitTestTreeRef) -> bool {
+        sender == self.ht_root
+            || sender == self.ht_dismiss
+            || sender == self.ht_list
+            || self.ht_items.contains(&sender)
+    }
+
+    pub fn try_handle_cursor_shape(&self, sender: HitTestTreeRef) -> Option<CursorShape> {
+        if sender == self.ht_root || self.ht_items.contains(&sender) {
+            return Some(CursorShape::Pointer);
+        }
+
+        None
+    }
+
+    pub fn try_handle_pointer_enter(&self, sender: HitTestTreeRef) -> Option<EventContinueControl> {
+        let n = self.ht_items.iter().position(|&x| x == sender)?;
+        self.hovered_item.set(Some(n));
+
+        Some(EventContinueControl::STOP_PROPAGATION)
+    }
+
+    pub fn try_handle_pointer_leave(&self, sender: HitTestTreeRef) -> Option<EventContinueControl> {
+        let n = self.ht_items.iter().position(|&x| x == sender)?;
+        if self.hovered_item.get() == Some(n) {
+            self.hovered_item.set(None);
+        }
+
+        Some(EventContinueControl::STOP_PROPAGATION)
+    }
+
+    /// 項目が選ばれたらselected()が変わる
+    pub fn try_handle_on_click(
+        &self,
+        sender: HitTestTreeRef,
+        args: &PointerActionArgs,
+    ) -> Option<EventContinueControl> {
+        if sender == self.ht_root {
+            if self.opened.get() {
+                self.close_request.set(true);
+            } else {
+                self.open_request.set(Some(*args));
+            }
+
+            return Some(EventContinueControl::STOP_PROPAGATION);
+        }
+        if let Some(n) = self.ht_items.iter().position(|&x| x == sender) {
+            self.selected.set(n);
+            self.close_request.set(true);
+
+            return Some(EventContinueControl::STOP_PROPAGATION);
+        }
+        if sender == self.ht_dismiss {
+            self.close_request.set(true);
+
+            return Some(EventContinueControl::STOP_PROPAGATION);
+        }
+        if sender == self.ht_list {
+            // guard
+            return Some(EventContinueControl::STOP_PROPAGATION);
+        }
+
+        None
+    }
+}
+impl LayoutElement for DropdownView {
+    fn layout_roots(&self) -> (CompositeTreeRef, Option<HitTestTreeRef>) {
+        (self.ct_root, Some(self.ht_root))
+    }
+
+    fn preferred_size(&self) -> (f32, f32) {
+        (self.width, Self::HEIGHT)
+    }
+}
+
+/// 範囲内の値をドラッグで選ぶ
+pub struct SliderView {
+    ct_root: CompositeTreeRef,
+    ct_track: CompositeTreeRef,
+    ct_fill: CompositeTreeRef,
+    ct_knob: CompositeTreeRef,
+    ht_root: HitTestTreeRef,
+    range: (f32, f32),
+    step: f32,
+    width: f32,
+    value: Cell<f32>,
+    value_rendered: Cell<Option<f32>>,
+    pending_pointer: Cell<Option<PointerActionArgs>>,
+    dragging: Cell<bool>,
+    changed: Cell<bool>,
+}
+impl SliderView {
+    const HEIGHT: f32 = 16.0;
+    const TRACK_THICKNESS: f32 = 2.0;
+    const KNOB_SIZE: f32 = 12.0;
+    const KNOB_RADIUS: SafeF32 = unsafe { SafeF32::new_unchecked(6.0) };
+
+    /// stepが0なら連続値
+    #[tracing::instrument(name = "SliderView::new", skip(init))]
+    pub fn new(
+        init: &mut ViewInitContext,
+        min: f32,
+        max: f32,
+        step: f32,
+        init_value: f32,
+        width: f32,
+    ) -> Self {
+        let render_scale = unsafe { SafeF32::new_unchecked(init.ui_scale_factor.ceil()) };
+        let knob_atlas_rect = init
+            .base_system
+            .rounded_fill_rect_mask(render_scale, Self::KNOB_RADIUS)
+            .unwrap();
+
+        let ct_root = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(width),
+                AnimatableFloat::Value(Self::HEIGHT),
+            ],
+            ..Default::default()
+        });
+        let ct_track = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            offset: [
+                AnimatableFloat::Value(Self::KNOB_SIZE * 0.5),
+                AnimatableFloat::Value(-Self::TRACK_THICKNESS * 0.5),
+            ],
+            size: [
+                AnimatableFloat::Value(-Self::KNOB_SIZE),
+                AnimatableFloat::Value(Self::TRACK_THICKNESS),
+            ],
+            relative_offset_adjustment: [0.0, 0.5],
+            relative_size_adjustment: [1.0, 0.0],
+            has_bitmap: true,
+            composite_mode: CompositeMode::FillColor(
+                init.base_system
+                    .theme
+                    .color_with_alpha(ThemeColor::Text, 0.25),
+            ),
+            ..Default::default()
+        });
+        let ct_fill = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            relative_size_adjustment: [0.0, 1.0],
+            has_bitmap: true,
+            composite_mode: CompositeMode::FillColor(
+                init.base_system.theme.color(ThemeColor::Accent),
+            ),
+            ..Default::default()
+        });
+        let ct_knob = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            offset: [
+                AnimatableFloat::Value(0.0),
+                AnimatableFloat::Value(-Self::KNOB_SIZE * 0.5),
+            ],
+            size: [
+                AnimatableFloat::Value(Self::KNOB_SIZE),
+                AnimatableFloat::Value(Self::KNOB_SIZE),
+            ],
+            relative_offset_adjustment: [0.0, 0.5],
+            has_bitmap: true,
+            texatlas_rect: knob_atlas_rect,
+            slice_borders: [Self::KNOB_RADIUS.value() * render_scale.value(); 4],
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::Text),
+            ),
+            ..Default::default()
+        });
+
+        init.base_system
+            .set_composite_tree_parent(ct_fill, ct_track);
+        init.base_system
+            .set_composite_tree_parent(ct_track, ct_root);
+        init.base_system.set_composite_tree_parent(ct_knob, ct_root);
+
+        let ht_root = init.base_system.create_hit_tree(HitTestTreeData {
+            width,
+            height: Self::HEIGHT,
+            ..Default::default()
+        });
+
+        Self {
+            ct_root,
+            ct_track,
+            ct_fill,
+            ct_knob,
+            ht_root,
+            range: (min, max),
+            step,
+            width,
+            value: Cell::new(Self::snap((min, max), step, init_value)),
+            value_rendered: Cell::new(None),
+            pending_pointer: Cell::new(None),
+            dragging: Cell::new(false),
+            changed: Cell::new(false),
+        }
+    }
+
+    pub fn mount(
+        &self,
+        base_sys: &mut AppBaseSystem,
+        ct_parent: CompositeTreeRef,
+        ht_parent: HitTestTreeRef,
+    ) {
+        base_sys.set_tree_parent((self.ct_root, self.ht_root), (ct_parent, ht_parent));
+    }
+
+    pub fn bind_action_handler<'subsystem>(
+        &self,
+        base_sys: &mut AppBaseSystem<'subsystem>,
+        handler: &Rc<impl HitTestTreeActionHandler + 'subsystem>,
+    ) {
+        base_sys.hit_tree.set_action_handler(self.ht_root, handler);
+    }
+
+    pub fn rescale(&self, base_sys: &mut AppBaseSystem, ui_scale_factor: f32) {
+        let render_scale = unsafe { SafeF32::new_unchecked(ui_scale_factor.ceil()) };
+        let knob_atlas_rect = base_sys
+            .rounded_fill_rect_mask(render_scale, Self::KNOB_RADIUS)
+            .unwrap();
+
+        for ct in [self.ct_root, self.ct_track, self.ct_fill] {
+            ct.entity_mut_dirtified(&mut base_sys.composite_tree)
+                .base_scale_factor = ui_scale_factor;
+        }
+        let cr = self
+            .ct_knob
+            .entity_mut_dirtified(&mut base_sys.composite_tree);
+        cr.base_scale_factor = ui_scale_factor;
+        cr.texatlas_rect = knob_atlas_rect;
+        cr.slice_borders = [Self::KNOB_RADIUS.value() * render_scale.value(); 4];
+    }
+
+    /// 範囲内に収めてstep刻みに丸める
+    fn snap((min, max): (f32, f32), step: f32, value: f32) -> f32 {
+        let value = if step > 0.0 {
+            min + ((value - min) / step).round() * step
+        } else {
+            value
+        };
+
+        value.clamp(min, max)
+    }
+
+    pub fn update(&self, base_sys: &mut AppBaseSystem, current_sec: f32) {
+        let (min, max) = self.range;
+        let movable_width = (self.width - Self::KNOB_SIZE).max(0.0);
+
+        if let Some(args) = self.pending_pointer.take() {
+            let (x, _, _, _) = base_sys.hit_tree.translate_client_to_tree_local(
+                self.ht_root,
+                args.client_x,
+                args.client_y,
+                args.client_width,
+                args.client_height,
+            );
+            let ratio = if movable_width > 0.0 {
+                ((x - Self::KNOB_SIZE * 0.5) / movable_width).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let value = Self::snap(self.range, self.step, min + ratio * (max - min));
+            if self.value.replace(value) != value {
+                self.changed.set(true);
+            }
+        }
+
+        if self.value_rendered.get() != Some(self.value.get()) {
+            let ratio = if max > min {
+                (self.value.get() - min) / (max - min)
+            } else {
+                0.0
+            };
+
+            self.ct_fill
+                .entity_mut_dirtified(&mut base_sys.composite_tree)
+                .size[0] = AnimatableFloat::Value(ratio * movable_width);
+            let current_x = self.ct_knob.entity(&base_sys.composite_tree).offset[0]
+                .evaluate(current_sec, base_sys.composite_tree.parameter_store());
+            let cr = self
+                .ct_knob
+                .entity_mut_dirtified(&mut base_sys.composite_tree);
+            // Note: ドラッグ中はそのまま追従させる
+            cr.offset[0] = if self.dragging.get() || self.value_rendered.get().is_none() {
+                AnimatableFloat::Value(ratio * movable_width)
+            } else {
+                AnimatableFloat::Animated {
+                    from_value: current_x,
+                    to_value: ratio * movable_width,
+                    start_sec: current_sec,
+                    end_sec: current_sec + 0.1,
+                    curve: AnimationCurve::CubicBezier {
+                        p1: (0.5, 0.0),
+                        p2: (0.5, 1.0),
+                    },
+                    event_on_complete: None,
+                }
+            };
+            self.value_rendered.set(Some(self.value.get()));
+        }
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value.get()
+    }
+
+    pub fn set_value(&self, value: f32) {
+        self.value.set(Self::snap(self.range, self.step, value));
+    }
+
+    /// ユーザー操作で値が変わっていたらtrue
+    pub fn take_changed(&self) -> bool {
+        self.changed.replace(false)
+    }
+
+    pub fn try_handle_cursor_shape(&self, sender: HitTestTreeRef) -> Option<CursorShape> {
+        if sender == self.ht_root {
+            return Some(CursorShape::Pointer);
+        }
+
+        None
+    }
+
+    pub fn try_handle_pointer_down(
+        &self,
+        sender: HitTestTreeRef,
+        args: &PointerActionArgs,
+    ) -> Option<EventContinueControl> {
+        if sender != self.ht_root {
+            return None;
+        }
+
+        self.pending_pointer.set(Some(*args));
+        self.dragging.set(true);
+        Some(EventContinueControl::STOP_PROPAGATION | EventContinueControl::CAPTURE_ELEMENT)
+    }
+
+    pub fn try_handle_pointer_move(
+        &self,
+        sender: HitTestTreeRef,
+        args: &PointerActionArgs,
+    ) -> Option<EventContinueControl> {
+        if sender != self.ht_root {
+            return None;
+        }
+
+        if self.dragging.get() {
+            self.pending_pointer.set(Some(*args));
+        }
+        Some(EventContinueControl::STOP_PROPAGATION)
+    }
+
+    pub fn try_handle_pointer_up(
+        &self,
+        sender: HitTestTreeRef,
+        args: &PointerActionArgs,
+    ) -> Option<EventContinueControl> {
+        if sender != self.ht_root {
+            return None;
+        }
+
+        if self.dragging.replace(false) {
+            self.pending_pointer.set(Some(*args));
+            return Some(
+                EventContinueControl::STOP_PROPAGATION
+                    | EventContinueControl::RELEASE_CAPTURE_ELEMENT,
+            );
+        }
+        Some(EventContinueControl::STOP_PROPAGATION)
+    }
+}
+impl LayoutElement for SliderView {
+    fn layout_roots(&self) -> (CompositeTreeRef, Option<HitTestTreeRef>) {
+        (self.ct_root, Some(self.ht_root))
+    }
+
+    fn preferred_size(&self) -> (f32, f32) {
+        (self.width, Self::HEIGHT)
+    }
+}
+
+/// 増減ボタンつきの数値入力欄
+pub struct SpinBoxView {
+    ct_root: CompositeTreeRef,
+    ht_root: HitTestTreeRef,
+    ht_decrement: HitTestTreeRef,
+    ht_increment: HitTestTreeRef,
+    ct_button_labels: [CompositeTreeRef; 2],
+    input_view: TextInputView,
+    width: f32,
+    range: (u32, u32),
+    step: u32,
+    changed: Cell<bool>,
+}
+impl SpinBoxView {
+    const HEIGHT: f32 = 20.0;
+    const BUTTON_WIDTH: f32 = 16.0;
+    const BUTTON_LABELS: [&'static str; 2] = ["-", "+"];
+
+    #[tracing::instrument(name = "SpinBoxView::new", skip(init))]
+    pub fn new(
+        init: &mut ViewInitContext,
+        min: u32,
+        max: u32,
+        step: u32,
+        init_value: u32,
+        width: f32,
+    ) -> Self {
+        let ct_root = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(width),
+                AnimatableFloat::Value(Self::HEIGHT),
+            ],
+            ..Default::default()
+        });
+        let ht_root = init.base_system.create_hit_tree(HitTestTreeData {
+            width,
+            height: Self::HEIGHT,
+            ..Default::default()
+        });
+
+        let input_view = TextInputView::new(
+            init,
+            &init_value.clamp(min, max).to_string(),
+            width - Self::BUTTON_WIDTH * 2.0,
+            Self::HEIGHT,
+            TextInputValidation::Numeric,
+        );
+        input_view.mount(init.base_system, ct_root, ht_root);
+
+        let mut ht_buttons = [HitTestTreeManager::ROOT; 2];
+        let mut ct_button_labels = [CompositeTree::ROOT; 2];
+        for (n, label) in Self::BUTTON_LABELS.into_iter().enumerate() {
+            let left = -Self::BUTTON_WIDTH * (2 - n) as f32;
+
+            let label_atlas_rect = init.base_system.text_mask(FontType::UI, label).unwrap();
+            let ct_button = init.base_system.register_composite_rect(CompositeRect {
+                base_scale_factor: init.ui_scale_factor,
+                offset: [AnimatableFloat::Value(left), AnimatableFloat::Value(0.0)],
+                size: [
+                    AnimatableFloat::Value(Self::BUTTON_WIDTH),
+                    AnimatableFloat::Value(0.0),
+                ],
+                relative_offset_adjustment: [1.0, 0.0],
+                relative_size_adjustment: [0.0, 1.0],
+                has_bitmap: true,
+                composite_mode: CompositeMode::FillColor(
+                    init.base_system
+                        .theme
+                        .color_with_alpha(ThemeColor::Foreground, 0.0625),
+                ),
+                ..Default::default()
+            });
+            let ct_label = init.base_system.register_composite_rect(text_label_rect(
+                label_atlas_rect,
+                init.ui_scale_factor,
+                0.5 * (Self::BUTTON_WIDTH - label_atlas_rect.width() as f32 / init.ui_scale_factor),
+                init.base_system.theme.color(ThemeColor::Text),
+            ));
+            init.base_system
+                .set_composite_tree_parent(ct_label, ct_button);
+            init.base_system
+                .set_composite_tree_parent(ct_button, ct_root);
+
+            let ht_button = init.base_system.create_hit_tree(HitTestTreeData {
+                left,
+                width: Self::BUTTON_WIDTH,
+                left_adjustment_factor: 1.0,
+                height_adjustment_factor: 1.0,
+                ..Default::default()
+            });
+            init.base_system.set_hit_tree_parent(ht_button, ht_root);
+
+            ht_buttons[n] = ht_button;
+            ct_button_labels[n] = ct_label;
+        }
+
+        Self {
+            ct_root,
+            ht_root,
+            ht_decrement: ht_buttons[0],
+            ht_increment: ht_buttons[1],
+            ct_button_labels,
+            input_view,
+            width,
+            range: (min, max),
+            step,
+            changed: Cell::new(false),
+        }
+    }
+
+    pub fn mount(
+        &self,
+        base_sys: &mut AppBaseSystem,
+        ct_parent: CompositeTreeRef,
+        ht_parent: HitTestTreeRef,
+    ) {
+        base_sys.set_tree_parent((self.ct_root, self.ht_root), (ct_parent, ht_parent));
+    }
+
+    pub fn bind_action_handler<'subsystem>(
+        &self,
+        base_sys: &mut AppBaseSystem<'subsystem>,
+        handler: &Rc<impl HitTestTreeActionHandler + 'subsystem>,
+    ) {
+        base_sys
+            .hit_tree
+            .set_action_handler(self.ht_decrement, handler);
+        base_sys
+            .hit_tree
+            .set_action_handler(self.ht_increment, handler);
+        self.input_view.bind_action_handler(base_sys, handler);
+    }
+
+    pub fn set_position(&self, base_sys: &mut AppBaseSystem, x: f32, y: f32) {
+        self.ct_root
+            .entity_mut_dirtified(&mut base_sys.composite_tree)
+            .offset = [AnimatableFloat::Value(x), AnimatableFloat::Value(y)];
+        base_sys.hit_tree.get_data_mut(self.ht_root).left = x;
+        base_sys.hit_tree.get_data_mut(self.ht_root).top = y;
+    }
+
+    pub fn rescale(&self, base_sys: &mut AppBaseSystem, ui_scale_factor: f32) {
+        self.ct_root
+            .entity_mut_dirtified(&mut base_sys.composite_tree)
+            .base_scale_factor = ui_scale_factor;
+        for (&ct, label) in self.ct_button_labels.iter().zip(Self::BUTTON_LABELS) {
+            let atlas_rect = replace_text_label(base_sys, ct, label, ui_scale_factor);
+            ct.entity_mut_dirtified(&mut base_sys.composite_tree).offset[0] =
+                AnimatableFloat::Value(
+                    0.5 * (Self::BUTTON_WIDTH - atlas_rect.width() as f32 / ui_scale_factor),
+                );
+        }
+        self.input_view.rescale(base_sys, ui_scale_factor);
+    }
+
+    pub fn update(&self, base_sys: &mut AppBaseSystem, current_sec: f32) {
+        self.input_view.update(base_sys, current_sec);
+    }
+
+    /// 入力中の値を範囲内に収めたもの
+    pub fn value(&self) -> u32 {
+        Self::parse_value(&self.input_view.text(), self.range)
+    }
+
+    pub fn set_value(&self, value: u32) {
+        let (min, max) = self.range;
+
+        self.input_view.set_text(&value.clamp(min, max).to_string());
+    }
+
+    /// ユーザー操作で値が変わっていたらtrue
+    pub fn take_changed(&self) -> bool {
+        self.changed.replace(false)
+    }
+
+    pub fn blur(&self) {
+        self.input_view.blur();
+    }
+
+    /// 空や数値でなければ最小値
+    fn parse_value(text: &str, (min, max): (u32, u32)) -> u32 {
+        text.parse::<u32>().map_or(min, |x| x.clamp(min, max))
+    }
+
+    fn stepped_value(value: u32, step: u32, forward: bool, (min, max): (u32, u32)) -> u32 {
+        let v = if forward {
+            value.saturating_add(step)
+        } else {
+            value.saturating_sub(step)
+        };
+
+        v.clamp(min, max)
+    }
+
+    fn step_value(&self, forward: bool) {
+        self.set_value(Self::stepped_value(
+            self.value(),
+            self.step,
+            forward,
+            self.range,
+        ));
+        self.changed.set(true);
+    }
+
+    /// 入力し終わったら範囲内に収める
+    fn normalize(&self) {
+        let v = self.value();
+        if self.input_view.text() != v.to_string() {
+            self.set_value(v);
+        }
+        self.changed.set(true);
+    }
+
+    pub fn try_handle_cursor_shape(&self, sender: HitTestTreeRef) -> Option<CursorShape> {
+        if sender == self.ht_decrement || sender == self.ht_increment {
+            return Some(CursorShape::Pointer);
+        }
+
+        self.input_view.try_handle_cursor_shape(sender)
+    }
+
+    pub fn try_handle_keyboard_focus(&self, sender: HitTestTreeRef) -> Option<FocusTargetToken> {
+        self.input_view.try_handle_keyboard_focus(sender)
+    }
+
+    pub fn try_handle_text_input_target(&self, sender: HitTestTreeRef) -> Option<TextInputTarget> {
+        self.input_view.try_handle_text_input_target(sender)
+    }
+
+    pub fn try_handle_focus(&self, sender: HitTestTreeRef) -> bool {
+        self.input_view.try_handle_focus(sender)
+    }
+
+    pub fn try_handle_blur(&self, sender: HitTestTreeRef) -> bool {
+        if !self.input_view.try_handle_blur(sender) {
+            return false;
+        }
+
+        let _ = self.input_view.take_edit_end();
+        self.normalize();
+        true
+    }
+
+    pub fn try_handle_pointer_down(
+        &self,
+        sender: HitTestTreeRef,
+        args: &PointerActionArgs,
+    ) -> Option<EventContinueControl> {
+        self.input_view.try_handle_pointer_down(sender, args)
+    }
+
+    pub fn try_handle_pointer_move(
+        &self,
+        sender: HitTestTreeRef,
+        args: &PointerActionArgs,
+    ) -> Option<EventContinueControl> {
+        self.input_view.try_handle_pointer_move(sender, args)
+    }
+
+    pub fn try_handle_pointer_up(
+        &self,
+        sender: HitTestTreeRef,
+        args: &PointerActionArgs,
+    ) -> Option<EventContinueControl> {
+        self.input_view.try_handle_pointer_up(sender, args)
+    }
+
+    pub fn try_handle_on_click(&self, sender: HitTestTreeRef) -> Option<EventContinueControl> {
+        if sender == self.ht_decrement {
+            self.step_value(false);
+            return Some(EventContinueControl::STOP_PROPAGATION);
+        }
+        if sender == self.ht_increment {
+            self.step_value(true);
+            return Some(EventContinueControl::STOP_PROPAGATION);
+        }
+
+        None
+    }
+
+    pub fn try_handle_key_down(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut AppUpdateContext,
+        args: &KeyActionArgs,
+    ) -> Option<EventContinueControl> {
+        if !self.input_view.is_sender(sender) {
+            return None;
+        }
+
+        match args.key {
+            KeyCode::Up => {
+                self.step_value(true);
+                Some(EventContinueControl::STOP_PROPAGATION)
+            }
+            KeyCode::Down => {
+                self.step_value(false);
+                Some(EventContinueControl::STOP_PROPAGATION)
+            }
+            _ => {
+                let r = self.input_view.try_handle_key_down(sender, context, args);
+                if self.input_view.take_edit_end().is_some() {
+                    self.normalize();
+                }
+
+                r
+            }
+        }
+    }
+
+    pub fn try_handle_text_input(
+        &self,
+        sender: HitTestTreeRef,
+        event: &TextInputEvent,
+    ) -> Option<EventContinueControl> {
+        self.input_view.try_handle_text_input(sender, event)
+    }
+}
+impl LayoutElement for SpinBoxView {
+    fn layout_roots(&self) -> (CompositeTreeRef, Option<HitTestTreeRef>) {
+        (self.ct_root, Some(self.ht_root))
+    }
+
+    fn preferred_size(&self) -> (f32, f32) {
+        (self.width, Self::HEIGHT)
+    }
+}
+
+/// 縦に並んだ排他選択肢
+pub struct RadioGroupView {
+    ct_root: CompositeTreeRef,
+    ct_marks: Vec<CompositeTreeRef>,
+    ct_dots: Vec<CompositeTreeRef>,
+    ct_labels: Vec<CompositeTreeRef>,
+    ht_root: HitTestTreeRef,
+    ht_options: Vec<HitTestTreeRef>,
+    options: Vec<String>,
+    preferred_width: f32,
+    selected: Cell<usize>,
+    selected_rendered: Cell<Option<usize>>,
+}
+impl RadioGroupView {
+    const ROW_HEIGHT: f32 = 20.0;
+    const MARK_SIZE: f32 = 14.0;
+    const DOT_SIZE: f32 = 6.0;
+    const LABEL_GAP: f32 = 4.0;
+
+    const fn mark_radius() -> SafeF32 {
+        unsafe { SafeF32::new_unchecked(Self::MARK_SIZE * 0.5) }
+    }
+
+    const fn dot_radius() -> SafeF32 {
+        unsafe { SafeF32::new_unchecked(Self::DOT_SIZE * 0.5) }
+    }
+
+    #[tracing::instrument(name = "RadioGroupView::new", skip(init))]
+    pub fn new(init: &mut ViewInitContext, options: &[&str], init_selected: usize) -> Self {
+        let render_scale = unsafe { SafeF32::new_unchecked(init.ui_scale_factor.ceil()) };
+        let mark_atlas_rect = init
+            .base_system
+            .rounded_rect_mask(render_scale, Self::mark_radius(), unsafe {
+                SafeF32::new_unchecked(1.0)
+            })
+            .unwrap();
+        let dot_atlas_rect = init
+            .base_system
+            .rounded_fill_rect_mask(render_scale, Self::dot_radius())
+            .unwrap();
+
+        let ct_root = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            ..Default::default()
+        });
+        let ht_root = init.base_system.create_hit_tree(HitTestTreeData::default());
+
+        let mut ct_marks = Vec::with_capacity(options.len());
+        let mut ct_dots = Vec::with_capacity(options.len());
+        let mut ct_labels = Vec::with_capacity(options.len());
+        let mut ht_options = Vec::with_capacity(options.len());
+        let mut preferred_width: f32 = 0.0;
+        for (n, &option) in options.iter().enumerate() {
+            let top = n as f32 * Self::ROW_HEIGHT;
+            let label_atlas_rect = init.base_system.text_mask(FontType::UI, option).unwrap();
+            let row_width = Self::MARK_SIZE
+                + Self::LABEL_GAP
+                + label_atlas_rect.width() as f32 / init.ui_scale_factor;
+            preferred_width = preferred_width.max(row_width);
+
+            let ct_row = init.base_system.register_composite_rect(CompositeRect {
+                base_scale_factor: init.ui_scale_factor,
+                offset: [AnimatableFloat::Value(0.0), AnimatableFloat::Value(top)],
+                size: [
+                    AnimatableFloat::Value(row_width),
+                    AnimatableFloat::Value(Self::ROW_HEIGHT),
+                ],
+                ..Default::default()
+            });
+            let ct_mark = init.base_system.register_composite_rect(CompositeRect {
+                base_scale_factor: init.ui_scale_factor,
+                offset: [
+                    AnimatableFloat::Value(0.0),
+                    AnimatableFloat::Value(-Self::MARK_SIZE * 0.5),
+                ],
+                size: [
+                    AnimatableFloat::Value(Self::MARK_SIZE),
+                    AnimatableFloat::Value(Self::MARK_SIZE),
+                ],
+                relative_offset_adjustment: [0.0, 0.5],
+                has_bitmap: true,
+                texatlas_rect: mark_atlas_rect,
+                slice_borders: [Self::mark_radius().value() * render_scale.value(); 4],
+                composite_mode: CompositeMode::ColorTint(
+                    init.base_system.theme.color(ThemeColor::Text),
+                ),
+                ..Default::default()
+            });
+            let ct_dot = init.base_system.register_composite_rect(CompositeRect {
+                base_scale_factor: init.ui_scale_factor,
+                offset: [
+                    AnimatableFloat::Value((Self::MARK_SIZE - Self::DOT_SIZE) * 0.5),
+                    AnimatableFloat::Value((Self::MARK_SIZE - Self::DOT_SIZE) * 0.5),
+                ],
+                size: [
+                    AnimatableFloat::Value(Self::DOT_SIZE),
+                    AnimatableFloat::Value(Self::DOT_SIZE),
+                ],
+                has_bitmap: true,
+                texatlas_rect: dot_atlas_rect,
+                slice_borders: [Self::dot_radius().value() * render_scale.value(); 4],
+                composite_mode: CompositeMode::ColorTint(
+                    init.base_system.theme.color(ThemeColor::Foreground),
+                ),
+                opacity: AnimatableFloat::Value(0.0),
+                ..Default::default()
+            });
+            let ct_label = init.base_system.register_composite_rect(text_label_rect(
+                label_atlas_rect,
+                init.ui_scale_factor,
+                Self::MARK_SIZE + Self::LABEL_GAP,
+                init.base_system.theme.color(ThemeColor::Text),
+            ));
+            init.base_system.set_composite_tree_parent(ct_dot, ct_mark);
+            init.base_system.set_composite_tree_parent(ct_mark, ct_row);
+            init.base_system.set_composite_tree_parent(ct_label, ct_row);
+            init.base_system.set_composite_tree_parent(ct_row, ct_root);
+
+            let ht_option = init.base_system.create_hit_tree(HitTestTreeData {
+                top,
+                width: row_width,
+                height: Self::ROW_HEIGHT,
+                ..Default::default()
+            });
+            init.base_system.set_hit_tree_parent(ht_option, ht_root);
+
+            ct_marks.push(ct_mark);
+            ct_dots.push(ct_dot);
+            ct_labels.push(ct_label);
+            ht_options.push(ht_option);
+        }
+
+        let preferred_height = options.len() as f32 * Self::ROW_HEIGHT;
+        let cr = ct_root.entity_mut_dirtified(&mut init.base_system.composite_tree);
+        cr.size = [
+            AnimatableFloat::Value(preferred_width),
+            AnimatableFloat::Value(preferred_height),
+        ];
+        let hr = init.base_system.hit_tree.get_data_mut(ht_root);
+        hr.width = preferred_width;
+        hr.height = preferred_height;
+
+        Self {
+            ct_root,
+            ct_marks,
+            ct_dots,
+            ct_labels,
+            ht_root,
+            ht_options,
+            options: options.iter().map(|&x| x.into()).collect(),
+            preferred_width,
+            selected: Cell::new(init_selected.min(options.len().saturating_sub(1))),
+            selected_rendered: Cell::new(None),
+        }
+    }
+
+    pub fn preferred_height(&self) -> f32 {
+        self.options.len() as f32 * Self::ROW_HEIGHT
+    }
+
+    pub fn mount(
+        &self,
+        base_sys: &mut AppBaseSystem,
+        ct_parent: CompositeTreeRef,
+        ht_parent: HitTestTreeRef,
+    ) {
+        base_sys.set_tree_parent((self.ct_root, self.ht_root), (ct_parent, ht_parent));
+    }
+
+    pub fn bind_action_handler<'subsystem>(
+        &self,
+        base_sys: &mut AppBaseSystem<'subsystem>,
+        handler: &Rc<impl HitTestTreeActionHandler + 'subsystem>,
+    ) {
+        for &x in self.ht_options.iter() {
+            base_sys.hit_tree.set_action_handler(x, handler);
+        }
+    }
+
+    pub fn rescale(&self, base_sys: &mut AppBaseSystem, ui_scale_factor: f32) {
+        let render_scale = unsafe { SafeF32::new_unchecked(ui_scale_factor.ceil()) };
+        let mark_atlas_rect = base_sys
+            .rounded_rect_mask(render_scale, Self::mark_radius(), unsafe {
+                SafeF32::new_unchecked(1.0)
+            })
+            .unwrap();
+        let dot_atlas_rect = base_sys
+            .rounded_fill_rect_mask(render_scale, Self::dot_radius())
+            .unwrap();
+
+        for &ct in self.ct_marks.iter() {
+            let cr = ct.entity_mut_dirtified(&mut base_sys.composite_tree);
+            cr.base_scale_factor = ui_scale_factor;
+            cr.texatlas_rect = mark_atlas_rect;
+            cr.slice_borders = [Self::mark_radius().value() * render_scale.value(); 4];
+        }
+        for &ct in self.ct_dots.iter() {
+            let cr = ct.entity_mut_dirtified(&mut base_sys.composite_tree);
+            cr.base_scale_factor = ui_scale_factor;
+            cr.texatlas_rect = dot_atlas_rect;
+            cr.slice_borders = [Self::dot_radius().value() * render_scale.value(); 4];
+        }
+        for (&ct, option) in self.ct_labels.iter().zip(self.options.iter()) {
+            replace_text_label(base_sys, ct, option, ui_scale_factor);
+        }
+    }
+
+    pub fn update(&self, base_sys: &mut AppBaseSystem, current_sec: f32) {
+        let selected = self.selected.get();
+        let Some(prev) = self.selected_rendered.replace(Some(selected)) else {
+            // 初回はアニメーションなし
+            if let Some(&ct) = self.ct_dots.get(selected) {
+                ct.entity_mut_dirtified(&mut base_sys.composite_tree)
+                    .opacity = AnimatableFloat::Value(1.0);
+            }
+            return;
+        };
+        if prev == selected {
+            return;
+        }
+
+        for (n, to_value) in [(prev, 0.0), (selected, 1.0)] {
+            self.ct_dots[n]
+                .entity_mut_dirtified(&mut base_sys.composite_tree)
+                .opacity = AnimatableFloat::Animated {
+                from_value: 1.0 - to_value,
+                to_value,
+                start_sec: current_sec,
+                end_sec: current_sec + 0.1,
+                curve: AnimationCurve::Linear,
+                event_on_complete: None,
+            };
+        }
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected.get()
+    }
+
+    pub fn try_handle_cursor_shape(&self, sender: HitTestTreeRef) -> Option<CursorShape> {
+        if self.ht_options.contains(&sender) {
+            return Some(CursorShape::Pointer);
+        }
+
+        None
+    }
+
+    /// 選ばれたらselected()が変わる
+    pub fn try_handle_on_click(&self, sender: HitTestTreeRef) -> Option<EventContinueControl> {
+        let n = self.ht_options.iter().position(|&x| x == sender)?;
+        self.selected.set(n);
+
+        Some(EventContinueControl::STOP_PROPAGATION)
+    }
+}
+impl LayoutElement for RadioGroupView {
+    fn layout_roots(&self) -> (CompositeTreeRef, Option<HitTestTreeRef>) {
+        (self.ct_root, Some(self.ht_root))
+    }
+
+    fn preferred_size(&self) -> (f32, f32) {
+        (self.preferred_width, self.preferred_height())
+    }
+}
+
+/// ホバーしてしばらくすると出る説明
+///
+/// ヒットテストは持たないので、対象要素のpointer enter/move/leaveを持ち主から流してもらう
+pub struct TooltipView {
+    ct_root: CompositeTreeRef,
+    ct_label: CompositeTreeRef,
+    text: String,
+    hover: Cell<Option<PointerActionArgs>>,
+    hover_changed: Cell<bool>,
+    /// 表示され始める時刻
+    visible_from: Cell<Option<f32>>,
+}
+impl TooltipView {
+    const PADDING_H: f32 = 8.0;
+    const PADDING_V: f32 = 4.0;
+    const CORNER_RADIUS: SafeF32 = unsafe { SafeF32::new_unchecked(4.0) };
+    const DELAY: f32 = 0.5;
+    const POINTER_OFFSET: (f32, f32) = (12.0, 20.0);
+
+    #[tracing::instrument(name = "TooltipView::new", skip(init))]
+    pub fn new(init: &mut ViewInitContext, text: &str) -> Self {
+        let render_scale = unsafe { SafeF32::new_unchecked(init.ui_scale_factor.ceil()) };
+        let bg_atlas_rect = init
+            .base_system
+            .rounded_fill_rect_mask(render_scale, Self::CORNER_RADIUS)
+            .unwrap();
+        let label_atlas_rect = init.base_system.text_mask(FontType::UI, text).unwrap();
+
+        let ct_root = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(
+                    label_atlas_rect.width() as f32 / init.ui_scale_factor + Self::PADDING_H * 2.0,
+                ),
+                AnimatableFloat::Value(
+                    label_atlas_rect.height() as f32 / init.ui_scale_factor + Self::PADDING_V * 2.0,
+                ),
+            ],
+            has_bitmap: true,
+            texatlas_rect: bg_atlas_rect,
+            slice_borders: [Self::CORNER_RADIUS.value() * render_scale.value(); 4],
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::TooltipBackground),
+            ),
+            opacity: AnimatableFloat::Value(0.0),
+            ..Default::default()
+        });
+        let ct_label = init.base_system.register_composite_rect(text_label_rect(
+            label_atlas_rect,
+            init.ui_scale_factor,
+            Self::PADDING_H,
+            init.base_system.theme.color(ThemeColor::Text),
+        ));
+        init.base_system
+            .set_composite_tree_parent(ct_label, ct_root);
+
+        Self {
+            ct_root,
+            ct_label,
+            text: text.into(),
+            hover: Cell::new(None),
+            hover_changed: Cell::new(false),
+            visible_from: Cell::new(None),
+        }
+    }
+
+    /// ct_overlayはクライアント全体を覆う親(ポインタの位置にそのまま出すため)
+    pub fn mount(&self, base_sys: &mut AppBaseSystem, ct_overlay: CompositeTreeRef) {
+        base_sys.set_composite_tree_parent(self.ct_root, ct_overlay);
+    }
+
+    pub fn rescale(&self, base_sys: &mut AppBaseSystem, ui_scale_factor: f32) {
+        let render_scale = unsafe { SafeF32::new_unchecked(ui_scale_factor.ceil()) };
+        let bg_atlas_rect = base_sys
+            .rounded_fill_rect_mask(render_scale, Self::CORNER_RADIUS)
+            .unwrap();
+        let label_atlas_rect =
+            replace_text_label(base_sys, self.ct_label, &self.text, ui_scale_factor);
+
+        let cr = self
+            .ct_root
+            .entity_mut_dirtified(&mut base_sys.composite_tree);
+        cr.base_scale_factor = ui_scale_factor;
+        cr.texatlas_rect = bg_atlas_rect;
+        cr.slice_borders = [Self::CORNER_RADIUS.value() * render_scale.value(); 4];
+        cr.size = [
+            AnimatableFloat::Value(
+                label_atlas_rect.width() as f32 / ui_scale_factor + Self::PADDING_H * 2.0,
+            ),
+            AnimatableFloat::Value(
+                label_atlas_rect.height() as f32 / ui_scale_factor + Self::PADDING_V * 2.0,
+            ),
+        ];
+    }
+
+    pub fn on_pointer_enter(&self, args: &PointerActionArgs) {
+        self.hover.set(Some(*args));
+        self.hover_changed.set(true);
+    }
+
+    /// 止まってから出したいので、まだ出ていなければ待ち時間をやり直す
+    pub fn on_pointer_move(&self, args: &PointerActionArgs) {
+        if self.hover.get().is_some() {
+            self.hover.set(Some(*args));
+            self.hover_changed.set(true);
+        }
+    }
+
+    pub fn on_pointer_leave(&self) {
+        self.hover.set(None);
+        self.hover_changed.set(true);
+    }
+
+    pub fn update(&self, base_sys: &mut AppBaseSystem, current_sec: f32) {
+        if !self.hover_changed.replace(false) {
+            return;
+        }
+
+        let Some(args) = self.hover.get() else {
+            self.visible_from.set(None);
+            self.ct_root
+                .entity_mut_dirtified(&mut base_sys.composite_tree)
+                .opacity = AnimatableFloat::Value(0.0);
+            return;
+        };
+        if self.visible_from.get().is_some_and(|t| t <= current_sec) {
+            // もう出ているので動かさない
+            return;
+        }
+
+        let cr = self.ct_root.entity(&base_sys.composite_tree);
+        let (width, height) = (
+            cr.size[0].evaluate(current_sec, base_sys.composite_tree.parameter_store()),
+            cr.size[1].evaluate(current_sec, base_sys.composite_tree.parameter_store()),
+        );
+        let left = (args.client_x + Self::POINTER_OFFSET.0)
+            .min(args.client_width - width)
+            .max(0.0);
+        let mut top = args.client_y + Self::POINTER_OFFSET.1;
+        if top + height > args.client_height {
+            // 下にはみ出るならポインタの上に出す
+            top = args.client_y - Self::POINTER_OFFSET.1 - height;
+        }
+
+        let start_sec = current_sec + Self::DELAY;
+        let cr = self
+            .ct_root
+            .entity_mut_dirtified(&mut base_sys.composite_tree);
+        cr.offset = [AnimatableFloat::Value(left), AnimatableFloat::Value(top)];
+        cr.opacity = AnimatableFloat::Animated {
+            from_value: 0.0,
+            to_value: 1.0,
+            start_sec,
+            end_sec: start_sec + 0.1,
+            curve: AnimationCurve::Linear,
+            event_on_complete: None,
+        };
+        self.visible_from.set(Some(start_sec));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(text: &str, anchor: usize, caret: usize) -> TextInputState {
+        TextInputState {
+            text: text.to_owned(),
+            caret,
+            anchor,
+            preedit: None,
+        }
+    }
+
+    #[test]
+    fn caret_boundaries_on_multibyte_text() {
+        // "aあ😀b": a(1) あ(3) 😀(4) b(1)
+        let st = state("aあ😀b", 0, 0);
+        assert_eq!(st.next_boundary(0), 1);
+        assert_eq!(st.next_boundary(1), 4);
+        assert_eq!(st.next_boundary(4), 8);
+        assert_eq!(st.next_boundary(9), 9);
+        assert_eq!(st.prev_boundary(9), 8);
+        assert_eq!(st.prev_boundary(8), 4);
+        assert_eq!(st.prev_boundary(4), 1);
+        assert_eq!(st.prev_boundary(0), 0);
+    }
+
+    #[test]
+    fn replace_selection_in_either_direction() {
+        let mut st = state("hello world", 6, 11);
+        st.replace_selection("あ");
+        assert_eq!(st.text, "hello あ");
+        assert_eq!((st.anchor, st.caret), (9, 9));
+
+        // キャレットが選択の起点より前にあっても同じ範囲を置き換える
+        let mut st = state("hello world", 5, 0);
+        st.replace_selection("");
+        assert_eq!(st.text, " world");
+        assert_eq!((st.anchor, st.caret), (0, 0));
+    }
+
+    #[test]
+    fn backspace_and_delete_without_selection() {
+        let mut st = state("aあb", 4, 4);
+        st.delete_backward();
+        assert_eq!(st.text, "ab");
+        assert_eq!(st.caret, 1);
+
+        st.delete_forward();
+        assert_eq!(st.text, "a");
+        assert_eq!(st.caret, 1);
+
+        // 端ではなにもしない
+        st.delete_forward();
+        st.move_caret(0, false);
+        st.delete_backward();
+        assert_eq!(st.text, "a");
+        assert_eq!(st.caret, 0);
+    }
+
+    #[test]
+    fn backspace_and_delete_with_selection() {
+        let mut st = state("abcdef", 1, 4);
+        st.delete_backward();
+        assert_eq!(st.text, "aef");
+        assert_eq!((st.anchor, st.caret), (1, 1));
+
+        let mut st = state("abcdef", 5, 2);
+        st.delete_forward();
+        assert_eq!(st.text, "abf");
+        assert_eq!((st.anchor, st.caret), (2, 2));
+    }
+
+    #[test]
+    fn delete_surrounding_widens_to_char_boundaries() {
+        // キャレットは"い"の後ろ 前2byte/後ろ1byteはどちらも文字の途中
+        let mut st = state("あいう", 6, 6);
+        st.delete_surrounding(2, 1);
+        assert_eq!(st.text, "あ");
+        assert_eq!((st.anchor, st.caret), (3, 3));
+
+        // 範囲外は端で止める
+        let mut st = state("ab", 1, 1);
+        st.delete_surrounding(5, 5);
+        assert_eq!(st.text, "");
+        assert_eq!(st.caret, 0);
+    }
+
+    #[test]
+    fn numeric_validation_keeps_only_ascii_digits() {
+        assert_eq!(TextInputValidation::Numeric.filter("1a2 ３-4"), "124");
+        assert_eq!(TextInputValidation::Any.filter("a\tb\nc"), "abc");
+    }
+
+    #[test]
+    fn slider_snaps_to_step_within_range() {
+        assert_eq!(SliderView::snap((0.0, 64.0), 1.0, 3.4), 3.0);
+        assert_eq!(SliderView::snap((0.0, 64.0), 1.0, 3.6), 4.0);
+        // 刻みはminから数える
+        assert_eq!(SliderView::snap((1.0, 10.0), 4.0, 4.0), 5.0);
+        assert_eq!(SliderView::snap((0.0, 64.0), 1.0, -2.0), 0.0);
+        assert_eq!(SliderView::snap((0.0, 64.0), 1.0, 100.0), 64.0);
+        // 刻みで丸めた結果が範囲を超えたら端で止める
+        assert_eq!(SliderView::snap((0.0, 10.0), 4.0, 11.0), 10.0);
+        // stepが0なら連続値
+        assert_eq!(SliderView::snap((0.0, 1.0), 0.0, 0.25), 0.25);
+    }
+
+    #[test]
+    fn spin_box_parses_and_clamps_input() {
+        assert_eq!(SpinBoxView::parse_value("12", (0, 64)), 12);
+        assert_eq!(SpinBoxView::parse_value("100", (0, 64)), 64);
+        assert_eq!(SpinBoxView::parse_value("1", (4, 64)), 4);
+        assert_eq!(SpinBoxView::parse_value("", (4, 64)), 4);
+    }
+
+    #[test]
+    fn spin_box_steps_stay_in_range() {
+        assert_eq!(SpinBoxView::stepped_value(4, 1, true, (0, 64)), 5);
+        assert_eq!(SpinBoxView::stepped_value(4, 1, false, (0, 64)), 3);
+        assert_eq!(SpinBoxView::stepped_value(0, 1, false, (0, 64)), 0);
+        assert_eq!(SpinBoxView::stepped_value(62, 4, true, (0, 64)), 64);
+        assert_eq!(
+            SpinBoxView::stepped_value(u32::MAX, 1, true, (0, u32::MAX)),
+            u32::MAX
+        );
+    }
+}
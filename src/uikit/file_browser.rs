@@ -0,0 +1,1585 @@
+//! ポータルのFileChooserが使えない環境向けの、アプリ内で完結するファイル選択ポップアップ
+//!
+//! 選ばれたパスは`FileBrowserRequest::request`のFutureで受け取る
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Duration,
+};
+
+use crate::{
+    AppEvent, AppEventBus, AppUpdateContext, PresenterInitContext, ViewInitContext,
+    base_system::{AppBaseSystem, FontType, theme::ThemeColor},
+    composite::{AnimatableFloat, CompositeMode, CompositeRect, CompositeTreeRef},
+    helper_types::SafeF32,
+    hittest::{
+        CursorShape, HitTestTreeActionHandler, HitTestTreeData, HitTestTreeRef, PointerActionArgs,
+        ScrollActionArgs,
+    },
+    input::{
+        EventContinueControl, FocusTargetToken, KeyActionArgs, TextInputEvent, TextInputTarget,
+    },
+    source_reader,
+    text::{TextLayoutOptions, TextTruncation},
+    uikit::{
+        common_controls::{
+            CommonButtonView, DropdownView, TextInputEditEnd, TextInputValidation, TextInputView,
+        },
+        layout::{CrossAlign, LayoutElement, LayoutRect, Padding, StackLayout},
+        popup::{self, PopupPresenter, PopupPresenterSpawnable},
+        scroll::ScrollContainerView,
+    },
+};
+
+/// 何を選ばせるか
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileBrowserMode {
+    /// 既存のファイルを1つ選ぶ
+    OpenFile,
+    /// 既存のファイルをいくつでも選ぶ(クリックで選択を切り替える)
+    OpenFiles,
+    /// 保存先のファイル名を入力する
+    SaveFile,
+    /// フォルダを1つ選ぶ
+    SelectFolder,
+}
+impl FileBrowserMode {
+    const fn confirm_label(self) -> &'static str {
+        match self {
+            Self::OpenFile | Self::OpenFiles => "Open",
+            Self::SaveFile => "Save",
+            Self::SelectFolder => "Select",
+        }
+    }
+}
+
+/// 拡張子での絞り込み
+#[derive(Debug, Clone)]
+pub struct FileFilter {
+    label: String,
+    /// ドットなし(空ならすべてのファイル)
+    extensions: Vec<String>,
+}
+impl FileFilter {
+    pub fn new(label: impl Into<String>, extensions: &[&str]) -> Self {
+        Self {
+            label: label.into(),
+            extensions: extensions.iter().map(|&x| x.into()).collect(),
+        }
+    }
+
+    pub fn all_files() -> Self {
+        Self::new("All files", &[])
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        if self.extensions.is_empty() {
+            return true;
+        }
+
+        path.extension()
+            .and_then(|x| x.to_str())
+            .is_some_and(|x| self.extensions.iter().any(|e| e.eq_ignore_ascii_case(x)))
+    }
+}
+
+/// ファイル選択の内容と結果の返し先
+#[derive(Debug)]
+pub struct FileBrowserRequest {
+    title: String,
+    mode: FileBrowserMode,
+    filters: Vec<FileFilter>,
+    initial_directory: Option<PathBuf>,
+    reply: Option<smol::channel::Sender<Vec<PathBuf>>>,
+}
+impl FileBrowserRequest {
+    pub fn new(mode: FileBrowserMode, title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            mode,
+            filters: Vec::new(),
+            initial_directory: None,
+            reply: None,
+        }
+    }
+
+    /// 先頭のものが最初に選ばれている(空なら絞り込まない)
+    pub fn with_filters(mut self, filters: impl IntoIterator<Item = FileFilter>) -> Self {
+        self.filters = filters.into_iter().collect();
+        self
+    }
+
+    /// 指定しなければホームディレクトリから始める
+    pub fn with_initial_directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.initial_directory = Some(directory.into());
+        self
+    }
+
+    /// 表示を依頼して、選ばれたパスを待つ
+    ///
+    /// キャンセルされたときはNone
+    pub async fn request(mut self, events: &AppEventBus) -> Option<Vec<PathBuf>> {
+        let (sender, receiver) = smol::channel::bounded(1);
+        self.reply = Some(sender);
+        events.push(AppEvent::UIFileBrowserRequest(self));
+
+        receiver.recv().await.ok()
+    }
+}
+
+fn default_directory() -> PathBuf {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_else(|| PathBuf::from("/"))
+}
+
+struct DirectoryEntry {
+    name: String,
+    path: PathBuf,
+    is_dir: bool,
+}
+impl DirectoryEntry {
+    fn display_name(&self) -> String {
+        if self.is_dir {
+            format!("{}/", self.name)
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+/// フォルダ→ファイルの順に名前で並べて読む(隠しファイルは除く)
+fn read_directory(
+    directory: &Path,
+    filter: Option<&FileFilter>,
+    include_files: bool,
+) -> std::io::Result<Vec<DirectoryEntry>> {
+    let mut entries = Vec::new();
+    for e in std::fs::read_dir(directory)? {
+        let e = match e {
+            Ok(x) => x,
+            Err(e) => {
+                tracing::warn!(?directory, reason = ?e, "reading directory entry failed");
+                continue;
+            }
+        };
+        let name = e.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+
+        let path = e.path();
+        // Note: シンボリックリンクは辿った先で判断する
+        let is_dir = path.is_dir();
+        if !is_dir && !(include_files && filter.is_none_or(|f| f.matches(&path))) {
+            continue;
+        }
+
+        entries.push(DirectoryEntry { name, path, is_dir });
+    }
+    entries.sort_by(|a, b| {
+        b.is_dir
+            .cmp(&a.is_dir)
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+
+    Ok(entries)
+}
+
+/// PNGなら画像の大きさを読む
+fn png_dimensions(path: &Path) -> Option<(u32, u32)> {
+    let mut fs = std::fs::File::open(path).ok()?;
+    let meta = source_reader::png::Metadata::try_read(&mut fs)?;
+
+    Some((meta.width, meta.height))
+}
+
+/// 1行のテキスト(幅を超えるぶんは省略する)
+///
+/// 後からテキストが変わっても並びが崩れないよう、最大幅の枠の中で縦方向中央に置く
+struct LabelView {
+    ct_root: CompositeTreeRef,
+    ct_text: CompositeTreeRef,
+    font: FontType,
+    max_width: f32,
+    height: f32,
+    truncation: TextTruncation,
+    ui_scale_factor: f32,
+    text: RefCell<String>,
+}
+impl LabelView {
+    const MIN_HEIGHT: f32 = 20.0;
+
+    fn layout_options(
+        max_width: f32,
+        truncation: TextTruncation,
+        ui_scale_factor: f32,
+    ) -> TextLayoutOptions {
+        TextLayoutOptions::truncated(
+            unsafe { SafeF32::new_unchecked((max_width * ui_scale_factor).max(1.0)) },
+            truncation,
+        )
+    }
+
+    #[tracing::instrument(name = "FileBrowser::LabelView::new", skip(init))]
+    fn new(
+        init: &mut ViewInitContext,
+        font: FontType,
+        text: &str,
+        max_width: f32,
+        truncation: TextTruncation,
+        color: ThemeColor,
+    ) -> Self {
+        let ct_text = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            relative_offset_adjustment: [0.0, 0.5],
+            has_bitmap: true,
+            composite_mode: CompositeMode::ColorTint(init.base_system.theme.color(color)),
+            opacity: AnimatableFloat::Value(0.0),
+            ..Default::default()
+        });
+        let ct_root = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            ..Default::default()
+        });
+        init.base_system.set_composite_tree_parent(ct_text, ct_root);
+
+        let this = Self {
+            ct_root,
+            ct_text,
+            font,
+            max_width,
+            height: Self::MIN_HEIGHT,
+            truncation,
+            ui_scale_factor: init.ui_scale_factor,
+            text: RefCell::new(String::new()),
+        };
+        let text_height = this.set_text(init.base_system, text);
+
+        Self {
+            height: text_height.max(Self::MIN_HEIGHT),
+            ..this
+        }
+    }
+
+    fn mount(&self, base_sys: &mut AppBaseSystem, ct_parent: CompositeTreeRef) {
+        base_sys.set_composite_tree_parent(self.ct_root, ct_parent);
+    }
+
+    /// 空文字列なら隠す
+    ///
+    /// 表示したテキストの高さを返す
+    fn set_text(&self, base_sys: &mut AppBaseSystem, text: &str) -> f32 {
+        if text == self.text.borrow().as_str() {
+            // no changes
+            return self
+                .ct_text
+                .entity(&base_sys.composite_tree)
+                .texatlas_rect
+                .height() as f32
+                / self.ui_scale_factor;
+        }
+
+        if !self.text.borrow().is_empty() {
+            base_sys
+                .free_mask_atlas_rect(self.ct_text.entity(&base_sys.composite_tree).texatlas_rect);
+        }
+        *self.text.borrow_mut() = text.into();
+        if text.is_empty() {
+            self.ct_text
+                .entity_mut_dirtified(&mut base_sys.composite_tree)
+                .opacity = AnimatableFloat::Value(0.0);
+            return 0.0;
+        }
+
+        let atlas_rect = base_sys
+            .text_mask_with_options(
+                self.font,
+                text,
+                &Self::layout_options(self.max_width, self.truncation, self.ui_scale_factor),
+            )
+            .unwrap();
+        let (width, height) = (
+            atlas_rect.width() as f32 / self.ui_scale_factor,
+            atlas_rect.height() as f32 / self.ui_scale_factor,
+        );
+
+        let cr = self
+            .ct_text
+            .entity_mut_dirtified(&mut base_sys.composite_tree);
+        cr.texatlas_rect = atlas_rect;
+        cr.size = [
+            AnimatableFloat::Value(width),
+            AnimatableFloat::Value(height),
+        ];
+        cr.offset = [
+            AnimatableFloat::Value(0.0),
+            AnimatableFloat::Value(-0.5 * height),
+        ];
+        cr.opacity = AnimatableFloat::Value(1.0);
+
+        height
+    }
+}
+impl LayoutElement for LabelView {
+    fn layout_roots(&self) -> (CompositeTreeRef, Option<HitTestTreeRef>) {
+        (self.ct_root, None)
+    }
+
+    fn preferred_size(&self) -> (f32, f32) {
+        (self.max_width, self.height)
+    }
+}
+
+/// 一覧の1行
+struct RowView {
+    ct_root: CompositeTreeRef,
+    ct_bg: CompositeTreeRef,
+    ct_bg_selected: CompositeTreeRef,
+    ct_label: CompositeTreeRef,
+    ht_root: HitTestTreeRef,
+    label: RefCell<String>,
+    label_max_width: f32,
+    ui_scale_factor: f32,
+    /// 表示しているエントリ(Noneなら使われておらずツリーから外れている)
+    bound_index: Cell<Option<usize>>,
+}
+impl RowView {
+    const HEIGHT: f32 = 22.0;
+    const CORNER_RADIUS: SafeF32 = unsafe { SafeF32::new_unchecked(4.0) };
+    const LABEL_LEFT: f32 = 8.0;
+
+    fn label_layout_options(max_width: f32, ui_scale_factor: f32) -> TextLayoutOptions {
+        TextLayoutOptions::truncated(
+            unsafe { SafeF32::new_unchecked((max_width * ui_scale_factor).max(1.0)) },
+            TextTruncation::Middle,
+        )
+    }
+
+    #[tracing::instrument(name = "FileBrowser::RowView::new", skip(init))]
+    fn new(init: &mut ViewInitContext, label: &str, label_max_width: f32) -> Self {
+        let label_atlas_rect = init
+            .base_system
+            .text_mask_with_options(
+                FontType::UI,
+                label,
+                &Self::label_layout_options(label_max_width, init.ui_scale_factor),
+            )
+            .unwrap();
+        let bg_atlas_rect = init
+            .base_system
+            .rounded_fill_rect_mask(
+                unsafe { SafeF32::new_unchecked(init.ui_scale_factor) },
+                Self::CORNER_RADIUS,
+            )
+            .unwrap();
+
+        let ct_root = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            relative_size_adjustment: [1.0, 0.0],
+            size: [
+                AnimatableFloat::Value(0.0),
+                AnimatableFloat::Value(Self::HEIGHT),
+            ],
+            ..Default::default()
+        });
+        let ct_bg = init.base_system.register_composite_rect(CompositeRect {
+            relative_size_adjustment: [1.0, 1.0],
+            has_bitmap: true,
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system
+                    .theme
+                    .color_with_alpha(ThemeColor::Foreground, 0.125),
+            ),
+            texatlas_rect: bg_atlas_rect,
+            slice_borders: [Self::CORNER_RADIUS.value() * init.ui_scale_factor; 4],
+            opacity: AnimatableFloat::Value(0.0),
+            ..Default::default()
+        });
+        let ct_bg_selected = init.base_system.register_composite_rect(CompositeRect {
+            relative_size_adjustment: [1.0, 1.0],
+            has_bitmap: true,
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system
+                    .theme
+                    .color_with_alpha(ThemeColor::Accent, 0.25),
+            ),
+            texatlas_rect: bg_atlas_rect,
+            slice_borders: [Self::CORNER_RADIUS.value() * init.ui_scale_factor; 4],
+            opacity: AnimatableFloat::Value(0.0),
+            ..Default::default()
+        });
+        let ct_label = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            offset: [
+                AnimatableFloat::Value(Self::LABEL_LEFT),
+                AnimatableFloat::Value(
+                    -0.5 * label_atlas_rect.height() as f32 / init.ui_scale_factor,
+                ),
+            ],
+            relative_offset_adjustment: [0.0, 0.5],
+            size: [
+                AnimatableFloat::Value(label_atlas_rect.width() as f32 / init.ui_scale_factor),
+                AnimatableFloat::Value(label_atlas_rect.height() as f32 / init.ui_scale_factor),
+            ],
+            has_bitmap: true,
+            texatlas_rect: label_atlas_rect,
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::Text),
+            ),
+            ..Default::default()
+        });
+
+        init.base_system
+            .set_composite_tree_parent(ct_bg_selected, ct_root);
+        init.base_system.set_composite_tree_parent(ct_bg, ct_root);
+        init.base_system
+            .set_composite_tree_parent(ct_label, ct_root);
+
+        let ht_root = init.base_system.create_hit_tree(HitTestTreeData {
+            width_adjustment_factor: 1.0,
+            height: Self::HEIGHT,
+            ..Default::default()
+        });
+
+        Self {
+            ct_root,
+            ct_bg,
+            ct_bg_selected,
+            ct_label,
+            ht_root,
+            label: RefCell::new(label.into()),
+            label_max_width,
+            ui_scale_factor: init.ui_scale_factor,
+            bound_index: Cell::new(None),
+        }
+    }
+
+    fn bind(
+        &self,
+        base_sys: &mut AppBaseSystem,
+        parents: (CompositeTreeRef, HitTestTreeRef),
+        index: usize,
+        label: &str,
+    ) {
+        base_sys.set_tree_parent((self.ct_root, self.ht_root), parents);
+        self.bound_index.set(Some(index));
+
+        let top = index as f32 * Self::HEIGHT;
+        self.ct_root
+            .entity_mut_dirtified(&mut base_sys.composite_tree)
+            .offset[1] = AnimatableFloat::Value(top);
+        base_sys.hit_tree.get_data_mut(self.ht_root).top = top;
+
+        if label == self.label.borrow().as_str() {
+            // no changes
+            return;
+        }
+
+        base_sys.free_mask_atlas_rect(self.ct_label.entity(&base_sys.composite_tree).texatlas_rect);
+        let label_atlas_rect = base_sys
+            .text_mask_with_options(
+                FontType::UI,
+                label,
+                &Self::label_layout_options(self.label_max_width, self.ui_scale_factor),
+            )
+            .unwrap();
+        let cr = self
+            .ct_label
+            .entity_mut_dirtified(&mut base_sys.composite_tree);
+        cr.texatlas_rect = label_atlas_rect;
+        cr.size = [
+            AnimatableFloat::Value(label_atlas_rect.width() as f32 / self.ui_scale_factor),
+            AnimatableFloat::Value(label_atlas_rect.height() as f32 / self.ui_scale_factor),
+        ];
+        cr.offset[1] =
+            AnimatableFloat::Value(-0.5 * label_atlas_rect.height() as f32 / self.ui_scale_factor);
+        *self.label.borrow_mut() = label.into();
+    }
+
+    fn unbind(&self, base_sys: &mut AppBaseSystem) {
+        base_sys.composite_tree.remove_child(self.ct_root);
+        base_sys.hit_tree.remove_child(self.ht_root);
+        self.bound_index.set(None);
+    }
+
+    fn set_state(&self, base_sys: &mut AppBaseSystem, selected: bool, hovered: bool) {
+        self.ct_bg_selected
+            .entity_mut_dirtified(&mut base_sys.composite_tree)
+            .opacity = AnimatableFloat::Value(if selected { 1.0 } else { 0.0 });
+        self.ct_bg
+            .entity_mut_dirtified(&mut base_sys.composite_tree)
+            .opacity = AnimatableFloat::Value(if hovered { 1.0 } else { 0.0 });
+    }
+}
+
+/// 一覧の背景(中にスクロール領域を置く)
+struct ListBoxView {
+    ct_root: CompositeTreeRef,
+    ht_root: HitTestTreeRef,
+    preferred_width: f32,
+    preferred_height: f32,
+}
+impl ListBoxView {
+    const CORNER_RADIUS: SafeF32 = unsafe { SafeF32::new_unchecked(6.0) };
+
+    #[tracing::instrument(name = "FileBrowser::ListBoxView::new", skip(init))]
+    fn new(init: &mut ViewInitContext, preferred_width: f32, preferred_height: f32) -> Self {
+        let bg_atlas_rect = init
+            .base_system
+            .rounded_fill_rect_mask(
+                unsafe { SafeF32::new_unchecked(init.ui_scale_factor) },
+                Self::CORNER_RADIUS,
+            )
+            .unwrap();
+
+        let ct_root = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(preferred_width),
+                AnimatableFloat::Value(preferred_height),
+            ],
+            has_bitmap: true,
+            texatlas_rect: bg_atlas_rect,
+            slice_borders: [Self::CORNER_RADIUS.value() * init.ui_scale_factor; 4],
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::Backdrop),
+            ),
+            ..Default::default()
+        });
+        let ht_root = init.base_system.create_hit_tree(HitTestTreeData {
+            width: preferred_width,
+            height: preferred_height,
+            ..Default::default()
+        });
+
+        Self {
+            ct_root,
+            ht_root,
+            preferred_width,
+            preferred_height,
+        }
+    }
+
+    fn mount(&self, base_sys: &mut AppBaseSystem, parents: (CompositeTreeRef, HitTestTreeRef)) {
+        base_sys.set_tree_parent((self.ct_root, self.ht_root), parents);
+    }
+}
+impl LayoutElement for ListBoxView {
+    fn layout_roots(&self) -> (CompositeTreeRef, Option<HitTestTreeRef>) {
+        (self.ct_root, Some(self.ht_root))
+    }
+
+    fn preferred_size(&self) -> (f32, f32) {
+        (self.preferred_width, self.preferred_height)
+    }
+}
+
+/// 保存するファイル名の入力欄
+struct NameFieldView {
+    ct_root: CompositeTreeRef,
+    ht_root: HitTestTreeRef,
+    input_view: TextInputView,
+    preferred_width: f32,
+}
+impl NameFieldView {
+    const HEIGHT: f32 = 20.0;
+    const MARGIN_H_LABEL_FIELD: f32 = 8.0;
+    const FIELD_UNDERLINE_THICKNESS: f32 = 1.0;
+
+    #[tracing::instrument(name = "FileBrowser::NameFieldView::new", skip(init))]
+    fn new(init: &mut ViewInitContext, width: f32) -> Self {
+        let label_atlas_rect = init.base_system.text_mask(FontType::UI, "Name").unwrap();
+        let label_width = label_atlas_rect.width() as f32 / init.ui_scale_factor;
+        let field_left = label_width + Self::MARGIN_H_LABEL_FIELD;
+
+        let ct_root = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(width),
+                AnimatableFloat::Value(Self::HEIGHT),
+            ],
+            ..Default::default()
+        });
+        let ct_label = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(label_width),
+                AnimatableFloat::Value(label_atlas_rect.height() as f32 / init.ui_scale_factor),
+            ],
+            offset: [
+                AnimatableFloat::Value(0.0),
+                AnimatableFloat::Value(
+                    -0.5 * label_atlas_rect.height() as f32 / init.ui_scale_factor,
+                ),
+            ],
+            relative_offset_adjustment: [0.0, 0.5],
+            has_bitmap: true,
+            texatlas_rect: label_atlas_rect,
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::Text),
+            ),
+            ..Default::default()
+        });
+        let ct_field_underline = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(-field_left),
+                AnimatableFloat::Value(Self::FIELD_UNDERLINE_THICKNESS),
+            ],
+            relative_size_adjustment: [1.0, 0.0],
+            offset: [
+                AnimatableFloat::Value(field_left),
+                AnimatableFloat::Value(-Self::FIELD_UNDERLINE_THICKNESS),
+            ],
+            relative_offset_adjustment: [0.0, 1.0],
+            has_bitmap: true,
+            composite_mode: CompositeMode::FillColor(
+                init.base_system.theme.color(ThemeColor::TextSecondary),
+            ),
+            ..Default::default()
+        });
+        init.base_system
+            .set_composite_tree_parent(ct_label, ct_root);
+        init.base_system
+            .set_composite_tree_parent(ct_field_underline, ct_root);
+
+        let ht_root = init.base_system.create_hit_tree(HitTestTreeData {
+            width,
+            height: Self::HEIGHT,
+            ..Default::default()
+        });
+
+        let input_view = TextInputView::new(
+            init,
+            "",
+            width - field_left,
+            Self::HEIGHT,
+            TextInputValidation::Any,
+        );
+        input_view.mount(init.base_system, ct_root, ht_root);
+        input_view.set_position(init.base_system, field_left, 0.0);
+
+        Self {
+            ct_root,
+            ht_root,
+            input_view,
+            preferred_width: width,
+        }
+    }
+
+    fn mount(&self, base_sys: &mut AppBaseSystem, parents: (CompositeTreeRef, HitTestTreeRef)) {
+        base_sys.set_tree_parent((self.ct_root, self.ht_root), parents);
+    }
+
+    fn bind_action_handler<'subsystem>(
+        &self,
+        base_sys: &mut AppBaseSystem<'subsystem>,
+        handler: &Rc<impl HitTestTreeActionHandler + 'subsystem>,
+    ) {
+        base_sys.hit_tree.set_action_handler(self.ht_root, handler);
+        self.input_view.bind_action_handler(base_sys, handler);
+    }
+}
+impl LayoutElement for NameFieldView {
+    fn layout_roots(&self) -> (CompositeTreeRef, Option<HitTestTreeRef>) {
+        (self.ct_root, Some(self.ht_root))
+    }
+
+    fn preferred_size(&self) -> (f32, f32) {
+        (self.preferred_width, Self::HEIGHT)
+    }
+}
+
+/// 決定ボタンなどを押したときにやること
+enum Confirmation {
+    Navigate(PathBuf),
+    Close(Vec<PathBuf>),
+}
+
+struct ActionHandler {
+    mask_view: popup::MaskView,
+    frame_view: popup::CommonFrameView,
+    up_button: CommonButtonView,
+    cancel_button: CommonButtonView,
+    confirm_button: CommonButtonView,
+    filter_dropdown: Option<DropdownView>,
+    name_field: Option<NameFieldView>,
+    scroll_view: ScrollContainerView,
+    row_views: RefCell<Vec<RowView>>,
+    row_label_max_width: f32,
+    ui_scale_factor: f32,
+    mode: FileBrowserMode,
+    filters: Vec<FileFilter>,
+    current_directory: RefCell<PathBuf>,
+    entries: RefCell<Vec<DirectoryEntry>>,
+    /// 一覧を読めなかったときの理由
+    read_error: RefCell<Option<String>>,
+    selected: RefCell<BTreeSet<usize>>,
+    hovered: Cell<Option<usize>>,
+    applied_filter: Cell<usize>,
+    /// (行のインデックス, 時刻)
+    last_row_click: Cell<Option<(usize, f32)>>,
+    entries_dirty: Cell<bool>,
+    row_states_dirty: Cell<bool>,
+    reply: RefCell<Option<smol::channel::Sender<Vec<PathBuf>>>>,
+    popup_id: uuid::Uuid,
+}
+impl ActionHandler {
+    const DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(400);
+
+    /// 結果を返して閉じる(Noneなら返さずに閉じる)
+    fn close(&self, context: &mut AppUpdateContext, result: Option<Vec<PathBuf>>) {
+        if let Some(reply) = self.reply.take()
+            && let Some(result) = result
+        {
+            // Note: 待っている側がもういなくても気にしない
+            let _ = reply.try_send(result);
+        }
+        if let Some(ref f) = self.name_field {
+            f.input_view.blur();
+        }
+
+        context
+            .event_queue
+            .push(AppEvent::UIPopupClose { id: self.popup_id });
+    }
+
+    fn current_filter(&self) -> Option<&FileFilter> {
+        self.filters.get(self.applied_filter.get())
+    }
+
+    fn navigate(&self, directory: PathBuf) {
+        let entries = read_directory(
+            &directory,
+            self.current_filter(),
+            self.mode != FileBrowserMode::SelectFolder,
+        );
+        let entries = match entries {
+            Ok(x) => {
+                *self.read_error.borrow_mut() = None;
+                x
+            }
+            Err(e) => {
+                tracing::warn!(?directory, reason = ?e, "reading directory failed");
+                *self.read_error.borrow_mut() = Some(format!("Cannot read this folder: {e}"));
+                Vec::new()
+            }
+        };
+
+        *self.entries.borrow_mut() = entries;
+        *self.current_directory.borrow_mut() = directory;
+        self.selected.borrow_mut().clear();
+        self.hovered.set(None);
+        self.last_row_click.set(None);
+        self.scroll_view.scroll_into_view(0.0, 0.0);
+        self.entries_dirty.set(true);
+    }
+
+    fn reload(&self) {
+        let directory = self.current_directory.borrow().clone();
+        self.navigate(directory);
+    }
+
+    fn row_entry_index(&self, sender: HitTestTreeRef) -> Option<usize> {
+        self.row_views
+            .borrow()
+            .iter()
+            .find(|r| r.ht_root == sender)
+            .and_then(|r| r.bound_index.get())
+    }
+
+    fn select(&self, index: usize) {
+        let entries = self.entries.borrow();
+        let mut selected = self.selected.borrow_mut();
+        if self.mode == FileBrowserMode::OpenFiles && !entries[index].is_dir {
+            // フォルダは開くだけなので複数選択には含めない
+            selected.retain(|&n| !entries[n].is_dir);
+            if !selected.remove(&index) {
+                selected.insert(index);
+            }
+        } else {
+            selected.clear();
+            selected.insert(index);
+        }
+
+        if self.mode == FileBrowserMode::SaveFile
+            && !entries[index].is_dir
+            && let Some(ref f) = self.name_field
+        {
+            f.input_view.set_text(&entries[index].name);
+        }
+        self.row_states_dirty.set(true);
+    }
+
+    fn clear_selection(&self) {
+        self.selected.borrow_mut().clear();
+        self.row_states_dirty.set(true);
+    }
+
+    /// ダブルクリックされた
+    fn activation(&self, index: usize) -> Option<Confirmation> {
+        let entries = self.entries.borrow();
+        let entry = &entries[index];
+        if entry.is_dir {
+            return Some(Confirmation::Navigate(entry.path.clone()));
+        }
+
+        match self.mode {
+            FileBrowserMode::OpenFile | FileBrowserMode::OpenFiles | FileBrowserMode::SaveFile => {
+                Some(Confirmation::Close(vec![entry.path.clone()]))
+            }
+            // フォルダしか並ばない
+            FileBrowserMode::SelectFolder => None,
+        }
+    }
+
+    /// 決定ボタンが押された
+    fn confirmation(&self) -> Option<Confirmation> {
+        let entries = self.entries.borrow();
+        let selected = self.selected.borrow();
+        let selected_dir = match selected.iter().collect::<Vec<_>>()[..] {
+            [&n] if entries[n].is_dir => Some(entries[n].path.clone()),
+            _ => None,
+        };
+
+        match self.mode {
+            FileBrowserMode::OpenFile | FileBrowserMode::OpenFiles => {
+                if let Some(d) = selected_dir {
+                    return Some(Confirmation::Navigate(d));
+                }
+
+                let paths = selected
+                    .iter()
+                    .filter(|&&n| !entries[n].is_dir)
+                    .map(|&n| entries[n].path.clone())
+                    .collect::<Vec<_>>();
+                (!paths.is_empty()).then_some(Confirmation::Close(paths))
+            }
+            FileBrowserMode::SaveFile => {
+                let name = self
+                    .name_field
+                    .as_ref()
+                    .map_or_else(String::new, |f| f.input_view.text());
+                let name = name.trim();
+                if name.is_empty() {
+                    return selected_dir.map(Confirmation::Navigate);
+                }
+
+                let mut path = self.current_directory.borrow().join(name);
+                if path.extension().is_none()
+                    && let Some(ext) = self.current_filter().and_then(|f| f.extensions.first())
+                {
+                    path.set_extension(ext);
+                }
+                Some(Confirmation::Close(vec![path]))
+            }
+            FileBrowserMode::SelectFolder => Some(Confirmation::Close(vec![
+                selected_dir.unwrap_or_else(|| self.current_directory.borrow().clone()),
+            ])),
+        }
+    }
+
+    fn apply(&self, context: &mut AppUpdateContext, confirmation: Option<Confirmation>) {
+        match confirmation {
+            Some(Confirmation::Navigate(d)) => self.navigate(d),
+            Some(Confirmation::Close(paths)) => self.close(context, Some(paths)),
+            None => (),
+        }
+    }
+
+    fn button_for(&self, sender: HitTestTreeRef) -> Option<&CommonButtonView> {
+        [&self.up_button, &self.cancel_button, &self.confirm_button]
+            .into_iter()
+            .find(|b| b.is_sender(sender))
+    }
+
+    /// 選択中のものについての表示
+    fn preview_text(&self) -> String {
+        if let Some(ref e) = *self.read_error.borrow() {
+            return e.clone();
+        }
+
+        let entries = self.entries.borrow();
+        let selected = self.selected.borrow();
+        match selected.iter().collect::<Vec<_>>()[..] {
+            [] if entries.is_empty() => String::from("No matching files in this folder"),
+            [] => String::new(),
+            [&n] if entries[n].is_dir => String::new(),
+            [&n] => match png_dimensions(&entries[n].path) {
+                Some((w, h)) => format!("{}: {w} x {h} px", entries[n].name),
+                None => entries[n].name.clone(),
+            },
+            ref xs => format!("{} files selected", xs.len()),
+        }
+    }
+}
+impl HitTestTreeActionHandler for ActionHandler {
+    fn cursor_shape(&self, sender: HitTestTreeRef, _context: &mut AppUpdateContext) -> CursorShape {
+        for b in [&self.up_button, &self.cancel_button, &self.confirm_button] {
+            if let Some(c) = b.try_handle_cursor_shape(sender) {
+                return c;
+            }
+        }
+        if let Some(ref d) = self.filter_dropdown
+            && let Some(c) = d.try_handle_cursor_shape(sender)
+        {
+            return c;
+        }
+        if let Some(ref f) = self.name_field
+            && let Some(c) = f.input_view.try_handle_cursor_shape(sender)
+        {
+            return c;
+        }
+
+        CursorShape::Default
+    }
+
+    fn keyboard_focus(&self, sender: HitTestTreeRef) -> Option<FocusTargetToken> {
+        self.name_field
+            .as_ref()
+            .and_then(|f| f.input_view.try_handle_keyboard_focus(sender))
+    }
+
+    fn text_input_target(&self, sender: HitTestTreeRef) -> Option<TextInputTarget> {
+        self.name_field
+            .as_ref()
+            .and_then(|f| f.input_view.try_handle_text_input_target(sender))
+    }
+
+    fn on_focus(&self, sender: HitTestTreeRef, _context: &mut AppUpdateContext) {
+        if let Some(ref f) = self.name_field {
+            f.input_view.try_handle_focus(sender);
+        }
+    }
+
+    fn on_blur(&self, sender: HitTestTreeRef, _context: &mut AppUpdateContext) {
+        if let Some(ref f) = self.name_field
+            && f.input_view.try_handle_blur(sender)
+        {
+            // 値は決定時に読むので編集結果は捨てる
+            let _ = f.input_view.take_edit_end();
+        }
+    }
+
+    fn on_key_down(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut AppUpdateContext,
+        args: &KeyActionArgs,
+    ) -> EventContinueControl {
+        if let Some(ref f) = self.name_field
+            && let Some(x) = f.input_view.try_handle_key_down(sender, context, args)
+        {
+            if let Some(TextInputEditEnd::Commit(_)) = f.input_view.take_edit_end() {
+                // Enterで決定する
+                let c = self.confirmation();
+                self.apply(context, c);
+            }
+
+            return x;
+        }
+
+        EventContinueControl::empty()
+    }
+
+    fn on_text_input(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut AppUpdateContext,
+        event: &TextInputEvent,
+    ) -> EventContinueControl {
+        if let Some(ref f) = self.name_field
+            && let Some(x) = f.input_view.try_handle_text_input(sender, event)
+        {
+            return x;
+        }
+
+        EventContinueControl::empty()
+    }
+
+    fn on_scroll(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut AppUpdateContext,
+        args: &ScrollActionArgs,
+    ) -> EventContinueControl {
+        if let Some(x) = self
+            .scroll_view
+            .try_handle_scroll(sender, args, context.current_sec)
+        {
+            return x;
+        }
+
+        EventContinueControl::empty()
+    }
+
+    fn on_pointer_enter(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut AppUpdateContext,
+        _args: &PointerActionArgs,
+    ) -> EventContinueControl {
+        if let Some(b) = self.button_for(sender) {
+            b.on_hover();
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+        if let Some(ref d) = self.filter_dropdown
+            && let Some(x) = d.try_handle_pointer_enter(sender)
+        {
+            return x;
+        }
+        if let Some(index) = self.row_entry_index(sender) {
+            self.hovered.set(Some(index));
+            self.row_states_dirty.set(true);
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if self.frame_view.is_sender(sender) || self.mask_view.is_sender(sender) {
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        EventContinueControl::empty()
+    }
+
+    fn on_pointer_leave(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut AppUpdateContext,
+        _args: &PointerActionArgs,
+    ) -> EventContinueControl {
+        if let Some(b) = self.button_for(sender) {
+            b.on_leave();
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+        if let Some(ref d) = self.filter_dropdown
+            && let Some(x) = d.try_handle_pointer_leave(sender)
+        {
+            return x;
+        }
+        if let Some(index) = self.row_entry_index(sender) {
+            if self.hovered.get() == Some(index) {
+                self.hovered.set(None);
+                self.row_states_dirty.set(true);
+            }
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if self.frame_view.is_sender(sender) || self.mask_view.is_sender(sender) {
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        EventContinueControl::empty()
+    }
+
+    fn on_pointer_move(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut AppUpdateContext,
+        args: &PointerActionArgs,
+    ) -> EventContinueControl {
+        if let Some(ref f) = self.name_field
+            && let Some(x) = f.input_view.try_handle_pointer_move(sender, args)
+        {
+            return x;
+        }
+        if let Some(x) = self.scroll_view.try_handle_pointer_move(sender, args) {
+            return x;
+        }
+
+        EventContinueControl::STOP_PROPAGATION
+    }
+
+    fn on_pointer_down(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut AppUpdateContext,
+        args: &PointerActionArgs,
+    ) -> EventContinueControl {
+        if let Some(ref f) = self.name_field
+            && let Some(x) = f.input_view.try_handle_pointer_down(sender, args)
+        {
+            return x;
+        }
+        if let Some(x) = self.scroll_view.try_handle_pointer_down(sender, args) {
+            return x;
+        }
+        if let Some(b) = self.button_for(sender) {
+            b.on_press();
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if self.mask_view.is_sender(sender) {
+            self.close(context, None);
+        }
+
+        EventContinueControl::STOP_PROPAGATION
+    }
+
+    fn on_pointer_up(
+        &self,
+        sender: HitTestTreeRef,
+        _context: &mut AppUpdateContext,
+        args: &PointerActionArgs,
+    ) -> EventContinueControl {
+        if let Some(ref f) = self.name_field
+            && let Some(x) = f.input_view.try_handle_pointer_up(sender, args)
+        {
+            return x;
+        }
+        if let Some(x) = self.scroll_view.try_handle_pointer_up(sender, args) {
+            return x;
+        }
+        if let Some(b) = self.button_for(sender) {
+            b.on_release();
+        }
+
+        EventContinueControl::STOP_PROPAGATION
+    }
+
+    fn on_click(
+        &self,
+        sender: HitTestTreeRef,
+        context: &mut AppUpdateContext,
+        args: &PointerActionArgs,
+    ) -> EventContinueControl {
+        if let Some(ref d) = self.filter_dropdown
+            && let Some(x) = d.try_handle_on_click(sender, args)
+        {
+            return x;
+        }
+
+        if self.up_button.is_sender(sender) {
+            let parent = self
+                .current_directory
+                .borrow()
+                .parent()
+                .map(Path::to_path_buf);
+            if let Some(p) = parent {
+                self.navigate(p);
+            }
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+        if self.cancel_button.is_sender(sender) {
+            self.close(context, None);
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+        if self.confirm_button.is_sender(sender) {
+            let c = self.confirmation();
+            self.apply(context, c);
+
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+
+        if let Some(index) = self.row_entry_index(sender) {
+            let now = context.current_sec;
+            if let Some((last_index, last_t)) = self.last_row_click.replace(Some((index, now)))
+                && last_index == index
+                && now - last_t <= Self::DOUBLE_CLICK_INTERVAL.as_secs_f32()
+            {
+                // ダブルクリックでフォルダに入る/そのファイルで決定する
+                self.last_row_click.set(None);
+                let c = self.activation(index);
+                self.apply(context, c);
+
+                return EventContinueControl::STOP_PROPAGATION;
+            }
+
+            self.select(index);
+            return EventContinueControl::STOP_PROPAGATION;
+        }
+        if self.scroll_view.is_viewport(sender) {
+            // 何もないところをクリックしたら選択を外す
+            self.clear_selection();
+        }
+
+        EventContinueControl::STOP_PROPAGATION
+    }
+}
+
+pub struct Presenter {
+    action_handler: Rc<ActionHandler>,
+    path_label: LabelView,
+    preview_label: LabelView,
+}
+impl Presenter {
+    const FRAME_WIDTH: f32 = 560.0;
+    const FRAME_HEIGHT: f32 = 440.0;
+    const FRAME_PADDING: f32 = 16.0;
+    const GAP: f32 = 8.0;
+    const LIST_INSET: f32 = 4.0;
+    const FILTER_DROPDOWN_WIDTH: f32 = 160.0;
+
+    fn update_visible_rows(&self, base_sys: &mut AppBaseSystem, rebind_all: bool) {
+        let h = &self.action_handler;
+        let entries = h.entries.borrow();
+        let (visible_top, visible_bottom) = h.scroll_view.visible_range();
+        let first = ((visible_top / RowView::HEIGHT).floor().max(0.0) as usize).min(entries.len());
+        let last = ((visible_bottom / RowView::HEIGHT).ceil().max(0.0) as usize)
+            .clamp(first, entries.len());
+
+        let mut row_views = h.row_views.borrow_mut();
+        // 範囲外になった行を空ける
+        let mut bound_rows = vec![false; last - first];
+        for r in row_views.iter() {
+            let Some(index) = r.bound_index.get() else {
+                continue;
+            };
+
+            if !rebind_all && (first..last).contains(&index) {
+                bound_rows[index - first] = true;
+                continue;
+            }
+
+            r.unbind(base_sys);
+        }
+        let mut free_rows = row_views
+            .iter()
+            .enumerate()
+            .filter_map(|(n, r)| r.bound_index.get().is_none().then_some(n))
+            .collect::<Vec<_>>();
+
+        for (index, bound) in (first..last).zip(bound_rows) {
+            if bound {
+                // 表示中で中身も変わっていない
+                continue;
+            }
+
+            let label = entries[index].display_name();
+            let n = match free_rows.pop() {
+                Some(n) => n,
+                None => {
+                    let r = RowView::new(
+                        &mut ViewInitContext {
+                            base_system: base_sys,
+                            ui_scale_factor: h.ui_scale_factor,
+                        },
+                        &label,
+                        h.row_label_max_width,
+                    );
+                    base_sys.hit_tree.set_action_handler(r.ht_root, h);
+                    row_views.push(r);
+
+                    row_views.len() - 1
+                }
+            };
+
+            row_views[n].bind(base_sys, h.scroll_view.content_parents(), index, &label);
+        }
+    }
+}
+impl PopupPresenterSpawnable for Presenter {
+    type SpawnArgs<'a> = FileBrowserRequest;
+
+    fn new<'a>(
+        init_context: &mut PresenterInitContext,
+        id: uuid::Uuid,
+        args: Self::SpawnArgs<'a>,
+    ) -> Self {
+        let content_width = Self::FRAME_WIDTH - Self::FRAME_PADDING * 2.0;
+
+        let title_label = LabelView::new(
+            &mut init_context.for_view,
+            FontType::UIExtraLarge,
+            &args.title,
+            content_width,
+            TextTruncation::End,
+            ThemeColor::Text,
+        );
+        let up_button = CommonButtonView::new(&mut init_context.for_view, "Up");
+        let path_label = LabelView::new(
+            &mut init_context.for_view,
+            FontType::UI,
+            "",
+            content_width - up_button.preferred_width() - Self::GAP,
+            TextTruncation::Middle,
+            ThemeColor::TextSecondary,
+        );
+        let preview_label = LabelView::new(
+            &mut init_context.for_view,
+            FontType::UI,
+            "",
+            content_width,
+            TextTruncation::Middle,
+            ThemeColor::TextSecondary,
+        );
+        let name_field = (args.mode == FileBrowserMode::SaveFile)
+            .then(|| NameFieldView::new(&mut init_context.for_view, content_width));
+        let filter_labels = args
+            .filters
+            .iter()
+            .map(|x| x.label.as_str())
+            .collect::<Vec<_>>();
+        let filter_dropdown = (!filter_labels.is_empty()).then(|| {
+            DropdownView::new(
+                &mut init_context.for_view,
+                &filter_labels,
+                Self::FILTER_DROPDOWN_WIDTH,
+            )
+        });
+        let cancel_button = CommonButtonView::new(&mut init_context.for_view, "Cancel");
+        let confirm_button =
+            CommonButtonView::new(&mut init_context.for_view, args.mode.confirm_label());
+        // Note: 高さは残りを埋めるので、ここでは仮の値
+        let list_box = ListBoxView::new(&mut init_context.for_view, content_width, 0.0);
+        let scroll_view = ScrollContainerView::new(
+            &mut init_context.for_view,
+            Padding::uniform(Self::LIST_INSET),
+        );
+
+        let frame_view = popup::CommonFrameView::new(
+            &mut init_context.for_view,
+            Self::FRAME_WIDTH,
+            Self::FRAME_HEIGHT,
+        );
+        let mask_view = popup::MaskView::new(&mut init_context.for_view);
+
+        frame_view.mount(
+            init_context.for_view.base_system,
+            mask_view.ct_root(),
+            mask_view.ht_root(),
+        );
+        let frame_parents = (frame_view.ct_root(), frame_view.ht_root());
+        title_label.mount(init_context.for_view.base_system, frame_parents.0);
+        up_button.mount(
+            init_context.for_view.base_system,
+            frame_parents.0,
+            frame_parents.1,
+        );
+        path_label.mount(init_context.for_view.base_system, frame_parents.0);
+        list_box.mount(init_context.for_view.base_system, frame_parents);
+        scroll_view.mount(
+            init_context.for_view.base_system,
+            list_box.ct_root,
+            list_box.ht_root,
+        );
+        preview_label.mount(init_context.for_view.base_system, frame_parents.0);
+        if let Some(ref f) = name_field {
+            f.mount(init_context.for_view.base_system, frame_parents);
+        }
+        if let Some(ref d) = filter_dropdown {
+            // Note: マスクはクライアント全体を覆っているのでリストを重ねる先に使う
+            d.mount(
+                init_context.for_view.base_system,
+                frame_parents.0,
+                frame_parents.1,
+                (mask_view.ct_root(), mask_view.ht_root()),
+            );
+        }
+        cancel_button.mount(
+            init_context.for_view.base_system,
+            frame_parents.0,
+            frame_parents.1,
+        );
+        confirm_button.mount(
+            init_context.for_view.base_system,
+            frame_parents.0,
+            frame_parents.1,
+        );
+
+        let mut button_stack = StackLayout::horizontal().gap(Self::GAP);
+        if let Some(ref d) = filter_dropdown {
+            button_stack = button_stack.element(d).aligned(CrossAlign::Center);
+        }
+        let mut layout = StackLayout::vertical()
+            .padding(Padding::uniform(Self::FRAME_PADDING))
+            .gap(Self::GAP)
+            .element(&title_label)
+            .stack(
+                StackLayout::horizontal()
+                    .gap(Self::GAP)
+                    .cross_align(CrossAlign::Center)
+                    .element(&up_button)
+                    .element(&path_label),
+            )
+            .flex_element(&list_box, 1.0)
+            .aligned(CrossAlign::Stretch)
+            .element(&preview_label);
+        if let Some(ref f) = name_field {
+            layout = layout.element(f);
+        }
+        layout
+            .stack(
+                button_stack
+                    .flex_spacer(1.0)
+                    .element(&cancel_button)
+                    .element(&confirm_button),
+            )
+            .aligned(CrossAlign::Stretch)
+            .apply(
+                init_context.for_view.base_system,
+                LayoutRect::from_size(Self::FRAME_WIDTH, Self::FRAME_HEIGHT),
+            );
+
+        let action_handler = Rc::new(ActionHandler {
+            mask_view,
+            frame_view,
+            up_button,
+            cancel_button,
+            confirm_button,
+            filter_dropdown,
+            name_field,
+            scroll_view,
+            row_views: RefCell::new(Vec::new()),
+            row_label_max_width: content_width - Self::LIST_INSET * 2.0 - RowView::LABEL_LEFT * 2.0,
+            ui_scale_factor: init_context.for_view.ui_scale_factor,
+            mode: args.mode,
+            filters: args.filters,
+            current_directory: RefCell::new(PathBuf::new()),
+            entries: RefCell::new(Vec::new()),
+            read_error: RefCell::new(None),
+            selected: RefCell::new(BTreeSet::new()),
+            hovered: Cell::new(None),
+            applied_filter: Cell::new(0),
+            last_row_click: Cell::new(None),
+            entries_dirty: Cell::new(true),
+            row_states_dirty: Cell::new(true),
+            reply: RefCell::new(args.reply),
+            popup_id: id,
+        });
+        action_handler.navigate(args.initial_directory.unwrap_or_else(default_directory));
+
+        action_handler.mask_view.bind_action_handler(
+            &action_handler,
+            &mut init_context.for_view.base_system.hit_tree,
+        );
+        action_handler.frame_view.bind_action_handler(
+            &action_handler,
+            &mut init_context.for_view.base_system.hit_tree,
+        );
+        for b in [
+            &action_handler.up_button,
+            &action_handler.cancel_button,
+            &action_handler.confirm_button,
+        ] {
+            b.bind_action_handler(
+                &action_handler,
+                &mut init_context.for_view.base_system.hit_tree,
+            );
+        }
+        action_handler
+            .scroll_view
+            .bind_action_handler(init_context.for_view.base_system, &action_handler);
+        if let Some(ref d) = action_handler.filter_dropdown {
+            d.bind_action_handler(init_context.for_view.base_system, &action_handler);
+        }
+        if let Some(ref f) = action_handler.name_field {
+            f.bind_action_handler(init_context.for_view.base_system, &action_handler);
+        }
+
+        Self {
+            action_handler,
+            path_label,
+            preview_label,
+        }
+    }
+}
+impl PopupPresenter for Presenter {
+    fn show(
+        &self,
+        app_system: &mut AppBaseSystem,
+        parents: (CompositeTreeRef, HitTestTreeRef),
+        current_sec: f32,
+    ) {
+        self.action_handler
+            .mask_view
+            .mount(app_system, parents.0, parents.1);
+        self.action_handler
+            .mask_view
+            .show(&mut app_system.composite_tree, current_sec);
+        self.action_handler
+            .frame_view
+            .show(&mut app_system.composite_tree, current_sec);
+    }
+
+    fn set_client_size(&self, width: f32, height: f32) {
+        self.action_handler
+            .scroll_view
+            .set_client_size(width, height);
+    }
+
+    fn update(&self, base_sys: &mut AppBaseSystem, current_sec: f32) {
+        let h = &self.action_handler;
+
+        if let Some(ref d) = h.filter_dropdown {
+            d.update(base_sys, current_sec);
+            if d.selected() != h.applied_filter.get() {
+                h.applied_filter.set(d.selected());
+                h.reload();
+            }
+        }
+
+        let entries_changed = h.entries_dirty.replace(false);
+        if entries_changed {
+            self.path_label
+                .set_text(base_sys, &h.current_directory.borrow().to_string_lossy());
+            h.scroll_view
+                .set_content_height(h.entries.borrow().len() as f32 * RowView::HEIGHT);
+        }
+        h.scroll_view.update(base_sys, current_sec);
+        if h.scroll_view.take_scrolled() || entries_changed {
+            self.update_visible_rows(base_sys, entries_changed);
+            h.row_states_dirty.set(true);
+        }
+
+        if h.row_states_dirty.replace(false) {
+            let selected = h.selected.borrow();
+            for r in h.row_views.borrow().iter() {
+                if let Some(index) = r.bound_index.get() {
+                    r.set_state(
+                        base_sys,
+                        selected.contains(&index),
+                        h.hovered.get() == Some(index),
+                    );
+                }
+            }
+            drop(selected);
+
+            self.preview_label.set_text(base_sys, &h.preview_text());
+        }
+
+        for b in [&h.up_button, &h.cancel_button, &h.confirm_button] {
+            b.update(&mut base_sys.composite_tree, current_sec);
+        }
+        if let Some(ref f) = h.name_field {
+            f.input_view.update(base_sys, current_sec);
+        }
+    }
+
+    fn hide(&self, app_system: &mut AppBaseSystem, current_sec: f32) {
+        if let Some(ref d) = self.action_handler.filter_dropdown {
+            d.close();
+            d.update(app_system, current_sec);
+        }
+        self.action_handler
+            .mask_view
+            .unmount_ht(&mut app_system.hit_tree);
+        self.action_handler.mask_view.hide(
+            &mut app_system.composite_tree,
+            current_sec,
+            AppEvent::UIPopupUnmount {
+                id: self.action_handler.popup_id,
+            },
+        );
+        self.action_handler
+            .frame_view
+            .hide(&mut app_system.composite_tree, current_sec);
+    }
+
+    fn unmount(&self, base_sys: &mut AppBaseSystem) {
+        self.action_handler
+            .mask_view
+            .unmount_visual(&mut base_sys.composite_tree);
+    }
+}
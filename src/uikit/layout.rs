@@ -0,0 +1,530 @@
+//! スタック/フレックスによるレイアウト
+//!
+//! 子のプリファードサイズと伸縮率から位置とサイズを決めて、CompositeRectとHitTestTreeDataへまとめて反映する
+
+use crate::{
+    base_system::AppBaseSystem,
+    composite::{AnimatableFloat, CompositeTreeRef},
+    hittest::HitTestTreeRef,
+};
+
+/// レイアウトで配置できるView
+pub trait LayoutElement {
+    /// 配置対象のルート要素
+    fn layout_roots(&self) -> (CompositeTreeRef, Option<HitTestTreeRef>);
+    fn preferred_size(&self) -> (f32, f32);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+/// 主軸方向の余りの配分(伸縮する子がいるときは余りが出ないので効かない)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MainAlign {
+    Start,
+    Center,
+    End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrossAlign {
+    Start,
+    Center,
+    End,
+    /// 交差軸方向いっぱいに広げる
+    Stretch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Padding {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+impl Padding {
+    pub const fn uniform(x: f32) -> Self {
+        Self {
+            left: x,
+            top: x,
+            right: x,
+            bottom: x,
+        }
+    }
+
+    pub const fn symmetric(horizontal: f32, vertical: f32) -> Self {
+        Self {
+            left: horizontal,
+            top: vertical,
+            right: horizontal,
+            bottom: vertical,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LayoutRect {
+    pub left: f32,
+    pub top: f32,
+    pub width: f32,
+    pub height: f32,
+}
+impl LayoutRect {
+    pub const fn from_size(width: f32, height: f32) -> Self {
+        Self {
+            left: 0.0,
+            top: 0.0,
+            width,
+            height,
+        }
+    }
+}
+
+enum LayoutNode {
+    Element {
+        ct: CompositeTreeRef,
+        ht: Option<HitTestTreeRef>,
+        preferred_size: (f32, f32),
+    },
+    Spacer {
+        size: f32,
+    },
+    Stack(StackLayout),
+}
+impl LayoutNode {
+    fn preferred_size(&self, parent_axis: Axis) -> (f32, f32) {
+        match self {
+            &Self::Element { preferred_size, .. } => preferred_size,
+            &Self::Spacer { size } => match parent_axis {
+                Axis::Horizontal => (size, 0.0),
+                Axis::Vertical => (0.0, size),
+            },
+            Self::Stack(s) => s.preferred_size(),
+        }
+    }
+}
+
+struct LayoutChild {
+    node: LayoutNode,
+    /// 主軸方向の余りを受け取る比率(0なら伸びない)
+    flex: f32,
+    cross_align: Option<CrossAlign>,
+}
+
+/// 子を一列に並べるコンテナ
+pub struct StackLayout {
+    axis: Axis,
+    padding: Padding,
+    gap: f32,
+    main_align: MainAlign,
+    cross_align: CrossAlign,
+    children: Vec<LayoutChild>,
+}
+impl StackLayout {
+    pub const fn new(axis: Axis) -> Self {
+        Self {
+            axis,
+            padding: Padding {
+                left: 0.0,
+                top: 0.0,
+                right: 0.0,
+                bottom: 0.0,
+            },
+            gap: 0.0,
+            main_align: MainAlign::Start,
+            cross_align: CrossAlign::Start,
+            children: Vec::new(),
+        }
+    }
+
+    pub const fn horizontal() -> Self {
+        Self::new(Axis::Horizontal)
+    }
+
+    pub const fn vertical() -> Self {
+        Self::new(Axis::Vertical)
+    }
+
+    pub const fn padding(mut self, padding: Padding) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub const fn gap(mut self, gap: f32) -> Self {
+        self.gap = gap;
+        self
+    }
+
+    pub const fn main_align(mut self, align: MainAlign) -> Self {
+        self.main_align = align;
+        self
+    }
+
+    pub const fn cross_align(mut self, align: CrossAlign) -> Self {
+        self.cross_align = align;
+        self
+    }
+
+    fn push(mut self, node: LayoutNode, flex: f32) -> Self {
+        self.children.push(LayoutChild {
+            node,
+            flex,
+            cross_align: None,
+        });
+        self
+    }
+
+    pub fn element(self, element: &impl LayoutElement) -> Self {
+        self.flex_element(element, 0.0)
+    }
+
+    pub fn flex_element(self, element: &impl LayoutElement, flex: f32) -> Self {
+        let (ct, ht) = element.layout_roots();
+
+        self.push(
+            LayoutNode::Element {
+                ct,
+                ht,
+                preferred_size: element.preferred_size(),
+            },
+            flex,
+        )
+    }
+
+    /// 直前に追加した子だけ交差軸方向の揃え方を変える
+    pub fn aligned(mut self, align: CrossAlign) -> Self {
+        if let Some(c) = self.children.last_mut() {
+            c.cross_align = Some(align);
+        }
+        self
+    }
+
+    /// 固定幅の空き
+    pub fn spacer(self, size: f32) -> Self {
+        self.push(LayoutNode::Spacer { size }, 0.0)
+    }
+
+    /// 余りを埋める空き(右寄せ/下寄せなどに使う)
+    pub fn flex_spacer(self, flex: f32) -> Self {
+        self.push(LayoutNode::Spacer { size: 0.0 }, flex)
+    }
+
+    pub fn stack(self, stack: StackLayout) -> Self {
+        self.flex_stack(stack, 0.0)
+    }
+
+    pub fn flex_stack(self, stack: StackLayout, flex: f32) -> Self {
+        self.push(LayoutNode::Stack(stack), flex)
+    }
+
+    /// (主軸, 交差軸)の順に並べ替える
+    const fn to_axis_local(&self, (w, h): (f32, f32)) -> (f32, f32) {
+        match self.axis {
+            Axis::Horizontal => (w, h),
+            Axis::Vertical => (h, w),
+        }
+    }
+
+    pub fn preferred_size(&self) -> (f32, f32) {
+        let (mut main, mut cross) = (0.0f32, 0.0f32);
+        for c in self.children.iter() {
+            let (m, x) = self.to_axis_local(c.node.preferred_size(self.axis));
+            main += m;
+            cross = cross.max(x);
+        }
+        main += self.gap * self.children.len().saturating_sub(1) as f32;
+
+        let (pw, ph) = (
+            self.padding.left + self.padding.right,
+            self.padding.top + self.padding.bottom,
+        );
+        match self.axis {
+            Axis::Horizontal => (main + pw, cross + ph),
+            Axis::Vertical => (cross + pw, main + ph),
+        }
+    }
+
+    /// 子の配置を計算する(Spacerのぶんも含めて子の順に返す)
+    fn compute(&self, rect: LayoutRect) -> Vec<LayoutRect> {
+        let inner = LayoutRect {
+            left: rect.left + self.padding.left,
+            top: rect.top + self.padding.top,
+            width: (rect.width - self.padding.left - self.padding.right).max(0.0),
+            height: (rect.height - self.padding.top - self.padding.bottom).max(0.0),
+        };
+        let (inner_main, inner_cross) = self.to_axis_local((inner.width, inner.height));
+        let (inner_main_start, inner_cross_start) = self.to_axis_local((inner.left, inner.top));
+
+        let sizes = self
+            .children
+            .iter()
+            .map(|c| self.to_axis_local(c.node.preferred_size(self.axis)))
+            .collect::<Vec<_>>();
+        let total_main = sizes.iter().map(|&(m, _)| m).sum::<f32>()
+            + self.gap * self.children.len().saturating_sub(1) as f32;
+        let total_flex = self.children.iter().map(|c| c.flex).sum::<f32>();
+        let mut remaining = (inner_main - total_main).max(0.0);
+        let flex_unit = if total_flex > 0.0 {
+            let u = remaining / total_flex;
+            remaining = 0.0;
+            u
+        } else {
+            0.0
+        };
+
+        let mut main_pos = inner_main_start
+            + match self.main_align {
+                MainAlign::Start => 0.0,
+                MainAlign::Center => remaining * 0.5,
+                MainAlign::End => remaining,
+            };
+        let mut rects = Vec::with_capacity(self.children.len());
+        for (c, &(main, cross)) in self.children.iter().zip(sizes.iter()) {
+            let main = main + c.flex * flex_unit;
+            let (cross_pos, cross) = match c.cross_align.unwrap_or(self.cross_align) {
+                CrossAlign::Start => (inner_cross_start, cross),
+                CrossAlign::Center => (inner_cross_start + (inner_cross - cross) * 0.5, cross),
+                CrossAlign::End => (inner_cross_start + inner_cross - cross, cross),
+                CrossAlign::Stretch => (inner_cross_start, inner_cross),
+            };
+
+            rects.push(match self.axis {
+                Axis::Horizontal => LayoutRect {
+                    left: main_pos,
+                    top: cross_pos,
+                    width: main,
+                    height: cross,
+                },
+                Axis::Vertical => LayoutRect {
+                    left: cross_pos,
+                    top: main_pos,
+                    width: cross,
+                    height: main,
+                },
+            });
+            main_pos += main + self.gap;
+        }
+
+        rects
+    }
+
+    /// rect(親要素のローカル座標)の中に子を配置する
+    ///
+    /// 配置した要素の相対オフセット/サイズ調整は打ち消される
+    pub fn apply(&self, base_sys: &mut AppBaseSystem, rect: LayoutRect) {
+        for (c, r) in self.children.iter().zip(self.compute(rect)) {
+            match c.node {
+                LayoutNode::Element { ct, ht, .. } => {
+                    let cr = ct.entity_mut_dirtified(&mut base_sys.composite_tree);
+                    cr.offset = [
+                        AnimatableFloat::Value(r.left),
+                        AnimatableFloat::Value(r.top),
+                    ];
+                    cr.size = [
+                        AnimatableFloat::Value(r.width),
+                        AnimatableFloat::Value(r.height),
+                    ];
+                    cr.relative_offset_adjustment = [0.0, 0.0];
+                    cr.relative_size_adjustment = [0.0, 0.0];
+
+                    if let Some(ht) = ht {
+                        let hr = base_sys.hit_tree.get_data_mut(ht);
+                        hr.left = r.left;
+                        hr.top = r.top;
+                        hr.width = r.width;
+                        hr.height = r.height;
+                        hr.left_adjustment_factor = 0.0;
+                        hr.top_adjustment_factor = 0.0;
+                        hr.width_adjustment_factor = 0.0;
+                        hr.height_adjustment_factor = 0.0;
+                    }
+                }
+                LayoutNode::Spacer { .. } => (),
+                LayoutNode::Stack(ref s) => s.apply(base_sys, r),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::composite::CompositeTree;
+
+    use super::*;
+
+    /// 大きさだけ持つ要素
+    struct Fixed(f32, f32);
+    impl LayoutElement for Fixed {
+        fn layout_roots(&self) -> (CompositeTreeRef, Option<HitTestTreeRef>) {
+            (CompositeTree::ROOT, None)
+        }
+
+        fn preferred_size(&self) -> (f32, f32) {
+            (self.0, self.1)
+        }
+    }
+
+    const fn rect(left: f32, top: f32, width: f32, height: f32) -> LayoutRect {
+        LayoutRect {
+            left,
+            top,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn vertical_with_padding_and_gap() {
+        let rects = StackLayout::vertical()
+            .padding(Padding::uniform(4.0))
+            .gap(2.0)
+            .element(&Fixed(10.0, 20.0))
+            .element(&Fixed(30.0, 5.0))
+            .compute(LayoutRect::from_size(100.0, 100.0));
+
+        assert_eq!(
+            rects,
+            [rect(4.0, 4.0, 10.0, 20.0), rect(4.0, 26.0, 30.0, 5.0)]
+        );
+    }
+
+    #[test]
+    fn flex_takes_remaining_space() {
+        let rects = StackLayout::horizontal()
+            .element(&Fixed(10.0, 5.0))
+            .flex_element(&Fixed(10.0, 5.0), 1.0)
+            .flex_spacer(1.0)
+            .compute(LayoutRect::from_size(100.0, 50.0));
+
+        assert_eq!(
+            rects,
+            [
+                rect(0.0, 0.0, 10.0, 5.0),
+                rect(10.0, 0.0, 50.0, 5.0),
+                rect(60.0, 0.0, 40.0, 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn main_align_end() {
+        let rects = StackLayout::horizontal()
+            .gap(5.0)
+            .main_align(MainAlign::End)
+            .element(&Fixed(10.0, 5.0))
+            .element(&Fixed(20.0, 5.0))
+            .compute(LayoutRect::from_size(100.0, 50.0));
+
+        assert_eq!(
+            rects,
+            [rect(65.0, 0.0, 10.0, 5.0), rect(80.0, 0.0, 20.0, 5.0)]
+        );
+    }
+
+    #[test]
+    fn main_align_center() {
+        let rects = StackLayout::horizontal()
+            .padding(Padding::uniform(5.0))
+            .gap(10.0)
+            .main_align(MainAlign::Center)
+            .element(&Fixed(10.0, 5.0))
+            .element(&Fixed(20.0, 5.0))
+            .compute(LayoutRect::from_size(100.0, 50.0));
+
+        // 余りは(90 - 40) / 2ずつ両側に付く
+        assert_eq!(
+            rects,
+            [rect(30.0, 5.0, 10.0, 5.0), rect(50.0, 5.0, 20.0, 5.0)]
+        );
+    }
+
+    #[test]
+    fn main_align_center_is_ignored_with_flex() {
+        let rects = StackLayout::vertical()
+            .main_align(MainAlign::Center)
+            .element(&Fixed(10.0, 10.0))
+            .flex_spacer(1.0)
+            .compute(LayoutRect::from_size(50.0, 100.0));
+
+        assert_eq!(
+            rects,
+            [rect(0.0, 0.0, 10.0, 10.0), rect(0.0, 10.0, 0.0, 90.0)]
+        );
+    }
+
+    #[test]
+    fn cross_align_end() {
+        let rects = StackLayout::vertical()
+            .padding(Padding::symmetric(4.0, 2.0))
+            .cross_align(CrossAlign::End)
+            .element(&Fixed(10.0, 5.0))
+            .element(&Fixed(30.0, 5.0))
+            .aligned(CrossAlign::Start)
+            .compute(rect(10.0, 0.0, 100.0, 50.0));
+
+        assert_eq!(
+            rects,
+            [rect(96.0, 2.0, 10.0, 5.0), rect(14.0, 7.0, 30.0, 5.0)]
+        );
+    }
+
+    #[test]
+    fn cross_align_per_child() {
+        let rects = StackLayout::horizontal()
+            .cross_align(CrossAlign::Center)
+            .element(&Fixed(10.0, 10.0))
+            .element(&Fixed(10.0, 10.0))
+            .aligned(CrossAlign::Stretch)
+            .element(&Fixed(10.0, 10.0))
+            .aligned(CrossAlign::Start)
+            .compute(rect(5.0, 5.0, 100.0, 50.0));
+
+        assert_eq!(
+            rects,
+            [
+                rect(5.0, 25.0, 10.0, 10.0),
+                rect(15.0, 5.0, 10.0, 50.0),
+                rect(25.0, 5.0, 10.0, 10.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_stack_and_preferred_size() {
+        let layout = StackLayout::vertical()
+            .padding(Padding::symmetric(3.0, 4.0))
+            .gap(2.0)
+            .element(&Fixed(10.0, 5.0))
+            .spacer(7.0)
+            .stack(
+                StackLayout::horizontal()
+                    .gap(1.0)
+                    .element(&Fixed(10.0, 6.0))
+                    .element(&Fixed(10.0, 6.0)),
+            );
+
+        assert_eq!(layout.preferred_size(), (27.0, 30.0));
+        assert_eq!(
+            layout.compute(LayoutRect::from_size(27.0, 30.0)),
+            [
+                rect(3.0, 4.0, 10.0, 5.0),
+                rect(3.0, 11.0, 0.0, 7.0),
+                rect(3.0, 20.0, 21.0, 6.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn overflow_does_not_shrink_children() {
+        let rects = StackLayout::vertical()
+            .padding(Padding::uniform(8.0))
+            .main_align(MainAlign::End)
+            .flex_element(&Fixed(10.0, 20.0), 1.0)
+            .compute(LayoutRect::from_size(10.0, 10.0));
+
+        assert_eq!(rects, [rect(8.0, 8.0, 10.0, 20.0)]);
+    }
+}
@@ -1,82 +1,273 @@
-use std::rc::Rc;
+//! タイトル/本文/アイコンと任意個のボタンを持つダイアログ
+//!
+//! 押されたボタンは`DialogRequest::request`のFutureで受け取る
+
+use std::{cell::RefCell, rc::Rc};
 
 use crate::{
-    AppEvent, AppUpdateContext, PresenterInitContext, ViewInitContext,
-    base_system::{AppBaseSystem, FontType},
-    composite::{
-        AnimatableColor, AnimatableFloat, CompositeMode, CompositeRect, CompositeTree,
-        CompositeTreeRef,
-    },
+    AppEvent, AppEventBus, AppUpdateContext, PresenterInitContext, ViewInitContext,
+    base_system::{AppBaseSystem, FontType, theme::ThemeColor},
+    composite::{AnimatableColor, AnimatableFloat, CompositeMode, CompositeRect, CompositeTreeRef},
+    helper_types::SafeF32,
     hittest::{HitTestTreeActionHandler, HitTestTreeRef, PointerActionArgs},
     input::EventContinueControl,
-    uikit::popup::{PopupPresenter, PopupPresenterSpawnable},
+    text::{TextAlignment, TextLayoutOptions},
+    uikit::{
+        layout::{CrossAlign, LayoutElement, LayoutRect, MainAlign, Padding, StackLayout},
+        popup::{PopupPresenter, PopupPresenterSpawnable},
+    },
 };
 
 use super::{common_controls::CommonButtonView, popup};
 
-struct ContentView {
+/// 本文の左に出すアイコン
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DialogIcon {
+    None,
+    Information,
+    Question,
+    Warning,
+    Error,
+}
+impl DialogIcon {
+    /// (記号, 背景色)
+    const fn appearance(self) -> Option<(&'static str, [f32; 4])> {
+        match self {
+            Self::None => None,
+            Self::Information => Some(("i", [0.3, 0.55, 0.9, 1.0])),
+            Self::Question => Some(("?", [0.3, 0.55, 0.9, 1.0])),
+            Self::Warning => Some(("!", [0.85, 0.6, 0.15, 1.0])),
+            Self::Error => Some(("!", [0.8, 0.25, 0.25, 1.0])),
+        }
+    }
+}
+
+/// ダイアログの内容と結果の返し先
+#[derive(Debug)]
+pub struct DialogRequest {
+    title: String,
+    body: String,
+    icon: DialogIcon,
+    buttons: Vec<String>,
+    reply: Option<smol::channel::Sender<usize>>,
+}
+impl DialogRequest {
+    /// OKボタンだけのメッセージ
+    pub fn message(body: impl Into<String>) -> Self {
+        Self {
+            title: String::new(),
+            body: body.into(),
+            icon: DialogIcon::None,
+            buttons: vec!["OK".into()],
+            reply: None,
+        }
+    }
+
+    /// 失敗したことと、その詳細を出す
+    pub fn error(title: impl Into<String>, detail: impl core::fmt::Display) -> Self {
+        Self::message(detail.to_string())
+            .with_title(title)
+            .with_icon(DialogIcon::Error)
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    pub const fn with_icon(mut self, icon: DialogIcon) -> Self {
+        self.icon = icon;
+        self
+    }
+
+    /// ボタンを左から順に並べる(結果はこの並びでのindexになる)
+    pub fn with_buttons(mut self, labels: &[&str]) -> Self {
+        self.buttons = labels.iter().map(|&x| x.into()).collect();
+        self
+    }
+
+    /// 表示を依頼して、押されたボタンを待つ
+    ///
+    /// ボタン以外(マスクのクリックなど)で閉じられたときはNone
+    pub async fn request(mut self, events: &AppEventBus) -> Option<usize> {
+        let (sender, receiver) = smol::channel::bounded(1);
+        self.reply = Some(sender);
+        events.push(AppEvent::UIMessageDialogRequest(self));
+
+        receiver.recv().await.ok()
+    }
+}
+
+struct TextView {
     ct_root: CompositeTreeRef,
     preferred_width: f32,
     preferred_height: f32,
 }
-impl ContentView {
-    const FRAME_PADDING_H: f32 = 32.0;
-    const FRAME_PADDING_V: f32 = 16.0;
+impl TextView {
+    #[tracing::instrument(name = "MessageDialog::TextView::new", skip(init))]
+    fn new(init: &mut ViewInitContext, font: FontType, text: &str, max_width: f32) -> Self {
+        let text_atlas_rect = init
+            .base_system
+            .text_mask_with_options(
+                font,
+                text,
+                &TextLayoutOptions::wrapped(unsafe {
+                    SafeF32::new_unchecked(max_width * init.ui_scale_factor)
+                })
+                .with_alignment(TextAlignment::Left),
+            )
+            .unwrap();
+
+        let preferred_width = text_atlas_rect.width() as f32 / init.ui_scale_factor;
+        let preferred_height = text_atlas_rect.height() as f32 / init.ui_scale_factor;
+        let ct_root = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            size: [
+                AnimatableFloat::Value(preferred_width),
+                AnimatableFloat::Value(preferred_height),
+            ],
+            has_bitmap: true,
+            texatlas_rect: text_atlas_rect,
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::Text),
+            ),
+            ..Default::default()
+        });
 
-    #[tracing::instrument(name = "ContentView::new", skip(init))]
-    pub fn new(init: &mut ViewInitContext, content: &str) -> Self {
-        let text_atlas_rect = init.base_system.text_mask(FontType::UI, content).unwrap();
+        Self {
+            ct_root,
+            preferred_width,
+            preferred_height,
+        }
+    }
+
+    fn mount(&self, base_system: &mut AppBaseSystem, ct_parent: CompositeTreeRef) {
+        base_system.set_composite_tree_parent(self.ct_root, ct_parent);
+    }
+}
+impl LayoutElement for TextView {
+    fn layout_roots(&self) -> (CompositeTreeRef, Option<HitTestTreeRef>) {
+        (self.ct_root, None)
+    }
+
+    fn preferred_size(&self) -> (f32, f32) {
+        (self.preferred_width, self.preferred_height)
+    }
+}
 
-        let preferred_width =
-            Self::FRAME_PADDING_H * 2.0 + text_atlas_rect.width() as f32 / init.ui_scale_factor;
-        let preferred_height =
-            Self::FRAME_PADDING_V * 2.0 + text_atlas_rect.height() as f32 / init.ui_scale_factor;
+struct IconView {
+    ct_root: CompositeTreeRef,
+}
+impl IconView {
+    const SIZE: f32 = 32.0;
+
+    #[tracing::instrument(name = "MessageDialog::IconView::new", skip(init))]
+    fn new(init: &mut ViewInitContext, glyph: &str, color: [f32; 4]) -> Self {
+        let radius = unsafe { SafeF32::new_unchecked(Self::SIZE * 0.5) };
+        let bg_atlas_rect = init
+            .base_system
+            .rounded_fill_rect_mask(
+                unsafe { SafeF32::new_unchecked(init.ui_scale_factor) },
+                radius,
+            )
+            .unwrap();
+        let glyph_atlas_rect = init
+            .base_system
+            .text_mask(FontType::UIExtraLarge, glyph)
+            .unwrap();
 
         let ct_root = init.base_system.register_composite_rect(CompositeRect {
             base_scale_factor: init.ui_scale_factor,
             size: [
-                AnimatableFloat::Value(text_atlas_rect.width() as f32 / init.ui_scale_factor),
-                AnimatableFloat::Value(text_atlas_rect.height() as f32 / init.ui_scale_factor),
+                AnimatableFloat::Value(Self::SIZE),
+                AnimatableFloat::Value(Self::SIZE),
             ],
+            has_bitmap: true,
+            texatlas_rect: bg_atlas_rect,
+            slice_borders: [radius.value() * init.ui_scale_factor; 4],
+            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value(color)),
+            ..Default::default()
+        });
+        let ct_glyph = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
             offset: [
                 AnimatableFloat::Value(
-                    -(text_atlas_rect.width() as f32 / init.ui_scale_factor) * 0.5,
+                    -0.5 * glyph_atlas_rect.width() as f32 / init.ui_scale_factor,
                 ),
-                AnimatableFloat::Value(Self::FRAME_PADDING_V),
+                AnimatableFloat::Value(
+                    -0.5 * glyph_atlas_rect.height() as f32 / init.ui_scale_factor,
+                ),
+            ],
+            relative_offset_adjustment: [0.5, 0.5],
+            size: [
+                AnimatableFloat::Value(glyph_atlas_rect.width() as f32 / init.ui_scale_factor),
+                AnimatableFloat::Value(glyph_atlas_rect.height() as f32 / init.ui_scale_factor),
             ],
-            relative_offset_adjustment: [0.5, 0.0],
             has_bitmap: true,
-            texatlas_rect: text_atlas_rect,
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value([0.9, 0.9, 0.9, 1.0])),
+            texatlas_rect: glyph_atlas_rect,
+            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value([1.0, 1.0, 1.0, 1.0])),
             ..Default::default()
         });
 
-        Self {
-            ct_root,
-            preferred_width,
-            preferred_height,
-        }
+        init.base_system
+            .set_composite_tree_parent(ct_glyph, ct_root);
+
+        Self { ct_root }
     }
 
-    pub fn mount(&self, ct_parent: CompositeTreeRef, ct: &mut CompositeTree) {
-        ct.add_child(ct_parent, self.ct_root);
+    fn mount(&self, base_system: &mut AppBaseSystem, ct_parent: CompositeTreeRef) {
+        base_system.set_composite_tree_parent(self.ct_root, ct_parent);
+    }
+}
+impl LayoutElement for IconView {
+    fn layout_roots(&self) -> (CompositeTreeRef, Option<HitTestTreeRef>) {
+        (self.ct_root, None)
+    }
+
+    fn preferred_size(&self) -> (f32, f32) {
+        (Self::SIZE, Self::SIZE)
     }
 }
 
 struct ActionHandler {
     mask_view: popup::MaskView,
     frame_view: popup::CommonFrameView,
-    confirm_button: CommonButtonView,
+    buttons: Vec<CommonButtonView>,
+    reply: RefCell<Option<smol::channel::Sender<usize>>>,
     popup_id: uuid::Uuid,
 }
+impl ActionHandler {
+    /// 結果を返して閉じる(Noneなら返さずに閉じる)
+    fn close(&self, context: &mut AppUpdateContext, result: Option<usize>) {
+        if let Some(reply) = self.reply.take()
+            && let Some(result) = result
+        {
+            // Note: 待っている側がもういなくても気にしない
+            let _ = reply.try_send(result);
+        }
+
+        context
+            .event_queue
+            .push(AppEvent::UIPopupClose { id: self.popup_id });
+    }
+
+    fn button_for(&self, sender: HitTestTreeRef) -> Option<(usize, &CommonButtonView)> {
+        self.buttons
+            .iter()
+            .enumerate()
+            .find(|(_, b)| b.is_sender(sender))
+    }
+}
 impl HitTestTreeActionHandler for ActionHandler {
     fn cursor_shape(
         &self,
         sender: HitTestTreeRef,
         _context: &mut AppUpdateContext,
     ) -> crate::hittest::CursorShape {
-        if let Some(c) = self.confirm_button.try_handle_cursor_shape(sender) {
-            return c;
+        for b in self.buttons.iter() {
+            if let Some(c) = b.try_handle_cursor_shape(sender) {
+                return c;
+            }
         }
 
         return crate::hittest::CursorShape::Default;
@@ -88,8 +279,8 @@ impl HitTestTreeActionHandler for ActionHandler {
         _context: &mut AppUpdateContext,
         _args: &PointerActionArgs,
     ) -> EventContinueControl {
-        if self.confirm_button.is_sender(sender) {
-            self.confirm_button.on_hover();
+        if let Some((_, b)) = self.button_for(sender) {
+            b.on_hover();
 
             return EventContinueControl::STOP_PROPAGATION;
         }
@@ -111,8 +302,8 @@ impl HitTestTreeActionHandler for ActionHandler {
         _context: &mut AppUpdateContext,
         _args: &PointerActionArgs,
     ) -> EventContinueControl {
-        if self.confirm_button.is_sender(sender) {
-            self.confirm_button.on_leave();
+        if let Some((_, b)) = self.button_for(sender) {
+            b.on_leave();
 
             return EventContinueControl::STOP_PROPAGATION;
         }
@@ -134,8 +325,8 @@ impl HitTestTreeActionHandler for ActionHandler {
         context: &mut AppUpdateContext,
         _args: &PointerActionArgs,
     ) -> EventContinueControl {
-        if self.confirm_button.is_sender(sender) {
-            self.confirm_button.on_press();
+        if let Some((_, b)) = self.button_for(sender) {
+            b.on_press();
 
             return EventContinueControl::STOP_PROPAGATION;
         }
@@ -145,9 +336,7 @@ impl HitTestTreeActionHandler for ActionHandler {
         }
 
         if self.mask_view.is_sender(sender) {
-            context
-                .event_queue
-                .push(AppEvent::UIPopupClose { id: self.popup_id });
+            self.close(context, None);
             return EventContinueControl::STOP_PROPAGATION;
         }
 
@@ -160,8 +349,8 @@ impl HitTestTreeActionHandler for ActionHandler {
         _context: &mut AppUpdateContext,
         _args: &PointerActionArgs,
     ) -> EventContinueControl {
-        if self.confirm_button.is_sender(sender) {
-            self.confirm_button.on_release();
+        if let Some((_, b)) = self.button_for(sender) {
+            b.on_release();
 
             return EventContinueControl::STOP_PROPAGATION;
         }
@@ -183,10 +372,8 @@ impl HitTestTreeActionHandler for ActionHandler {
         context: &mut AppUpdateContext,
         _args: &PointerActionArgs,
     ) -> EventContinueControl {
-        if self.confirm_button.is_sender(sender) {
-            context
-                .event_queue
-                .push(AppEvent::UIPopupClose { id: self.popup_id });
+        if let Some((n, _)) = self.button_for(sender) {
+            self.close(context, Some(n));
 
             return EventContinueControl::STOP_PROPAGATION;
         }
@@ -206,21 +393,82 @@ impl HitTestTreeActionHandler for ActionHandler {
 pub struct Presenter {
     action_handler: Rc<ActionHandler>,
 }
+impl Presenter {
+    const MIN_FRAME_WIDTH: f32 = 280.0;
+    const MAX_TEXT_WIDTH: f32 = 400.0;
+    const FRAME_PADDING_H: f32 = 24.0;
+    const FRAME_PADDING_V: f32 = 20.0;
+}
 impl PopupPresenterSpawnable for Presenter {
-    type SpawnArgs<'a> = &'a str;
+    type SpawnArgs<'a> = DialogRequest;
 
     fn new<'a>(
         init_context: &mut PresenterInitContext,
         id: uuid::Uuid,
         args: Self::SpawnArgs<'a>,
     ) -> Self {
-        let content_view = ContentView::new(&mut init_context.for_view, args);
-        let confirm_button = CommonButtonView::new(&mut init_context.for_view, "OK");
-        let frame_view = popup::CommonFrameView::new(
+        let icon_view = args
+            .icon
+            .appearance()
+            .map(|(glyph, color)| IconView::new(&mut init_context.for_view, glyph, color));
+        let text_max_width = Self::MAX_TEXT_WIDTH
+            - icon_view
+                .as_ref()
+                .map_or(0.0, |_| IconView::SIZE + Self::FRAME_PADDING_H * 0.5);
+        let title_view = (!args.title.is_empty()).then(|| {
+            TextView::new(
+                &mut init_context.for_view,
+                FontType::UIExtraLarge,
+                &args.title,
+                text_max_width,
+            )
+        });
+        let body_view = TextView::new(
             &mut init_context.for_view,
-            content_view.preferred_width,
-            content_view.preferred_height + 4.0 + confirm_button.preferred_height(),
+            FontType::UI,
+            &args.body,
+            text_max_width,
         );
+        let buttons = args
+            .buttons
+            .iter()
+            .map(|x| CommonButtonView::new(&mut init_context.for_view, x))
+            .collect::<Vec<_>>();
+
+        let mut text_stack = StackLayout::vertical().gap(8.0);
+        if let Some(ref v) = title_view {
+            text_stack = text_stack.element(v);
+        }
+        let mut content_stack = StackLayout::horizontal().gap(Self::FRAME_PADDING_H * 0.5);
+        if let Some(ref v) = icon_view {
+            content_stack = content_stack.element(v);
+        }
+        // Note: ボタンがひとつだけ(お知らせ)のときは真ん中に置く
+        let mut button_stack =
+            StackLayout::horizontal()
+                .gap(8.0)
+                .main_align(if buttons.len() == 1 {
+                    MainAlign::Center
+                } else {
+                    MainAlign::End
+                });
+        for b in buttons.iter() {
+            button_stack = button_stack.element(b);
+        }
+        let layout = StackLayout::vertical()
+            .padding(Padding::symmetric(
+                Self::FRAME_PADDING_H,
+                Self::FRAME_PADDING_V,
+            ))
+            .gap(Self::FRAME_PADDING_V)
+            .stack(content_stack.stack(text_stack.element(&body_view)))
+            .stack(button_stack)
+            .aligned(CrossAlign::Stretch);
+        let (preferred_width, preferred_height) = layout.preferred_size();
+        let frame_width = preferred_width.max(Self::MIN_FRAME_WIDTH);
+
+        let frame_view =
+            popup::CommonFrameView::new(&mut init_context.for_view, frame_width, preferred_height);
         let mask_view = popup::MaskView::new(&mut init_context.for_view);
 
         frame_view.mount(
@@ -228,36 +476,30 @@ impl PopupPresenterSpawnable for Presenter {
             mask_view.ct_root(),
             mask_view.ht_root(),
         );
-        content_view.mount(
-            frame_view.ct_root(),
-            &mut init_context.for_view.base_system.composite_tree,
-        );
-        confirm_button.mount(
+        if let Some(ref v) = icon_view {
+            v.mount(init_context.for_view.base_system, frame_view.ct_root());
+        }
+        if let Some(ref v) = title_view {
+            v.mount(init_context.for_view.base_system, frame_view.ct_root());
+        }
+        body_view.mount(init_context.for_view.base_system, frame_view.ct_root());
+        for b in buttons.iter() {
+            b.mount(
+                init_context.for_view.base_system,
+                frame_view.ct_root(),
+                frame_view.ht_root(),
+            );
+        }
+        layout.apply(
             init_context.for_view.base_system,
-            frame_view.ct_root(),
-            frame_view.ht_root(),
+            LayoutRect::from_size(frame_width, preferred_height),
         );
 
-        {
-            let confirm_button_ct =
-                confirm_button.ct_mut(&mut init_context.for_view.base_system.composite_tree);
-            let confirm_button_ht =
-                confirm_button.ht_mut(&mut init_context.for_view.base_system.hit_tree);
-
-            confirm_button_ct.relative_offset_adjustment = [0.5, 0.0];
-            confirm_button_ct.offset = [
-                AnimatableFloat::Value(-0.5 * confirm_button.preferred_width()),
-                AnimatableFloat::Value(content_view.preferred_height - 4.0),
-            ];
-            confirm_button_ht.left_adjustment_factor = 0.5;
-            confirm_button_ht.left = -0.5 * confirm_button_ht.width;
-            confirm_button_ht.top = content_view.preferred_height - 4.0;
-        }
-
         let action_handler = Rc::new(ActionHandler {
             mask_view,
             frame_view,
-            confirm_button,
+            buttons,
+            reply: RefCell::new(args.reply),
             popup_id: id,
         });
         action_handler.mask_view.bind_action_handler(
@@ -268,10 +510,12 @@ impl PopupPresenterSpawnable for Presenter {
             &action_handler,
             &mut init_context.for_view.base_system.hit_tree,
         );
-        action_handler.confirm_button.bind_action_handler(
-            &action_handler,
-            &mut init_context.for_view.base_system.hit_tree,
-        );
+        for b in action_handler.buttons.iter() {
+            b.bind_action_handler(
+                &action_handler,
+                &mut init_context.for_view.base_system.hit_tree,
+            );
+        }
 
         Self { action_handler }
     }
@@ -295,9 +539,9 @@ impl PopupPresenter for Presenter {
     }
 
     fn update(&self, base_sys: &mut AppBaseSystem, current_sec: f32) {
-        self.action_handler
-            .confirm_button
-            .update(&mut base_sys.composite_tree, current_sec);
+        for b in self.action_handler.buttons.iter() {
+            b.update(&mut base_sys.composite_tree, current_sec);
+        }
     }
 
     fn hide(&self, app_system: &mut AppBaseSystem, current_sec: f32) {
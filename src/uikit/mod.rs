@@ -1,5 +1,10 @@
 pub mod common_controls;
+pub mod layout;
+// Note: いまはポータルのFileChooserがないLinuxでしか使っていない
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub mod file_browser;
 pub mod message_dialog;
 pub mod popup;
+pub mod scroll;
 
 pub use self::popup::{PopupPresenter, PopupPresenterSpawnable};
@@ -2,7 +2,7 @@ use std::{collections::HashMap, rc::Rc};
 
 use crate::{
     AppEvent, PresenterInitContext, ViewInitContext,
-    base_system::AppBaseSystem,
+    base_system::{AppBaseSystem, theme::ThemeColor},
     composite::{
         AnimatableColor, AnimatableFloat, AnimationCurve, CompositeMode, CompositeRect,
         CompositeTree, CompositeTreeRef,
@@ -103,6 +103,7 @@ impl MaskView {
         ct.mark_dirty(self.ct_root);
     }
 
+    /// `event_on_complete`はマスク以下（ポップアップの内容を含む）のアニメーションがすべて完了した時点で発行される
     pub fn hide(&self, ct: &mut CompositeTree, current_sec: f32, event_on_complete: AppEvent) {
         ct.get_mut(self.ct_root).composite_mode = CompositeMode::FillColorBackdropBlur(
             AnimatableColor::Animated {
@@ -111,7 +112,7 @@ impl MaskView {
                 start_sec: current_sec,
                 end_sec: current_sec + POPUP_ANIMATION_DURATION,
                 curve: AnimationCurve::Linear,
-                event_on_complete: Some(event_on_complete),
+                event_on_complete: None,
             },
             AnimatableFloat::Animated {
                 from_value: POPUP_MASK_BLUR_POWER,
@@ -124,6 +125,7 @@ impl MaskView {
         );
 
         ct.mark_dirty(self.ct_root);
+        ct.notify_when_settled(self.ct_root, event_on_complete);
     }
 }
 
@@ -166,8 +168,12 @@ impl CommonFrameView {
             has_bitmap: true,
             texatlas_rect: frame_image_atlas_rect,
             slice_borders: [Self::CORNER_RADIUS.value() * init.ui_scale_factor.ceil(); 4],
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value([0.0, 0.0, 0.0, 1.0])),
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::PopupBackground),
+            ),
             opacity: AnimatableFloat::Value(0.0),
+            // 枠線や中身が透けて重ならないようにまとめてフェードさせる（フェードしている間だけレイヤーを使う）
+            offscreen_layer: true,
             ..Default::default()
         });
         let ct_border = init.base_system.register_composite_rect(CompositeRect {
@@ -183,9 +189,9 @@ impl CommonFrameView {
             has_bitmap: true,
             texatlas_rect: frame_border_image_atlas_rect,
             slice_borders: [Self::CORNER_RADIUS.value() * init.ui_scale_factor.ceil(); 4],
-            composite_mode: CompositeMode::ColorTint(AnimatableColor::Value([
-                0.25, 0.25, 0.25, 1.0,
-            ])),
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system.theme.color(ThemeColor::PopupBorder),
+            ),
             ..Default::default()
         });
 
@@ -347,6 +353,16 @@ pub trait PopupPresenter {
     #[inline(always)]
     fn update(&self, base_sys: &mut AppBaseSystem, current_sec: f32) {}
 
+    /// スクロール領域など、クライアント全体の大きさが要るものに渡す
+    #[allow(unused_variables)]
+    #[inline(always)]
+    fn set_client_size(&self, width: f32, height: f32) {}
+
+    /// UIスケールが変わったときに呼ばれる(文字の描き直しとレイアウトのやり直し)
+    #[allow(unused_variables)]
+    #[inline(always)]
+    fn rescale(&self, base_sys: &mut AppBaseSystem, ui_scale_factor: f32) {}
+
     fn hide(&self, base_sys: &mut AppBaseSystem, current_sec: f32);
     fn unmount(&self, base_sys: &mut AppBaseSystem);
 }
@@ -365,6 +381,7 @@ pub struct PopupManager {
     instance_by_id: HashMap<uuid::Uuid, Box<dyn PopupPresenter>>,
     hit_base_layer: HitTestTreeRef,
     composite_base_layer: CompositeTreeRef,
+    client_size: (f32, f32),
 }
 impl PopupManager {
     pub fn new(hit_base_layer: HitTestTreeRef, composite_base_layer: CompositeTreeRef) -> Self {
@@ -372,6 +389,7 @@ impl PopupManager {
             instance_by_id: HashMap::new(),
             hit_base_layer,
             composite_base_layer,
+            client_size: (0.0, 0.0),
         }
     }
 
@@ -383,6 +401,7 @@ impl PopupManager {
     ) -> uuid::Uuid {
         let id = uuid::Uuid::new_v4();
         let presenter = P::new(presenter_init_context, id, args);
+        presenter.set_client_size(self.client_size.0, self.client_size.1);
         presenter.show(
             presenter_init_context.for_view.base_system,
             (self.composite_base_layer, self.hit_base_layer),
@@ -411,9 +430,27 @@ impl PopupManager {
         inst.unmount(base_system);
     }
 
+    /// 開いている(閉じるアニメーション中のものもふくむ)ポップアップの数
+    pub fn count(&self) -> usize {
+        self.instance_by_id.len()
+    }
+
+    pub fn set_client_size(&mut self, width: f32, height: f32) {
+        self.client_size = (width, height);
+        for x in self.instance_by_id.values() {
+            x.set_client_size(width, height);
+        }
+    }
+
     pub fn update(&mut self, base_system: &mut AppBaseSystem, current_sec: f32) {
         for x in self.instance_by_id.values() {
             x.update(base_system, current_sec);
         }
     }
+
+    pub fn rescale(&self, base_system: &mut AppBaseSystem, ui_scale_factor: f32) {
+        for x in self.instance_by_id.values() {
+            x.rescale(base_system, ui_scale_factor);
+        }
+    }
 }
@@ -0,0 +1,496 @@
+//! 縦スクロールできる領域
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    rc::Rc,
+    time::Duration,
+};
+
+use crate::{
+    ViewInitContext,
+    base_system::{AppBaseSystem, theme::ThemeColor},
+    composite::{
+        AnimatableFloat, AnimationCurve, ClipConfig, CompositeMode, CompositeRect, CompositeTreeRef,
+    },
+    helper_types::SafeF32,
+    hittest::{
+        HitTestTreeActionHandler, HitTestTreeData, HitTestTreeRef, PointerActionArgs,
+        ScrollActionArgs,
+    },
+    input::EventContinueControl,
+    uikit::layout::Padding,
+};
+
+/// 中身(content)を縦にスクロールして見せるビューポート
+///
+/// 中身の要素はct_content/ht_contentの下にぶら下げる ht_viewportは範囲外の子にあたらないようにクリップする
+pub struct ScrollContainerView {
+    ct_viewport: CompositeTreeRef,
+    ct_content: CompositeTreeRef,
+    ct_thumb: CompositeTreeRef,
+    ht_viewport: HitTestTreeRef,
+    ht_content: HitTestTreeRef,
+    ht_thumb: HitTestTreeRef,
+    client_size: Cell<(f32, f32)>,
+    viewport_height: Cell<f32>,
+    /// クライアント座標でのビューポートの上端
+    viewport_client_top: Cell<f32>,
+    content_height: Cell<f32>,
+    /// スクロール位置(慣性スクロール中は行き先)
+    offset: Cell<f32>,
+    /// 表示が追いつくまでの間の、表示上のスクロール位置の範囲
+    visual_range: Cell<(f32, f32)>,
+    pending_delta: Cell<f32>,
+    pending_fling: Cell<bool>,
+    /// (時刻, スクロール量)
+    velocity_samples: RefCell<VecDeque<(f32, f32)>>,
+    /// つまみのドラッグ開始時の(スクロール位置, ポインタのy)
+    thumb_drag: Cell<Option<(f32, f32)>>,
+    pending_thumb_drag_client_y: Cell<Option<f32>>,
+    thumb_drag_released: Cell<bool>,
+    is_dirty: Cell<bool>,
+    scrolled: Cell<bool>,
+}
+impl ScrollContainerView {
+    const THUMB_WIDTH: f32 = 4.0;
+    const THUMB_MARGIN: f32 = 4.0;
+    const THUMB_MIN_HEIGHT: f32 = 16.0;
+    const THUMB_HIT_WIDTH: f32 = 12.0;
+    const THUMB_RADIUS: SafeF32 = unsafe { SafeF32::new_unchecked(2.0) };
+    const FLING_SAMPLE_WINDOW: Duration = Duration::from_millis(100);
+    /// 指を離したときの速度(px/s)からどこまで流れるか
+    const FLING_DISTANCE_FACTOR: f32 = 0.35;
+    const FLING_DURATION: f32 = 0.6;
+
+    /// 親いっぱいからinsetsを引いた範囲をビューポートにする
+    #[tracing::instrument(name = "ScrollContainerView::new", skip(init))]
+    pub fn new(init: &mut ViewInitContext, insets: Padding) -> Self {
+        let thumb_atlas_rect = init
+            .base_system
+            .rounded_fill_rect_mask(
+                unsafe { SafeF32::new_unchecked(init.ui_scale_factor.ceil()) },
+                Self::THUMB_RADIUS,
+            )
+            .unwrap();
+
+        let ct_viewport = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            offset: [
+                AnimatableFloat::Value(insets.left),
+                AnimatableFloat::Value(insets.top),
+            ],
+            size: [
+                AnimatableFloat::Value(-(insets.left + insets.right)),
+                AnimatableFloat::Value(-(insets.top + insets.bottom)),
+            ],
+            relative_size_adjustment: [1.0, 1.0],
+            clip_child: Some(ClipConfig {
+                left_softness: SafeF32::ZERO,
+                top_softness: SafeF32::ZERO,
+                right_softness: SafeF32::ZERO,
+                bottom_softness: SafeF32::ZERO,
+            }),
+            ..Default::default()
+        });
+        let ct_content = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            relative_size_adjustment: [1.0, 0.0],
+            ..Default::default()
+        });
+        let ct_thumb = init.base_system.register_composite_rect(CompositeRect {
+            base_scale_factor: init.ui_scale_factor,
+            offset: [
+                AnimatableFloat::Value(-Self::THUMB_MARGIN - Self::THUMB_WIDTH),
+                AnimatableFloat::Value(0.0),
+            ],
+            relative_offset_adjustment: [1.0, 0.0],
+            size: [
+                AnimatableFloat::Value(Self::THUMB_WIDTH),
+                AnimatableFloat::Value(Self::THUMB_MIN_HEIGHT),
+            ],
+            has_bitmap: true,
+            texatlas_rect: thumb_atlas_rect,
+            slice_borders: [Self::THUMB_RADIUS.value() * init.ui_scale_factor.ceil(); 4],
+            composite_mode: CompositeMode::ColorTint(
+                init.base_system
+                    .theme
+                    .color_with_alpha(ThemeColor::Foreground, 0.25),
+            ),
+            opacity: AnimatableFloat::Value(0.0),
+            ..Default::default()
+        });
+
+        init.base_system
+            .set_composite_tree_parent(ct_content, ct_viewport);
+        init.base_system
+            .set_composite_tree_parent(ct_thumb, ct_viewport);
+
+        let ht_viewport = init.base_system.create_hit_tree(HitTestTreeData {
+            left: insets.left,
+            top: insets.top,
+            width: -(insets.left + insets.right),
+            height: -(insets.top + insets.bottom),
+            width_adjustment_factor: 1.0,
+            height_adjustment_factor: 1.0,
+            clip_children: true,
+            ..Default::default()
+        });
+        let ht_content = init.base_system.create_hit_tree(HitTestTreeData {
+            width_adjustment_factor: 1.0,
+            ..Default::default()
+        });
+        let ht_thumb = init.base_system.create_hit_tree(HitTestTreeData {
+            left: -Self::THUMB_HIT_WIDTH,
+            left_adjustment_factor: 1.0,
+            width: Self::THUMB_HIT_WIDTH,
+            ..Default::default()
+        });
+
+        init.base_system
+            .set_hit_tree_parent(ht_content, ht_viewport);
+        init.base_system.set_hit_tree_parent(ht_thumb, ht_viewport);
+
+        Self {
+            ct_viewport,
+            ct_content,
+            ct_thumb,
+            ht_viewport,
+            ht_content,
+            ht_thumb,
+            client_size: Cell::new((0.0, 0.0)),
+            viewport_height: Cell::new(0.0),
+            viewport_client_top: Cell::new(0.0),
+            content_height: Cell::new(0.0),
+            offset: Cell::new(0.0),
+            visual_range: Cell::new((0.0, 0.0)),
+            pending_delta: Cell::new(0.0),
+            pending_fling: Cell::new(false),
+            velocity_samples: RefCell::new(VecDeque::new()),
+            thumb_drag: Cell::new(None),
+            pending_thumb_drag_client_y: Cell::new(None),
+            thumb_drag_released: Cell::new(false),
+            is_dirty: Cell::new(true),
+            scrolled: Cell::new(true),
+        }
+    }
+
+    pub fn mount(
+        &self,
+        base_sys: &mut AppBaseSystem,
+        ct_parent: CompositeTreeRef,
+        ht_parent: HitTestTreeRef,
+    ) {
+        base_sys.set_tree_parent((self.ct_viewport, self.ht_viewport), (ct_parent, ht_parent));
+    }
+
+    pub fn bind_action_handler<'subsystem>(
+        &self,
+        base_sys: &mut AppBaseSystem<'subsystem>,
+        handler: &Rc<impl HitTestTreeActionHandler + 'subsystem>,
+    ) {
+        base_sys
+            .hit_tree
+            .set_action_handler(self.ht_viewport, handler);
+        base_sys.hit_tree.set_action_handler(self.ht_thumb, handler);
+    }
+
+    pub fn rescale(&self, base_sys: &mut AppBaseSystem, ui_scale_factor: f32) {
+        let render_scale = unsafe { SafeF32::new_unchecked(ui_scale_factor.ceil()) };
+        let thumb_atlas_rect = base_sys
+            .rounded_fill_rect_mask(render_scale, Self::THUMB_RADIUS)
+            .unwrap();
+
+        for ct in [self.ct_viewport, self.ct_content] {
+            ct.entity_mut_dirtified(&mut base_sys.composite_tree)
+                .base_scale_factor = ui_scale_factor;
+        }
+        let cr = self
+            .ct_thumb
+            .entity_mut_dirtified(&mut base_sys.composite_tree);
+        cr.base_scale_factor = ui_scale_factor;
+        cr.texatlas_rect = thumb_atlas_rect;
+        cr.slice_borders = [Self::THUMB_RADIUS.value() * render_scale.value(); 4];
+    }
+
+    /// 中身をぶら下げる先
+    pub const fn content_parents(&self) -> (CompositeTreeRef, HitTestTreeRef) {
+        (self.ct_content, self.ht_content)
+    }
+
+    pub fn is_viewport(&self, sender: HitTestTreeRef) -> bool {
+        sender == self.ht_viewport
+    }
+
+    /// ビューポートの大きさを出すのに使う
+    pub fn set_client_size(&self, width: f32, height: f32) {
+        if self.client_size.replace((width, height)) != (width, height) {
+            self.is_dirty.set(true);
+        }
+    }
+
+    pub fn set_content_height(&self, height: f32) {
+        if self.content_height.replace(height) != height {
+            self.is_dirty.set(true);
+        }
+    }
+
+    pub fn scroll_offset(&self) -> f32 {
+        self.offset.get()
+    }
+
+    /// 中身の座標で、いま(とアニメーションが終わるまでに)見えうる範囲
+    pub fn visible_range(&self) -> (f32, f32) {
+        let (from, to) = self.visual_range.get();
+
+        (from.min(to), from.max(to) + self.viewport_height.get())
+    }
+
+    /// クライアント座標でビューポートが占める縦方向の範囲(top, bottom)
+    pub fn viewport_client_range_y(&self) -> (f32, f32) {
+        let top = self.viewport_client_top.get();
+
+        (top, top + self.viewport_height.get())
+    }
+
+    /// 見える範囲が変わっていたらtrue
+    pub fn take_scrolled(&self) -> bool {
+        self.scrolled.replace(false)
+    }
+
+    /// 中身の座標で[top, bottom)が見えるようにスクロールする
+    pub fn scroll_into_view(&self, top: f32, bottom: f32) {
+        let offset = self.offset.get();
+        let viewport_height = self.viewport_height.get();
+
+        if top < offset {
+            self.pending_delta.set(top - offset);
+        } else if bottom > offset + viewport_height {
+            self.pending_delta.set(bottom - (offset + viewport_height));
+        }
+    }
+
+    fn max_offset(&self) -> f32 {
+        (self.content_height.get() - self.viewport_height.get()).max(0.0)
+    }
+
+    fn thumb_height(&self) -> f32 {
+        let (viewport_height, content_height) =
+            (self.viewport_height.get(), self.content_height.get());
+        if content_height <= 0.0 {
+            return viewport_height;
+        }
+
+        (viewport_height * viewport_height / content_height)
+            .clamp(Self::THUMB_MIN_HEIGHT.min(viewport_height), viewport_height)
+    }
+
+    fn thumb_top(&self, offset: f32) -> f32 {
+        let max_offset = self.max_offset();
+        if max_offset <= 0.0 {
+            return 0.0;
+        }
+
+        (offset / max_offset) * (self.viewport_height.get() - self.thumb_height())
+    }
+
+    /// 指を離す直前の速度(px/s)
+    fn fling_velocity(&self, now: f32) -> f32 {
+        let mut samples = self.velocity_samples.borrow_mut();
+        samples.retain(|&(t, _)| now - t <= Self::FLING_SAMPLE_WINDOW.as_secs_f32());
+        let Some(&(first_t, _)) = samples.front() else {
+            return 0.0;
+        };
+
+        let distance = samples.iter().map(|&(_, d)| d).sum::<f32>();
+        samples.clear();
+        distance / (now - first_t).max(1.0 / 60.0)
+    }
+
+    pub fn update(&self, base_sys: &mut AppBaseSystem, current_sec: f32) {
+        let relayout = self.is_dirty.replace(false);
+        if relayout {
+            let (client_width, client_height) = self.client_size.get();
+            let (_, client_origin_y, _, viewport_height) =
+                base_sys.hit_tree.translate_client_to_tree_local(
+                    self.ht_viewport,
+                    0.0,
+                    0.0,
+                    client_width,
+                    client_height,
+                );
+            self.viewport_height.set(viewport_height.max(0.0));
+            self.viewport_client_top.set(-client_origin_y);
+
+            self.ct_content
+                .entity_mut_dirtified(&mut base_sys.composite_tree)
+                .size[1] = AnimatableFloat::Value(self.content_height.get());
+            base_sys.hit_tree.get_data_mut(self.ht_content).height = self.content_height.get();
+
+            let scrollable = self.max_offset() > 0.0;
+            let cr = self
+                .ct_thumb
+                .entity_mut_dirtified(&mut base_sys.composite_tree);
+            cr.size[1] = AnimatableFloat::Value(self.thumb_height());
+            cr.opacity = AnimatableFloat::Value(if scrollable { 1.0 } else { 0.0 });
+            base_sys.hit_tree.get_data_mut(self.ht_thumb).height =
+                if scrollable { self.thumb_height() } else { 0.0 };
+        }
+
+        if let Some(client_y) = self.pending_thumb_drag_client_y.take()
+            && let Some((base_offset, base_client_y)) = self.thumb_drag.get()
+        {
+            let track = self.viewport_height.get() - self.thumb_height();
+            if track > 0.0 {
+                let offset = base_offset + (client_y - base_client_y) * self.max_offset() / track;
+                self.pending_delta.set(offset - self.offset.get());
+            }
+        }
+        if self.thumb_drag_released.replace(false) {
+            self.thumb_drag.set(None);
+        }
+
+        let delta = self.pending_delta.replace(0.0);
+        let fling = self.pending_fling.replace(false);
+        let current_offset = self.offset.get();
+        let mut new_offset = (current_offset + delta).clamp(0.0, self.max_offset());
+        let animate_from = if fling {
+            let v = self.fling_velocity(current_sec);
+            new_offset =
+                (new_offset + v * Self::FLING_DISTANCE_FACTOR).clamp(0.0, self.max_offset());
+
+            Some(current_offset)
+        } else {
+            None
+        };
+
+        if new_offset == current_offset && animate_from.is_none() && !relayout {
+            // no changes
+            return;
+        }
+        self.offset.set(new_offset);
+
+        let (content_offset, thumb_top) = match animate_from {
+            Some(from) if from != new_offset => {
+                let curve = AnimationCurve::CubicBezier {
+                    p1: (0.25, 1.0),
+                    p2: (0.5, 1.0),
+                };
+
+                (
+                    AnimatableFloat::Animated {
+                        from_value: -from,
+                        to_value: -new_offset,
+                        start_sec: current_sec,
+                        end_sec: current_sec + Self::FLING_DURATION,
+                        curve: curve.clone(),
+                        event_on_complete: None,
+                    },
+                    AnimatableFloat::Animated {
+                        from_value: self.thumb_top(from),
+                        to_value: self.thumb_top(new_offset),
+                        start_sec: current_sec,
+                        end_sec: current_sec + Self::FLING_DURATION,
+                        curve,
+                        event_on_complete: None,
+                    },
+                )
+            }
+            _ => (
+                AnimatableFloat::Value(-new_offset),
+                AnimatableFloat::Value(self.thumb_top(new_offset)),
+            ),
+        };
+        self.visual_range
+            .set((animate_from.unwrap_or(new_offset), new_offset));
+
+        self.ct_content
+            .entity_mut_dirtified(&mut base_sys.composite_tree)
+            .offset[1] = content_offset;
+        self.ct_thumb
+            .entity_mut_dirtified(&mut base_sys.composite_tree)
+            .offset[1] = thumb_top;
+        // Note: ヒットテストはアニメーションしないので行き先に合わせておく
+        base_sys.hit_tree.get_data_mut(self.ht_content).top = -new_offset;
+        base_sys.hit_tree.get_data_mut(self.ht_thumb).top = self.thumb_top(new_offset);
+        self.scrolled.set(true);
+    }
+
+    /// current_secはイベントを処理している時刻(慣性スクロールの速さを求めるのに使う)
+    pub fn try_handle_scroll(
+        &self,
+        sender: HitTestTreeRef,
+        args: &ScrollActionArgs,
+        current_sec: f32,
+    ) -> Option<EventContinueControl> {
+        if sender != self.ht_viewport && sender != self.ht_thumb {
+            return None;
+        }
+
+        if args.delta_y != 0.0 {
+            self.pending_delta
+                .set(self.pending_delta.get() + args.delta_y);
+            self.velocity_samples
+                .borrow_mut()
+                .push_back((current_sec, args.delta_y));
+        }
+        if args.fling {
+            self.pending_fling.set(true);
+        } else if args.delta_y == 0.0 {
+            // 横スクロールだけなら親に任せる
+            return None;
+        }
+
+        Some(EventContinueControl::STOP_PROPAGATION | EventContinueControl::RECOMPUTE_POINTER_ENTER)
+    }
+
+    pub fn try_handle_pointer_down(
+        &self,
+        sender: HitTestTreeRef,
+        args: &PointerActionArgs,
+    ) -> Option<EventContinueControl> {
+        if sender != self.ht_thumb {
+            return None;
+        }
+
+        self.thumb_drag
+            .set(Some((self.offset.get(), args.client_y)));
+        Some(EventContinueControl::STOP_PROPAGATION | EventContinueControl::CAPTURE_ELEMENT)
+    }
+
+    pub fn try_handle_pointer_move(
+        &self,
+        sender: HitTestTreeRef,
+        args: &PointerActionArgs,
+    ) -> Option<EventContinueControl> {
+        if sender != self.ht_thumb {
+            return None;
+        }
+
+        if self.thumb_drag.get().is_some() {
+            self.pending_thumb_drag_client_y.set(Some(args.client_y));
+        }
+        Some(EventContinueControl::STOP_PROPAGATION)
+    }
+
+    pub fn try_handle_pointer_up(
+        &self,
+        sender: HitTestTreeRef,
+        args: &PointerActionArgs,
+    ) -> Option<EventContinueControl> {
+        if sender != self.ht_thumb {
+            return None;
+        }
+
+        if self.thumb_drag.get().is_some() {
+            self.pending_thumb_drag_client_y.set(Some(args.client_y));
+            // Note: 反映はupdateでやるのでドラッグ状態はそこまで残す
+            self.thumb_drag_released.set(true);
+            return Some(
+                EventContinueControl::STOP_PROPAGATION
+                    | EventContinueControl::RELEASE_CAPTURE_ELEMENT,
+            );
+        }
+        Some(EventContinueControl::STOP_PROPAGATION)
+    }
+}
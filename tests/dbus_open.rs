@@ -0,0 +1,63 @@
+//! 自動操作インターフェイス(D-Bus)のOpenが未保存の変更を捨てないことの確認
+
+use std::process::Command;
+
+const BUS_NAME: &str = "io.ct2.peridot.SpriteAtlasVisualizer";
+const OBJECT_PATH: &str = "/io/ct2/peridot/SpriteAtlasVisualizer";
+
+#[test]
+#[ignore = "requires a Wayland session, a Vulkan device, dbus-run-session and dbus-send"]
+fn open_is_refused_while_modified() {
+    let dir = std::env::temp_dir().join(format!("peridot-dbus-open-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let sprite = dir.join("sprite.png");
+    image::RgbaImage::from_pixel(8, 8, image::Rgba([255, 0, 0, 255]))
+        .save(&sprite)
+        .unwrap();
+    let atlas = dir.join("atlas.psa");
+
+    // Note: 各呼び出しの結果を行頭のマーカーで出力して、最後にまとめて確認する
+    let script = format!(
+        r#"
+app="$1"
+"$app" >&2 &
+app_pid=$!
+trap 'kill $app_pid 2>/dev/null' EXIT
+
+call() {{
+    dbus-send --session --print-reply --dest={BUS_NAME} {OBJECT_PATH} {BUS_NAME}."$@" 2>&1
+}}
+
+for _ in $(seq 1 100); do
+    dbus-send --session --print-reply --dest=org.freedesktop.DBus /org/freedesktop/DBus \
+        org.freedesktop.DBus.NameHasOwner string:{BUS_NAME} | grep -q 'boolean true' && break
+    sleep 0.1
+done
+
+call AddSprites array:string:"{sprite}" >/dev/null && echo add:ok
+call Open string:"{atlas}" | grep -q org.freedesktop.DBus.Error.Failed && echo open-modified:failed
+call Save string:"{atlas}" >/dev/null && echo save:ok
+call Open string:"{atlas}" >/dev/null && echo open-saved:ok
+"#,
+        sprite = sprite.display(),
+        atlas = atlas.display(),
+    );
+
+    let output = Command::new("dbus-run-session")
+        .args(["--", "sh", "-c", &script, "sh"])
+        .arg(env!(
+            "CARGO_BIN_EXE_peridot-sprite-atlas-visualizer-wayland"
+        ))
+        .output()
+        .expect("failed to run dbus-run-session");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let results = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(
+        results,
+        ["add:ok", "open-modified:failed", "save:ok", "open-saved:ok"],
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
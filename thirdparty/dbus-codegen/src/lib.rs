@@ -0,0 +1,746 @@
+//! Introspection XMLから型付きのプロキシを生成する(build scriptから使う)
+//!
+//! 生成されるもの(インターフェイスひとつにつき):
+//! - `INTERFACE`: インターフェイス名
+//! - `Proxy`: メソッド呼び出しとプロパティ取得を`dbus::AsyncConnection`越しに非同期で行う
+//! - `<Signal名>Signal`: シグナルの引数を読み出す
+//!
+//! 型の対応は基本型(`s`/`o`/`u`/`b`)だけ値として扱い、それ以外は呼び出し側で組み立て/読み出しをする
+//! (`a{sv}`の引数は辞書のコンテナ、それ以外の引数は引数列のappender、返り値は`dbus::MessageIter`か返答のメッセージそのもの)
+
+use std::fmt::Write;
+
+use dbus::introspect_document::{
+    InterfaceElementContent, MethodSignalElementContent, read_interface_tag_content,
+    read_method_tag_content, read_signal_tag_content, read_toplevel,
+    skip_read_interface_tag_contents,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to read introspection document: {0}")]
+    Read(String),
+    #[error("interface {0} was not found in the document")]
+    InterfaceNotFound(String),
+    #[error("generated name `{0}` collides")]
+    NameCollision(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Arg {
+    pub name: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Method {
+    pub name: String,
+    pub in_args: Vec<Arg>,
+    pub out_args: Vec<Arg>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Signal {
+    pub name: String,
+    pub args: Vec<Arg>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Property {
+    pub name: String,
+    pub signature: String,
+    pub readable: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Interface {
+    pub name: String,
+    pub methods: Vec<Method>,
+    pub signals: Vec<Signal>,
+    pub properties: Vec<Property>,
+}
+
+fn lossy(x: &[u8]) -> String {
+    String::from_utf8_lossy(x).into_owned()
+}
+
+/// ドキュメントから指定した名前のインターフェイスを読み出す
+pub fn parse_interface(document: &str, interface_name: &str) -> Result<Interface, Error> {
+    let mut found = None;
+
+    read_toplevel(&mut quick_xml::Reader::from_str(document), |_, name, r| {
+        if name.as_ref() != interface_name.as_bytes() {
+            return skip_read_interface_tag_contents(r);
+        }
+
+        let mut iface = Interface {
+            name: interface_name.into(),
+            ..Default::default()
+        };
+        read_interface_tag_content(r, |e, r| {
+            match e {
+                InterfaceElementContent::Method { name, empty } => {
+                    let mut m = Method {
+                        name: lossy(&name),
+                        in_args: Vec::new(),
+                        out_args: Vec::new(),
+                    };
+                    if !empty {
+                        read_method_tag_content(r, |c, _| {
+                            if let MethodSignalElementContent::Arg {
+                                name,
+                                r#type,
+                                direction,
+                            } = c
+                            {
+                                let arg = Arg {
+                                    name: lossy(&name),
+                                    signature: lossy(&r#type),
+                                };
+                                // Note: メソッドの引数のdirectionは省略時in
+                                if direction.is_some_and(|x| x.as_ref() == b"out") {
+                                    m.out_args.push(arg);
+                                } else {
+                                    m.in_args.push(arg);
+                                }
+                            }
+
+                            Ok(())
+                        })?;
+                    }
+
+                    iface.methods.push(m);
+                }
+                InterfaceElementContent::Signal { name, empty } => {
+                    let mut s = Signal {
+                        name: lossy(&name),
+                        args: Vec::new(),
+                    };
+                    if !empty {
+                        read_signal_tag_content(r, |c, _| {
+                            if let MethodSignalElementContent::Arg { name, r#type, .. } = c {
+                                s.args.push(Arg {
+                                    name: lossy(&name),
+                                    signature: lossy(&r#type),
+                                });
+                            }
+
+                            Ok(())
+                        })?;
+                    }
+
+                    iface.signals.push(s);
+                }
+                InterfaceElementContent::Property {
+                    name,
+                    r#type,
+                    access,
+                } => {
+                    iface.properties.push(Property {
+                        name: lossy(&name),
+                        signature: lossy(&r#type),
+                        readable: access.as_ref() != b"write",
+                    });
+                }
+            }
+
+            Ok(())
+        })?;
+
+        found = Some(iface);
+        Ok(())
+    })
+    .map_err(|e| Error::Read(e.to_string()))?;
+
+    found.ok_or_else(|| Error::InterfaceNotFound(interface_name.into()))
+}
+
+/// ドキュメントから指定した名前のインターフェイスのコードを生成する
+pub fn generate_interface(document: &str, interface_name: &str) -> Result<String, Error> {
+    generate(&parse_interface(document, interface_name)?)
+}
+
+pub fn generate(iface: &Interface) -> Result<String, Error> {
+    let mut out = String::new();
+    let has_proxy = !iface.methods.is_empty() || iface.properties.iter().any(|x| x.readable);
+
+    writeln!(
+        out,
+        "// generated by dbus-codegen from {}. do not edit.\n",
+        iface.name
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "pub const INTERFACE: &core::ffi::CStr = {};\n",
+        cstr_literal(&iface.name)
+    )
+    .unwrap();
+
+    if has_proxy {
+        generate_proxy(&mut out, iface)?;
+    }
+
+    for s in iface.signals.iter() {
+        generate_signal(&mut out, s);
+    }
+
+    Ok(out)
+}
+
+fn generate_proxy(out: &mut String, iface: &Interface) -> Result<(), Error> {
+    let mut fn_names = std::collections::HashSet::new();
+    fn_names.insert(String::from("new"));
+    fn_names.insert(String::from("call"));
+
+    out.push_str(
+        "pub struct Proxy<'c, C: dbus::AsyncConnection + ?Sized> {
+    con: &'c C,
+    destination: Option<&'c core::ffi::CStr>,
+    path: &'c core::ffi::CStr,
+}
+impl<'c, C: dbus::AsyncConnection + ?Sized> Proxy<'c, C> {
+    pub const fn new(
+        con: &'c C,
+        destination: Option<&'c core::ffi::CStr>,
+        path: &'c core::ffi::CStr,
+    ) -> Self {
+        Self {
+            con,
+            destination,
+            path,
+        }
+    }
+
+    async fn call(&self, mut msg_: dbus::Message) -> Result<dbus::Message, dbus::Error> {
+        let serial = self
+            .con
+            .connection()
+            .send_with_serial(&mut msg_)
+            .expect(\"no enough memory\");
+        let reply_ = self.con.wait_for_reply(serial).await;
+        match reply_.try_get_error() {
+            Some(e) => Err(e),
+            None => Ok(reply_),
+        }
+    }
+",
+    );
+
+    for m in iface.methods.iter() {
+        let fn_name = rust_ident(&snake_case(&m.name));
+        if !fn_names.insert(fn_name.clone()) {
+            return Err(Error::NameCollision(fn_name));
+        }
+
+        writeln!(
+            out,
+            "\n    /// `{}({}) -> ({})`",
+            m.name,
+            describe_args(&m.in_args),
+            describe_args(&m.out_args)
+        )
+        .unwrap();
+        write!(out, "    pub async fn {fn_name}(\n        &self,\n").unwrap();
+        for a in m.in_args.iter() {
+            writeln!(
+                out,
+                "        {}: {},",
+                rust_ident(&snake_case(&a.name)),
+                ArgKind::of(&a.signature).in_type()
+            )
+            .unwrap();
+        }
+        let basic_outs = m
+            .out_args
+            .iter()
+            .all(|a| ArgKind::of(&a.signature).out_type().is_some());
+        let ret_type = if !basic_outs {
+            String::from("dbus::Message")
+        } else if m.out_args.len() == 1 {
+            String::from(ArgKind::of(&m.out_args[0].signature).out_type().unwrap())
+        } else {
+            format!(
+                "({})",
+                m.out_args
+                    .iter()
+                    .map(|a| ArgKind::of(&a.signature).out_type().unwrap())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        };
+        writeln!(out, "    ) -> Result<{ret_type}, dbus::Error> {{").unwrap();
+
+        writeln!(
+            out,
+            "        let {}msg_ = dbus::Message::new_method_call(
+            self.destination,
+            self.path,
+            Some(INTERFACE),
+            {},
+        )
+        .expect(\"no enough memory\");",
+            if m.in_args.is_empty() { "" } else { "mut " },
+            cstr_literal(&m.name)
+        )
+        .unwrap();
+        if !m.in_args.is_empty() {
+            out.push_str("        {\n            let mut args_ = msg_.iter_append();\n");
+            for a in m.in_args.iter() {
+                ArgKind::of(&a.signature).write_append(
+                    out,
+                    "            ",
+                    &rust_ident(&snake_case(&a.name)),
+                );
+            }
+            out.push_str("        }\n");
+        }
+
+        if !basic_outs {
+            out.push_str("        self.call(msg_).await\n    }\n");
+            continue;
+        }
+        if m.out_args.is_empty() {
+            out.push_str("        self.call(msg_).await?;\n        Ok(())\n    }\n");
+            continue;
+        }
+
+        out.push_str("        let reply_ = self.call(msg_).await?;\n");
+        let names = write_reads(out, "        ", "reply_.iter()", &m.out_args, |e, name| {
+            format!(
+                "{e}.ok_or_else(|| {})?",
+                unexpected_reply_error(&format!("unexpected {name} value"))
+            )
+        });
+        if names.len() == 1 {
+            writeln!(out, "        Ok({})\n    }}", names[0]).unwrap();
+        } else {
+            writeln!(out, "        Ok(({}))\n    }}", names.join(", ")).unwrap();
+        }
+    }
+
+    for p in iface.properties.iter().filter(|x| x.readable) {
+        let fn_name = rust_ident(&snake_case(&p.name));
+        if !fn_names.insert(fn_name.clone()) {
+            return Err(Error::NameCollision(fn_name));
+        }
+
+        let kind = ArgKind::of(&p.signature);
+        writeln!(out, "\n    /// `{}` property (`{}`)", p.name, p.signature).unwrap();
+        writeln!(
+            out,
+            "    pub async fn {fn_name}(&self) -> Result<{}, dbus::Error> {{",
+            kind.out_type().unwrap_or("dbus::Message")
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "        let mut msg_ = dbus::Message::new_method_call(
+            self.destination,
+            self.path,
+            Some(c\"org.freedesktop.DBus.Properties\"),
+            c\"Get\",
+        )
+        .expect(\"no enough memory\");
+        {{
+            let mut args_ = msg_.iter_append();
+            dbus::MessageIterAppendLike::append_cstr(&mut args_, INTERFACE).expect(\"no enough memory\");
+            dbus::MessageIterAppendLike::append_cstr(&mut args_, {}).expect(\"no enough memory\");
+        }}",
+            cstr_literal(&p.name)
+        )
+        .unwrap();
+        if kind.out_type().is_none() {
+            out.push_str("        self.call(msg_).await\n    }\n");
+            continue;
+        }
+
+        writeln!(
+            out,
+            "        let reply_ = self.call(msg_).await?;
+        let mut iter_ = reply_.iter();
+        let value_iter_ = iter_
+            .try_begin_iter_variant_content()
+            .map_err(|_| {})?;
+        {}.ok_or_else(|| {})
+    }}",
+            unexpected_reply_error("property value must be a variant"),
+            kind.read_expr("value_iter_").unwrap(),
+            unexpected_reply_error(&format!("unexpected {} value", p.name))
+        )
+        .unwrap();
+    }
+
+    out.push_str("}\n\n");
+    Ok(())
+}
+
+fn generate_signal(out: &mut String, s: &Signal) {
+    let struct_name = format!("{}Signal", s.name);
+    let borrowing = s
+        .args
+        .iter()
+        .any(|a| ArgKind::of(&a.signature).out_type().is_none());
+    let lt = if borrowing { "<'m>" } else { "" };
+
+    writeln!(out, "/// `{}({})`", s.name, describe_args(&s.args)).unwrap();
+    if s.args.is_empty() {
+        writeln!(out, "pub struct {struct_name};").unwrap();
+    } else {
+        writeln!(out, "pub struct {struct_name}{lt} {{").unwrap();
+        for a in s.args.iter() {
+            let kind = ArgKind::of(&a.signature);
+            match kind.out_type() {
+                Some(t) => {
+                    writeln!(out, "    pub {}: {t},", rust_ident(&snake_case(&a.name))).unwrap()
+                }
+                None => writeln!(
+                    out,
+                    "    /// `{}`\n    pub {}: dbus::MessageIter<'m>,",
+                    a.signature,
+                    rust_ident(&snake_case(&a.name))
+                )
+                .unwrap(),
+            }
+        }
+        out.push_str("}\n");
+    }
+
+    writeln!(out, "impl{lt} {struct_name}{lt} {{").unwrap();
+    writeln!(
+        out,
+        "    pub const MEMBER: &'static core::ffi::CStr = {};\n",
+        cstr_literal(&s.name)
+    )
+    .unwrap();
+    out.push_str("    /// 引数の型が合わなければNone\n");
+    if s.args.is_empty() {
+        out.push_str(
+            "    pub fn read(_msg: &dbus::Message) -> Option<Self> {\n        Some(Self)\n    }\n}\n\n",
+        );
+        return;
+    }
+
+    writeln!(
+        out,
+        "    pub fn read(msg: &{}dbus::Message) -> Option<Self> {{",
+        if borrowing { "'m " } else { "" }
+    )
+    .unwrap();
+    let names = write_reads(out, "        ", "msg.iter()", &s.args, |e, _| {
+        format!("{e}?")
+    });
+    writeln!(
+        out,
+        "        Some(Self {{ {} }})\n    }}\n}}\n",
+        names.join(", ")
+    )
+    .unwrap();
+}
+
+/// 引数を順に読み出すコードを書いて、束縛した変数名を返す
+///
+/// `unwrap`は読み出した`Option`(と引数名)から値を取り出す式を作る(型が合わなかったときに早期リターンさせる)
+fn write_reads(
+    out: &mut String,
+    indent: &str,
+    iter_init: &str,
+    args: &[Arg],
+    unwrap: impl Fn(&str, &str) -> String,
+) -> Vec<String> {
+    let mut names = Vec::with_capacity(args.len());
+
+    writeln!(
+        out,
+        "{indent}let {}iter_ = {iter_init};",
+        if args.len() > 1 { "mut " } else { "" }
+    )
+    .unwrap();
+    for (n, a) in args.iter().enumerate() {
+        let name = rust_ident(&snake_case(&a.name));
+        let is_last = n == args.len() - 1;
+
+        if n > 0 {
+            writeln!(out, "{indent}iter_.next();").unwrap();
+        }
+        match ArgKind::of(&a.signature).read_expr("iter_") {
+            Some(e) => writeln!(out, "{indent}let {name} = {};", unwrap(&e, &a.name)).unwrap(),
+            None => {
+                // 最後ならそのまま渡す
+                writeln!(
+                    out,
+                    "{indent}let {name} = iter_{};",
+                    if is_last { "" } else { ".clone()" }
+                )
+                .unwrap();
+            }
+        }
+        names.push(name);
+    }
+
+    names
+}
+
+enum ArgKind {
+    String,
+    ObjectPath,
+    U32,
+    Bool,
+    VariantDict,
+    Other,
+}
+impl ArgKind {
+    fn of(signature: &str) -> Self {
+        match signature {
+            "s" => Self::String,
+            "o" => Self::ObjectPath,
+            "u" => Self::U32,
+            "b" => Self::Bool,
+            "a{sv}" => Self::VariantDict,
+            _ => Self::Other,
+        }
+    }
+
+    const fn in_type(&self) -> &'static str {
+        match self {
+            Self::String | Self::ObjectPath => "&core::ffi::CStr",
+            Self::U32 => "u32",
+            Self::Bool => "bool",
+            Self::VariantDict => {
+                "impl FnOnce(&mut dbus::MessageIterAppendContainer<'_, dbus::MessageIterAppend<'_>>)"
+            }
+            Self::Other => "impl FnOnce(&mut dbus::MessageIterAppend<'_>)",
+        }
+    }
+
+    const fn out_type(&self) -> Option<&'static str> {
+        match self {
+            Self::String => Some("std::ffi::CString"),
+            Self::ObjectPath => Some("dbus::ObjectPath"),
+            Self::U32 => Some("u32"),
+            Self::Bool => Some("bool"),
+            Self::VariantDict | Self::Other => None,
+        }
+    }
+
+    fn write_append(&self, out: &mut String, indent: &str, value: &str) {
+        match self {
+            Self::String => writeln!(
+                out,
+                "{indent}dbus::MessageIterAppendLike::append_cstr(&mut args_, {value}).expect(\"no enough memory\");"
+            ),
+            Self::ObjectPath => writeln!(
+                out,
+                "{indent}dbus::MessageIterAppendLike::append_object_path(&mut args_, {value}).expect(\"no enough memory\");"
+            ),
+            Self::U32 => writeln!(
+                out,
+                "{indent}dbus::MessageIterAppendLike::append_u32(&mut args_, {value}).expect(\"no enough memory\");"
+            ),
+            Self::Bool => writeln!(
+                out,
+                "{indent}dbus::MessageIterAppendLike::append_bool(&mut args_, {value}).expect(\"no enough memory\");"
+            ),
+            Self::VariantDict => writeln!(
+                out,
+                "{indent}let mut dict_ = dbus::MessageIterAppendLike::open_array_container(&mut args_, c\"{{sv}}\").expect(\"no enough memory\");
+{indent}{value}(&mut dict_);
+{indent}dict_.close().expect(\"no enough memory\");"
+            ),
+            Self::Other => writeln!(out, "{indent}{value}(&mut args_);"),
+        }
+        .unwrap();
+    }
+
+    /// 値を読み出す式(`Option<out_type>`になる)
+    fn read_expr(&self, iter: &str) -> Option<String> {
+        Some(match self {
+            Self::String => format!("{iter}.try_get_cstr().ok().map(core::ffi::CStr::to_owned)"),
+            Self::ObjectPath => {
+                format!("{iter}.try_get_object_path().ok().map(dbus::ObjectPath::from)")
+            }
+            Self::U32 => format!("{iter}.try_get_u32().ok()"),
+            Self::Bool => format!("{iter}.try_get_bool().ok()"),
+            Self::VariantDict | Self::Other => return None,
+        })
+    }
+}
+
+fn describe_args(args: &[Arg]) -> String {
+    args.iter()
+        .map(|a| format!("{} {}", a.signature, a.name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn cstr_literal(s: &str) -> String {
+    format!("c{s:?}")
+}
+
+/// 返答の中身が想定と違っていたときにプロキシから返すエラー
+fn unexpected_reply_error(message: &str) -> String {
+    format!(
+        "dbus::Error::new_const(dbus::ERROR_INVALID_SIGNATURE, {})",
+        cstr_literal(message)
+    )
+}
+
+/// `OpenFile` -> `open_file`, `GetURIs` -> `get_uris`
+fn snake_case(name: &str) -> String {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut out = String::with_capacity(name.len() + 4);
+
+    for (n, &c) in chars.iter().enumerate() {
+        if c == '-' {
+            out.push('_');
+            continue;
+        }
+        if c.is_ascii_uppercase() && n > 0 {
+            let prev = chars[n - 1];
+            let next_lower = chars.get(n + 1).is_some_and(|x| x.is_ascii_lowercase());
+            // 略語の複数形(URIs)は区切らない
+            let plural_suffix = chars.get(n + 1) == Some(&'s') && n + 2 == chars.len();
+            if prev.is_ascii_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_ascii_uppercase() && next_lower && !plural_suffix)
+            {
+                out.push('_');
+            }
+        }
+
+        out.push(c.to_ascii_lowercase());
+    }
+
+    out
+}
+
+fn rust_ident(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern",
+        "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+        "mut", "pub", "ref", "return", "static", "struct", "trait", "true", "type", "unsafe",
+        "use", "where", "while", "abstract", "become", "box", "do", "final", "macro", "override",
+        "priv", "try", "typeof", "unsized", "virtual", "yield",
+    ];
+
+    // Note: これらはraw identifierにもできない
+    const NON_RAW_KEYWORDS: &[&str] = &["self", "Self", "super", "crate", "_"];
+
+    if NON_RAW_KEYWORDS.contains(&name) {
+        format!("{name}_")
+    } else if KEYWORDS.contains(&name) {
+        format!("r#{name}")
+    } else {
+        name.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN" "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="org.example.Other">
+    <method name="Ignored"/>
+  </interface>
+  <interface name="org.example.Test">
+    <method name="GetURIs">
+      <!-- comments are allowed anywhere -->
+      <arg name="self" type="s" direction="in"/>
+      <arg name="uris" type="s" direction="out"/>
+      <arg name="count" type="u" direction="out"/>
+    </method>
+    <method name="Notify">
+      <arg name="options" type="a{sv}"/>
+    </method>
+    <property name="version" type="u" access="read"/>
+    <signal name="Response">
+      <arg name="type" type="u"/>
+      <arg name="results" type="a{sv}"/>
+    </signal>
+    <signal name="Ping"/>
+  </interface>
+</node>
+"#;
+
+    #[test]
+    fn snake_case_names() {
+        assert_eq!(snake_case("OpenFile"), "open_file");
+        assert_eq!(snake_case("GetURIs"), "get_uris");
+        assert_eq!(snake_case("URI"), "uri");
+        assert_eq!(snake_case("ReadOne"), "read_one");
+        assert_eq!(snake_case("HTTPRequest"), "http_request");
+        assert_eq!(snake_case("Version2Info"), "version2_info");
+        assert_eq!(snake_case("color-scheme"), "color_scheme");
+        assert_eq!(snake_case("handle_token"), "handle_token");
+    }
+
+    #[test]
+    fn rust_ident_escapes_keywords() {
+        assert_eq!(rust_ident("name"), "name");
+        assert_eq!(rust_ident("type"), "r#type");
+        assert_eq!(rust_ident("async"), "r#async");
+        assert_eq!(rust_ident("self"), "self_");
+        assert_eq!(rust_ident("crate"), "crate_");
+        assert_eq!(rust_ident("super"), "super_");
+    }
+
+    #[test]
+    fn parse_picks_requested_interface() {
+        let iface = parse_interface(DOCUMENT, "org.example.Test").unwrap();
+
+        assert_eq!(iface.methods.len(), 2);
+        assert_eq!(iface.methods[0].in_args.len(), 1);
+        assert_eq!(iface.methods[0].out_args.len(), 2);
+        // Note: directionの省略はin
+        assert_eq!(iface.methods[1].in_args.len(), 1);
+        assert_eq!(iface.signals.len(), 2);
+        assert_eq!(iface.properties.len(), 1);
+        assert!(matches!(
+            parse_interface(DOCUMENT, "org.example.Missing"),
+            Err(Error::InterfaceNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn generated_readers_do_not_panic() {
+        let code = generate_interface(DOCUMENT, "org.example.Test").unwrap();
+
+        assert!(
+            code.contains(
+                "pub async fn get_uris(\n        &self,\n        self_: &core::ffi::CStr,"
+            )
+        );
+        assert!(code.contains(") -> Result<(std::ffi::CString, u32), dbus::Error> {"));
+        assert!(code.contains("let count = iter_.try_get_u32().ok().ok_or_else(|| dbus::Error::new_const(dbus::ERROR_INVALID_SIGNATURE, c\"unexpected count value\"))?;"));
+        assert!(code.contains("pub async fn version(&self) -> Result<u32, dbus::Error> {"));
+        assert!(code.contains(".try_begin_iter_variant_content()\n            .map_err(|_| "));
+        assert!(code.contains("pub struct ResponseSignal<'m> {\n    pub r#type: u32,"));
+        assert!(code.contains("pub fn read(msg: &'m dbus::Message) -> Option<Self> {"));
+        assert!(code.contains("let r#type = iter_.try_get_u32().ok()?;"));
+        assert!(code.contains("pub fn read(_msg: &dbus::Message) -> Option<Self> {"));
+        assert!(!code.contains(".expect(\"unexpected"));
+        assert!(!code.contains("r#self"));
+    }
+
+    #[test]
+    fn name_collision_is_reported() {
+        let iface = Interface {
+            name: String::from("org.example.Collide"),
+            methods: vec![Method {
+                name: String::from("Version"),
+                in_args: Vec::new(),
+                out_args: Vec::new(),
+            }],
+            signals: Vec::new(),
+            properties: vec![Property {
+                name: String::from("version"),
+                signature: String::from("u"),
+                readable: true,
+            }],
+        };
+
+        assert!(matches!(generate(&iface), Err(Error::NameCollision(x)) if x == "version"));
+    }
+}
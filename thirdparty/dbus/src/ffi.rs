@@ -89,6 +89,7 @@ pub const DBUS_MESSAGE_TYPE_SIGNAL: core::ffi::c_int = 4;
 
 pub const DBUS_TYPE_INVALID: core::ffi::c_int = 0;
 pub const DBUS_TYPE_BOOLEAN: core::ffi::c_int = b'b' as _;
+pub const DBUS_TYPE_BYTE: core::ffi::c_int = b'y' as _;
 pub const DBUS_TYPE_STRING: core::ffi::c_int = b's' as _;
 pub const DBUS_TYPE_OBJECT_PATH: core::ffi::c_int = b'o' as _;
 pub const DBUS_TYPE_ARRAY: core::ffi::c_int = b'a' as _;
@@ -96,6 +97,16 @@ pub const DBUS_TYPE_VARIANT: core::ffi::c_int = b'v' as _;
 pub const DBUS_TYPE_DICT_ENTRY: core::ffi::c_int = b'e' as _;
 pub const DBUS_TYPE_STRUCT: core::ffi::c_int = b'r' as _;
 pub const DBUS_TYPE_UINT: core::ffi::c_int = b'u' as _;
+pub const DBUS_TYPE_DOUBLE: core::ffi::c_int = b'd' as _;
+
+pub const DBUS_NAME_FLAG_ALLOW_REPLACEMENT: core::ffi::c_uint = 0x1;
+pub const DBUS_NAME_FLAG_REPLACE_EXISTING: core::ffi::c_uint = 0x2;
+pub const DBUS_NAME_FLAG_DO_NOT_QUEUE: core::ffi::c_uint = 0x4;
+
+pub const DBUS_REQUEST_NAME_REPLY_PRIMARY_OWNER: core::ffi::c_int = 1;
+pub const DBUS_REQUEST_NAME_REPLY_IN_QUEUE: core::ffi::c_int = 2;
+pub const DBUS_REQUEST_NAME_REPLY_EXISTS: core::ffi::c_int = 3;
+pub const DBUS_REQUEST_NAME_REPLY_ALREADY_OWNER: core::ffi::c_int = 4;
 
 pub type DBusWatchFlags = core::ffi::c_uint;
 pub const DBUS_WATCH_READABLE: DBusWatchFlags = 1 << 0;
@@ -151,6 +162,17 @@ unsafe extern "C" {
     pub unsafe fn dbus_bus_get_unique_name(
         connection: *mut DBusConnection,
     ) -> *const core::ffi::c_char;
+    pub unsafe fn dbus_bus_request_name(
+        connection: *mut DBusConnection,
+        name: *const core::ffi::c_char,
+        flags: core::ffi::c_uint,
+        error: *mut DBusError,
+    ) -> core::ffi::c_int;
+    pub unsafe fn dbus_bus_add_match(
+        connection: *mut DBusConnection,
+        rule: *const core::ffi::c_char,
+        error: *mut DBusError,
+    );
 
     pub unsafe fn dbus_message_new_method_call(
         destination: *const core::ffi::c_char,
@@ -158,6 +180,18 @@ unsafe extern "C" {
         iface: *const core::ffi::c_char,
         method: *const core::ffi::c_char,
     ) -> *mut DBusMessage;
+    pub unsafe fn dbus_message_new_method_return(method_call: *mut DBusMessage)
+    -> *mut DBusMessage;
+    pub unsafe fn dbus_message_new_error(
+        reply_to: *mut DBusMessage,
+        error_name: *const core::ffi::c_char,
+        error_message: *const core::ffi::c_char,
+    ) -> *mut DBusMessage;
+    pub unsafe fn dbus_message_new_signal(
+        path: *const core::ffi::c_char,
+        iface: *const core::ffi::c_char,
+        name: *const core::ffi::c_char,
+    ) -> *mut DBusMessage;
     pub unsafe fn dbus_message_ref(message: *mut DBusMessage) -> *mut DBusMessage;
     pub unsafe fn dbus_message_unref(message: *mut DBusMessage);
     pub unsafe fn dbus_message_get_type(message: *mut DBusMessage) -> core::ffi::c_int;
@@ -209,6 +243,8 @@ unsafe extern "C" {
     pub unsafe fn dbus_message_get_member(message: *mut DBusMessage) -> *const core::ffi::c_char;
     pub unsafe fn dbus_message_get_signature(message: *mut DBusMessage)
     -> *const core::ffi::c_char;
+    pub unsafe fn dbus_message_get_sender(message: *mut DBusMessage) -> *const core::ffi::c_char;
+    pub unsafe fn dbus_message_get_no_reply(message: *mut DBusMessage) -> dbus_bool_t;
     pub unsafe fn dbus_message_get_serial(message: *mut DBusMessage) -> u32;
     pub unsafe fn dbus_message_get_reply_serial(message: *mut DBusMessage) -> u32;
 
@@ -224,6 +260,11 @@ unsafe extern "C" {
     pub unsafe fn dbus_error_init(error: *mut DBusError);
     pub unsafe fn dbus_error_free(error: *mut DBusError);
     pub unsafe fn dbus_error_is_set(error: *const DBusError) -> dbus_bool_t;
+    pub unsafe fn dbus_set_error_const(
+        error: *mut DBusError,
+        name: *const core::ffi::c_char,
+        message: *const core::ffi::c_char,
+    );
 
     pub unsafe fn dbus_free(memory: *mut core::ffi::c_void);
 }
@@ -295,6 +295,7 @@ pub fn read_interface_tag_content<'a>(
                 });
             }
             quick_xml::events::Event::Text(x) if x.trim_ascii().is_empty() => (),
+            quick_xml::events::Event::Comment(_) => (),
             e => {
                 return Err(ReadError::UnexpectedElement {
                     phase: "interface",
@@ -349,6 +350,7 @@ pub fn read_method_tag_content<'a>(
                 });
             }
             quick_xml::events::Event::Text(x) if x.trim_ascii().is_empty() => (),
+            quick_xml::events::Event::Comment(_) => (),
             e => {
                 return Err(ReadError::UnexpectedElement {
                     phase: "method",
@@ -403,6 +405,7 @@ pub fn read_signal_tag_content<'a>(
                 });
             }
             quick_xml::events::Event::Text(x) if x.trim_ascii().is_empty() => (),
+            quick_xml::events::Event::Comment(_) => (),
             e => {
                 return Err(ReadError::UnexpectedElement {
                     phase: "signal",
@@ -23,7 +23,10 @@ pub use self::ffi::DBUS_MESSAGE_TYPE_SIGNAL as MESSAGE_TYPE_SIGNAL;
 pub use self::ffi::DBusBusType as BusType;
 
 pub use self::ffi::DBUS_TYPE_ARRAY as TYPE_ARRAY;
+pub use self::ffi::DBUS_TYPE_BOOLEAN as TYPE_BOOLEAN;
+pub use self::ffi::DBUS_TYPE_BYTE as TYPE_BYTE;
 pub use self::ffi::DBUS_TYPE_DICT_ENTRY as TYPE_DICT_ENTRY;
+pub use self::ffi::DBUS_TYPE_DOUBLE as TYPE_DOUBLE;
 pub use self::ffi::DBUS_TYPE_INVALID as TYPE_INVALID;
 pub use self::ffi::DBUS_TYPE_OBJECT_PATH as TYPE_OBJECT_PATH;
 pub use self::ffi::DBUS_TYPE_STRING as TYPE_STRING;
@@ -31,6 +34,22 @@ pub use self::ffi::DBUS_TYPE_STRUCT as TYPE_STRUCT;
 pub use self::ffi::DBUS_TYPE_UINT as TYPE_UINT;
 pub use self::ffi::DBUS_TYPE_VARIANT as TYPE_VARIANT;
 
+pub const ERROR_INVALID_SIGNATURE: &CStr = c"org.freedesktop.DBus.Error.InvalidSignature";
+
+pub use self::ffi::DBUS_REQUEST_NAME_REPLY_ALREADY_OWNER as REQUEST_NAME_REPLY_ALREADY_OWNER;
+pub use self::ffi::DBUS_REQUEST_NAME_REPLY_EXISTS as REQUEST_NAME_REPLY_EXISTS;
+pub use self::ffi::DBUS_REQUEST_NAME_REPLY_IN_QUEUE as REQUEST_NAME_REPLY_IN_QUEUE;
+pub use self::ffi::DBUS_REQUEST_NAME_REPLY_PRIMARY_OWNER as REQUEST_NAME_REPLY_PRIMARY_OWNER;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct NameFlags : core::ffi::c_uint {
+        const ALLOW_REPLACEMENT = ffi::DBUS_NAME_FLAG_ALLOW_REPLACEMENT;
+        const REPLACE_EXISTING = ffi::DBUS_NAME_FLAG_REPLACE_EXISTING;
+        const DO_NOT_QUEUE = ffi::DBUS_NAME_FLAG_DO_NOT_QUEUE;
+    }
+}
+
 bitflags! {
     #[derive(Debug, Clone, Copy)]
     pub struct WatchFlags : ffi::DBusWatchFlags {
@@ -68,6 +87,16 @@ impl Error {
         Self(unsafe { ptr.assume_init() })
     }
 
+    /// 名前とメッセージを指定してエラーを作る(受け取った値が想定外だったときなど、こちら側で失敗を報告するのに使う)
+    pub fn new_const(name: &'static CStr, message: &'static CStr) -> Self {
+        let mut e = Self::new();
+        unsafe {
+            ffi::dbus_set_error_const(&mut e.0, name.as_ptr(), message.as_ptr());
+        }
+
+        e
+    }
+
     #[inline]
     pub fn reset(&mut self) {
         unsafe {
@@ -168,6 +197,28 @@ impl Connection {
         }
     }
 
+    /// バス名を要求する(結果はREQUEST_NAME_REPLY_*)
+    ///
+    /// Note: 応答が来るまでブロックする
+    pub fn request_name(&self, name: &CStr, flags: NameFlags) -> Result<core::ffi::c_int, Error> {
+        let mut e = Error::new();
+        let r = unsafe {
+            ffi::dbus_bus_request_name(self.0.as_ptr(), name.as_ptr(), flags.bits(), e.as_mut())
+        };
+
+        if r < 0 { Err(e) } else { Ok(r) }
+    }
+
+    /// ブロードキャストされるシグナルを受け取るためのマッチルールを登録する
+    pub fn add_match(&self, rule: &CStr) -> Result<(), Error> {
+        let mut e = Error::new();
+        unsafe {
+            ffi::dbus_bus_add_match(self.0.as_ptr(), rule.as_ptr(), e.as_mut());
+        }
+
+        if e.is_set() { Err(e) } else { Ok(()) }
+    }
+
     pub fn send_with_serial(&self, message: &mut Message) -> Option<u32> {
         let mut serial = MaybeUninit::uninit();
         let r = unsafe {
@@ -360,6 +411,32 @@ impl Message {
         .map(Self)
     }
 
+    #[inline]
+    pub fn new_method_return(method_call: &Message) -> Option<Self> {
+        NonNull::new(unsafe { ffi::dbus_message_new_method_return(method_call.0.as_ptr()) })
+            .map(Self)
+    }
+
+    #[inline]
+    pub fn new_error(reply_to: &Message, error_name: &CStr, error_message: &CStr) -> Option<Self> {
+        NonNull::new(unsafe {
+            ffi::dbus_message_new_error(
+                reply_to.0.as_ptr(),
+                error_name.as_ptr(),
+                error_message.as_ptr(),
+            )
+        })
+        .map(Self)
+    }
+
+    #[inline]
+    pub fn new_signal(path: &CStr, iface: &CStr, name: &CStr) -> Option<Self> {
+        NonNull::new(unsafe {
+            ffi::dbus_message_new_signal(path.as_ptr(), iface.as_ptr(), name.as_ptr())
+        })
+        .map(Self)
+    }
+
     #[inline]
     pub fn try_get_error(&self) -> Option<Error> {
         let mut e = Error::new();
@@ -410,6 +487,22 @@ impl Message {
         }
     }
 
+    #[inline]
+    pub fn sender(&self) -> Option<&CStr> {
+        let p = unsafe { ffi::dbus_message_get_sender(self.0.as_ptr()) };
+        if p.is_null() {
+            None
+        } else {
+            Some(unsafe { CStr::from_ptr(p) })
+        }
+    }
+
+    /// 呼び出し側が応答を必要としていないか
+    #[inline(always)]
+    pub fn no_reply(&self) -> bool {
+        unsafe { ffi::dbus_message_get_no_reply(self.0.as_ptr()) != 0 }
+    }
+
     #[inline]
     pub fn signature(&self) -> &CStr {
         unsafe { CStr::from_ptr(ffi::dbus_message_get_signature(self.0.as_ptr())) }
@@ -450,6 +543,16 @@ impl Message {
 pub struct MessageIter<'m>(UnsafeCell<ffi::DBusMessageIter>, PhantomData<&'m Message>);
 unsafe impl Sync for MessageIter<'_> {}
 unsafe impl Send for MessageIter<'_> {}
+impl Clone for MessageIter<'_> {
+    /// 現在位置を複製する(読み取り用のイテレータは値としてコピーしてよい)
+    #[inline]
+    fn clone(&self) -> Self {
+        Self(
+            UnsafeCell::new(unsafe { core::ptr::read(self.0.get()) }),
+            PhantomData,
+        )
+    }
+}
 impl MessageIter<'_> {
     pub fn signature(&self) -> Option<OwnedStr> {
         NonNull::new(unsafe { ffi::dbus_message_iter_get_signature(self.0.get()) }).map(OwnedStr)
@@ -504,6 +607,48 @@ impl MessageIter<'_> {
         }
     }
 
+    #[inline(always)]
+    pub fn try_get_u8(&self) -> Result<u8, core::ffi::c_int> {
+        match self.arg_type() {
+            TYPE_BYTE => {
+                let mut sink = MaybeUninit::<u8>::uninit();
+                unsafe {
+                    self.get_value_basic(sink.as_mut_ptr() as _);
+                    Ok(sink.assume_init())
+                }
+            }
+            v => Err(v),
+        }
+    }
+
+    #[inline(always)]
+    pub fn try_get_f64(&self) -> Result<f64, core::ffi::c_int> {
+        match self.arg_type() {
+            TYPE_DOUBLE => {
+                let mut sink = MaybeUninit::<f64>::uninit();
+                unsafe {
+                    self.get_value_basic(sink.as_mut_ptr() as _);
+                    Ok(sink.assume_init())
+                }
+            }
+            v => Err(v),
+        }
+    }
+
+    #[inline(always)]
+    pub fn try_get_bool(&self) -> Result<bool, core::ffi::c_int> {
+        match self.arg_type() {
+            TYPE_BOOLEAN => {
+                let mut sink = MaybeUninit::<ffi::dbus_bool_t>::uninit();
+                unsafe {
+                    self.get_value_basic(sink.as_mut_ptr() as _);
+                    Ok(sink.assume_init() != 0)
+                }
+            }
+            v => Err(v),
+        }
+    }
+
     #[inline]
     pub unsafe fn get_cstr_unchecked(&self) -> &CStr {
         let mut sink = MaybeUninit::<*const core::ffi::c_char>::uninit();
@@ -590,6 +735,11 @@ pub trait MessageIterAppendLike {
         unsafe { self.append_basic(ffi::DBUS_TYPE_STRING, &value.as_ptr() as *const _ as _) }
     }
 
+    #[inline(always)]
+    fn append_object_path(&mut self, value: &CStr) -> Result<(), NotEnoughMemory> {
+        unsafe { self.append_basic(ffi::DBUS_TYPE_OBJECT_PATH, &value.as_ptr() as *const _ as _) }
+    }
+
     #[inline(always)]
     fn append_bool(&mut self, value: bool) -> Result<(), NotEnoughMemory> {
         let v1: ffi::dbus_bool_t = if value { 1 } else { 0 };
@@ -729,6 +879,38 @@ impl<'p, P: MessageIterAppendLike + ?Sized + 'p> MessageIterAppendLike
     }
 }
 
+/// 返答を非同期に待てる接続(dbus-codegenで生成したプロキシはこれを通して呼び出す)
+///
+/// Note: メッセージの受信と振り分けはイベントループ側の仕事なので、そちらで実装する
+pub trait AsyncConnection {
+    fn connection(&self) -> &Connection;
+    fn wait_for_reply(&self, serial: u32) -> impl Future<Output = Message>;
+}
+
+#[repr(transparent)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ObjectPath(std::ffi::CString);
+impl ObjectPath {
+    /// # Safety
+    /// 中身がD-Busのオブジェクトパスとして正しい形式であること
+    #[inline(always)]
+    pub const unsafe fn from_cstring_unchecked(path: std::ffi::CString) -> Self {
+        Self(path)
+    }
+
+    #[inline(always)]
+    pub fn as_c_str(&self) -> &CStr {
+        self.0.as_c_str()
+    }
+}
+impl From<&CStr> for ObjectPath {
+    /// Note: MessageIter::try_get_object_pathで得たもの(libdbusが検証済み)を想定している
+    #[inline(always)]
+    fn from(value: &CStr) -> Self {
+        Self(value.into())
+    }
+}
+
 #[repr(transparent)]
 pub struct PendingCall(NonNull<ffi::DBusPendingCall>);
 unsafe impl Sync for PendingCall {}
@@ -14,6 +14,11 @@ pub use freetype2::FT_Bool as Bool;
 pub use freetype2::FT_GlyphSlotRec as GlyphSlotRec;
 pub use freetype2::FT_Vector as Vector;
 
+/// GlyphSlotRec::bitmap.pixel_modeの値 1ピクセル8bitのグレースケール
+pub const PIXEL_MODE_GRAY: u8 = FT_PIXEL_MODE_GRAY as _;
+/// GlyphSlotRec::bitmap.pixel_modeの値 1ピクセル32bitのBGRA(premultiplied) カラー絵文字など
+pub const PIXEL_MODE_BGRA: u8 = FT_PIXEL_MODE_BGRA as _;
+
 #[repr(transparent)]
 pub struct FreeType(core::ptr::NonNull<FT_LibraryRec>);
 impl Drop for FreeType {
@@ -133,6 +138,7 @@ bitflags! {
     #[derive(Debug, Clone, Copy)]
     pub struct LoadFlags : i32 {
         const DEFAULT = FT_LOAD_DEFAULT;
+        const COLOR = FT_LOAD_COLOR;
     }
 }
 
@@ -177,6 +183,32 @@ impl Face {
         unsafe { (*self.0.size).metrics.height as f64 / 64.0 }
     }
 
+    /// アウトラインを持っている(任意のサイズにできる)か ビットマップのみのフェイス(カラー絵文字等)ではfalse
+    pub const fn is_scalable(&self) -> bool {
+        (self.0.face_flags & FT_FACE_FLAG_SCALABLE as FT_Long) != 0
+    }
+
+    /// ビットマップのみのフェイスが持っているストライクの大きさ(pixels)
+    pub fn fixed_size_pixels(&self) -> impl Iterator<Item = f32> + '_ {
+        let sizes = if self.0.available_sizes.is_null() {
+            &[][..]
+        } else {
+            unsafe {
+                core::slice::from_raw_parts(self.0.available_sizes, self.0.num_fixed_sizes as _)
+            }
+        };
+
+        sizes.iter().map(|s| s.y_ppem as f32 / 64.0)
+    }
+
+    /// fixed_size_pixelsのstrike_index番目のストライクを選ぶ
+    pub fn select_size(&mut self, strike_index: usize) -> Result<(), Error> {
+        match unsafe { FT_Select_Size(self as *mut _ as _, strike_index as _) } {
+            0 => Ok(()),
+            r => Err(Error(r)),
+        }
+    }
+
     pub fn set_char_size(
         &mut self,
         char_width: FT_F26Dot6,
@@ -235,6 +267,12 @@ impl Face {
         Ok(())
     }
 
+    /// 0のときはグリフなし(.notdef)
+    #[inline]
+    pub fn char_index(&mut self, charcode: FT_ULong) -> FT_UInt {
+        unsafe { FT_Get_Char_Index(self as *mut _ as _, charcode) }
+    }
+
     pub const fn glyph_slot(&self) -> Option<&FT_GlyphSlotRec> {
         unsafe { self.0.glyph.as_ref() }
     }
@@ -55,6 +55,19 @@ impl Buffer {
         }
     }
 
+    /// contentの一部(byte範囲)だけを追加する 前後はシェーピングのコンテキストとして使われる
+    pub fn add_range(&mut self, content: &str, range: core::ops::Range<usize>) {
+        unsafe {
+            ffi::hb_buffer_add_utf8(
+                self as *mut _ as _,
+                content.as_ptr() as _,
+                content.len() as _,
+                range.start as _,
+                (range.end - range.start) as _,
+            )
+        }
+    }
+
     pub fn guess_segment_properties(&mut self) {
         unsafe { ffi::hb_buffer_guess_segment_properties(self as *mut _ as _) }
     }
@@ -199,6 +199,7 @@ unsafe extern "C" {
 #[repr(u32)]
 pub enum NSSearchPathDirectory {
     CachesDirectory = 13,
+    ApplicationSupportDirectory = 14,
 }
 
 bitflags! {
@@ -0,0 +1,27 @@
+const INTERFACES: &[(&str, &str)] = &[
+    ("org.freedesktop.portal.Documents", "documents.rs"),
+    ("org.freedesktop.portal.FileChooser", "file_chooser.rs"),
+    ("org.freedesktop.portal.Request", "request.rs"),
+    ("org.freedesktop.portal.Settings", "settings.rs"),
+];
+
+fn main() {
+    let project_root = std::path::PathBuf::from(
+        std::env::var_os("CARGO_MANIFEST_DIR").expect("no CARGO_MANIFEST_DIR"),
+    );
+    let out_dir = std::path::PathBuf::from(std::env::var_os("OUT_DIR").expect("no OUT_DIR"));
+
+    for &(interface_name, out_file_name) in INTERFACES {
+        let document_path = project_root
+            .join("interfaces")
+            .join(format!("{interface_name}.xml"));
+        println!("cargo:rerun-if-changed={}", document_path.display());
+
+        let document = std::fs::read_to_string(&document_path).unwrap_or_else(|e| {
+            panic!("Failed to read {}: {e:?}", document_path.display());
+        });
+        let code = dbus_codegen::generate_interface(&document, interface_name)
+            .unwrap_or_else(|e| panic!("Failed to generate {interface_name}: {e}"));
+        std::fs::write(out_dir.join(out_file_name), code).expect("Failed to write generated code");
+    }
+}
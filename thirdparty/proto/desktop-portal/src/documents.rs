@@ -0,0 +1,65 @@
+use dbus::MessageIterAppendLike;
+
+// Note: Proxy(GetMountPoint/GetHostPaths/version)はinterfaces/org.freedesktop.portal.Documents.xmlからbuild.rsで生成している
+include!(concat!(env!("OUT_DIR"), "/documents.rs"));
+
+pub const BUS_NAME: &core::ffi::CStr = c"org.freedesktop.portal.Documents";
+pub const OBJECT_PATH: &core::ffi::CStr = c"/org/freedesktop/portal/documents";
+
+/// GetHostPathsが使えるようになったバージョン
+pub const GET_HOST_PATHS_SINCE: u32 = 5;
+
+/// GetHostPathsのdoc_ids(as)を書き込む
+pub fn append_doc_ids<'s>(
+    args: &mut dbus::MessageIterAppend<'_>,
+    doc_ids: impl IntoIterator<Item = &'s core::ffi::CStr>,
+) {
+    let mut array = args.open_array_container(c"s").expect("no enough memory");
+    for x in doc_ids {
+        array.append_cstr(x).expect("no enough memory");
+    }
+    array.close().expect("no enough memory");
+}
+
+/// バイト列(ay)を読む 末尾のNULは取り除く
+pub fn read_byte_string(iter: &mut dbus::MessageIter<'_>) -> Option<Vec<u8>> {
+    let mut bytes_iter = iter.try_begin_iter_array_content().ok()?;
+    let mut bytes = Vec::new();
+    while let Ok(b) = bytes_iter.try_get_u8() {
+        bytes.push(b);
+        bytes_iter.next();
+    }
+    if bytes.last() == Some(&0) {
+        bytes.pop();
+    }
+
+    Some(bytes)
+}
+
+/// GetMountPointの戻り値を読む
+pub fn read_mount_point(reply: &dbus::Message) -> Option<Vec<u8>> {
+    read_byte_string(&mut reply.iter())
+}
+
+/// GetHostPathsの戻り値(a{say})を(doc_id, ホストのパス)の組にして読む
+pub fn read_host_paths(reply: &dbus::Message) -> Vec<(std::ffi::CString, Vec<u8>)> {
+    let mut iter = reply.iter();
+    let Ok(mut entries) = iter.try_begin_iter_array_content() else {
+        return Vec::new();
+    };
+
+    let mut paths = Vec::new();
+    while let Ok(mut entry) = entries.try_begin_iter_dict_entry_content() {
+        if let Ok(doc_id) = entry.try_get_cstr() {
+            let doc_id = doc_id.to_owned();
+            entry.next();
+            if let Some(path) = read_byte_string(&mut entry) {
+                paths.push((doc_id, path));
+            }
+        }
+
+        entries.next();
+    }
+
+    paths
+}
@@ -1,63 +1,21 @@
 use dbus::MessageIterAppendLike;
 
-use crate::ObjectPath;
-
-pub fn get_version(dbus: &dbus::Connection) -> u32 {
-    dbus_proto::properties_get(
-        dbus,
-        Some(c"org.freedesktop.portal.Desktop"),
-        c"/org/freedesktop/portal/desktop",
-        c"org.freedesktop.portal.FileChooser",
-        c"version",
-    )
-}
-
-pub fn read_get_version_reply(msg: dbus::Message) -> Result<u32, dbus::Error> {
-    if let Some(e) = msg.try_get_error() {
-        return Err(e);
-    }
-
-    let mut reply_iter = msg.iter();
-    Ok(reply_iter
-        .try_begin_iter_variant_content()
-        .expect("property must returns variant")
-        .try_get_u32()
-        .expect("unexpected version value"))
-}
-
-/// https://flatpak.github.io/xdg-desktop-portal/docs/doc-org.freedesktop.portal.FileChooser.html#org-freedesktop-portal-filechooser-openfile
-pub fn open_file(
-    dbus: &dbus::Connection,
-    parent_window: Option<&core::ffi::CStr>,
-    title: &core::ffi::CStr,
-    options_builder: impl FnOnce(OpenFileOptionsAppender),
-) -> u32 {
-    let mut msg = dbus::Message::new_method_call(
-        Some(c"org.freedesktop.portal.Desktop"),
-        c"/org/freedesktop/portal/desktop",
-        Some(c"org.freedesktop.portal.FileChooser"),
-        c"OpenFile",
-    )
-    .unwrap();
-    let mut msg_args_appender = msg.iter_append();
-    msg_args_appender
-        .append_cstr(parent_window.unwrap_or(c""))
-        .expect("no enough memory");
-    msg_args_appender
-        .append_cstr(title)
-        .expect("no enough memory");
-    let mut options_appender = msg_args_appender.open_array_container(c"{sv}").unwrap();
-    options_builder(OpenFileOptionsAppender(&mut options_appender));
-    options_appender.close().expect("no enough memory");
-
-    dbus.send_with_serial(&mut msg).expect("no enough memory")
-}
+// Note: Proxy(OpenFile/SaveFile/SaveFiles/version)はinterfaces/org.freedesktop.portal.FileChooser.xmlからbuild.rsで生成している
+include!(concat!(env!("OUT_DIR"), "/file_chooser.rs"));
 
+/// OpenFileのoptions(a{sv})に書き込む
 #[repr(transparent)]
-pub struct OpenFileOptionsAppender<'a, 'm>(
-    &'a mut dbus::MessageIterAppendContainer<'m, dbus::MessageIterAppend<'m>>,
+pub struct OpenFileOptionsAppender<'a, 'p, 'm>(
+    &'a mut dbus::MessageIterAppendContainer<'p, dbus::MessageIterAppend<'m>>,
 );
-impl OpenFileOptionsAppender<'_, '_> {
+impl<'a, 'p, 'm> OpenFileOptionsAppender<'a, 'p, 'm> {
+    #[inline(always)]
+    pub const fn new(
+        options: &'a mut dbus::MessageIterAppendContainer<'p, dbus::MessageIterAppend<'m>>,
+    ) -> Self {
+        Self(options)
+    }
+
     pub fn append_handle_token(&mut self, value: &core::ffi::CStr) {
         let mut dict_appender = self.0.open_dict_entry_container().unwrap();
         dict_appender.append_cstr(c"handle_token").unwrap();
@@ -72,6 +30,14 @@ impl OpenFileOptionsAppender<'_, '_> {
         dict_appender.close().unwrap();
     }
 
+    /// ファイルではなくフォルダを選ばせる(version 3以降)
+    pub fn append_directory(&mut self, value: bool) {
+        let mut dict_appender = self.0.open_dict_entry_container().unwrap();
+        dict_appender.append_cstr(c"directory").unwrap();
+        dict_appender.append_variant_bool(value).unwrap();
+        dict_appender.close().unwrap();
+    }
+
     pub fn append_filters<'fname>(
         &mut self,
         filters: impl IntoIterator<Item = (&'fname core::ffi::CStr, impl IntoIterator<Item = Filter>)>,
@@ -149,56 +115,19 @@ impl OpenFileOptionsAppender<'_, '_> {
     }
 }
 
-pub fn read_open_file_reply(msg: dbus::Message) -> Result<ObjectPath, dbus::Error> {
-    if let Some(e) = msg.try_get_error() {
-        return Err(e);
-    }
-
-    let msg_iter = msg.iter();
-    let handle = ObjectPath(
-        msg_iter
-            .try_get_object_path()
-            .expect("invalid response")
-            .into(),
-    );
-    debug_assert!(!msg_iter.has_next(), "reply data left");
-
-    Ok(handle)
-}
-
-/// https://flatpak.github.io/xdg-desktop-portal/docs/doc-org.freedesktop.portal.FileChooser.html#org-freedesktop-portal-filechooser-savefile
-pub fn save_file(
-    dbus: &dbus::Connection,
-    parent_window: Option<&core::ffi::CStr>,
-    title: &core::ffi::CStr,
-    options_builder: impl FnOnce(SaveFileOptionsAppender),
-) -> u32 {
-    let mut msg = dbus::Message::new_method_call(
-        Some(c"org.freedesktop.portal.Desktop"),
-        c"/org/freedesktop/portal/desktop",
-        Some(c"org.freedesktop.portal.FileChooser"),
-        c"SaveFile",
-    )
-    .unwrap();
-    let mut msg_args_appender = msg.iter_append();
-    msg_args_appender
-        .append_cstr(parent_window.unwrap_or(c""))
-        .expect("no enough memory");
-    msg_args_appender
-        .append_cstr(title)
-        .expect("no enough memory");
-    let mut options_appender = msg_args_appender.open_array_container(c"{sv}").unwrap();
-    options_builder(SaveFileOptionsAppender(&mut options_appender));
-    options_appender.close().expect("no enough memory");
-
-    dbus.send_with_serial(&mut msg).expect("no enough memory")
-}
-
+/// SaveFileのoptions(a{sv})に書き込む
 #[repr(transparent)]
-pub struct SaveFileOptionsAppender<'a, 'm>(
-    &'a mut dbus::MessageIterAppendContainer<'m, dbus::MessageIterAppend<'m>>,
+pub struct SaveFileOptionsAppender<'a, 'p, 'm>(
+    &'a mut dbus::MessageIterAppendContainer<'p, dbus::MessageIterAppend<'m>>,
 );
-impl SaveFileOptionsAppender<'_, '_> {
+impl<'a, 'p, 'm> SaveFileOptionsAppender<'a, 'p, 'm> {
+    #[inline(always)]
+    pub const fn new(
+        options: &'a mut dbus::MessageIterAppendContainer<'p, dbus::MessageIterAppend<'m>>,
+    ) -> Self {
+        Self(options)
+    }
+
     pub fn append_handle_token(&mut self, value: &core::ffi::CStr) {
         let mut dict_appender = self
             .0
@@ -255,23 +184,6 @@ impl SaveFileOptionsAppender<'_, '_> {
     }
 }
 
-pub fn read_save_file_reply(msg: dbus::Message) -> Result<ObjectPath, dbus::Error> {
-    if let Some(e) = msg.try_get_error() {
-        return Err(e);
-    }
-
-    let msg_iter = msg.iter();
-    let handle = ObjectPath(
-        msg_iter
-            .try_get_object_path()
-            .expect("invalid response")
-            .into(),
-    );
-    debug_assert!(!msg_iter.has_next(), "reply data left");
-
-    Ok(handle)
-}
-
 #[derive(Debug, Clone)]
 pub enum Filter {
     Glob(std::ffi::CString),
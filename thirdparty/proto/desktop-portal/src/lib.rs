@@ -1,12 +1,4 @@
-#[repr(transparent)]
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct ObjectPath(pub(crate) std::ffi::CString);
-impl ObjectPath {
-    #[inline(always)]
-    pub fn as_c_str(&self) -> &core::ffi::CStr {
-        self.0.as_c_str()
-    }
-}
+pub use dbus::ObjectPath;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RequestResponseCode {
@@ -15,9 +7,9 @@ pub enum RequestResponseCode {
     InteractionEnded,
     Unknown(u32),
 }
-impl RequestResponseCode {
-    pub fn read(msg_iter: &dbus::MessageIter) -> Self {
-        match msg_iter.try_get_u32().expect("invalid response code") {
+impl From<u32> for RequestResponseCode {
+    fn from(value: u32) -> Self {
+        match value {
             0 => Self::Success,
             1 => Self::Cancelled,
             2 => Self::InteractionEnded,
@@ -26,4 +18,13 @@ impl RequestResponseCode {
     }
 }
 
+/// https://flatpak.github.io/xdg-desktop-portal/docs/doc-org.freedesktop.portal.Documents.html
+pub mod documents;
 pub mod file_chooser;
+/// https://flatpak.github.io/xdg-desktop-portal/docs/doc-org.freedesktop.portal.Settings.html
+pub mod settings;
+
+/// https://flatpak.github.io/xdg-desktop-portal/docs/doc-org.freedesktop.portal.Request.html
+pub mod request {
+    include!(concat!(env!("OUT_DIR"), "/request.rs"));
+}
@@ -0,0 +1,83 @@
+// Note: Proxy(Read/ReadOne/version)とSettingChangedSignalはinterfaces/org.freedesktop.portal.Settings.xmlからbuild.rsで生成している
+include!(concat!(env!("OUT_DIR"), "/settings.rs"));
+
+pub const APPEARANCE_NAMESPACE: &core::ffi::CStr = c"org.freedesktop.appearance";
+pub const COLOR_SCHEME_KEY: &core::ffi::CStr = c"color-scheme";
+pub const ACCENT_COLOR_KEY: &core::ffi::CStr = c"accent-color";
+
+impl<C: dbus::AsyncConnection + ?Sized> Proxy<'_, C> {
+    /// 値を1つ読む ReadOneがないバージョン(1)ではReadを使う
+    ///
+    /// 返ってきたメッセージの値は[`peel_variants`]で中身を取り出せる
+    pub async fn read_value(
+        &self,
+        version: u32,
+        namespace: &core::ffi::CStr,
+        key: &core::ffi::CStr,
+    ) -> Result<dbus::Message, dbus::Error> {
+        if version >= 2 {
+            self.read_one(namespace, key).await
+        } else {
+            self.read(namespace, key).await
+        }
+    }
+}
+
+/// 値を包んでいるvariantを剥がす
+///
+/// Readの戻り値は二重にvariantで包まれているので、ReadOne/SettingChangedと同じように扱えるようすべて剥がす
+pub fn peel_variants<'m>(mut iter: dbus::MessageIter<'m>) -> dbus::MessageIter<'m> {
+    while let Ok(inner) = iter.try_begin_iter_variant_content() {
+        iter = inner;
+    }
+
+    iter
+}
+
+/// `org.freedesktop.appearance` `color-scheme`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorScheme {
+    NoPreference,
+    PreferDark,
+    PreferLight,
+}
+impl From<u32> for ColorScheme {
+    fn from(value: u32) -> Self {
+        match value {
+            1 => Self::PreferDark,
+            2 => Self::PreferLight,
+            // 未知の値は設定なしとして扱う
+            _ => Self::NoPreference,
+        }
+    }
+}
+impl ColorScheme {
+    pub fn read(value: dbus::MessageIter<'_>) -> Option<Self> {
+        peel_variants(value).try_get_u32().ok().map(Self::from)
+    }
+}
+
+/// `org.freedesktop.appearance` `accent-color`（sRGB）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AccentColor {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+}
+impl AccentColor {
+    /// 範囲外の値は未設定を表すのでNoneになる
+    pub fn read(value: dbus::MessageIter<'_>) -> Option<Self> {
+        let mut iter = peel_variants(value).try_begin_iter_struct_content().ok()?;
+        let r = iter.try_get_f64().ok()?;
+        iter.next();
+        let g = iter.try_get_f64().ok()?;
+        iter.next();
+        let b = iter.try_get_f64().ok()?;
+
+        if ![r, g, b].iter().all(|x| (0.0..=1.0).contains(x)) {
+            return None;
+        }
+
+        Some(Self { r, g, b })
+    }
+}
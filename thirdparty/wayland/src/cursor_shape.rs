@@ -74,6 +74,7 @@ pub enum WpCursorShapeDeviceV1Shape {
     Default = 1,
     // ContextMenu = 2,
     Pointer = 4,
+    Text = 9,
     NeResize = 20,
     NwResize = 21,
     SeResize = 23,
@@ -4,7 +4,10 @@ use core::{
     ops::{Deref, DerefMut},
     ptr::NonNull,
 };
-use std::{cell::UnsafeCell, os::fd::AsRawFd};
+use std::{
+    cell::UnsafeCell,
+    os::fd::{AsRawFd, FromRawFd},
+};
 
 use bitflags::bitflags;
 use ffi::wl_proxy_destroy;
@@ -13,6 +16,7 @@ mod cursor_shape;
 pub mod ffi;
 mod fractional_scale;
 mod gtk_shell;
+mod text_input;
 mod viewporter;
 mod xdg_decoration;
 mod xdg_foreign;
@@ -22,6 +26,7 @@ pub use cursor_shape::*;
 pub use ffi::Fixed;
 pub use fractional_scale::*;
 pub use gtk_shell::*;
+pub use text_input::*;
 pub use viewporter::*;
 pub use xdg_decoration::*;
 pub use xdg_foreign::*;
@@ -624,6 +629,32 @@ impl Surface {
             .marshal_array_flags_void(8, 0, &mut [ffi::Argument { i: scale }])
     }
 
+    /// since version 4
+    #[inline]
+    pub fn damage_buffer(
+        &self,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    ) -> Result<(), std::io::Error> {
+        self.0.marshal_array_flags_void(
+            9,
+            0,
+            &mut [
+                ffi::Argument { i: x },
+                ffi::Argument { i: y },
+                ffi::Argument { i: width },
+                ffi::Argument { i: height },
+            ],
+        )
+    }
+
+    #[inline(always)]
+    pub fn version(&self) -> u32 {
+        self.0.version()
+    }
+
     pub fn add_listener<'l, L: SurfaceEventListener + 'l>(
         &'l mut self,
         listener: &'l mut L,
@@ -972,6 +1003,19 @@ impl Seat {
         Ok(unsafe { Owned::from_untyped_unchecked(proxy_ptr) })
     }
 
+    #[inline]
+    pub fn get_keyboard(&self) -> Result<Owned<Keyboard>, std::io::Error> {
+        let proxy_ptr = self.0.marshal_array_flags(
+            1,
+            Keyboard::def(),
+            self.0.version(),
+            0,
+            &mut [NEWID_ARG],
+        )?;
+
+        Ok(unsafe { Owned::from_untyped_unchecked(proxy_ptr) })
+    }
+
     // v5
     #[inline]
     pub unsafe fn destroy(&self) -> Result<(), std::io::Error> {
@@ -1268,6 +1312,96 @@ pub enum PointerButtonState {
     Pressed = 1,
 }
 
+#[repr(transparent)]
+pub struct Keyboard(Proxy);
+unsafe impl Interface for Keyboard {
+    fn def() -> &'static ffi::Interface {
+        unsafe { &wl_keyboard_interface }
+    }
+}
+impl Keyboard {
+    pub fn add_listener<'l, L: KeyboardEventListener + 'l>(
+        &'l mut self,
+        listener: &'l mut L,
+    ) -> Result<(), ()> {
+        let fp = EventFnTable! {
+            for L: KeyboardEventListener {
+                keymap(
+                    format: u32 => format,
+                    fd: core::ffi::c_int => unsafe { std::os::fd::OwnedFd::from_raw_fd(fd) },
+                    size: u32 => size
+                ),
+                enter(
+                    serial: u32 => serial,
+                    surface: *mut ffi::Proxy => unsafe { &mut *(surface as *mut Surface) },
+                    keys: *mut ffi::Array => unsafe {
+                        core::slice::from_raw_parts((*keys).data as *const u32, (*keys).size >> 2)
+                    }
+                ),
+                leave(
+                    serial: u32 => serial,
+                    surface: *mut ffi::Proxy => unsafe { &mut *(surface as *mut Surface) }
+                ),
+                key(
+                    serial: u32 => serial,
+                    time: u32 => time,
+                    key: u32 => key,
+                    state: KeyboardKeyState => state
+                ),
+                modifiers(
+                    serial: u32 => serial,
+                    mods_depressed: u32 => mods_depressed,
+                    mods_latched: u32 => mods_latched,
+                    mods_locked: u32 => mods_locked,
+                    group: u32 => group
+                ),
+                repeat_info(rate: i32 => rate, delay: i32 => delay)
+            }
+        };
+
+        unsafe {
+            self.0
+                .add_listener(fp as *const _ as _, listener as *mut _ as _)
+        }
+    }
+}
+
+pub trait KeyboardEventListener {
+    /// formatは1(xkb_v1)のみ fdは受け取った側で閉じる
+    fn keymap(&mut self, keyboard: &mut Keyboard, format: u32, fd: std::os::fd::OwnedFd, size: u32);
+    fn enter(&mut self, keyboard: &mut Keyboard, serial: u32, surface: &mut Surface, keys: &[u32]);
+    fn leave(&mut self, keyboard: &mut Keyboard, serial: u32, surface: &mut Surface);
+    /// keyはevdevのキーコード(xkbのキーコードは+8したもの)
+    fn key(
+        &mut self,
+        keyboard: &mut Keyboard,
+        serial: u32,
+        time: u32,
+        key: u32,
+        state: KeyboardKeyState,
+    );
+    fn modifiers(
+        &mut self,
+        keyboard: &mut Keyboard,
+        serial: u32,
+        mods_depressed: u32,
+        mods_latched: u32,
+        mods_locked: u32,
+        group: u32,
+    );
+    // v4
+    fn repeat_info(&mut self, keyboard: &mut Keyboard, rate: i32, delay: i32);
+}
+
+#[repr(u32)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeyboardKeyState {
+    Released = 0,
+    Pressed = 1,
+    // v10
+    Repeated = 2,
+}
+
 #[repr(u32)]
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum OutputTransform {
@@ -1675,6 +1809,7 @@ unsafe extern "C" {
     static wl_output_interface: ffi::Interface;
     static wl_callback_interface: ffi::Interface;
     static wl_pointer_interface: ffi::Interface;
+    static wl_keyboard_interface: ffi::Interface;
     static wl_data_device_manager_interface: ffi::Interface;
     static wl_data_device_interface: ffi::Interface;
     static wl_data_source_interface: ffi::Interface;
@@ -0,0 +1,396 @@
+use super::{Interface, ffi, interface, message};
+
+#[repr(transparent)]
+pub struct ZwpTextInputManagerV3(super::Proxy);
+unsafe impl super::Interface for ZwpTextInputManagerV3 {
+    fn def() -> &'static ffi::Interface {
+        Self::INTERFACE
+    }
+
+    #[tracing::instrument(name = "<ZwpTextInputManagerV3 as Interface>::destruct", skip(self))]
+    unsafe fn destruct(&mut self) {
+        if let Err(e) =
+            self.0
+                .marshal_array_flags_void(0, super::ffi::MARSHAL_FLAG_DESTROY, &mut [])
+        {
+            let de = unsafe {
+                ffi::wl_display_get_error(ffi::wl_proxy_get_display(&mut self.0 as *mut _ as _))
+            };
+
+            tracing::error!(reason = ?e, display_error = de, "Failed to call destroy");
+        }
+    }
+}
+impl ZwpTextInputManagerV3 {
+    const INTERFACE: &'static ffi::Interface = &interface(
+        c"zwp_text_input_manager_v3",
+        1,
+        &[
+            message(c"destroy", c"", &[]),
+            message(
+                c"get_text_input",
+                c"no",
+                &[
+                    const { ZwpTextInputV3::INTERFACE },
+                    const { unsafe { &super::wl_seat_interface } },
+                ],
+            ),
+        ],
+        &[],
+    );
+
+    #[tracing::instrument(
+        name = "ZwpTextInputManagerV3::get_text_input",
+        skip(self, seat),
+        err(level = tracing::Level::WARN)
+    )]
+    pub fn get_text_input(
+        &self,
+        seat: &super::Seat,
+    ) -> Result<super::Owned<ZwpTextInputV3>, std::io::Error> {
+        Ok(unsafe {
+            super::Owned::from_untyped_unchecked(self.0.marshal_array_flags(
+                1,
+                ZwpTextInputV3::def(),
+                self.0.version(),
+                0,
+                &mut [
+                    super::NEWID_ARG,
+                    super::ffi::Argument {
+                        o: seat.0.0.get() as _,
+                    },
+                ],
+            )?)
+        })
+    }
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZwpTextInputV3ChangeCause {
+    InputMethod = 0,
+    Other = 1,
+}
+
+bitflags::bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ZwpTextInputV3ContentHint : u32 {
+        const NONE = 0x0000;
+        const COMPLETION = 0x0001;
+        const SPELLCHECK = 0x0002;
+        const AUTO_CAPITALIZATION = 0x0004;
+        const LOWERCASE = 0x0008;
+        const UPPERCASE = 0x0010;
+        const TITLECASE = 0x0020;
+        const HIDDEN_TEXT = 0x0040;
+        const SENSITIVE_DATA = 0x0080;
+        const LATIN = 0x0100;
+        const MULTILINE = 0x0200;
+    }
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZwpTextInputV3ContentPurpose {
+    Normal = 0,
+    Alpha = 1,
+    Digits = 2,
+    Number = 3,
+    Phone = 4,
+    Url = 5,
+    Email = 6,
+    Name = 7,
+    Password = 8,
+    Pin = 9,
+    Date = 10,
+    Time = 11,
+    Datetime = 12,
+    Terminal = 13,
+}
+
+#[repr(transparent)]
+pub struct ZwpTextInputV3(super::Proxy);
+unsafe impl super::Interface for ZwpTextInputV3 {
+    fn def() -> &'static ffi::Interface {
+        Self::INTERFACE
+    }
+
+    #[tracing::instrument(name = "<ZwpTextInputV3 as Interface>::destruct", skip(self))]
+    unsafe fn destruct(&mut self) {
+        if let Err(e) =
+            self.0
+                .marshal_array_flags_void(0, super::ffi::MARSHAL_FLAG_DESTROY, &mut [])
+        {
+            let de = unsafe {
+                ffi::wl_display_get_error(ffi::wl_proxy_get_display(&mut self.0 as *mut _ as _))
+            };
+
+            tracing::error!(reason = ?e, display_error = de, "Failed to call destroy");
+        }
+    }
+}
+impl ZwpTextInputV3 {
+    const INTERFACE: &'static ffi::Interface = &interface(
+        c"zwp_text_input_v3",
+        1,
+        &[
+            message(c"destroy", c"", &[]),
+            message(c"enable", c"", &[]),
+            message(c"disable", c"", &[]),
+            message(
+                c"set_surrounding_text",
+                c"sii",
+                &[core::ptr::null(), core::ptr::null(), core::ptr::null()],
+            ),
+            message(c"set_text_change_cause", c"u", &[core::ptr::null()]),
+            message(
+                c"set_content_type",
+                c"uu",
+                &[core::ptr::null(), core::ptr::null()],
+            ),
+            message(c"set_cursor_rectangle", c"iiii", &[core::ptr::null(); 4]),
+            message(c"commit", c"", &[]),
+        ],
+        &[
+            message(
+                c"enter",
+                c"o",
+                &[const { unsafe { &super::wl_surface_interface } }],
+            ),
+            message(
+                c"leave",
+                c"o",
+                &[const { unsafe { &super::wl_surface_interface } }],
+            ),
+            message(
+                c"preedit_string",
+                c"?sii",
+                &[core::ptr::null(), core::ptr::null(), core::ptr::null()],
+            ),
+            message(c"commit_string", c"?s", &[core::ptr::null()]),
+            message(
+                c"delete_surrounding_text",
+                c"uu",
+                &[core::ptr::null(), core::ptr::null()],
+            ),
+            message(c"done", c"u", &[core::ptr::null()]),
+        ],
+    );
+
+    #[inline]
+    pub fn enable(&self) -> Result<(), std::io::Error> {
+        self.0.marshal_array_flags_void(1, 0, &mut [])
+    }
+
+    #[inline]
+    pub fn disable(&self) -> Result<(), std::io::Error> {
+        self.0.marshal_array_flags_void(2, 0, &mut [])
+    }
+
+    #[inline]
+    pub fn set_surrounding_text(
+        &self,
+        text: &core::ffi::CStr,
+        cursor: i32,
+        anchor: i32,
+    ) -> Result<(), std::io::Error> {
+        self.0.marshal_array_flags_void(
+            3,
+            0,
+            &mut [
+                ffi::Argument { s: text.as_ptr() },
+                ffi::Argument { i: cursor },
+                ffi::Argument { i: anchor },
+            ],
+        )
+    }
+
+    #[inline]
+    pub fn set_text_change_cause(
+        &self,
+        cause: ZwpTextInputV3ChangeCause,
+    ) -> Result<(), std::io::Error> {
+        self.0
+            .marshal_array_flags_void(4, 0, &mut [ffi::Argument { u: cause as _ }])
+    }
+
+    #[inline]
+    pub fn set_content_type(
+        &self,
+        hint: ZwpTextInputV3ContentHint,
+        purpose: ZwpTextInputV3ContentPurpose,
+    ) -> Result<(), std::io::Error> {
+        self.0.marshal_array_flags_void(
+            5,
+            0,
+            &mut [
+                ffi::Argument { u: hint.bits() },
+                ffi::Argument { u: purpose as _ },
+            ],
+        )
+    }
+
+    #[inline]
+    pub fn set_cursor_rectangle(
+        &self,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    ) -> Result<(), std::io::Error> {
+        self.0.marshal_array_flags_void(
+            6,
+            0,
+            &mut [
+                ffi::Argument { i: x },
+                ffi::Argument { i: y },
+                ffi::Argument { i: width },
+                ffi::Argument { i: height },
+            ],
+        )
+    }
+
+    #[inline]
+    pub fn commit(&self) -> Result<(), std::io::Error> {
+        self.0.marshal_array_flags_void(7, 0, &mut [])
+    }
+
+    pub fn add_listener<'l, L: ZwpTextInputV3EventListener + 'l>(
+        &'l mut self,
+        listener: &'l mut L,
+    ) -> Result<(), ()> {
+        extern "C" fn enter<L: ZwpTextInputV3EventListener>(
+            data: *mut core::ffi::c_void,
+            sender: *mut ffi::Proxy,
+            surface: *mut ffi::Proxy,
+        ) {
+            L::enter(
+                unsafe { &mut *(data as *mut _) },
+                unsafe { &mut *(sender as *mut _) },
+                unsafe { &mut *(surface as *mut _) },
+            )
+        }
+        extern "C" fn leave<L: ZwpTextInputV3EventListener>(
+            data: *mut core::ffi::c_void,
+            sender: *mut ffi::Proxy,
+            surface: *mut ffi::Proxy,
+        ) {
+            L::leave(
+                unsafe { &mut *(data as *mut _) },
+                unsafe { &mut *(sender as *mut _) },
+                unsafe { &mut *(surface as *mut _) },
+            )
+        }
+        extern "C" fn preedit_string<L: ZwpTextInputV3EventListener>(
+            data: *mut core::ffi::c_void,
+            sender: *mut ffi::Proxy,
+            text: *const core::ffi::c_char,
+            cursor_begin: i32,
+            cursor_end: i32,
+        ) {
+            L::preedit_string(
+                unsafe { &mut *(data as *mut _) },
+                unsafe { &mut *(sender as *mut _) },
+                if text.is_null() {
+                    None
+                } else {
+                    Some(unsafe { core::ffi::CStr::from_ptr(text) })
+                },
+                cursor_begin,
+                cursor_end,
+            )
+        }
+        extern "C" fn commit_string<L: ZwpTextInputV3EventListener>(
+            data: *mut core::ffi::c_void,
+            sender: *mut ffi::Proxy,
+            text: *const core::ffi::c_char,
+        ) {
+            L::commit_string(
+                unsafe { &mut *(data as *mut _) },
+                unsafe { &mut *(sender as *mut _) },
+                if text.is_null() {
+                    None
+                } else {
+                    Some(unsafe { core::ffi::CStr::from_ptr(text) })
+                },
+            )
+        }
+        extern "C" fn delete_surrounding_text<L: ZwpTextInputV3EventListener>(
+            data: *mut core::ffi::c_void,
+            sender: *mut ffi::Proxy,
+            before_length: u32,
+            after_length: u32,
+        ) {
+            L::delete_surrounding_text(
+                unsafe { &mut *(data as *mut _) },
+                unsafe { &mut *(sender as *mut _) },
+                before_length,
+                after_length,
+            )
+        }
+        extern "C" fn done<L: ZwpTextInputV3EventListener>(
+            data: *mut core::ffi::c_void,
+            sender: *mut ffi::Proxy,
+            serial: u32,
+        ) {
+            L::done(
+                unsafe { &mut *(data as *mut _) },
+                unsafe { &mut *(sender as *mut _) },
+                serial,
+            )
+        }
+
+        #[repr(C)]
+        struct FunctionPointers {
+            enter: extern "C" fn(*mut core::ffi::c_void, *mut ffi::Proxy, *mut ffi::Proxy),
+            leave: extern "C" fn(*mut core::ffi::c_void, *mut ffi::Proxy, *mut ffi::Proxy),
+            preedit_string: extern "C" fn(
+                *mut core::ffi::c_void,
+                *mut ffi::Proxy,
+                *const core::ffi::c_char,
+                i32,
+                i32,
+            ),
+            commit_string:
+                extern "C" fn(*mut core::ffi::c_void, *mut ffi::Proxy, *const core::ffi::c_char),
+            delete_surrounding_text:
+                extern "C" fn(*mut core::ffi::c_void, *mut ffi::Proxy, u32, u32),
+            done: extern "C" fn(*mut core::ffi::c_void, *mut ffi::Proxy, u32),
+        }
+        let fp: &'static FunctionPointers = &FunctionPointers {
+            enter: enter::<L>,
+            leave: leave::<L>,
+            preedit_string: preedit_string::<L>,
+            commit_string: commit_string::<L>,
+            delete_surrounding_text: delete_surrounding_text::<L>,
+            done: done::<L>,
+        };
+        unsafe {
+            self.0
+                .add_listener(fp as *const _ as _, listener as *mut _ as _)
+        }
+    }
+}
+
+pub trait ZwpTextInputV3EventListener {
+    fn enter(&mut self, sender: &mut ZwpTextInputV3, surface: &mut super::Surface);
+    fn leave(&mut self, sender: &mut ZwpTextInputV3, surface: &mut super::Surface);
+    /// cursor_begin/cursor_endはtext内のbyte offset(両方-1のときはカーソル非表示)
+    fn preedit_string(
+        &mut self,
+        sender: &mut ZwpTextInputV3,
+        text: Option<&core::ffi::CStr>,
+        cursor_begin: i32,
+        cursor_end: i32,
+    );
+    fn commit_string(&mut self, sender: &mut ZwpTextInputV3, text: Option<&core::ffi::CStr>);
+    /// 長さはbyte単位
+    fn delete_surrounding_text(
+        &mut self,
+        sender: &mut ZwpTextInputV3,
+        before_length: u32,
+        after_length: u32,
+    );
+    /// ここまでに来たイベントをまとめて適用する
+    fn done(&mut self, sender: &mut ZwpTextInputV3, serial: u32);
+}
@@ -0,0 +1,73 @@
+#![allow(non_camel_case_types, dead_code)]
+
+/// https://doc.rust-lang.org/nomicon/ffi.html#representing-opaque-structs
+macro_rules! FFIOpaqueStruct {
+    ($v: vis struct $t: ident) => {
+        #[repr(C)]
+        $v struct $t {
+            _data: [u8; 0],
+            _marker: core::marker::PhantomData<(*mut u8, core::marker::PhantomPinned)>,
+        }
+    }
+}
+
+FFIOpaqueStruct!(pub struct xkb_context);
+FFIOpaqueStruct!(pub struct xkb_keymap);
+FFIOpaqueStruct!(pub struct xkb_state);
+
+pub type xkb_keycode_t = u32;
+pub type xkb_keysym_t = u32;
+pub type xkb_mod_mask_t = u32;
+pub type xkb_layout_index_t = u32;
+
+pub type xkb_context_flags = core::ffi::c_int;
+pub const XKB_CONTEXT_NO_FLAGS: xkb_context_flags = 0;
+
+pub type xkb_keymap_format = core::ffi::c_int;
+pub const XKB_KEYMAP_FORMAT_TEXT_V1: xkb_keymap_format = 1;
+
+pub type xkb_keymap_compile_flags = core::ffi::c_int;
+pub const XKB_KEYMAP_COMPILE_NO_FLAGS: xkb_keymap_compile_flags = 0;
+
+pub type xkb_state_component = core::ffi::c_int;
+pub const XKB_STATE_MODS_EFFECTIVE: xkb_state_component = 1 << 3;
+
+#[link(name = "xkbcommon")]
+unsafe extern "C" {
+    pub fn xkb_context_new(flags: xkb_context_flags) -> *mut xkb_context;
+    pub fn xkb_context_unref(context: *mut xkb_context);
+
+    pub fn xkb_keymap_new_from_buffer(
+        context: *mut xkb_context,
+        buffer: *const core::ffi::c_char,
+        length: usize,
+        format: xkb_keymap_format,
+        flags: xkb_keymap_compile_flags,
+    ) -> *mut xkb_keymap;
+    pub fn xkb_keymap_unref(keymap: *mut xkb_keymap);
+    pub fn xkb_keymap_key_repeats(keymap: *mut xkb_keymap, key: xkb_keycode_t) -> core::ffi::c_int;
+
+    pub fn xkb_state_new(keymap: *mut xkb_keymap) -> *mut xkb_state;
+    pub fn xkb_state_unref(state: *mut xkb_state);
+    pub fn xkb_state_update_mask(
+        state: *mut xkb_state,
+        depressed_mods: xkb_mod_mask_t,
+        latched_mods: xkb_mod_mask_t,
+        locked_mods: xkb_mod_mask_t,
+        depressed_layout: xkb_layout_index_t,
+        latched_layout: xkb_layout_index_t,
+        locked_layout: xkb_layout_index_t,
+    ) -> xkb_state_component;
+    pub fn xkb_state_key_get_one_sym(state: *mut xkb_state, key: xkb_keycode_t) -> xkb_keysym_t;
+    pub fn xkb_state_key_get_utf8(
+        state: *mut xkb_state,
+        key: xkb_keycode_t,
+        buffer: *mut core::ffi::c_char,
+        size: usize,
+    ) -> core::ffi::c_int;
+    pub fn xkb_state_mod_name_is_active(
+        state: *mut xkb_state,
+        name: *const core::ffi::c_char,
+        r#type: xkb_state_component,
+    ) -> core::ffi::c_int;
+}
@@ -0,0 +1,154 @@
+//! minimal libxkbcommon binding(keymap/state for wl_keyboard)
+
+mod ffi;
+
+pub use ffi::{xkb_keycode_t as Keycode, xkb_keysym_t as Keysym};
+
+/// xkbcommon-keysyms.hから必要なものだけ
+pub mod keysyms {
+    use super::Keysym;
+
+    pub const BACKSPACE: Keysym = 0xff08;
+    pub const TAB: Keysym = 0xff09;
+    pub const RETURN: Keysym = 0xff0d;
+    pub const ESCAPE: Keysym = 0xff1b;
+    pub const HOME: Keysym = 0xff50;
+    pub const LEFT: Keysym = 0xff51;
+    pub const UP: Keysym = 0xff52;
+    pub const RIGHT: Keysym = 0xff53;
+    pub const DOWN: Keysym = 0xff54;
+    pub const END: Keysym = 0xff57;
+    pub const KP_ENTER: Keysym = 0xff8d;
+    pub const DELETE: Keysym = 0xffff;
+    pub const LOWER_A: Keysym = 0x0061;
+    pub const LOWER_C: Keysym = 0x0063;
+    pub const LOWER_V: Keysym = 0x0076;
+    pub const LOWER_X: Keysym = 0x0078;
+    pub const UPPER_A: Keysym = 0x0041;
+    pub const UPPER_C: Keysym = 0x0043;
+    pub const UPPER_V: Keysym = 0x0056;
+    pub const UPPER_X: Keysym = 0x0058;
+}
+
+pub const MOD_NAME_SHIFT: &core::ffi::CStr = c"Shift";
+pub const MOD_NAME_CTRL: &core::ffi::CStr = c"Control";
+pub const MOD_NAME_ALT: &core::ffi::CStr = c"Mod1";
+pub const MOD_NAME_LOGO: &core::ffi::CStr = c"Mod4";
+
+pub struct Context(core::ptr::NonNull<ffi::xkb_context>);
+impl Drop for Context {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::xkb_context_unref(self.0.as_ptr());
+        }
+    }
+}
+impl Context {
+    pub fn new() -> Option<Self> {
+        core::ptr::NonNull::new(unsafe { ffi::xkb_context_new(ffi::XKB_CONTEXT_NO_FLAGS) })
+            .map(Self)
+    }
+}
+
+pub struct Keymap(core::ptr::NonNull<ffi::xkb_keymap>);
+impl Drop for Keymap {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::xkb_keymap_unref(self.0.as_ptr());
+        }
+    }
+}
+impl Keymap {
+    /// XKB_KEYMAP_FORMAT_TEXT_V1のキーマップを読む
+    pub fn from_text_v1(context: &Context, buffer: &[u8]) -> Option<Self> {
+        // Note: wl_keyboard.keymapのバッファは終端のNULまで含んでいることがあるので落としておく
+        let buffer = match buffer.iter().position(|&x| x == 0) {
+            Some(p) => &buffer[..p],
+            None => buffer,
+        };
+
+        core::ptr::NonNull::new(unsafe {
+            ffi::xkb_keymap_new_from_buffer(
+                context.0.as_ptr(),
+                buffer.as_ptr() as _,
+                buffer.len(),
+                ffi::XKB_KEYMAP_FORMAT_TEXT_V1,
+                ffi::XKB_KEYMAP_COMPILE_NO_FLAGS,
+            )
+        })
+        .map(Self)
+    }
+
+    #[inline]
+    pub fn key_repeats(&self, key: Keycode) -> bool {
+        unsafe { ffi::xkb_keymap_key_repeats(self.0.as_ptr(), key) != 0 }
+    }
+}
+
+pub struct State(core::ptr::NonNull<ffi::xkb_state>);
+impl Drop for State {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::xkb_state_unref(self.0.as_ptr());
+        }
+    }
+}
+impl State {
+    pub fn new(keymap: &Keymap) -> Option<Self> {
+        core::ptr::NonNull::new(unsafe { ffi::xkb_state_new(keymap.0.as_ptr()) }).map(Self)
+    }
+
+    #[inline]
+    pub fn update_mask(
+        &mut self,
+        depressed_mods: u32,
+        latched_mods: u32,
+        locked_mods: u32,
+        group: u32,
+    ) {
+        unsafe {
+            ffi::xkb_state_update_mask(
+                self.0.as_ptr(),
+                depressed_mods,
+                latched_mods,
+                locked_mods,
+                0,
+                0,
+                group,
+            );
+        }
+    }
+
+    #[inline]
+    pub fn key_get_one_sym(&self, key: Keycode) -> Keysym {
+        unsafe { ffi::xkb_state_key_get_one_sym(self.0.as_ptr(), key) }
+    }
+
+    /// キーを押したときに入力される文字列(ないときは空)
+    pub fn key_get_utf8(&self, key: Keycode) -> String {
+        let required =
+            unsafe { ffi::xkb_state_key_get_utf8(self.0.as_ptr(), key, core::ptr::null_mut(), 0) };
+        if required <= 0 {
+            return String::new();
+        }
+
+        let mut buf = vec![0u8; required as usize + 1];
+        unsafe {
+            ffi::xkb_state_key_get_utf8(self.0.as_ptr(), key, buf.as_mut_ptr() as _, buf.len());
+        }
+        buf.truncate(required as usize);
+
+        String::from_utf8(buf).unwrap_or_default()
+    }
+
+    #[inline]
+    pub fn mod_name_is_active(&self, name: &core::ffi::CStr) -> bool {
+        unsafe {
+            ffi::xkb_state_mod_name_is_active(
+                self.0.as_ptr(),
+                name.as_ptr(),
+                ffi::XKB_STATE_MODS_EFFECTIVE,
+            ) > 0
+        }
+    }
+}